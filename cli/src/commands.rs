@@ -1,6 +1,8 @@
 use anchor_client::solana_sdk::pubkey::Pubkey;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use api_common::requests::LogLevel;
 use clap::{Args, Parser};
+use mu_stack::StackID;
 
 use crate::{
     config::{Config, ConfigOverride},
@@ -69,10 +71,14 @@ pub enum Command {
 
     #[cfg(feature = "dev-env")]
     /// Run mu project
+    #[command(alias = "dev")]
     Run(dev_env::RunCommand),
 
     /// Deploy the project
     Deploy(DeployStackCommand),
+
+    /// Stream function logs of a deployed stack
+    Logs(LogsCommand),
 }
 
 #[derive(Debug, Args)]
@@ -96,6 +102,17 @@ pub struct DeployStackCommand {
     update: bool,
 }
 
+#[derive(Debug, Args)]
+pub struct LogsCommand {
+    /// The ID of the stack to stream logs from.
+    stack: Pubkey,
+
+    #[arg(long, short, default_value = "info")]
+    /// Only show logs at this level or more severe. One of
+    /// error, warn, info, debug or trace.
+    level: LogLevel,
+}
+
 #[derive(Debug, Parser)]
 #[clap(version = VERSION, about)]
 pub struct Arguments {
@@ -114,6 +131,7 @@ pub fn execute(args: Arguments) -> Result<()> {
         Command::Stack { sub_command } => stack::execute(config, sub_command),
         Command::RequestSigner { sub_command } => request_signer::execute(config, sub_command),
         Command::Deploy(sub_command) => execute_deploy(config, sub_command),
+        Command::Logs(sub_command) => execute_logs(config, sub_command),
 
         #[cfg(feature = "admin")]
         Command::Admin { sub_command } => admin::execute(config, sub_command),
@@ -156,3 +174,25 @@ pub fn execute_deploy(config: Config, cmd: DeployStackCommand) -> Result<()> {
         deploy_mode,
     )
 }
+
+pub fn execute_logs(config: Config, cmd: LogsCommand) -> Result<()> {
+    let marketplace_client = config.build_marketplace_client()?;
+    let user_wallet = config.get_signer()?;
+
+    let stack = marketplace_client
+        .program
+        .account::<marketplace::Stack>(cmd.stack)
+        .context("Failed to fetch stack")?;
+
+    let region_base_url =
+        marketplace_client::region::get_base_url(&marketplace_client, stack.region)?;
+
+    let region_api_client = api_common::client::ApiClient::new(region_base_url);
+
+    region_api_client.stream_logs(
+        StackID::SolanaPublicKey(cmd.stack.to_bytes()),
+        cmd.level,
+        user_wallet,
+        |entry| println!("[{}] {}: {}", entry.level, entry.function, entry.body),
+    )
+}
@@ -12,6 +12,17 @@ use crate::{config::Config, marketplace_client};
 pub enum Command {
     Initialize(InitializeCommand),
     UpdateDeposit(UpdateDepositCommand),
+    UpdateDeletedStackGracePeriod(DeletedStackGracePeriodCommand),
+    /// Brings a state created by an older version of the marketplace up to
+    /// the current layout.
+    MigrateState(DeletedStackGracePeriodCommand),
+    /// Brings one of your regions created by an older version of the
+    /// marketplace up to the current layout. Must be signed by the region's
+    /// provider.
+    MigrateRegion(MigrateRegionCommand),
+    /// Brings a stack created by an older version of the marketplace up to
+    /// the current layout. Its region must be migrated first.
+    MigrateStack(MigrateStackCommand),
     CreateProviderAuthorizer(CreateAuthorizerCommand),
     ListUnauthorizedProviders,
     AuthorizeProvider(AuthorizeProviderCommand),
@@ -27,6 +38,11 @@ pub struct InitializeCommand {
 
     #[arg(long)]
     provider_deposit: f64,
+
+    /// How long providers can still report usage for a stack after
+    /// it's deleted, in seconds.
+    #[arg(long, default_value_t = 7 * 24 * 60 * 60)]
+    deleted_stack_grace_period_secs: u32,
 }
 
 #[derive(Debug, Parser)]
@@ -34,6 +50,25 @@ pub struct UpdateDepositCommand {
     deposit: f64,
 }
 
+#[derive(Debug, Parser)]
+pub struct DeletedStackGracePeriodCommand {
+    /// How long providers can still report usage for a stack after
+    /// it's deleted, in seconds.
+    #[arg(long, default_value_t = 7 * 24 * 60 * 60)]
+    deleted_stack_grace_period_secs: u32,
+}
+
+#[derive(Debug, Parser)]
+pub struct MigrateRegionCommand {
+    #[arg(long)]
+    region_num: u32,
+}
+
+#[derive(Debug, Parser)]
+pub struct MigrateStackCommand {
+    stack: Pubkey,
+}
+
 #[derive(Debug, Parser)]
 pub struct CreateAuthorizerCommand {
     authorizer_keypair: String,
@@ -48,6 +83,12 @@ pub fn execute(config: Config, command: Command) -> Result<()> {
     match command {
         Command::Initialize(cmd) => execute_initialize(config, cmd),
         Command::UpdateDeposit(cmd) => execute_update_deposit(config, cmd),
+        Command::UpdateDeletedStackGracePeriod(cmd) => {
+            execute_update_deleted_stack_grace_period(config, cmd)
+        }
+        Command::MigrateState(cmd) => execute_migrate_state(config, cmd),
+        Command::MigrateRegion(cmd) => execute_migrate_region(config, cmd),
+        Command::MigrateStack(cmd) => execute_migrate_stack(config, cmd),
         Command::CreateProviderAuthorizer(cmd) => execute_create_provider_authorizer(config, cmd),
         Command::ListUnauthorizedProviders => execute_list_unauthorized_providers(config),
         Command::AuthorizeProvider(cmd) => execute_authorize_provider(config, cmd),
@@ -66,6 +107,7 @@ fn execute_initialize(config: Config, command: InitializeCommand) -> Result<()>
         command.token_mint,
         command.commission_rate_micros,
         crate::token_utils::ui_amount_to_token_amount(&mint, command.provider_deposit),
+        command.deleted_stack_grace_period_secs,
     )
 }
 
@@ -81,6 +123,41 @@ fn execute_update_deposit(config: Config, command: UpdateDepositCommand) -> Resu
     )
 }
 
+fn execute_update_deleted_stack_grace_period(
+    config: Config,
+    command: DeletedStackGracePeriodCommand,
+) -> Result<()> {
+    let client = config.build_marketplace_client()?;
+    let signer = config.get_signer()?;
+    marketplace_client::admin::update_deleted_stack_grace_period(
+        &client,
+        signer.as_ref(),
+        command.deleted_stack_grace_period_secs,
+    )
+}
+
+fn execute_migrate_state(config: Config, command: DeletedStackGracePeriodCommand) -> Result<()> {
+    let client = config.build_marketplace_client()?;
+    let signer = config.get_signer()?;
+    marketplace_client::admin::migrate_state(
+        &client,
+        signer.as_ref(),
+        command.deleted_stack_grace_period_secs,
+    )
+}
+
+fn execute_migrate_region(config: Config, command: MigrateRegionCommand) -> Result<()> {
+    let client = config.build_marketplace_client()?;
+    let signer = config.get_signer()?;
+    marketplace_client::admin::migrate_region(&client, signer.as_ref(), command.region_num)
+}
+
+fn execute_migrate_stack(config: Config, command: MigrateStackCommand) -> Result<()> {
+    let client = config.build_marketplace_client()?;
+    let signer = config.get_signer()?;
+    marketplace_client::admin::migrate_stack(&client, signer.as_ref(), command.stack)
+}
+
 fn execute_create_provider_authorizer(
     config: Config,
     command: CreateAuthorizerCommand,
@@ -39,6 +39,10 @@ pub struct RunCommand {
     #[arg(long)]
     /// Build artifacts in release mode, with optimizations
     release: bool,
+
+    #[arg(long)]
+    /// Rebuild and redeploy functions when their sources or the manifest change
+    watch: bool,
 }
 
 pub fn execute_init(cmd: InitCommand) -> Result<()> {
@@ -139,8 +143,12 @@ pub fn execute_run(cmd: RunCommand) -> Result<()> {
         .map_err(|(_, e)| e)
         .context("Invalid stack manifest")?;
 
+    let stack_id = manifest.dev_id;
+    let watch = cmd.watch.then_some((manifest, build_mode));
+
     tokio::runtime::Runtime::new()?.block_on(local_run::start_local_node(
-        (stack, manifest.dev_id),
+        (stack, stack_id),
         project_root,
+        watch,
     ))
 }
@@ -91,6 +91,7 @@ fn create(config: Config, args: CreateArgs) -> Result<()> {
     let accounts = marketplace::accounts::CreateRegion {
         provider: provider_pda,
         region: region_pda,
+        region_rates: client.get_region_rates_pda(&region_pda, 1),
         owner: provider_keypair.pubkey(),
         system_program: system_program::id(),
     };
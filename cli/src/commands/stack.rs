@@ -1,19 +1,26 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use anchor_client::{
     solana_client::rpc_filter::{Memcmp, RpcFilterType},
     solana_sdk::pubkey::Pubkey,
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::{Args, Parser};
 use marketplace::StackState;
+use mu_stack::StackID;
 
-use crate::{config::Config, marketplace_client};
+use crate::{config::Config, marketplace_client, mu_manifest::read_manifest_at};
 
 #[derive(Debug, Parser)]
 pub enum Command {
     List(ListStacksCommand),
     Delete(DeleteStackCommand),
+    Validate(ValidateStackCommand),
+    SetSecret(SetSecretCommand),
+    Metrics(StackMetricsCommand),
 }
 
 #[derive(Debug, Args)]
@@ -42,10 +49,40 @@ pub struct DeleteStackCommand {
     region: String,
 }
 
+#[derive(Debug, Args)]
+pub struct ValidateStackCommand {
+    /// The manifest file to validate, or the project directory containing it.
+    #[arg(default_value = ".")]
+    path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct SetSecretCommand {
+    /// The ID of the stack the secret belongs to.
+    stack: Pubkey,
+
+    /// The name functions use to reference the secret.
+    name: String,
+
+    #[arg(long)]
+    /// The secret's value. If not given, it is read from stdin so it
+    /// doesn't end up in your shell history.
+    value: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct StackMetricsCommand {
+    /// The ID of the stack to show custom metrics for.
+    stack: Pubkey,
+}
+
 pub fn execute(config: Config, cmd: Command) -> Result<()> {
     match cmd {
         Command::List(sub_command) => execute_list(config, sub_command),
         Command::Delete(sub_command) => execute_delete(config, sub_command),
+        Command::Validate(sub_command) => execute_validate(sub_command),
+        Command::SetSecret(sub_command) => execute_set_secret(config, sub_command),
+        Command::Metrics(sub_command) => execute_metrics(config, sub_command),
     }
 }
 
@@ -151,3 +188,87 @@ pub fn execute_delete(config: Config, cmd: DeleteStackCommand) -> Result<()> {
 
     marketplace_client::stack::delete(&client, user_wallet, &cmd.stack, region.as_ref())
 }
+
+pub fn execute_set_secret(config: Config, cmd: SetSecretCommand) -> Result<()> {
+    let marketplace_client = config.build_marketplace_client()?;
+    let user_wallet = config.get_signer()?;
+
+    let stack = marketplace_client
+        .program
+        .account::<marketplace::Stack>(cmd.stack)
+        .context("Failed to fetch stack")?;
+
+    let value = match cmd.value {
+        Some(value) => value,
+        None => {
+            let mut value = String::new();
+            std::io::stdin()
+                .read_line(&mut value)
+                .context("Failed to read secret value")?;
+            value.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    let region_base_url =
+        marketplace_client::region::get_base_url(&marketplace_client, stack.region)?;
+
+    api_common::client::ApiClient::new(region_base_url).set_secret(
+        StackID::SolanaPublicKey(cmd.stack.to_bytes()),
+        cmd.name,
+        value,
+        user_wallet,
+    )?;
+
+    println!("Secret set");
+    Ok(())
+}
+
+pub fn execute_metrics(config: Config, cmd: StackMetricsCommand) -> Result<()> {
+    let marketplace_client = config.build_marketplace_client()?;
+    let user_wallet = config.get_signer()?;
+
+    let stack = marketplace_client
+        .program
+        .account::<marketplace::Stack>(cmd.stack)
+        .context("Failed to fetch stack")?;
+
+    let region_base_url =
+        marketplace_client::region::get_base_url(&marketplace_client, stack.region)?;
+
+    let metrics = api_common::client::ApiClient::new(region_base_url)
+        .get_custom_metrics(StackID::SolanaPublicKey(cmd.stack.to_bytes()), user_wallet)?;
+
+    if metrics.is_empty() {
+        println!("No custom metrics reported");
+        return Ok(());
+    }
+
+    let mut metrics = metrics.into_iter().collect::<Vec<_>>();
+    metrics.sort();
+    for (name, value) in metrics {
+        println!("{name}: {value}");
+    }
+    Ok(())
+}
+
+pub fn execute_validate(cmd: ValidateStackCommand) -> Result<()> {
+    let (manifest, project_root) = read_manifest_at(&cmd.path)?;
+    let stack = manifest.generate_stack_manifest_for_validation(&project_root)?;
+
+    let errors = stack.validation_errors();
+    if errors.is_empty() {
+        println!("Stack '{}' is valid", stack.name);
+        return Ok(());
+    }
+
+    let report = errors
+        .iter()
+        .map(|e| format!("  - {e}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    bail!(
+        "Stack '{}' has {} error(s):\n{report}",
+        stack.name,
+        errors.len()
+    )
+}
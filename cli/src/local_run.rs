@@ -8,20 +8,29 @@ use log::LevelFilter;
 use mu_stack::{StackID, ValidatedStack};
 use tokio_util::sync::CancellationToken;
 
+use crate::mu_manifest::{BuildMode, Function, MuManifest};
+
 mod database;
 mod runtime;
 mod storage;
+mod watch;
 
 pub type StackWithID = (ValidatedStack, StackID);
 
-pub async fn start_local_node(stack: StackWithID, project_root: PathBuf) -> Result<()> {
+/// With `watch` set, functions are rebuilt and redeployed as their sources
+/// change until the node is stopped.
+pub async fn start_local_node(
+    stack: StackWithID,
+    project_root: PathBuf,
+    watch: Option<(MuManifest, BuildMode)>,
+) -> Result<()> {
     println!("Starting local mu runtime . . .");
 
     //TODO: make this configurable
     setup_logging();
 
     let (runtime, gateway, database, storage, gateways, stack_id) =
-        runtime::start(stack, project_root).await?;
+        runtime::start(stack, project_root.clone()).await?;
 
     let cancellation_token = CancellationToken::new();
     ctrlc::set_handler({
@@ -52,7 +61,26 @@ pub async fn start_local_node(stack: StackWithID, project_root: PathBuf) -> Resu
 
     println!("\nStack deployed at: http://localhost:12012/{stack_id}/");
 
+    let watcher = watch.map(|(manifest, build_mode)| {
+        println!("Watching for changes . . .");
+        let watcher = watch::Watcher::new(
+            manifest,
+            project_root,
+            build_mode,
+            Function::build,
+            watch::RuntimeDeployer {
+                runtime: runtime.clone(),
+                stack_id,
+            },
+        );
+        tokio::spawn(watcher.run(cancellation_token.clone()))
+    });
+
     cancellation_token.cancelled().await;
+    if let Some(watcher) = watcher {
+        // A build in progress is allowed to finish
+        let _ = watcher.await;
+    }
     [
         runtime.stop().await.map_err(Into::into),
         gateway.stop().await,
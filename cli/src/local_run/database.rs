@@ -6,7 +6,10 @@ use std::{
 
 use anyhow::Result;
 
-use db_embedded_tikv::{DbManagerWithTikv, PdConfig, TikvConfig, TikvRunnerConfig};
+use db_embedded_tikv::{
+    DbManagerWithTikv, PdConfig, TikvConfig, TikvRunnerConfig, TikvRunnerNotification,
+    DEFAULT_MAX_RESTARTS,
+};
 use mu_common::serde_support::{IpOrHostname, TcpPortAddress};
 
 pub const DATA_SUBDIR: &str = ".mu/key_value_table";
@@ -41,7 +44,27 @@ pub async fn start(project_root: PathBuf) -> Result<DbManagerWithTikv> {
             data_dir: subdir(&data_dir, "tikv_data")?,
             log_file: None,
         },
+        max_restarts: DEFAULT_MAX_RESTARTS,
     };
 
-    db_embedded_tikv::new_with_embedded_cluster(node_address, vec![], tikv_config).await
+    let mut db_manager =
+        db_embedded_tikv::new_with_embedded_cluster(node_address, vec![], tikv_config).await?;
+
+    if let Some(mut notifications) = db_manager.take_notifications() {
+        tokio::spawn(async move {
+            while let Some(notification) = notifications.recv().await {
+                match notification {
+                    TikvRunnerNotification::Restarted { process, status } => {
+                        eprintln!("Database process {process} exited with {status:?}, restarted it")
+                    }
+                    TikvRunnerNotification::GaveUp { process } => eprintln!(
+                        "Database process {process} keeps exiting, the database is unavailable \
+                        until mu is restarted"
+                    ),
+                }
+            }
+        });
+    }
+
+    Ok(db_manager)
 }
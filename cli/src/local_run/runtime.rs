@@ -1,17 +1,17 @@
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    path::PathBuf,
-};
+use std::{net::Ipv4Addr, path::PathBuf, time::Duration};
 
 use anyhow::{Context, Result};
 
 use db_embedded_tikv::DbManagerWithTikv;
 use mu_db::DeleteTable;
-use mu_gateway::{GatewayManager, GatewayManagerConfig};
+use mu_gateway::{
+    CompressionConfig, DbIdempotencyStore, FunctionError, FunctionResponse, GatewayManager,
+    GatewayManagerConfig,
+};
 use mu_runtime::{AssemblyDefinition, Runtime, RuntimeConfig};
 use mu_stack::{AssemblyID, FunctionID, Gateway, StackID};
 use mu_storage::{DeleteStorage, StorageManager};
-use musdk_common::{Request, Response};
+use musdk_common::Request;
 
 use super::StackWithID;
 
@@ -37,8 +37,17 @@ pub async fn start(
     // know how much resources they are consuming
     let runtime_config = RuntimeConfig {
         cache_path,
+        cache_max_bytes: None,
         include_function_logs: true,
         max_giga_instructions_per_call: None,
+        compiler: Default::default(),
+        max_instance_lifetime: None,
+        max_execution_time: Duration::from_secs(60).into(),
+        warm_instances_per_function: 0,
+        warm_instance_idle_timeout: None,
+        max_concurrent_invocations_per_stack: None,
+        shutdown_timeout: None,
+        wasi: Default::default(),
     };
 
     let db_manager = super::database::start(project_root).await?;
@@ -65,51 +74,43 @@ pub async fn start(
         mu_runtime::start(db_manager.clone(), storage_manager.clone(), runtime_config).await?;
 
     let mut function_defs = vec![];
-
     for func in stack.functions() {
-        let assembly_source = tokio::fs::read(&func.binary)
-            .await
-            .context("Failed to get function source")?;
-
-        function_defs.push(AssemblyDefinition::try_new(
-            AssemblyID {
-                stack_id,
-                assembly_name: func.name.clone(),
-            },
-            assembly_source.into(),
-            func.runtime,
-            func.env.clone(),
-            func.memory_limit,
-        ));
+        function_defs.push(function_definition(stack_id, func).await?);
     }
 
-    let function_defs = function_defs
-        .into_iter()
-        .collect::<Result<Vec<AssemblyDefinition>, mu_runtime::Error>>()?;
-
     runtime.add_functions(function_defs).await?;
 
     let gateway_config = GatewayManagerConfig {
-        listen_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
-        listen_port: 12012,
+        listen_addresses: vec![(Ipv4Addr::LOCALHOST, 12012).into()],
+        max_request_body_bytes: None,
+        compression: CompressionConfig::default(),
+        access_log: false,
+        rate_limit: None,
+        debug_errors: true,
+        shutdown_timeout_secs: 30,
+        idempotency_ttl_secs: 60 * 60,
     };
 
+    let db_client = db_manager
+        .make_client()
+        .await
+        .context("couldn't create database client")?;
+
     //TODO: Report usage using the notifications
-    let (gateway, _) = mu_gateway::start_without_additional_services(gateway_config, {
-        let runtime = runtime.clone();
-        move |f, r| Box::pin(handle_request(f, r, runtime.clone()))
-    })
+    let (gateway, _) = mu_gateway::start_without_additional_services(
+        gateway_config,
+        Some(Box::new(DbIdempotencyStore::new(db_client.clone()))),
+        {
+            let runtime = runtime.clone();
+            move |f, r| Box::pin(handle_request(f, r, runtime.clone()))
+        },
+    )
     .await?;
 
     gateway
         .deploy_gateways(stack_id, stack.gateways().map(ToOwned::to_owned).collect())
         .await?;
 
-    let db_client = db_manager
-        .make_client()
-        .await
-        .context("couldn't create database client")?;
-
     let mut table_actions = vec![];
     for kvt in stack.key_value_tables() {
         let table_name = kvt
@@ -138,13 +139,41 @@ pub async fn start(
     ))
 }
 
+pub async fn function_definition(
+    stack_id: StackID,
+    func: &mu_stack::Function,
+) -> Result<AssemblyDefinition> {
+    let assembly_source = tokio::fs::read(&func.binary)
+        .await
+        .context("Failed to get function source")?;
+
+    Ok(AssemblyDefinition::try_new(
+        AssemblyID {
+            stack_id,
+            assembly_name: func.name.clone(),
+        },
+        assembly_source.into(),
+        func.runtime,
+        func.env.clone(),
+        func.secrets.clone(),
+        func.memory_limit,
+    )?
+    .with_compression(func.compression)
+    .with_compiler(func.compiler))
+}
+
 async fn handle_request(
     function_id: FunctionID,
     request: Request<'_>,
     runtime: Box<dyn Runtime>,
-) -> Result<Response<'static>> {
-    runtime
-        .invoke_function(function_id, request)
+) -> Result<FunctionResponse> {
+    let response = runtime
+        .invoke_function_streaming(function_id, request)
         .await
-        .map_err(Into::into)
+        .map_err(FunctionError::from_runtime_error)?;
+
+    Ok(FunctionResponse {
+        response: response.response,
+        body_stream: response.body_stream,
+    })
 }
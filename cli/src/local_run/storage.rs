@@ -22,6 +22,9 @@ pub async fn start() -> Result<Box<dyn StorageManager>> {
                 endpoint: addr(3089),
             },
         }),
+        filesystem: None,
+        health_check: None,
+        retry: None,
     };
 
     mu_storage::start(&config).await
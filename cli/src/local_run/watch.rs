@@ -0,0 +1,406 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use mu_runtime::Runtime;
+use mu_stack::StackID;
+use tokio_util::sync::CancellationToken;
+
+use crate::mu_manifest::{BuildMode, Function, MuManifest, MU_MANIFEST_FILE_NAME};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Saving in an editor or switching branches touches several files in quick
+// succession, so we only rebuild once nothing has changed for this long.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Receives functions after they're rebuilt. This is the local runtime,
+/// except in tests.
+#[async_trait]
+pub trait FunctionDeployer: Send + Sync {
+    async fn deploy(&self, functions: Vec<mu_stack::Function>) -> Result<()>;
+    async fn remove(&self, names: Vec<String>) -> Result<()>;
+}
+
+pub struct RuntimeDeployer {
+    pub runtime: Box<dyn Runtime>,
+    pub stack_id: StackID,
+}
+
+#[async_trait]
+impl FunctionDeployer for RuntimeDeployer {
+    async fn deploy(&self, functions: Vec<mu_stack::Function>) -> Result<()> {
+        let mut function_defs = vec![];
+        for func in &functions {
+            function_defs.push(super::runtime::function_definition(self.stack_id, func).await?);
+        }
+
+        Ok(self.runtime.add_functions(function_defs).await?)
+    }
+
+    async fn remove(&self, names: Vec<String>) -> Result<()> {
+        Ok(self.runtime.remove_functions(self.stack_id, names).await?)
+    }
+}
+
+// Compared between polls to detect changes. File counts and sizes catch edits
+// made within the file system's timestamp resolution, as well as deletions.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Fingerprint {
+    files: usize,
+    total_size: u64,
+    latest_modification: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> Self {
+        let mut fingerprint = Self::default();
+        fingerprint.add(path);
+        fingerprint
+    }
+
+    fn add(&mut self, path: &Path) {
+        let Ok(metadata) = fs::metadata(path) else {
+            return;
+        };
+
+        if metadata.is_dir() {
+            let Ok(entries) = fs::read_dir(path) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                if !is_ignored(&entry.path()) {
+                    self.add(&entry.path());
+                }
+            }
+        } else {
+            self.files += 1;
+            self.total_size += metadata.len();
+            self.latest_modification = self.latest_modification.max(metadata.modified().ok());
+        }
+    }
+}
+
+// Build output changes with every build, which would trigger another one
+fn is_ignored(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name == "target" || name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+#[derive(Default)]
+struct PendingChanges {
+    manifest: bool,
+    functions: HashSet<String>,
+}
+
+impl PendingChanges {
+    fn is_empty(&self) -> bool {
+        !self.manifest && self.functions.is_empty()
+    }
+}
+
+/// Rebuilds functions when their sources change and redeploys them. Changes
+/// to the manifest redeploy every function and remove the ones that are gone
+/// from it; gateways and tables are only deployed on startup.
+pub struct Watcher<B, D> {
+    manifest: MuManifest,
+    project_root: PathBuf,
+    build_mode: BuildMode,
+    // Shared with the blocking tasks running builds
+    build: Arc<B>,
+    deployer: D,
+    manifest_fingerprint: Fingerprint,
+    source_fingerprints: HashMap<String, Fingerprint>,
+}
+
+impl<B, D> Watcher<B, D>
+where
+    B: Fn(&Function, BuildMode, &Path) -> Result<()> + Send + Sync + 'static,
+    D: FunctionDeployer,
+{
+    /// Sources are snapshotted immediately, so only changes made after this
+    /// call trigger a rebuild.
+    pub fn new(
+        manifest: MuManifest,
+        project_root: PathBuf,
+        build_mode: BuildMode,
+        build: B,
+        deployer: D,
+    ) -> Self {
+        let mut watcher = Self {
+            manifest,
+            project_root,
+            build_mode,
+            build: Arc::new(build),
+            deployer,
+            manifest_fingerprint: Fingerprint::default(),
+            source_fingerprints: HashMap::new(),
+        };
+        watcher.manifest_fingerprint = Fingerprint::of(&watcher.manifest_path());
+        watcher.source_fingerprints = watcher.current_source_fingerprints();
+        watcher
+    }
+
+    pub async fn run(mut self, cancellation_token: CancellationToken) {
+        let mut pending = PendingChanges::default();
+        let mut last_change = Instant::now();
+
+        loop {
+            tokio::select! {
+                _ = cancellation_token.cancelled() => return,
+                _ = tokio::time::sleep(POLL_INTERVAL) => (),
+            }
+
+            if self.poll(&mut pending) {
+                last_change = Instant::now();
+            }
+
+            if !pending.is_empty() && last_change.elapsed() >= DEBOUNCE {
+                self.redeploy(std::mem::take(&mut pending)).await;
+            }
+        }
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.project_root.join(MU_MANIFEST_FILE_NAME)
+    }
+
+    fn current_source_fingerprints(&self) -> HashMap<String, Fingerprint> {
+        self.manifest
+            .all_functions()
+            .map(|f| {
+                let fingerprint = Fingerprint::of(&f.root_dir(&self.project_root));
+                (f.name.clone(), fingerprint)
+            })
+            .collect()
+    }
+
+    // Returns whether anything changed since the last poll
+    fn poll(&mut self, pending: &mut PendingChanges) -> bool {
+        let mut changed = false;
+
+        let manifest_fingerprint = Fingerprint::of(&self.manifest_path());
+        if manifest_fingerprint != self.manifest_fingerprint {
+            self.manifest_fingerprint = manifest_fingerprint;
+            pending.manifest = true;
+            changed = true;
+        }
+
+        for (name, fingerprint) in self.current_source_fingerprints() {
+            if self.source_fingerprints.insert(name.clone(), fingerprint) != Some(fingerprint) {
+                pending.functions.insert(name);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    async fn redeploy(&mut self, mut changes: PendingChanges) {
+        if changes.manifest {
+            match self.reload_manifest().await {
+                // Any function's definition may have changed
+                Ok(()) => changes
+                    .functions
+                    .extend(self.manifest.all_functions().map(|f| f.name.clone())),
+                Err(e) => eprintln!("Failed to reload {MU_MANIFEST_FILE_NAME}: {e:?}"),
+            }
+        }
+
+        let mut built = HashSet::new();
+        let functions = self
+            .manifest
+            .all_functions()
+            .filter(|f| changes.functions.contains(&f.name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for function in functions {
+            println!("Rebuilding {}", function.name);
+            match self.build(function.clone()).await {
+                Ok(()) => {
+                    built.insert(function.name);
+                }
+                Err(e) => eprintln!("Failed to build {}: {e:?}", function.name),
+            }
+        }
+
+        if built.is_empty() {
+            return;
+        }
+
+        let stack = match self
+            .manifest
+            .generate_stack_manifest_for_local_run(self.build_mode, &self.project_root)
+        {
+            Ok(stack) => stack,
+            Err(e) => {
+                eprintln!("Failed to generate stack definition: {e:?}");
+                return;
+            }
+        };
+
+        let functions = stack
+            .functions()
+            .filter(|f| built.contains(&f.name))
+            .cloned()
+            .collect::<Vec<_>>();
+        let names = functions
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match self.deployer.deploy(functions).await {
+            Ok(()) => println!("Redeployed {names}"),
+            Err(e) => eprintln!("Failed to redeploy {names}: {e:?}"),
+        }
+    }
+
+    // Builds run external tools and can take minutes, so they're kept off
+    // the async workers
+    async fn build(&self, function: Function) -> Result<()> {
+        let build = self.build.clone();
+        let build_mode = self.build_mode;
+        let project_root = self.project_root.clone();
+        tokio::task::spawn_blocking(move || build(&function, build_mode, &project_root))
+            .await
+            .context("Build task panicked")?
+    }
+
+    async fn reload_manifest(&mut self) -> Result<()> {
+        let mut file = fs::File::open(self.manifest_path())?;
+        let manifest = MuManifest::read(&mut file)?;
+
+        let names = manifest
+            .all_functions()
+            .map(|f| f.name.clone())
+            .collect::<HashSet<_>>();
+        let removed = self
+            .manifest
+            .all_functions()
+            .filter(|f| !names.contains(&f.name))
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>();
+
+        self.manifest = manifest;
+        self.source_fingerprints = self.current_source_fingerprints();
+
+        if !removed.is_empty() {
+            let removed_names = removed.join(", ");
+            self.deployer
+                .remove(removed)
+                .await
+                .context("Failed to remove functions")?;
+            println!("Removed {removed_names}");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    struct RecordingDeployer(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl FunctionDeployer for RecordingDeployer {
+        async fn deploy(&self, functions: Vec<mu_stack::Function>) -> Result<()> {
+            let mut deployed = self.0.lock().unwrap();
+            deployed.extend(functions.into_iter().map(|f| f.name));
+            Ok(())
+        }
+
+        async fn remove(&self, _names: Vec<String>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    const MANIFEST: &str = r#"
+name: test
+version: 0.1.0
+dev_id: p_67e55044-10b1-426f-9247-bb680e5fe0c8
+services:
+  - type: Function
+    name: greeter
+    lang: Rust
+    runtime: wasi1.0
+    memory_limit: 64MiB
+    env: {}
+    env_dev: {}
+"#;
+
+    fn temp_project() -> PathBuf {
+        let rand: [u8; 8] = rand::random();
+        let name = rand
+            .into_iter()
+            .fold(String::from("mu-watch-"), |a, i| format!("{a}{i:02x}"));
+        let project_root = std::env::temp_dir().join(name);
+
+        let src = project_root.join("functions/greeter/src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(project_root.join(MU_MANIFEST_FILE_NAME), MANIFEST).unwrap();
+
+        project_root
+    }
+
+    #[tokio::test]
+    async fn editing_a_source_file_rebuilds_and_redeploys_the_function() {
+        let project_root = temp_project();
+        let mut file = fs::File::open(project_root.join(MU_MANIFEST_FILE_NAME)).unwrap();
+        let manifest = MuManifest::read(&mut file).unwrap();
+
+        let builds = Arc::new(Mutex::new(vec![]));
+        let deployed = Arc::new(Mutex::new(vec![]));
+        let watcher = Watcher::new(
+            manifest,
+            project_root.clone(),
+            BuildMode::Debug,
+            {
+                let builds = builds.clone();
+                move |f: &Function, _, _: &Path| {
+                    builds.lock().unwrap().push(f.name.clone());
+                    Ok(())
+                }
+            },
+            RecordingDeployer(deployed.clone()),
+        );
+
+        let cancellation_token = CancellationToken::new();
+        let watcher = tokio::spawn(watcher.run(cancellation_token.clone()));
+
+        // Nothing is rebuilt until a source file changes
+        tokio::time::sleep(DEBOUNCE * 2).await;
+        assert!(builds.lock().unwrap().is_empty());
+
+        fs::write(
+            project_root.join("functions/greeter/src/main.rs"),
+            "fn main() { println!(\"changed\"); }",
+        )
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while deployed.lock().unwrap().is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        cancellation_token.cancel();
+        watcher.await.unwrap();
+
+        assert_eq!(*builds.lock().unwrap(), vec!["greeter".to_string()]);
+        assert_eq!(*deployed.lock().unwrap(), vec!["greeter".to_string()]);
+
+        fs::remove_dir_all(project_root).unwrap();
+    }
+}
@@ -85,6 +85,18 @@ impl MarketplaceClient {
         region_pda
     }
 
+    pub fn get_region_rates_pda(&self, region_pda: &Pubkey, rates_version: u32) -> Pubkey {
+        let (region_rates_pda, _) = Pubkey::find_program_address(
+            &[
+                b"rates",
+                &region_pda.to_bytes(),
+                &rates_version.to_le_bytes(),
+            ],
+            &self.program.id(),
+        );
+        region_rates_pda
+    }
+
     pub fn get_request_signer_pda(
         &self,
         user_wallet: &Pubkey,
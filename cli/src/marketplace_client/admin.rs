@@ -9,6 +9,7 @@ pub fn initialize(
     token_mint: Pubkey,
     commission_rate_micros: u32,
     provider_deposit: u64,
+    deleted_stack_grace_period_secs: u32,
 ) -> Result<()> {
     let state_pda = client.get_mu_state_pda();
     if client.account_exists(&state_pda)? {
@@ -28,6 +29,7 @@ pub fn initialize(
         .args(marketplace::instruction::Initialize {
             commission_rate_micros,
             provider_deposit,
+            deleted_stack_grace_period_secs,
         })
         .accounts(marketplace::accounts::Initialize {
             authority: signer.pubkey(),
@@ -61,6 +63,105 @@ pub fn update_deposit(client: &MarketplaceClient, signer: &dyn Signer, deposit:
     Ok(())
 }
 
+pub fn update_deleted_stack_grace_period(
+    client: &MarketplaceClient,
+    signer: &dyn Signer,
+    deleted_stack_grace_period_secs: u32,
+) -> Result<()> {
+    client
+        .program
+        .request()
+        .args(marketplace::instruction::UpdateDeletedStackGracePeriod {
+            deleted_stack_grace_period_secs,
+        })
+        .accounts(marketplace::accounts::UpdateDeletedStackGracePeriod {
+            authority: signer.pubkey(),
+            state: client.get_mu_state_pda(),
+        })
+        .send_with_spinner_and_config(Default::default())
+        .context("Failed to send grace period update transaction")?;
+
+    Ok(())
+}
+
+pub fn migrate_state(
+    client: &MarketplaceClient,
+    signer: &dyn Signer,
+    deleted_stack_grace_period_secs: u32,
+) -> Result<()> {
+    client
+        .program
+        .request()
+        .args(marketplace::instruction::MigrateState {
+            deleted_stack_grace_period_secs,
+        })
+        .accounts(marketplace::accounts::MigrateState {
+            authority: signer.pubkey(),
+            state: client.get_mu_state_pda(),
+            system_program: system_program::id(),
+        })
+        .send_with_spinner_and_config(Default::default())
+        .context("Failed to send migration transaction")?;
+
+    Ok(())
+}
+
+pub fn migrate_region(
+    client: &MarketplaceClient,
+    provider_owner: &dyn Signer,
+    region_num: u32,
+) -> Result<()> {
+    let region = client.get_region_pda(&provider_owner.pubkey(), region_num);
+    if !client.account_exists(&region)? {
+        bail!("Region doesn't exist");
+    }
+
+    client
+        .program
+        .request()
+        .args(marketplace::instruction::MigrateRegion {})
+        .accounts(marketplace::accounts::MigrateRegion {
+            provider: client.get_provider_pda(provider_owner.pubkey()),
+            region,
+            region_rates: client.get_region_rates_pda(&region, 1),
+            owner: provider_owner.pubkey(),
+            system_program: system_program::id(),
+        })
+        .send_with_spinner_and_config(Default::default())
+        .context("Failed to send migration transaction")?;
+
+    Ok(())
+}
+
+pub fn migrate_stack(client: &MarketplaceClient, payer: &dyn Signer, stack: Pubkey) -> Result<()> {
+    let account = client
+        .program
+        .rpc()
+        .get_account(&stack)
+        .context("Failed to fetch stack")?;
+
+    // Stacks start with their user and region in every layout, after the
+    // account discriminator
+    let Some(region) = account.data.get(40..72) else {
+        bail!("Account is not a stack");
+    };
+
+    client
+        .program
+        .request()
+        .args(marketplace::instruction::MigrateStack {})
+        .accounts(marketplace::accounts::MigrateStack {
+            region: Pubkey::try_from(region)?,
+            stack,
+            payer: payer.pubkey(),
+            system_program: system_program::id(),
+        })
+        .send_with_spinner_and_config(Default::default())
+        .context("Failed to send migration transaction")?;
+
+    Ok(())
+}
+
 pub fn create_provider_authorizer(
     client: &MarketplaceClient,
     authority: &dyn Signer,
@@ -39,7 +39,7 @@ pub fn deploy(
             (
                 _,
                 Some(marketplace::Stack {
-                    state: marketplace::StackState::Deleted,
+                    state: marketplace::StackState::Deleted { .. },
                     ..
                 }),
             ) => bail!(
@@ -9,7 +9,7 @@ use std::{
 
 use anyhow::{anyhow, bail, Context, Result};
 use beau_collector::BeauCollector;
-use mu_stack::{AssemblyRuntime, Gateway, NameAndDelete, Stack, StackID};
+use mu_stack::{AssemblyRuntime, Gateway, NameAndDelete, Stack, StackID, WasmCompiler};
 use serde::{Deserialize, Serialize};
 
 pub const MU_MANIFEST_FILE_NAME: &str = "mu.yaml";
@@ -32,7 +32,7 @@ impl MuManifest {
     }
 
     #[allow(dead_code)]
-    fn all_functions(&self) -> impl Iterator<Item = &Function> {
+    pub fn all_functions(&self) -> impl Iterator<Item = &Function> {
         self.services.iter().filter_map(|s| {
             if let Service::Function(f) = s {
                 Some(f)
@@ -82,6 +82,17 @@ impl MuManifest {
         )
     }
 
+    /// Builds the stack without building or uploading any functions; each
+    /// function's binary is the path its wasm module would be built to.
+    pub fn generate_stack_manifest_for_validation(&self, project_root: &Path) -> Result<Stack> {
+        self.generate_stack_manifest(
+            BuildMode::Release,
+            ArtifactGenerationMode::Publish,
+            project_root,
+            Ok,
+        )
+    }
+
     fn generate_stack_manifest<F>(
         &self,
         build_mode: BuildMode,
@@ -129,7 +140,10 @@ impl MuManifest {
                             binary,
                             runtime: f.runtime,
                             env,
+                            secrets: f.secrets.clone(),
                             memory_limit: f.memory_limit,
+                            compression: Default::default(),
+                            compiler: f.compiler,
                         })
                     }
                 })
@@ -185,8 +199,16 @@ pub struct Function {
     pub runtime: AssemblyRuntime,
     pub env: HashMap<String, String>,
     pub env_dev: HashMap<String, String>,
+    #[serde(default)]
+    pub secrets: HashMap<String, String>,
     #[serde(serialize_with = "custom_byte_unit_serialization::serialize")]
     pub memory_limit: byte_unit::Byte,
+    /// Compiles the function with this backend instead of the region's
+    /// default. LLVM produces the fastest code but compiles slowly,
+    /// Cranelift compiles faster, and Singlepass compiles fastest but
+    /// produces the slowest code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiler: Option<WasmCompiler>,
 }
 
 impl Function {
@@ -311,6 +333,21 @@ mod custom_byte_unit_serialization {
     }
 }
 
+/// Reads the manifest at `path`, which is either the manifest file itself or
+/// the project directory containing it.
+pub fn read_manifest_at(path: &Path) -> Result<(MuManifest, PathBuf)> {
+    let (manifest_path, project_root) = if path.is_dir() {
+        (path.join(MU_MANIFEST_FILE_NAME), path.to_owned())
+    } else {
+        let project_root = path.parent().unwrap_or_else(|| Path::new("."));
+        (path.to_owned(), project_root.to_owned())
+    };
+
+    let mut file = std::fs::File::open(&manifest_path)
+        .with_context(|| format!("Failed to open `{}`", manifest_path.display()))?;
+    Ok((MuManifest::read(&mut file)?, project_root))
+}
+
 pub fn read_manifest() -> Result<(MuManifest, PathBuf)> {
     let mut path = std::env::current_dir()?;
 
@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use mu_cli::{execute, Arguments};
+
+const FUNCTION: &str = r#"
+  - type: Function
+    name: greeter
+    lang: Rust
+    runtime: wasi1.0
+    memory_limit: 64MiB
+    env: {}
+    env_dev: {}
+"#;
+
+struct Manifest(PathBuf);
+
+impl Manifest {
+    fn new(services: &str) -> Self {
+        let rand: [u8; 8] = rand::random();
+        let name = rand
+            .into_iter()
+            .fold(String::from("mu-stack-validate-"), |a, i| {
+                format!("{a}{i:02x}")
+            });
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let contents = format!(
+            "name: test\nversion: 0.1.0\ndev_id: p_67e55044-10b1-426f-9247-bb680e5fe0c8\nservices:{services}"
+        );
+        std::fs::write(dir.join("mu.yaml"), contents).unwrap();
+
+        Self(dir)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let path = self.0.display().to_string();
+        let args = Arguments::try_parse_from(["mu", "stack", "validate", &path]).unwrap();
+        execute(args)
+    }
+}
+
+impl Drop for Manifest {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).unwrap();
+    }
+}
+
+fn validation_error(services: &str) -> String {
+    Manifest::new(services).validate().unwrap_err().to_string()
+}
+
+#[test]
+fn valid_manifest_passes() {
+    let manifest = Manifest::new(&format!(
+        r#"{FUNCTION}
+  - type: Gateway
+    name: gw
+    endpoints:
+      /greet/{{name}}:
+        get: greeter.greet_user
+"#
+    ));
+
+    manifest.validate().unwrap();
+
+    // The manifest file itself can be given instead of its directory
+    let path = manifest.0.join("mu.yaml").display().to_string();
+    let args = Arguments::try_parse_from(["mu", "stack", "validate", &path]).unwrap();
+    execute(args).unwrap();
+}
+
+#[test]
+fn malformed_gateway_paths_are_reported() {
+    let error = validation_error(&format!(
+        r#"{FUNCTION}
+  - type: Gateway
+    name: gw
+    endpoints:
+      /greet/{{name:
+        get: greeter.greet_user
+"#
+    ));
+
+    assert!(
+        error.contains("Invalid endpoint path '/greet/{name'"),
+        "{error}"
+    );
+}
+
+#[test]
+fn duplicate_service_names_are_reported() {
+    let error = validation_error(&format!("{FUNCTION}{FUNCTION}"));
+
+    assert!(
+        error.contains("Duplicate function name 'greeter'"),
+        "{error}"
+    );
+}
+
+#[test]
+fn unknown_functions_in_gateways_are_reported() {
+    let error = validation_error(&format!(
+        r#"{FUNCTION}
+  - type: Gateway
+    name: gw
+    endpoints:
+      /greet:
+        get: missing.greet_user
+"#
+    ));
+
+    assert!(
+        error.contains("Unknown function name 'missing' in gateway 'gw'"),
+        "{error}"
+    );
+}
+
+#[test]
+fn all_errors_are_reported_together() {
+    let error = validation_error(&format!(
+        r#"{FUNCTION}{FUNCTION}
+  - type: Gateway
+    name: gw
+    endpoints:
+      /greet/{{}}:
+        get: missing.greet_user
+"#
+    ));
+
+    assert!(error.contains("has 3 error(s)"), "{error}");
+}
+
+#[test]
+fn unparseable_manifests_are_rejected() {
+    let error = validation_error("\n  - type: Unknown\n");
+
+    assert!(error.contains("Invalid mu manifest file"), "{error}");
+}
@@ -2,22 +2,35 @@ use actix_web::{
     guard,
     http::header::HeaderMap,
     services,
-    web::{self, Json, PayloadConfig},
-    HttpRequest,
+    web::{self, Bytes, Json, PayloadConfig},
+    HttpRequest, HttpResponse,
 };
 use anyhow::Result;
 use api_common::{
-    requests::{UploadFunctionRequest, UploadFunctionResponse},
+    requests::{
+        FunctionLogEntry, GetCustomMetricsRequest, GetCustomMetricsResponse, LogLevel,
+        SetSecretRequest, StreamLogsRequest, UploadFunctionRequest, UploadFunctionResponse,
+    },
     ApiRequestTemplate, SIGNATURE_HEADER_NAME,
 };
-use log::error;
+use futures::Stream;
+use log::{error, warn};
+use mu_common::serde_support::ConfigDuration;
+use mu_db::{DbClient, Key};
 use mu_gateway::HttpServiceFactoryBuilder;
-use mu_stack::StackOwner;
+use mu_runtime::FunctionLog;
+use mu_stack::{StackID, StackOwner};
 use mu_storage::StorageClient;
 use serde::Deserialize;
 use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::{self, error::RecvError};
 
-use crate::stack::blockchain_monitor::BlockchainMonitor;
+use crate::stack::{
+    blockchain_monitor::BlockchainMonitor,
+    request_signer_cache::{RequestFreshness, RequestSignerCache},
+    usage_aggregator, ApiRequestSigner,
+};
 
 pub const FUNCTION_STORAGE_NAME: &str = "FUNCTIONS";
 
@@ -37,16 +50,25 @@ pub fn service_factory(config: ApiConfig) -> impl HttpServiceFactoryBuilder {
                     headers.contains_key(SIGNATURE_HEADER_NAME)
                 })))
                 .to(handle_request),
-            web::resource("/api").to(|| async { handle_bad_request() })
+            web::resource("/api").to(|| async { handle_bad_request() }),
+            web::resource("/api/logs")
+                .guard(guard::All(guard::Post()).and(guard::fn_guard(|ctx| {
+                    let headers = ctx.head().headers();
+                    headers.contains_key(SIGNATURE_HEADER_NAME)
+                })))
+                .to(handle_stream_logs_request),
+            web::resource("/api/logs").to(|| async { handle_bad_request() })
         ]
     }
 }
 
 #[derive(Clone)]
 pub struct DependencyAccessor {
-    //pub request_signer_cache: Box<dyn RequestSignerCache>,
+    pub request_signer_cache: Box<dyn RequestSignerCache>,
     pub blockchain_monitor: Box<dyn BlockchainMonitor>,
     pub storage_client: Box<dyn StorageClient>,
+    pub db_client: Box<dyn DbClient>,
+    pub function_logs: broadcast::Sender<FunctionLog>,
 }
 
 async fn handle_request(
@@ -64,21 +86,23 @@ async fn handle_request(
             .map_err(|_| bad_request("can not deserialize request"))?;
 
         if let Some(owner) = request.user {
-            let _pubkey = verify_signature(&owner, headers, &payload)?;
+            let pubkey = verify_signature(&owner, headers, &payload)?;
             //verify_stack_ownership(&stack_id, &pubkey, &dependency_accessor).await?; //TODO
             verify_escrow_account_balance(dependency_accessor.blockchain_monitor.clone(), &owner)
                 .await?;
+            verify_freshness(
+                dependency_accessor.request_signer_cache.as_ref(),
+                &pubkey,
+                &request,
+            )
+            .await?;
         } else {
             return Err(bad_request("invalid signature"));
         }
 
-        execute_request(
-            request.user,
-            request,
-            dependency_accessor.storage_client.clone(),
-        )
-        .await
-        .map(Json)
+        execute_request(request.user, request, &dependency_accessor)
+            .await
+            .map(Json)
     }
 
     match helper(request, payload, dependency_accessor).await {
@@ -87,31 +111,174 @@ async fn handle_request(
     }
 }
 
+async fn handle_stream_logs_request(
+    request: HttpRequest,
+    payload: String,
+    dependency_accessor: web::Data<DependencyAccessor>,
+) -> HttpResponse {
+    async fn helper(
+        request: HttpRequest,
+        payload: String,
+        dependency_accessor: web::Data<DependencyAccessor>,
+    ) -> Result<HttpResponse, Error> {
+        let headers = request.headers();
+        let request = serde_json::from_str::<ApiRequestTemplate>(payload.as_str())
+            .map_err(|_| bad_request("can not deserialize request"))?;
+
+        if request.request != "stream_logs" {
+            return Err(bad_request("unknown request"));
+        }
+
+        let Some(owner) = request.user else {
+            return Err(bad_request("invalid signature"));
+        };
+        let pubkey = verify_signature(&owner, headers, &payload)?;
+
+        let req = serde_json::from_value::<StreamLogsRequest>(request.params.clone())
+            .map_err(|_| bad_request("invalid input"))?;
+
+        verify_stack_owner(
+            dependency_accessor.blockchain_monitor.as_ref(),
+            req.stack_id,
+            &owner,
+        )
+        .await?;
+        verify_freshness(
+            dependency_accessor.request_signer_cache.as_ref(),
+            &pubkey,
+            &request,
+        )
+        .await?;
+
+        let receiver = dependency_accessor.function_logs.subscribe();
+        let stream = function_log_stream(receiver, req.stack_id, req.level);
+
+        Ok(HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .streaming(stream))
+    }
+
+    match helper(request, payload, dependency_accessor).await {
+        Ok(response) => response,
+        Err((response, status_code)) => HttpResponse::build(status_code).json(response),
+    }
+}
+
+// Streams the logs of a stack's functions as JSON lines, until the receiver
+// is closed
+fn function_log_stream(
+    receiver: broadcast::Receiver<FunctionLog>,
+    stack_id: StackID,
+    level: LogLevel,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    futures::stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(log) => {
+                    if log.function_id.stack_id != stack_id {
+                        continue;
+                    }
+
+                    let entry = FunctionLogEntry {
+                        function: log.function_id.assembly_name,
+                        level: log_level(log.level),
+                        body: log.body,
+                    };
+                    if entry.level > level {
+                        continue;
+                    }
+
+                    let mut line = match serde_json::to_vec(&entry) {
+                        Ok(l) => l,
+                        Err(e) => {
+                            error!("Failed to serialize function log: {e:?}");
+                            continue;
+                        }
+                    };
+                    line.push(b'\n');
+                    return Some((Ok::<_, actix_web::Error>(Bytes::from(line)), receiver));
+                }
+
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Log stream for stack {stack_id} skipped {count} logs");
+                }
+
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+fn log_level(level: log::Level) -> LogLevel {
+    match level {
+        log::Level::Error => LogLevel::Error,
+        log::Level::Warn => LogLevel::Warn,
+        log::Level::Info => LogLevel::Info,
+        log::Level::Debug => LogLevel::Debug,
+        log::Level::Trace => LogLevel::Trace,
+    }
+}
+
+async fn verify_stack_owner(
+    blockchain_monitor: &dyn BlockchainMonitor,
+    stack_id: StackID,
+    owner: &StackOwner,
+) -> Result<(), Error> {
+    match blockchain_monitor.get_metadata(stack_id).await {
+        Ok(Some(metadata)) if metadata.owner() == *owner => Ok(()),
+        Ok(_) => Err(bad_request("stack not found")),
+        Err(e) => {
+            error!("can not fetch stack metadata: {e:?}");
+            Err(internal_server_error("can not fetch stack"))
+        }
+    }
+}
+
 fn verify_signature(
     user: &StackOwner,
     headers: &HeaderMap,
-    payload: &String,
-) -> Result<ed25519_dalek::PublicKey, Error> {
-    let pubkey = match user {
-        StackOwner::Solana(pk) => ed25519_dalek::PublicKey::from_bytes(pk)
-            .map_err(|_| internal_server_error("parsing pubkey"))?,
-    };
-
+    payload: &str,
+) -> Result<Pubkey, Error> {
     let signature_header = headers
         .get(SIGNATURE_HEADER_NAME)
-        .ok_or_else(|| bad_request("signature header not found"))?;
-
-    let signature_bytes = base64::decode(signature_header)
+        .ok_or_else(|| bad_request("signature header not found"))?
+        .to_str()
         .map_err(|_| bad_request("invalid base64 encoded signature"))?;
 
-    let signature = ed25519_dalek::Signature::from_bytes(&signature_bytes[..])
-        .map_err(|_| bad_request("invalid signature"))?;
+    // Request signers are only tracked for Solana stacks so far
+    let Some(pubkey) = user.solana_public_key() else {
+        return Err(bad_request("PWR accounts can't sign requests yet"));
+    };
 
-    pubkey
-        .verify_strict(payload.as_bytes(), &signature)
+    api_common::verify_request(payload.as_bytes(), signature_header, Some(*user))
         .map_err(|_| bad_request("invalid signature"))?;
 
-    Ok(pubkey)
+    Ok(Pubkey::new_from_array(pubkey))
+}
+
+async fn verify_freshness(
+    request_signer_cache: &dyn RequestSignerCache,
+    pubkey: &Pubkey,
+    request: &ApiRequestTemplate,
+) -> Result<(), Error> {
+    let signer = ApiRequestSigner::Solana(*pubkey);
+
+    match request_signer_cache
+        .check_request_freshness(signer, request.nonce, request.timestamp)
+        .await
+    {
+        Ok(RequestFreshness::Fresh) => Ok(()),
+        Ok(RequestFreshness::Stale) => Err(bad_request("request timestamp is out of range")),
+        Ok(RequestFreshness::Replayed) => Err(bad_request("request was already received")),
+        Ok(RequestFreshness::Overloaded) => Err((
+            json!("too many requests, try again later"),
+            http::StatusCode::TOO_MANY_REQUESTS,
+        )),
+        Err(e) => {
+            error!("can not check request freshness: {e:?}");
+            Err(internal_server_error("can not check request"))
+        }
+    }
 }
 
 async fn verify_escrow_account_balance(
@@ -182,11 +349,22 @@ fn internal_server_error(description: &'static str) -> Error {
 async fn execute_request(
     user: Option<StackOwner>,
     request: ApiRequestTemplate,
-    storage_client: Box<dyn StorageClient>,
+    dependency_accessor: &DependencyAccessor,
 ) -> ExecutionResult {
     match request.request.as_str() {
         // "echo" => execute_echo(request.params),
-        "upload_function" => execute_upload_function(request.params, user, storage_client).await,
+        "upload_function" => {
+            execute_upload_function(
+                request.params,
+                user,
+                dependency_accessor.storage_client.clone(),
+            )
+            .await
+        }
+        "set_secret" => execute_set_secret(request.params, user, dependency_accessor).await,
+        "get_custom_metrics" => {
+            execute_get_custom_metrics(request.params, user, dependency_accessor).await
+        }
         _ => Err(bad_request("unknown request")),
     }
 }
@@ -249,6 +427,7 @@ async fn execute_upload_function(
             FUNCTION_STORAGE_NAME,
             &file_id,
             &mut bytes.as_slice(),
+            None,
         )
         .await
     {
@@ -265,7 +444,152 @@ async fn execute_upload_function(
     }
 }
 
+async fn execute_set_secret(
+    params: serde_json::Value,
+    user: Option<StackOwner>,
+    dependency_accessor: &DependencyAccessor,
+) -> ExecutionResult {
+    let Some(user) = user else {
+        return Err(bad_request("this request needs user field"));
+    };
+
+    let req = serde_json::from_value::<SetSecretRequest>(params)
+        .map_err(|_| bad_request("invalid input"))?;
+
+    if req.name.is_empty() || req.name.contains('=') || req.name.contains('\0') {
+        return Err(bad_request("invalid secret name"));
+    }
+    if req.value.contains('\0') {
+        return Err(bad_request("secret values can't contain null characters"));
+    }
+
+    verify_stack_owner(
+        dependency_accessor.blockchain_monitor.as_ref(),
+        req.stack_id,
+        &user,
+    )
+    .await?;
+
+    if let Err(e) = dependency_accessor
+        .db_client
+        .put(
+            Key::secret(req.stack_id, &req.name),
+            req.value.into_bytes(),
+            false,
+            None,
+        )
+        .await
+    {
+        error!("Failed to store secret: {e:?}");
+        return Err(internal_server_error("failed to store secret"));
+    }
+
+    Ok(json!({}))
+}
+
+async fn execute_get_custom_metrics(
+    params: serde_json::Value,
+    user: Option<StackOwner>,
+    dependency_accessor: &DependencyAccessor,
+) -> ExecutionResult {
+    let Some(user) = user else {
+        return Err(bad_request("this request needs user field"));
+    };
+
+    let req = serde_json::from_value::<GetCustomMetricsRequest>(params)
+        .map_err(|_| bad_request("invalid input"))?;
+
+    verify_stack_owner(
+        dependency_accessor.blockchain_monitor.as_ref(),
+        req.stack_id,
+        &user,
+    )
+    .await?;
+
+    let metrics = match usage_aggregator::custom_metric_totals(
+        dependency_accessor.db_client.as_ref(),
+        req.stack_id,
+    )
+    .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Failed to read custom metrics: {e:?}");
+            return Err(internal_server_error("failed to read custom metrics"));
+        }
+    };
+
+    match serde_json::to_value(GetCustomMetricsResponse { metrics }) {
+        Ok(r) => Ok(r),
+        Err(e) => {
+            error!("Failed to serialize response: {e:?}");
+            Err(internal_server_error("failed to serialize response"))
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ApiConfig {
     payload_size_limit: byte_unit::Byte,
+
+    /// How far a signed request's timestamp may be from our clock.
+    pub max_request_clock_skew: ConfigDuration,
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use mu_stack::AssemblyID;
+
+    use super::*;
+
+    fn log(stack_id: StackID, level: log::Level, body: &str) -> FunctionLog {
+        FunctionLog {
+            function_id: AssemblyID {
+                stack_id,
+                assembly_name: "func".to_string(),
+            },
+            level,
+            body: body.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn function_log_stream_sends_matching_logs_as_json_lines() {
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let other_stack_id = StackID::SolanaPublicKey([2; 32]);
+
+        let (sender, receiver) = broadcast::channel(16);
+        let stream = function_log_stream(receiver, stack_id, LogLevel::Info);
+
+        sender
+            .send(log(stack_id, log::Level::Info, "first"))
+            .unwrap();
+        sender
+            .send(log(other_stack_id, log::Level::Info, "other stack"))
+            .unwrap();
+        sender
+            .send(log(stack_id, log::Level::Debug, "too verbose"))
+            .unwrap();
+        sender
+            .send(log(stack_id, log::Level::Error, "second"))
+            .unwrap();
+        drop(sender);
+
+        let lines = stream.map(|line| line.unwrap()).collect::<Vec<_>>().await;
+        let entries = lines
+            .iter()
+            .map(|line| {
+                assert!(line.ends_with(b"\n"));
+                serde_json::from_slice::<FunctionLogEntry>(line).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(2, entries.len());
+        assert_eq!("func", entries[0].function);
+        assert_eq!(LogLevel::Info, entries[0].level);
+        assert_eq!("first", entries[0].body);
+        assert_eq!(LogLevel::Error, entries[1].level);
+        assert_eq!("second", entries[1].body);
+    }
 }
@@ -8,7 +8,7 @@ use config::{Config, Environment, File, FileFormat};
 use mu_db::DbConfig;
 
 use mu_gateway::GatewayManagerConfig;
-use mu_runtime::RuntimeConfig;
+use mu_runtime::{ReloadableRuntimeConfig, RuntimeConfig, WasiConfig, WasmCompiler};
 use mu_storage::StorageConfig;
 use serde::Deserialize;
 
@@ -32,9 +32,61 @@ pub struct SystemConfig(
     pub ApiConfig,
 );
 
-pub fn initialize_config() -> Result<SystemConfig> {
+pub fn initialize_config() -> Result<(Config, SystemConfig)> {
+    let config = read_config()?;
+
+    let connection_manager_config = config
+        .get("connection_manager")
+        .context("Invalid connection_manager config")?;
+
+    let membership_config = config
+        .get("membership")
+        .context("Invalid membership config")?;
+
+    let db_config = config.get("db").context("Invalid database config")?;
+
+    let storage_config = config.get("storage").context("Invalid storage config")?;
+
+    let gateway_config = config
+        .get("gateway_manager")
+        .context("Invalid gateway config")?;
+
+    let log_config = config.get("log").context("Invalid log config")?;
+
+    let partial_runtime_config: PartialRuntimeConfig =
+        config.get("runtime").context("Invalid runtime config")?;
+
+    let scheduler_config = config
+        .get("scheduler")
+        .context("Invalid scheduler config")?;
+
+    let blockchain_monitor_config = config
+        .get("blockchain_monitor")
+        .context("Invalid blockchain monitor config")?;
+
+    let api_config = config.get("api").context("Invalid api config")?;
+
+    let system_config = SystemConfig(
+        connection_manager_config,
+        membership_config,
+        db_config,
+        storage_config,
+        gateway_config,
+        log_config,
+        partial_runtime_config,
+        scheduler_config,
+        blockchain_monitor_config,
+        api_config,
+    );
+
+    Ok((config, system_config))
+}
+
+/// Reads the configuration from its sources, without interpreting it.
+pub fn read_config() -> Result<Config> {
     let defaults = vec![
         ("log.level", "warn"),
+        ("log.format", "human"),
         ("connection_manager.listen_ip", "0.0.0.0"),
         ("connection_manager.listen_port", "12012"),
         ("connection_manager.max_request_size_kb", "8192"),
@@ -51,8 +103,17 @@ pub fn initialize_config() -> Result<SystemConfig> {
         ("blockchain_monitor.solana_provider_public_key", "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"),
         ("blockchain_monitor.solana_region_number", "1"),
         ("blockchain_monitor.solana_usage_signer_private_key", "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"),
+        ("blockchain_monitor.start_degraded_if_unavailable", "false"),
+        ("blockchain_monitor.degraded_mode_retry_interval", "30s"),
+        ("blockchain_monitor.known_stacks_path", "known-stacks.json"),
         ("runtime.include_function_logs", "false"),
+        ("runtime.compiler", "llvm"),
+        ("runtime.max_instance_lifetime", "5m"),
+        ("runtime.max_execution_time", "30s"),
+        ("runtime.warm_instances_per_function", "0"),
+        ("runtime.warm_instance_idle_timeout", "1m"),
         ("api.payload_size_limit", "10Mib"),
+        ("api.max_request_clock_skew", "5m"),
     ];
 
     let default_arrays = vec!["log.filters", "gossip.seeds"];
@@ -89,68 +150,52 @@ pub fn initialize_config() -> Result<SystemConfig> {
 
     builder = builder.add_source(env);
 
-    let config = builder
+    builder
         .build()
-        .context("Failed to initialize configuration")?;
-
-    let connection_manager_config = config
-        .get("connection_manager")
-        .context("Invalid connection_manager config")?;
-
-    let membership_config = config
-        .get("membership")
-        .context("Invalid membership config")?;
-
-    let db_config = config.get("db").context("Invalid database config")?;
-
-    let storage_config = config.get("storage").context("Invalid storage config")?;
-
-    let gateway_config = config
-        .get("gateway_manager")
-        .context("Invalid gateway config")?;
-
-    let log_config = config.get("log").context("Invalid log config")?;
-
-    let partial_runtime_config: PartialRuntimeConfig =
-        config.get("runtime").context("Invalid runtime config")?;
-
-    let scheduler_config = config
-        .get("scheduler")
-        .context("Invalid scheduler config")?;
-
-    let blockchain_monitor_config = config
-        .get("blockchain_monitor")
-        .context("Invalid blockchain monitor config")?;
-
-    let api_config = config.get("api").context("Invalid api config")?;
-
-    Ok(SystemConfig(
-        connection_manager_config,
-        membership_config,
-        db_config,
-        storage_config,
-        gateway_config,
-        log_config,
-        partial_runtime_config,
-        scheduler_config,
-        blockchain_monitor_config,
-        api_config,
-    ))
+        .context("Failed to initialize configuration")
 }
 
 //We need this so `giga_instructions_limit` is not read from config, only from blockchain.
 #[derive(Deserialize, Clone)]
 pub struct PartialRuntimeConfig {
     pub cache_path: PathBuf,
+    pub cache_max_bytes: Option<u64>,
     pub include_function_logs: bool,
+    pub compiler: WasmCompiler,
+    pub max_instance_lifetime: Option<ConfigDuration>,
+    pub max_execution_time: ConfigDuration,
+    pub warm_instances_per_function: usize,
+    pub warm_instance_idle_timeout: Option<ConfigDuration>,
+    pub max_concurrent_invocations_per_stack: Option<usize>,
+    pub shutdown_timeout: Option<ConfigDuration>,
+    #[serde(default)]
+    pub wasi: WasiConfig,
 }
 
 impl PartialRuntimeConfig {
+    pub fn reloadable(&self) -> ReloadableRuntimeConfig {
+        ReloadableRuntimeConfig {
+            include_function_logs: self.include_function_logs,
+            max_instance_lifetime: self.max_instance_lifetime.clone(),
+            max_execution_time: self.max_execution_time.clone(),
+            max_concurrent_invocations_per_stack: self.max_concurrent_invocations_per_stack,
+        }
+    }
+
     pub fn complete(self, max_giga_instructions_per_call: Option<u32>) -> RuntimeConfig {
         RuntimeConfig {
             cache_path: self.cache_path,
+            cache_max_bytes: self.cache_max_bytes,
             include_function_logs: self.include_function_logs,
             max_giga_instructions_per_call,
+            compiler: self.compiler,
+            max_instance_lifetime: self.max_instance_lifetime,
+            max_execution_time: self.max_execution_time,
+            warm_instances_per_function: self.warm_instances_per_function,
+            warm_instance_idle_timeout: self.warm_instance_idle_timeout,
+            max_concurrent_invocations_per_stack: self.max_concurrent_invocations_per_stack,
+            shutdown_timeout: self.shutdown_timeout,
+            wasi: self.wasi,
         }
     }
 }
@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use config::Config;
+use log::{error, info, warn};
+use mu_gateway::{GatewayManager, GatewayManagerConfig};
+use mu_runtime::Runtime;
+use serde_json::Value;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    config::{read_config, PartialRuntimeConfig},
+    log_setup::LogHandle,
+};
+
+// Settings (or whole sections) that can be changed without restarting the
+// executor. Everything else is reported as requiring a restart; this includes
+// the rest of the gateway settings, since its HTTP servers are only built at
+// startup.
+const LOG_SECTION: &str = "log";
+const RUNTIME_SECTION: &str = "runtime";
+const GATEWAY_SECTION: &str = "gateway_manager";
+const RELOADABLE_RUNTIME_SETTINGS: &[&str] = &[
+    "runtime.include_function_logs",
+    "runtime.max_instance_lifetime",
+    "runtime.max_execution_time",
+    "runtime.max_concurrent_invocations_per_stack",
+];
+// Applied to each stack the next time its gateways are deployed
+const RELOADABLE_GATEWAY_SETTINGS: &[&str] = &[
+    "gateway_manager.max_request_body_bytes",
+    "gateway_manager.rate_limit",
+];
+
+/// Re-reads the configuration whenever the process receives a SIGHUP, and
+/// applies the settings that can safely be changed on a running executor.
+pub fn start(
+    initial_config: Config,
+    log_handle: LogHandle,
+    runtime: Box<dyn Runtime>,
+    gateway_manager: Box<dyn GatewayManager>,
+    cancellation_token: CancellationToken,
+) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup()).context("Failed to install SIGHUP handler")?;
+
+    tokio::spawn(async move {
+        let mut current = initial_config;
+
+        loop {
+            tokio::select! {
+                () = cancellation_token.cancelled() => break,
+
+                Some(()) = hangup.recv() => {
+                    info!("Received SIGHUP, reloading configuration");
+                    let reloaded =
+                        reload(&current, log_handle, runtime.as_ref(), gateway_manager.as_ref())
+                            .await;
+                    if let Some(new) = reloaded {
+                        current = new;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn reload(
+    current: &Config,
+    log_handle: LogHandle,
+    runtime: &dyn Runtime,
+    gateway_manager: &dyn GatewayManager,
+) -> Option<Config> {
+    let new = match read_config() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Failed to re-read configuration, keeping the current one: {e:?}");
+            return None;
+        }
+    };
+
+    let (mut applied, requires_restart) = match changed_settings(current, &new) {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("Failed to compare configurations: {e:?}");
+            return None;
+        }
+    };
+
+    if applied.is_empty() && requires_restart.is_empty() {
+        info!("Configuration has not changed");
+        return Some(new);
+    }
+
+    if applied.iter().any(|k| is_log_setting(k)) {
+        match new.get(LOG_SECTION) {
+            Ok(log_config) => log_handle.reload(log_config),
+            Err(e) => {
+                error!("Invalid log config, not applying it: {e:?}");
+                applied.retain(|k| !is_log_setting(k));
+            }
+        }
+    }
+
+    if applied.iter().any(|k| is_runtime_setting(k)) {
+        let result = match new.get::<PartialRuntimeConfig>(RUNTIME_SECTION) {
+            Ok(runtime_config) => runtime
+                .reconfigure(runtime_config.reloadable())
+                .await
+                .map_err(Into::into),
+            Err(e) => Err(anyhow::Error::from(e)),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to apply runtime config: {e:?}");
+            applied.retain(|k| !is_runtime_setting(k));
+        }
+    }
+
+    if applied.iter().any(|k| is_gateway_setting(k)) {
+        let result = match new.get::<GatewayManagerConfig>(GATEWAY_SECTION) {
+            Ok(gateway_config) => gateway_manager.set_limits(gateway_config.limits()).await,
+            Err(e) => Err(e.into()),
+        };
+
+        match result {
+            Ok(()) => info!("New gateway limits apply to stacks as they are redeployed"),
+            Err(e) => {
+                error!("Failed to apply gateway limits: {e:?}");
+                applied.retain(|k| !is_gateway_setting(k));
+            }
+        }
+    }
+
+    if !applied.is_empty() {
+        info!("Applied configuration changes: {}", applied.join(", "));
+    }
+
+    if !requires_restart.is_empty() {
+        warn!(
+            "Configuration changes require a restart to take effect: {}",
+            requires_restart.join(", ")
+        );
+    }
+
+    Some(new)
+}
+
+/// Splits the settings that differ between the two configs into the ones
+/// that can be applied right away and the ones that need a restart.
+fn changed_settings(current: &Config, new: &Config) -> Result<(Vec<String>, Vec<String>)> {
+    let changed = changed_keys(&flatten(current)?, &flatten(new)?);
+    Ok(changed
+        .into_iter()
+        .partition(|k| is_log_setting(k) || is_runtime_setting(k) || is_gateway_setting(k)))
+}
+
+fn is_log_setting(key: &str) -> bool {
+    key == LOG_SECTION || key.starts_with("log.")
+}
+
+fn is_runtime_setting(key: &str) -> bool {
+    RELOADABLE_RUNTIME_SETTINGS.contains(&key)
+}
+
+// Rate limits are flattened into `gateway_manager.rate_limit.per_second` etc.
+fn is_gateway_setting(key: &str) -> bool {
+    RELOADABLE_GATEWAY_SETTINGS.iter().any(|setting| {
+        key == *setting
+            || key
+                .strip_prefix(setting)
+                .map_or(false, |rest| rest.starts_with('.'))
+    })
+}
+
+fn flatten(config: &Config) -> Result<BTreeMap<String, Value>> {
+    fn helper(prefix: String, value: Value, result: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (k, v) in map {
+                    let key = if prefix.is_empty() {
+                        k
+                    } else {
+                        format!("{prefix}.{k}")
+                    };
+                    helper(key, v, result);
+                }
+            }
+            v => {
+                result.insert(prefix, v);
+            }
+        }
+    }
+
+    let value: Value = config
+        .clone()
+        .try_deserialize()
+        .context("Failed to read configuration values")?;
+
+    let mut result = BTreeMap::new();
+    helper(String::new(), value, &mut result);
+    Ok(result)
+}
+
+fn changed_keys(old: &BTreeMap<String, Value>, new: &BTreeMap<String, Value>) -> Vec<String> {
+    old.keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)))
+        .filter(|k| old.get(*k) != new.get(*k))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(settings: &[(&str, &str)]) -> Config {
+        let mut builder = Config::builder()
+            .set_default("log.level", "info")
+            .unwrap()
+            .set_default("runtime.max_execution_time", "30s")
+            .unwrap()
+            .set_default("runtime.warm_instances_per_function", "0")
+            .unwrap()
+            .set_default("gateway_manager.listen_port", "12080")
+            .unwrap()
+            .set_default("gateway_manager.max_request_body_bytes", "1024")
+            .unwrap();
+        for (key, value) in settings {
+            builder = builder.set_override(*key, *value).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn unchanged_configs_have_no_changes() {
+        let (applied, requires_restart) = changed_settings(&config(&[]), &config(&[])).unwrap();
+        assert!(applied.is_empty());
+        assert!(requires_restart.is_empty());
+    }
+
+    #[test]
+    fn changes_are_split_by_whether_they_need_a_restart() {
+        let new = config(&[
+            ("log.level", "debug"),
+            ("runtime.max_execution_time", "10s"),
+            ("runtime.max_concurrent_invocations_per_stack", "4"),
+            ("runtime.warm_instances_per_function", "2"),
+            ("gateway_manager.listen_port", "12081"),
+            ("gateway_manager.max_request_body_bytes", "2048"),
+            ("gateway_manager.rate_limit.burst", "10"),
+        ]);
+
+        let (applied, requires_restart) = changed_settings(&config(&[]), &new).unwrap();
+
+        assert_eq!(
+            applied,
+            vec![
+                "gateway_manager.max_request_body_bytes",
+                "log.level",
+                "runtime.max_execution_time",
+                "gateway_manager.rate_limit.burst",
+                "runtime.max_concurrent_invocations_per_stack",
+            ]
+        );
+        assert_eq!(
+            requires_restart,
+            vec![
+                "gateway_manager.listen_port",
+                "runtime.warm_instances_per_function",
+            ]
+        );
+    }
+}
@@ -1,10 +1,32 @@
-use anyhow::{Ok, Result};
-use env_logger::Builder;
+use std::{
+    io::{self, Write},
+    sync::RwLock,
+};
+
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use env_logger::{Builder, Logger};
+use log::{Log, Metadata, Record};
 use serde::Deserialize;
 
 use super::config::ConfigLogLevelFilter;
 
-pub fn setup(config: LogConfig) -> Result<()> {
+pub fn setup(config: LogConfig) -> Result<LogHandle> {
+    let logger = build_logger(config);
+    let max_level = logger.filter();
+
+    // The logger lives for the rest of the process, so leaking it is fine
+    let reloadable: &'static ReloadableLogger = Box::leak(Box::new(ReloadableLogger {
+        inner: RwLock::new(logger),
+    }));
+
+    log::set_logger(reloadable).context("Failed to set up logger")?;
+    log::set_max_level(max_level);
+
+    Ok(LogHandle(reloadable))
+}
+
+fn build_logger(config: LogConfig) -> Logger {
     let mut builder = Builder::new();
 
     builder.filter_level(*config.level);
@@ -13,15 +35,72 @@ pub fn setup(config: LogConfig) -> Result<()> {
         builder.filter(Some(&filter.module), *filter.level);
     }
 
-    builder.init();
+    if let LogFormat::Json = config.format {
+        builder.format(|buf, record| write_json_line(buf, record));
+    }
+
+    builder.build()
+}
+
+fn write_json_line(buf: &mut impl Write, record: &Record) -> io::Result<()> {
+    let line = serde_json::json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "module": record.module_path(),
+        "file": record.file(),
+        "line": record.line(),
+    });
+    writeln!(buf, "{line}")
+}
+
+struct ReloadableLogger {
+    inner: RwLock<Logger>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner
+            .read()
+            .map(|l| l.enabled(metadata))
+            .unwrap_or(false)
+    }
+
+    fn log(&self, record: &Record) {
+        if let Ok(l) = self.inner.read() {
+            l.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(l) = self.inner.read() {
+            l.flush();
+        }
+    }
+}
+
+/// Allows replacing the log configuration after [`setup`] has run.
+#[derive(Clone, Copy)]
+pub struct LogHandle(&'static ReloadableLogger);
+
+impl LogHandle {
+    pub fn reload(&self, config: LogConfig) {
+        let logger = build_logger(config);
+        let max_level = logger.filter();
 
-    Ok(())
+        if let Ok(mut inner) = self.0.inner.write() {
+            *inner = logger;
+        }
+        log::set_max_level(max_level);
+    }
 }
 
 #[derive(Deserialize)]
 pub struct LogConfig {
     pub level: ConfigLogLevelFilter,
     pub filters: Vec<LogFilterConfig>,
+    pub format: LogFormat,
 }
 
 #[derive(Deserialize)]
@@ -29,3 +108,51 @@ pub struct LogFilterConfig {
     pub module: String,
     pub level: ConfigLogLevelFilter,
 }
+
+/// Output format of log lines. `Human` (or `text`) is meant for local
+/// development, `Json` emits one JSON object per line for log aggregators.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[serde(alias = "text")]
+    Human,
+    Json,
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn json_lines_include_record_fields() {
+        let mut buf = vec![];
+        write_json_line(
+            &mut buf,
+            &Record::builder()
+                .level(Level::Warn)
+                .target("mu::gateway")
+                .module_path(Some("mu_gateway::rate_limit"))
+                .args(format_args!("stack {} is over its limit", 42))
+                .build(),
+        )
+        .unwrap();
+
+        assert_eq!(Some(&b'\n'), buf.last());
+        let line: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!("WARN", line["level"]);
+        assert_eq!("mu::gateway", line["target"]);
+        assert_eq!("mu_gateway::rate_limit", line["module"]);
+        assert_eq!("stack 42 is over its limit", line["message"]);
+
+        let timestamp = line["timestamp"].as_str().unwrap();
+        chrono::DateTime::parse_from_rfc3339(timestamp).unwrap();
+    }
+
+    #[test]
+    fn text_is_accepted_as_the_human_format() {
+        let format: LogFormat = serde_json::from_str("\"text\"").unwrap();
+        assert_eq!(LogFormat::Human, format);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod config;
+pub mod config_reload;
 pub mod log_setup;
+pub mod shutdown;
@@ -0,0 +1,115 @@
+use std::future::Future;
+
+use anyhow::{anyhow, Result};
+use log::{error, trace};
+
+/// Stops components one after the other, moving on to the next one even if
+/// a component fails to stop. Otherwise, an early failure would leave every
+/// later component (and the processes they manage) running.
+#[derive(Default)]
+pub struct Shutdown {
+    errors: Vec<anyhow::Error>,
+}
+
+impl Shutdown {
+    pub async fn stop(&mut self, component: &str, stop: impl Future<Output = Result<()>>) {
+        trace!("Stopping {component}");
+        if let Err(e) = stop.await {
+            let e = e.context(format!("Failed to stop {component}"));
+            error!("{e:?}");
+            self.errors.push(e);
+        }
+    }
+
+    /// Fails if any component failed to stop, with all their errors.
+    pub fn finish(mut self) -> Result<()> {
+        match self.errors.len() {
+            0 => Ok(()),
+            1 => Err(self.errors.remove(0)),
+            n => Err(anyhow!(
+                "{n} components failed to stop:\n{}",
+                self.errors
+                    .iter()
+                    .map(|e| format!("  - {e:#}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use anyhow::bail;
+
+    use super::*;
+
+    struct Component<'a> {
+        name: &'static str,
+        fails: bool,
+        stopped: &'a Mutex<Vec<&'static str>>,
+    }
+
+    impl Component<'_> {
+        async fn stop(&self) -> Result<()> {
+            self.stopped.lock().unwrap().push(self.name);
+            if self.fails {
+                bail!("{} is stuck", self.name);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn components_after_a_failing_one_are_still_stopped() {
+        let stopped = Mutex::new(vec![]);
+        let components = [
+            ("gateway", false),
+            ("blockchain monitor", true),
+            ("runtime", false),
+            ("database", true),
+            ("membership", false),
+        ]
+        .map(|(name, fails)| Component {
+            name,
+            fails,
+            stopped: &stopped,
+        });
+
+        let mut shutdown = Shutdown::default();
+        for component in &components {
+            shutdown.stop(component.name, component.stop()).await;
+        }
+
+        assert_eq!(
+            vec![
+                "gateway",
+                "blockchain monitor",
+                "runtime",
+                "database",
+                "membership"
+            ],
+            *stopped.lock().unwrap()
+        );
+
+        let error = shutdown.finish().unwrap_err().to_string();
+        assert!(error.starts_with("2 components failed to stop"), "{error}");
+        assert!(
+            error.contains("Failed to stop blockchain monitor: blockchain monitor is stuck"),
+            "{error}"
+        );
+        assert!(
+            error.contains("Failed to stop database: database is stuck"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_succeeds_when_every_component_stops() {
+        let mut shutdown = Shutdown::default();
+        shutdown.stop("runtime", async { Ok(()) }).await;
+        shutdown.finish().unwrap();
+    }
+}
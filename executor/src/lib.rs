@@ -22,12 +22,12 @@ use stack::{
 };
 use tokio::{
     select,
-    sync::{mpsc, RwLock},
+    sync::{broadcast, mpsc, RwLock},
 };
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    infrastructure::{config, log_setup},
+    infrastructure::{config, config_reload, log_setup, shutdown::Shutdown},
     network::{
         connection_manager::{self, ConnectionManagerNotification},
         membership, NodeAddress,
@@ -38,6 +38,10 @@ use crate::{
     },
 };
 
+// Function logs are only buffered for API clients streaming them; slow
+// clients will skip logs instead of holding up the runtime.
+const FUNCTION_LOG_BUFFER_SIZE: usize = 1024;
+
 pub async fn run() -> Result<()> {
     // TODO handle failures in components
 
@@ -47,17 +51,20 @@ pub async fn run() -> Result<()> {
     ctrlc::set_handler(move || cancellation_token_clone.cancel())
         .context("Failed to initialize Ctrl+C handler")?;
 
-    let config::SystemConfig(
-        connection_manager_config,
-        membership_config,
-        db_config,
-        storage_config,
-        gateway_manager_config,
-        log_config,
-        partial_runtime_config,
-        scheduler_config,
-        blockchain_monitor_config,
-        api_config,
+    let (
+        raw_config,
+        config::SystemConfig(
+            connection_manager_config,
+            membership_config,
+            db_config,
+            storage_config,
+            gateway_manager_config,
+            log_config,
+            partial_runtime_config,
+            scheduler_config,
+            blockchain_monitor_config,
+            api_config,
+        ),
     ) = config::initialize_config()?;
 
     let my_node = NodeAddress {
@@ -70,7 +77,7 @@ pub async fn run() -> Result<()> {
     };
     let my_hash = my_node.get_hash();
 
-    log_setup::setup(log_config)?;
+    let log_handle = log_setup::setup(log_config)?;
 
     info!("Initializing Mu...");
 
@@ -87,15 +94,20 @@ pub async fn run() -> Result<()> {
         process::exit(0);
     }
 
-    let usage_aggregator = stack::usage_aggregator::start();
+    let database_manager = mu_db::start(db_config).await?;
+
+    let usage_aggregator = stack::usage_aggregator::start(
+        database_manager.as_ref(),
+        &format!("{}:{}", my_node.address, my_node.port),
+    )
+    .await
+    .context("Failed to start usage aggregator")?;
 
     let (blockchain_monitor, mut blockchain_monitor_notification_receiver, region_config) =
         blockchain_monitor::start(blockchain_monitor_config, usage_aggregator.clone())
             .await
             .context("Failed to start blockchain monitor")?;
 
-    let database_manager = mu_db::start(db_config).await?;
-
     let storage_manager = mu_storage::start(&storage_config).await?;
 
     let runtime_config =
@@ -124,19 +136,35 @@ pub async fn run() -> Result<()> {
     .await
     .context("Failed to start membership")?;
 
-    let request_signer_cache = request_signer_cache::start();
+    let request_signer_cache = request_signer_cache::start(
+        blockchain_monitor.clone(),
+        *api_config.max_request_clock_skew,
+    );
+
+    let (function_log_sender, _) = broadcast::channel(FUNCTION_LOG_BUFFER_SIZE);
 
     let scheduler_ref = Arc::new(RwLock::new(None));
     let (gateway_manager, mut gateway_notification_receiver) = mu_gateway::start(
         gateway_manager_config,
         api::service_factory(api_config),
         Some(api::DependencyAccessor {
-            //request_signer_cache: request_signer_cache.clone(),
+            request_signer_cache: request_signer_cache.clone(),
             blockchain_monitor: blockchain_monitor.clone(),
             storage_client: storage_manager
                 .make_client()
                 .context("Failed to create storage client for executor api")?,
+            db_client: database_manager
+                .make_client()
+                .await
+                .context("Failed to create database client for executor api")?,
+            function_logs: function_log_sender.clone(),
         }),
+        Some(Box::new(mu_gateway::DbIdempotencyStore::new(
+            database_manager
+                .make_client()
+                .await
+                .context("Failed to create database client for gateway")?,
+        ))),
         {
             let connection_manager = connection_manager.clone();
             let membership = membership.clone();
@@ -148,6 +176,7 @@ pub async fn run() -> Result<()> {
                 Box::pin(request_routing::route_request(
                     f,
                     r,
+                    my_hash,
                     connection_manager.clone(),
                     membership.clone(),
                     scheduler_ref.clone(),
@@ -183,6 +212,14 @@ pub async fn run() -> Result<()> {
 
     *scheduler_ref.write().await = Some(scheduler.clone());
 
+    config_reload::start(
+        raw_config,
+        log_handle,
+        runtime.clone(),
+        gateway_manager.clone(),
+        cancellation_token.clone(),
+    )?;
+
     glue_modules(
         cancellation_token,
         connection_manager_notification_receiver,
@@ -196,50 +233,49 @@ pub async fn run() -> Result<()> {
         usage_aggregator.as_ref(),
         &mut gateway_notification_receiver,
         &mut runtime_notification_receiver,
+        &function_log_sender,
         request_signer_cache.as_ref(),
     )
     .await;
 
-    trace!("Stopping blockchain monitor");
-    blockchain_monitor
-        .stop()
-        .await
-        .context("Failed to stop blockchain monitor")?;
-
-    trace!("Stopping scheduler");
-    scheduler.stop().await.context("Failed to stop scheduler")?;
-
-    trace!("Stopping runtime");
-    runtime.stop().await.context("Failed to stop runtime")?;
-
-    trace!("Stopping database manager");
-    database_manager
-        .stop()
-        .await
-        .context("Failed to stop runtime")?;
-
-    // Stop gateway manager first. This waits for actix-web to shut down, essentially
-    // running all requests to completion or cancelling them safely before shutting
-    // the rest of the system down.
-    trace!("Stopping gateway manager");
-    gateway_manager
-        .stop()
-        .await
-        .context("Failed to stop gateway manager")?;
-
-    request_signer_cache.stop().await;
-
-    trace!("Stopping membership");
-    membership
-        .stop()
-        .await
-        .context("Failed to stop membership")?;
-
-    trace!("Stopping connection manager");
-    connection_manager
-        .stop()
-        .await
-        .context("Failed to stop connection manager")?;
+    let mut shutdown = Shutdown::default();
+
+    // Stop the gateway manager first. This waits for actix-web to shut down,
+    // running requests to completion or cancelling them safely before the
+    // runtime and database they depend on go away.
+    shutdown
+        .stop("gateway manager", gateway_manager.stop())
+        .await;
+    shutdown
+        .stop("blockchain monitor", blockchain_monitor.stop())
+        .await;
+    shutdown.stop("scheduler", scheduler.stop()).await;
+    shutdown
+        .stop("runtime", async { Ok(runtime.stop().await?) })
+        .await;
+
+    // Stopping the usage aggregator writes a final checkpoint, so the
+    // database must still be up.
+    shutdown
+        .stop("usage aggregator", async {
+            usage_aggregator.stop().await;
+            Ok(())
+        })
+        .await;
+    shutdown
+        .stop("database manager", database_manager.stop())
+        .await;
+    shutdown
+        .stop("request signer cache", async {
+            request_signer_cache.stop().await;
+            Ok(())
+        })
+        .await;
+    shutdown.stop("membership", membership.stop()).await;
+    shutdown
+        .stop("connection manager", connection_manager.stop())
+        .await;
+    shutdown.finish()?;
 
     info!("Goodbye!");
 
@@ -258,11 +294,16 @@ impl RpcRequestHandler for RpcRequestHandlerImpl {
             request;
 
         let helper = async move {
+            // Function failures are sent back as such, so the requesting
+            // node's gateway reports them the same way as local ones
             let result = self
                 .runtime
                 .invoke_function(function_id, request)
                 .await
-                .context("Failed to invoke function")?;
+                .map_err(|e| {
+                    mu_gateway::FunctionError::from_runtime_error(e)
+                        .context("Failed to invoke function")
+                })?;
 
             Ok(result)
         };
@@ -289,6 +330,7 @@ async fn glue_modules(
     usage_aggregator: &dyn UsageAggregator,
     gateway_notification_receiver: &mut mpsc::UnboundedReceiver<mu_gateway::Notification>,
     runtime_notification_receiver: &mut mpsc::UnboundedReceiver<mu_runtime::Notification>,
+    function_log_sender: &broadcast::Sender<mu_runtime::FunctionLog>,
     request_signer_cache: &dyn RequestSignerCache,
 ) {
     loop {
@@ -319,7 +361,7 @@ async fn glue_modules(
             }
 
             notification = runtime_notification_receiver.recv() => {
-                handle_runtime_notification(notification, usage_aggregator);
+                handle_runtime_notification(notification, usage_aggregator, function_log_sender);
             }
         }
     }
@@ -365,6 +407,12 @@ async fn process_membership_notification(
             debug!("Node discovered: {node}");
             scheduler.node_discovered(node.get_hash()).await.unwrap(); // TODO: unwrap
         }
+        Some(membership::Notification::NodeRejected {
+            node,
+            colliding_with,
+        }) => {
+            warn!("Node {node} was rejected, its hash collides with node {colliding_with}");
+        }
         Some(membership::Notification::NodeDied(node, reason)) => {
             debug!("Node{node} died due to {reason:?}",);
             scheduler.node_died(node).await.unwrap(); // TODO: unwrap
@@ -404,8 +452,8 @@ async fn process_scheduler_notification(
             debug!("Undeployed stack {id}");
             membership.stack_undeployed_locally(id).await.unwrap(); // TODO: unwrap
         }
-        Some(SchedulerNotification::FailedToDeployStack(id)) => {
-            debug!("Failed to deploy stack {id}");
+        Some(SchedulerNotification::FailedToDeployStack(id, failure)) => {
+            debug!("Failed to deploy stack {id}: {failure}");
         }
     }
 }
@@ -471,12 +519,25 @@ fn handle_gateway_notification(
 fn handle_runtime_notification(
     notification: Option<mu_runtime::Notification>,
     usage_aggregator: &dyn UsageAggregator,
+    function_log_sender: &broadcast::Sender<mu_runtime::FunctionLog>,
 ) {
-    let mu_runtime::Notification::ReportUsage(stack_id, usage) = notification.unwrap();
+    let (stack_id, usage) = match notification.unwrap() {
+        mu_runtime::Notification::ReportUsage(stack_id, usage) => (stack_id, usage),
+        mu_runtime::Notification::FunctionLog(log) => {
+            // Sending only fails when nobody is streaming logs, which is fine
+            let _ = function_log_sender.send(log);
+            return;
+        }
+    };
+
+    let custom_metrics = usage
+        .custom_metrics
+        .into_iter()
+        .map(|(name, value)| Usage::Custom { name, value });
 
     usage_aggregator.register_usage(
         stack_id,
-        vec![
+        [
             Usage::DBRead {
                 weak_reads: usage.db_weak_reads,
                 strong_reads: usage.db_strong_reads,
@@ -489,6 +550,9 @@ fn handle_runtime_notification(
                 memory_megabytes: usage.memory_megabytes,
                 instructions: usage.function_instructions,
             },
-        ],
+        ]
+        .into_iter()
+        .chain(custom_metrics)
+        .collect(),
     );
 }
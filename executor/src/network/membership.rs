@@ -7,7 +7,7 @@ mod protos;
 use std::{
     collections::{HashMap, HashSet},
     net::IpAddr,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{bail, Context, Result};
@@ -20,10 +20,11 @@ use mu_common::serde_support::ConfigDuration;
 use mu_db::{DbClient, DbManager};
 use mu_stack::StackID;
 use protobuf::Message;
+use rand::{seq::SliceRandom, Rng};
 use serde::Deserialize;
 use tokio::sync::mpsc;
 
-use self::node_collection::NodeCollection;
+use self::node_collection::{InsertError, NodeCollection};
 
 use super::{NodeAddress, NodeHash};
 
@@ -50,8 +51,109 @@ pub trait Membership: Clone + Sync + Send {
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct MembershipConfig {
+    /// How often each node writes its status, i.e. its heartbeat.
     pub update_interval: ConfigDuration,
+    /// Nodes that haven't written their status for this long are
+    /// considered dead, which moves their stacks to other nodes. A node is
+    /// only considered dead once an earlier update already suspected it, so
+    /// unless `suspicion_timeout` is shorter than this, nodes are noticed
+    /// dying one `update_interval` later, plus `death_timeout`.
     pub assume_dead_after: ConfigDuration,
+    /// Nodes that haven't written their status for this long are suspected
+    /// of having died, which is logged, but they're still considered alive
+    /// until `assume_dead_after` has passed, see `death_timeout`. Defaults to
+    /// `assume_dead_after`.
+    pub suspicion_timeout: Option<ConfigDuration>,
+    /// How long a node must have been suspected before it's assumed dead.
+    /// Either way, missing `assume_dead_after` worth of updates only kills
+    /// a node that was already suspected in an earlier update, so a single
+    /// late read doesn't move its stacks. Defaults to zero.
+    pub death_timeout: Option<ConfigDuration>,
+    /// If set, each update only reads the status of this many randomly
+    /// picked nodes, instead of every node's, so the load each node puts on
+    /// the database stays bounded as the cluster grows. Nodes that aren't
+    /// picked may be noticed dying up to `anti_entropy_interval` late.
+    pub fanout: Option<usize>,
+    /// How often every node's status is read when `fanout` is set, which is
+    /// also when new nodes are discovered. Defaults to `assume_dead_after`.
+    pub anti_entropy_interval: Option<ConfigDuration>,
+}
+
+// Which nodes' statuses are read in each update, when not all of them are
+#[derive(Debug, Clone, Copy)]
+struct PeerSampling {
+    fanout: usize,
+    anti_entropy_interval: Duration,
+}
+
+impl PeerSampling {
+    fn from_config(config: &MembershipConfig) -> Result<Option<Self>> {
+        let Some(fanout) = config.fanout else {
+            return Ok(None);
+        };
+
+        if fanout == 0 {
+            bail!("membership.fanout must be at least 1");
+        }
+
+        let anti_entropy_interval = config
+            .anti_entropy_interval
+            .as_ref()
+            .unwrap_or(&config.assume_dead_after);
+
+        Ok(Some(Self {
+            fanout,
+            anti_entropy_interval: **anti_entropy_interval,
+        }))
+    }
+
+    fn full_update_due(&self, last_full_update: Option<Instant>, now: Instant) -> bool {
+        match last_full_update {
+            None => true,
+            Some(last) => now.duration_since(last) >= self.anti_entropy_interval,
+        }
+    }
+}
+
+fn pick_peers<T: Clone>(peers: &[T], fanout: usize, rng: &mut impl Rng) -> Vec<T> {
+    peers.choose_multiple(rng, fanout).cloned().collect()
+}
+
+// When nodes that miss their updates are suspected and then assumed dead
+#[derive(Debug, Clone, Copy)]
+struct FailureThresholds {
+    suspect_after: chrono::Duration,
+    assume_dead_after: chrono::Duration,
+    death_timeout: Duration,
+}
+
+impl FailureThresholds {
+    fn from_config(config: &MembershipConfig) -> Result<Self> {
+        let to_chrono = |d: &ConfigDuration| {
+            chrono::Duration::from_std(**d).context("Membership timeout is too large")
+        };
+
+        let update_interval = to_chrono(&config.update_interval)?;
+        let assume_dead_after = to_chrono(&config.assume_dead_after)?;
+        let suspect_after = match &config.suspicion_timeout {
+            Some(timeout) => to_chrono(timeout)?,
+            None => assume_dead_after,
+        };
+
+        // Otherwise, nodes would be assumed dead between two of their updates
+        if assume_dead_after <= update_interval {
+            bail!("membership.assume_dead_after must be longer than membership.update_interval");
+        }
+        if suspect_after > assume_dead_after {
+            bail!("membership.suspicion_timeout can't be longer than membership.assume_dead_after");
+        }
+
+        Ok(Self {
+            suspect_after,
+            assume_dead_after,
+            death_timeout: config.death_timeout.as_deref().copied().unwrap_or_default(),
+        })
+    }
 }
 
 enum MailboxMessage {
@@ -71,6 +173,12 @@ pub enum Notification {
         added: Vec<StackID>,
         removed: Vec<StackID>,
     },
+    /// A node was ignored because its hash collides with a different,
+    /// already known node.
+    NodeRejected {
+        node: NodeAddress,
+        colliding_with: NodeHash,
+    },
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -81,12 +189,32 @@ pub enum NodeDeadReason {
     MissingFromDb,
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Liveness {
+    Alive,
+    // Missed its updates for a while, but not long enough to be assumed dead
+    Suspected,
+    Dead(NodeDeadReason),
+}
+
+impl Liveness {
+    fn dead_reason(self) -> Option<NodeDeadReason> {
+        match self {
+            Liveness::Alive | Liveness::Suspected => None,
+            Liveness::Dead(reason) => Some(reason),
+        }
+    }
+}
+
 struct State {
     notification_channel: NotificationChannel<Notification>,
     db: Box<dyn DbClient>,
 
     nodes: NodeCollection,
-    assume_dead_after: chrono::Duration,
+    rejected_nodes: RejectedNodes,
+    failure_thresholds: FailureThresholds,
+    peer_sampling: Option<PeerSampling>,
+    last_full_update: Option<Instant>,
 
     my_version: u32,
     my_address: NodeAddress,
@@ -119,6 +247,12 @@ impl NodeStatus {
     }
 }
 
+type NodeStatuses = HashMap<(IpAddr, u16), NodeStatus>;
+
+// Nodes whose hash collides with a known node, along with that node's hash,
+// so they aren't discovered and rejected again on every update
+type RejectedNodes = HashMap<(IpAddr, u16), (NodeAddress, NodeHash)>;
+
 // Each node's view of other nodes
 #[derive(Debug)]
 struct RemoteNodeInfo {
@@ -126,6 +260,7 @@ struct RemoteNodeInfo {
     version: u32,
     address: NodeAddress,
     dead_reason: Option<NodeDeadReason>,
+    suspected_since: Option<Instant>,
     deployed_stacks: HashSet<StackID>,
 }
 
@@ -189,7 +324,8 @@ pub async fn start(
         + PKG_VERSION_MINOR.parse::<u32>().unwrap() * 1_000
         + PKG_VERSION_PATCH.parse::<u32>().unwrap();
     let update_interval = *config.update_interval;
-    let assume_dead_after = chrono::Duration::from_std(*config.assume_dead_after).unwrap();
+    let failure_thresholds = FailureThresholds::from_config(&config)?;
+    let peer_sampling = PeerSampling::from_config(&config)?;
 
     let now = chrono::Utc::now().naive_utc();
 
@@ -209,11 +345,12 @@ pub async fn start(
             } else if v.address.address == my_address.address && v.address.port == my_address.port {
                 None
             } else {
-                let dead_reason = get_dead_reason(&assume_dead_after, &now, &v);
+                let liveness = get_liveness(&failure_thresholds, &now, &v);
                 Some(RemoteNodeInfo {
                     version: v.version,
                     address: v.address,
-                    dead_reason,
+                    dead_reason: liveness.dead_reason(),
+                    suspected_since: matches!(liveness, Liveness::Suspected).then(Instant::now),
                     deployed_stacks: v.deployed_stacks,
                 })
             }
@@ -222,8 +359,26 @@ pub async fn start(
 
     debug!("Found existing nodes: {all_nodes:?}");
 
-    let live_nodes = all_nodes
-        .iter()
+    let mut nodes = NodeCollection::default();
+    let mut rejected_nodes = RejectedNodes::new();
+    for node in all_nodes {
+        let address = node.address.clone();
+        match nodes.insert(node) {
+            Ok(()) => (),
+            Err(InsertError::DuplicateAddress) => {
+                bail!("Duplicate node address found: {address}")
+            }
+            Err(InsertError::HashCollision(existing)) => {
+                error!(
+                    "Ignoring node {address} since its hash {existing} is already used by another node"
+                );
+                rejected_nodes.insert((address.address, address.port), (address, existing));
+            }
+        }
+    }
+
+    let live_nodes = nodes
+        .get_nodes()
         .filter_map(|n| {
             if n.dead_reason.is_some() {
                 None
@@ -239,8 +394,11 @@ pub async fn start(
     let state = State {
         notification_channel: tx,
         db: db_client,
-        nodes: NodeCollection::new(all_nodes),
-        assume_dead_after,
+        nodes,
+        rejected_nodes,
+        failure_thresholds,
+        peer_sampling,
+        last_full_update: None,
         my_version,
         my_address,
         deployed_stacks: Default::default(),
@@ -354,15 +512,18 @@ async fn perform_update(state: &mut State) -> Result<()> {
         .await
         .context("Failed to write my status to DB")?;
 
-    let mut all_nodes = read_status_all(state.db.as_ref())
-        .await
-        .context("Failed to load node statuses from DB")?;
+    let (mut all_nodes, checked) = read_statuses_to_check(state).await?;
 
     all_nodes.retain(|_, v| v.region_id == state.region_id && v.address != state.my_address);
 
     let mut missing = vec![];
     for known in state.nodes.get_nodes() {
-        if !all_nodes.contains_key(&(known.address.address, known.address.port)) {
+        let address = (known.address.address, known.address.port);
+        let was_checked = match &checked {
+            None => true,
+            Some(checked) => checked.contains(&address),
+        };
+        if was_checked && !all_nodes.contains_key(&address) {
             let hash = known.address.get_hash();
             state
                 .notification_channel
@@ -374,13 +535,32 @@ async fn perform_update(state: &mut State) -> Result<()> {
         state.nodes.remove(&hash);
     }
 
-    for new in all_nodes {
-        let dead_reason = get_dead_reason(&state.assume_dead_after, &now, &new.1);
+    // Rejected nodes get another chance once the node they collide with is
+    // gone, and are forgotten once they're gone themselves
+    state.rejected_nodes.retain(|address, (_, colliding_with)| {
+        state.nodes.get_node(colliding_with).is_some()
+            && (checked.is_some() || all_nodes.contains_key(address))
+    });
 
-        match (state.nodes.get_by_address(&new.0), dead_reason) {
-            (None, None) => {
-                on_node_discovered(state, new.1);
+    for new in all_nodes {
+        let mut liveness = get_liveness(&state.failure_thresholds, &now, &new.1);
+        let known = state.nodes.get_by_address(&new.0);
+        if let Some(known) = known {
+            if known.address.generation == new.1.address.generation {
+                liveness =
+                    confirm_liveness(&state.failure_thresholds, known, liveness, Instant::now());
             }
+        }
+        let dead_reason = liveness.dead_reason();
+        let suspected = matches!(liveness, Liveness::Suspected);
+
+        match (known, dead_reason) {
+            (None, None) => on_node_discovered(
+                &mut state.nodes,
+                &mut state.rejected_nodes,
+                &state.notification_channel,
+                new.1,
+            ),
 
             (None, Some(_)) => debug!(
                 "Discovered dead node {}:{}, will ignore",
@@ -420,6 +600,21 @@ async fn perform_update(state: &mut State) -> Result<()> {
                         })
                 }
 
+                if suspected && existing.suspected_since.is_none() {
+                    warn!(
+                        "Node {}:{} missed its updates for over {}s, suspecting it's dead",
+                        new.1.address.address,
+                        new.1.address.port,
+                        state.failure_thresholds.suspect_after.num_seconds()
+                    );
+                } else if dead_reason.is_none() && !suspected && existing.suspected_since.is_some()
+                {
+                    info!(
+                        "Suspected node {}:{} is updating its status again",
+                        new.1.address.address, new.1.address.port
+                    );
+                }
+
                 if let Some(dead_reason) = dead_reason {
                     if existing.dead_reason.is_none() {
                         debug!(
@@ -435,6 +630,13 @@ async fn perform_update(state: &mut State) -> Result<()> {
 
                 state.nodes.update_in_place(&hash, |node| {
                     node.dead_reason = dead_reason;
+                    node.suspected_since = match liveness {
+                        Liveness::Alive => None,
+                        Liveness::Suspected => {
+                            Some(node.suspected_since.unwrap_or_else(Instant::now))
+                        }
+                        Liveness::Dead(_) => node.suspected_since,
+                    };
                     node.deployed_stacks = new.1.deployed_stacks;
                 });
             }
@@ -457,7 +659,12 @@ async fn perform_update(state: &mut State) -> Result<()> {
                 state.nodes.remove(&existing_hash);
 
                 if dead_reason.is_none() {
-                    on_node_discovered(state, new.1);
+                    on_node_discovered(
+                        &mut state.nodes,
+                        &mut state.rejected_nodes,
+                        &state.notification_channel,
+                        new.1,
+                    );
                 }
             }
 
@@ -471,36 +678,129 @@ async fn perform_update(state: &mut State) -> Result<()> {
     Ok(())
 }
 
-fn on_node_discovered(state: &mut State, node: NodeStatus) {
+// Reads the statuses of every node, or only of a few known ones if peer
+// sampling is on and no full update is due. Returns the addresses that were
+// read in the latter case, since only those can be noticed missing.
+async fn read_statuses_to_check(
+    state: &mut State,
+) -> Result<(NodeStatuses, Option<HashSet<(IpAddr, u16)>>)> {
+    let now = Instant::now();
+    let sampling = match state.peer_sampling {
+        Some(sampling) if !sampling.full_update_due(state.last_full_update, now) => sampling,
+        _ => {
+            let all_nodes = read_status_all(state.db.as_ref())
+                .await
+                .context("Failed to load node statuses from DB")?;
+            state.last_full_update = Some(now);
+            return Ok((all_nodes, None));
+        }
+    };
+
+    let known = state
+        .nodes
+        .get_nodes()
+        .map(|n| (n.address.address, n.address.port))
+        .collect::<Vec<_>>();
+    let picked = pick_peers(&known, sampling.fanout, &mut rand::thread_rng());
+
+    let mut statuses = HashMap::new();
+    for address in &picked {
+        if let Some(status) = read_status(state.db.as_ref(), *address)
+            .await
+            .context("Failed to load node status from DB")?
+        {
+            statuses.insert(*address, status);
+        }
+    }
+
+    Ok((statuses, Some(picked.into_iter().collect())))
+}
+
+fn on_node_discovered(
+    nodes: &mut NodeCollection,
+    rejected_nodes: &mut RejectedNodes,
+    notification_channel: &NotificationChannel<Notification>,
+    node: NodeStatus,
+) {
+    let key = (node.address.address, node.address.port);
+    if matches!(rejected_nodes.get(&key), Some((rejected, _)) if *rejected == node.address) {
+        return;
+    }
+
     debug!("Node discovered: {node:?}");
-    state
-        .notification_channel
-        .send(Notification::NodeDiscovered(node.address.clone()));
-    assert!(state.nodes.insert(RemoteNodeInfo {
+    let address = node.address.clone();
+    match nodes.insert(RemoteNodeInfo {
         version: node.version,
         address: node.address,
         dead_reason: None,
-        deployed_stacks: node.deployed_stacks
-    }));
+        suspected_since: None,
+        deployed_stacks: node.deployed_stacks,
+    }) {
+        Ok(()) => {
+            rejected_nodes.remove(&key);
+            notification_channel.send(Notification::NodeDiscovered(address));
+        }
+
+        Err(InsertError::HashCollision(existing)) => {
+            error!(
+                "Rejecting node {address} since its hash {existing} is already used by another node"
+            );
+            rejected_nodes.insert(key, (address.clone(), existing));
+            notification_channel.send(Notification::NodeRejected {
+                node: address,
+                colliding_with: existing,
+            });
+        }
+
+        // Callers only discover nodes they don't know yet
+        Err(InsertError::DuplicateAddress) => {
+            error!("Discovered node {address} is already known, ignoring")
+        }
+    }
 }
 
-fn get_dead_reason(
-    assume_dead_after: &chrono::Duration,
+fn get_liveness(
+    thresholds: &FailureThresholds,
     now: &chrono::NaiveDateTime,
     node: &NodeStatus,
-) -> Option<NodeDeadReason> {
+) -> Liveness {
     match node.state {
-        NodeState::Dead => Some(NodeDeadReason::DeadState),
+        NodeState::Dead => Liveness::Dead(NodeDeadReason::DeadState),
         NodeState::Alive => {
-            if now.signed_duration_since(node.last_update) < *assume_dead_after {
-                None
+            let since_last_update = now.signed_duration_since(node.last_update);
+            if since_last_update >= thresholds.assume_dead_after {
+                Liveness::Dead(NodeDeadReason::MissedUpdate)
+            } else if since_last_update >= thresholds.suspect_after {
+                Liveness::Suspected
             } else {
-                Some(NodeDeadReason::MissedUpdate)
+                Liveness::Alive
             }
         }
     }
 }
 
+// Nodes that stopped are dead right away, but missing updates only kills a
+// node that we already suspected for `death_timeout`, so that one late or
+// skewed read of its status doesn't move its stacks
+fn confirm_liveness(
+    thresholds: &FailureThresholds,
+    known: &RemoteNodeInfo,
+    liveness: Liveness,
+    now: Instant,
+) -> Liveness {
+    let Liveness::Dead(NodeDeadReason::MissedUpdate) = liveness else {
+        return liveness;
+    };
+    if known.dead_reason.is_some() {
+        return liveness;
+    }
+
+    match known.suspected_since {
+        Some(since) if now.duration_since(since) >= thresholds.death_timeout => liveness,
+        _ => Liveness::Suspected,
+    }
+}
+
 async fn mark_me_dead(state: &State) -> Result<()> {
     debug!("Writing dead node status");
     let status = NodeStatus {
@@ -527,7 +827,6 @@ fn deserialize_status(
     NodeStatus::try_from((address_proto, status)).context("Failed to read node status")
 }
 
-#[allow(dead_code)]
 async fn read_status(db: &dyn DbClient, node_port: (IpAddr, u16)) -> Result<Option<NodeStatus>> {
     let (address_proto, key) = serialize_key(node_port)?;
     let raw = read_status_raw(db, key).await?;
@@ -535,7 +834,7 @@ async fn read_status(db: &dyn DbClient, node_port: (IpAddr, u16)) -> Result<Opti
         .transpose()
 }
 
-async fn read_status_all(db: &dyn DbClient) -> Result<HashMap<(IpAddr, u16), NodeStatus>> {
+async fn read_status_all(db: &dyn DbClient) -> Result<NodeStatuses> {
     let kvs = db
         .scan_raw(DB_KEY_PREFIX.to_vec(), DB_KEY_UPPER_BOUND.to_vec(), 10240)
         .await
@@ -624,3 +923,571 @@ fn compare_deployed_stack_list(
 
     CompareDeployedStacksResult { added, removed }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        net::Ipv4Addr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use mu_db::{Blob, DeleteTable, Key, Scan, TableName, TransactionFn};
+
+    use super::*;
+    use crate::stack::usage_aggregator::tests::add_custom_metrics_to_db;
+
+    // Only supports the raw API, which is all that membership and the
+    // usage aggregator's totals use
+    #[derive(Debug, Clone, Default)]
+    struct RawInMemoryDb(Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>);
+
+    #[async_trait]
+    impl DbClient for RawInMemoryDb {
+        async fn update_stack_tables(
+            &self,
+            _stack_id: StackID,
+            _table_action_tuples: Vec<(TableName, DeleteTable)>,
+        ) -> mu_db::error::Result<()> {
+            unimplemented!()
+        }
+
+        async fn get_raw(&self, key: Vec<u8>) -> mu_db::error::Result<Option<Vec<u8>>> {
+            Ok(self.0.lock().unwrap().get(&key).cloned())
+        }
+
+        async fn scan_raw(
+            &self,
+            lower_inclusive: Vec<u8>,
+            upper_exclusive: Vec<u8>,
+            limit: u32,
+        ) -> mu_db::error::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .range(lower_inclusive..upper_exclusive)
+                .take(limit as usize)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+        }
+
+        async fn put_raw(
+            &self,
+            key: Vec<u8>,
+            value: Vec<u8>,
+            _is_atomic: bool,
+            _ttl: Option<Duration>,
+        ) -> mu_db::error::Result<()> {
+            self.0.lock().unwrap().insert(key, value);
+            Ok(())
+        }
+
+        async fn compare_and_swap_raw(
+            &self,
+            key: Vec<u8>,
+            previous_value: Option<Vec<u8>>,
+            new_value: Vec<u8>,
+        ) -> mu_db::error::Result<(Option<Vec<u8>>, bool)> {
+            let mut kvs = self.0.lock().unwrap();
+            let current = kvs.get(&key).cloned();
+            if current != previous_value {
+                return Ok((current, false));
+            }
+            kvs.insert(key, new_value);
+            Ok((current, true))
+        }
+
+        async fn delete_raw(&self, key: Vec<u8>, _is_atomic: bool) -> mu_db::error::Result<()> {
+            self.0.lock().unwrap().remove(&key);
+            Ok(())
+        }
+
+        async fn get(&self, _key: Key) -> mu_db::error::Result<Option<Vec<u8>>> {
+            unimplemented!()
+        }
+
+        async fn put(
+            &self,
+            _key: Key,
+            _value: Vec<u8>,
+            _is_atomic: bool,
+            _ttl: Option<Duration>,
+        ) -> mu_db::error::Result<()> {
+            unimplemented!()
+        }
+
+        async fn get_ttl(&self, _key: Key) -> mu_db::error::Result<Option<Duration>> {
+            unimplemented!()
+        }
+
+        async fn delete(&self, _key: Key, _is_atomic: bool) -> mu_db::error::Result<()> {
+            unimplemented!()
+        }
+
+        async fn delete_by_prefix(
+            &self,
+            _stack_id: StackID,
+            _table_name: TableName,
+            _prefix_user_key: Blob,
+        ) -> mu_db::error::Result<u64> {
+            unimplemented!()
+        }
+
+        async fn clear_table(
+            &self,
+            _stack_id: StackID,
+            _table_name: TableName,
+        ) -> mu_db::error::Result<u64> {
+            unimplemented!()
+        }
+
+        async fn scan(
+            &self,
+            _scan: Scan,
+            _limit: u32,
+            _reverse: bool,
+        ) -> mu_db::error::Result<Vec<(Key, Vec<u8>)>> {
+            unimplemented!()
+        }
+
+        async fn scan_keys(
+            &self,
+            _scan: Scan,
+            _limit: u32,
+            _reverse: bool,
+        ) -> mu_db::error::Result<Vec<Key>> {
+            unimplemented!()
+        }
+
+        async fn count_by_prefix(
+            &self,
+            _stack_id: StackID,
+            _table_name: TableName,
+            _prefix_inner_key: Blob,
+            _limit: u64,
+        ) -> mu_db::error::Result<u64> {
+            unimplemented!()
+        }
+
+        async fn count(&self, _scan: Scan, _limit: u64) -> mu_db::error::Result<u64> {
+            unimplemented!()
+        }
+
+        async fn batch_put(
+            &self,
+            _pairs: Vec<(Key, Vec<u8>)>,
+            _is_atomic: bool,
+        ) -> mu_db::error::Result<()> {
+            unimplemented!()
+        }
+
+        async fn batch_get(&self, _keys: Vec<Key>) -> mu_db::error::Result<Vec<(Key, Vec<u8>)>> {
+            unimplemented!()
+        }
+
+        async fn batch_get_ordered(
+            &self,
+            _keys: Vec<Key>,
+        ) -> mu_db::error::Result<Vec<Option<(Key, Vec<u8>)>>> {
+            unimplemented!()
+        }
+
+        async fn batch_delete(&self, _keys: Vec<Key>) -> mu_db::error::Result<()> {
+            unimplemented!()
+        }
+
+        async fn batch_scan(
+            &self,
+            _scans: Vec<Scan>,
+            _each_limit: u32,
+        ) -> mu_db::error::Result<Vec<(Key, Vec<u8>)>> {
+            unimplemented!()
+        }
+
+        async fn batch_scan_keys(
+            &self,
+            _scans: Vec<Scan>,
+            _each_limit: u32,
+        ) -> mu_db::error::Result<Vec<Key>> {
+            unimplemented!()
+        }
+
+        async fn table_list(
+            &self,
+            _stack_id: StackID,
+            _table_name_prefix: Option<TableName>,
+        ) -> mu_db::error::Result<Vec<TableName>> {
+            unimplemented!()
+        }
+
+        async fn stack_id_list(&self) -> mu_db::error::Result<Vec<StackID>> {
+            unimplemented!()
+        }
+
+        async fn compare_and_swap(
+            &self,
+            _key: Key,
+            _previous_value: Option<Vec<u8>>,
+            _new_value: Vec<u8>,
+        ) -> mu_db::error::Result<(Option<Vec<u8>>, bool)> {
+            unimplemented!()
+        }
+
+        async fn batch_compare_and_swap_non_atomic(
+            &self,
+            _ops: Vec<(Key, Option<Vec<u8>>, Vec<u8>)>,
+        ) -> mu_db::error::Result<Vec<(Option<Vec<u8>>, bool)>> {
+            unimplemented!()
+        }
+
+        async fn transaction(&self, _f: TransactionFn) -> mu_db::error::Result<()> {
+            unimplemented!()
+        }
+    }
+
+    fn config(suspicion_timeout: Option<u64>) -> MembershipConfig {
+        MembershipConfig {
+            update_interval: Duration::from_secs(5).into(),
+            assume_dead_after: Duration::from_secs(20).into(),
+            suspicion_timeout: suspicion_timeout.map(|s| Duration::from_secs(s).into()),
+            death_timeout: None,
+            fanout: None,
+            anti_entropy_interval: None,
+        }
+    }
+
+    fn sampling_config(fanout: usize, anti_entropy_secs: Option<u64>) -> MembershipConfig {
+        MembershipConfig {
+            fanout: Some(fanout),
+            anti_entropy_interval: anti_entropy_secs.map(|s| Duration::from_secs(s).into()),
+            ..config(None)
+        }
+    }
+
+    fn node_updated_secs_ago(now: &chrono::NaiveDateTime, secs: i64) -> NodeStatus {
+        NodeStatus {
+            version: 1,
+            address: NodeAddress {
+                address: Ipv4Addr::LOCALHOST.into(),
+                port: 12012,
+                generation: 1,
+            },
+            region_id: vec![],
+            last_update: *now - chrono::Duration::seconds(secs),
+            state: NodeState::Alive,
+            deployed_stacks: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn nodes_with_colliding_hashes_are_rejected() {
+        let mut nodes = NodeCollection::with_hasher(|_| NodeHash([1; 32]));
+        let (channel, mut rx) = NotificationChannel::new();
+        let now = chrono::Utc::now().naive_utc();
+
+        let first = node_updated_secs_ago(&now, 0);
+        let mut second = node_updated_secs_ago(&now, 0);
+        second.address.port += 1;
+
+        let (first_address, second_address) = (first.address.clone(), second.address.clone());
+
+        let mut rejected = RejectedNodes::new();
+        on_node_discovered(&mut nodes, &mut rejected, &channel, first);
+        on_node_discovered(&mut nodes, &mut rejected, &channel, second);
+
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Notification::NodeDiscovered(node)) if node == first_address
+        ));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Notification::NodeRejected { node, colliding_with })
+                if node == second_address && colliding_with == NodeHash([1; 32])
+        ));
+        assert!(rx.try_recv().is_err());
+
+        let known = nodes.get_nodes().collect::<Vec<_>>();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].address, first_address);
+    }
+
+    #[tokio::test]
+    async fn custom_metrics_are_not_read_as_node_statuses() {
+        let db = RawInMemoryDb::default();
+        let now = chrono::Utc::now().naive_utc();
+
+        write_status(&db, node_updated_secs_ago(&now, 0))
+            .await
+            .unwrap();
+        add_custom_metrics_to_db(
+            Box::new(db.clone()),
+            StackID::SolanaPublicKey([1; 32]),
+            &[("premium_calls".to_string(), 2)].into(),
+        )
+        .await
+        .unwrap();
+
+        let statuses = read_status_all(&db).await.unwrap();
+        assert_eq!(statuses.len(), 1);
+    }
+
+    #[test]
+    fn rejected_nodes_are_only_rejected_once() {
+        let mut nodes = NodeCollection::with_hasher(|_| NodeHash([1; 32]));
+        let mut rejected = RejectedNodes::new();
+        let (channel, mut rx) = NotificationChannel::new();
+        let now = chrono::Utc::now().naive_utc();
+
+        let second = || {
+            let mut node = node_updated_secs_ago(&now, 0);
+            node.address.port += 1;
+            node
+        };
+
+        on_node_discovered(
+            &mut nodes,
+            &mut rejected,
+            &channel,
+            node_updated_secs_ago(&now, 0),
+        );
+        on_node_discovered(&mut nodes, &mut rejected, &channel, second());
+        assert!(matches!(rx.try_recv(), Ok(Notification::NodeDiscovered(_))));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Notification::NodeRejected { .. })
+        ));
+
+        // As it's found again in the next update
+        on_node_discovered(&mut nodes, &mut rejected, &channel, second());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn rediscovering_a_known_node_is_ignored() {
+        let mut nodes = NodeCollection::default();
+        let mut rejected = RejectedNodes::new();
+        let (channel, mut rx) = NotificationChannel::new();
+        let now = chrono::Utc::now().naive_utc();
+
+        on_node_discovered(
+            &mut nodes,
+            &mut rejected,
+            &channel,
+            node_updated_secs_ago(&now, 0),
+        );
+        on_node_discovered(
+            &mut nodes,
+            &mut rejected,
+            &channel,
+            node_updated_secs_ago(&now, 0),
+        );
+
+        assert!(matches!(rx.try_recv(), Ok(Notification::NodeDiscovered(_))));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(nodes.get_nodes().count(), 1);
+    }
+
+    fn liveness_after_missing_updates_for(config: &MembershipConfig, secs: i64) -> Liveness {
+        let thresholds = FailureThresholds::from_config(config).unwrap();
+        let now = chrono::Utc::now().naive_utc();
+        get_liveness(&thresholds, &now, &node_updated_secs_ago(&now, secs))
+    }
+
+    #[test]
+    fn missed_updates_within_the_suspicion_window_keep_nodes_alive() {
+        let config = config(Some(10));
+        assert!(matches!(
+            liveness_after_missing_updates_for(&config, 0),
+            Liveness::Alive
+        ));
+        assert!(matches!(
+            liveness_after_missing_updates_for(&config, 9),
+            Liveness::Alive
+        ));
+    }
+
+    #[test]
+    fn nodes_are_suspected_before_they_are_assumed_dead() {
+        let config = config(Some(10));
+        for secs in [10, 19] {
+            let liveness = liveness_after_missing_updates_for(&config, secs);
+            assert!(matches!(liveness, Liveness::Suspected), "{secs}s");
+            assert!(liveness.dead_reason().is_none());
+        }
+        assert!(matches!(
+            liveness_after_missing_updates_for(&config, 20),
+            Liveness::Dead(NodeDeadReason::MissedUpdate)
+        ));
+    }
+
+    #[test]
+    fn nodes_die_without_suspicion_by_default() {
+        let config = config(None);
+        assert!(matches!(
+            liveness_after_missing_updates_for(&config, 19),
+            Liveness::Alive
+        ));
+        assert!(matches!(
+            liveness_after_missing_updates_for(&config, 20),
+            Liveness::Dead(NodeDeadReason::MissedUpdate)
+        ));
+    }
+
+    #[test]
+    fn nodes_that_stopped_are_dead_right_away() {
+        let thresholds = FailureThresholds::from_config(&config(Some(10))).unwrap();
+        let now = chrono::Utc::now().naive_utc();
+        let node = NodeStatus {
+            state: NodeState::Dead,
+            ..node_updated_secs_ago(&now, 0)
+        };
+        assert!(matches!(
+            get_liveness(&thresholds, &now, &node),
+            Liveness::Dead(NodeDeadReason::DeadState)
+        ));
+    }
+
+    fn known_node(suspected_since: Option<Instant>) -> RemoteNodeInfo {
+        let now = chrono::Utc::now().naive_utc();
+        let status = node_updated_secs_ago(&now, 0);
+        RemoteNodeInfo {
+            version: status.version,
+            address: status.address,
+            dead_reason: None,
+            suspected_since,
+            deployed_stacks: status.deployed_stacks,
+        }
+    }
+
+    #[test]
+    fn missed_updates_only_kill_nodes_suspected_for_the_death_timeout() {
+        let config = MembershipConfig {
+            death_timeout: Some(Duration::from_secs(30).into()),
+            ..config(Some(10))
+        };
+        let thresholds = FailureThresholds::from_config(&config).unwrap();
+        let now = Instant::now();
+        let missed = Liveness::Dead(NodeDeadReason::MissedUpdate);
+
+        let never_suspected = known_node(None);
+        assert!(matches!(
+            confirm_liveness(&thresholds, &never_suspected, missed, now),
+            Liveness::Suspected
+        ));
+
+        let suspected_recently = known_node(Some(now - Duration::from_secs(29)));
+        assert!(matches!(
+            confirm_liveness(&thresholds, &suspected_recently, missed, now),
+            Liveness::Suspected
+        ));
+
+        let suspected_long_ago = known_node(Some(now - Duration::from_secs(30)));
+        assert!(matches!(
+            confirm_liveness(&thresholds, &suspected_long_ago, missed, now),
+            Liveness::Dead(NodeDeadReason::MissedUpdate)
+        ));
+    }
+
+    #[test]
+    fn missed_updates_are_confirmed_by_a_later_update_by_default() {
+        let thresholds = FailureThresholds::from_config(&config(None)).unwrap();
+        let now = Instant::now();
+        let missed = Liveness::Dead(NodeDeadReason::MissedUpdate);
+
+        // Suspicion starts at the update that first sees the node dead...
+        assert!(matches!(
+            confirm_liveness(&thresholds, &known_node(None), missed, now),
+            Liveness::Suspected
+        ));
+        // ...and the next one confirms it
+        assert!(matches!(
+            confirm_liveness(&thresholds, &known_node(Some(now)), missed, now),
+            Liveness::Dead(NodeDeadReason::MissedUpdate)
+        ));
+    }
+
+    #[test]
+    fn stopped_nodes_are_not_held_back_by_suspicion() {
+        let thresholds = FailureThresholds::from_config(&MembershipConfig {
+            death_timeout: Some(Duration::from_secs(30).into()),
+            ..config(Some(10))
+        })
+        .unwrap();
+
+        assert!(matches!(
+            confirm_liveness(
+                &thresholds,
+                &known_node(None),
+                Liveness::Dead(NodeDeadReason::DeadState),
+                Instant::now()
+            ),
+            Liveness::Dead(NodeDeadReason::DeadState)
+        ));
+    }
+
+    #[test]
+    fn inconsistent_thresholds_are_rejected() {
+        assert!(FailureThresholds::from_config(&config(Some(21))).is_err());
+
+        let too_short = MembershipConfig {
+            assume_dead_after: Duration::from_secs(5).into(),
+            ..config(None)
+        };
+        assert!(FailureThresholds::from_config(&too_short).is_err());
+    }
+
+    #[test]
+    fn at_most_fanout_peers_are_picked_per_round() {
+        let peers = (0..50).collect::<Vec<u32>>();
+        let mut rng = rand::thread_rng();
+
+        for fanout in [1, 3, 50, 100] {
+            let mut picked = pick_peers(&peers, fanout, &mut rng);
+            assert_eq!(picked.len(), fanout.min(peers.len()));
+
+            picked.sort();
+            picked.dedup();
+            assert_eq!(picked.len(), fanout.min(peers.len()), "peers picked twice");
+        }
+    }
+
+    #[test]
+    fn every_peer_is_eventually_checked() {
+        use rand::SeedableRng;
+
+        let peers = (0..100).collect::<Vec<u32>>();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut checked = HashSet::new();
+
+        for _ in 0..200 {
+            checked.extend(pick_peers(&peers, 5, &mut rng));
+        }
+        assert_eq!(checked.len(), peers.len());
+    }
+
+    #[test]
+    fn full_updates_happen_every_anti_entropy_interval() {
+        let sampling = PeerSampling::from_config(&sampling_config(3, Some(60)))
+            .unwrap()
+            .unwrap();
+        let start = Instant::now();
+
+        assert!(sampling.full_update_due(None, start));
+        assert!(!sampling.full_update_due(Some(start), start + Duration::from_secs(59)));
+        assert!(sampling.full_update_due(Some(start), start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn peer_sampling_is_off_by_default() {
+        assert!(PeerSampling::from_config(&config(None)).unwrap().is_none());
+
+        let sampling = PeerSampling::from_config(&sampling_config(3, None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(sampling.anti_entropy_interval, Duration::from_secs(20));
+
+        assert!(PeerSampling::from_config(&sampling_config(0, None)).is_err());
+    }
+}
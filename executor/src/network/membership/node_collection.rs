@@ -1,32 +1,40 @@
 use std::{collections::HashMap, net::IpAddr};
 
-use crate::network::NodeHash;
+use crate::network::{NodeAddress, NodeHash};
 
 use super::RemoteNodeInfo;
 
-#[derive(Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum InsertError {
+    /// A node with the same address and port is already known.
+    DuplicateAddress,
+    /// A distinct node with the same hash is already known.
+    HashCollision(NodeHash),
+}
+
 pub(super) struct NodeCollection {
     nodes: HashMap<NodeHash, RemoteNodeInfo>,
     nodes_by_addr_and_port: HashMap<(IpAddr, u16), NodeHash>,
+    hasher: fn(&NodeAddress) -> NodeHash,
+}
+
+impl Default for NodeCollection {
+    fn default() -> Self {
+        Self::with_hasher(NodeAddress::get_hash)
+    }
 }
 
 impl NodeCollection {
-    pub fn new(nodes: Vec<RemoteNodeInfo>) -> Self {
-        let result = Self {
-            nodes_by_addr_and_port: nodes
-                .iter()
-                .map(|n| ((n.address.address, n.address.port), n.address.get_hash()))
-                .collect(),
-            nodes: nodes
-                .into_iter()
-                .map(|n| (n.address.get_hash(), n))
-                .collect(),
-        };
-        assert!(
-            result.nodes.len() == result.nodes_by_addr_and_port.len(),
-            "Duplicate node addresses found"
-        );
-        result
+    pub(super) fn with_hasher(hasher: fn(&NodeAddress) -> NodeHash) -> Self {
+        Self {
+            nodes: Default::default(),
+            nodes_by_addr_and_port: Default::default(),
+            hasher,
+        }
+    }
+
+    pub(super) fn hash_of(&self, address: &NodeAddress) -> NodeHash {
+        (self.hasher)(address)
     }
 
     pub(super) fn get_nodes(&self) -> impl Iterator<Item = &RemoteNodeInfo> {
@@ -43,21 +51,26 @@ impl NodeCollection {
             .map(|hash| self.nodes.get(hash).expect("Index out of sync"))
     }
 
-    pub(super) fn insert(&mut self, node: RemoteNodeInfo) -> bool {
-        let hash = node.address.get_hash();
+    pub(super) fn insert(&mut self, node: RemoteNodeInfo) -> Result<(), InsertError> {
+        let hash = self.hash_of(&node.address);
         if self
             .nodes_by_addr_and_port
             .get(&(node.address.address, node.address.port))
             .is_some()
         {
-            // No need to check self.nodes, this check covers it
-            return false;
+            return Err(InsertError::DuplicateAddress);
+        }
+
+        // The address check above means whatever is stored under this hash
+        // is a different node, which must not be overwritten.
+        if self.nodes.contains_key(&hash) {
+            return Err(InsertError::HashCollision(hash));
         }
 
         self.nodes_by_addr_and_port
             .insert((node.address.address, node.address.port), hash);
         self.nodes.insert(hash, node);
-        true
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -69,8 +82,8 @@ impl NodeCollection {
         match self.remove(hash) {
             None => false,
             Some(node) => {
-                if !self.insert(update(node)) {
-                    panic!("Update resulted in duplicate node address");
+                if let Err(e) = self.insert(update(node)) {
+                    panic!("Update resulted in conflicting node: {e:?}");
                 }
 
                 true
@@ -88,7 +101,7 @@ impl NodeCollection {
             Some(node) => {
                 update(node);
 
-                if node.address.get_hash() != *hash {
+                if (self.hasher)(&node.address) != *hash {
                     panic!("Update resulted in different node hash");
                 }
 
@@ -109,3 +122,60 @@ impl NodeCollection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn node(port: u16) -> RemoteNodeInfo {
+        RemoteNodeInfo {
+            version: 1,
+            address: NodeAddress {
+                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port,
+                generation: 1,
+            },
+            dead_reason: None,
+            suspected_since: None,
+            deployed_stacks: Default::default(),
+        }
+    }
+
+    #[test]
+    fn duplicate_addresses_are_rejected() {
+        let mut nodes = NodeCollection::default();
+        nodes.insert(node(1000)).unwrap();
+
+        let mut next_generation = node(1000);
+        next_generation.address.generation = 2;
+
+        assert_eq!(
+            nodes.insert(next_generation),
+            Err(InsertError::DuplicateAddress)
+        );
+        assert_eq!(nodes.get_nodes().count(), 1);
+    }
+
+    #[test]
+    fn hash_collisions_are_rejected() {
+        let mut nodes = NodeCollection::with_hasher(|_| NodeHash([7; 32]));
+        nodes.insert(node(1000)).unwrap();
+
+        assert_eq!(
+            nodes.insert(node(2000)),
+            Err(InsertError::HashCollision(NodeHash([7; 32])))
+        );
+
+        // The existing node must be left untouched
+        assert_eq!(nodes.get_nodes().count(), 1);
+        assert_eq!(
+            nodes.get_node(&NodeHash([7; 32])).unwrap().address.port,
+            1000
+        );
+        assert!(nodes
+            .get_by_address(&(IpAddr::V4(Ipv4Addr::LOCALHOST), 2000))
+            .is_none());
+    }
+}
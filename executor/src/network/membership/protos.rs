@@ -82,6 +82,10 @@ impl From<super::NodeStatus> for membership::NodeStatus {
                     id: Some(membership::stack_id::Id::Solana(k.into())),
                     ..Default::default()
                 },
+                mu_stack::StackID::PWRStackID(id) => membership::StackID {
+                    id: Some(membership::stack_id::Id::Pwr(id.as_bytes().to_vec())),
+                    ..Default::default()
+                },
             }
         }
 
@@ -142,6 +146,11 @@ impl TryFrom<(membership::NodeAddress, membership::NodeStatus)> for super::NodeS
                     k.try_into()
                         .map_err(|_| anyhow::anyhow!("Expected 32 bytes for a Solana stack ID"))?,
                 ),
+
+                Some(membership::stack_id::Id::Pwr(k)) => mu_stack::StackID::PWRStackID(
+                    uuid::Uuid::from_slice(&k)
+                        .map_err(|_| anyhow::anyhow!("Expected 16 bytes for a PWR stack ID"))?,
+                ),
             })
         }
 
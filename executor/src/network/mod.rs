@@ -85,3 +85,45 @@ pub enum NodeConnection {
     Established(ConnectionID),
     NotEstablished(NodeAddress),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn address() -> NodeAddress {
+        NodeAddress {
+            address: Ipv4Addr::new(10, 0, 0, 1).into(),
+            port: 12012,
+            generation: 1_700_000_000_000_000_000,
+        }
+    }
+
+    #[test]
+    fn node_hashes_are_stable() {
+        let address = address();
+        let roundtripped: NodeAddress =
+            serde_json::from_str(&serde_json::to_string(&address).unwrap()).unwrap();
+
+        assert_eq!(address.get_hash(), address.get_hash());
+        assert_eq!(address.get_hash(), address.clone().get_hash());
+        assert_eq!(address.get_hash(), roundtripped.get_hash());
+    }
+
+    #[test]
+    fn node_hashes_cover_every_field() {
+        let hash = address().get_hash();
+
+        let mut other_address = address();
+        other_address.address = Ipv4Addr::new(10, 0, 0, 2).into();
+        let mut other_port = address();
+        other_port.port += 1;
+        let mut other_generation = address();
+        other_generation.generation += 1;
+
+        assert_ne!(hash, other_address.get_hash());
+        assert_ne!(hash, other_port.get_hash());
+        assert_ne!(hash, other_generation.get_hash());
+    }
+}
@@ -165,6 +165,11 @@ impl<RequestHandler: RpcRequestHandler + Clone + Send + Sync + 'static> RpcHandl
                 Some(protos::rpc::execute_function_response::Result::Error(f)) => {
                     bail!("Received error response to execute function request: {f}")
                 }
+                Some(protos::rpc::execute_function_response::Result::FunctionError(f)) => {
+                    let error = mu_gateway::FunctionError::try_from(f)
+                        .context("Failed to read execute function error")?;
+                    Err(error.into())
+                }
                 Some(protos::rpc::execute_function_response::Result::Ok(response)) => {
                     let response = Response::<'static>::try_from(response)
                         .context("Failed to read execute function response")?;
@@ -190,9 +195,12 @@ async fn send_execute_function_reply(
                 ..Default::default()
             },
             Err(f) => protos::rpc::ExecuteFunctionResponse {
-                result: Some(protos::rpc::execute_function_response::Result::Error(
-                    format!("{f:?}"),
-                )),
+                result: Some(match f.downcast_ref::<mu_gateway::FunctionError>() {
+                    Some(e) => protos::rpc::execute_function_response::Result::FunctionError(
+                        protos::rpc::FunctionError::from(e),
+                    ),
+                    None => protos::rpc::execute_function_response::Result::Error(format!("{f:?}")),
+                }),
                 ..Default::default()
             },
         };
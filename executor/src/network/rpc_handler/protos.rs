@@ -9,10 +9,13 @@ include!(concat!(env!("OUT_DIR"), "/protos/rpc/mod.rs"));
 
 impl From<mu_stack::FunctionID> for rpc::FunctionID {
     fn from(id: mu_stack::FunctionID) -> Self {
-        let StackID::SolanaPublicKey(pk) = id.assembly_id.stack_id;
+        let stack_id = match id.assembly_id.stack_id {
+            StackID::SolanaPublicKey(pk) => rpc::stack_id::Id::Solana(pk.into()),
+            StackID::PWRStackID(id) => rpc::stack_id::Id::Pwr(id.as_bytes().to_vec()),
+        };
         Self {
             stack_id: MessageField(Some(Box::new(rpc::StackID {
-                id: Some(rpc::stack_id::Id::Solana(pk.into())),
+                id: Some(stack_id),
                 ..Default::default()
             }))),
             assembly_name: id.assembly_id.assembly_name,
@@ -34,6 +37,10 @@ impl TryFrom<rpc::FunctionID> for mu_stack::FunctionID {
                     .map_err(|_| anyhow!("Incorrect stack ID length"))?,
             ),
 
+            Some(rpc::stack_id::Id::Pwr(bytes)) => StackID::PWRStackID(
+                uuid::Uuid::from_slice(&bytes).map_err(|_| anyhow!("Incorrect stack ID length"))?,
+            ),
+
             None => bail!("Empty stack ID"),
         };
 
@@ -92,6 +99,7 @@ impl<'a> From<musdk_common::Request<'a>> for rpc::Request {
 
         Self {
             method: convert_http_method(request.method),
+            route_template: request.route_template.into_owned(),
             path_params: request
                 .path_params
                 .into_iter()
@@ -143,6 +151,7 @@ impl TryFrom<rpc::Request> for musdk_common::Request<'static> {
 
         Ok(Self {
             method: convert_http_method(request.method)?,
+            route_template: Cow::Owned(request.route_template),
             path_params: request
                 .path_params
                 .into_iter()
@@ -191,3 +200,44 @@ impl TryFrom<rpc::Response> for musdk_common::Response<'static> {
         })
     }
 }
+
+impl From<&mu_gateway::FunctionError> for rpc::FunctionError {
+    fn from(error: &mu_gateway::FunctionError) -> Self {
+        let kind = match error.kind {
+            mu_gateway::FunctionErrorKind::Failed => rpc::FunctionErrorKind::FAILED,
+            mu_gateway::FunctionErrorKind::TimedOut => rpc::FunctionErrorKind::TIMED_OUT,
+            mu_gateway::FunctionErrorKind::MemoryExceeded => {
+                rpc::FunctionErrorKind::MEMORY_EXCEEDED
+            }
+        };
+
+        Self {
+            kind: EnumOrUnknown::new(kind),
+            message: error.message.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<rpc::FunctionError> for mu_gateway::FunctionError {
+    type Error = anyhow::Error;
+
+    fn try_from(error: rpc::FunctionError) -> Result<Self, Self::Error> {
+        let kind = error
+            .kind
+            .enum_value()
+            .map(|k| match k {
+                rpc::FunctionErrorKind::FAILED => mu_gateway::FunctionErrorKind::Failed,
+                rpc::FunctionErrorKind::TIMED_OUT => mu_gateway::FunctionErrorKind::TimedOut,
+                rpc::FunctionErrorKind::MEMORY_EXCEEDED => {
+                    mu_gateway::FunctionErrorKind::MemoryExceeded
+                }
+            })
+            .map_err(|i| anyhow!("Unknown enum value {i} for type FunctionErrorKind"))?;
+
+        Ok(Self {
+            kind,
+            message: error.message,
+        })
+    }
+}
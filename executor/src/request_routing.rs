@@ -2,18 +2,19 @@ use std::sync::Arc;
 
 use anyhow::{bail, Context, Result};
 use log::{debug, trace};
+use mu_gateway::{FunctionError, FunctionResponse};
 use mu_runtime::Runtime;
 use mu_stack::{FunctionID, StackID};
-use musdk_common::{Request, Response};
+use musdk_common::Request;
 use rand::seq::SliceRandom;
 use tokio::sync::RwLock;
 
 use crate::{
     network::{
         connection_manager::ConnectionManager, membership::Membership, rpc_handler::RpcHandler,
-        NodeAddress,
+        NodeAddress, NodeHash,
     },
-    stack::scheduler::{Scheduler, StackDeploymentStatus},
+    stack::scheduler::Scheduler,
 };
 
 #[derive(Clone, Debug)]
@@ -25,54 +26,60 @@ enum RoutingTarget {
 
 async fn get_route(
     stack_id: StackID,
+    my_hash: NodeHash,
     scheduler: &dyn Scheduler,
     membership: &dyn Membership,
 ) -> Result<RoutingTarget> {
-    let deployment_status = scheduler
-        .get_deployment_status(stack_id)
+    let mut placements = scheduler
+        .get_stack_placements(stack_id)
         .await
-        .context("Failed to get deployment status")?;
+        .context("Failed to get stack placements")?;
 
-    match deployment_status {
-        StackDeploymentStatus::Unknown | StackDeploymentStatus::NotDeployed => {
-            Ok(RoutingTarget::NotDeployed)
-        }
-
-        StackDeploymentStatus::DeployedToSelf { .. } => Ok(RoutingTarget::Local),
+    if placements.is_empty() {
+        return Ok(RoutingTarget::NotDeployed);
+    }
 
-        StackDeploymentStatus::DeployedToOthers { deployed_to } => {
-            let Some(invocation_target) = deployed_to.choose(&mut rand::thread_rng()) else {
-                bail!("Internal error: no deployment targets");
-            };
+    // Running the function here saves a round-trip to another node, so
+    // we do that whenever we have a copy of the stack
+    if placements.contains(&my_hash) {
+        return Ok(RoutingTarget::Local);
+    }
 
-            let address = membership
-                .get_node(*invocation_target)
-                .await
-                .context("Failed to get address of invocation target node")?;
+    placements.shuffle(&mut rand::thread_rng());
+    for invocation_target in &placements {
+        let address = membership
+            .get_node(*invocation_target)
+            .await
+            .context("Failed to get address of invocation target node")?;
 
-            match address {
-                None => bail!("Scheduler reported stack is deployed to {invocation_target} but the hash is not known"),
-                Some(a) => Ok(RoutingTarget::Remote(a)),
-            }
+        // Membership may drop a node before the scheduler hears about it,
+        // in which case one of the other nodes can still serve the request
+        match address {
+            None => debug!("Scheduler reported stack is deployed to {invocation_target} but the hash is not known, skipping"),
+            Some(a) => return Ok(RoutingTarget::Remote(a)),
         }
     }
+
+    bail!("Scheduler reported stack is deployed to {placements:?} but none of the hashes are known")
 }
 
 pub async fn route_request(
     function_id: FunctionID,
     request: Request<'_>,
+    my_hash: NodeHash,
     connection_manager: Box<dyn ConnectionManager>,
     membership: Box<dyn Membership>,
     scheduler: Arc<RwLock<Option<Box<dyn Scheduler>>>>,
     rpc_handler: Box<dyn RpcHandler>,
     runtime: Box<dyn Runtime>,
-) -> Result<Response<'static>> {
+) -> Result<FunctionResponse> {
     trace!("Request received for {function_id}, will check deployment status");
 
     let scheduler_guard = scheduler.read().await;
     let scheduler = scheduler_guard.as_ref().unwrap().as_ref();
     let route = get_route(
         function_id.assembly_id.stack_id,
+        my_hash,
         scheduler,
         membership.as_ref(),
     )
@@ -87,10 +94,17 @@ pub async fn route_request(
 
     match route {
         RoutingTarget::NotDeployed => bail!("Stack not deployed"),
-        RoutingTarget::Local => runtime
-            .invoke_function(function_id, request)
-            .await
-            .map_err(Into::into),
+        RoutingTarget::Local => {
+            let response = runtime
+                .invoke_function_streaming(function_id, request)
+                .await
+                .map_err(FunctionError::from_runtime_error)?;
+
+            Ok(FunctionResponse {
+                response: response.response,
+                body_stream: response.body_stream,
+            })
+        }
         RoutingTarget::Remote(address) => {
             let (connection_id, new_connection) = {
                 // TODO should pool these connections so we don't do a connection handshake
@@ -117,7 +131,9 @@ pub async fn route_request(
                 let _ = connection_manager.disconnect(connection_id).await;
             }
 
-            response
+            // Responses from other nodes arrive whole, so they're never streamed.
+            // Function failures come back as `FunctionError`s, see `send_execute_function`.
+            response.map(Into::into)
         }
     }
 }
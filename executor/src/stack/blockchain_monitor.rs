@@ -1,8 +1,10 @@
+mod known_stacks;
 mod stack_collection;
 
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::time::{Duration, SystemTime};
-use std::{collections::HashMap, marker::PhantomPinned, ops::Deref, pin::Pin};
+use std::{collections::HashMap, marker::PhantomPinned, ops::Deref, pin::Pin, sync::Arc};
 
 use anchor_client::anchor_lang::{AccountDeserialize, Discriminator};
 use anchor_client::{Cluster, Program};
@@ -52,6 +54,13 @@ pub trait BlockchainMonitor: Clone + Send + Sync {
     async fn get_stack(&self, stack_id: StackID) -> Result<Option<StackWithMetadata>>;
     async fn get_metadata(&self, stack_id: StackID) -> Result<Option<StackMetadata>>;
     async fn get_escrow_balance(&self, owner: StackOwner) -> Result<Option<EscrowBalance>>;
+    /// Checks the chain for an active request signer of `owner`'s, for
+    /// signers that aren't known locally anymore.
+    async fn is_request_signer_active(
+        &self,
+        signer: ApiRequestSigner,
+        owner: StackOwner,
+    ) -> Result<bool>;
     async fn stop(&self) -> Result<()>;
 }
 
@@ -87,6 +96,12 @@ pub struct BlockchainMonitorConfig {
     solana_region_number: u32,
     solana_usage_signer_private_key: Base58PrivateKey,
     solana_usage_report_interval: ConfigDuration,
+    /// If set, the node will start even when Solana is unreachable, serving
+    /// the stacks it knew about the last time it was connected and retrying
+    /// the connection every `degraded_mode_retry_interval`.
+    start_degraded_if_unavailable: bool,
+    degraded_mode_retry_interval: ConfigDuration,
+    known_stacks_path: PathBuf,
 }
 
 type SolanaUnsubscribeFn = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
@@ -118,12 +133,14 @@ struct SolanaPubSub<'a> {
 }
 
 struct Solana<'a> {
-    rpc_client: RpcClient,
+    // Shared with lookups that run outside the mailbox
+    rpc_client: Arc<RpcClient>,
     pub_sub: SolanaPubSub<'a>,
     region_pda: Pubkey,
     provider_pda: Pubkey,
     token_decimals: u8,
     min_escrow_balance: u64,
+    max_giga_instructions_per_call: u32,
     escrow_balances: HashMap<Pubkey, u64>,
 }
 
@@ -138,6 +155,7 @@ enum BlockchainMonitorMessage {
     GetStack(StackID, ReplyChannel<Option<StackWithMetadata>>),
     GetMetadata(StackID, ReplyChannel<Option<StackMetadata>>),
     GetEscrowBalance(StackOwner, ReplyChannel<Option<EscrowBalance>>),
+    IsRequestSignerActive(ApiRequestSigner, StackOwner, ReplyChannel<bool>),
     Tick(ReplyChannel<()>),
     Stop(ReplyChannel<()>),
 }
@@ -178,6 +196,17 @@ impl BlockchainMonitor for BlockchainMonitorImpl {
             .map_err(Into::into)
     }
 
+    async fn is_request_signer_active(
+        &self,
+        signer: ApiRequestSigner,
+        owner: StackOwner,
+    ) -> Result<bool> {
+        self.mailbox
+            .post_and_reply(|r| BlockchainMonitorMessage::IsRequestSignerActive(signer, owner, r))
+            .await
+            .map_err(Into::into)
+    }
+
     async fn stop(&self) -> Result<()> {
         self.mailbox
             .post_and_reply(BlockchainMonitorMessage::Stop)
@@ -211,16 +240,108 @@ pub async fn start(
         &marketplace::id(),
     );
 
+    let tick_interval = *config.solana_usage_report_interval;
+
+    let (mailbox, max_giga_instructions_per_call) =
+        match connect(&config, region_pda, usage_aggregator.clone()).await {
+            Ok(connection) => {
+                let max_giga_instructions_per_call =
+                    connection.state.solana.max_giga_instructions_per_call;
+
+                notification_channel.send(BlockchainMonitorNotification::RequestSignersAvailable(
+                    connection.request_signers,
+                ));
+
+                let mailbox = PlainMailboxProcessor::start(
+                    |_mailbox, message_receiver| {
+                        mailbox_body(
+                            config,
+                            connection.state,
+                            message_receiver,
+                            notification_channel,
+                        )
+                    },
+                    10000,
+                );
+
+                (mailbox, Some(max_giga_instructions_per_call))
+            }
+
+            Err(f) if config.start_degraded_if_unavailable => {
+                warn!("Failed to connect to Solana, starting in degraded mode: {f:?}");
+
+                let known_stacks = match known_stacks::load(&config.known_stacks_path).await {
+                    Ok(x) => x,
+                    Err(f) => {
+                        warn!("Failed to load known stacks, will start with none: {f:?}");
+                        None
+                    }
+                };
+
+                let (max_giga_instructions_per_call, stacks) = match known_stacks {
+                    Some(k) => (Some(k.max_giga_instructions_per_call), k.stacks),
+                    None => (None, vec![]),
+                };
+
+                info!(
+                    "Serving {} previously known stacks until Solana is available",
+                    stacks.len()
+                );
+
+                let mailbox = PlainMailboxProcessor::start(
+                    |_mailbox, message_receiver| {
+                        degraded_mailbox_body(
+                            config,
+                            region_pda,
+                            stacks,
+                            usage_aggregator,
+                            message_receiver,
+                            notification_channel,
+                        )
+                    },
+                    10000,
+                );
+
+                (mailbox, max_giga_instructions_per_call)
+            }
+
+            Err(f) => return Err(f),
+        };
+
+    let res = BlockchainMonitorImpl { mailbox };
+
+    let res_clone = res.clone();
+    tokio::spawn(async move { generate_tick(res_clone, tick_interval).await });
+
+    let region_config = RegionConfig {
+        id: region_pda.to_bytes().into(),
+        max_giga_instructions_per_call,
+    };
+
+    debug!("Initialization complete");
+    Ok((Box::new(res), rx, region_config))
+}
+
+struct Connection<'a> {
+    state: State<'a>,
+    request_signers: Vec<(ApiRequestSigner, StackOwner)>,
+}
+
+async fn connect<'a>(
+    config: &BlockchainMonitorConfig,
+    region_pda: Pubkey,
+    usage_aggregator: Box<dyn UsageAggregator>,
+) -> Result<Connection<'a>> {
     debug!(
         "Solana cluster URLs: {}, {}",
         config.solana_cluster_rpc_url.0,
         config.solana_cluster_pub_sub_url.0.to_string()
     );
 
-    let rpc_client = RpcClient::new_with_commitment(
+    let rpc_client = Arc::new(RpcClient::new_with_commitment(
         config.solana_cluster_rpc_url.0.to_string(),
         CommitmentConfig::finalized(),
-    );
+    ));
 
     debug!("Verifying provider public key and region number");
     let region = get_region(&region_pda, &rpc_client).await?;
@@ -393,7 +514,7 @@ pub async fn start(
     .await?;
     let escrow_balances = owner_states
         .iter()
-        .map(|(k, v)| (Pubkey::new_from_array(k.to_inner()), v.1))
+        .filter_map(|(k, v)| Some((Pubkey::new_from_array(k.solana_public_key()?), v.1)))
         .collect();
     let stacks =
         StackCollection::from_known(owner_states.into_iter().map(|(k, v)| (k, (v.0, v.2))));
@@ -405,9 +526,9 @@ pub async fn start(
         }
         .unwrap(),
         &solana_provider_pda,
-        stacks.owners().map(|o| match o {
-            StackOwner::Solana(pk) => Pubkey::new_from_array(*pk),
-        }),
+        stacks
+            .owners()
+            .filter_map(|o| o.solana_public_key().map(Pubkey::new_from_array)),
     )
     .await?;
 
@@ -420,36 +541,17 @@ pub async fn start(
             token_decimals: solana_token_decimals,
             region_pda,
             min_escrow_balance: region.min_escrow_balance,
+            max_giga_instructions_per_call: region.max_giga_instructions_per_call,
             escrow_balances,
         },
         usage_aggregator,
     };
 
-    let tick_interval = *config.solana_usage_report_interval;
-
-    notification_channel.send(BlockchainMonitorNotification::RequestSignersAvailable(
-        existing_request_signers,
-    ));
-
-    let mailbox = PlainMailboxProcessor::start(
-        |_mailbox, message_receiver| {
-            mailbox_body(config, state, message_receiver, notification_channel)
-        },
-        10000,
-    );
-
-    let res = BlockchainMonitorImpl { mailbox };
-
-    let res_clone = res.clone();
-    tokio::spawn(async move { generate_tick(res_clone, tick_interval).await });
-
-    let region_config = RegionConfig {
-        id: region_pda.to_bytes().into(),
-        max_giga_instructions_per_call: Some(region.max_giga_instructions_per_call),
-    };
+    Ok(Connection {
+        state,
+        request_signers: existing_request_signers,
+    })
 
-    debug!("Initialization complete");
-    Ok((Box::new(res), rx, region_config))
 }
 
 async fn get_owner_states(
@@ -485,10 +587,13 @@ async fn fetch_owner_escrow_balance(
     owner: &StackOwner,
     provider_pda: &Pubkey,
 ) -> Result<Option<u64>> {
-    let StackOwner::Solana(owner_key) = owner;
+    // Only Solana accounts have escrow accounts with us
+    let Some(owner_key) = owner.solana_public_key() else {
+        return Ok(None);
+    };
     //b"escrow", user.key().as_ref(), provider.key().as_ref()
     let (escrow_pda, _) = Pubkey::find_program_address(
-        &[b"escrow", owner_key, &provider_pda.to_bytes()],
+        &[b"escrow", &owner_key, &provider_pda.to_bytes()],
         &marketplace::id(),
     );
 
@@ -509,6 +614,40 @@ async fn fetch_owner_escrow_balance(
     Ok(token_balance)
 }
 
+async fn fetch_request_signer_active(
+    rpc_client: &RpcClient,
+    signer: &ApiRequestSigner,
+    owner: &StackOwner,
+    region_pda: &Pubkey,
+) -> Result<bool> {
+    let Some(owner_key) = owner.solana_public_key() else {
+        return Ok(false);
+    };
+    let ApiRequestSigner::Solana(signer_key) = signer;
+
+    //b"request_signer", user.key.as_ref(), signer.key.as_ref(), region.key().as_ref()
+    let (request_signer_pda, _) = Pubkey::find_program_address(
+        &[
+            b"request_signer",
+            &owner_key,
+            signer_key.as_ref(),
+            region_pda.as_ref(),
+        ],
+        &marketplace::id(),
+    );
+
+    let Some(account) = rpc_client
+        .get_account_with_commitment(&request_signer_pda, rpc_client.commitment())
+        .await
+        .context("Failed to fetch request signer from Solana")?
+        .value
+    else {
+        return Ok(false);
+    };
+
+    Ok(read_solana_request_signer_account(account)?.active)
+}
+
 async fn get_token_decimals(rpc_client: &RpcClient) -> Result<u8> {
     let (state_pda, _) = Pubkey::find_program_address(&[b"state"], &marketplace::id());
     let state = rpc_client
@@ -561,6 +700,8 @@ async fn mailbox_body(
         ));
     }
 
+    persist_known_stacks(&state, &config).await;
+
     let mut stop_reply_channel = None;
 
     'main_loop: loop {
@@ -588,13 +729,13 @@ async fn mailbox_body(
                     }
 
                     Some(BlockchainMonitorMessage::GetEscrowBalance(owner, r)) => {
-                        let pubkey = Pubkey::new_from_array(owner.to_inner());
-                        let mut balance = state.solana.escrow_balances.get(&pubkey).copied();
+                        let pubkey = owner.solana_public_key().map(Pubkey::new_from_array);
+                        let mut balance = pubkey.and_then(|p| state.solana.escrow_balances.get(&p).copied());
                         if balance.is_none() {
                             match fetch_owner_escrow_balance(&state.solana.rpc_client, &owner, &state.solana.provider_pda).await {
                                 Ok(x) => balance = x,
                                 Err(f) => {
-                                    warn!("Failed to fetch escrow balance for {pubkey} because {f:?}");
+                                    warn!("Failed to fetch escrow balance for {owner} because {f:?}");
                                 }
                             }
                         }
@@ -619,6 +760,22 @@ async fn mailbox_body(
                         )
                     }
 
+                    // Looked up in its own task, so a slow RPC doesn't hold up
+                    // every other message
+                    Some(BlockchainMonitorMessage::IsRequestSignerActive(signer, owner, r)) => {
+                        let rpc_client = state.solana.rpc_client.clone();
+                        let region_pda = state.solana.region_pda;
+                        tokio::spawn(async move {
+                            match fetch_request_signer_active(&rpc_client, &signer, &owner, &region_pda).await {
+                                Ok(active) => r.reply(active),
+                                Err(f) => {
+                                    warn!("Failed to fetch request signer {signer:?} because {f:?}");
+                                    r.reply(false);
+                                }
+                            }
+                        });
+                    }
+
                     Some(BlockchainMonitorMessage::GetStack(stack_id, r)) => {
                         r.reply(
                             match state.stacks.entry(stack_id) {
@@ -642,12 +799,13 @@ async fn mailbox_body(
             stack = state.solana.pub_sub.stack_subscription.stream.next() => {
                 if let Some(stack) = stack {
                     debug!("Received new stack");
-                    if let Err(f) = on_new_stack_received(
+                    match on_new_stack_received(
                         &mut state,
                         stack,
                         &notification_channel
                     ).await {
-                        warn!("Failed to process new stack: {f}");
+                        Ok(()) => persist_known_stacks(&state, &config).await,
+                        Err(f) => warn!("Failed to process new stack: {f}"),
                     }
                 } else {
                     warn!("Solana notification stream disconnected, attempting to reconnect");
@@ -686,13 +844,16 @@ async fn mailbox_body(
                         // should probably handle subscriptions on a separate task
                         state = reconnect_solana_subscriber(state, &config).await;
                     },
-                    Ok(Some((owner_pubkey, escrow_balance))) =>
-                        on_solana_escrow_updated(
+                    Ok(Some((owner_pubkey, escrow_balance))) => {
+                        if on_solana_escrow_updated(
                             &mut state,
                             &notification_channel,
                             owner_pubkey,
                             escrow_balance
-                        ),
+                        ) {
+                            persist_known_stacks(&state, &config).await;
+                        }
+                    }
                 }
             }
         }
@@ -716,6 +877,123 @@ async fn mailbox_body(
     }
 }
 
+async fn persist_known_stacks(state: &State<'_>, config: &BlockchainMonitorConfig) {
+    if !config.start_degraded_if_unavailable {
+        return;
+    }
+
+    if let Err(f) = known_stacks::persist(
+        &config.known_stacks_path,
+        state.solana.max_giga_instructions_per_call,
+        state.stacks.all_active(),
+    )
+    .await
+    {
+        warn!("Failed to persist known stacks: {f:?}");
+    }
+}
+
+async fn degraded_mailbox_body(
+    config: BlockchainMonitorConfig,
+    region_pda: Pubkey,
+    known_stacks: Vec<StackWithMetadata>,
+    usage_aggregator: Box<dyn UsageAggregator>,
+    mut message_receiver: MessageReceiver<BlockchainMonitorMessage>,
+    notification_channel: NotificationChannel<BlockchainMonitorNotification>,
+) {
+    let known_stacks = known_stacks
+        .into_iter()
+        .map(|s| (s.id(), s))
+        .collect::<HashMap<_, _>>();
+
+    if !known_stacks.is_empty() {
+        notification_channel.send(BlockchainMonitorNotification::StacksAvailable(
+            known_stacks.values().cloned().collect(),
+        ));
+    }
+
+    let mut retry_timer = tokio::time::interval(*config.degraded_mode_retry_interval);
+    // Timers tick once immediately, and we just failed to connect
+    retry_timer.tick().await;
+
+    let mut connection = loop {
+        select! {
+            message = message_receiver.receive() => {
+                match message {
+                    None => {
+                        warn!("All senders were dropped, stopping");
+                        return;
+                    }
+
+                    Some(BlockchainMonitorMessage::Stop(r)) => {
+                        debug!("Stopping while in degraded mode");
+                        r.reply(());
+                        return;
+                    }
+
+                    Some(BlockchainMonitorMessage::GetMetadata(stack_id, r)) => {
+                        r.reply(known_stacks.get(&stack_id).map(|s| s.metadata.clone()));
+                    }
+
+                    // Escrow balances can't be verified without the chain
+                    Some(BlockchainMonitorMessage::GetEscrowBalance(_, r)) => {
+                        r.reply(None);
+                    }
+
+                    // Neither can request signers
+                    Some(BlockchainMonitorMessage::IsRequestSignerActive(_, _, r)) => {
+                        r.reply(false);
+                    }
+
+                    Some(BlockchainMonitorMessage::GetStack(stack_id, r)) => {
+                        r.reply(known_stacks.get(&stack_id).cloned());
+                    }
+
+                    // Usages are kept in the aggregator until we can report them
+                    Some(BlockchainMonitorMessage::Tick(r)) => {
+                        r.reply(());
+                    }
+                }
+            }
+
+            _ = retry_timer.tick() => {
+                debug!("Retrying connection to Solana");
+                match connect(&config, region_pda, usage_aggregator.clone()).await {
+                    Ok(connection) => break connection,
+                    Err(f) => warn!("Solana is still unavailable: {f:?}"),
+                }
+            }
+        }
+    };
+
+    info!("Connected to Solana, leaving degraded mode");
+
+    let removed_stacks = known_stacks
+        .keys()
+        .filter_map(|id| match connection.state.stacks.entry(*id) {
+            stack_collection::Entry::Active(_) => None,
+            stack_collection::Entry::Inactive(_) => Some((*id, StackRemovalMode::Temporary)),
+            stack_collection::Entry::Vacant => Some((*id, StackRemovalMode::Permanent)),
+        })
+        .collect::<Vec<_>>();
+
+    if !removed_stacks.is_empty() {
+        notification_channel.send(BlockchainMonitorNotification::StacksRemoved(removed_stacks));
+    }
+
+    notification_channel.send(BlockchainMonitorNotification::RequestSignersAvailable(
+        connection.request_signers,
+    ));
+
+    mailbox_body(
+        config,
+        connection.state,
+        message_receiver,
+        notification_channel,
+    )
+    .await
+}
+
 async fn select_next_escrow_update(
     subs: &mut HashMap<Pubkey, SolanaSubscription<'_, UiAccount>>,
     token_decimals: u8,
@@ -765,12 +1043,13 @@ async fn next_escrow_update(
     }
 }
 
+// Returns whether the owner's stacks were activated or deactivated
 fn on_solana_escrow_updated(
     state: &mut State,
     notification_channel: &NotificationChannel<BlockchainMonitorNotification>,
     owner_pubkey: Pubkey,
     escrow_balance: u64,
-) {
+) -> bool {
     state
         .solana
         .escrow_balances
@@ -789,6 +1068,7 @@ fn on_solana_escrow_updated(
     match owner_entry {
         OwnerEntry::Vacant(_) => {
             warn!("Received escrow update for unknown developer {owner_pubkey}");
+            false
         }
 
         OwnerEntry::Occupied(occ) => {
@@ -820,8 +1100,10 @@ fn on_solana_escrow_updated(
                             .send(BlockchainMonitorNotification::StacksRemoved(stack_id_modes));
                     }
                 }
+                true
             } else {
                 trace!("Already in desired state");
+                false
             }
         }
     }
@@ -845,7 +1127,7 @@ async fn report_usages<'a>(state: &mut State<'a>, config: &BlockchainMonitorConf
 
     debug!("Will report {} usages", usages.len());
 
-    spawn_blocking(move || {
+    let reported_stacks = spawn_blocking(move || {
         let program_id = marketplace::id();
 
         let payer: Rc<dyn Signer> = Rc::new(signer_private_key);
@@ -888,9 +1170,15 @@ async fn report_usages<'a>(state: &mut State<'a>, config: &BlockchainMonitorConf
         //     }
         // }
 
+        let mut reported_stacks = vec![];
+
         for (stack_id, usages) in usages {
             let solana_stack_id = match stack_id {
                 StackID::SolanaPublicKey(x) => Pubkey::new_from_array(x),
+                StackID::PWRStackID(_) => {
+                    warn!("Can't report usage for {stack_id}, it's not a Solana stack");
+                    continue;
+                }
             };
             let mut usage = marketplace::ServiceUsage::default();
             for (category, amount) in usages {
@@ -903,12 +1191,15 @@ async fn report_usages<'a>(state: &mut State<'a>, config: &BlockchainMonitorConf
                     UsageCategory::DBWrites => usage.db_writes = amount as u64,
                     UsageCategory::GatewayRequests => usage.gateway_requests = amount as u64,
                     UsageCategory::GatewayTraffic => usage.gateway_traffic_bytes = amount as u64,
+                    // Custom metrics have no on-chain rates, the usage aggregator
+                    // keeps them out of reports
+                    UsageCategory::Custom(_) => (),
                 }
             }
 
             trace!("Stack {stack_id} has total usage {usage:?}");
 
-            if let Err(e) = report_usage(
+            match report_usage(
                 &program,
                 commission_pda,
                 payer.clone(),
@@ -919,15 +1210,27 @@ async fn report_usages<'a>(state: &mut State<'a>, config: &BlockchainMonitorConf
                 region_pda,
                 auth_signer_pda,
             ) {
-                // TODO: need some way to keep the usage around for later
-                error!("Failed to report usage for {stack_id} due to: {e:?}");
+                Ok(()) => reported_stacks.push(stack_id),
+                // The usage aggregator keeps unconfirmed usage around, so
+                // it'll be reported again next time
+                Err(e) => error!("Failed to report usage for {stack_id} due to: {e:?}"),
             }
         }
 
-        Ok(())
+        Ok(reported_stacks)
     })
     .await
-    .context("spawn_blocking failed")?
+    .context("spawn_blocking failed")??;
+
+    for stack_id in reported_stacks {
+        // Even if clearing the checkpoint fails, the usage is marked as
+        // reported and the checkpoint is brought up to date later
+        if let Err(e) = state.usage_aggregator.confirm_reported(stack_id).await {
+            warn!("Failed to clear usage checkpoint for {stack_id}: {e:?}");
+        }
+    }
+
+    Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -966,10 +1269,21 @@ fn report_usage(
         &program_id,
     );
 
+    // The program bills usage against the rates the stack is on
+    let (region_rates_pda, _) = Pubkey::find_program_address(
+        &[
+            b"rates",
+            &region_pda.to_bytes(),
+            &stack.rates_version.to_le_bytes(),
+        ],
+        &program_id,
+    );
+
     let accounts = marketplace::accounts::UpdateUsage {
         authorized_signer: auth_signer_pda,
         escrow_account: escrow_pda,
         region: region_pda,
+        region_rates: region_rates_pda,
         signer: payer.pubkey(),
         stack: stack_id,
         state: state_pda,
@@ -1057,9 +1371,10 @@ async fn reconnect_solana_subscriber<'a>(
             &state.solana.pub_sub.get_stacks_config,
             &state.solana.pub_sub.get_request_signers_config,
             &state.solana.provider_pda,
-            state.stacks.owners().map(|o| match o {
-                StackOwner::Solana(pk) => Pubkey::new_from_array(*pk),
-            }),
+            state
+                .stacks
+                .owners()
+                .filter_map(|o| o.solana_public_key().map(Pubkey::new_from_array)),
         )
         .await;
 
@@ -1320,7 +1635,7 @@ fn read_solana_stack_account((pubkey, account): (Pubkey, Account)) -> Result<Sta
             }))
         }
 
-        marketplace::StackState::Deleted => Ok(StackWithState::Deleted {
+        marketplace::StackState::Deleted { .. } => Ok(StackWithState::Deleted {
             stack_id: StackID::SolanaPublicKey(pubkey.to_bytes()),
             owner_id: StackOwner::Solana(stack_account.user.to_bytes()),
         }),
@@ -1362,3 +1677,99 @@ async fn get_region(
 
     Ok(region)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::stack::usage_aggregator::tests::start_in_memory;
+
+    use known_stacks::tests::{temp_path, test_stack};
+
+    fn unreachable_solana_config(
+        known_stacks_path: PathBuf,
+        start_degraded_if_unavailable: bool,
+    ) -> BlockchainMonitorConfig {
+        BlockchainMonitorConfig {
+            // Nothing listens on port 1, so connecting fails right away
+            solana_cluster_rpc_url: ConfigUri("http://127.0.0.1:1/".parse().unwrap()),
+            solana_cluster_pub_sub_url: ConfigUri("ws://127.0.0.1:1/".parse().unwrap()),
+            solana_provider_public_key: Base58PublicKey {
+                public_key: Pubkey::new_unique(),
+            },
+            solana_region_number: 1,
+            solana_usage_signer_private_key: Base58PrivateKey {
+                keypair: Keypair::new(),
+            },
+            solana_usage_report_interval: Duration::from_secs(3600).into(),
+            start_degraded_if_unavailable,
+            degraded_mode_retry_interval: Duration::from_secs(3600).into(),
+            known_stacks_path,
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_known_stacks_when_solana_is_down_at_startup() {
+        let path = temp_path("degraded-start");
+        let stack = test_stack("known");
+        known_stacks::persist(&path, 7, [&stack].into_iter())
+            .await
+            .unwrap();
+
+        let (monitor, mut notifications, region_config) = start(
+            unreachable_solana_config(path.clone(), true),
+            start_in_memory(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(region_config.max_giga_instructions_per_call, Some(7));
+        assert!(matches!(
+            notifications.try_recv(),
+            Ok(BlockchainMonitorNotification::StacksAvailable(stacks))
+                if stacks.len() == 1 && stacks[0].id() == stack.id()
+        ));
+
+        let served = monitor.get_stack(stack.id()).await.unwrap().unwrap();
+        assert_eq!(served.name, "known");
+        // Without the chain there's no way to know the owner's balance
+        assert!(monitor
+            .get_escrow_balance(stack.owner())
+            .await
+            .unwrap()
+            .is_none());
+
+        monitor.stop().await.unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn starts_without_stacks_when_solana_is_down_and_nothing_was_persisted() {
+        let path = temp_path("degraded-start-empty");
+
+        let (monitor, mut notifications, region_config) =
+            start(unreachable_solana_config(path, true), start_in_memory())
+                .await
+                .unwrap();
+
+        assert_eq!(region_config.max_giga_instructions_per_call, None);
+        assert!(notifications.try_recv().is_err());
+        assert!(monitor
+            .get_stack(test_stack("unknown").id())
+            .await
+            .unwrap()
+            .is_none());
+
+        monitor.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_to_start_when_solana_is_down_and_degraded_mode_is_disabled() {
+        let path = temp_path("no-degraded-start");
+
+        let result = start(unreachable_solana_config(path, false), start_in_memory()).await;
+
+        assert!(result.is_err());
+    }
+}
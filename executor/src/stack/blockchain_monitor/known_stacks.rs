@@ -0,0 +1,174 @@
+//! A local snapshot of the stacks we know about, so the node can keep serving
+//! already-deployed stacks if the blockchain is unreachable at startup.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::stack::{SolanaStackMetadata, StackMetadata, StackWithMetadata};
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    max_giga_instructions_per_call: u32,
+    stacks: Vec<PersistedStack>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedStack {
+    account_id: [u8; 32],
+    owner: [u8; 32],
+    name: String,
+    revision: u32,
+    stack_data: Vec<u8>,
+}
+
+pub(super) struct KnownStacks {
+    pub max_giga_instructions_per_call: u32,
+    pub stacks: Vec<StackWithMetadata>,
+}
+
+pub(super) async fn persist<'a>(
+    path: &Path,
+    max_giga_instructions_per_call: u32,
+    stacks: impl Iterator<Item = &'a StackWithMetadata>,
+) -> Result<()> {
+    let stacks = stacks
+        .map(|s| {
+            let StackMetadata::Solana(metadata) = &s.metadata;
+            Ok(PersistedStack {
+                account_id: metadata.account_id.to_bytes(),
+                owner: metadata.owner.to_bytes(),
+                name: s.name.clone(),
+                revision: s.revision,
+                stack_data: s
+                    .stack
+                    .clone()
+                    .into_inner()
+                    .serialize_to_proto()
+                    .context("Failed to serialize stack definition")?
+                    .to_vec(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let snapshot = Snapshot {
+        max_giga_instructions_per_call,
+        stacks,
+    };
+
+    let bytes = serde_json::to_vec(&snapshot).context("Failed to serialize known stacks")?;
+
+    // Write to a temporary file first so a crash can't leave a half-written snapshot behind
+    let temp_path = path.with_extension("tmp");
+    tokio::fs::write(&temp_path, bytes)
+        .await
+        .context("Failed to write known stacks")?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .context("Failed to replace known stacks")?;
+
+    Ok(())
+}
+
+pub(super) async fn load(path: &Path) -> Result<Option<KnownStacks>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let bytes = tokio::fs::read(path)
+        .await
+        .context("Failed to read known stacks")?;
+
+    let snapshot: Snapshot =
+        serde_json::from_slice(&bytes).context("Failed to deserialize known stacks")?;
+
+    let stacks = snapshot
+        .stacks
+        .into_iter()
+        .map(|s| {
+            let stack = mu_stack::Stack::try_deserialize_proto(&s.stack_data)
+                .context("Failed to deserialize stack definition")?
+                .validate()
+                .map_err(|(_, e)| e)
+                .context("Invalid stack definition")?;
+
+            Ok(StackWithMetadata {
+                stack,
+                name: s.name,
+                revision: s.revision,
+                metadata: StackMetadata::Solana(SolanaStackMetadata {
+                    account_id: Pubkey::new_from_array(s.account_id),
+                    owner: Pubkey::new_from_array(s.owner),
+                }),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(KnownStacks {
+        max_giga_instructions_per_call: snapshot.max_giga_instructions_per_call,
+        stacks,
+    }))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    pub(in crate::stack::blockchain_monitor) fn test_stack(name: &str) -> StackWithMetadata {
+        StackWithMetadata {
+            stack: mu_stack::Stack {
+                name: name.into(),
+                version: "1".into(),
+                services: vec![],
+            }
+            .validate()
+            .map_err(|(_, e)| e)
+            .unwrap(),
+            name: name.into(),
+            revision: 3,
+            metadata: StackMetadata::Solana(SolanaStackMetadata {
+                account_id: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+            }),
+        }
+    }
+
+    pub(in crate::stack::blockchain_monitor) fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "mu-known-stacks-{name}-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn persisted_stacks_can_be_loaded() {
+        let path = temp_path("roundtrip");
+        let stacks = vec![test_stack("first"), test_stack("second")];
+
+        persist(&path, 7, stacks.iter()).await.unwrap();
+        let loaded = load(&path).await.unwrap().unwrap();
+
+        assert_eq!(loaded.max_giga_instructions_per_call, 7);
+        assert_eq!(
+            loaded.stacks.iter().map(|s| s.id()).collect::<Vec<_>>(),
+            stacks.iter().map(|s| s.id()).collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.stacks[1].name, "second");
+        assert_eq!(loaded.stacks[1].revision, 3);
+        assert_eq!(loaded.stacks[1].owner(), stacks[1].owner());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_snapshot_loads_as_none() {
+        let path = temp_path("missing");
+        assert!(load(&path).await.unwrap().is_none());
+    }
+}
@@ -1,7 +1,7 @@
 use mu_storage::{DeleteStorage, StorageClient, StorageManager};
 use thiserror::Error;
 
-use mu_db::{DbManager, DeleteTable};
+use mu_db::{DbClient, DbManager, DeleteTable, Key, TableName};
 use mu_gateway::GatewayManager;
 use mu_runtime::{AssemblyDefinition, Runtime};
 use mu_stack::{AssemblyID, Stack, StackID, StackOwner};
@@ -16,6 +16,9 @@ pub enum StackDeploymentError {
     #[error("Failed to fetch binary for function '{0}' due to {1}")]
     CannotFetchFunction(String, anyhow::Error),
 
+    #[error("Secret '{secret}' used by function '{function}' is not defined")]
+    MissingSecret { function: String, secret: String },
+
     #[error("Failed to deploy functions due to: {0}")]
     FailedToDeployFunctions(anyhow::Error),
 
@@ -35,36 +38,212 @@ pub enum StackDeploymentError {
     FailedToConnectToStorage(anyhow::Error),
 }
 
+/// A part of a stack that is deployed as a unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackComponent {
+    Functions,
+    Tables,
+    Storages,
+}
+
+/// Describes how far a failed deployment got. Components deployed before the
+/// failure are rolled back, so the stack is left as close as possible to how
+/// it was before the deployment started.
+#[derive(Error, Debug)]
+#[error(
+    "{error} (deployed: {deployed:?}, failed: {failed:?}, rollback errors: {rollback_errors:?})"
+)]
+pub struct StackDeploymentFailure {
+    pub deployed: Vec<StackComponent>,
+    /// `None` if the deployment failed before any component was deployed.
+    pub failed: Option<StackComponent>,
+    pub error: StackDeploymentError,
+    pub rollback_errors: Vec<(StackComponent, anyhow::Error)>,
+}
+
+impl StackDeploymentFailure {
+    fn before_deploying(error: StackDeploymentError) -> Self {
+        Self {
+            deployed: vec![],
+            failed: None,
+            error,
+            rollback_errors: vec![],
+        }
+    }
+
+    fn after_deploying(
+        deployed: &[DeployedComponent],
+        failed: StackComponent,
+        error: StackDeploymentError,
+        rollback_errors: Vec<(StackComponent, anyhow::Error)>,
+    ) -> Self {
+        Self {
+            // The failed component is recorded too, so it can be rolled back
+            // if it was partially deployed
+            deployed: deployed
+                .iter()
+                .map(DeployedComponent::component)
+                .filter(|c| *c != failed)
+                .collect(),
+            failed: Some(failed),
+            error,
+            rollback_errors,
+        }
+    }
+}
+
+// What a component's deployment added, so it can be removed again.
+// Only additions are undone; replaced function definitions and deleted
+// tables can't be restored. Components are recorded before they're deployed,
+// since a failed deployment may still have added some of its parts.
+enum DeployedComponent {
+    Functions { added: Vec<String> },
+    Tables { created: Vec<TableName> },
+    Storages,
+}
+
+impl DeployedComponent {
+    fn component(&self) -> StackComponent {
+        match self {
+            Self::Functions { .. } => StackComponent::Functions,
+            Self::Tables { .. } => StackComponent::Tables,
+            Self::Storages => StackComponent::Storages,
+        }
+    }
+}
+
 pub(super) async fn deploy(
     id: StackID,
     stack: StackWithMetadata,
     runtime: &dyn Runtime,
     db_manager: &dyn DbManager,
     storage_manager: &dyn StorageManager,
-) -> Result<(), StackDeploymentError> {
+) -> Result<(), StackDeploymentFailure> {
     let stack_owner = stack.metadata.owner();
     let stack = stack.stack;
 
-    let db_client = db_manager
-        .make_client()
-        .await
-        .map_err(StackDeploymentError::FailedToConnectToDatabase)?;
+    let db_client = db_manager.make_client().await.map_err(|e| {
+        StackDeploymentFailure::before_deploying(StackDeploymentError::FailedToConnectToDatabase(e))
+    })?;
 
-    let storage_client = storage_manager
-        .make_client()
-        .map_err(StackDeploymentError::FailedToConnectToStorage)?;
+    let storage_client = storage_manager.make_client().map_err(|e| {
+        StackDeploymentFailure::before_deploying(StackDeploymentError::FailedToConnectToStorage(e))
+    })?;
 
-    // TODO: handle partial deployments
+    let existing_function_names = runtime.get_function_names(id).await.unwrap_or_default();
+
+    let mut deployed = vec![];
+    if let Err((failed, error)) = deploy_components(
+        id,
+        &stack,
+        &stack_owner,
+        &existing_function_names,
+        runtime,
+        &*db_client,
+        &*storage_client,
+        &mut deployed,
+    )
+    .await
+    {
+        let rollback_errors = roll_back(id, &deployed, runtime, &*db_client).await;
+        return Err(StackDeploymentFailure::after_deploying(
+            &deployed,
+            failed,
+            error,
+            rollback_errors,
+        ));
+    }
+
+    // Functions are only removed once everything else succeeded, since
+    // removed functions can't be brought back by a rollback.
+    let functions_to_delete = existing_function_names
+        .into_iter()
+        .filter(|existing| !stack.functions().any(|f| f.name == *existing))
+        .collect::<Vec<_>>();
+    if !functions_to_delete.is_empty() {
+        runtime
+            .remove_functions(id, functions_to_delete)
+            .await
+            .unwrap_or(());
+    }
 
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn deploy_components(
+    id: StackID,
+    stack: &Stack,
+    stack_owner: &StackOwner,
+    existing_function_names: &[String],
+    runtime: &dyn Runtime,
+    db_client: &dyn DbClient,
+    storage_client: &dyn StorageClient,
+    deployed: &mut Vec<DeployedComponent>,
+) -> Result<(), (StackComponent, StackDeploymentError)> {
     // Step 1: Functions
     // Since functions need to be fetched from remote sources, they're more error-prone, so deploy them first
-    let mut function_names = vec![];
+    let (function_defs, added) = prepare_functions(
+        id,
+        stack,
+        stack_owner,
+        existing_function_names,
+        db_client,
+        storage_client,
+    )
+    .await
+    .map_err(|e| (StackComponent::Functions, e))?;
+    deploy_functions(function_defs, added, runtime, deployed)
+        .await
+        .map_err(|e| (StackComponent::Functions, e))?;
+
+    // Step 2: Database tables
+    deploy_tables(id, stack, db_client, deployed)
+        .await
+        .map_err(|e| (StackComponent::Tables, e))?;
+
+    // Step 3: Storage names
+    let storage_delete_pairs = stack
+        .storages()
+        .map(|n| {
+            let name = n.name.as_str();
+            let del = DeleteStorage(matches!(n.delete, Some(true)));
+            (name, del)
+        })
+        .collect();
+
+    storage_client
+        .update_stack_storages(mu_storage::Owner::Stack(id), storage_delete_pairs)
+        .await
+        .map_err(|e| {
+            (
+                StackComponent::Storages,
+                StackDeploymentError::FailedToDeployStorageNames(e),
+            )
+        })?;
+    deployed.push(DeployedComponent::Storages);
+
+    Ok(())
+}
+
+// Also returns the names of functions that didn't exist before
+async fn prepare_functions(
+    id: StackID,
+    stack: &Stack,
+    stack_owner: &StackOwner,
+    existing_function_names: &[String],
+    db_client: &dyn DbClient,
+    storage_client: &dyn StorageClient,
+) -> Result<(Vec<AssemblyDefinition>, Vec<String>), StackDeploymentError> {
+    let mut added = vec![];
     let mut function_defs = vec![];
     for func in stack.functions() {
-        let function_source = download_function(&*storage_client, &stack_owner, &func.binary)
+        let function_source = download_function(storage_client, stack_owner, &func.binary)
             .await
             .map_err(|e| StackDeploymentError::FailedToDeployFunctions(e.into()))?;
 
+        ensure_secrets_exist(db_client, id, &func.name, func.secrets.values()).await?;
+
         function_defs.push(
             AssemblyDefinition::try_new(
                 AssemblyID {
@@ -74,19 +253,42 @@ pub(super) async fn deploy(
                 function_source,
                 func.runtime,
                 func.env.clone(),
+                func.secrets.clone(),
                 func.memory_limit,
             )
-            .map_err(|_| StackDeploymentError::BadAssemblyDefinition)?,
+            .map_err(|_| StackDeploymentError::BadAssemblyDefinition)?
+            .with_compression(func.compression)
+            .with_compiler(func.compiler),
         );
-        function_names.push(&func.name);
+
+        if !existing_function_names.contains(&func.name) {
+            added.push(func.name.clone());
+        }
     }
+
+    Ok((function_defs, added))
+}
+
+async fn deploy_functions(
+    function_defs: Vec<AssemblyDefinition>,
+    added: Vec<String>,
+    runtime: &dyn Runtime,
+    deployed: &mut Vec<DeployedComponent>,
+) -> Result<(), StackDeploymentError> {
+    deployed.push(DeployedComponent::Functions { added });
     runtime
         .add_functions(function_defs)
         .await
-        .map_err(|e| StackDeploymentError::FailedToDeployFunctions(e.into()))?;
+        .map_err(|e| StackDeploymentError::FailedToDeployFunctions(e.into()))
+}
 
-    // Step 2: Database tables
-    let table_delete_paris = stack
+async fn deploy_tables(
+    id: StackID,
+    stack: &Stack,
+    db_client: &dyn DbClient,
+    deployed: &mut Vec<DeployedComponent>,
+) -> Result<(), StackDeploymentError> {
+    let table_delete_pairs: Vec<(TableName, DeleteTable)> = stack
         .key_value_tables()
         .map(|kvt| {
             let table_name = kvt
@@ -99,41 +301,73 @@ pub(super) async fn deploy(
         })
         .collect::<anyhow::Result<_, _>>()?;
 
-    db_client
-        .update_stack_tables(id, table_delete_paris)
+    let existing_tables = db_client
+        .table_list(id, None)
         .await
         .map_err(|e| StackDeploymentError::FailedToDeployTables(e.into()))?;
-
-    // Step 3: Storage names
-    let storage_delete_pairs = stack
-        .storages()
-        .map(|n| {
-            let name = n.name.as_str();
-            let del = DeleteStorage(matches!(n.delete, Some(true)));
-            (name, del)
-        })
+    let created = table_delete_pairs
+        .iter()
+        .filter(|(name, delete)| !**delete && !existing_tables.contains(name))
+        .map(|(name, _)| name.clone())
         .collect();
+    deployed.push(DeployedComponent::Tables { created });
 
-    storage_client
-        .update_stack_storages(mu_storage::Owner::Stack(id), storage_delete_pairs)
+    db_client
+        .update_stack_tables(id, table_delete_pairs)
         .await
-        .map_err(StackDeploymentError::FailedToDeployStorageNames)?;
+        .map_err(|e| StackDeploymentError::FailedToDeployTables(e.into()))
+}
 
-    let existing_function_names = runtime.get_function_names(id).await.unwrap_or_default();
-    let mut functions_to_delete = vec![];
-    for existing in existing_function_names {
-        if !function_names.iter().any(|n| ***n == *existing) {
-            functions_to_delete.push(existing);
+async fn roll_back(
+    id: StackID,
+    deployed: &[DeployedComponent],
+    runtime: &dyn Runtime,
+    db_client: &dyn DbClient,
+) -> Vec<(StackComponent, anyhow::Error)> {
+    let mut errors = vec![];
+
+    for component in deployed.iter().rev() {
+        let result: anyhow::Result<()> = match component {
+            DeployedComponent::Functions { added } => {
+                remove_added_functions(id, added, runtime).await
+            }
+
+            DeployedComponent::Tables { created } if !created.is_empty() => db_client
+                .update_stack_tables(
+                    id,
+                    created
+                        .iter()
+                        .map(|name| (name.clone(), DeleteTable(true)))
+                        .collect(),
+                )
+                .await
+                .map_err(Into::into),
+
+            // Storages are deployed last, so they're never rolled back
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            errors.push((component.component(), e));
         }
     }
-    if !functions_to_delete.is_empty() {
-        runtime
-            .remove_functions(id, functions_to_delete)
-            .await
-            .unwrap_or(());
+
+    errors
+}
+
+async fn remove_added_functions(
+    id: StackID,
+    added: &[String],
+    runtime: &dyn Runtime,
+) -> anyhow::Result<()> {
+    if added.is_empty() {
+        return Ok(());
     }
 
-    Ok(())
+    runtime
+        .remove_functions(id, added.to_vec())
+        .await
+        .map_err(Into::into)
 }
 
 async fn download_function(
@@ -154,6 +388,28 @@ async fn download_function(
     Ok(buf.into())
 }
 
+async fn ensure_secrets_exist(
+    db_client: &dyn DbClient,
+    stack_id: StackID,
+    function_name: &str,
+    secret_names: impl Iterator<Item = &String>,
+) -> Result<(), StackDeploymentError> {
+    for secret_name in secret_names {
+        let value = db_client
+            .get(Key::secret(stack_id, secret_name))
+            .await
+            .map_err(|e| StackDeploymentError::FailedToDeployFunctions(e.into()))?;
+
+        if value.is_none() {
+            return Err(StackDeploymentError::MissingSecret {
+                function: function_name.to_string(),
+                secret: secret_name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
 pub(super) async fn undeploy_stack(
     id: StackID,
     mode: StackRemovalMode,
@@ -190,6 +446,10 @@ async fn delete_user_data_permanently_from_database(
         db_client.clear_table(stack_id, name).await?;
     }
 
+    db_client
+        .clear_table(stack_id, TableName::secrets())
+        .await?;
+
     let table_delete_pairs = table_names
         .into_iter()
         .map(|name| (name, DeleteTable(true)))
@@ -268,3 +528,171 @@ pub(super) async fn undeploy_gateways(
 ) -> anyhow::Result<()> {
     gateway_manager.delete_all_gateways(id).await
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use mu_runtime::{FunctionResponse, ReloadableRuntimeConfig};
+    use mu_stack::{AssemblyRuntime, FunctionID};
+    use musdk_common::{Request, Response};
+
+    use super::*;
+
+    // Adds the functions it's given, but reports a failure anyway
+    #[derive(Clone)]
+    struct FailingRuntime {
+        functions: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Runtime for FailingRuntime {
+        async fn invoke_function<'a>(
+            &self,
+            _function_id: FunctionID,
+            _request: Request<'a>,
+        ) -> mu_runtime::Result<Response<'static>> {
+            unimplemented!()
+        }
+
+        async fn invoke_function_streaming<'a>(
+            &self,
+            _function_id: FunctionID,
+            _request: Request<'a>,
+        ) -> mu_runtime::Result<FunctionResponse> {
+            unimplemented!()
+        }
+
+        async fn stop(&self) -> mu_runtime::Result<()> {
+            Ok(())
+        }
+
+        async fn add_functions(
+            &self,
+            functions: Vec<AssemblyDefinition>,
+        ) -> mu_runtime::Result<()> {
+            let mut existing = self.functions.lock().unwrap();
+            for f in functions {
+                if !existing.contains(&f.id.assembly_name) {
+                    existing.push(f.id.assembly_name);
+                }
+            }
+            Err(mu_runtime::Error::Internal(anyhow::anyhow!(
+                "lost track of the functions"
+            )))
+        }
+
+        async fn remove_functions(
+            &self,
+            _stack_id: StackID,
+            names: Vec<String>,
+        ) -> mu_runtime::Result<()> {
+            self.functions
+                .lock()
+                .unwrap()
+                .retain(|name| !names.contains(name));
+            Ok(())
+        }
+
+        async fn remove_all_functions(&self, _stack_id: StackID) -> mu_runtime::Result<()> {
+            self.functions.lock().unwrap().clear();
+            Ok(())
+        }
+
+        async fn get_function_names(&self, _stack_id: StackID) -> mu_runtime::Result<Vec<String>> {
+            Ok(self.functions.lock().unwrap().clone())
+        }
+
+        async fn reconfigure(&self, _config: ReloadableRuntimeConfig) -> mu_runtime::Result<()> {
+            Ok(())
+        }
+
+        async fn cold_start_count(&self) -> mu_runtime::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    fn definition(stack_id: StackID, name: &str) -> AssemblyDefinition {
+        AssemblyDefinition::try_new(
+            AssemblyID {
+                stack_id,
+                assembly_name: name.to_string(),
+            },
+            bytes::Bytes::new(),
+            AssemblyRuntime::Wasi1_0,
+            [],
+            [],
+            byte_unit::Byte::from_bytes(1024 * 1024),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn functions_added_by_a_failed_deployment_are_rolled_back() {
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let runtime = FailingRuntime {
+            functions: Arc::new(Mutex::new(vec!["existing".to_string()])),
+        };
+
+        let mut deployed = vec![];
+        let result = deploy_functions(
+            vec![
+                definition(stack_id, "existing"),
+                definition(stack_id, "new"),
+            ],
+            vec!["new".to_string()],
+            &runtime,
+            &mut deployed,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(StackDeploymentError::FailedToDeployFunctions(_))
+        ));
+
+        let [DeployedComponent::Functions { added }] = deployed.as_slice() else {
+            panic!("functions should be recorded for the rollback");
+        };
+        remove_added_functions(stack_id, added, &runtime)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec!["existing".to_string()],
+            runtime.get_function_names(stack_id).await.unwrap()
+        );
+    }
+
+    #[test]
+    fn failures_report_the_components_deployed_before_them() {
+        let deployed = vec![
+            DeployedComponent::Functions { added: vec![] },
+            DeployedComponent::Tables { created: vec![] },
+        ];
+
+        let failure = StackDeploymentFailure::after_deploying(
+            &deployed,
+            StackComponent::Tables,
+            StackDeploymentError::FailedToDeployTables(anyhow::anyhow!("no database")),
+            vec![],
+        );
+
+        assert_eq!(vec![StackComponent::Functions], failure.deployed);
+        assert_eq!(Some(StackComponent::Tables), failure.failed);
+        assert!(matches!(
+            failure.error,
+            StackDeploymentError::FailedToDeployTables(_)
+        ));
+    }
+
+    #[test]
+    fn failures_before_deploying_report_no_components() {
+        let failure = StackDeploymentFailure::before_deploying(
+            StackDeploymentError::FailedToConnectToDatabase(anyhow::anyhow!("no database")),
+        );
+
+        assert!(failure.deployed.is_empty());
+        assert_eq!(None, failure.failed);
+    }
+}
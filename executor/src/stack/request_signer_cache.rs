@@ -1,12 +1,50 @@
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use dyn_clonable::clonable;
+use log::info;
 use mailbox_processor::{callback::CallbackMailboxProcessor, ReplyChannel};
 use mu_stack::StackID;
 
-use super::{ApiRequestSigner, StackOwner};
+use super::{blockchain_monitor::BlockchainMonitor, ApiRequestSigner, StackOwner};
+
+// Stacks and signers are spread over this many mailboxes, so validations
+// for unrelated stacks don't queue up behind each other.
+const SHARD_COUNT: usize = 16;
+
+// Stack owners are evicted least-recently-used first once a shard holds this
+// many; they're fetched again from the blockchain monitor when needed.
+const MAX_STACKS_PER_SHARD: usize = 4096;
+
+// Same for request signers, which are checked on the chain again when needed.
+const MAX_SIGNERS_PER_SHARD: usize = 4096;
+
+// Signers the chain said aren't active for an owner are remembered this long,
+// so requests signed by them don't each cost an RPC. Signers activated in the
+// meantime are let through as soon as the monitor reports them.
+const REJECTED_SIGNER_TTL: Duration = Duration::from_secs(30);
+const MAX_REJECTED_SIGNERS_PER_SHARD: usize = 4096;
+
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Nonces of recently seen requests are kept per shard for replay protection,
+// until their request's timestamp goes stale. Requests are turned away once
+// a shard holds this many, rather than forgetting a nonce that could then be
+// replayed.
+const MAX_NONCES_PER_SHARD: usize = 65536;
+
+// Nonces are grouped by their request's timestamp into buckets this many
+// seconds wide, so whole buckets can be dropped once they go stale.
+const NONCE_BUCKET_SECS: i64 = 10;
 
 #[async_trait]
 #[clonable]
@@ -18,79 +56,420 @@ pub trait RequestSignerCache: Clone + Send + Sync {
     async fn signers_available(&self, signers: Vec<(ApiRequestSigner, StackOwner)>) -> Result<()>;
     async fn signers_removed(&self, signers: Vec<ApiRequestSigner>) -> Result<()>;
 
+    /// Checks a signed request's timestamp and nonce, remembering the nonce
+    /// so the same request is reported as `Replayed` if it's seen again.
+    /// Only call this once the signer is known to be allowed to make the
+    /// request, so others can't fill up the nonces kept for replay protection.
+    async fn check_request_freshness(
+        &self,
+        signer: ApiRequestSigner,
+        nonce: u64,
+        timestamp: i64,
+    ) -> Result<RequestFreshness>;
+
+    fn stats(&self) -> CacheStats;
+
     async fn stop(&self);
 }
 
+/// Lookups of stack owners and request signers, and how many of them had to
+/// go to the blockchain monitor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestFreshness {
+    Fresh,
+
+    /// The request's timestamp is further from our clock than allowed.
+    Stale,
+
+    /// A request with the same signer and nonce was already seen.
+    Replayed,
+
+    /// Too many requests were seen within the allowed clock skew to remember
+    /// another nonce.
+    Overloaded,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+struct LruMap<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    by_last_use: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruMap<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            by_last_use: BTreeMap::new(),
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.next_tick();
+        let (value, last_use) = self.entries.get_mut(key)?;
+        self.by_last_use.remove(last_use);
+        self.by_last_use.insert(tick, key.clone());
+        *last_use = tick;
+        Some(value.clone())
+    }
+
+    // Returns whether an entry was evicted to make room
+    fn insert(&mut self, key: K, value: V) -> bool {
+        let tick = self.next_tick();
+        if let Some((_, last_use)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.by_last_use.remove(&last_use);
+        }
+        self.by_last_use.insert(tick, key);
+
+        if self.entries.len() > self.capacity {
+            if let Some(oldest) = self.by_last_use.keys().next().copied() {
+                if let Some(evicted) = self.by_last_use.remove(&oldest) {
+                    self.entries.remove(&evicted);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((_, last_use)) = self.entries.remove(key) {
+            self.by_last_use.remove(&last_use);
+        }
+    }
+}
+
+// Nonces of requests that aren't stale yet, bucketed by the requests' timestamps
+struct NonceWindow {
+    capacity: usize,
+    len: usize,
+    buckets: BTreeMap<i64, HashSet<(ApiRequestSigner, u64)>>,
+}
+
+impl NonceWindow {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            len: 0,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    // Drops the buckets whose every timestamp is older than `oldest_fresh`
+    fn forget_before(&mut self, oldest_fresh: i64) {
+        let first_kept = oldest_fresh.div_euclid(NONCE_BUCKET_SECS);
+        let kept = self.buckets.split_off(&first_kept);
+        for nonces in std::mem::replace(&mut self.buckets, kept).into_values() {
+            self.len -= nonces.len();
+        }
+    }
+
+    fn contains(&self, key: &(ApiRequestSigner, u64)) -> bool {
+        self.buckets.values().any(|nonces| nonces.contains(key))
+    }
+
+    // Returns false if the window is full
+    fn insert(&mut self, key: (ApiRequestSigner, u64), timestamp: i64) -> bool {
+        if self.len >= self.capacity {
+            return false;
+        }
+        self.buckets
+            .entry(timestamp.div_euclid(NONCE_BUCKET_SECS))
+            .or_default()
+            .insert(key);
+        self.len += 1;
+        true
+    }
+}
+
 struct State {
-    stacks: HashMap<StackID, StackOwner>,
-    signers: HashMap<ApiRequestSigner, StackOwner>,
+    stacks: LruMap<StackID, StackOwner>,
+    signers: LruMap<ApiRequestSigner, StackOwner>,
+    // When each rejection stops being trusted
+    rejected_signers: LruMap<(ApiRequestSigner, StackOwner), Instant>,
+    nonces: NonceWindow,
+    max_clock_skew: Duration,
+    counters: Arc<Counters>,
+}
+
+fn check_request_freshness(
+    nonces: &mut NonceWindow,
+    max_clock_skew: Duration,
+    now: i64,
+    signer: ApiRequestSigner,
+    nonce: u64,
+    timestamp: i64,
+) -> RequestFreshness {
+    // Stale requests are rejected without remembering their nonce, since
+    // they'll be rejected for their timestamp anyway
+    if now.abs_diff(timestamp) > max_clock_skew.as_secs() {
+        return RequestFreshness::Stale;
+    }
+
+    nonces.forget_before(now.saturating_sub(max_clock_skew.as_secs() as i64));
+
+    let key = (signer, nonce);
+    if nonces.contains(&key) {
+        return RequestFreshness::Replayed;
+    }
+    if !nonces.insert(key, timestamp) {
+        return RequestFreshness::Overloaded;
+    }
+    RequestFreshness::Fresh
+}
+
+enum SignerLookup {
+    Owner(StackOwner),
+    RecentlyRejected,
+    Unknown,
 }
 
 enum Message {
-    ValidateSigner(StackID, ApiRequestSigner, ReplyChannel<bool>),
+    GetStackOwner(StackID, ReplyChannel<Option<StackOwner>>),
+    // The stack's owner, to look for a recent rejection of the signer
+    LookupSigner(ApiRequestSigner, StackOwner, ReplyChannel<SignerLookup>),
+    SignerRejected(ApiRequestSigner, StackOwner),
 
     StacksAvailable(Vec<(StackID, StackOwner)>),
     StacksRemoved(Vec<StackID>),
     SignersAvailable(Vec<(ApiRequestSigner, StackOwner)>),
     SignersRemoved(Vec<ApiRequestSigner>),
+
+    CheckRequestFreshness {
+        signer: ApiRequestSigner,
+        nonce: u64,
+        timestamp: i64,
+        reply: ReplyChannel<RequestFreshness>,
+    },
 }
 
 #[derive(Clone)]
 struct RequestSignerCacheImpl {
-    mailbox: CallbackMailboxProcessor<Message>,
+    shards: Vec<CallbackMailboxProcessor<Message>>,
+    blockchain_monitor: Box<dyn BlockchainMonitor>,
+    counters: Arc<Counters>,
+}
+
+impl RequestSignerCacheImpl {
+    fn shard_for<T: Hash>(&self, key: &T) -> &CallbackMailboxProcessor<Message> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    async fn post_grouped<K: Hash, T>(
+        &self,
+        items: Vec<T>,
+        key: impl Fn(&T) -> &K,
+        make_message: impl Fn(Vec<T>) -> Message,
+    ) -> Result<()> {
+        let mut grouped: Vec<Vec<T>> = (0..self.shards.len()).map(|_| vec![]).collect();
+        for item in items {
+            let mut hasher = DefaultHasher::new();
+            key(&item).hash(&mut hasher);
+            grouped[hasher.finish() as usize % self.shards.len()].push(item);
+        }
+
+        for (shard, items) in self.shards.iter().zip(grouped) {
+            if !items.is_empty() {
+                shard.post(make_message(items)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_stack_owner(&self, stack_id: StackID) -> Result<Option<StackOwner>> {
+        let shard = self.shard_for(&stack_id);
+
+        if let Some(owner) = shard
+            .post_and_reply(|r| Message::GetStackOwner(stack_id, r))
+            .await?
+        {
+            return Ok(Some(owner));
+        }
+
+        let Some(metadata) = self.blockchain_monitor.get_metadata(stack_id).await? else {
+            return Ok(None);
+        };
+
+        let owner = metadata.owner();
+        shard
+            .post(Message::StacksAvailable(vec![(stack_id, owner)]))
+            .await?;
+        Ok(Some(owner))
+    }
 }
 
 #[async_trait]
 impl RequestSignerCache for RequestSignerCacheImpl {
     async fn validate_signer(&self, stack_id: StackID, signer: ApiRequestSigner) -> Result<bool> {
-        self.mailbox
-            .post_and_reply(|r| Message::ValidateSigner(stack_id, signer, r))
-            .await
-            .map_err(Into::into)
+        let Some(stack_owner) = self.get_stack_owner(stack_id).await? else {
+            return Ok(false);
+        };
+
+        let ApiRequestSigner::Solana(signer_pubkey) = &signer;
+
+        if stack_owner.solana_public_key() == Some(signer_pubkey.to_bytes()) {
+            return Ok(true);
+        }
+
+        let shard = self.shard_for(&signer);
+        match shard
+            .post_and_reply(|r| Message::LookupSigner(signer.clone(), stack_owner, r))
+            .await?
+        {
+            SignerLookup::Owner(signer_owner) => return Ok(signer_owner == stack_owner),
+            SignerLookup::RecentlyRejected => return Ok(false),
+            SignerLookup::Unknown => (),
+        }
+
+        if !self
+            .blockchain_monitor
+            .is_request_signer_active(signer.clone(), stack_owner)
+            .await?
+        {
+            shard
+                .post(Message::SignerRejected(signer, stack_owner))
+                .await?;
+            return Ok(false);
+        }
+
+        shard
+            .post(Message::SignersAvailable(vec![(signer, stack_owner)]))
+            .await?;
+        Ok(true)
     }
 
     async fn stacks_available(&self, stacks: Vec<(StackID, StackOwner)>) -> Result<()> {
-        self.mailbox
-            .post(Message::StacksAvailable(stacks))
+        self.post_grouped(stacks, |s| &s.0, Message::StacksAvailable)
             .await
-            .map_err(Into::into)
     }
 
     async fn stacks_removed(&self, stack_ids: Vec<StackID>) -> Result<()> {
-        self.mailbox
-            .post(Message::StacksRemoved(stack_ids))
+        self.post_grouped(stack_ids, |s| s, Message::StacksRemoved)
             .await
-            .map_err(Into::into)
     }
 
     async fn signers_available(&self, signers: Vec<(ApiRequestSigner, StackOwner)>) -> Result<()> {
-        self.mailbox
-            .post(Message::SignersAvailable(signers))
+        self.post_grouped(signers, |s| &s.0, Message::SignersAvailable)
             .await
-            .map_err(Into::into)
     }
 
     async fn signers_removed(&self, signers: Vec<ApiRequestSigner>) -> Result<()> {
-        self.mailbox
-            .post(Message::SignersRemoved(signers))
+        self.post_grouped(signers, |s| s, Message::SignersRemoved)
             .await
-            .map_err(Into::into)
+    }
+
+    async fn check_request_freshness(
+        &self,
+        signer: ApiRequestSigner,
+        nonce: u64,
+        timestamp: i64,
+    ) -> Result<RequestFreshness> {
+        Ok(self
+            .shard_for(&signer)
+            .post_and_reply(|reply| Message::CheckRequestFreshness {
+                signer,
+                nonce,
+                timestamp,
+                reply,
+            })
+            .await?)
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+        }
     }
 
     async fn stop(&self) {
-        self.mailbox.clone().stop().await;
+        for shard in &self.shards {
+            shard.clone().stop().await;
+        }
     }
 }
 
-pub fn start() -> Box<dyn RequestSignerCache> {
-    let state = State {
-        stacks: Default::default(),
-        signers: Default::default(),
-    };
+pub fn start(
+    blockchain_monitor: Box<dyn BlockchainMonitor>,
+    max_request_clock_skew: Duration,
+) -> Box<dyn RequestSignerCache> {
+    let counters = Arc::new(Counters::default());
+
+    let shards = (0..SHARD_COUNT)
+        .map(|_| {
+            let state = State {
+                stacks: LruMap::new(MAX_STACKS_PER_SHARD),
+                signers: LruMap::new(MAX_SIGNERS_PER_SHARD),
+                rejected_signers: LruMap::new(MAX_REJECTED_SIGNERS_PER_SHARD),
+                nonces: NonceWindow::new(MAX_NONCES_PER_SHARD),
+                max_clock_skew: max_request_clock_skew,
+                counters: counters.clone(),
+            };
 
-    let mailbox = CallbackMailboxProcessor::start(mailbox_step, state, 10000);
+            CallbackMailboxProcessor::start(mailbox_step, state, 10000)
+        })
+        .collect();
 
-    Box::new(RequestSignerCacheImpl { mailbox })
+    tokio::spawn(log_stats(Arc::downgrade(&counters)));
+
+    Box::new(RequestSignerCacheImpl {
+        shards,
+        blockchain_monitor,
+        counters,
+    })
+}
+
+// Stops once the cache is dropped
+async fn log_stats(counters: Weak<Counters>) {
+    let mut interval = tokio::time::interval(STATS_LOG_INTERVAL);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let Some(counters) = counters.upgrade() else {
+            return;
+        };
+        info!(
+            "Request signer cache: {} hits, {} misses, {} evictions",
+            counters.hits.load(Ordering::Relaxed),
+            counters.misses.load(Ordering::Relaxed),
+            counters.evictions.load(Ordering::Relaxed),
+        );
+    }
+}
+
+fn count_lookup(counters: &Counters, is_hit: bool) {
+    let counter = if is_hit {
+        &counters.hits
+    } else {
+        &counters.misses
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
 }
 
 async fn mailbox_step(
@@ -99,13 +478,42 @@ async fn mailbox_step(
     mut state: State,
 ) -> State {
     match message {
-        Message::ValidateSigner(stack_id, signer, rep) => {
-            rep.reply(is_valid_signer(&state, &stack_id, &signer));
+        Message::GetStackOwner(stack_id, rep) => {
+            let owner = state.stacks.get(&stack_id);
+            count_lookup(&state.counters, owner.is_some());
+            rep.reply(owner);
+        }
+
+        Message::LookupSigner(signer, stack_owner, rep) => {
+            let lookup = match state.signers.get(&signer) {
+                Some(owner) => SignerLookup::Owner(owner),
+                None => {
+                    let key = (signer, stack_owner);
+                    match state.rejected_signers.get(&key) {
+                        Some(until) if until > Instant::now() => SignerLookup::RecentlyRejected,
+                        Some(_) => {
+                            state.rejected_signers.remove(&key);
+                            SignerLookup::Unknown
+                        }
+                        None => SignerLookup::Unknown,
+                    }
+                }
+            };
+            count_lookup(&state.counters, !matches!(lookup, SignerLookup::Unknown));
+            rep.reply(lookup);
+        }
+
+        Message::SignerRejected(signer, stack_owner) => {
+            state
+                .rejected_signers
+                .insert((signer, stack_owner), Instant::now() + REJECTED_SIGNER_TTL);
         }
 
         Message::StacksAvailable(stacks) => {
             for (stack_id, owner) in stacks {
-                state.stacks.insert(stack_id, owner);
+                if state.stacks.insert(stack_id, owner) {
+                    state.counters.evictions.fetch_add(1, Ordering::Relaxed);
+                }
             }
         }
 
@@ -117,7 +525,10 @@ async fn mailbox_step(
 
         Message::SignersAvailable(signers) => {
             for (signer, owner) in signers {
-                state.signers.insert(signer, owner);
+                state.rejected_signers.remove(&(signer.clone(), owner));
+                if state.signers.insert(signer, owner) {
+                    state.counters.evictions.fetch_add(1, Ordering::Relaxed);
+                }
             }
         }
 
@@ -126,26 +537,248 @@ async fn mailbox_step(
                 state.signers.remove(&signer);
             }
         }
+
+        Message::CheckRequestFreshness {
+            signer,
+            nonce,
+            timestamp,
+            reply,
+        } => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default();
+            reply.reply(check_request_freshness(
+                &mut state.nonces,
+                state.max_clock_skew,
+                now,
+                signer,
+                nonce,
+                timestamp,
+            ));
+        }
     }
 
     state
 }
 
-fn is_valid_signer(state: &State, stack_id: &StackID, signer: &ApiRequestSigner) -> bool {
-    let Some(stack_owner) = state.stacks.get(stack_id) else {
-        return false;
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::stack::{
+        blockchain_monitor::EscrowBalance, SolanaStackMetadata, StackMetadata, StackWithMetadata,
     };
 
-    let StackOwner::Solana(stack_owner_pubkey) = stack_owner;
-    let ApiRequestSigner::Solana(signer_pubkey) = signer;
+    const SKEW: Duration = Duration::from_secs(300);
+    const NOW: i64 = 1_700_000_000;
 
-    if signer_pubkey.to_bytes() == *stack_owner_pubkey {
-        return true;
+    fn signer() -> ApiRequestSigner {
+        ApiRequestSigner::Solana(Pubkey::new_from_array([1; 32]))
     }
 
-    let Some(signer_owner) = state.signers.get(signer) else {
-        return false;
-    };
+    #[test]
+    fn fresh_requests_are_accepted() {
+        let mut nonces = NonceWindow::new(16);
+
+        for (nonce, timestamp) in [(1, NOW), (2, NOW - 300), (3, NOW + 300)] {
+            assert_eq!(
+                check_request_freshness(&mut nonces, SKEW, NOW, signer(), nonce, timestamp),
+                RequestFreshness::Fresh
+            );
+        }
+    }
+
+    #[test]
+    fn replayed_requests_are_rejected() {
+        let mut nonces = NonceWindow::new(16);
+
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW, signer(), 1, NOW),
+            RequestFreshness::Fresh
+        );
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW + 10, signer(), 1, NOW),
+            RequestFreshness::Replayed
+        );
+
+        // Nonces only need to be unique per signer
+        let other_signer = ApiRequestSigner::Solana(Pubkey::new_from_array([2; 32]));
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW, other_signer, 1, NOW),
+            RequestFreshness::Fresh
+        );
+    }
+
+    #[test]
+    fn nonces_are_kept_until_their_requests_go_stale() {
+        let mut nonces = NonceWindow::new(2);
 
-    *signer_owner == *stack_owner
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW, signer(), 1, NOW - 100),
+            RequestFreshness::Fresh
+        );
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW, signer(), 2, NOW),
+            RequestFreshness::Fresh
+        );
+
+        // A full window turns requests away instead of forgetting a nonce
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW, signer(), 3, NOW),
+            RequestFreshness::Overloaded
+        );
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW + 150, signer(), 1, NOW - 100),
+            RequestFreshness::Replayed
+        );
+
+        // Once the first request goes stale, its nonce makes room for another
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW + 250, signer(), 3, NOW),
+            RequestFreshness::Fresh
+        );
+        assert_eq!(
+            check_request_freshness(&mut nonces, SKEW, NOW + 250, signer(), 2, NOW),
+            RequestFreshness::Replayed
+        );
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut map = LruMap::new(2);
+
+        assert!(!map.insert(1, "a"));
+        assert!(!map.insert(2, "b"));
+        assert_eq!(map.get(&1), Some("a"));
+
+        // 2 was used longest ago
+        assert!(map.insert(3, "c"));
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.get(&1), Some("a"));
+        assert_eq!(map.get(&3), Some("c"));
+
+        // Replacing an entry doesn't evict anything
+        assert!(!map.insert(3, "d"));
+        assert_eq!(map.get(&3), Some("d"));
+    }
+
+    #[derive(Clone)]
+    struct FakeBlockchainMonitor {
+        owner: Pubkey,
+        active_signers: Vec<ApiRequestSigner>,
+        signer_checks: Arc<AtomicU64>,
+    }
+
+    #[async_trait]
+    impl BlockchainMonitor for FakeBlockchainMonitor {
+        async fn get_stack(&self, _: StackID) -> Result<Option<StackWithMetadata>> {
+            unimplemented!()
+        }
+
+        async fn get_metadata(&self, stack_id: StackID) -> Result<Option<StackMetadata>> {
+            let StackID::SolanaPublicKey(account_id) = stack_id else {
+                return Ok(None);
+            };
+            Ok(Some(StackMetadata::Solana(SolanaStackMetadata {
+                account_id: Pubkey::new_from_array(account_id),
+                owner: self.owner,
+            })))
+        }
+
+        async fn get_escrow_balance(&self, _: StackOwner) -> Result<Option<EscrowBalance>> {
+            unimplemented!()
+        }
+
+        async fn is_request_signer_active(
+            &self,
+            signer: ApiRequestSigner,
+            owner: StackOwner,
+        ) -> Result<bool> {
+            self.signer_checks.fetch_add(1, Ordering::Relaxed);
+            Ok(owner == StackOwner::Solana(self.owner.to_bytes())
+                && self.active_signers.contains(&signer))
+        }
+
+        async fn stop(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_signers_are_checked_on_the_chain() {
+        let owner = Pubkey::new_from_array([7; 32]);
+        let signer_checks = Arc::new(AtomicU64::new(0));
+        let cache = start(
+            Box::new(FakeBlockchainMonitor {
+                owner,
+                active_signers: vec![signer()],
+                signer_checks: signer_checks.clone(),
+            }),
+            SKEW,
+        );
+        let stack_id = StackID::SolanaPublicKey([3; 32]);
+
+        // The owner can always sign for their stacks
+        assert!(cache
+            .validate_signer(stack_id, ApiRequestSigner::Solana(owner))
+            .await
+            .unwrap());
+        assert_eq!(signer_checks.load(Ordering::Relaxed), 0);
+
+        assert!(cache.validate_signer(stack_id, signer()).await.unwrap());
+        assert_eq!(signer_checks.load(Ordering::Relaxed), 1);
+
+        // The signer is cached after the first check
+        assert!(cache.validate_signer(stack_id, signer()).await.unwrap());
+        assert_eq!(signer_checks.load(Ordering::Relaxed), 1);
+
+        // Rejections are cached too
+        let stranger = ApiRequestSigner::Solana(Pubkey::new_from_array([9; 32]));
+        assert!(!cache
+            .validate_signer(stack_id, stranger.clone())
+            .await
+            .unwrap());
+        assert!(!cache
+            .validate_signer(stack_id, stranger.clone())
+            .await
+            .unwrap());
+        assert_eq!(signer_checks.load(Ordering::Relaxed), 2);
+
+        // Stack lookups: miss, then hits. Signer lookups: miss, hit, miss, hit.
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 6,
+                misses: 3,
+                evictions: 0,
+            }
+        );
+
+        // Until the signer is activated
+        cache
+            .signers_available(vec![(
+                stranger.clone(),
+                StackOwner::Solana(owner.to_bytes()),
+            )])
+            .await
+            .unwrap();
+        assert!(cache.validate_signer(stack_id, stranger).await.unwrap());
+        assert_eq!(signer_checks.load(Ordering::Relaxed), 2);
+
+        cache.stop().await;
+    }
+
+    #[test]
+    fn stale_requests_are_rejected() {
+        let mut nonces = NonceWindow::new(16);
+
+        for (nonce, timestamp) in [(1, NOW - 301), (2, NOW + 301), (3, 0)] {
+            assert_eq!(
+                check_request_freshness(&mut nonces, SKEW, NOW, signer(), nonce, timestamp),
+                RequestFreshness::Stale
+            );
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet},
+    sync::Arc,
     time::Duration,
 };
 
@@ -20,7 +21,9 @@ use crate::{infrastructure::config::ConfigDuration, network::NodeHash};
 
 use mu_stack::{Stack, StackID};
 
-use super::{blockchain_monitor::StackRemovalMode, StackWithMetadata};
+use super::{
+    blockchain_monitor::StackRemovalMode, deploy::StackDeploymentFailure, StackWithMetadata,
+};
 
 pub enum StackDeploymentStatus {
     DeployedToSelf { deployed_to_others: Vec<NodeHash> },
@@ -46,6 +49,10 @@ pub trait Scheduler: Clone + Send + Sync {
 
     async fn get_deployment_status(&self, stack_id: StackID) -> Result<StackDeploymentStatus>;
 
+    /// Returns every node the stack is currently deployed to, including
+    /// this one, or an empty list if it isn't deployed anywhere.
+    async fn get_stack_placements(&self, stack_id: StackID) -> Result<Vec<NodeHash>>;
+
     // This function currently doesn't fail, but we keep the return type
     // a `Result<()>` so we can later implement custom stopping logic.
     async fn stop(&self) -> Result<()>;
@@ -54,7 +61,7 @@ pub trait Scheduler: Clone + Send + Sync {
 pub enum SchedulerNotification {
     StackDeployed(StackID),
     StackUndeployed(StackID),
-    FailedToDeployStack(StackID),
+    FailedToDeployStack(StackID, Arc<StackDeploymentFailure>),
 }
 
 #[derive(Deserialize)]
@@ -74,6 +81,7 @@ enum SchedulerMessage {
     ReadyToScheduleStacks,
 
     GetDeploymentStatus(StackID, ReplyChannel<StackDeploymentStatus>),
+    GetStackPlacements(StackID, ReplyChannel<Vec<NodeHash>>),
 
     // We could just update the state every time a message arrives,
     // but we need to be able to cache operations for the duration
@@ -147,6 +155,13 @@ impl Scheduler for SchedulerImpl {
             .map_err(Into::into)
     }
 
+    async fn get_stack_placements(&self, stack_id: StackID) -> Result<Vec<NodeHash>> {
+        self.mailbox
+            .post_and_reply(|r| SchedulerMessage::GetStackPlacements(stack_id, r))
+            .await
+            .map_err(Into::into)
+    }
+
     async fn stop(&self) -> Result<()> {
         self.mailbox.clone().stop().await;
         Ok(())
@@ -383,39 +398,10 @@ async fn step(
         }
 
         SchedulerMessage::NodeDeployedStacks(node, stack_ids) => {
-            for stack_id in stack_ids {
-                state.reevaluate_on_next_tick.insert(stack_id);
-                match state.stacks.entry(stack_id) {
-                    Entry::Vacant(vac) => {
-                        let mut deployed_to = HashSet::new();
-                        deployed_to.insert(node);
-                        vac.insert(StackDeployment::Unknown { deployed_to });
-                    }
-
-                    Entry::Occupied(mut occ) => match occ.get_mut() {
-                        StackDeployment::DeployedToOthers { deployed_to, .. }
-                        | StackDeployment::DeployedToSelf {
-                            deployed_to_others: deployed_to,
-                            ..
-                        }
-                        | StackDeployment::DeployedToSelfWithPendingUpdate {
-                            deployed_to_others: deployed_to,
-                            ..
-                        }
-                        | StackDeployment::Unknown { deployed_to, .. } => {
-                            deployed_to.insert(node);
-                        }
-
-                        StackDeployment::HasDeploymentCandidate { stack, .. }
-                        | StackDeployment::Undeployed { stack } => {
-                            let mut deployed_to = HashSet::new();
-                            deployed_to.insert(node);
-                            let stack = stack.take_and_replace_with(useless_stack_with_metadata());
-                            occ.insert(StackDeployment::DeployedToOthers { stack, deployed_to });
-                        }
-                    },
-                }
-            }
+            state
+                .reevaluate_on_next_tick
+                .extend(stack_ids.iter().copied());
+            record_deployed_stacks(&mut state.stacks, node, stack_ids);
         }
 
         SchedulerMessage::NodeUndeployedStacks(node, stack_ids) => {
@@ -572,6 +558,10 @@ async fn step(
             }
         }
 
+        SchedulerMessage::GetStackPlacements(stack_id, r) => {
+            r.reply(get_stack_placements(&state.stacks, state.my_hash, stack_id))
+        }
+
         SchedulerMessage::GetDeploymentStatus(stack_id, r) => r.reply(
             state
                 .stacks
@@ -609,6 +599,71 @@ async fn step(
     state
 }
 
+fn record_deployed_stacks(
+    stacks: &mut HashMap<StackID, StackDeployment>,
+    node: NodeHash,
+    stack_ids: Vec<StackID>,
+) {
+    for stack_id in stack_ids {
+        match stacks.entry(stack_id) {
+            Entry::Vacant(vac) => {
+                let mut deployed_to = HashSet::new();
+                deployed_to.insert(node);
+                vac.insert(StackDeployment::Unknown { deployed_to });
+            }
+
+            Entry::Occupied(mut occ) => match occ.get_mut() {
+                StackDeployment::DeployedToOthers { deployed_to, .. }
+                | StackDeployment::DeployedToSelf {
+                    deployed_to_others: deployed_to,
+                    ..
+                }
+                | StackDeployment::DeployedToSelfWithPendingUpdate {
+                    deployed_to_others: deployed_to,
+                    ..
+                }
+                | StackDeployment::Unknown { deployed_to, .. } => {
+                    deployed_to.insert(node);
+                }
+
+                StackDeployment::HasDeploymentCandidate { stack, .. }
+                | StackDeployment::Undeployed { stack } => {
+                    let mut deployed_to = HashSet::new();
+                    deployed_to.insert(node);
+                    let stack = stack.take_and_replace_with(useless_stack_with_metadata());
+                    occ.insert(StackDeployment::DeployedToOthers { stack, deployed_to });
+                }
+            },
+        }
+    }
+}
+
+fn get_stack_placements(
+    stacks: &HashMap<StackID, StackDeployment>,
+    my_hash: NodeHash,
+    stack_id: StackID,
+) -> Vec<NodeHash> {
+    match stacks.get(&stack_id) {
+        None
+        | Some(StackDeployment::Undeployed { .. })
+        | Some(StackDeployment::HasDeploymentCandidate { .. }) => vec![],
+
+        Some(StackDeployment::Unknown { deployed_to })
+        | Some(StackDeployment::DeployedToOthers { deployed_to, .. }) => {
+            deployed_to.iter().cloned().collect()
+        }
+
+        Some(StackDeployment::DeployedToSelf {
+            deployed_to_others, ..
+        })
+        | Some(StackDeployment::DeployedToSelfWithPendingUpdate {
+            deployed_to_others, ..
+        }) => std::iter::once(my_hash)
+            .chain(deployed_to_others.iter().cloned())
+            .collect(),
+    }
+}
+
 async fn tick(state: &mut SchedulerState) {
     if !state.ready_to_schedule {
         trace!("Not ready to schedule stacks, won't tick");
@@ -850,11 +905,12 @@ async fn deploy_stack(
     runtime: &dyn Runtime,
     database_manager: &dyn DbManager,
     storage_manager: &dyn StorageManager,
-) -> Result<()> {
+) -> std::result::Result<(), Arc<StackDeploymentFailure>> {
     match super::deploy::deploy(id, stack, runtime, database_manager, storage_manager).await {
         Err(f) => {
-            notification_channel.send(SchedulerNotification::FailedToDeployStack(id));
-            Err(f.into())
+            let f = Arc::new(f);
+            notification_channel.send(SchedulerNotification::FailedToDeployStack(id, f.clone()));
+            Err(f)
         }
 
         Ok(()) => {
@@ -894,7 +950,12 @@ fn get_closest_node<'a>(
 
     trace!("Determining closest node to {id}");
 
-    let id_int = to_bigint(id.get_bytes());
+    // IDs shorter than node hashes are hashed first, otherwise they'd only
+    // ever be close to nodes whose hashes happen to have small upper bits
+    let id_int = match id {
+        StackID::SolanaPublicKey(pk) => to_bigint(&pk),
+        StackID::PWRStackID(_) => to_bigint(&stable_hash::crypto_stable_hash(&id.to_bytes())),
+    };
 
     let mut min_distance = id_int.clone() ^ to_bigint(&my_hash.0);
     trace!("Distance to self: {min_distance:?}");
@@ -924,3 +985,79 @@ fn useless_stack_with_metadata() -> StackWithMetadata {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(n: u8) -> NodeHash {
+        NodeHash([n; 32])
+    }
+
+    fn stack_id(n: u8) -> StackID {
+        StackID::SolanaPublicKey([n; 32])
+    }
+
+    fn placements(
+        stacks: &HashMap<StackID, StackDeployment>,
+        my_hash: NodeHash,
+        stack_id: StackID,
+    ) -> Vec<NodeHash> {
+        let mut placements = get_stack_placements(stacks, my_hash, stack_id);
+        placements.sort_by_key(|h| h.0);
+        placements
+    }
+
+    #[test]
+    fn placements_list_every_node_a_stack_is_deployed_to() {
+        let mut stacks = HashMap::new();
+        stacks.insert(
+            stack_id(1),
+            StackDeployment::Undeployed {
+                stack: useless_stack_with_metadata(),
+            },
+        );
+
+        for n in 2..5 {
+            record_deployed_stacks(&mut stacks, node(n), vec![stack_id(1), stack_id(2)]);
+        }
+
+        let expected = vec![node(2), node(3), node(4)];
+        assert_eq!(placements(&stacks, node(1), stack_id(1)), expected);
+        // Stacks we only know of from other nodes' heartbeats
+        assert_eq!(placements(&stacks, node(1), stack_id(2)), expected);
+    }
+
+    #[test]
+    fn placements_include_self_for_local_deployments() {
+        let mut stacks = HashMap::new();
+        stacks.insert(
+            stack_id(1),
+            StackDeployment::DeployedToSelf {
+                stack: useless_stack_with_metadata(),
+                deployed_to_others: HashSet::new(),
+            },
+        );
+
+        record_deployed_stacks(&mut stacks, node(2), vec![stack_id(1)]);
+
+        assert_eq!(
+            placements(&stacks, node(1), stack_id(1)),
+            vec![node(1), node(2)]
+        );
+    }
+
+    #[test]
+    fn undeployed_stacks_have_no_placements() {
+        let mut stacks = HashMap::new();
+        stacks.insert(
+            stack_id(1),
+            StackDeployment::Undeployed {
+                stack: useless_stack_with_metadata(),
+            },
+        );
+
+        assert!(placements(&stacks, node(1), stack_id(1)).is_empty());
+        assert!(placements(&stacks, node(1), stack_id(2)).is_empty());
+    }
+}
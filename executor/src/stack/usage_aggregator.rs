@@ -6,22 +6,46 @@
 // 2. Use concurrent data structures to store usages as they happen, directly. I don't
 //    like this option because it introduces some manner of lock one way or another.
 
-// TODO: store usage data in persistent storage somewhere (Mu DB itself?)
-
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use dyn_clonable::clonable;
+use log::{debug, warn};
 
 use mailbox_processor::callback::CallbackMailboxProcessor;
 use mailbox_processor::ReplyChannel;
+use marketplace::usage;
+use mu_db::{DbClient, DbManager};
 use mu_stack::StackID;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 #[async_trait]
 #[clonable]
 pub trait UsageAggregator: Clone + Sync + Send {
     fn register_usage(&self, stack_id: StackID, usage: Vec<Usage>);
+
+    /// Returns all usage that wasn't reported yet, including usage returned
+    /// by earlier calls whose report wasn't confirmed with
+    /// `confirm_reported`. Returned usage stays checkpointed until confirmed.
     async fn get_and_reset_usages(&self) -> Result<HashMap<StackID, HashMap<UsageCategory, u128>>>;
+
+    /// Marks the usage last returned for this stack as reported, removing
+    /// it from the checkpoint.
+    async fn confirm_reported(&self, stack_id: StackID) -> Result<()>;
+
+    /// Writes the checkpoint for every stack whose usage changed since the
+    /// last call. This also happens periodically and when stopping.
+    ///
+    /// Custom metrics have no on-chain rates, so they're never returned by
+    /// `get_and_reset_usages`. Instead, they're added to per-stack totals
+    /// shared by all nodes here, see `custom_metric_totals`.
+    async fn persist(&self) -> Result<()>;
+
+    /// Adds checkpointed usage to the in-memory totals. This is done once
+    /// on startup, calling it again counts the checkpoint twice.
+    async fn restore(&self) -> Result<()>;
+
     async fn stop(&self);
 }
 
@@ -49,13 +73,15 @@ pub enum Usage {
     GatewayTraffic {
         size_bytes: u64,
     },
+    Custom {
+        name: String,
+        value: u64,
+    },
 }
 
 impl Usage {
-    // Note: this may not be the best place for logic as important as
-    // calculating usage numbers.
-    // Also, we count each strong read/write as two weak reads/writes.
-    // We *may* want to make this more configurable.
+    // Unit conversions are shared with the marketplace program, so the
+    // usage we report is measured in the same units it's billed in.
     pub fn into_category(self) -> (UsageCategory, u128) {
         match self {
             Usage::FunctionMBInstructions {
@@ -63,33 +89,34 @@ impl Usage {
                 memory_megabytes,
             } => (
                 UsageCategory::FunctionMBInstructions,
-                memory_megabytes as u128 * instructions as u128,
+                usage::function_mb_instructions(memory_megabytes, instructions),
             ),
             Usage::DBStorage {
                 size_bytes,
                 seconds,
             } => (
                 UsageCategory::DBStorage,
-                size_bytes as u128 * seconds as u128,
+                usage::db_bytes_seconds(size_bytes, seconds),
             ),
             Usage::DBRead {
                 weak_reads,
                 strong_reads,
             } => (
                 UsageCategory::DBReads,
-                weak_reads as u128 + strong_reads as u128 * 2,
+                usage::db_operations(weak_reads, strong_reads) as u128,
             ),
             Usage::DBWrite {
                 weak_writes,
                 strong_writes,
             } => (
                 UsageCategory::DBWrites,
-                weak_writes as u128 + strong_writes as u128 * 2,
+                usage::db_operations(weak_writes, strong_writes) as u128,
             ),
             Usage::GatewayRequests { count } => (UsageCategory::GatewayRequests, count as u128),
             Usage::GatewayTraffic { size_bytes } => {
                 (UsageCategory::GatewayTraffic, size_bytes as u128)
             }
+            Usage::Custom { name, value } => (UsageCategory::Custom(name), value as u128),
         }
     }
 }
@@ -97,7 +124,7 @@ impl Usage {
 // This is different from `Usage` above in that it doesn't contain any data in the cases.
 // This is useful for storing usages in a hashset, keyed by category. Also, this is perfect
 // for reporting directly to the blockchain.
-#[derive(PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub enum UsageCategory {
     FunctionMBInstructions,
     DBStorage,
@@ -105,11 +132,171 @@ pub enum UsageCategory {
     DBWrites,
     GatewayRequests,
     GatewayTraffic,
+    /// Application-defined metrics reported by functions
+    Custom(String),
+}
+
+// Each function request is already limited in how many custom metrics it can
+// report, but many requests could still report different names.
+const MAX_CUSTOM_CATEGORIES_PER_STACK: usize = 64;
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Checkpoints live outside the stack key space (stack keys never start with
+// a zero byte), next to the membership data. `\0M` is taken by membership.
+const DB_KEY_PREFIX: &[u8] = b"\0U";
+const DB_CUSTOM_METRICS_KEY_PREFIX: &[u8] = b"\0C";
+const DB_SCAN_PAGE_SIZE: u32 = 1024;
+
+type StackUsages = HashMap<UsageCategory, u128>;
+pub type CustomMetrics = HashMap<String, u64>;
+
+fn custom_metrics_key(stack_id: StackID) -> Vec<u8> {
+    let mut key = DB_CUSTOM_METRICS_KEY_PREFIX.to_vec();
+    key.extend(stack_id.to_bytes());
+    key
+}
+
+/// Returns the totals of the custom metrics reported by a stack's functions,
+/// across all nodes. Metrics show up here once the node they were reported
+/// to persists its usage.
+pub async fn custom_metric_totals(db: &dyn DbClient, stack_id: StackID) -> Result<CustomMetrics> {
+    match db
+        .get_raw(custom_metrics_key(stack_id))
+        .await
+        .context("Failed to read custom metrics")?
+    {
+        None => Ok(HashMap::new()),
+        Some(value) => serde_json::from_slice(&value).context("Failed to parse custom metrics"),
+    }
+}
+
+fn add_to_custom_metrics(stack_id: StackID, totals: &mut CustomMetrics, metrics: &CustomMetrics) {
+    for (name, value) in metrics {
+        if !totals.contains_key(name) && totals.len() >= MAX_CUSTOM_CATEGORIES_PER_STACK {
+            warn!("Dropping custom metric '{name}' for stack {stack_id}, too many custom metrics");
+            continue;
+        }
+        let total = totals.entry(name.clone()).or_insert(0);
+        *total = total.saturating_add(*value);
+    }
+}
+
+/// Where usage checkpoints are kept. Each stack's checkpoint holds the
+/// total of its unreported usage.
+#[async_trait]
+#[clonable]
+trait UsageStore: Clone + Send + Sync {
+    async fn load_all(&self) -> Result<HashMap<StackID, StackUsages>>;
+    async fn save(&self, stack_id: StackID, usages: &StackUsages) -> Result<()>;
+    async fn clear(&self, stack_id: StackID) -> Result<()>;
+    async fn add_custom_metrics(&self, stack_id: StackID, metrics: &CustomMetrics) -> Result<()>;
+}
+
+// Nodes keep separate checkpoints, keyed by their address. The address
+// (unlike the node's generation) stays the same across restarts.
+#[derive(Clone)]
+struct DbUsageStore {
+    db: Box<dyn DbClient>,
+    node_prefix: Vec<u8>,
+}
+
+impl DbUsageStore {
+    fn key(&self, stack_id: StackID) -> Vec<u8> {
+        let mut key = self.node_prefix.clone();
+        key.extend(stack_id.to_bytes());
+        key
+    }
+}
+
+#[async_trait]
+impl UsageStore for DbUsageStore {
+    async fn load_all(&self) -> Result<HashMap<StackID, StackUsages>> {
+        let mut upper_bound = self.node_prefix.clone();
+        *upper_bound.last_mut().unwrap() += 1;
+
+        let mut result = HashMap::new();
+        let mut lower_bound = self.node_prefix.clone();
+        loop {
+            let kvs = self
+                .db
+                .scan_raw(lower_bound.clone(), upper_bound.clone(), DB_SCAN_PAGE_SIZE)
+                .await
+                .context("Failed to list usage checkpoints")?;
+            let page_size = kvs.len();
+
+            for (key, value) in kvs {
+                let stack_id = StackID::try_from_bytes(&key[self.node_prefix.len()..])
+                    .context("Invalid stack ID in usage checkpoint key")?;
+                let usages: Vec<(UsageCategory, u128)> =
+                    serde_json::from_slice(&value).context("Failed to parse usage checkpoint")?;
+                result.insert(stack_id, usages.into_iter().collect());
+
+                lower_bound = key;
+                lower_bound.push(0);
+            }
+
+            if page_size < DB_SCAN_PAGE_SIZE as usize {
+                return Ok(result);
+            }
+        }
+    }
+
+    async fn save(&self, stack_id: StackID, usages: &StackUsages) -> Result<()> {
+        let value = serde_json::to_vec(&usages.iter().collect::<Vec<_>>())
+            .context("Failed to serialize usage checkpoint")?;
+        self.db
+            .put_raw(self.key(stack_id), value, false, None)
+            .await
+            .context("Failed to write usage checkpoint")
+    }
+
+    async fn clear(&self, stack_id: StackID) -> Result<()> {
+        self.db
+            .delete_raw(self.key(stack_id), false)
+            .await
+            .context("Failed to clear usage checkpoint")
+    }
+
+    async fn add_custom_metrics(&self, stack_id: StackID, metrics: &CustomMetrics) -> Result<()> {
+        // Other nodes update the same totals, so retry until our update
+        // goes through without overwriting theirs
+        let key = custom_metrics_key(stack_id);
+        let mut previous = self
+            .db
+            .get_raw(key.clone())
+            .await
+            .context("Failed to read custom metrics")?;
+        loop {
+            let mut totals: CustomMetrics = match &previous {
+                None => HashMap::new(),
+                Some(value) => {
+                    serde_json::from_slice(value).context("Failed to parse custom metrics")?
+                }
+            };
+            add_to_custom_metrics(stack_id, &mut totals, metrics);
+            let new_value =
+                serde_json::to_vec(&totals).context("Failed to serialize custom metrics")?;
+
+            let (current, swapped) = self
+                .db
+                .compare_and_swap_raw(key.clone(), previous, new_value)
+                .await
+                .context("Failed to write custom metrics")?;
+            if swapped {
+                return Ok(());
+            }
+            previous = current;
+        }
+    }
 }
 
 enum Message {
     RegisterUsage(StackID, Vec<Usage>),
-    GetAndResetUsages(ReplyChannel<HashMap<StackID, HashMap<UsageCategory, u128>>>),
+    GetAndResetUsages(ReplyChannel<HashMap<StackID, StackUsages>>),
+    ConfirmReported(StackID, ReplyChannel<Result<()>>),
+    Persist(ReplyChannel<Result<()>>),
+    Restore(ReplyChannel<Result<()>>),
 }
 
 #[derive(Clone)]
@@ -131,25 +318,107 @@ impl UsageAggregator for UsageAggregatorImpl {
             .map_err(Into::into)
     }
 
+    async fn confirm_reported(&self, stack_id: StackID) -> Result<()> {
+        self.mailbox
+            .post_and_reply(|r| Message::ConfirmReported(stack_id, r))
+            .await?
+    }
+
+    async fn persist(&self) -> Result<()> {
+        self.mailbox.post_and_reply(Message::Persist).await?
+    }
+
+    async fn restore(&self) -> Result<()> {
+        self.mailbox.post_and_reply(Message::Restore).await?
+    }
+
     async fn stop(&self) {
+        // An error posting the message means we're already stopped
+        if let Ok(Err(e)) = self.mailbox.post_and_reply(Message::Persist).await {
+            warn!("Failed to persist usages before stopping: {e:?}");
+        }
         self.mailbox.clone().stop().await;
     }
 }
 
 struct State {
-    usages: HashMap<StackID, HashMap<UsageCategory, u128>>,
+    store: Box<dyn UsageStore>,
+
+    // Usage registered since the last `get_and_reset_usages`
+    usages: HashMap<StackID, StackUsages>,
+
+    // Usage that was handed out for reporting, but not confirmed yet
+    reporting: HashMap<StackID, StackUsages>,
+
+    // Stacks whose checkpoint is out of date
+    dirty: HashSet<StackID>,
 }
 
-pub fn start() -> Box<dyn UsageAggregator> {
+impl State {
+    fn unreported_usages(&self, stack_id: &StackID) -> StackUsages {
+        let mut result = self.usages.get(stack_id).cloned().unwrap_or_default();
+        if let Some(reporting) = self.reporting.get(stack_id) {
+            merge_usages(&mut result, reporting);
+        }
+        result
+    }
+}
+
+fn merge_usages(into: &mut StackUsages, from: &StackUsages) {
+    for (category, amount) in from {
+        *into.entry(category.clone()).or_insert(0) += amount;
+    }
+}
+
+pub async fn start(
+    db_manager: &dyn DbManager,
+    node_address: &str,
+) -> Result<Box<dyn UsageAggregator>> {
+    let mut node_prefix = DB_KEY_PREFIX.to_vec();
+    node_prefix.extend(node_address.as_bytes());
+    node_prefix.push(0);
+
+    let store = DbUsageStore {
+        db: db_manager.make_client().await?,
+        node_prefix,
+    };
+
+    let aggregator = start_with_store(Box::new(store));
+    aggregator
+        .restore()
+        .await
+        .context("Failed to restore usages")?;
+
+    Ok(aggregator)
+}
+
+fn start_with_store(store: Box<dyn UsageStore>) -> Box<dyn UsageAggregator> {
     let state = State {
+        store,
         usages: HashMap::new(),
+        reporting: HashMap::new(),
+        dirty: HashSet::new(),
     };
 
     let mailbox = CallbackMailboxProcessor::start(mailbox_step, state, 10000);
 
+    tokio::spawn(checkpoint_periodically(mailbox.clone()));
+
     Box::new(UsageAggregatorImpl { mailbox })
 }
 
+async fn checkpoint_periodically(mailbox: CallbackMailboxProcessor<Message>) {
+    loop {
+        tokio::time::sleep(CHECKPOINT_INTERVAL).await;
+
+        match mailbox.post_and_reply(Message::Persist).await {
+            Err(_) => return,
+            Ok(Err(e)) => warn!("Failed to checkpoint usages: {e:?}"),
+            Ok(Ok(())) => (),
+        }
+    }
+}
+
 async fn mailbox_step(
     _mb: CallbackMailboxProcessor<Message>,
     msg: Message,
@@ -157,10 +426,25 @@ async fn mailbox_step(
 ) -> State {
     match msg {
         Message::RegisterUsage(stack_id, usage) => {
+            state.dirty.insert(stack_id);
             let stack_usage_map = state.usages.entry(stack_id).or_insert_with(HashMap::new);
 
             for usage in usage {
                 let (category, amount) = usage.into_category();
+
+                if let UsageCategory::Custom(name) = &category {
+                    let custom_count = stack_usage_map
+                        .keys()
+                        .filter(|c| matches!(c, UsageCategory::Custom(_)))
+                        .count();
+                    if !stack_usage_map.contains_key(&category)
+                        && custom_count >= MAX_CUSTOM_CATEGORIES_PER_STACK
+                    {
+                        warn!("Dropping custom metric '{name}' for stack {stack_id}, too many custom metrics");
+                        continue;
+                    }
+                }
+
                 let usage_amount = stack_usage_map.entry(category).or_insert(0);
                 *usage_amount += amount;
             }
@@ -169,10 +453,283 @@ async fn mailbox_step(
         }
 
         Message::GetAndResetUsages(rep) => {
-            rep.reply(state.usages);
-            State {
-                usages: HashMap::new(),
+            // Usage whose report failed is handed out again, so it's
+            // retried with the next report. Custom metrics stay behind
+            // until they're persisted.
+            for (stack_id, usages) in std::mem::take(&mut state.usages) {
+                let (custom_metrics, usages): (StackUsages, StackUsages) = usages
+                    .into_iter()
+                    .partition(|(category, _)| matches!(category, UsageCategory::Custom(_)));
+                if !custom_metrics.is_empty() {
+                    state.usages.insert(stack_id, custom_metrics);
+                }
+                if usages.is_empty() {
+                    continue;
+                }
+
+                let reporting = state.reporting.entry(stack_id).or_insert_with(HashMap::new);
+                merge_usages(reporting, &usages);
+            }
+            rep.reply(state.reporting.clone());
+            state
+        }
+
+        Message::ConfirmReported(stack_id, rep) => {
+            if state.reporting.remove(&stack_id).is_some() {
+                state.dirty.insert(stack_id);
+            }
+            rep.reply(persist_stack(&mut state, stack_id).await);
+            state
+        }
+
+        Message::Persist(rep) => {
+            let mut result = flush_custom_metrics(&mut state).await;
+            for stack_id in state.dirty.clone() {
+                if let Err(e) = persist_stack(&mut state, stack_id).await {
+                    result = Err(e);
+                }
+            }
+            rep.reply(result);
+            state
+        }
+
+        Message::Restore(rep) => {
+            let result = state.store.load_all().await.map(|checkpoint| {
+                debug!("Restored usages for {} stacks", checkpoint.len());
+                for (stack_id, usages) in checkpoint {
+                    let stack_usages = state.usages.entry(stack_id).or_insert_with(HashMap::new);
+                    merge_usages(stack_usages, &usages);
+                    state.dirty.insert(stack_id);
+                }
+            });
+            rep.reply(result);
+            state
+        }
+    }
+}
+
+// Moves custom metrics into the shared totals. If the node stops before the
+// checkpoint is updated, the metrics are counted again after a restart, the
+// same as usage whose report isn't confirmed.
+async fn flush_custom_metrics(state: &mut State) -> Result<()> {
+    let mut result = Ok(());
+    for (stack_id, usages) in state.usages.iter_mut() {
+        let metrics = usages
+            .iter()
+            .filter_map(|(category, amount)| match category {
+                UsageCategory::Custom(name) => {
+                    Some((name.clone(), u64::try_from(*amount).unwrap_or(u64::MAX)))
+                }
+                _ => None,
+            })
+            .collect::<CustomMetrics>();
+        if metrics.is_empty() {
+            continue;
+        }
+
+        match state.store.add_custom_metrics(*stack_id, &metrics).await {
+            Ok(()) => {
+                usages.retain(|category, _| !matches!(category, UsageCategory::Custom(_)));
+                state.dirty.insert(*stack_id);
             }
+            Err(e) => result = Err(e),
         }
     }
+    state.usages.retain(|_, usages| !usages.is_empty());
+    result
+}
+
+async fn persist_stack(state: &mut State, stack_id: StackID) -> Result<()> {
+    if !state.dirty.contains(&stack_id) {
+        return Ok(());
+    }
+
+    let usages = state.unreported_usages(&stack_id);
+    if usages.is_empty() {
+        state.store.clear(stack_id).await?;
+    } else {
+        state.store.save(stack_id, &usages).await?;
+    }
+
+    state.dirty.remove(&stack_id);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const STACK_ID: StackID = StackID::SolanaPublicKey([1; 32]);
+
+    pub(crate) fn start_in_memory() -> Box<dyn UsageAggregator> {
+        start_with_store(Box::new(InMemoryStore::default()))
+    }
+
+    /// Adds to the custom metric totals kept in `db`, as persisting usage
+    /// does.
+    pub(crate) async fn add_custom_metrics_to_db(
+        db: Box<dyn DbClient>,
+        stack_id: StackID,
+        metrics: &CustomMetrics,
+    ) -> Result<()> {
+        let store = DbUsageStore {
+            db,
+            node_prefix: DB_KEY_PREFIX.to_vec(),
+        };
+        store.add_custom_metrics(stack_id, metrics).await
+    }
+
+    #[derive(Clone, Default)]
+    struct InMemoryStore(
+        Arc<Mutex<HashMap<StackID, StackUsages>>>,
+        Arc<Mutex<HashMap<StackID, CustomMetrics>>>,
+    );
+
+    #[async_trait]
+    impl UsageStore for InMemoryStore {
+        async fn load_all(&self) -> Result<HashMap<StackID, StackUsages>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        async fn save(&self, stack_id: StackID, usages: &StackUsages) -> Result<()> {
+            self.0.lock().unwrap().insert(stack_id, usages.clone());
+            Ok(())
+        }
+
+        async fn clear(&self, stack_id: StackID) -> Result<()> {
+            self.0.lock().unwrap().remove(&stack_id);
+            Ok(())
+        }
+
+        async fn add_custom_metrics(
+            &self,
+            stack_id: StackID,
+            metrics: &CustomMetrics,
+        ) -> Result<()> {
+            let mut custom_metrics = self.1.lock().unwrap();
+            let totals = custom_metrics.entry(stack_id).or_default();
+            add_to_custom_metrics(stack_id, totals, metrics);
+            Ok(())
+        }
+    }
+
+    async fn register_usages(aggregator: &dyn UsageAggregator) {
+        aggregator.register_usage(
+            STACK_ID,
+            vec![
+                Usage::GatewayRequests { count: 3 },
+                Usage::GatewayTraffic { size_bytes: 1024 },
+            ],
+        );
+        aggregator.register_usage(STACK_ID, vec![Usage::GatewayRequests { count: 2 }]);
+
+        // Usages are registered in the background
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    async fn restart(
+        aggregator: Box<dyn UsageAggregator>,
+        store: &InMemoryStore,
+    ) -> Box<dyn UsageAggregator> {
+        aggregator.stop().await;
+        let aggregator = start_with_store(Box::new(store.clone()));
+        aggregator.restore().await.unwrap();
+        aggregator
+    }
+
+    fn expected_usages() -> HashMap<StackID, StackUsages> {
+        [(
+            STACK_ID,
+            [
+                (UsageCategory::GatewayRequests, 5),
+                (UsageCategory::GatewayTraffic, 1024),
+            ]
+            .into(),
+        )]
+        .into()
+    }
+
+    #[tokio::test]
+    async fn usages_survive_restart() {
+        let store = InMemoryStore::default();
+        let aggregator = start_with_store(Box::new(store.clone()));
+        register_usages(aggregator.as_ref()).await;
+
+        let aggregator = restart(aggregator, &store).await;
+
+        assert_eq!(
+            aggregator.get_and_reset_usages().await.unwrap(),
+            expected_usages()
+        );
+        aggregator.stop().await;
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_usages_are_kept_until_confirmed() {
+        let store = InMemoryStore::default();
+        let aggregator = start_with_store(Box::new(store.clone()));
+        register_usages(aggregator.as_ref()).await;
+
+        assert_eq!(
+            aggregator.get_and_reset_usages().await.unwrap(),
+            expected_usages()
+        );
+        let aggregator = restart(aggregator, &store).await;
+        assert_eq!(
+            aggregator.get_and_reset_usages().await.unwrap(),
+            expected_usages()
+        );
+
+        aggregator.confirm_reported(STACK_ID).await.unwrap();
+        assert!(store.0.lock().unwrap().is_empty());
+
+        let aggregator = restart(aggregator, &store).await;
+        assert!(aggregator.get_and_reset_usages().await.unwrap().is_empty());
+        aggregator.stop().await;
+    }
+
+    #[tokio::test]
+    async fn custom_metrics_are_added_to_totals_instead_of_reported() {
+        let store = InMemoryStore::default();
+        let aggregator = start_with_store(Box::new(store.clone()));
+        let premium_calls = || Usage::Custom {
+            name: "premium_calls".into(),
+            value: 2,
+        };
+        aggregator.register_usage(STACK_ID, vec![premium_calls()]);
+        register_usages(aggregator.as_ref()).await;
+
+        assert_eq!(
+            aggregator.get_and_reset_usages().await.unwrap(),
+            expected_usages()
+        );
+        aggregator.confirm_reported(STACK_ID).await.unwrap();
+
+        // Confirming the report doesn't lose the metric, it's still
+        // checkpointed until it's added to the totals
+        assert_eq!(
+            store.0.lock().unwrap().get(&STACK_ID),
+            Some(&[(UsageCategory::Custom("premium_calls".into()), 2)].into())
+        );
+        assert!(store.1.lock().unwrap().is_empty());
+
+        let aggregator = restart(aggregator, &store).await;
+        assert_eq!(
+            store.1.lock().unwrap().get(&STACK_ID),
+            Some(&[("premium_calls".to_string(), 2)].into())
+        );
+
+        aggregator.register_usage(STACK_ID, vec![premium_calls()]);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        aggregator.persist().await.unwrap();
+
+        assert_eq!(
+            store.1.lock().unwrap().get(&STACK_ID),
+            Some(&[("premium_calls".to_string(), 4)].into())
+        );
+        assert!(store.0.lock().unwrap().is_empty());
+        assert!(aggregator.get_and_reset_usages().await.unwrap().is_empty());
+        aggregator.stop().await;
+    }
 }
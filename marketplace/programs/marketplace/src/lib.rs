@@ -1,9 +1,11 @@
 // We have to use anchor's error type, we have no control over it
 #![allow(clippy::result_large_err)]
 
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, Discriminator};
 use anchor_spl::token::{Mint, Token, TokenAccount, Transfer};
 
+pub mod usage;
+
 declare_id!("H7eDBkyrr5jLcjmNmyTbDo45sS6U6MvHx6fFGiF9AL8r");
 
 fn calc_usage(rates: &ServiceRates, usage: &ServiceUsage) -> u64 {
@@ -27,6 +29,15 @@ pub enum Error {
 
     #[msg("Cannot operate on a deleted stack")]
     CannotOperateOnDeletedStack,
+
+    #[msg("Provider deposit must be greater than zero")]
+    ProviderDepositMustBeNonZero,
+
+    #[msg("Usage updates are no longer accepted for this stack")]
+    DeletedStackGracePeriodExpired,
+
+    #[msg("Account already has the current layout")]
+    AccountAlreadyMigrated,
 }
 
 #[program]
@@ -37,11 +48,16 @@ pub mod marketplace {
         ctx: Context<Initialize>,
         commission_rate_micros: u32,
         provider_deposit: u64,
+        deleted_stack_grace_period_secs: u32,
     ) -> Result<()> {
         if commission_rate_micros > 1_000_000 {
             return Err(Error::CommissionRateOutOfBounds.into());
         }
 
+        if provider_deposit == 0 {
+            return Err(Error::ProviderDepositMustBeNonZero.into());
+        }
+
         ctx.accounts.state.set_inner(MuState {
             authority: ctx.accounts.authority.key(),
             mint: ctx.accounts.mint.key(),
@@ -49,6 +65,7 @@ pub mod marketplace {
             commission_token: ctx.accounts.commission_token.key(),
             commission_rate_micros,
             provider_deposit,
+            deleted_stack_grace_period_secs,
             bump: *ctx.bumps.get("state").unwrap(),
         });
 
@@ -59,11 +76,56 @@ pub mod marketplace {
         ctx: Context<UpdateProviderDeposit>,
         provider_deposit: u64,
     ) -> Result<()> {
+        if provider_deposit == 0 {
+            return Err(Error::ProviderDepositMustBeNonZero.into());
+        }
+
         ctx.accounts.state.provider_deposit = provider_deposit;
 
         Ok(())
     }
 
+    pub fn update_deleted_stack_grace_period(
+        ctx: Context<UpdateDeletedStackGracePeriod>,
+        deleted_stack_grace_period_secs: u32,
+    ) -> Result<()> {
+        ctx.accounts.state.deleted_stack_grace_period_secs = deleted_stack_grace_period_secs;
+
+        Ok(())
+    }
+
+    /// Brings the state created before deleted stacks had a grace period up
+    /// to the current layout.
+    pub fn migrate_state(
+        ctx: Context<MigrateState>,
+        deleted_stack_grace_period_secs: u32,
+    ) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        let old = read_legacy_account::<LegacyMuState>(&state_info, MuState::discriminator())?;
+        require_keys_eq!(
+            old.authority,
+            ctx.accounts.authority.key(),
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+
+        let state = MuState {
+            authority: old.authority,
+            mint: old.mint,
+            deposit_token: old.deposit_token,
+            commission_token: old.commission_token,
+            commission_rate_micros: old.commission_rate_micros,
+            provider_deposit: old.provider_deposit,
+            deleted_stack_grace_period_secs,
+            bump: old.bump,
+        };
+        rewrite_account(
+            &state_info,
+            &state,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
     pub fn create_provider_authorizer(ctx: Context<CreateProviderAuthorizer>) -> Result<()> {
         ctx.accounts
             .provider_authorizer
@@ -113,11 +175,19 @@ pub mod marketplace {
             return Err(Error::ProviderNotAuthorized.into());
         }
 
+        ctx.accounts.region_rates.set_inner(RegionRates {
+            region: ctx.accounts.region.key(),
+            version: 1,
+            rates: rates.clone(),
+            bump: *ctx.bumps.get("region_rates").unwrap(),
+        });
+
         ctx.accounts.region.set_inner(ProviderRegion {
             name,
             base_url,
             region_num,
             rates,
+            rates_version: 1,
             min_escrow_balance,
             max_giga_instructions_per_call,
             provider: ctx.accounts.provider.key(),
@@ -127,6 +197,102 @@ pub mod marketplace {
         Ok(())
     }
 
+    pub fn update_region_rates(ctx: Context<UpdateRegionRates>, rates: ServiceRates) -> Result<()> {
+        let region = &mut ctx.accounts.region;
+        region.rates_version += 1;
+        region.rates = rates.clone();
+
+        ctx.accounts.region_rates.set_inner(RegionRates {
+            region: region.key(),
+            version: region.rates_version,
+            rates,
+            bump: *ctx.bumps.get("region_rates").unwrap(),
+        });
+
+        Ok(())
+    }
+
+    /// Brings a region created before rates were versioned up to the current
+    /// layout. Its rates become version 1.
+    pub fn migrate_region(ctx: Context<MigrateRegion>) -> Result<()> {
+        let region_info = ctx.accounts.region.to_account_info();
+        let old = read_legacy_account::<LegacyProviderRegion>(
+            &region_info,
+            ProviderRegion::discriminator(),
+        )?;
+        require_keys_eq!(
+            old.provider,
+            ctx.accounts.provider.key(),
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+
+        ctx.accounts.region_rates.set_inner(RegionRates {
+            region: region_info.key(),
+            version: 1,
+            rates: old.rates.clone(),
+            bump: *ctx.bumps.get("region_rates").unwrap(),
+        });
+
+        let region = ProviderRegion {
+            provider: old.provider,
+            region_num: old.region_num,
+            rates: old.rates,
+            rates_version: 1,
+            min_escrow_balance: old.min_escrow_balance,
+            max_giga_instructions_per_call: old.max_giga_instructions_per_call,
+            bump: old.bump,
+            name: old.name,
+            base_url: old.base_url,
+        };
+        rewrite_account(
+            &region_info,
+            &region,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
+    /// Brings a stack created before its rates version and deletion time were
+    /// tracked up to the current layout. Its usage is billed at the region's
+    /// current rates from then on.
+    pub fn migrate_stack(ctx: Context<MigrateStack>) -> Result<()> {
+        let stack_info = ctx.accounts.stack.to_account_info();
+        let old = read_legacy_account::<LegacyStack>(&stack_info, Stack::discriminator())?;
+        require_keys_eq!(
+            old.region,
+            ctx.accounts.region.key(),
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+
+        let stack = Stack {
+            user: old.user,
+            region: old.region,
+            seed: old.seed,
+            bump: old.bump,
+            state: match old.state {
+                LegacyStackState::Active {
+                    revision,
+                    name,
+                    stack_data,
+                } => StackState::Active {
+                    revision,
+                    name,
+                    stack_data,
+                },
+                // When the stack was deleted is unknown, so its grace period
+                // is considered over
+                LegacyStackState::Deleted => StackState::Deleted { deleted_at: 0 },
+            },
+            rates_version: ctx.accounts.region.rates_version,
+        };
+        rewrite_account(
+            &stack_info,
+            &stack,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
     pub fn create_stack(
         ctx: Context<CreateStack>,
         stack_seed: u64,
@@ -147,6 +313,7 @@ pub mod marketplace {
                 name,
                 stack_data,
             },
+            rates_version: ctx.accounts.region.rates_version,
         });
 
         Ok(())
@@ -159,7 +326,7 @@ pub mod marketplace {
         name: String,
     ) -> Result<()> {
         match ctx.accounts.stack.state {
-            StackState::Deleted => Err(Error::CannotOperateOnDeletedStack.into()),
+            StackState::Deleted { .. } => Err(Error::CannotOperateOnDeletedStack.into()),
             StackState::Active {
                 ref mut revision,
                 name: ref mut name_ref,
@@ -175,11 +342,13 @@ pub mod marketplace {
     }
 
     pub fn delete_stack(ctx: Context<DeleteStack>, _stack_seed: u64) -> Result<()> {
-        if let StackState::Deleted = ctx.accounts.stack.state {
+        if let StackState::Deleted { .. } = ctx.accounts.stack.state {
             return Err(Error::CannotOperateOnDeletedStack.into());
         }
 
-        ctx.accounts.stack.state = StackState::Deleted;
+        ctx.accounts.stack.state = StackState::Deleted {
+            deleted_at: Clock::get()?.unix_timestamp,
+        };
         Ok(())
     }
 
@@ -253,14 +422,35 @@ pub mod marketplace {
         _escrow_bump: u8,
         usage: ServiceUsage,
     ) -> Result<()> {
-        // TODO: only allow usage updates up to a certain point in time after the stack was deleted
-        let usage_tokens = calc_usage(&ctx.accounts.region.rates, &usage);
-        let commission_tokens =
-            usage_tokens * ctx.accounts.state.commission_rate_micros as u64 / 1_000_000;
-        let provider_tokens = usage_tokens - commission_tokens;
+        if let StackState::Deleted { deleted_at } = ctx.accounts.stack.state {
+            // The upper 64 bits of the seed are the time the usage was
+            // generated at, in microseconds. Since signers pick the seed,
+            // the current time is checked as well, so usage can't be
+            // backdated to keep charging a deleted stack.
+            let seed_time = ((update_seed >> 64) / 1_000_000) as i64;
+            let update_time = seed_time.max(Clock::get()?.unix_timestamp);
+            let deadline = deleted_at
+                .saturating_add(ctx.accounts.state.deleted_stack_grace_period_secs as i64);
+            if update_time > deadline {
+                return Err(Error::DeletedStackGracePeriodExpired.into());
+            }
+        }
+
+        // Usage is billed against the rates the stack was on when the usage
+        // was generated, not the region's current rates, so rate changes are
+        // never applied retroactively. The stack moves to the current rates
+        // once its usage up to now is billed.
+        let rates_version = ctx.accounts.stack.rates_version;
+        ctx.accounts.stack.rates_version = ctx.accounts.region.rates_version;
+
+        let (provider_tokens, commission_tokens) = usage::estimate_usage_cost(
+            &ctx.accounts.region_rates.rates,
+            &usage,
+            ctx.accounts.state.commission_rate_micros,
+        );
         msg!(
             "Calculated price: {}, commission: {}, provider's share: {}",
-            usage_tokens,
+            provider_tokens + commission_tokens,
             commission_tokens,
             provider_tokens,
         );
@@ -297,6 +487,7 @@ pub mod marketplace {
             region: ctx.accounts.region.key(),
             stack: ctx.accounts.stack.key(),
             seed: update_seed,
+            rates_version,
             usage,
         });
 
@@ -313,6 +504,8 @@ pub struct MuState {
     pub commission_token: Pubkey,
     pub commission_rate_micros: u32,
     pub provider_deposit: u64,
+    /// How long usage can still be reported for a stack after it's deleted.
+    pub deleted_stack_grace_period_secs: u32,
     pub bump: u8,
 }
 
@@ -322,7 +515,7 @@ pub struct Initialize<'info> {
         init,
         payer = authority,
         seeds = [b"state"],
-        space = 8 + 32 + 32 + 32 + 32 + 4 + 8 + 1,
+        space = 8 + 32 + 32 + 32 + 32 + 4 + 8 + 4 + 1,
         bump
     )]
     state: Account<'info, MuState>,
@@ -361,7 +554,22 @@ pub struct UpdateProviderDeposit<'info> {
     #[account(
         mut,
         seeds = [b"state"],
-        bump = state.bump
+        bump = state.bump,
+        has_one = authority
+    )]
+    state: Account<'info, MuState>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDeletedStackGracePeriod<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = authority
     )]
     state: Account<'info, MuState>,
 
@@ -498,6 +706,8 @@ pub struct ProviderRegion {
     pub provider: Pubkey,
     pub region_num: u32,
     pub rates: ServiceRates,
+    // Incremented each time the rates change, see `RegionRates`
+    pub rates_version: u32,
     pub min_escrow_balance: u64,
     pub max_giga_instructions_per_call: u32,
     pub bump: u8,
@@ -513,13 +723,56 @@ pub struct CreateRegion<'info> {
 
     #[account(
         init,
-        space = 8 + 32 + 4 + (8 + 8 + 8 + 8 + 8 + 8) + 8 + 4 + 1 + 4 + name.as_bytes().len() + 4 + base_url.as_bytes().len(),
+        space = 8 + 32 + 4 + (8 + 8 + 8 + 8 + 8 + 8) + 4 + 8 + 4 + 1 + 4 + name.as_bytes().len() + 4 + base_url.as_bytes().len(),
         payer = owner,
         seeds = [b"region", owner.key().as_ref(), region_num.to_le_bytes().as_ref()],
         bump
     )]
     pub region: Account<'info, ProviderRegion>,
 
+    #[account(
+        init,
+        space = 8 + 32 + 4 + (8 + 8 + 8 + 8 + 8 + 8) + 1,
+        payer = owner,
+        seeds = [b"rates", region.key().as_ref(), 1u32.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub region_rates: Account<'info, RegionRates>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// A snapshot of a region's rates at a specific version. These are never
+// modified after creation, so usage can always be billed against the rates
+// that were active when it was generated.
+#[account]
+pub struct RegionRates {
+    pub region: Pubkey,
+    pub version: u32,
+    pub rates: ServiceRates,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRegionRates<'info> {
+    #[account(has_one = owner)]
+    pub provider: Account<'info, Provider>,
+
+    #[account(mut, has_one = provider)]
+    pub region: Account<'info, ProviderRegion>,
+
+    #[account(
+        init,
+        space = 8 + 32 + 4 + (8 + 8 + 8 + 8 + 8 + 8) + 1,
+        payer = owner,
+        seeds = [b"rates", region.key().as_ref(), (region.rates_version + 1).to_le_bytes().as_ref()],
+        bump
+    )]
+    pub region_rates: Account<'info, RegionRates>,
+
     #[account(mut)]
     pub owner: Signer<'info>,
 
@@ -588,6 +841,9 @@ pub struct Stack {
     pub seed: u64,
     pub bump: u8,
     pub state: StackState,
+    // The version of the region's rates this stack's usage is billed at, see
+    // `update_usage`
+    pub rates_version: u32,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -599,7 +855,9 @@ pub enum StackState {
         name: String,
         stack_data: Vec<u8>,
     },
-    Deleted,
+    Deleted {
+        deleted_at: i64,
+    },
 }
 
 #[repr(u8)]
@@ -619,7 +877,7 @@ pub struct CreateStack<'info> {
     #[account(
         init,
         payer = user,
-        space = 8 + 32 + 32 + 8 + 1 + 1 + 4 + 4 + name.len() + 4 + stack_data.len(),
+        space = 8 + 32 + 32 + 8 + 1 + 1 + 4 + 4 + name.len() + 4 + stack_data.len() + 4,
         seeds = [b"stack", user.key().as_ref(), region.key().as_ref(), stack_seed.to_le_bytes().as_ref()],
         bump
     )]
@@ -637,7 +895,7 @@ pub struct UpdateStack<'info> {
 
     #[account(
         mut,
-        realloc = 8 + 32 + 32 + 8 + 1 + 1 + 4 + 4 + name.len() + 4 + stack_data.len(),
+        realloc = 8 + 32 + 32 + 8 + 1 + 1 + 4 + 4 + name.len() + 4 + stack_data.len() + 4,
         realloc::payer = user,
         realloc::zero = false,
         seeds = [b"stack", user.key().as_ref(), region.key().as_ref(), stack_seed.to_le_bytes().as_ref()],
@@ -658,7 +916,7 @@ pub struct DeleteStack<'info> {
 
     #[account(
         mut,
-        realloc = 8 + 32 + 32 + 8 + 1 + 1,
+        realloc = 8 + 32 + 32 + 8 + 1 + 1 + 8 + 4,
         realloc::payer = user,
         realloc::zero = false,
         seeds = [b"stack", user.key().as_ref(), region.key().as_ref(), stack_seed.to_le_bytes().as_ref()],
@@ -798,6 +1056,7 @@ pub struct UsageUpdate {
     pub region: Pubkey,
     pub stack: Pubkey,
     pub seed: u128,
+    pub rates_version: u32,
     pub usage: ServiceUsage,
 }
 
@@ -825,6 +1084,13 @@ pub struct UpdateUsage<'info> {
 
     pub region: Account<'info, ProviderRegion>,
 
+    #[account(
+        has_one = region,
+        seeds = [b"rates", region.key().as_ref(), stack.rates_version.to_le_bytes().as_ref()],
+        bump = region_rates.bump
+    )]
+    pub region_rates: Account<'info, RegionRates>,
+
     /// CHECK: The token account for the provider
     #[account(mut)]
     token_account: AccountInfo<'info>,
@@ -832,7 +1098,7 @@ pub struct UpdateUsage<'info> {
     #[account(
         init,
         payer = signer,
-        space = 8 + 32 + 32 + 16 + (16 + 16 + 8 + 8 + 8 + 8),
+        space = 8 + 32 + 32 + 16 + 4 + (16 + 16 + 8 + 8 + 8 + 8),
         seeds = [
             b"update",
             stack.key().as_ref(),
@@ -851,7 +1117,7 @@ pub struct UpdateUsage<'info> {
     escrow_account: AccountInfo<'info>,
 
     // TODO: add the developer's account as input, calculate and validate the stack's PDA
-    #[account(has_one = region)]
+    #[account(mut, has_one = region)]
     stack: Account<'info, Stack>,
 
     #[account(mut)]
@@ -860,3 +1126,154 @@ pub struct UpdateUsage<'info> {
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
 }
+
+#[derive(Accounts)]
+pub struct MigrateRegion<'info> {
+    #[account(has_one = owner)]
+    pub provider: Account<'info, Provider>,
+
+    /// CHECK: Loaded with its old layout by `migrate_region`, which checks
+    /// the owner, discriminator and provider
+    #[account(mut)]
+    pub region: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        space = 8 + 32 + 4 + (8 + 8 + 8 + 8 + 8 + 8) + 1,
+        payer = owner,
+        seeds = [b"rates", region.key().as_ref(), 1u32.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub region_rates: Account<'info, RegionRates>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStack<'info> {
+    pub region: Account<'info, ProviderRegion>,
+
+    /// CHECK: Loaded with its old layout by `migrate_stack`, which checks
+    /// the owner, discriminator and region
+    #[account(mut)]
+    pub stack: UncheckedAccount<'info>,
+
+    // Anyone can migrate a stack, since the result doesn't depend on who does
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateState<'info> {
+    /// CHECK: Loaded with its old layout by `migrate_state`, which checks
+    /// the owner, discriminator and authority
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Account layouts from before `rates_version` and the deleted stack grace
+// period were added, used to migrate accounts created with them
+#[derive(AnchorDeserialize)]
+struct LegacyMuState {
+    authority: Pubkey,
+    mint: Pubkey,
+    deposit_token: Pubkey,
+    commission_token: Pubkey,
+    commission_rate_micros: u32,
+    provider_deposit: u64,
+    bump: u8,
+}
+
+#[derive(AnchorDeserialize)]
+struct LegacyProviderRegion {
+    provider: Pubkey,
+    region_num: u32,
+    rates: ServiceRates,
+    min_escrow_balance: u64,
+    max_giga_instructions_per_call: u32,
+    bump: u8,
+    name: String,
+    base_url: String,
+}
+
+#[derive(AnchorDeserialize)]
+struct LegacyStack {
+    user: Pubkey,
+    region: Pubkey,
+    seed: u64,
+    bump: u8,
+    state: LegacyStackState,
+}
+
+#[derive(AnchorDeserialize)]
+enum LegacyStackState {
+    Active {
+        revision: u32,
+        name: String,
+        stack_data: Vec<u8>,
+    },
+    Deleted,
+}
+
+// Accounts are always allocated with exactly the space their data needs, so
+// an account in the current layout leaves bytes behind when read with an
+// older, shorter one.
+fn read_legacy_account<T: AnchorDeserialize>(
+    account: &AccountInfo,
+    discriminator: [u8; 8],
+) -> Result<T> {
+    require_keys_eq!(
+        *account.owner,
+        crate::ID,
+        anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram
+    );
+
+    let data = account.try_borrow_data()?;
+    if data.len() < 8 || data[..8] != discriminator {
+        return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+
+    let mut rest = &data[8..];
+    match T::deserialize(&mut rest) {
+        Ok(old) if rest.is_empty() => Ok(old),
+        _ => Err(Error::AccountAlreadyMigrated.into()),
+    }
+}
+
+// Replaces an account's data, resizing it and topping up its rent from `payer`
+fn rewrite_account<'info, T: AccountSerialize>(
+    account: &AccountInfo<'info>,
+    value: &T,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let mut data = vec![];
+    value.try_serialize(&mut data)?;
+
+    let rent = Rent::get()?.minimum_balance(data.len());
+    let missing_rent = rent.saturating_sub(account.lamports());
+    if missing_rent > 0 {
+        let transfer = anchor_lang::system_program::Transfer {
+            from: payer.clone(),
+            to: account.clone(),
+        };
+        anchor_lang::system_program::transfer(
+            CpiContext::new(system_program.clone(), transfer),
+            missing_rent,
+        )?;
+    }
+
+    account.realloc(data.len(), false)?;
+    account.try_borrow_mut_data()?.copy_from_slice(&data);
+    Ok(())
+}
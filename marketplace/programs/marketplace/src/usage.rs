@@ -0,0 +1,90 @@
+// Conversions from the raw counters collected by executor nodes into the
+// units of `ServiceUsage`. Nodes use these when aggregating usage, so what
+// gets metered is exactly what `calc_usage` bills for. `estimate_usage_cost`
+// lets off-chain tooling price a `ServiceUsage` the same way `update_usage` does.
+
+use crate::{ServiceRates, ServiceUsage};
+
+// Strong (linearizable) database operations are billed as this many weak ones.
+pub const STRONG_DB_OPERATION_WEIGHT: u64 = 2;
+
+pub fn function_mb_instructions(memory_megabytes: u64, instructions: u64) -> u128 {
+    memory_megabytes as u128 * instructions as u128
+}
+
+pub fn db_bytes_seconds(size_bytes: u64, seconds: u64) -> u128 {
+    size_bytes as u128 * seconds as u128
+}
+
+pub fn db_operations(weak: u64, strong: u64) -> u64 {
+    weak.saturating_add(strong.saturating_mul(STRONG_DB_OPERATION_WEIGHT))
+}
+
+// Returns `(provider_tokens, commission_tokens)`, exactly as `update_usage`
+// would transfer them on-chain for the same rates and commission.
+pub fn estimate_usage_cost(
+    rates: &ServiceRates,
+    usage: &ServiceUsage,
+    commission_rate_micros: u32,
+) -> (u64, u64) {
+    let usage_tokens = crate::calc_usage(rates, usage);
+    let commission_tokens = usage_tokens * commission_rate_micros as u64 / 1_000_000;
+    (usage_tokens - commission_tokens, commission_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strong_db_operations_count_double() {
+        assert_eq!(db_operations(3, 0), 3);
+        assert_eq!(db_operations(3, 2), 7);
+        assert_eq!(db_operations(u64::MAX, 1), u64::MAX);
+    }
+
+    #[test]
+    fn function_usage_is_measured_in_mb_instructions() {
+        assert_eq!(function_mb_instructions(128, 1_000_000), 128_000_000);
+        assert_eq!(
+            function_mb_instructions(u64::MAX, u64::MAX),
+            u64::MAX as u128 * u64::MAX as u128
+        );
+    }
+
+    #[test]
+    fn db_storage_is_measured_in_byte_seconds() {
+        assert_eq!(db_bytes_seconds(1024, 60), 61_440);
+        assert_eq!(
+            db_bytes_seconds(u64::MAX, u64::MAX),
+            u64::MAX as u128 * u64::MAX as u128
+        );
+    }
+
+    #[test]
+    fn usage_cost_is_split_between_provider_and_commission() {
+        let rates = ServiceRates {
+            function_mb_tera_instructions: 1000,
+            db_gigabyte_months: 300,
+            million_db_reads: 500,
+            million_db_writes: 2000,
+            million_gateway_requests: 100,
+            gigabytes_gateway_traffic: 200,
+        };
+        let usage = ServiceUsage {
+            function_mb_instructions: 3_000_000_000_000,
+            // 2 GiB for 30 days
+            db_bytes_seconds: db_bytes_seconds(2 * 1024 * 1024 * 1024, 60 * 60 * 24 * 30),
+            db_reads: 2_000_000,
+            db_writes: 1_000_000,
+            gateway_requests: 10_000_000,
+            gateway_traffic_bytes: 5 * 1024 * 1024 * 1024,
+        };
+
+        // 3000 + 600 + 1000 + 2000 + 1000 + 1000 = 8600 tokens, 5% of which
+        // is commission
+        assert_eq!(estimate_usage_cost(&rates, &usage, 50_000), (8170, 430));
+        assert_eq!(estimate_usage_cost(&rates, &usage, 0), (8600, 0));
+        assert_eq!(estimate_usage_cost(&rates, &usage, 1_000_000), (0, 8600));
+    }
+}
@@ -262,10 +262,10 @@ export const getMu = (anchorProvider: anchor.AnchorProvider, mint: Keypair): MuP
 
 }
 
-export const initializeMu = async (anchorProvider: anchor.AnchorProvider, mint: Keypair, commission_rate_micros: number, providerDeposit: BN): Promise<MuProgram> => {
+export const initializeMu = async (anchorProvider: anchor.AnchorProvider, mint: Keypair, commission_rate_micros: number, providerDeposit: BN, deletedStackGracePeriodSecs: number): Promise<MuProgram> => {
     let mu = getMu(anchorProvider, mint);
 
-    await mu.program.methods.initialize(commission_rate_micros, providerDeposit).accounts({
+    await mu.program.methods.initialize(commission_rate_micros, providerDeposit, deletedStackGracePeriodSecs).accounts({
         authority: anchorProvider.wallet.publicKey,
         state: mu.statePda,
         depositToken: mu.depositPda,
@@ -276,11 +276,18 @@ export const initializeMu = async (anchorProvider: anchor.AnchorProvider, mint:
     return mu;
 }
 
-export const updateProviderDeposit = async (mu: MuProgram, providerDeposit: BN) => {
+export const updateProviderDeposit = async (mu: MuProgram, providerDeposit: BN, authority?: Keypair) => {
     await mu.program.methods.updateProviderDeposit(providerDeposit).accounts({
         state: mu.statePda,
-        authority: mu.anchorProvider.wallet.publicKey,
-    }).rpc();
+        authority: authority ? authority.publicKey : mu.anchorProvider.wallet.publicKey,
+    }).signers(authority ? [authority] : []).rpc();
+}
+
+export const updateDeletedStackGracePeriod = async (mu: MuProgram, deletedStackGracePeriodSecs: number, authority?: Keypair) => {
+    await mu.program.methods.updateDeletedStackGracePeriod(deletedStackGracePeriodSecs).accounts({
+        state: mu.statePda,
+        authority: authority ? authority.publicKey : mu.anchorProvider.wallet.publicKey,
+    }).signers(authority ? [authority] : []).rpc();
 }
 
 export interface MuProviderInfo {
@@ -418,12 +425,45 @@ export const createRegion = async (
         .accounts({
             provider: provider.pda,
             region: region.pda,
+            regionRates: getRegionRates(mu, region, 1),
             owner: provider.wallet.publicKey
         }).signers([provider.wallet]).rpc();
 
     return region;
 }
 
+export const getRegionRates = (mu: MuProgram, region: MuRegionInfo, ratesVersion: number): PublicKey => {
+    return publicKey.findProgramAddressSync(
+        [
+            anchor.utils.bytes.utf8.encode("rates"),
+            region.pda.toBytes(),
+            new anchor.BN(ratesVersion, 10, "le").toBuffer("le", 4)
+        ],
+        mu.program.programId
+    )[0];
+}
+
+export const updateRegionRates = async (
+    mu: MuProgram,
+    provider: MuProviderInfo,
+    region: MuRegionInfo,
+    rates: ServiceRates
+): Promise<number> => {
+    const regionAccount = await mu.program.account.providerRegion.fetch(region.pda);
+    const newVersion = regionAccount.ratesVersion + 1;
+
+    await mu.program.methods
+        .updateRegionRates(rates)
+        .accounts({
+            provider: provider.pda,
+            region: region.pda,
+            regionRates: getRegionRates(mu, region, newVersion),
+            owner: provider.wallet.publicKey
+        }).signers([provider.wallet]).rpc();
+
+    return newVersion;
+}
+
 export interface MuAuthorizedSignerInfo {
     wallet: Keypair,
     pda: PublicKey
@@ -642,7 +682,7 @@ export const updateStackUsage = async (
     authSigner: MuAuthorizedSignerInfo,
     provider: MuProviderInfo,
     escrow: MuEscrowAccountInfo,
-    updateSeed: number, // This is actually a 128-bit number, but a float64 is enough for testing purposes
+    updateSeed: number | BN, // This is actually a 128-bit number, but a float64 is enough for most tests
     usage: ServiceUsage
 ): Promise<MuStackUsageUpdateInfo> => {
     // Providers won't have access to the escrow account in the same way we
@@ -658,6 +698,9 @@ export const updateStackUsage = async (
         mu.program.programId
     );
 
+    // Usage is billed against the rates the stack is on
+    const stackAccount = await mu.program.account.stack.fetch(stack.pda);
+
     await mu.program.methods.updateUsage(
         new anchor.BN(updateSeed),
         escrow.bump,
@@ -667,6 +710,7 @@ export const updateStackUsage = async (
         commissionToken: mu.commissionPda,
         authorizedSigner: authSigner.pda,
         region: region.pda,
+        regionRates: getRegionRates(mu, region, stackAccount.ratesVersion),
         tokenAccount: provider.tokenAccount,
         usageUpdate: pda,
         escrowAccount: escrow.pda,
@@ -756,3 +800,31 @@ export const withdrawEscrowBalance = async (
         .signers([userWallet])
         .rpc();
 }
+
+export const migrateState = async (mu: MuProgram, deletedStackGracePeriodSecs: number) => {
+    await mu.program.methods.migrateState(deletedStackGracePeriodSecs).accounts({
+        state: mu.statePda,
+        authority: mu.anchorProvider.wallet.publicKey,
+    }).rpc();
+}
+
+export const migrateRegion = async (mu: MuProgram, owner: Keypair, provider: PublicKey, region: MuRegionInfo) => {
+    await mu.program.methods
+        .migrateRegion()
+        .accounts({
+            provider,
+            region: region.pda,
+            regionRates: getRegionRates(mu, region, 1),
+            owner: owner.publicKey
+        }).signers([owner]).rpc();
+}
+
+export const migrateStack = async (mu: MuProgram, stack: MuStackInfo, region: MuRegionInfo) => {
+    await mu.program.methods
+        .migrateStack()
+        .accounts({
+            region: region.pda,
+            stack: stack.pda,
+            payer: mu.anchorProvider.wallet.publicKey,
+        }).rpc();
+}
@@ -10,7 +10,7 @@ util.asyncMain(async () => {
     let mint = await createMint(anchorProvider, true);
 
     console.log("Initializing Mu smart contract");
-    let mu = await initializeMu(anchorProvider, mint, 100_000, new BN(200_000000));
+    let mu = await initializeMu(anchorProvider, mint, 100_000, new BN(200_000000), 7 * 24 * 60 * 60);
 
     console.log("Creating provider authorizer");
     await createProviderAuthorizer(mu, "1");
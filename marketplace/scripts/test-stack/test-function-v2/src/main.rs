@@ -2,11 +2,12 @@ use musdk::*;
 
 #[mu_functions]
 mod functions {
-    use musdk::{LogLevel, MuContext, PathParams};
+    use musdk::{BodyText, LogLevel, MuContext, PathParams};
 
     #[mu_function]
-    fn greet_user_v2<'a>(ctx: &'a mut MuContext, data: &'a [u8]) -> Vec<u8> {
-        let s = String::from_utf8_lossy(data);
+    fn greet_user_v2<'a>(ctx: &'a mut MuContext, body: BodyText<'a>) -> Vec<u8> {
+        let s = body.into_inner();
+        let data = s.as_bytes();
 
         let mut count = ctx
             .db()
@@ -1,4 +1,4 @@
-import { Keypair } from '@solana/web3.js'
+import { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram, Transaction } from '@solana/web3.js'
 import * as spl from '@solana/spl-token';
 import chai, { expect } from 'chai';
 import chaiAsPromised from 'chai-as-promised';
@@ -15,7 +15,11 @@ import {
     deactivateApiRequestSigner,
     deleteStack,
     deployStack,
+    getRegionRates,
     initializeMu,
+    migrateRegion,
+    migrateStack,
+    migrateState,
     mintToAccount,
     MuApiRequestSigner,
     MuAuthorizedSignerInfo,
@@ -25,10 +29,13 @@ import {
     MuProviderInfo,
     MuRegionInfo,
     MuStackInfo,
+    readOrCreateKeypair,
     readOrCreateUserWallet,
     readOrCreateWallet,
     ServiceRates, ServiceUsage,
     updateProviderDeposit,
+    updateDeletedStackGracePeriod,
+    updateRegionRates,
     updateStack,
     updateStackUsage,
     withdrawEscrowBalance
@@ -52,10 +59,12 @@ describe("marketplace", () => {
 
     let usagePrice = 1029044n;
 
+    const deletedStackGracePeriodSecs = 60 * 60;
+
     it("Initializes", async () => {
         let provider = AnchorProvider.env();
         let mint = await createMint(provider);
-        mu = await initializeMu(provider, mint, 100_000, new BN(100_000000));
+        mu = await initializeMu(provider, mint, 100_000, new BN(100_000000), deletedStackGracePeriodSecs);
     });
 
     it("Creates a provider authorizer", async () => {
@@ -82,6 +91,50 @@ describe("marketplace", () => {
         expect(depositAccount.amount).to.equals(300_000000n);
     });
 
+    it("Fails to update provider deposit without the authority's signature", async () => {
+        const otherWallet = (await readOrCreateWallet(mu)).keypair;
+
+        try {
+            await updateProviderDeposit(mu, new BN(1), otherWallet);
+            throw new Error("Deposit update succeeded when it should have failed");
+        } catch (e) {
+            let anchorError = e as AnchorError;
+            expect(anchorError.message).to.contains("A has one constraint was violated");
+        }
+    });
+
+    it("Fails to set provider deposit to zero", async () => {
+        try {
+            await updateProviderDeposit(mu, new BN(0));
+            throw new Error("Deposit update succeeded when it should have failed");
+        } catch (e) {
+            let anchorError = e as AnchorError;
+            expect(anchorError.message).to.contains("Provider deposit must be greater than zero");
+        }
+    });
+
+    it("Updates the deleted stack grace period", async () => {
+        await updateDeletedStackGracePeriod(mu, deletedStackGracePeriodSecs * 2);
+        let state = await mu.program.account.muState.fetch(mu.statePda);
+        expect(state.deletedStackGracePeriodSecs).to.equals(deletedStackGracePeriodSecs * 2);
+
+        await updateDeletedStackGracePeriod(mu, deletedStackGracePeriodSecs);
+        state = await mu.program.account.muState.fetch(mu.statePda);
+        expect(state.deletedStackGracePeriodSecs).to.equals(deletedStackGracePeriodSecs);
+    });
+
+    it("Fails to update the deleted stack grace period without the authority's signature", async () => {
+        const otherWallet = (await readOrCreateWallet(mu)).keypair;
+
+        try {
+            await updateDeletedStackGracePeriod(mu, 0, otherWallet);
+            throw new Error("Grace period update succeeded when it should have failed");
+        } catch (e) {
+            let anchorError = e as AnchorError;
+            expect(anchorError.message).to.contains("A has one constraint was violated");
+        }
+    });
+
     it("Fails to create region when provider isn't authorized", async () => {
         const rates: ServiceRates = {
             functionMbTeraInstructions: new BN(1000),
@@ -270,6 +323,57 @@ describe("marketplace", () => {
         expect(escrowAccount.amount).to.equals(10_000_000n - 2n * usagePrice);
     })
 
+    it("Bills usage against the rates it was generated under", async () => {
+        const rates: ServiceRates = {
+            functionMbTeraInstructions: new BN(2000),
+            dbGigabyteMonths: new BN(2000),
+            gigabytesGatewayTraffic: new BN(200),
+            millionDbReads: new BN(1000),
+            millionDbWrites: new BN(4000),
+            millionGatewayRequests: new BN(100)
+        };
+
+        const ratesVersion = await updateRegionRates(mu, provider, region, rates);
+        expect(ratesVersion).to.equals(2);
+
+        const usage: ServiceUsage = {
+            functionMbInstructions: new BN(2000 * 1000000000 * 512),
+            dbBytesSeconds: new BN(500 * 1024 * 1024 * 60 * 60 * 24 * 15),
+            dbReads: new BN(5000000),
+            dbWrites: new BN(800000),
+            gatewayRequests: new BN(4000000),
+            gatewayTrafficBytes: new BN(5 * 1024 * 1024 * 1024)
+        };
+
+        // Usage generated before the rate change is still billed with the old rates
+        const update = await updateStackUsage(mu, region, stack, authSigner, provider, escrow, 102, usage);
+
+        const escrowAccount = await spl.getAccount(
+            mu.anchorProvider.connection,
+            escrow.pda
+        );
+        expect(escrowAccount.amount).to.equals(10_000_000n - 3n * usagePrice);
+
+        const usageUpdate = await mu.program.account.usageUpdate.fetch(update.pda);
+        expect(usageUpdate.ratesVersion).to.equals(1);
+
+        // Usage reported from now on is billed with the new rates
+        const stackAccount = await mu.program.account.stack.fetch(stack.pda);
+        expect(stackAccount.ratesVersion).to.equals(2);
+    })
+
+    it("Accepts usage on a deleted stack within the grace period", async () => {
+        const seed = usageSeedAt(Date.now() + (deletedStackGracePeriodSecs / 2) * 1000, 103);
+        await updateStackUsage(mu, region, stack, authSigner, provider, escrow, seed, emptyUsage());
+    })
+
+    it("Rejects usage on a deleted stack past the grace period", async () => {
+        const seed = usageSeedAt(Date.now() + deletedStackGracePeriodSecs * 2 * 1000, 104);
+        await expect(
+            updateStackUsage(mu, region, stack, authSigner, provider, escrow, seed, emptyUsage())
+        ).to.be.rejectedWith("DeletedStackGracePeriodExpired");
+    })
+
     it("Creates an API request signer", async () => {
         let signer = Keypair.generate(); // Note: can, but doesn't need to be an account on the blockchain
         requestSigner = await createApiRequestSigner(mu, userWallet, signer, region);
@@ -301,8 +405,74 @@ describe("marketplace", () => {
         expect(tempWalletAccount.amount).to.equals(10_000_000_000n + 5_000_000n); // 10G initial balance
 
         let escrowAccount = await spl.getAccount(mu.anchorProvider.connection, escrow.pda);
-        expect(escrowAccount.amount).to.equals(5_000_000n - 2n * usagePrice); // 10M initial balance - 5M withdrawn - usage price
+        expect(escrowAccount.amount).to.equals(5_000_000n - 3n * usagePrice); // 10M initial balance - 5M withdrawn - usage price
     })
+
+    // The legacy accounts are loaded into the validator, see tests/fixtures
+    const legacyProvider = new PublicKey("FAu3FWywS1qjhk2zgffKS77kCE3XA7cWL2vqXRBrd5Kf");
+    const legacyRegion: MuRegionInfo = { pda: new PublicKey("5rVdL2Jeb7ceuX656iRhU2TbfwBy7kWzwLTKR7Fhz3ZQ") };
+    const legacyStack: MuStackInfo = { pda: new PublicKey("8UmToxMz4FpEppe6dg4F4SwegWGUNczntCrsejUhkw5B") };
+
+    it("Fails to migrate a stack whose region isn't migrated yet", async () => {
+        try {
+            await migrateStack(mu, legacyStack, legacyRegion);
+            throw new Error("Stack migration succeeded when it should have failed");
+        } catch (e) {
+            let anchorError = e as AnchorError;
+            expect(anchorError.message).to.contains("Failed to deserialize the account");
+        }
+    });
+
+    it("Migrates a region created before rates were versioned", async () => {
+        const owner = readOrCreateKeypair("cli_provider");
+        let fundTx = new Transaction();
+        fundTx.add(SystemProgram.transfer({
+            fromPubkey: mu.anchorProvider.wallet.publicKey,
+            toPubkey: owner.publicKey,
+            lamports: LAMPORTS_PER_SOL
+        }));
+        await mu.anchorProvider.sendAndConfirm(fundTx);
+
+        await migrateRegion(mu, owner, legacyProvider, legacyRegion);
+
+        let regionAccount = await mu.program.account.providerRegion.fetch(legacyRegion.pda);
+        expect(regionAccount.provider.toBase58()).to.equals(legacyProvider.toBase58());
+        expect(regionAccount.name).to.equals("Legacy region");
+        expect(regionAccount.baseUrl).to.equals("http://localhost:12012");
+        expect(regionAccount.ratesVersion).to.equals(1);
+        expect(regionAccount.maxGigaInstructionsPerCall).to.equals(2);
+
+        let ratesAccount = await mu.program.account.regionRates.fetch(getRegionRates(mu, legacyRegion, 1));
+        expect(ratesAccount.version).to.equals(1);
+        expect(ratesAccount.rates.millionDbReads.toNumber()).to.equals(1000);
+    });
+
+    it("Migrates a stack created before rates were versioned", async () => {
+        await migrateStack(mu, legacyStack, legacyRegion);
+
+        let stackAccount = await mu.program.account.stack.fetch(legacyStack.pda);
+        expect(stackAccount.region.toBase58()).to.equals(legacyRegion.pda.toBase58());
+        expect(stackAccount.ratesVersion).to.equals(1);
+        assertActiveStackAccount(stackAccount, "legacy_stack", Buffer.from("legacy stack"), 3);
+    });
+
+    it("Fails to migrate accounts that are already migrated", async () => {
+        try {
+            await migrateStack(mu, legacyStack, legacyRegion);
+            throw new Error("Stack migration succeeded when it should have failed");
+        } catch (e) {
+            let anchorError = e as AnchorError;
+            expect(anchorError.message).to.contains("Account already has the current layout");
+        }
+
+        try {
+            await migrateState(mu, deletedStackGracePeriodSecs);
+            throw new Error("State migration succeeded when it should have failed");
+        } catch (e) {
+            let anchorError = e as AnchorError;
+            expect(anchorError.message).to.contains("Account already has the current layout");
+        }
+    });
 });
 
 const assertActiveStackAccount = (account: any, name: string, stackData: Buffer, revision: number) => {
@@ -316,8 +486,23 @@ const assertActiveStackAccount = (account: any, name: string, stackData: Buffer,
 const assertDeletedStackAccount = (account: any) => {
     expect(account.state["active"]).to.be.undefined;
     expect(account.state["deleted"]).to.not.be.undefined;
+    expect(account.state["deleted"].deletedAt.toNumber()).to.be.greaterThan(0);
 }
 
+// Usage seeds carry the time the usage was generated at, in microseconds,
+// in their upper 64 bits
+const usageSeedAt = (millis: number, lowBits: number): BN =>
+    new BN(millis).muln(1000).shln(64).addn(lowBits);
+
+const emptyUsage = (): ServiceUsage => ({
+    functionMbInstructions: new BN(0),
+    dbBytesSeconds: new BN(0),
+    dbReads: new BN(0),
+    dbWrites: new BN(0),
+    gatewayRequests: new BN(0),
+    gatewayTrafficBytes: new BN(0)
+});
+
 const bufferEqual = (b1: Buffer) => (b2: Buffer) => {
     expect(b1.length).to.equals(b2.length);
     for (let i = 0; i < b1.length; ++i) {
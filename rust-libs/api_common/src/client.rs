@@ -1,18 +1,29 @@
-use std::{path::PathBuf, rc::Rc, time::Duration};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    rc::Rc,
+    time::Duration,
+};
 
 use anyhow::{bail, Context, Result};
 use base64::{engine::general_purpose, Engine};
-use mu_stack::StackOwner;
+use mu_stack::{StackID, StackOwner};
 use solana_sdk::signer::Signer;
 
 use crate::{
-    requests::{EchoRequest, EchoResponse, UploadFunctionRequest, UploadFunctionResponse},
+    requests::{
+        EchoRequest, EchoResponse, FunctionLogEntry, GetCustomMetricsRequest,
+        GetCustomMetricsResponse, LogLevel, SetSecretRequest, StreamLogsRequest,
+        UploadFunctionRequest, UploadFunctionResponse,
+    },
     sign_request, SIGNATURE_HEADER_NAME,
 };
 
 //TODO: support async clients too
 pub struct ApiClient {
     region_api_endpoint: String,
+    region_logs_endpoint: String,
     client: reqwest::blocking::Client,
 }
 
@@ -23,12 +34,18 @@ impl ApiClient {
             p.push("api").unwrap();
             p
         });
+        let region_api_endpoint = uri.to_string();
+        uri.map_path(|mut p| {
+            p.push("logs").unwrap();
+            p
+        });
         Self {
             client: reqwest::blocking::Client::builder()
                 .timeout(Duration::from_secs(5 * 60))
                 .build()
                 .unwrap(),
-            region_api_endpoint: uri.to_string(),
+            region_api_endpoint,
+            region_logs_endpoint: uri.to_string(),
         }
     }
 
@@ -52,6 +69,49 @@ impl ApiClient {
         Ok(response.file_id)
     }
 
+    pub fn set_secret(
+        &self,
+        stack_id: StackID,
+        name: String,
+        value: String,
+        signer: Rc<dyn Signer>,
+    ) -> Result<()> {
+        let request = SetSecretRequest {
+            stack_id,
+            name,
+            value,
+        };
+
+        let (request_body, sign) = sign_request(
+            request,
+            "set_secret".to_string(),
+            Some(StackOwner::Solana(signer.pubkey().to_bytes())),
+            signer,
+        )?;
+
+        self.send(request_body, sign)?;
+        Ok(())
+    }
+
+    pub fn get_custom_metrics(
+        &self,
+        stack_id: StackID,
+        signer: Rc<dyn Signer>,
+    ) -> Result<HashMap<String, u64>> {
+        let request = GetCustomMetricsRequest { stack_id };
+
+        let (request_body, sign) = sign_request(
+            request,
+            "get_custom_metrics".to_string(),
+            Some(StackOwner::Solana(signer.pubkey().to_bytes())),
+            signer,
+        )?;
+
+        let response: GetCustomMetricsResponse =
+            serde_json::from_slice(&self.send(request_body, sign)?)?;
+        Ok(response.metrics)
+    }
+
     pub fn echo(&self, message: String, signer: Rc<dyn Signer>) -> Result<String> {
         let request = EchoRequest { message };
 
@@ -61,6 +121,53 @@ impl ApiClient {
         Ok(response.message)
     }
 
+    /// Streams the stack's function logs, calling `on_entry` for each one
+    /// until the connection is closed.
+    pub fn stream_logs(
+        &self,
+        stack_id: StackID,
+        level: LogLevel,
+        signer: Rc<dyn Signer>,
+        mut on_entry: impl FnMut(FunctionLogEntry),
+    ) -> Result<()> {
+        let request = StreamLogsRequest { stack_id, level };
+
+        let (request_body, sign) = sign_request(
+            request,
+            "stream_logs".to_string(),
+            Some(StackOwner::Solana(signer.pubkey().to_bytes())),
+            signer,
+        )?;
+
+        // The stream stays open for as long as the user wants, so the
+        // regular client's timeout doesn't apply here
+        let client = reqwest::blocking::Client::builder()
+            .timeout(None)
+            .build()
+            .context("Creating HTTP client")?;
+
+        let resp = client
+            .post(&self.region_logs_endpoint)
+            .header(SIGNATURE_HEADER_NAME, sign)
+            .body(request_body)
+            .send()
+            .context("Sending API request")?;
+
+        if !resp.status().is_success() {
+            bail!("Api status {}, error: {}", resp.status(), resp.text()?)
+        }
+
+        for line in BufReader::new(resp).lines() {
+            let line = line.context("Reading log stream")?;
+            if line.is_empty() {
+                continue;
+            }
+            on_entry(serde_json::from_str(&line).context("Invalid log entry")?);
+        }
+
+        Ok(())
+    }
+
     fn send(&self, request: Vec<u8>, sign: String) -> Result<bytes::Bytes> {
         let request = self
             .client
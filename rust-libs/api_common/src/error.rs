@@ -31,6 +31,15 @@ pub enum ServerError {
 
     #[error("BadRequest: {0}")]
     BadRequest(String),
+
+    #[error("Request has no user to verify its signature against")]
+    UnsignedRequest,
+
+    #[error("Request is not from the expected user")]
+    UnexpectedUser,
+
+    #[error("Invalid request signature")]
+    InvalidSignature,
 }
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
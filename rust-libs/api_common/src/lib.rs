@@ -3,12 +3,21 @@ pub mod client;
 mod error;
 pub mod requests;
 
-use std::{rc::Rc, str::FromStr};
+use std::{
+    rc::Rc,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use base64::{engine::general_purpose, Engine};
+use k256::{
+    ecdsa::{RecoveryId, VerifyingKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+};
 use log::error;
-use mu_stack::StackOwner;
+use mu_stack::{StackOwner, PWR_ADDRESS_SIZE};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Keccak256};
 use solana_sdk::signer::Signer;
 
 pub use error::{ClientError, Error, ServerError};
@@ -23,6 +32,14 @@ pub struct ApiRequestTemplate {
     #[serde(serialize_with = "serialize_stack_owner")]
     #[serde(deserialize_with = "deserialize_stack_owner")]
     pub user: Option<StackOwner>,
+
+    /// Random for each request, so the same signed request can't be
+    /// accepted twice.
+    pub nonce: u64,
+
+    /// When the request was signed, in seconds since the Unix epoch.
+    /// Requests too far from the server's clock are rejected.
+    pub timestamp: i64,
     // TODO: Stack ID
 }
 
@@ -44,6 +61,11 @@ pub fn sign_request<T: Serialize>(
             error!("Failed to serialize request: {e:?}");
             Error::SerializeRequest
         })?,
+        nonce: rand::random(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
     };
 
     let body_json = serde_json::to_vec(&body).map_err(|e| {
@@ -60,6 +82,74 @@ pub fn sign_request<T: Serialize>(
     Ok((body_json, sig_payload_base64))
 }
 
+/// Verifies a request produced by `sign_request`, given its body and the
+/// contents of its signature header. If `expected_user` is provided, the
+/// request must also be from that user.
+///
+/// Requests from PWR users are signed the way PWR wallets sign messages,
+/// with a recoverable secp256k1 signature of the body's Keccak-256 hash.
+pub fn verify_request(
+    body_json: &[u8],
+    signature_b64: &str,
+    expected_user: Option<StackOwner>,
+) -> Result<(), Error> {
+    let body: ApiRequestTemplate = serde_json::from_slice(body_json)
+        .map_err(|_| ServerError::BadRequest("can not deserialize request".into()))?;
+
+    let user = body.user.ok_or(ServerError::UnsignedRequest)?;
+    if matches!(expected_user, Some(expected) if expected != user) {
+        return Err(ServerError::UnexpectedUser.into());
+    }
+
+    let signature_bytes = general_purpose::STANDARD
+        .decode(signature_b64)
+        .map_err(|_| ServerError::InvalidSignature)?;
+
+    match user {
+        StackOwner::Solana(pk) => {
+            let pubkey = ed25519_dalek::PublicKey::from_bytes(&pk)
+                .map_err(|_| ServerError::InvalidSignature)?;
+            let signature = ed25519_dalek::Signature::from_bytes(&signature_bytes[..])
+                .map_err(|_| ServerError::InvalidSignature)?;
+
+            pubkey
+                .verify_strict(body_json, &signature)
+                .map_err(|_| ServerError::InvalidSignature)?;
+        }
+
+        StackOwner::PWR(address) => {
+            if recover_pwr_address(body_json, &signature_bytes) != Some(address) {
+                return Err(ServerError::InvalidSignature.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// PWR signatures are 64 bytes of signature followed by the recovery ID, which
+// may be offset by 27 as in Ethereum. Signatures don't carry the public key,
+// so the signer's address is recovered from them.
+fn recover_pwr_address(message: &[u8], signature: &[u8]) -> Option<[u8; PWR_ADDRESS_SIZE]> {
+    let [signature @ .., v] = signature else {
+        return None;
+    };
+    let signature = k256::ecdsa::Signature::from_slice(signature).ok()?;
+    let recovery_id = RecoveryId::from_byte(if *v >= 27 { v - 27 } else { *v })?;
+
+    let prehash = Keccak256::digest(message);
+    let pubkey = VerifyingKey::recover_from_prehash(&prehash, &signature, recovery_id).ok()?;
+    Some(pwr_address(&pubkey))
+}
+
+// Like in Ethereum, addresses are the last 20 bytes of the Keccak-256 hash of
+// the uncompressed public key, without its leading tag byte
+fn pwr_address(pubkey: &VerifyingKey) -> [u8; PWR_ADDRESS_SIZE] {
+    let point = pubkey.to_encoded_point(false);
+    let hash = Keccak256::digest(&point.as_bytes()[1..]);
+    hash[hash.len() - PWR_ADDRESS_SIZE..].try_into().unwrap()
+}
+
 pub fn serialize_stack_owner<S>(item: &Option<StackOwner>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -86,3 +176,126 @@ where
         None => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+
+    fn signed_echo(keypair: Keypair) -> (Vec<u8>, String, StackOwner) {
+        let user = StackOwner::Solana(keypair.pubkey().to_bytes());
+        let (body, signature) = sign_request(
+            requests::EchoRequest {
+                message: "hello".into(),
+            },
+            "echo".into(),
+            Some(user),
+            Rc::new(keypair),
+        )
+        .unwrap();
+        (body, signature, user)
+    }
+
+    #[test]
+    fn valid_signatures_are_accepted() {
+        let (body, signature, user) = signed_echo(Keypair::new());
+
+        verify_request(&body, &signature, Some(user)).unwrap();
+        verify_request(&body, &signature, None).unwrap();
+    }
+
+    #[test]
+    fn tampered_bodies_are_rejected() {
+        let (body, signature, user) = signed_echo(Keypair::new());
+        let body = String::from_utf8(body)
+            .unwrap()
+            .replace("hello", "jello")
+            .into_bytes();
+
+        assert!(matches!(
+            verify_request(&body, &signature, Some(user)),
+            Err(Error::ServerError(ServerError::InvalidSignature))
+        ));
+    }
+
+    #[test]
+    fn requests_from_other_users_are_rejected() {
+        let (body, signature, _) = signed_echo(Keypair::new());
+        let other_user = StackOwner::Solana(Keypair::new().pubkey().to_bytes());
+
+        assert!(matches!(
+            verify_request(&body, &signature, Some(other_user)),
+            Err(Error::ServerError(ServerError::UnexpectedUser))
+        ));
+    }
+
+    fn signed_pwr_echo(key: &k256::ecdsa::SigningKey, message: &str) -> (Vec<u8>, String) {
+        let body = serde_json::to_vec(&ApiRequestTemplate {
+            request: "echo".into(),
+            params: serde_json::json!({ "message": message }),
+            user: Some(StackOwner::PWR(pwr_address(key.verifying_key()))),
+            nonce: 1,
+            timestamp: 0,
+        })
+        .unwrap();
+
+        let (signature, recovery_id) = key
+            .sign_prehash_recoverable(&Keccak256::digest(&body))
+            .unwrap();
+        let mut signature = signature.to_bytes().to_vec();
+        signature.push(recovery_id.to_byte() + 27);
+
+        (body, general_purpose::STANDARD.encode(signature))
+    }
+
+    fn pwr_key(seed: u8) -> k256::ecdsa::SigningKey {
+        k256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    #[test]
+    fn valid_pwr_signatures_are_accepted() {
+        let key = pwr_key(1);
+        let (body, signature) = signed_pwr_echo(&key, "hello");
+        let user = StackOwner::PWR(pwr_address(key.verifying_key()));
+
+        verify_request(&body, &signature, Some(user)).unwrap();
+        verify_request(&body, &signature, None).unwrap();
+    }
+
+    #[test]
+    fn tampered_pwr_bodies_are_rejected() {
+        let (body, signature) = signed_pwr_echo(&pwr_key(1), "hello");
+        let body = String::from_utf8(body)
+            .unwrap()
+            .replace("hello", "jello")
+            .into_bytes();
+
+        assert!(matches!(
+            verify_request(&body, &signature, None),
+            Err(Error::ServerError(ServerError::InvalidSignature))
+        ));
+    }
+
+    #[test]
+    fn pwr_signatures_from_other_keys_are_rejected() {
+        let (body, _) = signed_pwr_echo(&pwr_key(1), "hello");
+        let (_, other_signature) = signed_pwr_echo(&pwr_key(2), "hello");
+
+        assert!(matches!(
+            verify_request(&body, &other_signature, None),
+            Err(Error::ServerError(ServerError::InvalidSignature))
+        ));
+    }
+
+    #[test]
+    fn signatures_from_other_keys_are_rejected() {
+        let (body, _, user) = signed_echo(Keypair::new());
+        let (_, other_signature, _) = signed_echo(Keypair::new());
+
+        assert!(matches!(
+            verify_request(&body, &other_signature, Some(user)),
+            Err(Error::ServerError(ServerError::InvalidSignature))
+        ));
+    }
+}
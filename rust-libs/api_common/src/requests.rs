@@ -1,3 +1,6 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use mu_stack::StackID;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -10,6 +13,27 @@ pub struct UploadFunctionResponse {
     pub file_id: String,
 }
 
+/// Sets a secret that the stack's functions can reference by name, see
+/// `Function::secrets`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetSecretRequest {
+    pub stack_id: StackID,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetCustomMetricsRequest {
+    pub stack_id: StackID,
+}
+
+/// Totals of the custom metrics the stack's functions reported with
+/// `MuContext::report_metric`, keyed by metric name.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetCustomMetricsResponse {
+    pub metrics: HashMap<String, u64>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EchoRequest {
     pub message: String,
@@ -19,3 +43,60 @@ pub struct EchoRequest {
 pub struct EchoResponse {
     pub message: String,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StreamLogsRequest {
+    pub stack_id: StackID,
+    /// Only logs at this level or more severe are streamed.
+    #[serde(default)]
+    pub level: LogLevel,
+}
+
+/// Sent as one JSON object per line in response to a `StreamLogsRequest`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FunctionLogEntry {
+    pub function: String,
+    pub level: LogLevel,
+    pub body: String,
+}
+
+// Ordered from most to least severe, same as `log::Level`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            _ => Err(format!(
+                "Unknown log level '{s}', expected one of error, warn, info, debug or trace"
+            )),
+        }
+    }
+}
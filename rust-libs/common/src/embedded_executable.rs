@@ -0,0 +1,83 @@
+use std::{
+    fs, io,
+    os::unix::prelude::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use sha2::{Digest, Sha256};
+
+/// Writes an embedded executable to `dir/name` and returns its path.
+/// A file already at that path is only reused if its SHA-256 hash matches
+/// `sha256`, so stale or tampered binaries are replaced instead of run.
+pub fn extract_embedded_executable(
+    dir: &Path,
+    name: &str,
+    data: &[u8],
+    sha256: &[u8; 32],
+) -> Result<PathBuf> {
+    let path = dir.join(name);
+
+    match file_sha256(&path)? {
+        Some(hash) if hash == *sha256 => (),
+        existing => {
+            if existing.is_some() {
+                warn!(
+                    "Checksum of {} doesn't match, extracting it again",
+                    path.display()
+                );
+                // Extracted files are read-only, and may still be running
+                fs::remove_file(&path).context("Failed to remove stale executable")?;
+            }
+
+            fs::write(&path, data).context("Failed to write embedded resource to temp file")?;
+        }
+    }
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o500))
+        .context("Failed to set executable permission on temp file")?;
+
+    Ok(path)
+}
+
+fn file_sha256(path: &Path) -> Result<Option<[u8; 32]>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to open temp file"),
+    };
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).context("Failed to read temp file")?;
+    Ok(Some(hasher.finalize().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use sha2::{Digest, Sha256};
+
+    use super::extract_embedded_executable;
+
+    #[test]
+    fn corrupted_executable_is_extracted_again() {
+        let dir = env::temp_dir().join(format!("mu-embedded-executable-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let data = b"#!/bin/sh\necho hello\n";
+        let sha256: [u8; 32] = Sha256::digest(data).into();
+
+        let path = extract_embedded_executable(&dir, "tool", data, &sha256).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data);
+
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, b"tampered").unwrap();
+
+        let path = extract_embedded_executable(&dir, "tool", data, &sha256).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
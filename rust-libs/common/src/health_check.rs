@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Controls how startup health checks against backing services (TiKV,
+/// storage) are retried before giving up.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct HealthCheckConfig {
+    /// How many times a failed check is retried.
+    pub max_tries: u32,
+    pub base_delay_ms: u64,
+    pub backoff_factor: f64,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            max_tries: 5,
+            base_delay_ms: 1000,
+            backoff_factor: 1.5,
+        }
+    }
+}
+
+impl HealthCheckConfig {
+    /// Delay before retrying a check that has already failed `try_count + 1` times.
+    pub fn delay(&self, try_count: u32) -> Duration {
+        let delay_ms = self.backoff_factor.powf(try_count as f64) * self.base_delay_ms as f64;
+        Duration::from_millis(delay_ms.round() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_delays_grow_by_half_each_try() {
+        let config = HealthCheckConfig::default();
+        assert_eq!(config.delay(0), Duration::from_millis(1000));
+        assert_eq!(config.delay(1), Duration::from_millis(1500));
+        assert_eq!(config.delay(2), Duration::from_millis(2250));
+        assert_eq!(config.delay(3), Duration::from_millis(3375));
+    }
+
+    #[test]
+    fn custom_delays_follow_the_config() {
+        let config = HealthCheckConfig {
+            max_tries: 10,
+            base_delay_ms: 200,
+            backoff_factor: 2.0,
+        };
+        assert_eq!(config.delay(0), Duration::from_millis(200));
+        assert_eq!(config.delay(3), Duration::from_millis(1600));
+
+        let constant = HealthCheckConfig {
+            backoff_factor: 1.0,
+            ..config
+        };
+        assert_eq!(constant.delay(0), Duration::from_millis(200));
+        assert_eq!(constant.delay(7), Duration::from_millis(200));
+    }
+}
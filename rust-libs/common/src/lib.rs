@@ -1,3 +1,5 @@
+pub mod embedded_executable;
+pub mod health_check;
 pub mod id;
 pub mod replace_with;
 pub mod serde_support;
@@ -5,34 +5,47 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use dyn_clonable::clonable;
-use log::{error, warn};
+use log::{error, log, warn, Level};
 use mailbox_processor::callback::CallbackMailboxProcessor;
-use mu_common::serde_support::{IpOrHostname, TcpPortAddress};
+use mu_common::{
+    embedded_executable::extract_embedded_executable,
+    serde_support::{IpOrHostname, TcpPortAddress},
+};
 use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
 use rust_embed::RustEmbed;
 use serde::Deserialize;
 use std::ops::Deref;
 use std::{
-    env,
+    env, fmt,
+    io::{BufRead, BufReader, Read},
     net::{IpAddr, Ipv4Addr},
-    os::unix::prelude::PermissionsExt,
     path::PathBuf,
-    process::{self, Stdio},
+    process::{ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
-use tokio::{fs::File, io::AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
 
 use mu_db::{DbConfig, DbManager};
 
 pub struct DbManagerWithTikv {
     pub tikv: Box<dyn TikvRunner>,
     db_manager: Box<dyn DbManager>,
+    notifications: Option<mpsc::Receiver<TikvRunnerNotification>>,
 }
 
 impl DbManagerWithTikv {
     pub async fn stop(&self) -> anyhow::Result<()> {
         self.tikv.stop().await
     }
+
+    /// Returns the notifications raised by the embedded cluster's processes.
+    /// Only the first call returns `Some`. Notifications raised while the
+    /// receiver is full are dropped.
+    pub fn take_notifications(&mut self) -> Option<mpsc::Receiver<TikvRunnerNotification>> {
+        self.notifications.take()
+    }
 }
 
 impl Deref for DbManagerWithTikv {
@@ -47,12 +60,15 @@ pub async fn new_with_embedded_cluster(
     known_node_config: Vec<RemoteNode>,
     config: TikvRunnerConfig,
 ) -> anyhow::Result<DbManagerWithTikv> {
-    let tikv = start(node_address, known_node_config, config.clone())
+    let (tikv, notifications) = start(node_address, known_node_config, config.clone())
         .await
         .unwrap();
 
     let db_config = DbConfig {
         pd_addresses: vec![config.pd.advertise_client_url()],
+        keyspace_prefix: vec![],
+        retry: Default::default(),
+        health_check: None,
     };
 
     let inner = mu_db::start(db_config).await.unwrap();
@@ -60,6 +76,7 @@ pub async fn new_with_embedded_cluster(
     Ok(DbManagerWithTikv {
         tikv,
         db_manager: inner,
+        notifications: Some(notifications),
     })
 }
 
@@ -68,6 +85,9 @@ pub async fn new_with_external_cluster(
 ) -> anyhow::Result<Box<dyn DbManager>> {
     let db_config = DbConfig {
         pd_addresses: endpoints,
+        keyspace_prefix: vec![],
+        retry: Default::default(),
+        health_check: None,
     };
 
     mu_db::start(db_config).await
@@ -77,45 +97,14 @@ pub async fn new_with_external_cluster(
 #[folder = "assets"]
 pub struct Assets;
 
-async fn check_and_extract_embedded_executable(name: &str) -> Result<PathBuf> {
-    let mut temp_address = env::temp_dir();
-    temp_address.push(name);
-
-    // TODO check checksum instead existing.
-    let file = if temp_address.exists() {
-        File::open(temp_address.as_path())
-            .await
-            .context("Failed to open temp file")?
-    } else {
-        let mut file = File::create(temp_address.as_path())
-            .await
-            .context("Failed to create temp file")?;
-
-        let tool = <Assets as RustEmbed>::get(name).context("Failed to get embedded asset")?;
-        let tool_bytes = tool.data;
-
-        file.write_all(&tool_bytes)
-            .await
-            .context("Failed to write embedded resource to temp file")?;
-
-        file.flush().await.context("Failed to flush temp file")?;
-
-        file
-    };
-
-    let mut perms = file
-        .metadata()
-        .await
-        .context("Failed to get temp file metadata")?
-        .permissions();
-
-    perms.set_mode(0o500);
-
-    file.set_permissions(perms)
-        .await
-        .context("Failed to set executable permission on temp file")?;
-
-    Ok(temp_address)
+fn check_and_extract_embedded_executable(name: &str) -> Result<PathBuf> {
+    let tool = <Assets as RustEmbed>::get(name).context("Failed to get embedded asset")?;
+    extract_embedded_executable(
+        &env::temp_dir(),
+        name,
+        &tool.data,
+        &tool.metadata.sha256_hash(),
+    )
 }
 
 #[derive(Deserialize, Clone)]
@@ -159,10 +148,63 @@ impl TikvConfig {
     }
 }
 
+pub const DEFAULT_MAX_RESTARTS: u32 = 3;
+
+// A process that ran for this long before exiting is considered to have
+// recovered, so its earlier restarts no longer count towards `max_restarts`.
+const STABLE_RUN_TIME: Duration = Duration::from_secs(10 * 60);
+
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 32;
+
+fn default_max_restarts() -> u32 {
+    DEFAULT_MAX_RESTARTS
+}
+
 #[derive(Deserialize, Clone)]
 pub struct TikvRunnerConfig {
     pub pd: PdConfig,
     pub node: TikvConfig,
+
+    /// How many times in a row each of pd and tikv is started again after
+    /// exiting unexpectedly, before the runner gives up on it. Restarts are
+    /// forgotten once the process has been running for a while.
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessKind {
+    Pd,
+    Tikv,
+}
+
+impl ProcessKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ProcessKind::Pd => "pd",
+            ProcessKind::Tikv => "tikv",
+        }
+    }
+}
+
+impl fmt::Display for ProcessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug)]
+pub enum TikvRunnerNotification {
+    /// The process exited unexpectedly and was started again.
+    Restarted {
+        process: ProcessKind,
+        status: Option<ExitStatus>,
+    },
+
+    /// The process exited unexpectedly and won't be started again, either
+    /// because it was already restarted `max_restarts` times in a row or because
+    /// starting it failed. The DB is unusable from this point on.
+    GaveUp { process: ProcessKind },
 }
 
 #[async_trait]
@@ -294,6 +336,11 @@ fn generate_arguments(
 
 enum Message {
     Stop,
+    Exited {
+        process: ProcessKind,
+        pid: u32,
+        status: Option<ExitStatus>,
+    },
 }
 
 #[derive(Clone)]
@@ -305,42 +352,64 @@ pub async fn start(
     node_address: TcpPortAddress,
     known_node_config: Vec<RemoteNode>,
     config: TikvRunnerConfig,
-) -> Result<Box<dyn TikvRunner>> {
+) -> Result<(Box<dyn TikvRunner>, mpsc::Receiver<TikvRunnerNotification>)> {
     let tikv_version = env!("TIKV_VERSION");
     let pd_exe = check_and_extract_embedded_executable(&format!("pd-server-{tikv_version}"))
-        .await
         .context("Failed to create pd-exe")?;
     let tikv_exe = check_and_extract_embedded_executable(&format!("tikv-server-{tikv_version}"))
-        .await
         .context("Failed to create tikv-exe")?;
 
+    let max_restarts = config.max_restarts;
     let args = generate_arguments(node_address, known_node_config, config);
 
-    // TODO: capture stdio logs
-    let pd_process = std::process::Command::new(pd_exe)
-        .args(args.pd_args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("Failed to spawn process pd")?;
-
-    let tikv_process = std::process::Command::new(tikv_exe)
-        .args(args.tikv_args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("Failed to spawn process tikv")?;
+    start_supervised(
+        (pd_exe, args.pd_args),
+        (tikv_exe, args.tikv_args),
+        max_restarts,
+        STABLE_RUN_TIME,
+    )
+}
+
+fn start_supervised(
+    (pd_exe, pd_args): (PathBuf, Vec<String>),
+    (tikv_exe, tikv_args): (PathBuf, Vec<String>),
+    max_restarts: u32,
+    stable_run_time: Duration,
+) -> Result<(Box<dyn TikvRunner>, mpsc::Receiver<TikvRunnerNotification>)> {
+    // The processes are started before the mailbox exists, so their exits
+    // go through this channel and are forwarded to the mailbox below.
+    let (exit_sender, mut exit_receiver) = mpsc::unbounded_channel();
+
+    let pd = SupervisedProcess::spawn(ProcessKind::Pd, pd_exe, pd_args, &exit_sender)?;
+    let tikv = SupervisedProcess::spawn(ProcessKind::Tikv, tikv_exe, tikv_args, &exit_sender)?;
+
+    let (notification_sender, notification_receiver) = mpsc::channel(NOTIFICATION_CHANNEL_CAPACITY);
 
     let mailbox = CallbackMailboxProcessor::start(
         step,
         TikvRunnerState {
-            pd_process,
-            tikv_process,
+            pd,
+            tikv,
+            max_restarts,
+            stable_run_time,
+            stopping: false,
+            exit_sender,
+            notification_sender,
         },
         10000,
     );
 
-    Ok(Box::new(TikvRunnerImpl { mailbox }))
+    let mailbox_clone = mailbox.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = exit_receiver.recv().await {
+            if mailbox_clone.post(msg).await.is_err() {
+                // The runner was stopped, so the exit was expected
+                return;
+            }
+        }
+    });
+
+    Ok((Box::new(TikvRunnerImpl { mailbox }), notification_receiver))
 }
 
 #[async_trait]
@@ -354,9 +423,155 @@ impl TikvRunner for TikvRunnerImpl {
     }
 }
 
+// Writes each line of a child process' output to our own logs. This also
+// keeps the pipe drained, so the child never blocks on a full pipe buffer.
+// The thread exits once the child closes its end of the pipe.
+fn forward_output(
+    name: &'static str,
+    pipe: impl Read + Send + 'static,
+    level: Level,
+) -> Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(format!("{name}-output"))
+        .spawn(move || {
+            for line in BufReader::new(pipe).split(b'\n') {
+                match line {
+                    Ok(line) => log!(level, "{name}: {}", String::from_utf8_lossy(&line)),
+                    Err(e) => {
+                        error!("failed to read output of {name}: {e:?}");
+                        return;
+                    }
+                }
+            }
+        })
+        .context("Failed to spawn output forwarding thread")
+}
+
+struct SupervisedProcess {
+    kind: ProcessKind,
+    exe: PathBuf,
+    args: Vec<String>,
+    pid: u32,
+    started_at: Instant,
+    restarts: u32,
+    // Resolves once the current instance of the process has exited.
+    // `None` once the process is known to be gone for good.
+    exited: Option<oneshot::Receiver<()>>,
+}
+
+impl SupervisedProcess {
+    fn spawn(
+        kind: ProcessKind,
+        exe: PathBuf,
+        args: Vec<String>,
+        exit_sender: &mpsc::UnboundedSender<Message>,
+    ) -> Result<Self> {
+        let mut process = Self {
+            kind,
+            exe,
+            args,
+            pid: 0,
+            started_at: Instant::now(),
+            restarts: 0,
+            exited: None,
+        };
+        process.respawn(exit_sender)?;
+        Ok(process)
+    }
+
+    // Starts a new instance of the process and a task that reports
+    // its exit through `exit_sender`.
+    fn respawn(&mut self, exit_sender: &mpsc::UnboundedSender<Message>) -> Result<()> {
+        let name = self.kind.name();
+
+        let mut child = std::process::Command::new(&self.exe)
+            .args(&self.args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn process {name}"))?;
+
+        let mut output_forwarders = vec![];
+        let stdout = child.stdout.take().context("Failed to get stdout")?;
+        output_forwarders.push(forward_output(name, stdout, Level::Info)?);
+        let stderr = child.stderr.take().context("Failed to get stderr")?;
+        output_forwarders.push(forward_output(name, stderr, Level::Warn)?);
+
+        let pid = child.id();
+        let (exited_sender, exited_receiver) = oneshot::channel();
+        let exit_sender = exit_sender.clone();
+        let process = self.kind;
+
+        tokio::spawn(async move {
+            let status = tokio::task::spawn_blocking(move || {
+                let status = match child.wait() {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        error!("failed to wait for {name} to exit {e:?}");
+                        None
+                    }
+                };
+
+                // The process is gone, so its pipes are closed
+                for forwarder in output_forwarders {
+                    if forwarder.join().is_err() {
+                        error!("output forwarding thread panicked")
+                    }
+                }
+
+                status
+            })
+            .await
+            .ok()
+            .flatten();
+
+            let _ = exited_sender.send(());
+            let _ = exit_sender.send(Message::Exited {
+                process,
+                pid,
+                status,
+            });
+        });
+
+        self.pid = pid;
+        self.started_at = Instant::now();
+        self.exited = Some(exited_receiver);
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        let Some(exited) = self.exited.take() else {
+            return;
+        };
+
+        if let Err(f) = signal::kill(Pid::from_raw(self.pid.try_into().unwrap()), Signal::SIGINT) {
+            error!("failed to kill {} process due to: {f:?}", self.kind)
+        }
+
+        let _ = exited.await;
+    }
+}
+
 struct TikvRunnerState {
-    pub pd_process: process::Child,
-    pub tikv_process: process::Child,
+    pd: SupervisedProcess,
+    tikv: SupervisedProcess,
+    max_restarts: u32,
+    stable_run_time: Duration,
+    stopping: bool,
+    exit_sender: mpsc::UnboundedSender<Message>,
+    notification_sender: mpsc::Sender<TikvRunnerNotification>,
+}
+
+impl TikvRunnerState {
+    fn notify(&self, notification: TikvRunnerNotification) {
+        // Nothing here depends on notifications arriving, so a slow or
+        // absent consumer must not block supervision
+        if let Err(mpsc::error::TrySendError::Full(notification)) =
+            self.notification_sender.try_send(notification)
+        {
+            warn!("notification channel is full, dropping {notification:?}");
+        }
+    }
 }
 
 async fn step(
@@ -366,26 +581,50 @@ async fn step(
 ) -> TikvRunnerState {
     match msg {
         Message::Stop => {
-            if let Err(f) = signal::kill(
-                Pid::from_raw(state.tikv_process.id().try_into().unwrap()),
-                Signal::SIGINT,
-            ) {
-                error!("failed to kill tikv_process due to: {f:?}")
+            // Disable restarts first, so the exits we cause below
+            // aren't mistaken for crashes
+            state.stopping = true;
+
+            state.tikv.stop().await;
+            state.pd.stop().await;
+        }
+
+        Message::Exited {
+            process,
+            pid,
+            status,
+        } => {
+            let supervised = match process {
+                ProcessKind::Pd => &mut state.pd,
+                ProcessKind::Tikv => &mut state.tikv,
+            };
+
+            // Exits of earlier instances were already handled
+            if state.stopping || supervised.pid != pid {
+                return state;
             }
 
-            if let Err(e) = state.tikv_process.wait() {
-                error!("failed to wait for tikv to exit {e:?}")
+            if supervised.started_at.elapsed() >= state.stable_run_time {
+                supervised.restarts = 0;
             }
 
-            if let Err(f) = signal::kill(
-                Pid::from_raw(state.pd_process.id().try_into().unwrap()),
-                Signal::SIGINT,
-            ) {
-                error!("failed to kill pd_process due to: {f:?}")
+            if supervised.restarts >= state.max_restarts {
+                error!("{process} exited unexpectedly with {status:?}, giving up on it");
+                supervised.exited = None;
+                state.notify(TikvRunnerNotification::GaveUp { process });
+                return state;
             }
 
-            if let Err(e) = state.pd_process.wait() {
-                error!("failed to wait for pd to exit {e:?}")
+            warn!("{process} exited unexpectedly with {status:?}, restarting it");
+            supervised.restarts += 1;
+
+            match supervised.respawn(&state.exit_sender) {
+                Ok(()) => state.notify(TikvRunnerNotification::Restarted { process, status }),
+                Err(e) => {
+                    error!("failed to restart {process} due to: {e:?}");
+                    supervised.exited = None;
+                    state.notify(TikvRunnerNotification::GaveUp { process });
+                }
             }
         }
     }
@@ -438,6 +677,7 @@ mod test {
                 data_dir: PathBuf::from("./tikv_test_dir"),
                 log_file: None,
             },
+            max_restarts: DEFAULT_MAX_RESTARTS,
         };
 
         let res = generate_arguments(node_address, known_node_conf, tikv_runner_conf);
@@ -467,4 +707,93 @@ mod test {
         assert_eq!(res.tikv_args[2], "--advertise-addr=127.0.0.1:20160");
         assert_eq!(res.tikv_args[3], "--data-dir=./tikv_test_dir");
     }
+
+    fn shell(script: &str) -> (PathBuf, Vec<String>) {
+        ("sh".into(), vec!["-c".into(), script.into()])
+    }
+
+    async fn next_notification(
+        notifications: &mut mpsc::Receiver<TikvRunnerNotification>,
+    ) -> TikvRunnerNotification {
+        tokio::time::timeout(std::time::Duration::from_secs(10), notifications.recv())
+            .await
+            .expect("timed out waiting for a notification")
+            .expect("notification channel closed")
+    }
+
+    #[tokio::test]
+    async fn killed_processes_are_restarted_until_max_restarts() {
+        // tikv kills itself as soon as it starts
+        let (runner, mut notifications) = start_supervised(
+            shell("exec sleep 60"),
+            shell("kill -9 $$"),
+            2,
+            STABLE_RUN_TIME,
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            assert!(matches!(
+                next_notification(&mut notifications).await,
+                TikvRunnerNotification::Restarted {
+                    process: ProcessKind::Tikv,
+                    status: Some(_),
+                }
+            ));
+        }
+        assert!(matches!(
+            next_notification(&mut notifications).await,
+            TikvRunnerNotification::GaveUp {
+                process: ProcessKind::Tikv
+            }
+        ));
+
+        runner.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn restarts_are_forgotten_after_running_stably() {
+        // tikv runs for longer than the stable run time before exiting,
+        // so it's restarted every time despite `max_restarts`
+        let (runner, mut notifications) = start_supervised(
+            shell("exec sleep 60"),
+            shell("sleep 1; exit 1"),
+            1,
+            Duration::from_millis(500),
+        )
+        .unwrap();
+
+        for _ in 0..3 {
+            assert!(matches!(
+                next_notification(&mut notifications).await,
+                TikvRunnerNotification::Restarted {
+                    process: ProcessKind::Tikv,
+                    status: Some(_),
+                }
+            ));
+        }
+
+        runner.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stopped_processes_are_not_restarted() {
+        let (runner, mut notifications) = start_supervised(
+            shell("exec sleep 60"),
+            shell("exec sleep 60"),
+            2,
+            STABLE_RUN_TIME,
+        )
+        .unwrap();
+
+        runner.stop().await.unwrap();
+
+        // Once the runner is stopped, the channel closes without
+        // any restarts being reported
+        let notification =
+            tokio::time::timeout(std::time::Duration::from_secs(10), notifications.recv())
+                .await
+                .expect("timed out waiting for the channel to close");
+        assert!(notification.is_none());
+    }
 }
@@ -1,4 +1,4 @@
-use super::types::Key;
+use super::types::{Blob, Key};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,8 +11,72 @@ pub enum Error {
     CantDeserializeKey(String),
     #[error("mu_db: stack_id or table doesn't exist: {0:?}")]
     StackIdOrTableDoseNotExist(Key),
+    #[error("mu_db: invalid key: {0:?}")]
+    InvalidKey(Blob),
     #[error("mu_db: internal error: {0}")]
     InternalErr(#[from] anyhow::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Whether an error is likely to go away if the operation is retried,
+/// e.g. region or leader changes while the cluster is rebalancing.
+pub(crate) fn is_transient(e: &tikv_client::Error) -> bool {
+    use tikv_client::Error as E;
+
+    match e {
+        E::RegionError(_)
+        | E::RegionForKeyNotFound { .. }
+        | E::RegionNotFoundInResponse { .. }
+        | E::LeaderNotFound { .. }
+        | E::Grpc(_) => true,
+        E::MultipleKeyErrors(errors) | E::ExtractedErrors(errors) => {
+            !errors.is_empty() && errors.iter().all(is_transient)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tikv_client::Error as E;
+
+    fn region_not_found() -> E {
+        E::RegionForKeyNotFound { key: vec![1, 2, 3] }
+    }
+
+    fn leader_not_found() -> E {
+        E::LeaderNotFound { region_id: 1 }
+    }
+
+    fn permanent() -> E {
+        E::StringError("invalid request".into())
+    }
+
+    #[test]
+    fn region_and_leader_changes_are_transient() {
+        assert!(is_transient(&region_not_found()));
+        assert!(is_transient(&leader_not_found()));
+    }
+
+    #[test]
+    fn other_errors_are_not_transient() {
+        assert!(!is_transient(&permanent()));
+        assert!(!is_transient(&E::Unimplemented));
+        assert!(!is_transient(&E::DuplicateKeyInsertion));
+    }
+
+    #[test]
+    fn batches_are_transient_only_if_every_error_is() {
+        assert!(is_transient(&E::MultipleKeyErrors(vec![
+            region_not_found(),
+            leader_not_found(),
+        ])));
+        assert!(!is_transient(&E::MultipleKeyErrors(vec![
+            region_not_found(),
+            permanent(),
+        ])));
+        assert!(!is_transient(&E::ExtractedErrors(vec![])));
+    }
+}
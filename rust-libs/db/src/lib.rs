@@ -1,28 +1,80 @@
 pub mod error;
+mod transaction;
 mod types;
 
-pub use self::types::{Blob, DeleteTable, Key, Scan, TableName};
+pub use self::transaction::{transaction_fn, TransactionContext, TransactionFn, TransactionFuture};
+pub use self::types::{Blob, DeleteTable, Key, Keyspace, Scan, TableName};
 use dyn_clonable::clonable;
-use log::warn;
-use mu_common::serde_support::TcpPortAddress;
+use log::{debug, warn};
+use mu_common::{
+    health_check::HealthCheckConfig,
+    serde_support::{ConfigDuration, TcpPortAddress},
+};
 
 use crate::{
-    error::{Error, Result},
+    error::{is_transient, Error, Result},
     types::*,
 };
 use anyhow::bail;
 use async_trait::async_trait;
 use mu_stack::StackID;
 use serde::Deserialize;
-use std::{collections::HashSet, fmt::Debug};
-use tikv_client::{self, KvPair, RawClient, Value};
-use tokio::time::{sleep, Duration};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Debug,
+    future::Future,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tikv_client::{self, BoundRange, KvPair, RawClient, Value};
+use tokio::{
+    task::JoinHandle,
+    time::{sleep, Duration},
+};
+
+// Keys are fetched in pages of this size when counting or deleting them
+const COUNT_PAGE_SIZE: u32 = 1024;
+// Pairs are fetched in pages of this size during reverse scans
+const REVERSE_SCAN_PAGE_SIZE: u32 = 1024;
+// How often keys written with a TTL are checked for expiry
+const EXPIRED_KEYS_REAP_INTERVAL: Duration = Duration::from_secs(10);
+// Expiry records are fetched in pages of this size when reaping keys
+const EXPIRY_PAGE_SIZE: u32 = 1024;
 
 // Only one of the fields should be provided
 // Used struct instead of enum, only for better visual structure in config
 #[derive(Deserialize, Clone)]
 pub struct DbConfig {
     pub pd_addresses: Vec<TcpPortAddress>,
+
+    /// Prepended to every key, so multiple mu deployments can share a
+    /// single TiKV cluster without stepping on each other's data.
+    #[serde(default)]
+    pub keyspace_prefix: Vec<u8>,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// How startup checks wait for the cluster to become reachable.
+    pub health_check: Option<HealthCheckConfig>,
+}
+
+/// Controls how operations failing with transient TiKV errors are retried.
+#[derive(Deserialize, Clone)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff: ConfigDuration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50).into(),
+        }
+    }
 }
 
 #[async_trait]
@@ -41,7 +93,13 @@ pub trait DbClient: Send + Sync + Debug + Clone {
         upper_exclusive: Vec<u8>,
         limit: u32,
     ) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
-    async fn put_raw(&self, key: Vec<u8>, value: Value, is_atomic: bool) -> Result<()>;
+    async fn put_raw(
+        &self,
+        key: Vec<u8>,
+        value: Value,
+        is_atomic: bool,
+        ttl: Option<Duration>,
+    ) -> Result<()>;
     async fn compare_and_swap_raw(
         &self,
         key: Vec<u8>,
@@ -51,23 +109,61 @@ pub trait DbClient: Send + Sync + Debug + Clone {
     async fn delete_raw(&self, key: Vec<u8>, is_atomic: bool) -> Result<()>;
 
     async fn get(&self, key: Key) -> Result<Option<Value>>;
-    async fn put(&self, key: Key, value: Value, is_atomic: bool) -> Result<()>;
+    /// With a `ttl`, the key is deleted once it expires. Reads skip expired
+    /// keys right away, even before they're reaped every few seconds. Any
+    /// other write to the key, including a `put` without a TTL, makes it
+    /// permanent again, and so does deleting it.
+    async fn put(
+        &self,
+        key: Key,
+        value: Value,
+        is_atomic: bool,
+        ttl: Option<Duration>,
+    ) -> Result<()>;
+    /// Remaining lifetime of a key written with a TTL, or `None` if the key
+    /// is permanent or doesn't exist.
+    async fn get_ttl(&self, key: Key) -> Result<Option<Duration>>;
     async fn delete(&self, key: Key, is_atomic: bool) -> Result<()>;
 
+    /// Returns how many keys were deleted. Keys are deleted a page at a
+    /// time, so this is O(n) in the number of matching keys.
     async fn delete_by_prefix(
         &self,
         stack_id: StackID,
         table_name: TableName,
         prefix_user_key: Blob,
-    ) -> Result<()>;
+    ) -> Result<u64>;
 
-    async fn clear_table(&self, stack_id: StackID, table_name: TableName) -> Result<()>;
+    /// Same as `delete_by_prefix`, for a whole table.
+    async fn clear_table(&self, stack_id: StackID, table_name: TableName) -> Result<u64>;
 
-    async fn scan(&self, scan: Scan, limit: u32) -> Result<Vec<(Key, Value)>>;
-    async fn scan_keys(&self, scan: Scan, limit: u32) -> Result<Vec<Key>>;
+    /// Returns up to `limit` pairs in ascending key order, or with `reverse`,
+    /// the last `limit` pairs in descending order. TiKV's client can only
+    /// scan forwards, so reverse scans page through the whole range and are
+    /// slower on large ranges.
+    async fn scan(&self, scan: Scan, limit: u32, reverse: bool) -> Result<Vec<(Key, Value)>>;
+    /// Same as `scan`, including the cost of reverse scans.
+    async fn scan_keys(&self, scan: Scan, limit: u32, reverse: bool) -> Result<Vec<Key>>;
+
+    /// Counts keys starting with `prefix_inner_key` by paging through them,
+    /// so this is O(n) in the number of matching keys. Counting stops at
+    /// `limit`, in which case the actual count may be higher.
+    async fn count_by_prefix(
+        &self,
+        stack_id: StackID,
+        table_name: TableName,
+        prefix_inner_key: Blob,
+        limit: u64,
+    ) -> Result<u64>;
+    /// Same as `count_by_prefix` for any kind of scan, e.g. a whole table
+    /// with `Scan::ByTableName`.
+    async fn count(&self, scan: Scan, limit: u64) -> Result<u64>;
 
     async fn batch_put(&self, pairs: Vec<(Key, Value)>, is_atomic: bool) -> Result<()>;
     async fn batch_get(&self, keys: Vec<Key>) -> Result<Vec<(Key, Value)>>;
+    /// Like `batch_get`, but returns exactly one slot per requested key,
+    /// in the same order as `keys`, with `None` for keys that were not found.
+    async fn batch_get_ordered(&self, keys: Vec<Key>) -> Result<Vec<Option<(Key, Value)>>>;
     async fn batch_delete(&self, keys: Vec<Key>) -> Result<()>;
     async fn batch_scan(&self, scans: Vec<Scan>, each_limit: u32) -> Result<Vec<(Key, Value)>>;
     async fn batch_scan_keys(&self, scans: Vec<Scan>, each_limit: u32) -> Result<Vec<Key>>;
@@ -86,6 +182,31 @@ pub trait DbClient: Send + Sync + Debug + Clone {
         previous_value: Option<Value>,
         new_value: Value,
     ) -> Result<(Option<Value>, bool)>;
+    /// Compares and swaps several keys, trying to swap all of them or none.
+    /// Returns one `(current value, swapped)` pair per operation, in order.
+    ///
+    /// This is not atomic. All our data goes through TiKV's raw API, which
+    /// can't be mixed with its transactions and can only compare and swap
+    /// single keys. So every key is checked first, which fails the batch
+    /// without writing anything if one is stale, and then swapped one at
+    /// a time, undoing earlier swaps if a later one fails. Other clients may
+    /// see the first swaps of a batch that ends up failing. Undoing only
+    /// restores keys still holding the batch's value, but keys that didn't
+    /// exist before have to be deleted, and a write landing between that
+    /// check and the delete is lost. If undoing fails, the batch is left
+    /// partially applied.
+    async fn batch_compare_and_swap_non_atomic(
+        &self,
+        ops: Vec<(Key, Option<Value>, Value)>,
+    ) -> Result<Vec<(Option<Value>, bool)>>;
+
+    /// Runs `f` and only sends its writes if it succeeds, so an error
+    /// returned by `f` leaves the database untouched.
+    ///
+    /// Committing is not atomic: the writes are sent in several requests,
+    /// and a failure part way through leaves the ones sent so far in place.
+    /// Reads aren't isolated either, see [`TransactionContext`].
+    async fn transaction(&self, f: TransactionFn) -> Result<()>;
 }
 
 #[async_trait]
@@ -101,6 +222,8 @@ pub trait DbManager: Send + Sync + Clone {
 pub struct DbClientImpl {
     inner: tikv_client::RawClient,
     inner_atomic: tikv_client::RawClient,
+    keyspace: Keyspace,
+    retry_config: RetryConfig,
 }
 
 impl Debug for DbClientImpl {
@@ -110,16 +233,44 @@ impl Debug for DbClientImpl {
 }
 
 impl DbClientImpl {
-    // TODO: VERY inefficient to create and drop connections continuously.
-    // We need a connection pooling solution here.
-    pub async fn new(endpoints: Vec<TcpPortAddress>) -> Result<Self> {
+    // `RawClient` pools its connections internally, so prefer cloning an
+    // existing client over creating new ones.
+    pub async fn new(
+        endpoints: Vec<TcpPortAddress>,
+        keyspace: Keyspace,
+        retry_config: RetryConfig,
+    ) -> Result<Self> {
         let new = RawClient::new(endpoints).await?;
         Ok(Self {
             inner: new.clone(),
             inner_atomic: new.with_atomic_for_cas(),
+            keyspace,
+            retry_config,
         })
     }
 
+    // Retries operations that failed due to transient errors, such as region
+    // changes while the cluster is rebalancing, with exponential backoff.
+    async fn retry<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = std::result::Result<T, tikv_client::Error>>,
+    {
+        let mut try_count = 0;
+        loop {
+            match op().await {
+                Err(e) if try_count < self.retry_config.max_retries && is_transient(&e) => {
+                    let backoff =
+                        *self.retry_config.initial_backoff * 2u32.saturating_pow(try_count);
+                    debug!("Retrying TiKV operation in {backoff:?} due to: {e}");
+                    sleep(backoff).await;
+                    try_count += 1;
+                }
+                res => return res.map_err(Into::into),
+            }
+        }
+    }
+
     fn get_inner(&self, atomic: bool) -> &RawClient {
         if atomic {
             &self.inner_atomic
@@ -127,6 +278,448 @@ impl DbClientImpl {
             &self.inner
         }
     }
+
+    fn to_key<T: TryFrom<tikv_client::Key, Error = anyhow::Error>>(
+        &self,
+        key: tikv_client::Key,
+    ) -> Result<T> {
+        self.keyspace
+            .strip(key)
+            .and_then(T::try_from)
+            .map_err(Error::InternalErr)
+    }
+
+    fn to_keys<T: TryFrom<tikv_client::Key, Error = anyhow::Error>>(
+        &self,
+        keys: Vec<tikv_client::Key>,
+    ) -> Result<Vec<T>> {
+        keys.into_iter().map(|k| self.to_key(k)).collect()
+    }
+
+    fn kv_pairs_to_tuples(&self, kv_pairs: Vec<KvPair>) -> Result<Vec<(Key, Value)>> {
+        kv_pairs
+            .into_iter()
+            .map(|x| Ok((self.to_key(x.key().clone())?, x.into_value())))
+            .collect()
+    }
+
+    // Returns the last `limit` pairs in the range, last one first.
+    async fn scan_last(&self, range: BoundRange, limit: u32) -> Result<Vec<KvPair>> {
+        let limit = limit as usize;
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        let mut last_pairs = VecDeque::new();
+        while limit > 0 {
+            let pairs = self
+                .retry(|| {
+                    self.inner.scan(
+                        BoundRange::from((start.clone(), end.clone())),
+                        REVERSE_SCAN_PAGE_SIZE,
+                    )
+                })
+                .await?;
+
+            let is_last_page = pairs.len() < REVERSE_SCAN_PAGE_SIZE as usize;
+            if let Some(last) = pairs.last() {
+                start = Bound::Excluded(last.key().clone());
+            }
+
+            for pair in self.drop_expired_pairs(pairs).await? {
+                if last_pairs.len() == limit {
+                    last_pairs.pop_front();
+                }
+                last_pairs.push_back(pair);
+            }
+
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(last_pairs.into_iter().rev().collect())
+    }
+
+    // Fetches a key along with its expiry record, so expired keys can be
+    // hidden before they're reaped.
+    async fn get_with_expiry(&self, key: Blob) -> Result<Option<(Value, Option<SystemTime>)>> {
+        let tikv_key = self.keyspace.key(key.clone())?;
+        let expiry_key = self.keyspace.key(ExpiryKey(key))?;
+        let pairs = self
+            .retry(|| {
+                self.inner
+                    .batch_get(vec![tikv_key.clone(), expiry_key.clone()])
+            })
+            .await?;
+
+        let mut value = None;
+        let mut record = None;
+        for KvPair(k, v) in pairs {
+            if k == tikv_key {
+                value = Some(v);
+            } else {
+                record = Some(ExpiryRecord::decode(&v)?);
+            }
+        }
+
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        Ok(match record.filter(|r| r.applies_to(&value)) {
+            Some(r) if r.expires_at <= SystemTime::now() => None,
+            record => Some((value, record.map(|r| r.expires_at))),
+        })
+    }
+
+    async fn get_unexpired(&self, key: Blob) -> Result<Option<Value>> {
+        Ok(self.get_with_expiry(key).await?.map(|(v, _)| v))
+    }
+
+    fn expiry_key(&self, key: &tikv_client::Key) -> Result<tikv_client::Key> {
+        let key = self
+            .keyspace
+            .strip(key.clone())
+            .map_err(Error::InternalErr)?;
+        self.keyspace.key(ExpiryKey(key.into()))
+    }
+
+    // Returns the expiry records that exist for the keys. Looking them up
+    // is a read, so keys that never had a TTL don't cost an extra write.
+    async fn find_expiry_records(
+        &self,
+        keys: &[tikv_client::Key],
+        is_atomic: bool,
+    ) -> Result<Vec<tikv_client::Key>> {
+        let expiry_keys = keys
+            .iter()
+            .map(|k| self.expiry_key(k))
+            .collect::<Result<Vec<_>>>()?;
+        if expiry_keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let records = self
+            .retry(|| self.get_inner(is_atomic).batch_get(expiry_keys.clone()))
+            .await?;
+        Ok(records.into_iter().map(|KvPair(k, _)| k).collect())
+    }
+
+    async fn delete_expiry_records(
+        &self,
+        expiry_keys: Vec<tikv_client::Key>,
+        is_atomic: bool,
+    ) -> Result<()> {
+        if expiry_keys.is_empty() {
+            return Ok(());
+        }
+
+        self.retry(|| self.get_inner(is_atomic).batch_delete(expiry_keys.clone()))
+            .await
+    }
+
+    // Called after writing or deleting keys without a TTL, so they don't
+    // inherit the expiry of an earlier value.
+    async fn clear_expiry(&self, keys: &[tikv_client::Key], is_atomic: bool) -> Result<()> {
+        let expiry_keys = self.find_expiry_records(keys, is_atomic).await?;
+        self.delete_expiry_records(expiry_keys, is_atomic).await
+    }
+
+    // Same as running `write` and then `clear_expiry`, but looks up the
+    // expiry records while the write is in flight. A record that's still
+    // there for a moment after the write doesn't match the new value, so it
+    // can't hide it or get it reaped.
+    async fn write_without_expiry(
+        &self,
+        keys: &[tikv_client::Key],
+        is_atomic: bool,
+        write: impl Future<Output = Result<()>>,
+    ) -> Result<()> {
+        let (written, expiry_keys) = tokio::join!(write, self.find_expiry_records(keys, is_atomic));
+        written?;
+        self.delete_expiry_records(expiry_keys?, is_atomic).await
+    }
+
+    // Returns the expired records for the keys that haven't been reaped yet
+    async fn expired_records<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a tikv_client::Key>,
+    ) -> Result<HashMap<tikv_client::Key, ExpiryRecord>> {
+        let expiry_keys = keys
+            .map(|k| self.expiry_key(k))
+            .collect::<Result<Vec<_>>>()?;
+        if expiry_keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let records = self
+            .retry(|| self.inner.batch_get(expiry_keys.clone()))
+            .await?;
+
+        let now = SystemTime::now();
+        let mut expired = HashMap::new();
+        for KvPair(expiry_key, record) in records {
+            let record = ExpiryRecord::decode(&record)?;
+            if record.expires_at <= now {
+                let ExpiryKey(key) = self.to_key(expiry_key)?;
+                expired.insert(self.keyspace.key(key)?, record);
+            }
+        }
+        Ok(expired)
+    }
+
+    async fn drop_expired_pairs(&self, pairs: Vec<KvPair>) -> Result<Vec<KvPair>> {
+        let expired = self.expired_records(pairs.iter().map(KvPair::key)).await?;
+        Ok(pairs
+            .into_iter()
+            .filter(|p| !matches!(expired.get(p.key()), Some(r) if r.applies_to(p.value())))
+            .collect())
+    }
+
+    // Without the values, records can't be matched against them, so a key
+    // that was just re-written without a TTL may be skipped until its old
+    // record is cleared.
+    async fn drop_expired_keys(
+        &self,
+        keys: Vec<tikv_client::Key>,
+    ) -> Result<Vec<tikv_client::Key>> {
+        let expired = self.expired_records(keys.iter()).await?;
+        Ok(keys
+            .into_iter()
+            .filter(|k| !expired.contains_key(k))
+            .collect())
+    }
+
+    // Keeps paging until `limit` unexpired pairs are found or the range ends
+    async fn scan_unexpired(&self, range: BoundRange, limit: u32) -> Result<Vec<KvPair>> {
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        let mut found = vec![];
+        while found.len() < limit as usize {
+            let page_size = limit - found.len() as u32;
+            let pairs = self
+                .retry(|| {
+                    self.inner
+                        .scan(BoundRange::from((start.clone(), end.clone())), page_size)
+                })
+                .await?;
+
+            let is_last_page = pairs.len() < page_size as usize;
+            if let Some(last) = pairs.last() {
+                start = Bound::Excluded(last.key().clone());
+            }
+
+            found.extend(self.drop_expired_pairs(pairs).await?);
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(found)
+    }
+
+    // Same as `scan_unexpired`, without the values
+    async fn scan_keys_unexpired(
+        &self,
+        range: BoundRange,
+        limit: u32,
+    ) -> Result<Vec<tikv_client::Key>> {
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        let mut found = vec![];
+        while found.len() < limit as usize {
+            let page_size = limit - found.len() as u32;
+            let keys = self
+                .retry(|| {
+                    self.inner
+                        .scan_keys(BoundRange::from((start.clone(), end.clone())), page_size)
+                })
+                .await?;
+
+            let is_last_page = keys.len() < page_size as usize;
+            if let Some(last) = keys.last() {
+                start = Bound::Excluded(last.clone());
+            }
+
+            found.extend(self.drop_expired_keys(keys).await?);
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(found)
+    }
+
+    // The value and its expiry record go in one batch. Since the record only
+    // applies to the value it was written with, a write that only partly
+    // lands can't expire an older value.
+    async fn put_with_ttl(
+        &self,
+        key: Blob,
+        value: Value,
+        is_atomic: bool,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let client = self.get_inner(is_atomic);
+        let tikv_key = self.keyspace.key(key.clone())?;
+        match ttl {
+            Some(ttl) => {
+                let record = ExpiryRecord::new(SystemTime::now() + ttl, &value);
+                let pairs = vec![
+                    (tikv_key, value),
+                    (self.keyspace.key(ExpiryKey(key))?, record.encode()),
+                ];
+                self.retry(|| client.batch_put(pairs.clone())).await
+            }
+            None => {
+                let write = self.retry(|| client.put(tikv_key.clone(), value.clone()));
+                self.write_without_expiry(&[tikv_key.clone()], is_atomic, write)
+                    .await
+            }
+        }
+    }
+
+    // There's no conditional delete, so check the key still holds the value
+    // with a CAS right before deleting it. A write landing between the two
+    // is still lost.
+    async fn delete_if_unchanged(&self, key: tikv_client::Key, value: Value) -> Result<bool> {
+        let (_, unchanged) = self
+            .inner_atomic
+            .compare_and_swap(key.clone(), Some(value.clone()), value)
+            .await?;
+        if unchanged {
+            self.retry(|| self.inner_atomic.delete(key.clone())).await?;
+        }
+        Ok(unchanged)
+    }
+
+    // Deletes expired values that still match their expiry records, then
+    // the records themselves. Records left behind for values that were
+    // deleted or re-written are removed too.
+    async fn reap(&self, expired: Vec<(tikv_client::Key, KvPair, ExpiryRecord)>) -> Result<()> {
+        let keys = expired
+            .iter()
+            .map(|(k, _, _)| k.clone())
+            .collect::<Vec<_>>();
+        let values = self
+            .retry(|| self.inner.batch_get(keys.clone()))
+            .await?
+            .into_iter()
+            .map(|KvPair(k, v)| (k, v))
+            .collect::<HashMap<_, _>>();
+
+        for (key, KvPair(expiry_key, encoded), record) in expired {
+            let is_current = values.get(&key).filter(|v| record.applies_to(v));
+            if let Some(value) = is_current {
+                if !self.delete_if_unchanged(key, value.clone()).await? {
+                    // Re-written since we read it; the writer takes care
+                    // of the record
+                    continue;
+                }
+            }
+            self.delete_if_unchanged(expiry_key, encoded).await?;
+        }
+        Ok(())
+    }
+
+    async fn reap_expired_keys(&self) -> Result<()> {
+        let range = self.keyspace.range(ExpiryKey::range());
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        loop {
+            let pairs = self
+                .retry(|| {
+                    self.inner.scan(
+                        BoundRange::from((start.clone(), end.clone())),
+                        EXPIRY_PAGE_SIZE,
+                    )
+                })
+                .await?;
+
+            let is_last_page = pairs.len() < EXPIRY_PAGE_SIZE as usize;
+            if let Some(last) = pairs.last() {
+                start = Bound::Excluded(last.key().clone());
+            }
+
+            let now = SystemTime::now();
+            let mut expired = vec![];
+            for pair in pairs {
+                let record = ExpiryRecord::decode(pair.value())?;
+                if record.expires_at <= now {
+                    let ExpiryKey(key) = self.to_key(pair.key().clone())?;
+                    expired.push((self.keyspace.key(key)?, pair, record));
+                }
+            }
+
+            if !expired.is_empty() {
+                self.reap(expired).await?;
+            }
+
+            if is_last_page {
+                return Ok(());
+            }
+        }
+    }
+
+    // Counts keys in the range by paging through them, stopping at `limit`
+    async fn count_in_range(&self, range: BoundRange, limit: u64) -> Result<u64> {
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        let mut count = 0;
+        while count < limit {
+            let page_size = (limit - count).min(COUNT_PAGE_SIZE as u64) as u32;
+            let keys = self
+                .retry(|| {
+                    self.inner
+                        .scan_keys(BoundRange::from((start.clone(), end.clone())), page_size)
+                })
+                .await?;
+
+            let is_last_page = keys.len() < page_size as usize;
+            if let Some(last) = keys.last() {
+                start = Bound::Excluded(last.clone());
+            }
+
+            count += self.drop_expired_keys(keys).await?.len() as u64;
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    // `delete_range` can't tell how many keys it removed, so keys are
+    // listed and deleted a page at a time instead
+    async fn delete_in_range(&self, range: BoundRange) -> Result<u64> {
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        let mut count = 0;
+        loop {
+            let keys = self
+                .retry(|| {
+                    self.inner.scan_keys(
+                        BoundRange::from((start.clone(), end.clone())),
+                        COUNT_PAGE_SIZE,
+                    )
+                })
+                .await?;
+            let Some(last) = keys.last().cloned() else {
+                break;
+            };
+
+            count += keys.len() as u64;
+            let delete = self.retry(|| self.inner.batch_delete(keys.clone()));
+            self.write_without_expiry(&keys, false, delete).await?;
+            start = Bound::Excluded(last);
+        }
+
+        Ok(count)
+    }
 }
 
 #[async_trait]
@@ -138,41 +731,38 @@ impl DbClient for DbClientImpl {
     ) -> Result<()> {
         // TODO: think of something for deleting existing tables
         let existing_tables = self
-            .inner
-            .scan_keys(types::ScanTableList::ByStackID(stack_id), 10000)
-            .await?
+            .retry(|| {
+                self.inner.scan_keys(
+                    self.keyspace
+                        .range(types::ScanTableList::ByStackID(stack_id)),
+                    10000,
+                )
+            })
+            .await?;
+        let existing_tables = self
+            .to_keys::<TableListKey>(existing_tables)?
             .into_iter()
-            .map(|k| k.try_into().map_err(Into::into))
-            .collect::<Result<HashSet<TableListKey>>>()?;
-
-        let mut kvs_add = vec![];
-        let mut kvs_delete = vec![];
-        for (table, is_delete) in table_action_tuples {
-            let k = TableListKey::new(stack_id, table.clone());
-            if !existing_tables.contains(&k) && !*is_delete {
-                kvs_add.push((k, vec![]))
-            } else if existing_tables.contains(&k) && *is_delete {
-                let meta_data_key: tikv_client::Key = k.into();
-                kvs_delete.push(meta_data_key);
-                loop {
-                    let s = Scan::ByTableName(stack_id, table.clone());
-                    let data_keys = self.inner.scan_keys(s, 10000).await?;
-                    if data_keys.is_empty() {
-                        break;
+            .collect::<HashSet<_>>();
+
+        // Tables and their data are added and removed together
+        self.transaction(transaction_fn(move |txn| {
+            Box::pin(async move {
+                for (table, is_delete) in table_action_tuples {
+                    let k = TableListKey::new(stack_id, table.clone());
+                    if !existing_tables.contains(&k) && !*is_delete {
+                        txn.add_table(stack_id, table)?;
+                    } else if existing_tables.contains(&k) && *is_delete {
+                        txn.delete_table(stack_id, table)?;
                     }
-                    self.inner.batch_delete(data_keys).await?;
                 }
-            }
-        }
-
-        self.inner.batch_put(kvs_add).await?;
-        self.inner.batch_delete(kvs_delete.clone()).await?;
-
-        Ok(())
+                Ok(())
+            })
+        }))
+        .await
     }
 
     async fn get_raw(&self, key: Vec<u8>) -> Result<Option<Value>> {
-        Ok(self.inner.get(key).await?)
+        self.get_unexpired(key).await
     }
 
     async fn scan_raw(
@@ -181,56 +771,97 @@ impl DbClient for DbClientImpl {
         upper_exclusive: Vec<u8>,
         limit: u32,
     ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
-        Ok(self
-            .inner
-            .scan(lower_inclusive..upper_exclusive, limit)
+        let range = self
+            .keyspace
+            .range(lower_inclusive.clone()..upper_exclusive.clone());
+        self.scan_unexpired(range, limit)
             .await?
             .into_iter()
-            .map(|kv| (kv.0.into(), kv.1))
-            .collect())
+            .map(|kv| {
+                let key = self.keyspace.strip(kv.0).map_err(Error::InternalErr)?;
+                Ok((key.into(), kv.1))
+            })
+            .collect()
     }
 
-    async fn put_raw(&self, key: Vec<u8>, value: Value, is_atomic: bool) -> Result<()> {
-        Ok(self.get_inner(is_atomic).put(key, value).await?)
+    async fn put_raw(
+        &self,
+        key: Vec<u8>,
+        value: Value,
+        is_atomic: bool,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        self.put_with_ttl(key, value, is_atomic, ttl).await
     }
 
+    // CAS operations aren't retried, since we can't tell whether a failed
+    // attempt was applied or not.
     async fn compare_and_swap_raw(
         &self,
         key: Vec<u8>,
         previous_value: Option<Value>,
         new_value: Value,
     ) -> Result<(Option<Value>, bool)> {
-        Ok(self
+        let key = self.keyspace.key(key)?;
+        let res = self
             .inner_atomic
-            .compare_and_swap(key, previous_value, new_value)
-            .await?)
+            .compare_and_swap(key.clone(), previous_value, new_value)
+            .await?;
+        if res.1 {
+            self.clear_expiry(&[key], true).await?;
+        }
+        Ok(res)
     }
 
     async fn delete_raw(&self, key: Vec<u8>, is_atomic: bool) -> Result<()> {
-        Ok(self.get_inner(is_atomic).delete(key).await?)
+        let key = self.keyspace.key(key)?;
+        let delete = self.retry(|| self.get_inner(is_atomic).delete(key.clone()));
+        self.write_without_expiry(&[key.clone()], is_atomic, delete)
+            .await
     }
 
-    async fn put(&self, key: Key, value: Value, is_atomic: bool) -> Result<()> {
-        let k = TableListKey::new(key.stack_id, key.table_name.clone());
-        match self.inner.get(k).await? {
-            Some(_) => self
-                .get_inner(is_atomic)
-                .put(key, value)
-                .await
-                .map_err(Into::into),
+    async fn put(
+        &self,
+        key: Key,
+        value: Value,
+        is_atomic: bool,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        // The reserved secrets table is never registered with the stack's
+        // tables, and secrets can be set before the stack is deployed
+        if key.table_name == TableName::secrets() {
+            return self.put_with_ttl(key.into(), value, is_atomic, ttl).await;
+        }
+
+        let k = self
+            .keyspace
+            .key(TableListKey::new(key.stack_id, key.table_name.clone()))?;
+        match self.retry(|| self.inner.get(k.clone())).await? {
+            Some(_) => self.put_with_ttl(key.into(), value, is_atomic, ttl).await,
             None => Err(Error::StackIdOrTableDoseNotExist(key)),
         }
     }
 
     async fn get(&self, key: Key) -> Result<Option<Value>> {
-        self.inner.get(key).await.map_err(Into::into)
+        self.get_unexpired(key.into()).await
+    }
+
+    async fn get_ttl(&self, key: Key) -> Result<Option<Duration>> {
+        Ok(match self.get_with_expiry(key.into()).await? {
+            Some((_, Some(expires_at))) => Some(
+                expires_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        })
     }
 
     async fn delete(&self, key: Key, is_atomic: bool) -> Result<()> {
-        self.get_inner(is_atomic)
-            .delete(key)
+        let key = self.keyspace.key(key)?;
+        let delete = self.retry(|| self.get_inner(is_atomic).delete(key.clone()));
+        self.write_without_expiry(&[key.clone()], is_atomic, delete)
             .await
-            .map_err(Into::into)
     }
 
     async fn delete_by_prefix(
@@ -238,29 +869,49 @@ impl DbClient for DbClientImpl {
         stack_id: StackID,
         table_name: TableName,
         prefix_inner_key: Blob,
-    ) -> Result<()> {
+    ) -> Result<u64> {
         let scan = Scan::ByInnerKeyPrefix(stack_id, table_name, prefix_inner_key);
-        self.inner.delete_range(scan).await.map_err(Into::into)
+        self.delete_in_range(self.keyspace.range(scan)).await
     }
 
     // TODO change to delete_table and delete table_name from metadata too
-    async fn clear_table(&self, stack_id: StackID, table_name: TableName) -> Result<()> {
+    async fn clear_table(&self, stack_id: StackID, table_name: TableName) -> Result<u64> {
         let scan = Scan::ByTableName(stack_id, table_name);
-        self.inner.delete_range(scan).await.map_err(Into::into)
+        self.delete_in_range(self.keyspace.range(scan)).await
     }
 
-    async fn scan(&self, scan: Scan, limit: u32) -> Result<Vec<(Key, Value)>> {
-        kv_pairs_to_tuples(self.inner.scan(scan, limit).await?)
+    async fn scan(&self, scan: Scan, limit: u32, reverse: bool) -> Result<Vec<(Key, Value)>> {
+        let range = self.keyspace.range(scan);
+        if reverse {
+            return self.kv_pairs_to_tuples(self.scan_last(range, limit).await?);
+        }
+
+        self.kv_pairs_to_tuples(self.scan_unexpired(range, limit).await?)
     }
 
-    async fn scan_keys(&self, scan: Scan, limit: u32) -> Result<Vec<Key>> {
-        self.inner
-            .scan_keys(scan, limit)
-            .await?
-            .into_iter()
-            .map(|k| k.try_into().map_err(Error::InternalErr))
-            .collect::<Result<Vec<Key>>>()
-            .map_err(Into::into)
+    async fn scan_keys(&self, scan: Scan, limit: u32, reverse: bool) -> Result<Vec<Key>> {
+        let range = self.keyspace.range(scan);
+        if reverse {
+            let pairs = self.scan_last(range, limit).await?;
+            return self.to_keys(pairs.into_iter().map(|p| p.key().clone()).collect());
+        }
+
+        self.to_keys(self.scan_keys_unexpired(range, limit).await?)
+    }
+
+    async fn count_by_prefix(
+        &self,
+        stack_id: StackID,
+        table_name: TableName,
+        prefix_inner_key: Blob,
+        limit: u64,
+    ) -> Result<u64> {
+        let scan = Scan::ByInnerKeyPrefix(stack_id, table_name, prefix_inner_key);
+        self.count(scan, limit).await
+    }
+
+    async fn count(&self, scan: Scan, limit: u64) -> Result<u64> {
+        self.count_in_range(self.keyspace.range(scan), limit).await
     }
 
     async fn table_list(
@@ -272,145 +923,331 @@ impl DbClient for DbClientImpl {
             Some(prefix) => ScanTableList::ByTableName(stack_id, prefix),
             None => ScanTableList::ByStackID(stack_id),
         };
-        self.inner
-            .scan_keys(scan, 128)
-            .await?
+        let keys = self
+            .retry(|| self.inner.scan_keys(self.keyspace.range(scan.clone()), 128))
+            .await?;
+        Ok(self
+            .to_keys::<TableListKey>(keys)?
             .into_iter()
-            .map(|k| {
-                TableListKey::try_from(k)
-                    .map(|x| x.table_name)
-                    .map_err(Error::InternalErr)
-            })
-            .collect::<Result<Vec<TableName>>>()
-            .map_err(Into::into)
+            .map(|x| x.table_name)
+            .collect())
     }
 
     async fn stack_id_list(&self) -> Result<Vec<StackID>> {
-        self.inner
-            .scan_keys(ScanTableList::Whole, 32)
-            .await?
-            .into_iter()
-            .map(|k| {
-                TableListKey::try_from(k)
-                    .map(|x| x.stack_id)
-                    .map_err(Error::InternalErr)
+        let keys = self
+            .retry(|| {
+                self.inner
+                    .scan_keys(self.keyspace.range(ScanTableList::Whole), 32)
             })
-            .collect::<Result<Vec<StackID>>>()
-            .map_err(Into::into)
+            .await?;
+        Ok(self
+            .to_keys::<TableListKey>(keys)?
+            .into_iter()
+            .map(|x| x.stack_id)
+            .collect())
     }
 
     async fn batch_delete(&self, keys: Vec<Key>) -> Result<()> {
-        self.inner.batch_delete(keys).await.map_err(Into::into)
+        let keys = keys
+            .into_iter()
+            .map(|k| self.keyspace.key(k))
+            .collect::<Result<Vec<_>>>()?;
+        let delete = self.retry(|| self.inner.batch_delete(keys.clone()));
+        self.write_without_expiry(&keys, false, delete).await
     }
 
     async fn batch_get(&self, keys: Vec<Key>) -> Result<Vec<(Key, Value)>> {
-        kv_pairs_to_tuples(self.inner.batch_get(keys).await?)
+        let keys = keys
+            .into_iter()
+            .map(|k| self.keyspace.key(k))
+            .collect::<Result<Vec<_>>>()?;
+        let pairs = self.retry(|| self.inner.batch_get(keys.clone())).await?;
+        self.kv_pairs_to_tuples(self.drop_expired_pairs(pairs).await?)
+    }
+
+    async fn batch_get_ordered(&self, keys: Vec<Key>) -> Result<Vec<Option<(Key, Value)>>> {
+        let found = self
+            .batch_get(keys.clone())
+            .await?
+            .into_iter()
+            .collect::<HashMap<Key, Value>>();
+
+        Ok(keys
+            .into_iter()
+            .map(|k| found.get(&k).cloned().map(|v| (k, v)))
+            .collect())
     }
 
     async fn batch_put(&self, pairs: Vec<(Key, Value)>, is_atomic: bool) -> Result<()> {
-        self.get_inner(is_atomic)
-            .batch_put(pairs)
-            .await
-            .map_err(Into::into)
+        let pairs = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((self.keyspace.key(k)?, v)))
+            .collect::<Result<Vec<_>>>()?;
+        let keys = pairs.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>();
+        let write = self.retry(|| self.get_inner(is_atomic).batch_put(pairs.clone()));
+        self.write_without_expiry(&keys, is_atomic, write).await
     }
 
     async fn batch_scan(&self, scans: Vec<Scan>, each_limit: u32) -> Result<Vec<(Key, Value)>> {
-        kv_pairs_to_tuples(self.inner.batch_scan(scans, each_limit).await?)
+        let pairs = self
+            .retry(|| {
+                self.inner.batch_scan(
+                    scans.iter().map(|s| self.keyspace.range(s.clone())),
+                    each_limit,
+                )
+            })
+            .await?;
+        self.kv_pairs_to_tuples(self.drop_expired_pairs(pairs).await?)
     }
 
     async fn batch_scan_keys(&self, scans: Vec<Scan>, each_limit: u32) -> Result<Vec<Key>> {
-        self.inner
-            .batch_scan_keys(scans, each_limit)
-            .await?
-            .into_iter()
-            .map(|k| k.try_into().map_err(Error::InternalErr))
-            .collect::<Result<Vec<Key>>>()
-            .map_err(Into::into)
+        let keys = self
+            .retry(|| {
+                self.inner.batch_scan_keys(
+                    scans.iter().map(|s| self.keyspace.range(s.clone())),
+                    each_limit,
+                )
+            })
+            .await?;
+        self.to_keys(self.drop_expired_keys(keys).await?)
     }
 
+    // See `compare_and_swap_raw` for why this isn't retried.
     async fn compare_and_swap(
         &self,
         key: Key,
         previous_value: Option<Value>,
         new_value: Value,
     ) -> Result<(Option<Value>, bool)> {
-        self.inner
-            .with_atomic_for_cas()
-            .compare_and_swap(key, previous_value, new_value)
-            .await
-            .map_err(Into::into)
+        let key = self.keyspace.key(key)?;
+        let res = self
+            .inner_atomic
+            .compare_and_swap(key.clone(), previous_value, new_value)
+            .await?;
+        if res.1 {
+            self.clear_expiry(&[key], true).await?;
+        }
+        Ok(res)
+    }
+
+    async fn batch_compare_and_swap_non_atomic(
+        &self,
+        ops: Vec<(Key, Option<Value>, Value)>,
+    ) -> Result<Vec<(Option<Value>, bool)>> {
+        let keys = ops.iter().map(|(k, _, _)| k.clone()).collect::<Vec<_>>();
+        if keys.iter().collect::<HashSet<_>>().len() != keys.len() {
+            return Err(Error::InternalErr(anyhow::anyhow!(
+                "Keys can only appear once in a batch compare-and-swap"
+            )));
+        }
+        let tikv_keys = keys
+            .iter()
+            .map(|k| self.keyspace.key(k.clone()))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Checking all keys first means a stale value fails the batch before
+        // anything is written
+        let mut current = self
+            .batch_get_ordered(keys)
+            .await?
+            .into_iter()
+            .map(|pair| pair.map(|(_, v)| v))
+            .collect::<Vec<_>>();
+        let not_swapped = |current: Vec<Option<Value>>| current.into_iter().map(|v| (v, false));
+
+        if ops
+            .iter()
+            .zip(&current)
+            .any(|((_, previous_value, _), value)| previous_value != value)
+        {
+            return Ok(not_swapped(current).collect());
+        }
+
+        // Values may still change between the check and the swaps
+        let mut swapped_count = 0;
+        let mut failure = None;
+        for (key, (_, previous_value, new_value)) in tikv_keys.iter().zip(&ops) {
+            let res = self
+                .inner_atomic
+                .compare_and_swap(key.clone(), previous_value.clone(), new_value.clone())
+                .await;
+            match res {
+                Ok((_, true)) => swapped_count += 1,
+                Ok((value, false)) => {
+                    current[swapped_count] = value;
+                    break;
+                }
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if swapped_count == ops.len() {
+            self.clear_expiry(&tikv_keys, true).await?;
+            return Ok(current.into_iter().map(|v| (v, true)).collect());
+        }
+
+        for (key, (_, previous_value, new_value)) in
+            tikv_keys.into_iter().zip(&ops).take(swapped_count).rev()
+        {
+            let restored = match previous_value {
+                Some(previous_value) => self
+                    .inner_atomic
+                    .compare_and_swap(key, Some(new_value.clone()), previous_value.clone())
+                    .await
+                    .map(|(_, swapped)| swapped)
+                    .map_err(Error::from),
+                None => self.delete_if_unchanged(key, new_value.clone()).await,
+            };
+            match restored {
+                Ok(true) => (),
+                Ok(false) => warn!("Key changed before a failed batch compare-and-swap undid it"),
+                Err(e) => warn!("Failed to undo a failed batch compare-and-swap: {e}"),
+            }
+        }
+
+        match failure {
+            Some(e) => Err(e.into()),
+            None => Ok(not_swapped(current).collect()),
+        }
+    }
+
+    async fn transaction(&self, f: TransactionFn) -> Result<()> {
+        let mut txn = TransactionContext::new(self.clone());
+        // Nothing is written before this succeeds, so bailing out here is
+        // all it takes to roll back
+        f(&mut txn).await?;
+        txn.commit().await
     }
 }
 
 #[derive(Clone)]
 struct DbManagerImpl {
-    endpoints: Vec<TcpPortAddress>,
+    // Shared by all clients handed out by the manager
+    client: DbClientImpl,
+    reaper: Arc<JoinHandle<()>>,
 }
 
 async fn ensure_cluster_healthy(
     endpoints: &Vec<TcpPortAddress>,
-    max_try_count: u32,
+    config: &HealthCheckConfig,
 ) -> anyhow::Result<()> {
     #[tailcall::tailcall]
     async fn helper(
         endpoints: &Vec<TcpPortAddress>,
         try_count: u32,
-        max_try_count: u32,
+        config: &HealthCheckConfig,
     ) -> anyhow::Result<()> {
         // This call will not succeed unless the cluster is reachable and at least
         // N/2+1 PD nodes are already clustered.
 
         let check_cluster_health = || async {
-            let client = DbClientImpl::new(endpoints.clone()).await?;
+            let client = DbClientImpl::new(
+                endpoints.clone(),
+                Keyspace::default(),
+                RetryConfig::default(),
+            )
+            .await?;
             client.inner.get(vec![]).await?;
             Result::Ok(())
         };
 
         match check_cluster_health().await {
-            Err(e) if try_count < max_try_count => {
+            Err(e) if try_count < config.max_tries => {
                 warn!("Failed to reach TiKV cluster due to: {e:?}");
-                sleep(Duration::from_millis(
-                    (1.5_f64.powf(try_count as f64) * 1000.0).round() as u64,
-                ))
-                .await;
-                helper(endpoints, try_count + 1, max_try_count)
+                sleep(config.delay(try_count)).await;
+                helper(endpoints, try_count + 1, config)
             }
             Err(e) => bail!(e),
             Ok(_) => Ok(()),
         }
     }
 
-    helper(endpoints, 0, max_try_count).await
+    helper(endpoints, 0, config).await
 }
 
 pub async fn start(db_config: DbConfig) -> anyhow::Result<Box<dyn DbManager>> {
     let endpoints = db_config.pd_addresses;
-    ensure_cluster_healthy(&endpoints, 5).await?;
-    Ok(Box::new(DbManagerImpl { endpoints }))
+    let keyspace = Keyspace::new(&db_config.keyspace_prefix)?;
+    let health_check = db_config.health_check.unwrap_or_default();
+    ensure_cluster_healthy(&endpoints, &health_check).await?;
+    let client = DbClientImpl::new(endpoints, keyspace, db_config.retry).await?;
+    let reaper = Arc::new(tokio::spawn(reap_expired_keys(client.clone())));
+    Ok(Box::new(DbManagerImpl { client, reaper }))
+}
+
+async fn reap_expired_keys(client: DbClientImpl) {
+    loop {
+        sleep(EXPIRED_KEYS_REAP_INTERVAL).await;
+        if let Err(e) = client.reap_expired_keys().await {
+            warn!("Failed to reap expired keys: {e:?}");
+        }
+    }
+}
+
+// Stored under a key's `ExpiryKey`. The record only applies while the key
+// holds the value it was written with, so a value that's re-written without
+// a TTL is never hidden or reaped while its old record is being cleared.
+struct ExpiryRecord {
+    expires_at: SystemTime,
+    // Missing from records written before values were fingerprinted
+    fingerprint: Option<[u8; 32]>,
+}
+
+impl ExpiryRecord {
+    fn new(expires_at: SystemTime, value: &[u8]) -> Self {
+        Self {
+            expires_at,
+            fingerprint: Some(Sha256::digest(value).into()),
+        }
+    }
+
+    fn applies_to(&self, value: &[u8]) -> bool {
+        self.fingerprint
+            .map_or(true, |f| f == <[u8; 32]>::from(Sha256::digest(value)))
+    }
+
+    fn encode(&self) -> Value {
+        let millis = self
+            .expires_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut encoded = millis.to_be_bytes().to_vec();
+        if let Some(fingerprint) = self.fingerprint {
+            encoded.extend_from_slice(&fingerprint);
+        }
+        encoded
+    }
+
+    fn decode(value: &[u8]) -> Result<Self> {
+        let malformed = || Error::InternalErr(anyhow::anyhow!("Malformed expiry record"));
+        if value.len() < 8 {
+            return Err(malformed());
+        }
+        let (millis, fingerprint) = value.split_at(8);
+        let millis = u64::from_be_bytes(millis.try_into().map_err(|_| malformed())?);
+        let fingerprint = match fingerprint {
+            [] => None,
+            f => Some(<[u8; 32]>::try_from(f).map_err(|_| malformed())?),
+        };
+        Ok(Self {
+            expires_at: UNIX_EPOCH + Duration::from_millis(millis),
+            fingerprint,
+        })
+    }
 }
 
 #[async_trait]
 impl DbManager for DbManagerImpl {
     async fn make_client(&self) -> anyhow::Result<Box<dyn DbClient>> {
-        Ok(Box::new(DbClientImpl::new(self.endpoints.clone()).await?))
+        Ok(Box::new(self.client.clone()))
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
+        self.reaper.abort();
         Ok(())
     }
 }
-
-fn kv_pairs_to_tuples(kv_pairs: Vec<KvPair>) -> Result<Vec<(Key, Value)>> {
-    let kvpair_to_tuple = |x: KvPair| {
-        Ok((
-            x.key().clone().try_into().map_err(Error::InternalErr)?,
-            x.into_value(),
-        ))
-    };
-
-    kv_pairs
-        .into_iter()
-        .map(kvpair_to_tuple)
-        .collect::<Result<Vec<(Key, Value)>>>()
-        .map_err(Into::into)
-}
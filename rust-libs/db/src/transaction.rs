@@ -0,0 +1,164 @@
+use std::{collections::BTreeMap, future::Future, ops::RangeBounds, pin::Pin};
+
+use mu_stack::StackID;
+use tikv_client::{BoundRange, Key as TikvKey, Value};
+
+use crate::{
+    error::{Error, Result},
+    types::{Key, Scan, TableListKey, TableName},
+    DbClientImpl,
+};
+
+pub type TransactionFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// The body of a transaction, see [`crate::DbClient::transaction`].
+pub type TransactionFn =
+    Box<dyn for<'a> FnOnce(&'a mut TransactionContext) -> TransactionFuture<'a> + Send>;
+
+/// Boxes a closure into a [`TransactionFn`]. Closures passed straight to
+/// `Box::new` don't get the signature a transaction body needs, e.g.
+/// `transaction_fn(|txn| Box::pin(async move { txn.delete(key); Ok(()) }))`.
+pub fn transaction_fn<F>(f: F) -> TransactionFn
+where
+    F: for<'a> FnOnce(&'a mut TransactionContext) -> TransactionFuture<'a> + Send + 'static,
+{
+    Box::new(f)
+}
+
+/// Collects the writes of a transaction for [`crate::DbClient::transaction`]. Reads see the transaction's own
+/// writes on top of what's currently stored.
+///
+/// TiKV doesn't allow mixing its raw and transactional APIs on the same
+/// keys, and all our data goes through the raw API, so writes are buffered
+/// here and only sent to TiKV once the transaction's body succeeds. That
+/// leaves two gaps compared to a real transaction:
+/// * Reads aren't isolated. Nothing checks whether the keys read were
+///   changed by someone else before committing, so concurrent writes to
+///   them are overwritten.
+/// * Committing takes several requests, so a failure part way through it
+///   leaves the writes sent so far in place.
+pub struct TransactionContext {
+    client: DbClientImpl,
+    cleared_ranges: Vec<BoundRange>,
+    // `None` marks a deleted key
+    writes: BTreeMap<TikvKey, Option<Value>>,
+}
+
+impl TransactionContext {
+    pub(crate) fn new(client: DbClientImpl) -> Self {
+        Self {
+            client,
+            cleared_ranges: vec![],
+            writes: BTreeMap::new(),
+        }
+    }
+
+    pub async fn get(&self, key: Key) -> Result<Option<Value>> {
+        self.get_encoded(self.client.keyspace.key(key)?).await
+    }
+
+    /// Fails if the key's table doesn't exist, same as [`crate::DbClient::put`].
+    pub async fn put(&mut self, key: Key, value: Value) -> Result<()> {
+        let table_list_key = TableListKey::new(key.stack_id, key.table_name.clone());
+        if self
+            .get_encoded(self.client.keyspace.key(table_list_key)?)
+            .await?
+            .is_none()
+        {
+            return Err(Error::StackIdOrTableDoseNotExist(key));
+        }
+
+        self.put_encoded(self.client.keyspace.key(key)?, value);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: Key) -> Result<()> {
+        self.delete_encoded(self.client.keyspace.key(key)?);
+        Ok(())
+    }
+
+    pub fn clear_table(&mut self, stack_id: StackID, table_name: TableName) {
+        let range = self
+            .client
+            .keyspace
+            .range(Scan::ByTableName(stack_id, table_name));
+        self.writes.retain(|k, _| !range.contains(k));
+        self.cleared_ranges.push(range);
+    }
+
+    pub(crate) fn add_table(&mut self, stack_id: StackID, table_name: TableName) -> Result<()> {
+        let key = TableListKey::new(stack_id, table_name);
+        self.put_encoded(self.client.keyspace.key(key)?, vec![]);
+        Ok(())
+    }
+
+    pub(crate) fn delete_table(&mut self, stack_id: StackID, table_name: TableName) -> Result<()> {
+        let key = TableListKey::new(stack_id, table_name.clone());
+        self.delete_encoded(self.client.keyspace.key(key)?);
+        self.clear_table(stack_id, table_name);
+        Ok(())
+    }
+
+    fn put_encoded(&mut self, key: TikvKey, value: Value) {
+        self.writes.insert(key, Some(value));
+    }
+
+    fn delete_encoded(&mut self, key: TikvKey) {
+        self.writes.insert(key, None);
+    }
+
+    async fn get_encoded(&self, key: TikvKey) -> Result<Option<Value>> {
+        if let Some(value) = self.writes.get(&key) {
+            return Ok(value.clone());
+        }
+
+        if self.cleared_ranges.iter().any(|r| r.contains(&key)) {
+            return Ok(None);
+        }
+
+        let key = self
+            .client
+            .keyspace
+            .strip(key)
+            .map_err(Error::InternalErr)?;
+        self.client.get_unexpired(key.into()).await
+    }
+
+    // Cleared ranges go first, since later writes may land inside them.
+    // Each step is retried on its own, and an error leaves the earlier steps
+    // applied.
+    pub(crate) async fn commit(self) -> Result<()> {
+        let client = self.client;
+
+        for range in self.cleared_ranges {
+            client
+                .retry(|| client.inner.delete_range(range.clone()))
+                .await?;
+        }
+
+        let written = self.writes.keys().cloned().collect::<Vec<_>>();
+        let (puts, deletes): (Vec<_>, Vec<_>) = self
+            .writes
+            .into_iter()
+            .partition(|(_, value)| value.is_some());
+
+        if !deletes.is_empty() {
+            let keys = deletes.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+            client
+                .retry(|| client.inner.batch_delete(keys.clone()))
+                .await?;
+        }
+
+        if !puts.is_empty() {
+            let pairs = puts
+                .into_iter()
+                .filter_map(|(k, v)| v.map(|v| (k, v)))
+                .collect::<Vec<_>>();
+            client
+                .retry(|| client.inner.batch_put(pairs.clone()))
+                .await?;
+        }
+
+        client.clear_expiry(&written, false).await
+    }
+}
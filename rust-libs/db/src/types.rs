@@ -3,10 +3,12 @@ use bytes::BufMut;
 use mu_stack::StackID;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
-use std::ops::Deref;
+use std::ops::{Bound, Deref, RangeBounds};
 use tikv_client::{BoundRange, Key as TikvKey};
 
 const TABLE_LIST_METADATA: &str = "__tlm";
+const SECRETS_TABLE: &str = "__secrets";
+const EXPIRY_METADATA: &str = "__ttl";
 
 pub type Blob = Vec<u8>;
 
@@ -44,6 +46,99 @@ fn three_chunk_try_from_tikv_key(value: Blob) -> Result<(Blob, Blob, Blob)> {
     Ok((a, b, c))
 }
 
+/// A namespace prepended to every key, so multiple independent mu deployments
+/// can share one TiKV cluster. The empty keyspace leaves keys untouched, but
+/// its ranges stop short of the keys of every other keyspace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keyspace(Blob);
+
+// Keys of named keyspaces start with this byte. Keys of the empty keyspace
+// never do: they start with either a chunk length, and no first chunk is
+// this long, or a zero byte for internal keys.
+const NAMED_KEYSPACE_MARKER: u8 = u8::MAX;
+
+impl Keyspace {
+    pub fn new(prefix: &[u8]) -> Result<Self> {
+        if prefix.is_empty() {
+            return Ok(Self::default());
+        }
+
+        if prefix.len() > u8::MAX as usize {
+            bail!("keyspace prefix can't exceed 255 bytes")
+        }
+
+        // Length-prefixed, so no keyspace is a prefix of another one
+        let mut x = Vec::with_capacity(prefix.len() + 2);
+        x.push(NAMED_KEYSPACE_MARKER);
+        x.push(prefix.len() as u8);
+        x.put_slice(prefix);
+        Ok(Self(x))
+    }
+
+    /// Fails for raw keys of the empty keyspace that would fall into
+    /// a named keyspace.
+    pub fn key(&self, key: impl Into<TikvKey>) -> crate::error::Result<TikvKey> {
+        let key = key.into();
+        if self.0.is_empty() {
+            if Blob::from(key.clone()).first() == Some(&NAMED_KEYSPACE_MARKER) {
+                return Err(crate::error::Error::InvalidKey(key.into()));
+            }
+            return Ok(key);
+        }
+
+        Ok(self.prefixed(key))
+    }
+
+    fn prefixed(&self, key: TikvKey) -> TikvKey {
+        let mut x = self.0.clone();
+        x.put_slice(&Blob::from(key));
+        x.into()
+    }
+
+    pub fn strip(&self, key: TikvKey) -> Result<TikvKey> {
+        let mut key = Blob::from(key);
+        if !key.starts_with(&self.0) {
+            bail!("Key doesn't belong to this keyspace")
+        }
+        Ok(key.split_off(self.0.len()).into())
+    }
+
+    pub fn range(&self, range: impl Into<BoundRange>) -> BoundRange {
+        let range = range.into();
+        if self.0.is_empty() {
+            return Self::exclude_named_keyspaces(range);
+        }
+
+        let start = match range.start_bound() {
+            Bound::Included(k) => Bound::Included(self.prefixed(k.clone())),
+            Bound::Excluded(k) => Bound::Excluded(self.prefixed(k.clone())),
+            Bound::Unbounded => Bound::Included(self.0.clone().into()),
+        };
+
+        let whole_keyspace = subset_range(self.0.clone());
+        let end = match range.end_bound() {
+            Bound::Included(k) => Bound::Included(self.prefixed(k.clone())),
+            Bound::Excluded(k) => Bound::Excluded(self.prefixed(k.clone())),
+            Bound::Unbounded => whole_keyspace.end_bound().cloned(),
+        };
+
+        (start, end).into()
+    }
+
+    fn exclude_named_keyspaces(range: BoundRange) -> BoundRange {
+        let first_named_key: TikvKey = vec![NAMED_KEYSPACE_MARKER].into();
+        let end = match range.end_bound() {
+            Bound::Included(k) | Bound::Excluded(k) if *k >= first_named_key => {
+                Bound::Excluded(first_named_key)
+            }
+            Bound::Unbounded => Bound::Excluded(first_named_key),
+            end => end.cloned(),
+        };
+
+        (range.start_bound().cloned(), end).into()
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct TableListKey {
     pub stack_id: StackID,
@@ -84,6 +179,40 @@ impl TryFrom<TikvKey> for TableListKey {
     }
 }
 
+/// Records when a key written with a TTL expires. Wraps the encoded key
+/// (without the keyspace), so it works for both `Key`s and raw keys.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ExpiryKey(pub Blob);
+
+impl ExpiryKey {
+    pub fn range() -> BoundRange {
+        prefixed_by_a_chunk_bound_range(EXPIRY_METADATA.as_bytes())
+    }
+}
+
+impl From<ExpiryKey> for TikvKey {
+    fn from(k: ExpiryKey) -> Self {
+        let first = EXPIRY_METADATA.as_bytes();
+        let mut x: Blob = Vec::with_capacity(first.len() + k.0.len() + 1);
+        x.push(first.len() as u8);
+        x.put_slice(first);
+        x.put_slice(&k.0);
+        x.into()
+    }
+}
+
+impl TryFrom<TikvKey> for ExpiryKey {
+    type Error = Error;
+    fn try_from(value: TikvKey) -> Result<Self> {
+        let mut x = Blob::from(value);
+        let first = EXPIRY_METADATA.as_bytes();
+        if x.first() != Some(&(first.len() as u8)) || !x[1..].starts_with(first) {
+            bail!("Can't deserialize ExpiryKey as it doesn't begin with {EXPIRY_METADATA}")
+        }
+        Ok(Self(x.split_off(first.len() + 1)))
+    }
+}
+
 fn prefixed_by_a_chunk_bound_range(chunk: &[u8]) -> BoundRange {
     let mut buffer = Vec::with_capacity(chunk.len() + 1);
     buffer.push(chunk.len().try_into().unwrap());
@@ -147,6 +276,13 @@ fn subset_range(from: Blob) -> BoundRange {
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TableName(String);
 
+impl TableName {
+    /// The reserved table holding a stack's secrets, see [`Key::secret`].
+    pub fn secrets() -> Self {
+        Self(SECRETS_TABLE.into())
+    }
+}
+
 impl From<TableName> for String {
     fn from(t: TableName) -> Self {
         t.0
@@ -201,13 +337,25 @@ impl Deref for DeleteTable {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Key {
     pub stack_id: StackID,
     pub table_name: TableName,
     pub inner_key: Blob,
 }
 
+impl Key {
+    /// Key of a stack secret, stored in a reserved per-stack table that is
+    /// written out-of-band by the stack's owner.
+    pub fn secret(stack_id: StackID, name: &str) -> Self {
+        Self {
+            stack_id,
+            table_name: TableName::secrets(),
+            inner_key: name.as_bytes().to_vec(),
+        }
+    }
+}
+
 impl From<Key> for Blob {
     fn from(k: Key) -> Self {
         let first = k.stack_id.to_bytes();
@@ -327,6 +475,87 @@ mod test {
         assert_eq!(res.end_bound(), Bound::Unbounded);
     }
 
+    #[test]
+    fn keyspace_prefixes_and_strips_keys() {
+        let keyspace = Keyspace::new(&[7, 7]).unwrap();
+        let key = keyspace.key(vec![1, 2, 3]).unwrap();
+        assert_eq!(Blob::from(key.clone()), vec![255, 2, 7, 7, 1, 2, 3]);
+        assert_eq!(Blob::from(keyspace.strip(key).unwrap()), vec![1, 2, 3]);
+        assert!(keyspace.strip(vec![1, 2, 3].into()).is_err());
+
+        let empty = Keyspace::new(&[]).unwrap();
+        assert_eq!(Blob::from(empty.key(vec![1, 2, 3]).unwrap()), vec![1, 2, 3]);
+
+        // Raw keys can't reach into named keyspaces
+        assert!(empty.key(vec![255, 1, 7]).is_err());
+        assert!(keyspace.key(vec![255, 1, 7]).is_ok());
+    }
+
+    #[test]
+    fn keyspace_range_stays_within_keyspace() {
+        let keyspace = Keyspace::new(&[7]).unwrap();
+
+        let range = keyspace.range(subset_range(vec![0, 1]));
+        assert_eq!(
+            range.start_bound(),
+            Bound::Included(&vec![255, 1, 7, 0, 1].into())
+        );
+        assert_eq!(
+            range.end_bound(),
+            Bound::Excluded(&vec![255, 1, 7, 0, 2].into())
+        );
+
+        let range = keyspace.range(subset_range(vec![]));
+        assert_eq!(
+            range.start_bound(),
+            Bound::Included(&vec![255, 1, 7].into())
+        );
+        assert_eq!(range.end_bound(), Bound::Excluded(&vec![255, 1, 8].into()));
+    }
+
+    #[test]
+    fn empty_keyspace_is_isolated_from_named_keyspaces() {
+        let empty = Keyspace::default();
+        let named = Keyspace::new(&[7]).unwrap();
+        let stack_id = StackID::SolanaPublicKey([3; 32]);
+
+        let named_key = named
+            .key(Key {
+                stack_id,
+                table_name: "table".try_into().unwrap(),
+                inner_key: vec![1],
+            })
+            .unwrap();
+        let empty_key = empty
+            .key(Key {
+                stack_id,
+                table_name: "table".try_into().unwrap(),
+                inner_key: vec![1],
+            })
+            .unwrap();
+
+        let everything = empty.range(subset_range(vec![]));
+        assert!(everything.contains(&empty_key));
+        assert!(!everything.contains(&named_key));
+
+        let up_to_the_end =
+            empty.range((Bound::Included(TikvKey::from(vec![0])), Bound::Unbounded));
+        assert!(!up_to_the_end.contains(&named_key));
+
+        // Ranges that end before the named keyspaces are left alone
+        let range = empty.range(subset_range(vec![0, 1]));
+        assert_eq!(range.end_bound(), Bound::Excluded(&vec![0, 2].into()));
+    }
+
+    #[test]
+    fn secret_key_round_trips_through_reserved_table() {
+        let stack_id = StackID::SolanaPublicKey([3; 32]);
+        let key = Key::secret(stack_id, "API_TOKEN");
+        assert_eq!(key.table_name, TableName::secrets());
+        assert_eq!(key.inner_key, b"API_TOKEN".to_vec());
+        assert_eq!(Key::try_from(Blob::from(key.clone())).unwrap(), key);
+    }
+
     #[test]
     fn test_prefixed_by_two_chunk_bound_range() {
         let scan = prefixed_by_two_chunk_bound_range(&[0, 1], &[12, 12, 12]);
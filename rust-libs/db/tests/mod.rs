@@ -10,6 +10,7 @@ use serial_test::serial;
 use std::fs;
 use std::net::IpAddr;
 use std::path::Path;
+use std::time::Duration;
 
 const TEST_DATA_DIR: &str = "tests/mudb/test_data";
 
@@ -35,16 +36,16 @@ fn table_list() -> [TableName; 2] {
 }
 
 async fn seed(db: &dyn DbClient, keys: [Key; 4], is_atomic: bool) {
-    db.put(keys[0].clone(), values()[0].clone(), is_atomic)
+    db.put(keys[0].clone(), values()[0].clone(), is_atomic, None)
         .await
         .unwrap();
-    db.put(keys[1].clone(), values()[1].clone(), is_atomic)
+    db.put(keys[1].clone(), values()[1].clone(), is_atomic, None)
         .await
         .unwrap();
-    db.put(keys[2].clone(), values()[2].clone(), is_atomic)
+    db.put(keys[2].clone(), values()[2].clone(), is_atomic, None)
         .await
         .unwrap();
-    db.put(keys[3].clone(), values()[3].clone(), is_atomic)
+    db.put(keys[3].clone(), values()[3].clone(), is_atomic, None)
         .await
         .unwrap();
 }
@@ -111,7 +112,7 @@ async fn test_queries_on_a_node_with<T>(
     };
     let value = "hello".to_string();
     // put
-    db.put(key.clone(), value.clone().into(), is_atomic)
+    db.put(key.clone(), value.clone().into(), is_atomic, None)
         .await
         .unwrap();
     // get
@@ -128,7 +129,7 @@ async fn test_queries_on_a_node_with<T>(
         table_name: "no_existed_table".try_into().unwrap(),
         inner_key: vec![],
     };
-    let res = db.put(err_key.clone(), vec![], false).await;
+    let res = db.put(err_key.clone(), vec![], false, None).await;
     assert_matches!(res, Err(Error::StackIdOrTableDoseNotExist(_)));
 
     seed(db.as_ref(), keys.clone(), is_atomic).await;
@@ -144,7 +145,7 @@ async fn test_predictable_scans_for_keys(
     keys: [Key; 4],
 ) {
     let scan = Scan::ByTableName(stack_id, table_list[0].clone());
-    let res = db.scan_keys(scan, 800).await.unwrap();
+    let res = db.scan_keys(scan, 800, false).await.unwrap();
     let x: Vec<Key> = keys
         .iter()
         .filter(|k| k.stack_id == stack_id && k.table_name == table_list[0])
@@ -153,7 +154,7 @@ async fn test_predictable_scans_for_keys(
     assert_eq!(res, x);
 
     let scan = Scan::ByTableName(stack_id, table_list[1].clone());
-    let res = db.scan_keys(scan, 800).await.unwrap();
+    let res = db.scan_keys(scan, 800, false).await.unwrap();
     let x: Vec<Key> = keys
         .iter()
         .filter(|k| k.stack_id == stack_id && k.table_name == table_list[1])
@@ -162,7 +163,7 @@ async fn test_predictable_scans_for_keys(
     assert_eq!(res, x);
 
     let scan = Scan::ByInnerKeyPrefix(stack_id, table_list[0].clone(), vec![0, 1]);
-    let res = db.scan_keys(scan, 800).await.unwrap();
+    let res = db.scan_keys(scan, 800, false).await.unwrap();
     let x: Vec<Key> = keys
         .iter()
         .filter(|k| {
@@ -182,7 +183,7 @@ async fn test_unpredictable_scans_for_keys(
     keys: [Key; 4],
 ) {
     let scan = Scan::ByTableName(stack_id, table_list[0].clone());
-    let res = db.scan_keys(scan, 800).await.unwrap();
+    let res = db.scan_keys(scan, 800, false).await.unwrap();
     let mut x = keys
         .iter()
         .filter(|k| k.stack_id == stack_id && k.table_name == table_list[0])
@@ -190,7 +191,7 @@ async fn test_unpredictable_scans_for_keys(
     assert!(x.all(|xp| res.contains(&xp)));
 
     let scan = Scan::ByTableName(stack_id, table_list[1].clone());
-    let res2 = db.scan_keys(scan, 800).await.unwrap();
+    let res2 = db.scan_keys(scan, 800, false).await.unwrap();
     let mut x = keys
         .iter()
         .filter(|k| k.stack_id == stack_id && k.table_name == table_list[1])
@@ -198,7 +199,7 @@ async fn test_unpredictable_scans_for_keys(
     assert!(x.all(|xp| res2.contains(&xp)));
 
     let scan = Scan::ByInnerKeyPrefix(stack_id, table_list[0].clone(), vec![0, 1]);
-    let res = db.scan_keys(scan, 800).await.unwrap();
+    let res = db.scan_keys(scan, 800, false).await.unwrap();
     let mut x = keys
         .iter()
         .filter(|k| {
@@ -210,6 +211,128 @@ async fn test_unpredictable_scans_for_keys(
     assert!(x.all(|xp| res.contains(&xp)));
 }
 
+async fn test_batch_get_ordered(db: &dyn DbClient, keys: [Key; 4]) {
+    let missing_key = Key {
+        stack_id: keys[0].stack_id,
+        table_name: keys[0].table_name.clone(),
+        inner_key: vec![9, 9, 9],
+    };
+    let requested = vec![
+        keys[3].clone(),
+        missing_key,
+        keys[0].clone(),
+        keys[3].clone(),
+    ];
+    let res = db.batch_get_ordered(requested).await.unwrap();
+    assert_eq!(
+        res,
+        vec![
+            Some((keys[3].clone(), values()[3].clone())),
+            None,
+            Some((keys[0].clone(), values()[0].clone())),
+            Some((keys[3].clone(), values()[3].clone())),
+        ]
+    );
+}
+
+async fn test_scan_order(db: &dyn DbClient, keys: [Key; 4]) {
+    let scan = Scan::ByTableName(keys[0].stack_id, keys[0].table_name.clone());
+    // Only the first 3 keys are in the first table
+    let pairs = keys
+        .iter()
+        .cloned()
+        .zip(values())
+        .take(3)
+        .collect::<Vec<_>>();
+    let reversed = pairs.iter().rev().cloned().collect::<Vec<_>>();
+
+    assert_eq!(db.scan(scan.clone(), 10, false).await.unwrap(), pairs);
+    assert_eq!(db.scan(scan.clone(), 10, true).await.unwrap(), reversed);
+
+    // Limits keep the first or the last pairs, depending on direction
+    assert_eq!(db.scan(scan.clone(), 2, false).await.unwrap(), pairs[..2]);
+    assert_eq!(db.scan(scan.clone(), 2, true).await.unwrap(), reversed[..2]);
+    assert_eq!(
+        db.scan_keys(scan.clone(), 2, false).await.unwrap(),
+        vec![keys[0].clone(), keys[1].clone()]
+    );
+    assert_eq!(
+        db.scan_keys(scan.clone(), 2, true).await.unwrap(),
+        vec![keys[2].clone(), keys[1].clone()]
+    );
+    assert_eq!(db.scan(scan, 0, true).await.unwrap(), vec![]);
+}
+
+async fn test_count_by_prefix(db: &dyn DbClient, stack_id: StackID, table_list: [TableName; 2]) {
+    let count =
+        |prefix: Vec<u8>, limit| db.count_by_prefix(stack_id, table_list[0].clone(), prefix, limit);
+
+    assert_eq!(count(vec![], 100).await.unwrap(), 3);
+    assert_eq!(count(vec![0, 1], 100).await.unwrap(), 2);
+    assert_eq!(count(vec![0, 1], 1).await.unwrap(), 1);
+    assert_eq!(count(vec![2], 100).await.unwrap(), 0);
+}
+
+async fn test_ttl(db: &dyn DbClient, stack_id: StackID, table_list: [TableName; 2]) {
+    let key = Key {
+        stack_id,
+        table_name: table_list[1].clone(),
+        inner_key: b"ttl".to_vec(),
+    };
+    let ttl = Duration::from_secs(1);
+
+    db.put(key.clone(), values()[0].clone(), false, Some(ttl))
+        .await
+        .unwrap();
+    let remaining = db.get_ttl(key.clone()).await.unwrap().unwrap();
+    assert!(remaining <= ttl && remaining > Duration::ZERO);
+    assert_eq!(
+        db.get(key.clone()).await.unwrap(),
+        Some(values()[0].clone())
+    );
+
+    tokio::time::sleep(ttl + Duration::from_millis(200)).await;
+    assert_eq!(db.get(key.clone()).await.unwrap(), None);
+    assert_eq!(db.get_ttl(key.clone()).await.unwrap(), None);
+
+    // Expired keys are skipped by other reads too, before they're reaped
+    let scan = Scan::ByInnerKeyPrefix(stack_id, table_list[1].clone(), b"ttl".to_vec());
+    assert!(db.scan(scan.clone(), 100, false).await.unwrap().is_empty());
+    assert!(db
+        .scan_keys(scan.clone(), 100, false)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(db.count(scan, 100).await.unwrap(), 0);
+    assert!(db.batch_get(vec![key.clone()]).await.unwrap().is_empty());
+
+    // Keys deleted and written again don't inherit the old expiry
+    db.put(key.clone(), values()[0].clone(), false, Some(ttl))
+        .await
+        .unwrap();
+    db.delete(key.clone(), false).await.unwrap();
+    db.batch_put(vec![(key.clone(), values()[0].clone())], false)
+        .await
+        .unwrap();
+    assert_eq!(db.get_ttl(key.clone()).await.unwrap(), None);
+
+    // Writing without a TTL makes the key permanent again
+    db.put(key.clone(), values()[1].clone(), false, Some(ttl))
+        .await
+        .unwrap();
+    db.put(key.clone(), values()[1].clone(), false, None)
+        .await
+        .unwrap();
+    assert_eq!(db.get_ttl(key.clone()).await.unwrap(), None);
+    tokio::time::sleep(ttl + Duration::from_millis(200)).await;
+    assert_eq!(
+        db.get(key.clone()).await.unwrap(),
+        Some(values()[1].clone())
+    );
+
+    db.delete(key, false).await.unwrap();
+}
+
 async fn test_table_list(db: &dyn DbClient, tl: Vec<TableName>) {
     let table_names = db.table_list(STACK_ID, None).await.unwrap();
     assert_eq!(table_names, tl);
@@ -236,6 +359,227 @@ async fn test_update_stack_tables(db: Box<dyn DbClient>) {
     test_table_list(db.as_ref(), table_list).await;
 }
 
+async fn test_transactions(db: Box<dyn DbClient>) {
+    let stack_id = StackID::SolanaPublicKey([2; 32]);
+    let tl = table_list();
+    db.update_stack_tables(
+        stack_id,
+        tl.clone()
+            .into_iter()
+            .map(|t| (t, DeleteTable(false)))
+            .collect(),
+    )
+    .await
+    .unwrap();
+    let keys = keys(stack_id, tl);
+
+    // A failing transaction doesn't write anything, even what it wrote
+    // before failing
+    let failing_keys = keys.clone();
+    let res = db
+        .transaction(transaction_fn(move |txn| {
+            Box::pin(async move {
+                txn.put(failing_keys[0].clone(), values()[0].clone())
+                    .await?;
+                txn.put(failing_keys[1].clone(), values()[1].clone())
+                    .await?;
+                assert_eq!(
+                    txn.get(failing_keys[0].clone()).await?,
+                    Some(values()[0].clone())
+                );
+                Err(Error::InternalErr(anyhow::anyhow!("failed on purpose")))
+            })
+        }))
+        .await;
+    assert_matches!(res, Err(Error::InternalErr(_)));
+    assert_eq!(db.get(keys[0].clone()).await.unwrap(), None);
+    assert_eq!(db.get(keys[1].clone()).await.unwrap(), None);
+
+    let committed_keys = keys.clone();
+    db.transaction(transaction_fn(move |txn| {
+        Box::pin(async move {
+            txn.put(committed_keys[0].clone(), values()[0].clone())
+                .await?;
+            txn.put(committed_keys[1].clone(), values()[1].clone())
+                .await?;
+            txn.delete(committed_keys[1].clone())?;
+            Ok(())
+        })
+    }))
+    .await
+    .unwrap();
+    assert_eq!(
+        db.get(keys[0].clone()).await.unwrap(),
+        Some(values()[0].clone())
+    );
+    assert_eq!(db.get(keys[1].clone()).await.unwrap(), None);
+
+    // Writes to tables that don't exist fail the whole transaction
+    let key = Key {
+        stack_id,
+        table_name: "no_existed_table".try_into().unwrap(),
+        inner_key: vec![],
+    };
+    let existing_key = keys[2].clone();
+    let res = db
+        .transaction(transaction_fn(move |txn| {
+            Box::pin(async move {
+                txn.put(existing_key, values()[2].clone()).await?;
+                txn.put(key, vec![]).await
+            })
+        }))
+        .await;
+    assert_matches!(res, Err(Error::StackIdOrTableDoseNotExist(_)));
+    assert_eq!(db.get(keys[2].clone()).await.unwrap(), None);
+}
+
+async fn test_batch_compare_and_swap(db: Box<dyn DbClient>) {
+    let stack_id = StackID::SolanaPublicKey([3; 32]);
+    let tl = table_list();
+    db.update_stack_tables(
+        stack_id,
+        tl.clone()
+            .into_iter()
+            .map(|t| (t, DeleteTable(false)))
+            .collect(),
+    )
+    .await
+    .unwrap();
+    let keys = keys(stack_id, tl);
+    let values = values();
+    db.put(keys[0].clone(), values[0].clone(), true, None)
+        .await
+        .unwrap();
+
+    // One stale expected value keeps every key from being swapped
+    let res = db
+        .batch_compare_and_swap_non_atomic(vec![
+            (keys[0].clone(), Some(values[0].clone()), values[1].clone()),
+            (keys[1].clone(), Some(values[1].clone()), values[2].clone()),
+            (keys[2].clone(), None, values[3].clone()),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        res,
+        vec![
+            (Some(values[0].clone()), false),
+            (None, false),
+            (None, false)
+        ]
+    );
+    assert_eq!(
+        db.get(keys[0].clone()).await.unwrap(),
+        Some(values[0].clone())
+    );
+    assert_eq!(db.get(keys[1].clone()).await.unwrap(), None);
+    assert_eq!(db.get(keys[2].clone()).await.unwrap(), None);
+
+    let res = db
+        .batch_compare_and_swap_non_atomic(vec![
+            (keys[0].clone(), Some(values[0].clone()), values[1].clone()),
+            (keys[1].clone(), None, values[2].clone()),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(res, vec![(Some(values[0].clone()), true), (None, true)]);
+    assert_eq!(
+        db.get(keys[0].clone()).await.unwrap(),
+        Some(values[1].clone())
+    );
+    assert_eq!(
+        db.get(keys[1].clone()).await.unwrap(),
+        Some(values[2].clone())
+    );
+
+    let res = db
+        .batch_compare_and_swap_non_atomic(vec![
+            (keys[3].clone(), None, values[0].clone()),
+            (keys[3].clone(), None, values[1].clone()),
+        ])
+        .await;
+    assert_matches!(res, Err(Error::InternalErr(_)));
+    assert_eq!(db.get(keys[3].clone()).await.unwrap(), None);
+}
+
+async fn test_delete_counts(db: Box<dyn DbClient>) {
+    // More than a page of keys, to delete them in several rounds
+    const PREFIXED_KEY_COUNT: u64 = 2500;
+    const OTHER_KEY_COUNT: u64 = 10;
+
+    let stack_id = StackID::SolanaPublicKey([4; 32]);
+    let tl = table_list();
+    db.update_stack_tables(
+        stack_id,
+        tl.clone()
+            .into_iter()
+            .map(|t| (t, DeleteTable(false)))
+            .collect(),
+    )
+    .await
+    .unwrap();
+
+    let key = |inner_key: String| Key {
+        stack_id,
+        table_name: tl[0].clone(),
+        inner_key: inner_key.into_bytes(),
+    };
+    let pairs = (0..PREFIXED_KEY_COUNT)
+        .map(|i| (key(format!("a::{i:05}")), vec![]))
+        .chain((0..OTHER_KEY_COUNT).map(|i| (key(format!("b::{i:05}")), vec![])))
+        .collect();
+    db.batch_put(pairs, false).await.unwrap();
+
+    let deleted = db
+        .delete_by_prefix(stack_id, tl[0].clone(), b"a::".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(deleted, PREFIXED_KEY_COUNT);
+    assert_eq!(
+        db.count(Scan::ByTableName(stack_id, tl[0].clone()), u64::MAX)
+            .await
+            .unwrap(),
+        OTHER_KEY_COUNT
+    );
+
+    let deleted = db.clear_table(stack_id, tl[0].clone()).await.unwrap();
+    assert_eq!(deleted, OTHER_KEY_COUNT);
+    assert_eq!(db.clear_table(stack_id, tl[0].clone()).await.unwrap(), 0);
+}
+
+async fn test_secrets(db: Box<dyn DbClient>) {
+    // No tables are registered for this stack
+    let stack_id = StackID::SolanaPublicKey([5; 32]);
+
+    db.put(
+        Key::secret(stack_id, "api-token"),
+        b"s3cr3t".to_vec(),
+        false,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        db.get(Key::secret(stack_id, "api-token")).await.unwrap(),
+        Some(b"s3cr3t".to_vec())
+    );
+
+    // Other tables still have to exist
+    let res = db
+        .put(
+            Key {
+                stack_id,
+                table_name: TABLE_NAME_1.try_into().unwrap(),
+                inner_key: vec![1],
+            },
+            vec![1],
+            false,
+            None,
+        )
+        .await;
+    assert_matches!(res, Err(Error::StackIdOrTableDoseNotExist(_)));
+}
+
 async fn try_to_make_client_or_stop_cluster(
     db_manager: &dyn DbManager,
 ) -> Result<Box<dyn DbClient>> {
@@ -269,6 +613,14 @@ async fn test_queries_on_single_node(db: Box<dyn DbClient>) {
     )
     .await;
 
+    test_batch_get_ordered(db.as_ref(), keys(STACK_ID, table_list())).await;
+
+    test_scan_order(db.as_ref(), keys(STACK_ID, table_list())).await;
+
+    test_count_by_prefix(db.as_ref(), STACK_ID, table_list()).await;
+
+    test_ttl(db.as_ref(), STACK_ID, table_list()).await;
+
     // scan table names
     test_table_list(db.as_ref(), table_list().into()).await;
 }
@@ -305,6 +657,7 @@ fn make_tikv_runner_conf(peer_port: u16, client_port: u16, tikv_port: u16) -> Ti
             data_dir: data_dir.join(format!("tikv_data_dir_{tikv_port}")),
             log_file: Some(data_dir.join(format!("tikv_log_{tikv_port}"))),
         },
+        max_restarts: DEFAULT_MAX_RESTARTS,
     }
 }
 fn make_known_node_conf(gossip_port: u16, pd_port: u16) -> RemoteNode {
@@ -575,6 +928,137 @@ async fn success_to_start_and_query_single_embedded_clustered_node() {
     db_manager.stop().await.unwrap();
 }
 
+// Counts established TCP connections to the given local port, as seen
+// from both ends.
+fn count_connections_to_port(port: u16) -> usize {
+    let port = format!(":{port:04X}");
+    let mut count = 0;
+    for path in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(table) = fs::read_to_string(path) else {
+            continue;
+        };
+        for line in table.lines().skip(1) {
+            // Columns are: index, local address, remote address, state, ...
+            // and state 01 is ESTABLISHED
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            if columns.len() > 3
+                && columns[3] == "01"
+                && (columns[1].ends_with(&port) || columns[2].ends_with(&port))
+            {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+#[tokio::test]
+#[serial]
+async fn transactions_are_applied_only_when_they_succeed() {
+    clean_data_dir();
+
+    let node_address = make_node_address(2803);
+    let known_node_conf = vec![];
+    let tikv_runner_conf = make_tikv_runner_conf(2385, 2386, 20163);
+    let db_manager = new_with_embedded_cluster(node_address, known_node_conf, tikv_runner_conf)
+        .await
+        .unwrap();
+
+    let db_client = try_to_make_client_or_stop_cluster(db_manager.as_ref())
+        .await
+        .unwrap();
+
+    test_transactions(db_client).await;
+    db_manager.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn batch_compare_and_swap_checks_every_key_before_swapping() {
+    clean_data_dir();
+
+    let node_address = make_node_address(2803);
+    let known_node_conf = vec![];
+    let tikv_runner_conf = make_tikv_runner_conf(2385, 2386, 20163);
+    let db_manager = new_with_embedded_cluster(node_address, known_node_conf, tikv_runner_conf)
+        .await
+        .unwrap();
+
+    let db_client = try_to_make_client_or_stop_cluster(db_manager.as_ref())
+        .await
+        .unwrap();
+
+    test_batch_compare_and_swap(db_client).await;
+    db_manager.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn deletes_report_how_many_keys_they_removed() {
+    clean_data_dir();
+
+    let node_address = make_node_address(2803);
+    let known_node_conf = vec![];
+    let tikv_runner_conf = make_tikv_runner_conf(2385, 2386, 20163);
+    let db_manager = new_with_embedded_cluster(node_address, known_node_conf, tikv_runner_conf)
+        .await
+        .unwrap();
+
+    let db_client = try_to_make_client_or_stop_cluster(db_manager.as_ref())
+        .await
+        .unwrap();
+
+    test_delete_counts(db_client).await;
+    db_manager.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn secrets_can_be_set_without_registering_their_table() {
+    clean_data_dir();
+
+    let node_address = make_node_address(2803);
+    let known_node_conf = vec![];
+    let tikv_runner_conf = make_tikv_runner_conf(2385, 2386, 20163);
+    let db_manager = new_with_embedded_cluster(node_address, known_node_conf, tikv_runner_conf)
+        .await
+        .unwrap();
+
+    let db_client = try_to_make_client_or_stop_cluster(db_manager.as_ref())
+        .await
+        .unwrap();
+
+    test_secrets(db_client).await;
+    db_manager.stop().await.unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn making_clients_does_not_open_new_connections() {
+    clean_data_dir();
+
+    let node_address = make_node_address(2803);
+    let known_node_conf = vec![];
+    let tikv_runner_conf = make_tikv_runner_conf(2385, 2386, 20163);
+    let db_manager = new_with_embedded_cluster(node_address, known_node_conf, tikv_runner_conf)
+        .await
+        .unwrap();
+
+    let db_client = try_to_make_client_or_stop_cluster(db_manager.as_ref())
+        .await
+        .unwrap();
+    let connections_before = count_connections_to_port(2386);
+
+    let mut clients = vec![db_client];
+    for _ in 0..100 {
+        clients.push(db_manager.make_client().await.unwrap());
+    }
+    assert!(count_connections_to_port(2386) <= connections_before);
+
+    drop(clients);
+    db_manager.stop().await.unwrap();
+}
+
 #[tokio::test]
 #[serial]
 async fn success_to_start_and_query_3_embedded_clustered_nodes_with_same_stackids_and_tables() {
@@ -726,9 +1210,15 @@ async fn test_multi_node_with_manual_cluster_with_different_endpoint_but_same_ti
             .unwrap();
     }
 
-    db.put(ks[0].clone(), vs[0].clone(), false).await.unwrap();
-    db2.put(ks[1].clone(), vs[1].clone(), false).await.unwrap();
-    db3.put(ks[2].clone(), vs[2].clone(), false).await.unwrap();
+    db.put(ks[0].clone(), vs[0].clone(), false, None)
+        .await
+        .unwrap();
+    db2.put(ks[1].clone(), vs[1].clone(), false, None)
+        .await
+        .unwrap();
+    db3.put(ks[2].clone(), vs[2].clone(), false, None)
+        .await
+        .unwrap();
 
     let x = db.get(ks[0].clone()).await.unwrap();
     let y = db2.get(ks[0].clone()).await.unwrap();
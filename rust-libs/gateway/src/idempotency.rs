@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use borsh::{BorshDeserialize, BorshSerialize};
+use dyn_clonable::clonable;
+use log::warn;
+use mu_db::DbClient;
+use mu_stack::StackID;
+use musdk_common::Response;
+use sha2::{Digest, Sha256};
+
+use crate::FunctionResponse;
+
+pub(crate) const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+// Stack keys never start with a zero byte, so cached responses can't collide
+// with stack data
+const DB_KEY_PREFIX: &[u8] = b"\0I";
+
+/// Identifies a request that was sent with an `Idempotency-Key` header. Keys
+/// are scoped to a gateway and to the credentials the request was sent with,
+/// so neither different stacks nor different users of a gateway can see each
+/// other's responses.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey {
+    pub stack_id: StackID,
+    pub gateway_name: String,
+    /// Hash of the request's `Authorization` header, see [`auth_identity`].
+    pub auth_identity: [u8; 32],
+    pub key: String,
+}
+
+/// Returned when an idempotency key is reused for a request with a different
+/// method, path, query string or body than the one it was first used for.
+#[derive(Debug)]
+pub struct IdempotencyKeyReused;
+
+impl Display for IdempotencyKeyReused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Idempotency key was already used for a different request"
+        )
+    }
+}
+
+impl std::error::Error for IdempotencyKeyReused {}
+
+/// Requests without an `Authorization` header share the same identity.
+pub(crate) fn auth_identity(authorization: Option<&[u8]>) -> [u8; 32] {
+    Sha256::digest(authorization.unwrap_or_default()).into()
+}
+
+/// Identifies what a request asks for, so a key reused for a different
+/// request can be told apart from a retry.
+pub(crate) fn request_hash(method: &str, path: &str, query: &str, body: &[u8]) -> [u8; 32] {
+    // Methods, paths and query strings can't contain a zero byte
+    let mut hasher = Sha256::new();
+    for part in [method.as_bytes(), path.as_bytes(), query.as_bytes()] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+/// Where responses to requests with an idempotency key are kept, so repeated
+/// requests get the same response without invoking the function again.
+#[async_trait]
+#[clonable]
+pub trait IdempotencyStore: Clone + Send + Sync {
+    async fn get(&self, key: &IdempotencyKey) -> Result<Option<Vec<u8>>>;
+    async fn put(&self, key: &IdempotencyKey, response: Vec<u8>, ttl: Duration) -> Result<()>;
+}
+
+#[derive(Clone)]
+pub struct DbIdempotencyStore {
+    db: Box<dyn DbClient>,
+}
+
+impl DbIdempotencyStore {
+    pub fn new(db: Box<dyn DbClient>) -> Self {
+        Self { db }
+    }
+
+    // Gateway names can't contain a zero byte, so it separates them from the
+    // fixed-size auth identity and the key
+    fn db_key(key: &IdempotencyKey) -> Vec<u8> {
+        let mut db_key = DB_KEY_PREFIX.to_vec();
+        db_key.extend(key.stack_id.to_bytes());
+        db_key.extend(key.gateway_name.as_bytes());
+        db_key.push(0);
+        db_key.extend(key.auth_identity);
+        db_key.extend(key.key.as_bytes());
+        db_key
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for DbIdempotencyStore {
+    async fn get(&self, key: &IdempotencyKey) -> Result<Option<Vec<u8>>> {
+        self.db
+            .get_raw(Self::db_key(key))
+            .await
+            .context("Failed to read cached response")
+    }
+
+    async fn put(&self, key: &IdempotencyKey, response: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.db
+            .put_raw(Self::db_key(key), response, false, Some(ttl))
+            .await
+            .context("Failed to write cached response")
+    }
+}
+
+/// Runs requests with the same idempotency key one at a time, so a request
+/// arriving while another one with its key is in flight waits for it and
+/// then gets its cached response. This only holds within a single node; the
+/// function may still run twice if both requests reach different nodes at
+/// the same time.
+pub(crate) struct Idempotency {
+    store: Box<dyn IdempotencyStore>,
+    ttl: Duration,
+    in_flight: Mutex<HashMap<IdempotencyKey, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl Idempotency {
+    pub(crate) fn new(store: Box<dyn IdempotencyStore>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fails with [`IdempotencyKeyReused`] if the key's cached response was
+    /// for a request with a different [`request_hash`].
+    pub(crate) async fn run(
+        &self,
+        key: IdempotencyKey,
+        request_hash: [u8; 32],
+        invoke: impl Future<Output = Result<FunctionResponse>>,
+    ) -> Result<FunctionResponse> {
+        let lock = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        // Declared before the lock guard, so the lock is released by the time
+        // the entry is removed, including when this future is cancelled
+        let entry = InFlightEntry {
+            idempotency: self,
+            key,
+            lock,
+        };
+
+        let _guard = entry.lock.lock().await;
+        let result = self.run_exclusive(&entry.key, request_hash, invoke).await;
+        result
+    }
+
+    async fn run_exclusive(
+        &self,
+        key: &IdempotencyKey,
+        request_hash: [u8; 32],
+        invoke: impl Future<Output = Result<FunctionResponse>>,
+    ) -> Result<FunctionResponse> {
+        // The store failing shouldn't fail the request, it only makes
+        // retries run the function again
+        match self.store.get(key).await {
+            Ok(Some(cached)) => match decode_cached_response(&cached) {
+                Ok((cached_hash, _)) if cached_hash != request_hash => {
+                    return Err(IdempotencyKeyReused.into())
+                }
+                Ok((_, response)) => return Ok(response.into()),
+                Err(e) => warn!("Discarding invalid cached response for {key:?}: {e}"),
+            },
+            Ok(None) => (),
+            Err(e) => warn!("{e:?}"),
+        }
+
+        let result = invoke.await;
+
+        // Failures aren't cached so they can be retried, and streamed bodies
+        // can't be replayed
+        if let Ok(FunctionResponse {
+            response,
+            body_stream: None,
+        }) = &result
+        {
+            // Stored after the hash of the request it answers
+            let mut serialized = request_hash.to_vec();
+            match response.serialize(&mut serialized) {
+                Ok(()) => {
+                    if let Err(e) = self.store.put(key, serialized, self.ttl).await {
+                        warn!("{e:?}");
+                    }
+                }
+                Err(e) => warn!("Failed to serialize response for {key:?}: {e}"),
+            }
+        }
+
+        result
+    }
+}
+
+fn decode_cached_response(cached: &[u8]) -> std::io::Result<([u8; 32], Response<'static>)> {
+    let mut reader = cached;
+    let request_hash = <[u8; 32]>::deserialize_reader(&mut reader)?;
+    let response = Response::deserialize_reader(&mut reader)?;
+    Ok((request_hash, response))
+}
+
+struct InFlightEntry<'a> {
+    idempotency: &'a Idempotency,
+    key: IdempotencyKey,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Drop for InFlightEntry<'_> {
+    fn drop(&mut self) {
+        // Only the map and this request hold the lock, so nobody is waiting
+        let mut in_flight = self.idempotency.in_flight.lock().unwrap();
+        if Arc::strong_count(&self.lock) == 2 {
+            in_flight.remove(&self.key);
+        }
+    }
+}
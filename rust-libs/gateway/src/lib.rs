@@ -1,24 +1,44 @@
 #![allow(clippy::too_many_arguments)]
 
-use std::{borrow::Cow, collections::HashMap, future::Future, net::IpAddr, pin::Pin, sync::Arc};
+mod idempotency;
+mod rate_limit;
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
 
 use actix_web::{
-    body::BoxBody,
+    body::{BodySize, BoxBody, MessageBody},
     dev::{HttpServiceFactory, ServerHandle},
     guard,
     http::{self, StatusCode},
     web, App, HttpRequest, HttpResponse, HttpServer, Resource, Responder,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
 use dyn_clonable::clonable;
-use log::error;
+use flate2::write::GzEncoder;
+use log::{error, log, Level};
 use mailbox_processor::NotificationChannel;
-use mu_stack::{AssemblyID, FunctionID, Gateway, StackID};
+use mu_stack::{AssemblyID, FunctionID, Gateway, GatewayCors, StackID};
 use musdk_common::{Header, Request, Response, Status};
 use serde::Deserialize;
 use tokio::sync::{mpsc, RwLock};
 
+pub use idempotency::{DbIdempotencyStore, IdempotencyKey, IdempotencyKeyReused, IdempotencyStore};
+use idempotency::{Idempotency, IDEMPOTENCY_KEY_HEADER};
+pub use rate_limit::RateLimit;
+use rate_limit::RateLimiter;
+
 #[async_trait]
 #[clonable]
 pub trait GatewayManager: Clone + Send + Sync {
@@ -26,14 +46,185 @@ pub trait GatewayManager: Clone + Send + Sync {
     async fn deploy_gateways(&self, stack_id: StackID, gateways: Vec<Gateway>) -> Result<()>;
     async fn delete_gateways(&self, stack_id: StackID, gateways: Vec<String>) -> Result<()>;
     async fn delete_all_gateways(&self, stack_id: StackID) -> Result<()>;
+
+    /// Replaces the limits that stacks are deployed with. Stacks that are
+    /// already deployed keep their current limits until their gateways are
+    /// deployed again.
+    async fn set_limits(&self, limits: GatewayLimits) -> Result<()>;
+
+    /// Routes requests for `domain`, e.g. `app.example.com`, to a gateway
+    /// without the stack ID and gateway name in the path. Registering a
+    /// domain again replaces its previous gateway. Domains are unregistered
+    /// when their gateway is deleted.
+    async fn register_domain(
+        &self,
+        domain: String,
+        stack_id: StackID,
+        gateway_name: String,
+    ) -> Result<()>;
+    async fn unregister_domain(&self, domain: String) -> Result<()>;
+
     async fn stop(&self) -> Result<()>;
 }
 
-//TODO: support multiple listen addresses, including Ipv6
 #[derive(Deserialize)]
+#[serde(try_from = "RawGatewayManagerConfig")]
 pub struct GatewayManagerConfig {
-    pub listen_address: IpAddr,
-    pub listen_port: u16,
+    /// The HTTP server listens on all of these. Configs may give a single
+    /// `listen_address` and `listen_port` instead.
+    pub listen_addresses: Vec<SocketAddr>,
+    /// Requests with larger bodies are rejected with 413 while the body is
+    /// being received. Unlimited if not set. This is also the most that
+    /// [`GatewayManager::set_limits`] can raise the limit to, since bodies
+    /// beyond it are never received.
+    #[serde(default)]
+    pub max_request_body_bytes: Option<u64>,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    /// Logs every request under the `mu_gateway::access` target; requests
+    /// that reach a function at `info`, others at `debug`.
+    #[serde(default)]
+    pub access_log: bool,
+    /// Requests to a stack beyond this are rejected with 429 before any of
+    /// its functions are invoked. Unlimited if not set.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Includes the details of [`FunctionError`]s in responses. Only meant
+    /// for development, since they may reveal how a function works.
+    #[serde(default)]
+    pub debug_errors: bool,
+    /// How long [`GatewayManager::stop`] waits for in-flight requests before
+    /// dropping them.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// How long responses to requests with an `Idempotency-Key` header are
+    /// kept. Only used when the gateway is started with an
+    /// [`IdempotencyStore`].
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub idempotency_ttl_secs: u64,
+}
+
+impl GatewayManagerConfig {
+    pub fn limits(&self) -> GatewayLimits {
+        GatewayLimits {
+            max_request_body_bytes: self.max_request_body_bytes,
+            rate_limit: self.rate_limit,
+        }
+    }
+}
+
+/// The limits a stack's requests are checked against, see
+/// [`GatewayManagerConfig`] for what each one does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GatewayLimits {
+    pub max_request_body_bytes: Option<u64>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Deserialize)]
+struct RawGatewayManagerConfig {
+    #[serde(default)]
+    listen_addresses: Vec<SocketAddr>,
+    #[serde(default)]
+    listen_address: Option<IpAddr>,
+    #[serde(default)]
+    listen_port: Option<u16>,
+    #[serde(default)]
+    max_request_body_bytes: Option<u64>,
+    #[serde(default)]
+    compression: CompressionConfig,
+    #[serde(default)]
+    access_log: bool,
+    #[serde(default)]
+    rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    debug_errors: bool,
+    #[serde(default = "default_shutdown_timeout_secs")]
+    shutdown_timeout_secs: u64,
+    #[serde(default = "default_idempotency_ttl_secs")]
+    idempotency_ttl_secs: u64,
+}
+
+impl TryFrom<RawGatewayManagerConfig> for GatewayManagerConfig {
+    type Error = String;
+
+    fn try_from(raw: RawGatewayManagerConfig) -> Result<Self, Self::Error> {
+        // A default port may be configured alongside `listen_addresses`, so
+        // the list takes precedence rather than conflicting with it
+        let listen_addresses = match (raw.listen_addresses, raw.listen_address, raw.listen_port) {
+            (addresses, _, _) if !addresses.is_empty() => addresses,
+            (_, Some(address), Some(port)) => vec![SocketAddr::new(address, port)],
+            _ => {
+                return Err(
+                    "either listen_addresses, or listen_address and listen_port must be set".into(),
+                )
+            }
+        };
+
+        Ok(Self {
+            listen_addresses,
+            max_request_body_bytes: raw.max_request_body_bytes,
+            compression: raw.compression,
+            access_log: raw.access_log,
+            rate_limit: raw.rate_limit,
+            debug_errors: raw.debug_errors,
+            shutdown_timeout_secs: raw.shutdown_timeout_secs,
+            idempotency_ttl_secs: raw.idempotency_ttl_secs,
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "br")]
+    Brotli,
+}
+
+impl ContentEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CompressionConfig {
+    /// Smaller response bodies are sent uncompressed.
+    #[serde(default = "default_compression_min_size_bytes")]
+    pub min_size_bytes: u64,
+    /// In order of preference, used when the client accepts more than one.
+    /// Compression is disabled when empty.
+    #[serde(default = "default_compression_encodings")]
+    pub encodings: Vec<ContentEncoding>,
+}
+
+fn default_compression_min_size_bytes() -> u64 {
+    1024
+}
+
+fn default_compression_encodings() -> Vec<ContentEncoding> {
+    vec![ContentEncoding::Brotli, ContentEncoding::Gzip]
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_size_bytes: default_compression_min_size_bytes(),
+            encodings: default_compression_encodings(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -45,13 +236,115 @@ pub enum Notification {
     },
 }
 
+/// Chunks of a streamed response body. An error ends the response abruptly,
+/// so the client can tell the body is incomplete.
+pub type ResponseBodyStream = mpsc::Receiver<Result<Vec<u8>>>;
+
+/// What invoking a function results in. Streamed responses have an empty
+/// body, and are sent to the client chunk by chunk as they arrive.
+pub struct FunctionResponse {
+    pub response: Response<'static>,
+    pub body_stream: Option<ResponseBodyStream>,
+}
+
+impl From<Response<'static>> for FunctionResponse {
+    fn from(response: Response<'static>) -> Self {
+        Self {
+            response,
+            body_stream: None,
+        }
+    }
+}
+
+/// A failure caused by the invoked function rather than the platform.
+/// Request handlers return these (through `anyhow`) so callers get a status
+/// code that says what went wrong; any other error is reported as an
+/// internal error with no details.
+#[derive(Debug)]
+pub struct FunctionError {
+    pub kind: FunctionErrorKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionErrorKind {
+    Failed,
+    TimedOut,
+    MemoryExceeded,
+}
+
+impl FunctionError {
+    /// Turns runtime errors the function is at fault for into
+    /// [`FunctionError`]s, and passes any other error through.
+    pub fn from_runtime_error(error: mu_runtime::Error) -> anyhow::Error {
+        match error.function_failure() {
+            Some((kind, message)) => Self {
+                kind: kind.into(),
+                message,
+            }
+            .into(),
+            None => error.into(),
+        }
+    }
+}
+
+impl From<mu_runtime::FunctionFailureKind> for FunctionErrorKind {
+    fn from(kind: mu_runtime::FunctionFailureKind) -> Self {
+        match kind {
+            mu_runtime::FunctionFailureKind::Failed => Self::Failed,
+            mu_runtime::FunctionFailureKind::TimedOut => Self::TimedOut,
+            mu_runtime::FunctionFailureKind::MemoryExceeded => Self::MemoryExceeded,
+        }
+    }
+}
+
+impl FunctionErrorKind {
+    fn status(&self) -> Status {
+        match self {
+            Self::Failed => Status::InternalServerError,
+            Self::TimedOut => Status::GatewayTimeout,
+            Self::MemoryExceeded => Status::InsufficientStorage,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Failed => "Function failed",
+            Self::TimedOut => "Function timed out",
+            Self::MemoryExceeded => "Function exceeded its memory limit",
+        }
+    }
+}
+
+impl Display for FunctionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind.description(), self.message)
+    }
+}
+
+impl std::error::Error for FunctionError {}
+
 type PathParams<'a> = HashMap<Cow<'a, str>, Cow<'a, str>>;
-type Gateways = HashMap<StackID, HashMap<String, Gateway>>;
+type Gateways = HashMap<StackID, HashMap<String, DeployedGateway>>;
+// Keys are normalized with `normalize_host`
+type Domains = HashMap<String, (StackID, String)>;
+
+struct DeployedGateway {
+    gateway: Gateway,
+    // Endpoint paths are compiled once on deployment rather than on every request
+    compiled_paths: Vec<(String, Vec<PathSegment>)>,
+    // The limits at the time of deployment. All of a stack's gateways are
+    // deployed together, so they share the same limits.
+    limits: GatewayLimits,
+}
 
 #[derive(Clone)]
 struct GatewayManagerImpl {
     server_handle: ServerHandle,
     gateways: Arc<RwLock<Gateways>>,
+    domains: Arc<RwLock<Domains>>,
+    rate_limiter: Arc<RateLimiter>,
+    limits: Arc<RwLock<GatewayLimits>>,
 }
 
 #[async_trait]
@@ -70,8 +363,8 @@ impl GatewayManager for GatewayManagerImpl {
         stack_id: StackID,
         incoming_gateways: Vec<Gateway>,
     ) -> Result<()> {
-        let mut gateways = self.gateways.write().await;
-        let entry = gateways.entry(stack_id).or_insert_with(HashMap::new);
+        let limits = *self.limits.read().await;
+        let mut deployed_gateways = Vec::with_capacity(incoming_gateways.len());
 
         for mut incoming in incoming_gateways {
             incoming.endpoints = incoming
@@ -86,12 +379,40 @@ impl GatewayManager for GatewayManagerImpl {
                 })
                 .collect();
 
-            entry.insert(incoming.name.clone(), incoming);
+            let compiled_paths = incoming
+                .endpoints
+                .keys()
+                .map(|path| match compile_endpoint_path(path) {
+                    Ok(segments) => Ok((path.clone(), segments)),
+                    Err(reason) => Err(anyhow!(
+                        "Invalid endpoint path '{path}' in gateway '{}': {reason}",
+                        incoming.name
+                    )),
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            deployed_gateways.push(DeployedGateway {
+                gateway: incoming,
+                compiled_paths,
+                limits,
+            });
+        }
+
+        let mut gateways = self.gateways.write().await;
+        let entry = gateways.entry(stack_id).or_insert_with(HashMap::new);
+
+        for deployed in deployed_gateways {
+            entry.insert(deployed.gateway.name.clone(), deployed);
         }
         Ok(())
     }
 
     async fn delete_gateways(&self, stack_id: StackID, gateway_names: Vec<String>) -> Result<()> {
+        self.domains
+            .write()
+            .await
+            .retain(|_, (s, name)| *s != stack_id || !gateway_names.contains(name));
+
         if let Some(gateways) = self.gateways.write().await.get_mut(&stack_id) {
             for name in gateway_names {
                 gateways.remove(&name);
@@ -101,10 +422,51 @@ impl GatewayManager for GatewayManagerImpl {
     }
 
     async fn delete_all_gateways(&self, stack_id: StackID) -> Result<()> {
+        self.domains
+            .write()
+            .await
+            .retain(|_, (s, _)| *s != stack_id);
+
+        self.rate_limiter.remove(stack_id).await;
+
         self.gateways.write().await.remove(&stack_id);
         Ok(())
     }
 
+    async fn set_limits(&self, limits: GatewayLimits) -> Result<()> {
+        if let Some(rate_limit) = limits.rate_limit {
+            rate_limit
+                .validate()
+                .context("Invalid gateway rate limit")?;
+        }
+
+        *self.limits.write().await = limits;
+        Ok(())
+    }
+
+    async fn register_domain(
+        &self,
+        domain: String,
+        stack_id: StackID,
+        gateway_name: String,
+    ) -> Result<()> {
+        let domain = normalize_host(&domain);
+        if domain.is_empty() {
+            return Err(anyhow!("Domain name must not be empty"));
+        }
+
+        self.domains
+            .write()
+            .await
+            .insert(domain, (stack_id, gateway_name));
+        Ok(())
+    }
+
+    async fn unregister_domain(&self, domain: String) -> Result<()> {
+        self.domains.write().await.remove(&normalize_host(&domain));
+        Ok(())
+    }
+
     async fn stop(&self) -> Result<()> {
         self.server_handle.stop(true).await;
         Ok(())
@@ -114,6 +476,12 @@ impl GatewayManager for GatewayManagerImpl {
 // Used to access the gateway manager from within request handlers
 struct DependencyAccessor<F> {
     gateways: Arc<RwLock<Gateways>>,
+    domains: Arc<RwLock<Domains>>,
+    compression: CompressionConfig,
+    access_log: bool,
+    rate_limiter: Arc<RateLimiter>,
+    debug_errors: bool,
+    idempotency: Option<Arc<Idempotency>>,
     handle_request: F,
     notification_channel: NotificationChannel<Notification>,
 }
@@ -125,52 +493,116 @@ where
     fn clone(&self) -> Self {
         Self {
             gateways: self.gateways.clone(),
+            domains: self.domains.clone(),
+            compression: self.compression.clone(),
+            access_log: self.access_log,
+            rate_limiter: self.rate_limiter.clone(),
+            debug_errors: self.debug_errors,
+            idempotency: self.idempotency.clone(),
             handle_request: self.handle_request.clone(),
             notification_channel: self.notification_channel.clone(),
         }
     }
 }
 
-type MatchScore = usize;
+// Bytes of literal segments matched, then whether the path matched without a
+// catch-all segment, so that `/a/{x}` is preferred over `/a/{x:*}`
+type MatchScore = (usize, bool);
+
+enum PathMatchResult<'a> {
+    Function {
+        assembly_name: String,
+        function_name: String,
+        route_template: String,
+        path_params: PathParams<'a>,
+        accepted_content_types: Option<Vec<String>>,
+    },
+    AllowedMethods(Vec<mu_stack::HttpMethod>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PathSegment {
+    Literal(String),
+    Param(String),
+    // A `{name:*}` segment captures the rest of the request path, slashes
+    // included. It's always the last segment.
+    CatchAll(String),
+}
+
+fn compile_endpoint_path(endpoint_path: &str) -> Result<Vec<PathSegment>, &'static str> {
+    let mut segments = endpoint_path.split('/').peekable();
+    let mut compiled = vec![];
+
+    while let Some(segment) = segments.next() {
+        // Cases like `/get/{a}{b}/` are rejected during stack validation,
+        // so we can assume each segment holds at most one parameter.
+        let compiled_segment = match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            Some(name) => match name.strip_suffix(":*") {
+                Some(_) if segments.peek().is_some() => {
+                    return Err("catch-all parameters must be the last segment")
+                }
+                Some(name) => PathSegment::CatchAll(name.to_string()),
+                None => PathSegment::Param(name.to_string()),
+            },
+            None => PathSegment::Literal(segment.to_string()),
+        };
+
+        compiled.push(compiled_segment);
+    }
+
+    Ok(compiled)
+}
 
+#[cfg(test)]
 fn match_path_and_extract_path_params<'a>(
     request_path: &'a str,
     endpoint_path: &str,
 ) -> Option<(MatchScore, PathParams<'a>)> {
-    //TODO: Cache `endpoint_path` path segments for future matches
+    match_segments(request_path, &compile_endpoint_path(endpoint_path).ok()?)
+}
+
+fn match_segments<'a>(
+    request_path: &'a str,
+    endpoint_segments: &[PathSegment],
+) -> Option<(MatchScore, PathParams<'a>)> {
     let mut request_path_segments = request_path.split('/');
-    let mut endpoint_path_segments = endpoint_path.split('/');
 
     let mut path_params = HashMap::new();
     let mut match_score = 0;
 
-    loop {
-        match (request_path_segments.next(), endpoint_path_segments.next()) {
-            (Some(req_segment), Some(ep_segment)) => {
-                //TODO: Check for cases like `/get/{a}{b}/` which is invalid, since there
-                //is two variables in one segment -> should happen during stack validation
-
-                if req_segment == ep_segment {
-                    match_score += req_segment.len();
-                    continue;
-                } else if ep_segment.starts_with('{') && ep_segment.ends_with('}') {
-                    path_params.insert(
-                        Cow::Owned(ep_segment[1..ep_segment.len() - 1].to_string()),
-                        Cow::Borrowed(req_segment),
-                    );
-                } else {
-                    return None;
-                }
-            }
+    // Byte offset of the next request segment, used to capture catch-all tails
+    let mut request_offset = 0;
+
+    for ep_segment in endpoint_segments {
+        if let PathSegment::CatchAll(name) = ep_segment {
+            let tail = request_path.get(request_offset..).unwrap_or_default();
+            path_params.insert(Cow::Owned(name.clone()), Cow::Borrowed(tail));
+            return Some(((match_score, false), path_params));
+        }
+
+        let req_segment = request_path_segments.next()?;
+        request_offset += req_segment.len() + 1;
 
-            (None, None) => return Some((match_score, path_params)),
-            (None, Some(_)) | (Some(_), None) => return None,
+        match ep_segment {
+            PathSegment::Literal(literal) if literal == req_segment => {
+                match_score += literal.len();
+            }
+            PathSegment::Param(name) => {
+                path_params.insert(Cow::Owned(name.clone()), Cow::Borrowed(req_segment));
+            }
+            _ => return None,
         }
     }
+
+    match request_path_segments.next() {
+        None => Some(((match_score, true), path_params)),
+        Some(_) => None,
+    }
 }
 
 pub async fn start_without_additional_services<HandleRequest>(
     config: GatewayManagerConfig,
+    idempotency_store: Option<Box<dyn IdempotencyStore>>,
     handle_request_callback: HandleRequest,
 ) -> Result<(
     Box<dyn GatewayManager>,
@@ -180,7 +612,7 @@ where
     for<'a> HandleRequest: (Fn(
             FunctionID,
             Request<'a>,
-        ) -> Pin<Box<dyn Future<Output = Result<Response<'static>>> + Send + 'a>>)
+        ) -> Pin<Box<dyn Future<Output = Result<FunctionResponse>> + Send + 'a>>)
         // TODO: we're using a box because I don't know how I can use 'a in two where
         // clauses, so I can't express the same lifetime bound with a generic future
         + Clone
@@ -198,6 +630,7 @@ where
         config,
         || IdentityServiceFactory,
         Option::<()>::None,
+        idempotency_store,
         handle_request_callback,
     )
     .await
@@ -220,6 +653,7 @@ pub async fn start<HandleRequest, AppData: Clone + Send + 'static>(
     // note: use [actix_web::services!] to pass more than one service here.
     additional_services: impl HttpServiceFactoryBuilder,
     additional_app_data: Option<AppData>,
+    idempotency_store: Option<Box<dyn IdempotencyStore>>,
     handle_request_callback: HandleRequest,
 ) -> Result<(
     Box<dyn GatewayManager>,
@@ -229,7 +663,7 @@ where
     for<'a> HandleRequest: (Fn(
             FunctionID,
             Request<'a>,
-        ) -> Pin<Box<dyn Future<Output = Result<Response<'static>>> + Send + 'a>>)
+        ) -> Pin<Box<dyn Future<Output = Result<FunctionResponse>> + Send + 'a>>)
         // TODO: we're using a box because I don't know how I can use 'a in two where
         // clauses, so I can't express the same lifetime bound with a generic future
         + Clone
@@ -239,18 +673,42 @@ where
 {
     let (tx, rx) = NotificationChannel::<Notification>::new();
 
+    let max_request_body_bytes = config
+        .max_request_body_bytes
+        .map(|b| usize::try_from(b).unwrap_or(usize::MAX))
+        .unwrap_or(usize::MAX);
+
     let gateways = Arc::new(RwLock::new(HashMap::new()));
+    let domains = Arc::new(RwLock::new(HashMap::new()));
+    if let Some(rate_limit) = config.rate_limit {
+        rate_limit
+            .validate()
+            .context("Invalid gateway rate limit")?;
+    }
+    let limits = Arc::new(RwLock::new(config.limits()));
+    let rate_limiter = Arc::new(RateLimiter::new());
 
     let accessor: DependencyAccessor<HandleRequest> = {
         let gateways = gateways.clone();
+        let domains = domains.clone();
+        let rate_limiter = rate_limiter.clone();
         DependencyAccessor {
             gateways,
+            domains,
+            compression: config.compression,
+            access_log: config.access_log,
+            rate_limiter,
+            debug_errors: config.debug_errors,
+            idempotency: idempotency_store.map(|store| {
+                let ttl = Duration::from_secs(config.idempotency_ttl_secs);
+                Arc::new(Idempotency::new(store, ttl))
+            }),
             handle_request: handle_request_callback,
             notification_channel: tx,
         }
     };
 
-    let server = HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         let mut app = App::new().app_data(web::Data::new(accessor.clone()));
 
         if let Some(additional_data) = additional_app_data.as_ref() {
@@ -261,7 +719,9 @@ where
 
         app = app
             .service(
-                Resource::new("/{stack_id}/{gateway_name}/{path:.*}")
+                // Requests are either `/{stack_id}/{gateway_name}/{path}`, or
+                // just `/{path}` on a registered domain; see `resolve_route`
+                Resource::new("/{path:.*}")
                     .guard(
                         guard::Any(guard::Get())
                             .or(guard::Post())
@@ -271,17 +731,27 @@ where
                             .or(guard::Options())
                             .or(guard::Patch()),
                     )
+                    .app_data(web::PayloadConfig::new(max_request_body_bytes))
                     .to(handle_request::<HandleRequest>),
             )
             .default_service(web::to(|| async { ResponseWrapper::not_found() }));
 
         app
     })
-    .bind((config.listen_address, config.listen_port))
-    .context("Failed to bind HTTP server port")?
     .disable_signals()
-    .shutdown_timeout(15 * 60)
-    .run();
+    .shutdown_timeout(config.shutdown_timeout_secs);
+
+    if config.listen_addresses.is_empty() {
+        bail!("No listen addresses configured for the gateway");
+    }
+    for address in &config.listen_addresses {
+        server = server
+            .bind(address)
+            .with_context(|| format!("Failed to bind HTTP server to {address}"))?;
+    }
+
+    // One server serves every address, so stopping it closes all of them
+    let server = server.run();
 
     let server_handle = server.handle();
 
@@ -290,6 +760,9 @@ where
     let gateway_manager_impl = GatewayManagerImpl {
         server_handle,
         gateways,
+        domains,
+        rate_limiter,
+        limits,
     };
 
     Ok((Box::new(gateway_manager_impl), rx))
@@ -306,18 +779,63 @@ fn calculate_request_size(r: &HttpRequest, payload: &Option<web::Bytes>) -> u64
     size
 }
 
-fn calculate_response_size(r: &Response) -> u64 {
+// Responses to HEAD requests are sent without their body, see `route_request`
+fn calculate_response_size(r: &Response, include_body: bool) -> u64 {
     let mut size = 0;
     size += r
         .headers
         .iter()
         .map(|x| x.name.as_bytes().len() as u64 + x.value.as_bytes().len() as u64)
         .sum::<u64>();
-    size += r.body.len() as u64;
+    if include_body {
+        size += r.body.len() as u64;
+    }
     size
 }
 
-struct ResponseWrapper(Response<'static>);
+// Streamed bodies can't be measured up front, so their traffic is reported
+// once they're done, or the client goes away.
+struct StreamedBody {
+    chunks: ResponseBodyStream,
+    stack_id: StackID,
+    traffic: u64,
+    notification_channel: NotificationChannel<Notification>,
+}
+
+impl MessageBody for StreamedBody {
+    type Error = anyhow::Error;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<web::Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        this.chunks.poll_recv(cx).map(|chunk| {
+            chunk.map(|chunk| {
+                chunk.map(|chunk| {
+                    this.traffic += chunk.len() as u64;
+                    web::Bytes::from(chunk)
+                })
+            })
+        })
+    }
+}
+
+impl Drop for StreamedBody {
+    fn drop(&mut self) {
+        self.notification_channel.send(Notification::ReportUsage {
+            stack_id: self.stack_id,
+            traffic: self.traffic,
+            requests: 0,
+        });
+    }
+}
+
+struct ResponseWrapper(Response<'static>, Option<StreamedBody>);
 
 impl ResponseWrapper {
     fn bad_request(description: &str) -> Self {
@@ -325,6 +843,7 @@ impl ResponseWrapper {
             Response::builder()
                 .status(Status::BadRequest)
                 .body_from_string(description.to_string()),
+            None,
         )
     }
 
@@ -333,6 +852,88 @@ impl ResponseWrapper {
             Response::builder()
                 .status(Status::NotFound)
                 .body_from_str(Status::NotFound.reason().unwrap()),
+            None,
+        )
+    }
+
+    fn payload_too_large() -> Self {
+        Self(
+            Response::builder()
+                .status(Status::PayloadTooLarge)
+                .body_from_str("Request body is larger than the gateway allows"),
+            None,
+        )
+    }
+
+    fn too_many_requests(retry_after: Duration) -> Self {
+        // Retry-After only takes whole seconds
+        let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+        Self(
+            Response::builder()
+                .status(Status::TooManyRequests)
+                .header(Header {
+                    name: Cow::Borrowed("Retry-After"),
+                    value: Cow::Owned(retry_after.max(1).to_string()),
+                })
+                .body_from_str(Status::TooManyRequests.reason().unwrap()),
+            None,
+        )
+    }
+
+    fn idempotency_key_reused() -> Self {
+        Self(
+            Response::builder()
+                .status(Status::UnprocessableEntity)
+                .body_from_string(IdempotencyKeyReused.to_string()),
+            None,
+        )
+    }
+
+    fn unsupported_media_type() -> Self {
+        Self(
+            Response::builder()
+                .status(Status::UnsupportedMediaType)
+                .body_from_str(Status::UnsupportedMediaType.reason().unwrap()),
+            None,
+        )
+    }
+
+    fn allowed_methods(methods: &[mu_stack::HttpMethod]) -> Self {
+        Self(
+            Response::builder()
+                .status(Status::NoContent)
+                .header(Header {
+                    name: Cow::Borrowed("Allow"),
+                    value: Cow::Owned(allow_header_value(methods)),
+                })
+                .no_body(),
+            None,
+        )
+    }
+
+    fn cors_preflight(cors_headers: Vec<(&'static str, String)>) -> Self {
+        Self(
+            Response::builder()
+                .status(Status::NoContent)
+                .headers(cors_headers.into_iter().map(into_header).collect())
+                .no_body(),
+            None,
+        )
+    }
+
+    fn function_error(error: &FunctionError, include_details: bool) -> Self {
+        let body = if include_details {
+            error.to_string()
+        } else {
+            error.kind.description().to_string()
+        };
+
+        Self(
+            Response::builder()
+                .status(error.kind.status())
+                .body_from_string(body),
+            None,
         )
     }
 
@@ -341,8 +942,20 @@ impl ResponseWrapper {
             Response::builder()
                 .status(Status::InternalServerError)
                 .body_from_string(description.to_string()),
+            None,
         )
     }
+
+    // Browsers hide responses without CORS headers from cross-origin
+    // clients, errors included
+    fn with_cors_headers(mut self, cors_headers: &Option<Vec<(&'static str, String)>>) -> Self {
+        if let Some(cors_headers) = cors_headers {
+            self.0
+                .headers
+                .extend(cors_headers.iter().cloned().map(into_header));
+        }
+        self
+    }
 }
 
 impl Responder for ResponseWrapper {
@@ -360,7 +973,9 @@ impl Responder for ResponseWrapper {
             builder.append_header((header.name.into_owned(), header.value.into_owned()));
         }
 
-        if self.0.body.len() > 0 {
+        if let Some(body) = self.1 {
+            builder.body(body)
+        } else if self.0.body.len() > 0 {
             builder.body(self.0.body.into_owned())
         } else {
             builder.finish()
@@ -368,61 +983,449 @@ impl Responder for ResponseWrapper {
     }
 }
 
-fn stack_http_method_to_sdk(method: mu_stack::HttpMethod) -> musdk_common::HttpMethod {
-    match method {
-        mu_stack::HttpMethod::Get => musdk_common::HttpMethod::Get,
-        mu_stack::HttpMethod::Put => musdk_common::HttpMethod::Put,
-        mu_stack::HttpMethod::Post => musdk_common::HttpMethod::Post,
-        mu_stack::HttpMethod::Delete => musdk_common::HttpMethod::Delete,
-        mu_stack::HttpMethod::Options => musdk_common::HttpMethod::Options,
-        mu_stack::HttpMethod::Patch => musdk_common::HttpMethod::Patch,
-        mu_stack::HttpMethod::Head => musdk_common::HttpMethod::Head,
-    }
+fn allow_header_value(methods: &[mu_stack::HttpMethod]) -> String {
+    let mut methods = methods
+        .iter()
+        .chain(std::iter::once(&mu_stack::HttpMethod::Options))
+        .map(|m| m.to_string().to_uppercase())
+        .collect::<Vec<_>>();
+    methods.sort();
+    methods.dedup();
+    methods.join(", ")
 }
 
-fn actix_http_method_to_stack(method: &http::Method) -> mu_stack::HttpMethod {
-    if http::Method::GET == method {
-        mu_stack::HttpMethod::Get
-    } else if http::Method::POST == method {
-        mu_stack::HttpMethod::Post
-    } else if http::Method::PUT == method {
-        mu_stack::HttpMethod::Put
-    } else if http::Method::DELETE == method {
-        mu_stack::HttpMethod::Delete
-    } else if http::Method::OPTIONS == method {
-        mu_stack::HttpMethod::Options
-    } else if http::Method::PATCH == method {
-        mu_stack::HttpMethod::Patch
-    } else if http::Method::HEAD == method {
-        mu_stack::HttpMethod::Head
+// Requests from origins that aren't allowed, or that aren't cross-origin at
+// all, get no CORS headers; the browser enforces the rest.
+fn cors_headers(cors: &GatewayCors, origin: Option<&str>) -> Vec<(&'static str, String)> {
+    let Some(origin) = origin else {
+        return vec![];
+    };
+
+    let allowed_origin = if cors.allowed_origins.iter().any(|o| o == "*") {
+        "*".to_string()
+    } else if cors
+        .allowed_origins
+        .iter()
+        .any(|o| o.eq_ignore_ascii_case(origin))
+    {
+        origin.to_string()
     } else {
-        panic!("Unexpected HTTP method {}", method.as_str());
+        return vec![];
+    };
+
+    let mut headers = vec![];
+
+    if allowed_origin != "*" {
+        headers.push(("Vary", "Origin".to_string()));
+    }
+    headers.push(("Access-Control-Allow-Origin", allowed_origin));
+
+    let allowed_methods = if cors.allowed_methods.is_empty() {
+        "*".to_string()
+    } else {
+        allow_header_value(&cors.allowed_methods)
+    };
+    headers.push(("Access-Control-Allow-Methods", allowed_methods));
+
+    if !cors.allowed_headers.is_empty() {
+        headers.push((
+            "Access-Control-Allow-Headers",
+            cors.allowed_headers.join(", "),
+        ));
     }
+
+    headers
 }
 
-async fn handle_request<F>(
-    request: HttpRequest,
-    payload: Option<web::Bytes>,
-    dependency_accessor: web::Data<DependencyAccessor<F>>,
+fn into_header((name, value): (&'static str, String)) -> Header<'static> {
+    Header {
+        name: Cow::Borrowed(name),
+        value: Cow::Owned(value),
+    }
+}
+
+// Parameters such as `charset` are ignored, and accepted types may use a
+// `type/*` wildcard.
+fn is_content_type_accepted(content_type: Option<&str>, accepted: &[String]) -> bool {
+    let Some(content_type) = content_type else {
+        return false;
+    };
+
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    accepted.iter().any(|a| {
+        let a = a.trim().to_ascii_lowercase();
+        match a.strip_suffix("/*") {
+            Some(main_type) => media_type
+                .split_once('/')
+                .map(|(t, _)| t == main_type)
+                .unwrap_or(false),
+            None => a == media_type,
+        }
+    })
+}
+
+// Picks the most preferred of the configured encodings that the client
+// accepts, if the response is large enough and worth compressing at all.
+fn choose_encoding(
+    config: &CompressionConfig,
+    accept_encoding: Option<&str>,
+    response: &Response,
+) -> Option<ContentEncoding> {
+    let accept_encoding = accept_encoding?;
+
+    if (response.body.len() as u64) < config.min_size_bytes {
+        return None;
+    }
+
+    let mut content_type = None;
+    for header in &response.headers {
+        if header.name.eq_ignore_ascii_case("content-encoding") {
+            return None;
+        }
+        if header.name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(header.value.as_ref());
+        }
+    }
+
+    if !is_compressible(content_type?) {
+        return None;
+    }
+
+    config
+        .encodings
+        .iter()
+        .copied()
+        .find(|e| accepts_encoding(accept_encoding, e.name()))
+}
+
+// Already compressed formats, such as images and archives, only get bigger.
+fn is_compressible(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(
+            media_type.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-www-form-urlencoded"
+        )
+}
+
+// Encodings with `q=0` are refused, and `*` stands for any encoding that
+// isn't listed explicitly.
+fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    let mut wildcard = false;
+
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let token = params.next().unwrap_or_default().trim();
+        let refused = params.any(|p| {
+            p.split_once('=').map_or(false, |(k, v)| {
+                k.trim().eq_ignore_ascii_case("q") && v.trim().parse::<f32>() == Ok(0.0)
+            })
+        });
+
+        if token.eq_ignore_ascii_case(encoding) {
+            return !refused;
+        }
+        if token == "*" {
+            wildcard = !refused;
+        }
+    }
+
+    wildcard
+}
+
+fn compress(body: &[u8], encoding: ContentEncoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        ContentEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        ContentEncoding::Brotli => {
+            // Quality 11 is far too slow for compressing responses on the fly
+            let mut encoder = brotli::CompressorWriter::new(vec![], 4096, 5, 22);
+            encoder.write_all(body)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+// Responses that don't get any smaller are sent as they are.
+async fn compress_response(
+    mut response: Response<'static>,
+    encoding: ContentEncoding,
+) -> Response<'static> {
+    let body = std::mem::take(&mut response.body);
+
+    // Compression is CPU bound, so keep it off the server's worker threads
+    let result = web::block(move || {
+        let compressed = compress(&body, encoding);
+        (body, compressed)
+    })
+    .await;
+
+    // The body is lost if the compression task itself failed
+    let (body, compressed) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Compression task failed: {e:?}");
+            return ResponseWrapper::internal_error("Failed to compress response").0;
+        }
+    };
+
+    match compressed {
+        Ok(compressed) if compressed.len() < body.len() => {
+            response.body = Cow::Owned(compressed);
+            response.headers.push(into_header((
+                "Content-Encoding",
+                encoding.name().to_string(),
+            )));
+            response
+                .headers
+                .push(into_header(("Vary", "Accept-Encoding".to_string())));
+        }
+        Ok(_) => response.body = body,
+        Err(e) => {
+            error!("Failed to compress response: {e:?}");
+            response.body = body;
+        }
+    }
+
+    response
+}
+
+// Lowercases the host and strips the port and any trailing dot, so e.g.
+// `App.Example.com.:8080` and `app.example.com` are the same domain.
+fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = match host.strip_prefix('[') {
+        // IPv6 addresses contain colons themselves
+        Some(v6) => v6.split_once(']').map(|(addr, _)| addr).unwrap_or(v6),
+        None => host.rsplit_once(':').map(|(h, _)| h).unwrap_or(host),
+    };
+
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// Registered domains take precedence, otherwise the stack ID and gateway
+// name are taken from the first two segments of the path.
+fn resolve_route<'a>(
+    domains: &Domains,
+    host: Option<&str>,
+    path: &'a str,
+) -> Option<(StackID, String, &'a str)> {
+    if let Some((stack_id, gateway_name)) = host.and_then(|h| domains.get(&normalize_host(h))) {
+        return Some((*stack_id, gateway_name.clone(), path));
+    }
+
+    let mut segments = path.splitn(3, '/');
+    let stack_id = segments.next()?.parse().ok()?;
+    let gateway_name = segments.next()?;
+    let path = segments.next()?;
+
+    Some((stack_id, gateway_name.to_string(), path))
+}
+
+fn stack_http_method_to_sdk(method: mu_stack::HttpMethod) -> musdk_common::HttpMethod {
+    match method {
+        mu_stack::HttpMethod::Get => musdk_common::HttpMethod::Get,
+        mu_stack::HttpMethod::Put => musdk_common::HttpMethod::Put,
+        mu_stack::HttpMethod::Post => musdk_common::HttpMethod::Post,
+        mu_stack::HttpMethod::Delete => musdk_common::HttpMethod::Delete,
+        mu_stack::HttpMethod::Options => musdk_common::HttpMethod::Options,
+        mu_stack::HttpMethod::Patch => musdk_common::HttpMethod::Patch,
+        mu_stack::HttpMethod::Head => musdk_common::HttpMethod::Head,
+    }
+}
+
+fn actix_http_method_to_stack(method: &http::Method) -> mu_stack::HttpMethod {
+    if http::Method::GET == method {
+        mu_stack::HttpMethod::Get
+    } else if http::Method::POST == method {
+        mu_stack::HttpMethod::Post
+    } else if http::Method::PUT == method {
+        mu_stack::HttpMethod::Put
+    } else if http::Method::DELETE == method {
+        mu_stack::HttpMethod::Delete
+    } else if http::Method::OPTIONS == method {
+        mu_stack::HttpMethod::Options
+    } else if http::Method::PATCH == method {
+        mu_stack::HttpMethod::Patch
+    } else if http::Method::HEAD == method {
+        mu_stack::HttpMethod::Head
+    } else {
+        panic!("Unexpected HTTP method {}", method.as_str());
+    }
+}
+
+// What's known about a request once it's been handled, for the access log
+#[derive(Default)]
+struct AccessLogEntry {
+    stack_id: Option<StackID>,
+    gateway_name: Option<String>,
+    endpoint: Option<String>,
+    // Time spent in the stack's functions
+    elapsed: Option<Duration>,
+}
+
+fn log_access(
+    request: &HttpRequest,
+    request_body_bytes: usize,
+    response: &ResponseWrapper,
+    entry: &AccessLogEntry,
+) {
+    fn or_dash<T: ToString>(value: &Option<T>) -> String {
+        value
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    // Streamed bodies are still being sent at this point
+    let response_body_bytes = match response.1 {
+        Some(_) => "stream".to_string(),
+        None => response.0.body.len().to_string(),
+    };
+
+    let level = if entry.endpoint.is_some() {
+        Level::Info
+    } else {
+        Level::Debug
+    };
+
+    // Query strings are left out, since they may carry credentials
+    log!(
+        target: "mu_gateway::access",
+        level,
+        "method={} path={:?} stack_id={} gateway={} endpoint={} status={} \
+         request_body_bytes={} response_body_bytes={} elapsed_ms={}",
+        request.method(),
+        request.path(),
+        or_dash(&entry.stack_id),
+        or_dash(&entry.gateway_name),
+        or_dash(&entry.endpoint),
+        response.0.status.code,
+        request_body_bytes,
+        response_body_bytes,
+        or_dash(&entry.elapsed.map(|e| format!("{:.3}", e.as_secs_f64() * 1000.0))),
+    );
+}
+
+async fn handle_request<F>(
+    request: HttpRequest,
+    payload: Result<web::Bytes, actix_web::Error>,
+    dependency_accessor: web::Data<DependencyAccessor<F>>,
+) -> ResponseWrapper
+where
+    for<'a> F: (Fn(
+            FunctionID,
+            Request<'a>,
+        ) -> Pin<Box<dyn Future<Output = Result<FunctionResponse>> + Send + 'a>>)
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    if !dependency_accessor.access_log {
+        return route_request(&request, payload, &dependency_accessor, None).await;
+    }
+
+    let request_body_bytes = payload.as_ref().map(|p| p.len()).unwrap_or(0);
+    let mut entry = AccessLogEntry::default();
+
+    let response = route_request(&request, payload, &dependency_accessor, Some(&mut entry)).await;
+
+    log_access(&request, request_body_bytes, &response, &entry);
+    response
+}
+
+async fn route_request<F>(
+    request: &HttpRequest,
+    payload: Result<web::Bytes, actix_web::Error>,
+    dependency_accessor: &DependencyAccessor<F>,
+    mut access_log: Option<&mut AccessLogEntry>,
 ) -> ResponseWrapper
 where
     for<'a> F: (Fn(
             FunctionID,
             Request<'a>,
-        ) -> Pin<Box<dyn Future<Output = Result<Response<'static>>> + Send + 'a>>)
+        ) -> Pin<Box<dyn Future<Output = Result<FunctionResponse>> + Send + 'a>>)
         + Clone
         + Send
         + Sync
         + 'static,
 {
-    let mut traffic = calculate_request_size(&request, &payload);
+    // The configured body limit is enforced by the extractor, see
+    // `PayloadConfig` in `start`. Limits set later are checked below.
+    let (payload, payload_too_large) = match payload {
+        Ok(payload) => (Some(payload), false),
+        Err(e) => (
+            None,
+            e.as_response_error().status_code() == StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+    };
 
-    let Ok(stack_id) = request.match_info().get("stack_id").unwrap().parse() else {
+    let host = request
+        .headers()
+        .get(http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| request.uri().host());
+
+    let Some((stack_id, gateway_name, request_path)) = resolve_route(
+        &*dependency_accessor.domains.read().await,
+        host,
+        request.match_info().get("path").unwrap(),
+    ) else {
+        return ResponseWrapper::not_found();
+    };
+
+    if let Some(entry) = access_log.as_deref_mut() {
+        entry.stack_id = Some(stack_id);
+        entry.gateway_name = Some(gateway_name.clone());
+    }
+
+    let gateways = dependency_accessor.gateways.read().await;
+    let Some(deployed) = gateways.get(&stack_id).and_then(|s| s.get(&gateway_name)) else {
         return ResponseWrapper::not_found();
     };
+    let gateway = &deployed.gateway;
+
+    // Sent with every response from here on
+    let cors_response_headers = gateway.cors.as_ref().map(|cors| {
+        let origin = request
+            .headers()
+            .get(http::header::ORIGIN)
+            .and_then(|v| v.to_str().ok());
+        cors_headers(cors, origin)
+    });
+
+    let payload_too_large = payload_too_large
+        || match (&payload, deployed.limits.max_request_body_bytes) {
+            (Some(payload), Some(max)) => payload.len() as u64 > max,
+            _ => false,
+        };
+    if payload_too_large {
+        return ResponseWrapper::payload_too_large().with_cors_headers(&cors_response_headers);
+    }
 
-    let gateway_name = request.match_info().get("gateway_name").unwrap();
-    let request_path = request.match_info().get("path").unwrap();
+    let mut traffic = calculate_request_size(request, &payload);
 
     let method = actix_http_method_to_stack(request.method());
 
@@ -431,81 +1434,269 @@ where
         .iter()
         .map(|(k, v)| Ok(Header{name: Cow::Borrowed(k.as_str()), value: Cow::Borrowed(v.to_str()?)}))
         .collect::<Result<Vec<_>>>() else {
-            return ResponseWrapper::bad_request("Invalid header values in request");
+            return ResponseWrapper::bad_request("Invalid header values in request")
+                .with_cors_headers(&cors_response_headers);
         };
 
     let Ok(query_params) =
-        web::Query::<HashMap<Cow<'_, str>, Cow<'_, str>>>::from_query(
+        web::Query::<Vec<(Cow<'_, str>, Cow<'_, str>)>>::from_query(
             request.query_string()
         ) else {
-            return ResponseWrapper::bad_request("Invalid query string");
+            return ResponseWrapper::bad_request("Invalid query string")
+                .with_cors_headers(&cors_response_headers);
         };
     let query_params = query_params.into_inner();
 
-    let gateways = dependency_accessor.gateways.read().await;
-    let Some(gateway) = gateways.get(&stack_id).and_then(|s| s.get(gateway_name)) else {
-        return ResponseWrapper::not_found();
-    };
+    // Only requests to deployed gateways get a bucket, so made up stack IDs
+    // can't grow the limiter's state. Rejected requests count as gateway
+    // requests, but never reach a function.
+    if let Some(rate_limit) = deployed.limits.rate_limit {
+        let rate_limiter = &dependency_accessor.rate_limiter;
+        if let Err(retry_after) = rate_limiter.try_acquire(stack_id, rate_limit).await {
+            let response = ResponseWrapper::too_many_requests(retry_after)
+                .with_cors_headers(&cors_response_headers);
+            dependency_accessor
+                .notification_channel
+                .send(Notification::ReportUsage {
+                    stack_id,
+                    traffic: traffic + calculate_response_size(&response.0, true),
+                    requests: 1,
+                });
+            return response;
+        }
+    }
+
+    // Preflight requests are answered here when the gateway has a CORS
+    // policy, so the stack's functions never see them
+    if let Some(cors_response_headers) = cors_response_headers.as_ref() {
+        if method == mu_stack::HttpMethod::Options
+            && request
+                .headers()
+                .contains_key(http::header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return ResponseWrapper::cors_preflight(cors_response_headers.clone());
+        }
+    }
 
-    let mut matched_endpoints = gateway
-        .endpoints
+    let mut matched_endpoints = deployed
+        .compiled_paths
         .iter()
-        .filter_map(|(path, eps)| {
-            match_path_and_extract_path_params(request_path, path)
-                .map(|path_params| (path_params, eps))
+        .filter_map(|(path, segments)| {
+            let path_params = match_segments(request_path, segments)?;
+            let eps = gateway.endpoints.get(path)?;
+            Some((path_params, path, eps))
         })
         .collect::<Vec<_>>();
 
-    matched_endpoints.sort_by_cached_key(|((score, _), _)| *score);
+    matched_endpoints.sort_by_cached_key(|((score, _), _, _)| *score);
 
     let path_match_result =
         matched_endpoints
             .into_iter()
             .rev()
             .next()
-            .and_then(|((_, path_params), eps)| {
-                eps.iter()
-                    .find(|ep| *ep.0 == method)
-                    .map(|ep| (ep.1.assembly.clone(), ep.1.function.clone(), path_params))
+            .and_then(|((_, path_params), path, eps)| {
+                // HEAD requests are served by the GET endpoint unless the
+                // stack handles them itself
+                let ep = eps.get_key_value(&method).or_else(|| match method {
+                    mu_stack::HttpMethod::Head => eps.get_key_value(&mu_stack::HttpMethod::Get),
+                    _ => None,
+                });
+
+                match ep {
+                    Some(ep) => Some(PathMatchResult::Function {
+                        assembly_name: ep.1.assembly.clone(),
+                        function_name: ep.1.function.clone(),
+                        // Leading slashes are stripped when deploying gateways
+                        route_template: format!("/{path}"),
+                        path_params,
+                        accepted_content_types: gateway.accepted_content_types.get(path).cloned(),
+                    }),
+
+                    // Stacks may handle OPTIONS themselves, otherwise we
+                    // report the methods available on this path
+                    None if method == mu_stack::HttpMethod::Options => Some(
+                        PathMatchResult::AllowedMethods(eps.keys().copied().collect()),
+                    ),
+
+                    None => None,
+                }
             });
 
+    let auth = gateway.auth.clone();
+
     drop(gateways);
 
-    let Some((assembly_name, function_name, path_params)) = path_match_result else {
-        return ResponseWrapper::not_found();
-    };
+    let (assembly_name, function_name, route_template, path_params, accepted_content_types) =
+        match path_match_result {
+            Some(PathMatchResult::Function {
+                assembly_name,
+                function_name,
+                route_template,
+                path_params,
+                accepted_content_types,
+            }) => (
+                assembly_name,
+                function_name,
+                route_template,
+                path_params,
+                accepted_content_types,
+            ),
+            Some(PathMatchResult::AllowedMethods(methods)) => {
+                return ResponseWrapper::allowed_methods(&methods)
+                    .with_cors_headers(&cors_response_headers)
+            }
+            None => return ResponseWrapper::not_found().with_cors_headers(&cors_response_headers),
+        };
+
+    // Requests without a body don't need a content type, so e.g. GET
+    // requests on the same path are unaffected.
+    if let Some(accepted) = accepted_content_types {
+        let has_body = payload.as_ref().map(|p| !p.is_empty()).unwrap_or(false);
+        let content_type = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok());
+
+        if has_body && !is_content_type_accepted(content_type, &accepted) {
+            return ResponseWrapper::unsupported_media_type()
+                .with_cors_headers(&cors_response_headers);
+        }
+    }
+
+    let accept_encoding = request
+        .headers()
+        .get(http::header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok());
+
+    let body = payload.as_ref().map(AsRef::as_ref).unwrap_or(&[]);
+
+    let idempotency_key = request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|key| {
+            let authorization = request.headers().get(http::header::AUTHORIZATION);
+            let key = IdempotencyKey {
+                stack_id,
+                gateway_name: gateway_name.clone(),
+                auth_identity: idempotency::auth_identity(authorization.map(|v| v.as_bytes())),
+                key: key.to_string(),
+            };
+            let request_hash = idempotency::request_hash(
+                request.method().as_str(),
+                request_path,
+                request.query_string(),
+                body,
+            );
+            (key, request_hash)
+        });
 
     let request = Request {
         method: stack_http_method_to_sdk(method),
+        route_template: Cow::Owned(route_template),
         path_params,
         query_params,
         headers,
-        body: Cow::Borrowed(payload.as_ref().map(AsRef::as_ref).unwrap_or(&[])),
+        body: Cow::Borrowed(body),
     };
 
-    let response = match (dependency_accessor.handle_request)(
-        FunctionID {
-            assembly_id: AssemblyID {
-                stack_id,
-                assembly_name,
+    if let Some(entry) = access_log.as_deref_mut() {
+        entry.endpoint = Some(request.route_template.to_string());
+    }
+
+    let started = Instant::now();
+
+    // A non-2xx response from the gateway's auth function is returned as-is,
+    // and the target function is never invoked.
+    let auth_rejection = match auth {
+        None => None,
+        Some(auth) => match (dependency_accessor.handle_request)(
+            FunctionID {
+                assembly_id: AssemblyID {
+                    stack_id,
+                    assembly_name: auth.assembly,
+                },
+                function_name: auth.function,
             },
-            function_name,
+            request.clone(),
+        )
+        .await
+        {
+            Ok(r) if (200..300).contains(&r.response.status.code) => None,
+            r => Some(r),
         },
-        request,
-    )
-    .await
-    {
-        Ok(r) => {
-            traffic += calculate_response_size(&r);
-            ResponseWrapper(r)
+    };
+
+    let result = match auth_rejection {
+        Some(r) => r,
+        None => {
+            let invocation = (dependency_accessor.handle_request)(
+                FunctionID {
+                    assembly_id: AssemblyID {
+                        stack_id,
+                        assembly_name,
+                    },
+                    function_name,
+                },
+                request,
+            );
+
+            match (dependency_accessor.idempotency.as_ref(), idempotency_key) {
+                (Some(idempotency), Some((key, request_hash))) => {
+                    idempotency.run(key, request_hash, invocation).await
+                }
+                _ => invocation.await,
+            }
         }
-        // TODO: Only report a "user function failure" if the failure was in the user function
+    };
+
+    if let Some(entry) = access_log {
+        entry.elapsed = Some(started.elapsed());
+    }
+
+    let response = match result {
+        Ok(FunctionResponse {
+            response: mut r,
+            body_stream,
+        }) => {
+            // The body of a response to a HEAD request is kept, so the server
+            // sends the same Content-Length as for GET, but never the body
+            // itself. Streams would still be read to the end, so they're
+            // dropped instead.
+            let is_head = method == mu_stack::HttpMethod::Head;
+            let body_stream = body_stream.filter(|_| !is_head);
+
+            // Streamed bodies are sent as they are, since their size isn't known
+            if body_stream.is_none() {
+                if let Some(encoding) =
+                    choose_encoding(&dependency_accessor.compression, accept_encoding, &r)
+                {
+                    r = compress_response(r, encoding).await;
+                }
+            }
+
+            // Measured after compression, so usage reflects what's actually sent
+            traffic += calculate_response_size(&r, !is_head);
+            let body = body_stream.map(|chunks| StreamedBody {
+                chunks,
+                stack_id,
+                traffic: 0,
+                notification_channel: dependency_accessor.notification_channel.clone(),
+            });
+            ResponseWrapper(r, body)
+        }
+        Err(f) if f.is::<IdempotencyKeyReused>() => ResponseWrapper::idempotency_key_reused(),
         // TODO: Implement X-REQUEST-ID in responses and logs to enable debugging
         Err(f) => {
             error!("Failed to run user function: {f:?}");
-            ResponseWrapper::internal_error("User function failure")
+            match f.downcast_ref::<FunctionError>() {
+                Some(e) => ResponseWrapper::function_error(e, dependency_accessor.debug_errors),
+                None => ResponseWrapper::internal_error("Internal error"),
+            }
         }
-    };
+    }
+    .with_cors_headers(&cors_response_headers);
 
     dependency_accessor
         .notification_channel
@@ -520,8 +1711,123 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::match_path_and_extract_path_params;
-    use std::collections::HashMap;
+    use super::{
+        accepts_encoding, allow_header_value, choose_encoding, compile_endpoint_path, cors_headers,
+        is_content_type_accepted, match_path_and_extract_path_params, normalize_host,
+        resolve_route, CompressionConfig, ContentEncoding, Domains, PathSegment, ResponseWrapper,
+    };
+    use mu_stack::{GatewayCors, HttpMethod, StackID};
+    use musdk_common::{Header, Response, Status};
+    use std::{borrow::Cow, collections::HashMap, time::Duration};
+
+    #[test]
+    fn allow_header_lists_registered_methods_and_options() {
+        assert_eq!(
+            "GET, OPTIONS, POST",
+            allow_header_value(&[HttpMethod::Post, HttpMethod::Get])
+        );
+
+        assert_eq!(
+            "DELETE, OPTIONS",
+            allow_header_value(&[HttpMethod::Options, HttpMethod::Delete])
+        );
+    }
+
+    #[test]
+    fn cors_headers_are_only_sent_to_allowed_origins() {
+        let cors = GatewayCors {
+            allowed_origins: vec!["https://example.com".into()],
+            allowed_methods: vec![HttpMethod::Get, HttpMethod::Post],
+            allowed_headers: vec!["Authorization".into(), "X-Custom".into()],
+        };
+
+        assert_eq!(
+            vec![
+                ("Vary", "Origin".to_string()),
+                (
+                    "Access-Control-Allow-Origin",
+                    "https://example.com".to_string()
+                ),
+                (
+                    "Access-Control-Allow-Methods",
+                    "GET, OPTIONS, POST".to_string()
+                ),
+                (
+                    "Access-Control-Allow-Headers",
+                    "Authorization, X-Custom".to_string()
+                ),
+            ],
+            cors_headers(&cors, Some("https://example.com"))
+        );
+
+        assert!(cors_headers(&cors, Some("https://evil.com")).is_empty());
+        assert!(cors_headers(&cors, None).is_empty());
+    }
+
+    #[test]
+    fn cors_wildcards_allow_any_origin_and_method() {
+        let cors = GatewayCors {
+            allowed_origins: vec!["*".into()],
+            allowed_methods: vec![],
+            allowed_headers: vec![],
+        };
+
+        assert_eq!(
+            vec![
+                ("Access-Control-Allow-Origin", "*".to_string()),
+                ("Access-Control-Allow-Methods", "*".to_string()),
+            ],
+            cors_headers(&cors, Some("https://example.com"))
+        );
+    }
+
+    #[test]
+    fn cors_headers_are_added_to_error_responses() {
+        let cors = GatewayCors {
+            allowed_origins: vec!["*".into()],
+            allowed_methods: vec![],
+            allowed_headers: vec![],
+        };
+        let headers = Some(cors_headers(&cors, Some("https://example.com")));
+
+        for response in [
+            ResponseWrapper::not_found(),
+            ResponseWrapper::payload_too_large(),
+            ResponseWrapper::too_many_requests(Duration::from_secs(1)),
+            ResponseWrapper::internal_error("Internal error"),
+        ] {
+            let response = response.with_cors_headers(&headers);
+            assert!(response
+                .0
+                .headers
+                .iter()
+                .any(|h| h.name == "Access-Control-Allow-Origin" && h.value == "*"));
+        }
+
+        let response = ResponseWrapper::not_found().with_cors_headers(&None);
+        assert!(response.0.headers.is_empty());
+    }
+
+    #[test]
+    fn content_type_must_be_one_of_accepted_types() {
+        let accepted = ["application/json".to_string(), "text/*".to_string()];
+
+        assert!(is_content_type_accepted(
+            Some("application/json"),
+            &accepted
+        ));
+        assert!(is_content_type_accepted(
+            Some("Application/JSON; charset=utf-8"),
+            &accepted
+        ));
+        assert!(is_content_type_accepted(Some("text/plain"), &accepted));
+        assert!(!is_content_type_accepted(
+            Some("application/xml"),
+            &accepted
+        ));
+        assert!(!is_content_type_accepted(Some("textual/plain"), &accepted));
+        assert!(!is_content_type_accepted(None, &accepted));
+    }
 
     #[test]
     fn simple_request_path_will_match() {
@@ -529,7 +1835,7 @@ mod tests {
         let endpoint_path = "/get/users/";
 
         assert_eq!(
-            Some((8, HashMap::new())),
+            Some(((8, true), HashMap::new())),
             match_path_and_extract_path_params(request_path, endpoint_path)
         );
     }
@@ -537,7 +1843,7 @@ mod tests {
     #[test]
     fn can_extract_single_path_param() {
         assert_eq!(
-            Some((7, [("id".into(), "12".into())].into())),
+            Some(((7, true), [("id".into(), "12".into())].into())),
             match_path_and_extract_path_params("/get/user/12", "/get/user/{id}")
         );
     }
@@ -546,7 +1852,7 @@ mod tests {
     fn can_extract_multi_path_param() {
         assert_eq!(
             Some((
-                3,
+                (3, true),
                 [("type".into(), "user".into()), ("id".into(), "12".into())].into()
             )),
             match_path_and_extract_path_params("/get/user/12", "/get/{type}/{id}")
@@ -600,16 +1906,246 @@ mod tests {
     #[test]
     fn path_with_more_fixed_segments_has_higher_score() {
         assert_eq!(
-            Some((7, [("id".into(), "12".into())].into())),
+            Some(((7, true), [("id".into(), "12".into())].into())),
             match_path_and_extract_path_params("/get/user/12", "/get/user/{id}")
         );
 
         assert_eq!(
             Some((
-                3,
+                (3, true),
                 [("id".into(), "12".into()), ("user".into(), "john".into())].into()
             )),
             match_path_and_extract_path_params("/get/john/12", "/get/{user}/{id}")
         );
     }
+
+    #[test]
+    fn params_are_preferred_over_catch_alls() {
+        let (param_score, _) = match_path_and_extract_path_params("/a/x", "/a/{x}").unwrap();
+        let (catch_all_score, _) =
+            match_path_and_extract_path_params("/a/x", "/a/{rest:*}").unwrap();
+        assert!(param_score > catch_all_score);
+
+        // Matching more literal bytes still takes precedence
+        let (literal_catch_all_score, _) =
+            match_path_and_extract_path_params("/a/x/y", "/a/x/{rest:*}").unwrap();
+        let (params_score, _) = match_path_and_extract_path_params("/a/x/y", "/a/{x}/{y}").unwrap();
+        assert!(literal_catch_all_score > params_score);
+    }
+
+    #[test]
+    fn catch_all_captures_empty_tail() {
+        assert_eq!(
+            Some(((5, false), [("path".into(), "".into())].into())),
+            match_path_and_extract_path_params("/files/", "/files/{path:*}")
+        );
+
+        assert_eq!(
+            Some(((5, false), [("path".into(), "".into())].into())),
+            match_path_and_extract_path_params("/files", "/files/{path:*}")
+        );
+    }
+
+    #[test]
+    fn catch_all_captures_single_segment_tail() {
+        assert_eq!(
+            Some(((5, false), [("path".into(), "a.txt".into())].into())),
+            match_path_and_extract_path_params("/files/a.txt", "/files/{path:*}")
+        );
+    }
+
+    #[test]
+    fn catch_all_captures_multi_segment_tail_verbatim() {
+        assert_eq!(
+            Some(((5, false), [("path".into(), "a/b//c/".into())].into())),
+            match_path_and_extract_path_params("/files/a/b//c/", "/files/{path:*}")
+        );
+
+        assert_eq!(
+            Some((
+                (5, false),
+                [
+                    ("bucket".into(), "b1".into()),
+                    ("path".into(), "a/b".into())
+                ]
+                .into()
+            )),
+            match_path_and_extract_path_params("/files/b1/a/b", "/files/{bucket}/{path:*}")
+        );
+    }
+
+    #[test]
+    fn catch_all_must_be_last_segment() {
+        assert!(compile_endpoint_path("files/{path:*}").is_ok());
+        assert!(compile_endpoint_path("files/{id}").is_ok());
+        assert!(compile_endpoint_path("files/{path:*}/").is_err());
+        assert!(compile_endpoint_path("files/{path:*}/meta").is_err());
+
+        assert_eq!(
+            None,
+            match_path_and_extract_path_params("/files/a/meta", "/files/{path:*}/meta")
+        );
+    }
+
+    #[test]
+    fn endpoint_paths_compile_to_segments() {
+        assert_eq!(
+            Ok(vec![
+                PathSegment::Literal("get".into()),
+                PathSegment::Param("id".into()),
+                PathSegment::Literal("".into()),
+            ]),
+            compile_endpoint_path("get/{id}/")
+        );
+
+        assert_eq!(
+            Ok(vec![
+                PathSegment::Literal("files".into()),
+                PathSegment::CatchAll("path".into()),
+            ]),
+            compile_endpoint_path("files/{path:*}")
+        );
+    }
+
+    fn text_response(body_len: usize) -> Response<'static> {
+        Response::builder()
+            .status(Status::Ok)
+            .content_type(Cow::Borrowed("text/plain; charset=utf-8"))
+            .body_from_vec(vec![b'a'; body_len])
+    }
+
+    #[test]
+    fn responses_above_threshold_are_compressed() {
+        let config = CompressionConfig {
+            min_size_bytes: 100,
+            encodings: vec![ContentEncoding::Brotli, ContentEncoding::Gzip],
+        };
+
+        assert_eq!(
+            Some(ContentEncoding::Brotli),
+            choose_encoding(&config, Some("gzip, deflate, br"), &text_response(100))
+        );
+        assert_eq!(
+            Some(ContentEncoding::Gzip),
+            choose_encoding(&config, Some("gzip"), &text_response(1000))
+        );
+    }
+
+    #[test]
+    fn responses_below_threshold_are_not_compressed() {
+        let config = CompressionConfig {
+            min_size_bytes: 100,
+            encodings: vec![ContentEncoding::Gzip],
+        };
+
+        assert_eq!(
+            None,
+            choose_encoding(&config, Some("gzip"), &text_response(99))
+        );
+    }
+
+    #[test]
+    fn only_accepted_encodings_and_compressible_types_are_used() {
+        let config = CompressionConfig {
+            min_size_bytes: 0,
+            encodings: vec![ContentEncoding::Gzip],
+        };
+
+        assert_eq!(None, choose_encoding(&config, None, &text_response(10)));
+        assert_eq!(
+            None,
+            choose_encoding(&config, Some("br"), &text_response(10))
+        );
+        assert_eq!(
+            None,
+            choose_encoding(&config, Some("gzip;q=0, br"), &text_response(10))
+        );
+
+        let disabled = CompressionConfig {
+            min_size_bytes: 0,
+            encodings: vec![],
+        };
+        assert_eq!(
+            None,
+            choose_encoding(&disabled, Some("*"), &text_response(10))
+        );
+
+        let image = Response::builder()
+            .status(Status::Ok)
+            .content_type(Cow::Borrowed("image/png"))
+            .body_from_vec(vec![0; 10]);
+        assert_eq!(None, choose_encoding(&config, Some("gzip"), &image));
+
+        let already_encoded = Response::builder()
+            .status(Status::Ok)
+            .content_type(Cow::Borrowed("text/plain"))
+            .header(Header {
+                name: Cow::Borrowed("Content-Encoding"),
+                value: Cow::Borrowed("gzip"),
+            })
+            .body_from_vec(vec![b'a'; 10]);
+        assert_eq!(
+            None,
+            choose_encoding(&config, Some("gzip"), &already_encoded)
+        );
+    }
+
+    #[test]
+    fn accept_encoding_wildcards_and_weights_are_honored() {
+        assert!(accepts_encoding("gzip;q=0.5", "gzip"));
+        assert!(accepts_encoding("GZIP", "gzip"));
+        assert!(accepts_encoding("*", "br"));
+        assert!(!accepts_encoding("*, br;q=0", "br"));
+        assert!(!accepts_encoding("*;q=0", "gzip"));
+        assert!(!accepts_encoding("identity", "gzip"));
+    }
+
+    #[test]
+    fn hosts_are_normalized() {
+        assert_eq!("app.example.com", normalize_host("App.Example.COM"));
+        assert_eq!("app.example.com", normalize_host("app.example.com:8080"));
+        assert_eq!("app.example.com", normalize_host("app.example.com."));
+        assert_eq!("::1", normalize_host("[::1]:8080"));
+    }
+
+    #[test]
+    fn registered_domains_resolve_to_their_gateway() {
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let domains: Domains = [("app.example.com".into(), (stack_id, "gw".into()))].into();
+
+        assert_eq!(
+            Some((stack_id, "gw".to_string(), "users/12")),
+            resolve_route(&domains, Some("App.example.com:443"), "users/12")
+        );
+
+        assert_eq!(
+            Some((stack_id, "gw".to_string(), "")),
+            resolve_route(&domains, Some("app.example.com"), "")
+        );
+    }
+
+    #[test]
+    fn unregistered_domains_fall_back_to_path_routing() {
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let other_stack_id = StackID::SolanaPublicKey([2; 32]);
+        let domains: Domains = [("app.example.com".into(), (stack_id, "gw".into()))].into();
+
+        let path = format!("{other_stack_id}/api/users/12");
+        assert_eq!(
+            Some((other_stack_id, "api".to_string(), "users/12")),
+            resolve_route(&domains, Some("other.example.com"), &path)
+        );
+
+        let path = format!("{other_stack_id}/api/users/12");
+        assert_eq!(
+            Some((other_stack_id, "api".to_string(), "users/12")),
+            resolve_route(&domains, None, &path)
+        );
+
+        assert_eq!(None, resolve_route(&domains, None, "not-a-stack/api/users"));
+        assert_eq!(
+            None,
+            resolve_route(&domains, None, &format!("{other_stack_id}/api"))
+        );
+    }
 }
@@ -0,0 +1,194 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use mu_stack::StackID;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+const SHARD_COUNT: usize = 16;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed per second, on average.
+    pub per_second: u32,
+    /// Requests allowed at once after a quiet period.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.per_second == 0 || self.burst == 0 {
+            bail!("Rate limit must allow at least one request per second and a burst of one");
+        }
+        Ok(())
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+// A token bucket per stack. Stacks are spread over several maps, so requests
+// to different stacks rarely wait on the same lock. The limit is passed in
+// with every request, since each stack keeps the one it was deployed with;
+// a bucket that was filled under a larger burst is capped on its next use.
+pub(crate) struct RateLimiter {
+    shards: Vec<RwLock<HashMap<StackID, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARD_COUNT)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+        }
+    }
+
+    /// Takes a token from the stack's bucket, or returns how long until the
+    /// next one is available.
+    pub async fn try_acquire(&self, stack_id: StackID, limit: RateLimit) -> Result<(), Duration> {
+        self.try_acquire_at(stack_id, limit, Instant::now()).await
+    }
+
+    async fn try_acquire_at(
+        &self,
+        stack_id: StackID,
+        limit: RateLimit,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let burst = limit.burst as f64;
+        let per_second = limit.per_second as f64;
+
+        let mut shard = self.shard(&stack_id).write().await;
+        let bucket = shard.entry(stack_id).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * per_second).min(burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    pub async fn remove(&self, stack_id: StackID) {
+        self.shard(&stack_id).write().await.remove(&stack_id);
+    }
+
+    fn shard(&self, stack_id: &StackID) -> &RwLock<HashMap<StackID, Bucket>> {
+        let mut hasher = DefaultHasher::new();
+        stack_id.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARD_COUNT]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use mu_stack::StackID;
+
+    use super::{RateLimit, RateLimiter};
+
+    fn limit(per_second: u32, burst: u32) -> RateLimit {
+        RateLimit { per_second, burst }
+    }
+
+    #[tokio::test]
+    async fn bursts_are_allowed_up_to_the_limit() {
+        let limiter = RateLimiter::new();
+        let limit = limit(1, 3);
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(stack_id, limit, now).await);
+        }
+        assert_eq!(
+            Err(Duration::from_secs(1)),
+            limiter.try_acquire_at(stack_id, limit, now).await
+        );
+
+        // Other stacks have buckets of their own
+        let other_stack_id = StackID::SolanaPublicKey([2; 32]);
+        assert_eq!(
+            Ok(()),
+            limiter.try_acquire_at(other_stack_id, limit, now).await
+        );
+    }
+
+    #[tokio::test]
+    async fn tokens_are_refilled_at_the_steady_rate() {
+        let limiter = RateLimiter::new();
+        let limit = limit(2, 1);
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let start = Instant::now();
+
+        assert_eq!(Ok(()), limiter.try_acquire_at(stack_id, limit, start).await);
+
+        let at = |millis| start + Duration::from_millis(millis);
+        assert_eq!(
+            Err(Duration::from_millis(250)),
+            limiter.try_acquire_at(stack_id, limit, at(250)).await
+        );
+        assert_eq!(
+            Ok(()),
+            limiter.try_acquire_at(stack_id, limit, at(500)).await
+        );
+        assert_eq!(
+            Ok(()),
+            limiter.try_acquire_at(stack_id, limit, at(1000)).await
+        );
+
+        // Quiet periods don't allow more than the burst
+        assert_eq!(
+            Ok(()),
+            limiter.try_acquire_at(stack_id, limit, at(10_000)).await
+        );
+        assert!(limiter
+            .try_acquire_at(stack_id, limit, at(10_000))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn lowered_limits_apply_to_existing_buckets() {
+        let limiter = RateLimiter::new();
+        let stack_id = StackID::SolanaPublicKey([1; 32]);
+        let now = Instant::now();
+
+        assert_eq!(
+            Ok(()),
+            limiter.try_acquire_at(stack_id, limit(1, 10), now).await
+        );
+
+        // The bucket still holds 9 tokens, but no more than the new burst
+        // can be taken
+        let lowered = limit(1, 2);
+        assert_eq!(Ok(()), limiter.try_acquire_at(stack_id, lowered, now).await);
+        assert_eq!(Ok(()), limiter.try_acquire_at(stack_id, lowered, now).await);
+        assert!(limiter
+            .try_acquire_at(stack_id, lowered, now)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn limits_must_allow_some_requests() {
+        assert!(limit(0, 1).validate().is_err());
+        assert!(limit(1, 0).validate().is_err());
+        assert!(limit(1, 1).validate().is_ok());
+    }
+}
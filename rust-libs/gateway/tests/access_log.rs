@@ -0,0 +1,127 @@
+mod common;
+
+use std::{collections::HashMap, sync::Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use mu_gateway::GatewayManagerConfig;
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+// Keeps access log lines, so the test can inspect them
+struct CapturingLogger(Mutex<Vec<(Level, String)>>);
+
+impl Log for CapturingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == "mu_gateway::access"
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(vec![]));
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_are_logged() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let config = GatewayManagerConfig {
+        access_log: true,
+        ..common::config()
+    };
+    let base_url = common::base_url(&config);
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, |_, _| {
+            Box::pin(async {
+                Ok(Response::builder()
+                    .status(Status::Ok)
+                    .body_from_str("hello")
+                    .into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([4; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "users/{id}".into(),
+                    [(
+                        HttpMethod::Post,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "f".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let url = format!("{base_url}/{stack_id}/gw");
+
+    let response = client
+        .post(format!("{url}/users/12"))
+        .body("1234")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client.get(format!("{url}/missing")).send().await.unwrap();
+    assert_eq!(404, response.status().as_u16());
+
+    gateway_manager.stop().await.unwrap();
+
+    let lines = LOGGER.0.lock().unwrap().clone();
+    assert_eq!(2, lines.len(), "{lines:?}");
+
+    let (level, line) = &lines[0];
+    assert_eq!(Level::Info, *level);
+    for field in [
+        "method=POST".to_string(),
+        format!("path=\"/{stack_id}/gw/users/12\""),
+        format!("stack_id={stack_id}"),
+        "gateway=gw".to_string(),
+        "endpoint=/users/{id}".to_string(),
+        "status=200".to_string(),
+        "request_body_bytes=4".to_string(),
+        "response_body_bytes=5".to_string(),
+        "elapsed_ms=".to_string(),
+    ] {
+        assert!(line.contains(&field), "{field} missing from {line}");
+    }
+
+    let (level, line) = &lines[1];
+    assert_eq!(Level::Debug, *level);
+    for field in [
+        "method=GET",
+        "gateway=gw",
+        "endpoint=-",
+        "status=404",
+        "elapsed_ms=-",
+    ] {
+        assert!(line.contains(field), "{field} missing from {line}");
+    }
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+const TOKEN: &str = "Bearer let-me-in";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn auth_function_guards_every_endpoint() {
+    let config = common::config();
+    let base_url = common::base_url(&config);
+
+    let target_invocations = Arc::new(AtomicUsize::new(0));
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, {
+            let target_invocations = target_invocations.clone();
+            move |function_id, request| {
+                let target_invocations = target_invocations.clone();
+                Box::pin(async move {
+                    let response = match function_id.function_name.as_str() {
+                        "auth" => {
+                            let authorized = request.headers.iter().any(|h| {
+                                h.name.eq_ignore_ascii_case("authorization") && h.value == TOKEN
+                            });
+                            if authorized {
+                                Response::builder().status(Status::Ok).body_from_str("")
+                            } else {
+                                Response::builder()
+                                    .status(Status::Unauthorized)
+                                    .body_from_str("denied")
+                            }
+                        }
+                        _ => {
+                            target_invocations.fetch_add(1, Ordering::SeqCst);
+                            Response::builder()
+                                .status(Status::Ok)
+                                .body_from_str("hello")
+                        }
+                    };
+                    Ok(response.into())
+                })
+            }
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([10; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "hello".into(),
+                    [(
+                        HttpMethod::Get,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "hello".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: Some(AssemblyAndFunction {
+                    assembly: "a".into(),
+                    function: "auth".into(),
+                }),
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let url = format!("{base_url}/{stack_id}/gw/hello");
+    let client = reqwest::Client::new();
+
+    // Rejections are returned as-is, without invoking the target function
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!("denied", response.text().await.unwrap());
+
+    let response = client
+        .get(&url)
+        .header("Authorization", "Bearer wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(0, target_invocations.load(Ordering::SeqCst));
+
+    let response = client
+        .get(&url)
+        .header("Authorization", TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("hello", response.text().await.unwrap());
+    assert_eq!(1, target_invocations.load(Ordering::SeqCst));
+
+    gateway_manager.stop().await.unwrap();
+}
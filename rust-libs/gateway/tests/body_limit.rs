@@ -0,0 +1,72 @@
+mod common;
+
+use std::collections::HashMap;
+
+use mu_gateway::GatewayManagerConfig;
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+const MAX_BODY_BYTES: u64 = 16;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_over_body_limit_are_rejected() {
+    let config = GatewayManagerConfig {
+        max_request_body_bytes: Some(MAX_BODY_BYTES),
+        ..common::config()
+    };
+    let base_url = common::base_url(&config);
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, |_, _| {
+            Box::pin(async { Ok(Response::builder().status(Status::Ok).no_body().into()) })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([1; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "upload".into(),
+                    [(
+                        HttpMethod::Post,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "f".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let url = format!("{base_url}/{stack_id}/gw/upload");
+
+    let response = client
+        .post(&url)
+        .body(vec![0u8; MAX_BODY_BYTES as usize + 1])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(413, response.status().as_u16());
+
+    let response = client
+        .post(&url)
+        .body(vec![0u8; MAX_BODY_BYTES as usize])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    gateway_manager.stop().await.unwrap();
+}
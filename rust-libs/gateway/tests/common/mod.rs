@@ -0,0 +1,37 @@
+// Shared setup for the gateway tests. Each file in `tests` is built on its
+// own and only uses some of these.
+#![allow(dead_code)]
+
+use std::net::{Ipv4Addr, TcpListener};
+
+use mu_gateway::{CompressionConfig, GatewayManagerConfig};
+
+/// Listens on a free port on the IPv4 loopback, with everything else at its
+/// default. Tests override what they need with struct update syntax.
+pub fn config() -> GatewayManagerConfig {
+    GatewayManagerConfig {
+        listen_addresses: vec![(Ipv4Addr::LOCALHOST, free_port()).into()],
+        max_request_body_bytes: None,
+        compression: CompressionConfig::default(),
+        access_log: false,
+        rate_limit: None,
+        debug_errors: false,
+        shutdown_timeout_secs: 30,
+        idempotency_ttl_secs: 60,
+    }
+}
+
+/// A port nothing is listening on right now. Tests run in parallel, so none
+/// of them can count on a fixed port being free.
+pub fn free_port() -> u16 {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .unwrap()
+        .port()
+}
+
+/// Where a gateway started with `config` can be reached, e.g.
+/// `http://127.0.0.1:12345`.
+pub fn base_url(config: &GatewayManagerConfig) -> String {
+    format!("http://{}", config.listen_addresses[0])
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::{borrow::Cow, collections::HashMap, io::Read};
+
+use mu_gateway::{CompressionConfig, ContentEncoding, GatewayManagerConfig, Notification};
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+const MIN_SIZE_BYTES: u64 = 1024;
+const LARGE_BODY_BYTES: usize = 64 * 1024;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn large_text_responses_are_compressed() {
+    let config = GatewayManagerConfig {
+        compression: CompressionConfig {
+            min_size_bytes: MIN_SIZE_BYTES,
+            encodings: vec![ContentEncoding::Gzip],
+        },
+        ..common::config()
+    };
+    let base_url = common::base_url(&config);
+
+    let (gateway_manager, mut notifications) =
+        mu_gateway::start_without_additional_services(config, None, |function_id, _| {
+            let body_len = match function_id.function_name.as_str() {
+                "large" => LARGE_BODY_BYTES,
+                _ => MIN_SIZE_BYTES as usize - 1,
+            };
+
+            Box::pin(async move {
+                Ok(Response::builder()
+                    .status(Status::Ok)
+                    .content_type(Cow::Borrowed("text/plain"))
+                    .body_from_vec(vec![b'a'; body_len])
+                    .into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let endpoint = |name: &str| -> (String, HashMap<HttpMethod, AssemblyAndFunction>) {
+        (
+            name.to_string(),
+            [(
+                HttpMethod::Get,
+                AssemblyAndFunction {
+                    assembly: "a".into(),
+                    function: name.into(),
+                },
+            )]
+            .into(),
+        )
+    };
+
+    let stack_id = StackID::SolanaPublicKey([3; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [endpoint("large"), endpoint("small")].into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let get = |name: &str| {
+        client
+            .get(format!("{base_url}/{stack_id}/gw/{name}"))
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip")
+            .send()
+    };
+
+    let response = get("large").await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        Some("gzip"),
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+    );
+
+    let compressed = response.bytes().await.unwrap();
+    assert!(compressed.len() < LARGE_BODY_BYTES);
+
+    let mut body = vec![];
+    flate2::read::GzDecoder::new(compressed.as_ref())
+        .read_to_end(&mut body)
+        .unwrap();
+    assert_eq!(vec![b'a'; LARGE_BODY_BYTES], body);
+
+    // Usage is reported for the compressed body
+    let Some(Notification::ReportUsage { traffic, .. }) = notifications.recv().await else {
+        panic!("Expected a usage report");
+    };
+    assert!(traffic < LARGE_BODY_BYTES as u64);
+
+    let response = get("small").await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .is_none());
+    assert_eq!(
+        MIN_SIZE_BYTES as usize - 1,
+        response.bytes().await.unwrap().len()
+    );
+
+    gateway_manager.stop().await.unwrap();
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::collections::HashMap;
+
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_are_routed_by_domain_or_path() {
+    let config = common::config();
+    let base_url = common::base_url(&config);
+
+    // Responds with the invoked function's stack ID, so we can tell
+    // which gateway the request was routed to
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, |function_id, _| {
+            Box::pin(async move {
+                Ok(Response::builder()
+                    .status(Status::Ok)
+                    .body_from_string(function_id.assembly_id.stack_id.to_string())
+                    .into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let gateway = Gateway {
+        name: "gw".into(),
+        endpoints: [(
+            "hello".into(),
+            [(
+                HttpMethod::Get,
+                AssemblyAndFunction {
+                    assembly: "a".into(),
+                    function: "f".into(),
+                },
+            )]
+            .into(),
+        )]
+        .into(),
+        auth: None,
+        accepted_content_types: HashMap::new(),
+        cors: None,
+    };
+
+    let stack_id = StackID::SolanaPublicKey([1; 32]);
+    let other_stack_id = StackID::SolanaPublicKey([2; 32]);
+    for id in [stack_id, other_stack_id] {
+        gateway_manager
+            .deploy_gateways(id, vec![gateway.clone()])
+            .await
+            .unwrap();
+    }
+
+    gateway_manager
+        .register_domain("app.example.com".into(), stack_id, "gw".into())
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let get = |path: String, host: &'static str| {
+        client
+            .get(format!("{base_url}{path}"))
+            .header(reqwest::header::HOST, host)
+            .send()
+    };
+
+    let response = get("/hello".into(), "app.example.com").await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(stack_id.to_string(), response.text().await.unwrap());
+
+    let response = get(format!("/{other_stack_id}/gw/hello"), "127.0.0.1")
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(other_stack_id.to_string(), response.text().await.unwrap());
+
+    let response = get("/hello".into(), "other.example.com").await.unwrap();
+    assert_eq!(404, response.status().as_u16());
+
+    gateway_manager
+        .delete_gateways(stack_id, vec!["gw".into()])
+        .await
+        .unwrap();
+
+    // The domain went away with its gateway, so the path is used again
+    let response = get(format!("/{other_stack_id}/gw/hello"), "app.example.com")
+        .await
+        .unwrap();
+    assert_eq!(other_stack_id.to_string(), response.text().await.unwrap());
+
+    gateway_manager.stop().await.unwrap();
+}
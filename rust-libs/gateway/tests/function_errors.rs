@@ -0,0 +1,108 @@
+mod common;
+
+use std::collections::HashMap;
+
+use mu_gateway::{FunctionError, FunctionErrorKind, GatewayManager, GatewayManagerConfig};
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+
+const SECRET: &str = "connection string is db://admin:hunter2@db";
+
+async fn start_gateway(debug_errors: bool) -> (Box<dyn GatewayManager>, String) {
+    let config = GatewayManagerConfig {
+        debug_errors,
+        ..common::config()
+    };
+    let url = common::base_url(&config);
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, |function_id, _| {
+            Box::pin(async move {
+                let kind = match function_id.function_name.as_str() {
+                    "panic" => FunctionErrorKind::Failed,
+                    "timeout" => FunctionErrorKind::TimedOut,
+                    "memory" => FunctionErrorKind::MemoryExceeded,
+                    _ => anyhow::bail!("{SECRET}"),
+                };
+                Err(FunctionError {
+                    kind,
+                    message: "details about the failure".into(),
+                }
+                .into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let endpoints = ["panic", "timeout", "memory", "internal"]
+        .into_iter()
+        .map(|function| {
+            (
+                function.to_string(),
+                [(
+                    HttpMethod::Get,
+                    AssemblyAndFunction {
+                        assembly: "a".into(),
+                        function: function.into(),
+                    },
+                )]
+                .into(),
+            )
+        })
+        .collect();
+
+    let stack_id = StackID::SolanaPublicKey([8; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints,
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let base_url = format!("{url}/{stack_id}/gw");
+    (gateway_manager, base_url)
+}
+
+async fn get(url: String) -> (u16, String) {
+    let response = reqwest::get(url).await.unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn function_failures_are_mapped_to_status_codes() {
+    let (gateway_manager, base_url) = start_gateway(false).await;
+
+    for (function, status) in [("panic", 500), ("timeout", 504), ("memory", 507)] {
+        let (actual_status, body) = get(format!("{base_url}/{function}")).await;
+        assert_eq!(status, actual_status, "{function}");
+        assert!(!body.contains("details"), "{function}: {body}");
+    }
+
+    // Errors that aren't the function's fault never reveal anything
+    let (status, body) = get(format!("{base_url}/internal")).await;
+    assert_eq!(500, status);
+    assert!(!body.contains("hunter2"), "{body}");
+
+    gateway_manager.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failure_details_are_included_with_debug_errors() {
+    let (gateway_manager, base_url) = start_gateway(true).await;
+
+    let (status, body) = get(format!("{base_url}/timeout")).await;
+    assert_eq!(504, status);
+    assert_eq!("Function timed out: details about the failure", body);
+
+    let (status, body) = get(format!("{base_url}/internal")).await;
+    assert_eq!(500, status);
+    assert!(!body.contains("hunter2"), "{body}");
+
+    gateway_manager.stop().await.unwrap();
+}
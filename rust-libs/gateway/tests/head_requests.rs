@@ -0,0 +1,92 @@
+mod common;
+
+use std::{borrow::Cow, collections::HashMap};
+
+use mu_gateway::Notification;
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Header, Response, Status};
+
+const BODY: &str = "Hello, HEAD requests";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn head_requests_are_served_by_get_endpoints_without_a_body() {
+    let config = common::config();
+    let base_url = common::base_url(&config);
+
+    let (gateway_manager, mut notifications) =
+        mu_gateway::start_without_additional_services(config, None, |_, _| {
+            Box::pin(async {
+                Ok(Response::builder()
+                    .status(Status::Ok)
+                    .header(Header {
+                        name: Cow::Borrowed("X-Custom"),
+                        value: Cow::Borrowed("value"),
+                    })
+                    .body_from_str(BODY)
+                    .into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([6; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "hello".into(),
+                    [(
+                        HttpMethod::Get,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "f".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let url = format!("{base_url}/{stack_id}/gw/hello");
+
+    let get = client.get(&url).send().await.unwrap();
+    let head = client.head(&url).send().await.unwrap();
+
+    assert_eq!(200, head.status().as_u16());
+
+    let mut get_headers = get.headers().clone();
+    let mut head_headers = head.headers().clone();
+    get_headers.remove(reqwest::header::DATE);
+    head_headers.remove(reqwest::header::DATE);
+    assert_eq!(get_headers, head_headers);
+    assert_eq!(
+        Some(BODY.len().to_string().as_str()),
+        head_headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+    );
+
+    assert_eq!(BODY, get.text().await.unwrap());
+    assert!(head.bytes().await.unwrap().is_empty());
+
+    // Only the headers of the HEAD response are counted as traffic
+    let mut traffic = vec![];
+    for _ in 0..2 {
+        let Some(Notification::ReportUsage { traffic: t, .. }) = notifications.recv().await else {
+            panic!("Expected a usage report");
+        };
+        traffic.push(t);
+    }
+    assert_eq!(traffic[0] - BODY.len() as u64, traffic[1]);
+
+    gateway_manager.stop().await.unwrap();
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use mu_gateway::{IdempotencyKey, IdempotencyStore};
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+#[derive(Clone, Default)]
+struct InMemoryStore(Arc<Mutex<HashMap<IdempotencyKey, Vec<u8>>>>);
+
+#[async_trait]
+impl IdempotencyStore for InMemoryStore {
+    async fn get(&self, key: &IdempotencyKey) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    async fn put(&self, key: &IdempotencyKey, response: Vec<u8>, _ttl: Duration) -> Result<()> {
+        self.0.lock().unwrap().insert(key.clone(), response);
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_with_the_same_idempotency_key_invoke_the_function_once() {
+    let config = common::config();
+    let base_url = common::base_url(&config);
+
+    // Slow enough that concurrent requests overlap, and numbered so cached
+    // responses can be told apart from fresh ones
+    let invocations = Arc::new(AtomicUsize::new(0));
+    let (gateway_manager, _notifications) = mu_gateway::start_without_additional_services(
+        config,
+        Some(Box::new(InMemoryStore::default())),
+        {
+            let invocations = invocations.clone();
+            move |_, _| {
+                let invocations = invocations.clone();
+                Box::pin(async move {
+                    let count = invocations.fetch_add(1, Ordering::SeqCst) + 1;
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Ok(Response::builder()
+                        .status(Status::Created)
+                        .body_from_string(format!("todo {count}"))
+                        .into())
+                })
+            }
+        },
+    )
+    .await
+    .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([11; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "todos".into(),
+                    [(
+                        HttpMethod::Post,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "add_todo".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let send = |key: Option<&'static str>, body: &'static str, user: Option<&'static str>| {
+        let mut request = client
+            .post(format!("{base_url}/{stack_id}/gw/todos"))
+            .body(body);
+        if let Some(key) = key {
+            request = request.header("Idempotency-Key", key);
+        }
+        if let Some(user) = user {
+            request = request.header("Authorization", format!("Bearer {user}"));
+        }
+        async move {
+            let response = request.send().await.unwrap();
+            (response.status().as_u16(), response.text().await.unwrap())
+        }
+    };
+    let post = |key| send(key, "buy milk", None);
+
+    assert_eq!((201, "todo 1".into()), post(Some("first")).await);
+    assert_eq!((201, "todo 1".into()), post(Some("first")).await);
+    assert_eq!(1, invocations.load(Ordering::SeqCst));
+
+    // The second request waits for the first instead of running the function
+    let (a, b) = tokio::join!(post(Some("second")), post(Some("second")));
+    assert_eq!(a, b);
+    assert_eq!(2, invocations.load(Ordering::SeqCst));
+
+    // Requests without a key always reach the function
+    post(None).await;
+    post(None).await;
+    assert_eq!(4, invocations.load(Ordering::SeqCst));
+
+    // Keys are scoped to the credentials they were sent with
+    assert_eq!(
+        (201, "todo 5".into()),
+        send(Some("third"), "buy milk", Some("alice")).await
+    );
+    assert_eq!(
+        (201, "todo 6".into()),
+        send(Some("third"), "buy milk", Some("bob")).await
+    );
+    assert_eq!(
+        (201, "todo 5".into()),
+        send(Some("third"), "buy milk", Some("alice")).await
+    );
+
+    // Reusing a key for a different request is rejected
+    let (status, _) = send(Some("third"), "buy eggs", Some("alice")).await;
+    assert_eq!(422, status);
+    assert_eq!(6, invocations.load(Ordering::SeqCst));
+
+    gateway_manager.stop().await.unwrap();
+}
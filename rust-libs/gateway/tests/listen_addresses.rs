@@ -0,0 +1,109 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+};
+
+use mu_gateway::GatewayManagerConfig;
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+#[test]
+fn single_and_multiple_listen_addresses_are_accepted() {
+    let config: GatewayManagerConfig =
+        serde_yaml::from_str("listen_address: 0.0.0.0\nlisten_port: 12080").unwrap();
+    assert_eq!(
+        vec![SocketAddr::from((Ipv4Addr::UNSPECIFIED, 12080))],
+        config.listen_addresses
+    );
+
+    let config: GatewayManagerConfig = serde_yaml::from_str(
+        "listen_addresses: ['127.0.0.1:12080', '[::1]:12081']\nlisten_port: 12012",
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 12080)),
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 12081)),
+        ],
+        config.listen_addresses
+    );
+
+    assert!(serde_yaml::from_str::<GatewayManagerConfig>("listen_port: 12080").is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_are_served_on_every_listen_address() {
+    let port = common::free_port();
+    let v4 = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, port));
+
+    // Some CI machines don't have IPv6 enabled
+    if TcpListener::bind(v6).is_err() {
+        eprintln!("IPv6 loopback is unavailable, skipping test");
+        return;
+    }
+
+    let config = GatewayManagerConfig {
+        listen_addresses: vec![v4, v6],
+        ..common::config()
+    };
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, |_, _| {
+            Box::pin(async move {
+                Ok(Response::builder()
+                    .status(Status::Ok)
+                    .body_from_string("hello".into())
+                    .into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([9; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "hello".into(),
+                    [(
+                        HttpMethod::Get,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "f".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    for address in [v4, v6] {
+        let response = reqwest::get(format!("http://{address}/{stack_id}/gw/hello"))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!("hello", response.text().await.unwrap());
+    }
+
+    gateway_manager.stop().await.unwrap();
+
+    // Every listener is closed once the gateway stops
+    for address in [v4, v6] {
+        assert!(
+            reqwest::get(format!("http://{address}/{stack_id}/gw/hello"))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use mu_gateway::{GatewayLimits, GatewayManagerConfig, Notification, RateLimit};
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+
+const BURST: u32 = 3;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_over_the_rate_limit_are_rejected() {
+    let config = GatewayManagerConfig {
+        rate_limit: Some(RateLimit {
+            per_second: 1,
+            burst: BURST,
+        }),
+        ..common::config()
+    };
+    let base_url = common::base_url(&config);
+
+    let invocations = Arc::new(AtomicUsize::new(0));
+    let (gateway_manager, mut notifications) = {
+        let invocations = invocations.clone();
+        mu_gateway::start_without_additional_services(config, None, move |_, _| {
+            invocations.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(Response::builder().status(Status::Ok).no_body().into()) })
+        })
+        .await
+        .unwrap()
+    };
+
+    let stack_id = StackID::SolanaPublicKey([5; 32]);
+    gateway_manager
+        .deploy_gateways(stack_id, vec![gateway(HttpMethod::Get)])
+        .await
+        .unwrap();
+
+    let url = format!("{base_url}/{stack_id}/gw/hello");
+
+    for _ in 0..BURST {
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(429, response.status().as_u16());
+    assert_eq!(
+        Some("1"),
+        response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+    );
+
+    // The rejected request never reached the function, but is still
+    // reported as a gateway request
+    assert_eq!(BURST as usize, invocations.load(Ordering::SeqCst));
+    for _ in 0..=BURST {
+        let Some(Notification::ReportUsage { requests, .. }) = notifications.recv().await else {
+            panic!("Expected a usage report");
+        };
+        assert_eq!(1, requests);
+    }
+
+    // A token is available again once the steady rate catches up
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    gateway_manager.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_limits_apply_once_a_stack_is_redeployed() {
+    let config = common::config();
+    let base_url = common::base_url(&config);
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, move |_, _| {
+            Box::pin(async { Ok(Response::builder().status(Status::Ok).no_body().into()) })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([6; 32]);
+    gateway_manager
+        .deploy_gateways(stack_id, vec![gateway(HttpMethod::Post)])
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let url = format!("{base_url}/{stack_id}/gw/hello");
+    let post = |body: &'static str| client.post(&url).body(body).send();
+
+    gateway_manager
+        .set_limits(GatewayLimits {
+            max_request_body_bytes: Some(4),
+            rate_limit: Some(RateLimit {
+                per_second: 1,
+                burst: 1,
+            }),
+        })
+        .await
+        .unwrap();
+
+    // The stack keeps the limits it was deployed with
+    for _ in 0..3 {
+        let response = post("too large").await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    gateway_manager
+        .deploy_gateways(stack_id, vec![gateway(HttpMethod::Post)])
+        .await
+        .unwrap();
+
+    let response = post("too large").await.unwrap();
+    assert_eq!(413, response.status().as_u16());
+
+    let response = post("ok").await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let response = post("ok").await.unwrap();
+    assert_eq!(429, response.status().as_u16());
+
+    gateway_manager.stop().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_limits_are_rejected() {
+    let config = common::config();
+
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, move |_, _| {
+            Box::pin(async { Ok(Response::builder().status(Status::Ok).no_body().into()) })
+        })
+        .await
+        .unwrap();
+
+    assert!(gateway_manager
+        .set_limits(GatewayLimits {
+            max_request_body_bytes: None,
+            rate_limit: Some(RateLimit {
+                per_second: 0,
+                burst: 1,
+            }),
+        })
+        .await
+        .is_err());
+
+    gateway_manager.stop().await.unwrap();
+}
+
+fn gateway(method: HttpMethod) -> Gateway {
+    Gateway {
+        name: "gw".into(),
+        endpoints: [(
+            "hello".into(),
+            [(
+                method,
+                AssemblyAndFunction {
+                    assembly: "a".into(),
+                    function: "f".into(),
+                },
+            )]
+            .into(),
+        )]
+        .into(),
+        auth: None,
+        accepted_content_types: HashMap::new(),
+        cors: None,
+    }
+}
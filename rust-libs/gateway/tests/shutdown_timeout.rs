@@ -0,0 +1,74 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use mu_gateway::GatewayManagerConfig;
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+use tokio::sync::mpsc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stop_does_not_wait_longer_than_the_shutdown_timeout() {
+    let config = GatewayManagerConfig {
+        shutdown_timeout_secs: 1,
+        ..common::config()
+    };
+    let base_url = common::base_url(&config);
+
+    let (started_tx, mut started_rx) = mpsc::unbounded_channel();
+
+    // The function never finishes in time, so only the timeout can end it
+    let (gateway_manager, _notifications) =
+        mu_gateway::start_without_additional_services(config, None, move |_, _| {
+            let started_tx = started_tx.clone();
+            Box::pin(async move {
+                started_tx.send(()).unwrap();
+                tokio::time::sleep(Duration::from_secs(600)).await;
+                Ok(Response::builder().status(Status::Ok).no_body().into())
+            })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([10; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "slow".into(),
+                    [(
+                        HttpMethod::Get,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "f".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let request = tokio::spawn(reqwest::get(format!("{base_url}/{stack_id}/gw/slow")));
+    started_rx.recv().await.unwrap();
+
+    let start = Instant::now();
+    tokio::time::timeout(Duration::from_secs(10), gateway_manager.stop())
+        .await
+        .expect("stop should not wait for the slow request")
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // The in-flight request was dropped rather than answered
+    assert!(request.await.unwrap().is_err());
+}
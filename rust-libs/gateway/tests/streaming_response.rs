@@ -0,0 +1,93 @@
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use mu_gateway::{FunctionResponse, Notification};
+use mu_stack::{AssemblyAndFunction, Gateway, HttpMethod, StackID};
+use musdk_common::{Response, Status};
+use tokio::sync::mpsc;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNK_COUNT: usize = 160;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streamed_responses_reach_the_client() {
+    let config = common::config();
+    let base_url = common::base_url(&config);
+
+    let (gateway_manager, mut notifications) =
+        mu_gateway::start_without_additional_services(config, None, |_, _| {
+            Box::pin(async {
+                let (tx, rx) = mpsc::channel(4);
+                tokio::spawn(async move {
+                    for i in 0..CHUNK_COUNT {
+                        if tx.send(Ok(vec![i as u8; CHUNK_SIZE])).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                Ok(FunctionResponse {
+                    response: Response::builder().status(Status::Ok).no_body(),
+                    body_stream: Some(rx),
+                })
+            })
+        })
+        .await
+        .unwrap();
+
+    let stack_id = StackID::SolanaPublicKey([2; 32]);
+    gateway_manager
+        .deploy_gateways(
+            stack_id,
+            vec![Gateway {
+                name: "gw".into(),
+                endpoints: [(
+                    "download".into(),
+                    [(
+                        HttpMethod::Get,
+                        AssemblyAndFunction {
+                            assembly: "a".into(),
+                            function: "f".into(),
+                        },
+                    )]
+                    .into(),
+                )]
+                .into(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors: None,
+            }],
+        )
+        .await
+        .unwrap();
+
+    let url = format!("{base_url}/{stack_id}/gw/download");
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let body = response.bytes().await.unwrap();
+    assert_eq!(CHUNK_SIZE * CHUNK_COUNT, body.len());
+    for (i, chunk) in body.chunks(CHUNK_SIZE).enumerate() {
+        assert!(chunk.iter().all(|b| *b == i as u8));
+    }
+
+    // The streamed body's traffic is reported separately, once it's done
+    let streamed_traffic = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match notifications.recv().await.unwrap() {
+                Notification::ReportUsage {
+                    traffic,
+                    requests: 0,
+                    ..
+                } => return traffic,
+                Notification::ReportUsage { .. } => (),
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!((CHUNK_SIZE * CHUNK_COUNT) as u64, streamed_traffic);
+
+    gateway_manager.stop().await.unwrap();
+}
@@ -78,7 +78,8 @@ impl<T: Send + 'static> CallbackMailboxProcessor<T> {
     /// * `step` - The step function, invoked once per message.
     /// * `init_state` - The initial state of the mailbox.
     /// * `buffer_size` - The maximum number of messages which will be buffered while waiting for execution.
-    ///     Excessive messages will block the sending task until there is room to buffer them.
+    ///     Excessive messages will block the sending task until there is room to buffer them,
+    ///     or be rejected by [`CallbackMailboxProcessor::try_post`].
     pub fn start<State: Send + 'static, Step, Fut>(
         step: Step,
         init_state: State,
@@ -160,6 +161,18 @@ impl<T: Send + 'static> CallbackMailboxProcessor<T> {
         });
     }
 
+    /// Posts a message to the mailbox without waiting for the response, failing
+    /// with [`Error::MailboxFull`] instead of waiting if the mailbox's buffer is
+    /// full. This lets callers shed load when the mailbox can't keep up.
+    pub fn try_post(&self, msg: T) -> Result<()> {
+        self.sender
+            .try_send(ControlMessage::UserMessage(msg))
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => Error::MailboxFull,
+                mpsc::error::TrySendError::Closed(_) => Error::MailboxStopped,
+            })
+    }
+
     /// Posts a message to the mailbox and waits for it to be processed before
     /// returning.
     pub async fn post(&self, msg: T) -> Result<()> {
@@ -353,6 +366,49 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn try_post_delivers_messages() -> Result<()> {
+        let (mb, _) = make_mb();
+
+        mb.try_post(Message::Increment(5))?;
+        assert_eq!(mb.post_and_reply(Message::Get).await?, 5);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn try_post_reports_full_mailbox() -> Result<()> {
+        let state = Arc::new(Mutex::new(0));
+        let mb = CallbackMailboxProcessor::start(step, state, 1);
+
+        // Keeps the mailbox busy, so nothing else is taken out of the buffer
+        mb.try_post(Message::DelayAndFail)?;
+
+        // Depending on whether the mailbox picked up the first message yet,
+        // there may be room for one more in the buffer
+        let results = (0..2)
+            .map(|_| mb.try_post(Message::Increment(1)))
+            .collect::<Vec<_>>();
+        assert!(results.contains(&Err(Error::MailboxFull)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn try_post_reports_stopped_mailbox() -> Result<()> {
+        let (mb, _) = make_mb();
+
+        let mb2 = mb.clone();
+        mb.stop().await;
+
+        assert_eq!(
+            mb2.try_post(Message::Increment(10)),
+            Err(Error::MailboxStopped)
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn can_send_message_to_self() -> Result<()> {
         let (mb, _) = make_mb();
@@ -8,6 +8,9 @@ pub mod plain;
 pub enum Error {
     #[error("Mailbox is stopped")]
     MailboxStopped,
+
+    #[error("Mailbox is full")]
+    MailboxFull,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,8 +1,10 @@
 pub mod protobuf;
 pub mod protos;
+mod schema;
 pub mod string_serialization;
 mod validation;
 
+pub use schema::stack_schema;
 pub use validation::*;
 
 use std::{
@@ -13,48 +15,135 @@ use std::{
 
 #[rustfmt::skip]
 use ::protobuf::Message;
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, Context, Result};
 use base58::{FromBase58, ToBase58};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytes::{BufMut, Bytes};
+use schemars::JsonSchema;
 use serde::{de::Visitor, Deserialize, Deserializer, Serialize};
 use thiserror::Error;
+use uuid::Uuid;
 
 pub const SOLANA_PUBKEY_SIZE: usize = 32;
+pub const PWR_ADDRESS_SIZE: usize = 20;
+
+/// The blockchains stacks can be deployed from. Stack IDs and owners from
+/// different chains are told apart by the chain's discriminator in their
+/// byte encoding and its prefix in their string encoding.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Chain {
+    Solana,
+    PWR,
+}
+
+impl Chain {
+    pub fn discriminator(&self) -> u8 {
+        match self {
+            Self::Solana => 1,
+            Self::PWR => 2,
+        }
+    }
+
+    pub fn from_discriminator(discriminator: u8) -> Option<Self> {
+        match discriminator {
+            1 => Some(Self::Solana),
+            2 => Some(Self::PWR),
+            _ => None,
+        }
+    }
+
+    pub fn string_prefix(&self) -> char {
+        match self {
+            Self::Solana => 's',
+            Self::PWR => 'p',
+        }
+    }
+
+    pub fn from_string_prefix(prefix: char) -> Option<Self> {
+        match prefix {
+            's' => Some(Self::Solana),
+            'p' => Some(Self::PWR),
+            _ => None,
+        }
+    }
+}
+
+// Splits a `<prefix>_<id>` string into its chain and ID parts
+fn split_chain_prefix(s: &str) -> Option<(Option<Chain>, &str)> {
+    // With an underscore as the second byte, the first one is an ASCII char
+    let bytes = s.as_bytes();
+    if bytes.len() < 3 || bytes[1] != b'_' {
+        return None;
+    }
+    Some((Chain::from_string_prefix(bytes[0] as char), &s[2..]))
+}
+
+// Each variant's ID has a fixed length, so keys containing them can be
+// decoded unambiguously.
+fn to_prefixed_bytes(chain: Chain, bytes: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(bytes.len() + 1);
+    res.push(chain.discriminator());
+    res.put_slice(bytes);
+    res
+}
+
+fn split_discriminator(bytes: &[u8]) -> Result<(Chain, &[u8])> {
+    let (discriminator, rest) = bytes.split_first().context("Empty ID")?;
+    let chain = Chain::from_discriminator(*discriminator)
+        .ok_or_else(|| anyhow!("Unknown chain discriminator {discriminator}"))?;
+    Ok((chain, rest))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StackID {
     SolanaPublicKey([u8; SOLANA_PUBKEY_SIZE]),
+    PWRStackID(Uuid),
 }
 
 impl StackID {
-    pub fn get_bytes(&self) -> &[u8; SOLANA_PUBKEY_SIZE] {
+    pub fn chain(&self) -> Chain {
         match self {
-            Self::SolanaPublicKey(key) => key,
+            Self::SolanaPublicKey(_) => Chain::Solana,
+            Self::PWRStackID(_) => Chain::PWR,
         }
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut res = Vec::with_capacity(SOLANA_PUBKEY_SIZE + 1);
+    /// The ID's bytes, without the chain discriminator. The length is fixed
+    /// for each chain, but differs between chains.
+    pub fn get_bytes(&self) -> &[u8] {
         match self {
-            Self::SolanaPublicKey(key) => {
-                res.push(1u8);
-                res.put_slice(key);
-            }
+            Self::SolanaPublicKey(key) => key,
+            Self::PWRStackID(id) => id.as_bytes(),
         }
-        res
     }
 
-    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() != SOLANA_PUBKEY_SIZE + 1 {
-            bail!("Incorrect byte count");
-        }
-
-        match bytes[0] {
-            // We already know we have exactly enough bytes, so it's safe to unwrap
-            1u8 => Ok(Self::SolanaPublicKey(bytes[1..].try_into().unwrap())),
+    pub fn to_bytes(&self) -> Vec<u8> {
+        to_prefixed_bytes(self.chain(), self.get_bytes())
+    }
 
-            x => bail!("Unknown StackID discriminator {x}"),
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (chain, bytes) = split_discriminator(bytes)?;
+        match chain {
+            Chain::Solana => Ok(Self::SolanaPublicKey(
+                bytes.try_into().context("Incorrect byte count")?,
+            )),
+            Chain::PWR => Ok(Self::PWRStackID(
+                Uuid::from_slice(bytes).context("Incorrect byte count")?,
+            )),
         }
     }
 }
@@ -65,14 +154,17 @@ impl Debug for StackID {
             Self::SolanaPublicKey(pk) => {
                 write!(f, "<Solana public key (base58): {}>", pk.to_base58())
             }
+            Self::PWRStackID(id) => write!(f, "<PWR stack ID: {id}>"),
         }
     }
 }
 
 impl Display for StackID {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = self.chain().string_prefix();
         match self {
-            Self::SolanaPublicKey(pk) => write!(f, "s_{}", pk.to_base58()),
+            Self::SolanaPublicKey(pk) => write!(f, "{prefix}_{}", pk.to_base58()),
+            Self::PWRStackID(id) => write!(f, "{prefix}_{id}"),
         }
     }
 }
@@ -93,15 +185,10 @@ impl FromStr for StackID {
     type Err = ParseStackIDError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() < 3 || s.chars().nth(1) != Some('_') {
-            return Err(ParseStackIDError::InvalidFormat);
-        }
+        let (chain, code) = split_chain_prefix(s).ok_or(ParseStackIDError::InvalidFormat)?;
 
-        let variant_code = s.chars().next();
-
-        match variant_code {
-            Some('s') => {
-                let (_, code) = s.split_at(2);
+        match chain {
+            Some(Chain::Solana) => {
                 let bytes = code.from_base58().map_err(|_| {
                     ParseStackIDError::FailedToParse(anyhow!("Failed to parse base58 string"))
                 })?;
@@ -109,7 +196,10 @@ impl FromStr for StackID {
                     |_| ParseStackIDError::FailedToParse(anyhow!("Solana pubkey length mismatch")),
                 )?))
             }
-            _ => Err(ParseStackIDError::UnknownVariant),
+            Some(Chain::PWR) => Ok(Self::PWRStackID(
+                Uuid::parse_str(code).map_err(|e| ParseStackIDError::FailedToParse(e.into()))?,
+            )),
+            None => Err(ParseStackIDError::UnknownVariant),
         }
     }
 }
@@ -117,20 +207,55 @@ impl FromStr for StackID {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StackOwner {
     Solana([u8; SOLANA_PUBKEY_SIZE]),
+    PWR([u8; PWR_ADDRESS_SIZE]),
 }
 
 impl StackOwner {
-    // TODO: violates multi-chain
-    pub fn to_inner(&self) -> [u8; SOLANA_PUBKEY_SIZE] {
-        let StackOwner::Solana(pk) = self;
-        *pk
+    pub fn chain(&self) -> Chain {
+        match self {
+            Self::Solana(_) => Chain::Solana,
+            Self::PWR(_) => Chain::PWR,
+        }
+    }
+
+    /// The owner's address, without the chain discriminator. The length is
+    /// fixed for each chain, but differs between chains.
+    pub fn get_bytes(&self) -> &[u8] {
+        match self {
+            Self::Solana(pk) => pk,
+            Self::PWR(address) => address,
+        }
+    }
+
+    /// The owner's public key, if they're a Solana account.
+    pub fn solana_public_key(&self) -> Option<[u8; SOLANA_PUBKEY_SIZE]> {
+        match self {
+            Self::Solana(pk) => Some(*pk),
+            Self::PWR(_) => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        to_prefixed_bytes(self.chain(), self.get_bytes())
+    }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (chain, bytes) = split_discriminator(bytes)?;
+        match chain {
+            Chain::Solana => Ok(Self::Solana(
+                bytes.try_into().context("Incorrect byte count")?,
+            )),
+            Chain::PWR => Ok(Self::PWR(bytes.try_into().context("Incorrect byte count")?)),
+        }
     }
 }
 
 impl Display for StackOwner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = self.chain().string_prefix();
         match self {
-            Self::Solana(pk) => write!(f, "s_{}", pk.to_base58()),
+            Self::Solana(pk) => write!(f, "{prefix}_{}", pk.to_base58()),
+            Self::PWR(address) => write!(f, "{prefix}_{}", to_hex(address)),
         }
     }
 }
@@ -151,15 +276,10 @@ impl FromStr for StackOwner {
     type Err = ParseStackOwnerError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() < 3 || s.chars().nth(1) != Some('_') {
-            return Err(ParseStackOwnerError::InvalidFormat);
-        }
-
-        let variant_code = s.chars().next();
+        let (chain, code) = split_chain_prefix(s).ok_or(ParseStackOwnerError::InvalidFormat)?;
 
-        match variant_code {
-            Some('s') => {
-                let (_, code) = s.split_at(2);
+        match chain {
+            Some(Chain::Solana) => {
                 let bytes = code.from_base58().map_err(|_| {
                     ParseStackOwnerError::FailedToParse(anyhow!("Failed to parse base58 string"))
                 })?;
@@ -167,7 +287,15 @@ impl FromStr for StackOwner {
                     ParseStackOwnerError::FailedToParse(anyhow!("Solana pubkey length mismatch"))
                 })?))
             }
-            _ => Err(ParseStackOwnerError::UnknownVariant),
+            Some(Chain::PWR) => {
+                let bytes = from_hex(code).ok_or_else(|| {
+                    ParseStackOwnerError::FailedToParse(anyhow!("Failed to parse hex string"))
+                })?;
+                Ok(Self::PWR(bytes.as_slice().try_into().map_err(|_| {
+                    ParseStackOwnerError::FailedToParse(anyhow!("PWR address length mismatch"))
+                })?))
+            }
+            None => Err(ParseStackOwnerError::UnknownVariant),
         }
     }
 }
@@ -202,7 +330,7 @@ impl Display for FunctionID {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone)]
 pub struct Stack {
     pub name: String,
     pub version: String,
@@ -215,6 +343,12 @@ impl Stack {
         validate(self)
     }
 
+    /// Every problem with the stack, rather than just the first one
+    /// [`Stack::validate`] stops at. Empty if the stack is valid.
+    pub fn validation_errors(&self) -> Vec<StackValidationError> {
+        validation_errors(self)
+    }
+
     pub fn serialize_to_proto(self) -> Result<Bytes> {
         let stack: crate::protos::stack::Stack = self.into();
         Ok(stack.write_to_bytes()?.into())
@@ -224,6 +358,19 @@ impl Stack {
         crate::protos::stack::Stack::parse_from_bytes(bytes.as_ref())?.try_into()
     }
 
+    /// Parses a stack from YAML. If a service fails to parse, the error
+    /// includes the index and (if available) the name of that service.
+    pub fn from_yaml(yaml: &str) -> Result<Stack> {
+        serde_yaml::from_str(yaml).map_err(|e| match find_invalid_service(yaml) {
+            Some(context) => anyhow!(e).context(context),
+            None => anyhow!(e).context("Failed to parse stack"),
+        })
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self).context("Failed to serialize stack to YAML")
+    }
+
     pub fn key_value_tables(&self) -> impl Iterator<Item = &NameAndDelete> {
         self.services.iter().filter_map(|s| match s {
             Service::KeyValueTable(x) => Some(x),
@@ -253,7 +400,23 @@ impl Stack {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// Re-parses the services one by one to find the first one that fails,
+// so we can point the user to it.
+fn find_invalid_service(yaml: &str) -> Option<String> {
+    let value: serde_yaml::Value = serde_yaml::from_str(yaml).ok()?;
+    let services = value.get("services")?.as_sequence()?;
+
+    services.iter().enumerate().find_map(|(index, service)| {
+        serde_yaml::from_value::<Service>(service.clone())
+            .err()
+            .map(|_| match service.get("name").and_then(|n| n.as_str()) {
+                Some(name) => format!("Failed to parse service #{index} ({name})"),
+                None => format!("Failed to parse service #{index}"),
+            })
+    })
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum Service {
     KeyValueTable(NameAndDelete),
@@ -262,33 +425,62 @@ pub enum Service {
     Function(Function),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct NameAndDelete {
     pub name: String,
     pub delete: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Gateway {
     pub name: String,
+    #[schemars(schema_with = "schema::endpoints")]
     pub endpoints: HashMap<String, HashMap<HttpMethod, AssemblyAndFunction>>,
+    /// Invoked with each request before its target function; a non-2xx
+    /// response is returned to the caller instead of invoking the target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AssemblyAndFunction>,
+    /// Media types accepted in request bodies, by endpoint path. Requests
+    /// with a body of any other type are rejected with 415.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub accepted_content_types: HashMap<String, Vec<String>>,
+    /// Without a CORS policy, no CORS headers are sent and preflight
+    /// requests are routed to the stack's functions like any other request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<GatewayCors>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GatewayCors {
+    /// Origins allowed to make cross-origin requests, or `*` for any origin.
+    pub allowed_origins: Vec<String>,
+    /// If empty, all methods are allowed.
+    #[serde(default)]
+    pub allowed_methods: Vec<HttpMethod>,
+    /// Request headers allowed in cross-origin requests, in addition to
+    /// the ones browsers always allow.
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
 }
 
 impl Gateway {
     // Strip leading slashes from urls, since that's the format rocket provides
     pub fn clone_normalized(&self) -> Self {
-        let mut ep = HashMap::new();
-        for (url, endpoint) in &self.endpoints {
-            if let Some(stripped) = url.strip_prefix('/') {
-                ep.insert(stripped.to_string(), endpoint.clone());
-            } else {
-                ep.insert(url.clone(), endpoint.clone());
-            }
+        fn normalize<T: Clone>(map: &HashMap<String, T>) -> HashMap<String, T> {
+            map.iter()
+                .map(|(url, value)| {
+                    let url = url.strip_prefix('/').unwrap_or(url);
+                    (url.to_string(), value.clone())
+                })
+                .collect()
         }
 
         Self {
             name: self.name.clone(),
-            endpoints: ep,
+            endpoints: normalize(&self.endpoints),
+            auth: self.auth.clone(),
+            accepted_content_types: normalize(&self.accepted_content_types),
+            cors: self.cors.clone(),
         }
     }
 }
@@ -348,6 +540,7 @@ impl<'de> Visitor<'de> for AssemblyAndFunctionDeserializeVisitor {
     Deserialize,
     BorshSerialize,
     BorshDeserialize,
+    JsonSchema,
     Debug,
     Clone,
     Copy,
@@ -366,16 +559,62 @@ pub enum HttpMethod {
     Options,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Function {
     pub name: String,
     pub binary: String,
     pub runtime: AssemblyRuntime,
     pub env: HashMap<String, String>,
+    /// Env vars whose values are resolved from the stack's secrets at
+    /// instantiation, mapping env var name to secret name.
+    #[serde(default)]
+    pub secrets: HashMap<String, String>,
+    #[schemars(schema_with = "schema::byte_size")]
     pub memory_limit: byte_unit::Byte,
+    /// How `binary` is compressed. It's decompressed before being compiled.
+    #[serde(default)]
+    pub compression: BinaryCompression,
+    /// Pins the function to a compiler backend instead of the region's
+    /// default, e.g. to work around a backend bug.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiler: Option<WasmCompiler>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+/// The wasmer backend used to compile assemblies. Compiled modules are
+/// cached per backend, so switching backends recompiles them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WasmCompiler {
+    /// Slowest to compile, but produces the fastest code.
+    #[default]
+    Llvm,
+    /// Compiles much faster than LLVM, with somewhat slower code.
+    Cranelift,
+    /// Compiles in linear time, so it's safe against compiler bombs, but
+    /// produces the slowest code.
+    Singlepass,
+}
+
+impl WasmCompiler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Llvm => "llvm",
+            Self::Cranelift => "cranelift",
+            Self::Singlepass => "singlepass",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BinaryCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug)]
 pub enum AssemblyRuntime {
     #[serde(rename = "wasi1.0")]
     Wasi1_0,
@@ -395,3 +634,129 @@ impl Display for HttpMethod {
         std::fmt::Display::fmt(s, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack_ids() -> [StackID; 2] {
+        [
+            StackID::SolanaPublicKey([7; SOLANA_PUBKEY_SIZE]),
+            StackID::PWRStackID(Uuid::from_bytes([9; 16])),
+        ]
+    }
+
+    fn stack_owners() -> [StackOwner; 2] {
+        [
+            StackOwner::Solana([7; SOLANA_PUBKEY_SIZE]),
+            StackOwner::PWR([0xab; PWR_ADDRESS_SIZE]),
+        ]
+    }
+
+    #[test]
+    fn stack_ids_round_trip_through_bytes() {
+        for id in stack_ids() {
+            assert_eq!(StackID::try_from_bytes(&id.to_bytes()).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn stack_ids_round_trip_through_strings() {
+        for id in stack_ids() {
+            assert_eq!(id.to_string().parse::<StackID>().unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn stack_owners_round_trip_through_bytes() {
+        for owner in stack_owners() {
+            assert_eq!(
+                StackOwner::try_from_bytes(&owner.to_bytes()).unwrap(),
+                owner
+            );
+        }
+    }
+
+    #[test]
+    fn stack_owners_round_trip_through_strings() {
+        for owner in stack_owners() {
+            assert_eq!(owner.to_string().parse::<StackOwner>().unwrap(), owner);
+        }
+    }
+
+    #[test]
+    fn ids_of_one_chain_are_not_read_as_another() {
+        let [solana, pwr] = stack_ids();
+
+        let mut solana_bytes = solana.to_bytes();
+        solana_bytes[0] = Chain::PWR.discriminator();
+        assert!(StackID::try_from_bytes(&solana_bytes).is_err());
+
+        let mut pwr_bytes = pwr.to_bytes();
+        pwr_bytes[0] = Chain::Solana.discriminator();
+        assert!(StackID::try_from_bytes(&pwr_bytes).is_err());
+
+        let pwr_string = pwr.to_string().replacen('p', "s", 1);
+        assert!(pwr_string.parse::<StackID>().is_err());
+    }
+
+    const STACK_YAML: &str = r#"
+name: stack
+version: "1.0"
+services:
+  - type: KeyValueTable
+    name: table
+  - type: Function
+    name: func
+    binary: func.wasm
+    runtime: wasi1.0
+    env: {}
+    memory_limit: 64MiB
+"#;
+
+    #[test]
+    fn stacks_round_trip_through_yaml() {
+        let stack = Stack::from_yaml(STACK_YAML).unwrap();
+        assert_eq!(stack.name, "stack");
+        assert_eq!(stack.functions().next().unwrap().name, "func");
+
+        let reparsed = Stack::from_yaml(&stack.to_yaml().unwrap()).unwrap();
+        assert_eq!(reparsed.name, stack.name);
+        assert_eq!(reparsed.version, stack.version);
+        assert_eq!(reparsed.services.len(), 2);
+        assert_eq!(reparsed.key_value_tables().next().unwrap().name, "table");
+    }
+
+    #[test]
+    fn yaml_errors_point_to_the_invalid_service() {
+        let yaml = STACK_YAML.replace("runtime: wasi1.0", "runtime: wasi9.9");
+
+        let error = format!("{:#}", Stack::from_yaml(&yaml).unwrap_err());
+
+        assert!(
+            error.starts_with("Failed to parse service #1 (func)"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn yaml_errors_outside_services_are_still_reported() {
+        let error = format!("{:#}", Stack::from_yaml("name: [").unwrap_err());
+
+        assert!(error.starts_with("Failed to parse stack"), "{error}");
+    }
+
+    #[test]
+    fn unknown_chains_are_rejected() {
+        assert!(StackID::try_from_bytes(&[0; 17]).is_err());
+        assert!(StackID::try_from_bytes(&[]).is_err());
+        assert!(matches!(
+            "x_abc".parse::<StackID>(),
+            Err(ParseStackIDError::UnknownVariant)
+        ));
+        assert!(matches!(
+            "x_abc".parse::<StackOwner>(),
+            Err(ParseStackOwnerError::UnknownVariant)
+        ));
+    }
+}
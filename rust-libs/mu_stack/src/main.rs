@@ -39,6 +39,16 @@ enum Command {
         )]
         out_file: Option<String>,
     },
+
+    /// Print a JSON Schema for stack manifests, for use in editors
+    Schema {
+        #[arg(
+            short,
+            long,
+            help = "Output file name, will write to stdout if not provided"
+        )]
+        out_file: Option<String>,
+    },
 }
 
 fn read_file_or_stdin(path: &Option<String>) -> Result<String> {
@@ -73,7 +83,7 @@ fn main() -> anyhow::Result<()> {
     match command {
         Command::YamlToProto { in_file, out_file } => {
             let yaml = read_file_or_stdin(&in_file)?;
-            let stack: mu_stack::Stack = serde_yaml::from_str(yaml.as_ref())?;
+            let stack = mu_stack::Stack::from_yaml(yaml.as_ref())?;
             let proto = stack.serialize_to_proto()?;
             let base64 = base64::engine::general_purpose::STANDARD.encode(proto);
             write_file_or_stdout(&out_file, base64)?;
@@ -83,9 +93,14 @@ fn main() -> anyhow::Result<()> {
             let base64 = read_file_or_stdin(&in_file)?;
             let proto = base64::engine::general_purpose::STANDARD.decode(base64.trim())?;
             let stack = mu_stack::Stack::try_deserialize_proto(proto)?;
-            let yaml = serde_yaml::to_string(&stack)?;
+            let yaml = stack.to_yaml()?;
             write_file_or_stdout(&out_file, yaml)?;
         }
+
+        Command::Schema { out_file } => {
+            let schema = serde_json::to_string_pretty(&mu_stack::stack_schema())?;
+            write_file_or_stdout(&out_file, schema)?;
+        }
     }
 
     Ok(())
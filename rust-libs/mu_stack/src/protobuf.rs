@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::protos::stack::*;
 use anyhow::{anyhow, Result};
-use protobuf::EnumOrUnknown;
+use protobuf::{EnumOrUnknown, MessageField};
 
 impl From<super::Stack> for Stack {
     fn from(stack: super::Stack) -> Self {
@@ -26,6 +26,29 @@ impl From<super::Stack> for Stack {
             }
         }
 
+        fn convert_binary_compression(
+            compression: super::BinaryCompression,
+        ) -> EnumOrUnknown<BinaryCompression> {
+            match compression {
+                super::BinaryCompression::None => EnumOrUnknown::new(BinaryCompression::NONE),
+                super::BinaryCompression::Gzip => EnumOrUnknown::new(BinaryCompression::GZIP),
+                super::BinaryCompression::Zstd => EnumOrUnknown::new(BinaryCompression::ZSTD),
+            }
+        }
+
+        fn convert_wasm_compiler(
+            compiler: Option<super::WasmCompiler>,
+        ) -> EnumOrUnknown<WasmCompiler> {
+            match compiler {
+                None => EnumOrUnknown::new(WasmCompiler::DEFAULT),
+                Some(super::WasmCompiler::Llvm) => EnumOrUnknown::new(WasmCompiler::LLVM),
+                Some(super::WasmCompiler::Cranelift) => EnumOrUnknown::new(WasmCompiler::CRANELIFT),
+                Some(super::WasmCompiler::Singlepass) => {
+                    EnumOrUnknown::new(WasmCompiler::SINGLEPASS)
+                }
+            }
+        }
+
         Stack {
             name: stack.name,
             version: stack.version,
@@ -49,13 +72,17 @@ impl From<super::Stack> for Stack {
                         })),
                         ..Default::default()
                     },
-                    super::Service::Gateway(g) => Service {
+                    super::Service::Gateway(mut g) => Service {
                         service: Some(service::Service::Gateway(Gateway {
                             name: g.name,
                             endpoints: g
                                 .endpoints
                                 .into_iter()
                                 .map(|(path, eps)| GatewayEndpoints {
+                                    accepted_content_types: g
+                                        .accepted_content_types
+                                        .remove(&path)
+                                        .unwrap_or_default(),
                                     path,
                                     endpoints: eps
                                         .into_iter()
@@ -69,6 +96,25 @@ impl From<super::Stack> for Stack {
                                     ..Default::default()
                                 })
                                 .collect(),
+                            auth: MessageField(g.auth.map(|auth| {
+                                Box::new(GatewayAuth {
+                                    assembly: auth.assembly,
+                                    function: auth.function,
+                                    ..Default::default()
+                                })
+                            })),
+                            cors: MessageField(g.cors.map(|cors| {
+                                Box::new(GatewayCors {
+                                    allowed_origins: cors.allowed_origins,
+                                    allowed_methods: cors
+                                        .allowed_methods
+                                        .into_iter()
+                                        .map(convert_http_method)
+                                        .collect(),
+                                    allowed_headers: cors.allowed_headers,
+                                    ..Default::default()
+                                })
+                            })),
                             ..Default::default()
                         })),
                         ..Default::default()
@@ -86,8 +132,19 @@ impl From<super::Stack> for Stack {
                                     ..Default::default()
                                 })
                                 .collect(),
+                            secrets: f
+                                .secrets
+                                .into_iter()
+                                .map(|(env_name, secret_name)| SecretRef {
+                                    env_name,
+                                    secret_name,
+                                    ..Default::default()
+                                })
+                                .collect(),
                             runtime: convert_function_runtime(f.runtime),
                             memoryLimit: f.memory_limit.get_bytes(),
+                            compression: convert_binary_compression(f.compression),
+                            compiler: convert_wasm_compiler(f.compiler),
                             ..Default::default()
                         })),
                         ..Default::default()
@@ -129,6 +186,33 @@ impl TryFrom<Stack> for super::Stack {
                 .map_err(|i| anyhow!("Unknown enum value {i} for type FunctionRuntime"))
         }
 
+        fn convert_binary_compression(
+            compression: EnumOrUnknown<BinaryCompression>,
+        ) -> Result<super::BinaryCompression> {
+            compression
+                .enum_value()
+                .map(|c| match c {
+                    BinaryCompression::NONE => super::BinaryCompression::None,
+                    BinaryCompression::GZIP => super::BinaryCompression::Gzip,
+                    BinaryCompression::ZSTD => super::BinaryCompression::Zstd,
+                })
+                .map_err(|i| anyhow!("Unknown enum value {i} for type BinaryCompression"))
+        }
+
+        fn convert_wasm_compiler(
+            compiler: EnumOrUnknown<WasmCompiler>,
+        ) -> Result<Option<super::WasmCompiler>> {
+            compiler
+                .enum_value()
+                .map(|c| match c {
+                    WasmCompiler::DEFAULT => None,
+                    WasmCompiler::LLVM => Some(super::WasmCompiler::Llvm),
+                    WasmCompiler::CRANELIFT => Some(super::WasmCompiler::Cranelift),
+                    WasmCompiler::SINGLEPASS => Some(super::WasmCompiler::Singlepass),
+                })
+                .map_err(|i| anyhow!("Unknown enum value {i} for type WasmCompiler"))
+        }
+
         Ok(super::Stack {
             name: stack.name,
             version: stack.version,
@@ -153,6 +237,13 @@ impl TryFrom<Stack> for super::Stack {
                     }
 
                     Some(service::Service::Gateway(g)) => {
+                        let accepted_content_types = g
+                            .endpoints
+                            .iter()
+                            .filter(|eps| !eps.accepted_content_types.is_empty())
+                            .map(|eps| (eps.path.clone(), eps.accepted_content_types.clone()))
+                            .collect();
+
                         Ok(super::Service::Gateway(super::Gateway {
                             name: g.name,
                             endpoints: g
@@ -176,6 +267,26 @@ impl TryFrom<Stack> for super::Stack {
                                     ))
                                 })
                                 .collect::<Result<super::HashMap<_, _>, _>>()?,
+                            auth: g.auth.into_option().map(|auth| crate::AssemblyAndFunction {
+                                assembly: auth.assembly,
+                                function: auth.function,
+                            }),
+                            accepted_content_types,
+                            cors: g
+                                .cors
+                                .into_option()
+                                .map(|cors| {
+                                    anyhow::Ok(crate::GatewayCors {
+                                        allowed_origins: cors.allowed_origins,
+                                        allowed_methods: cors
+                                            .allowed_methods
+                                            .into_iter()
+                                            .map(convert_http_method)
+                                            .collect::<Result<_>>()?,
+                                        allowed_headers: cors.allowed_headers,
+                                    })
+                                })
+                                .transpose()?,
                         }))
                     }
 
@@ -184,8 +295,15 @@ impl TryFrom<Stack> for super::Stack {
                             name: f.name,
                             binary: f.binary,
                             env: f.env.into_iter().map(|env| (env.name, env.value)).collect(),
+                            secrets: f
+                                .secrets
+                                .into_iter()
+                                .map(|s| (s.env_name, s.secret_name))
+                                .collect(),
                             runtime: convert_function_runtime(f.runtime)?,
                             memory_limit: byte_unit::Byte::from_bytes(f.memoryLimit),
+                            compression: convert_binary_compression(f.compression)?,
+                            compiler: convert_wasm_compiler(f.compiler)?,
                         }))
                     }
                 })
@@ -193,3 +311,99 @@ impl TryFrom<Stack> for super::Stack {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        AssemblyRuntime, Function, Gateway, GatewayCors, HttpMethod, Service, Stack, WasmCompiler,
+    };
+
+    fn stack_with_gateway(cors: Option<GatewayCors>) -> Stack {
+        Stack {
+            name: "stack".into(),
+            version: "1.0".into(),
+            services: vec![Service::Gateway(Gateway {
+                name: "gw".into(),
+                endpoints: HashMap::new(),
+                auth: None,
+                accepted_content_types: HashMap::new(),
+                cors,
+            })],
+        }
+    }
+
+    fn gateway_cors(stack: &Stack) -> Option<GatewayCors> {
+        stack.gateways().next().unwrap().cors.clone()
+    }
+
+    fn cors() -> GatewayCors {
+        GatewayCors {
+            allowed_origins: vec!["https://example.com".into()],
+            allowed_methods: vec![HttpMethod::Get, HttpMethod::Post],
+            allowed_headers: vec!["Authorization".into()],
+        }
+    }
+
+    #[test]
+    fn gateway_cors_survives_proto_round_trip() {
+        let bytes = stack_with_gateway(Some(cors()))
+            .serialize_to_proto()
+            .unwrap();
+        let stack = Stack::try_deserialize_proto(bytes).unwrap();
+
+        assert_eq!(Some(cors()), gateway_cors(&stack));
+    }
+
+    #[test]
+    fn missing_gateway_cors_survives_proto_round_trip() {
+        let bytes = stack_with_gateway(None).serialize_to_proto().unwrap();
+        let stack = Stack::try_deserialize_proto(bytes).unwrap();
+
+        assert_eq!(None, gateway_cors(&stack));
+    }
+
+    #[test]
+    fn gateway_cors_survives_yaml_round_trip() {
+        let yaml = stack_with_gateway(Some(cors())).to_yaml().unwrap();
+        let stack = Stack::from_yaml(&yaml).unwrap();
+        assert_eq!(Some(cors()), gateway_cors(&stack));
+
+        let yaml = stack_with_gateway(None).to_yaml().unwrap();
+        assert!(!yaml.contains("cors"));
+        assert_eq!(None, gateway_cors(&Stack::from_yaml(&yaml).unwrap()));
+    }
+
+    fn stack_with_function(compiler: Option<WasmCompiler>) -> Stack {
+        Stack {
+            name: "stack".into(),
+            version: "1.0".into(),
+            services: vec![Service::Function(Function {
+                name: "f".into(),
+                binary: "f.wasm".into(),
+                runtime: AssemblyRuntime::Wasi1_0,
+                env: HashMap::new(),
+                secrets: HashMap::new(),
+                memory_limit: byte_unit::Byte::from_bytes(1024 * 1024),
+                compression: Default::default(),
+                compiler,
+            })],
+        }
+    }
+
+    #[test]
+    fn function_compiler_survives_proto_round_trip() {
+        for compiler in [
+            None,
+            Some(WasmCompiler::Llvm),
+            Some(WasmCompiler::Cranelift),
+            Some(WasmCompiler::Singlepass),
+        ] {
+            let bytes = stack_with_function(compiler).serialize_to_proto().unwrap();
+            let stack = Stack::try_deserialize_proto(bytes).unwrap();
+
+            assert_eq!(compiler, stack.functions().next().unwrap().compiler);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{
+        InstanceType, Metadata, ObjectValidation, RootSchema, Schema, SchemaObject, SingleOrVec,
+        StringValidation,
+    },
+    schema_for, JsonSchema,
+};
+
+use crate::{AssemblyAndFunction, HttpMethod, Stack};
+
+/// JSON Schema for stack manifests, for editors to validate and autocomplete
+/// YAML against. Only the structure is described; use [`Stack::validate`] to
+/// check names and endpoint paths.
+pub fn stack_schema() -> RootSchema {
+    schema_for!(Stack)
+}
+
+impl JsonSchema for AssemblyAndFunction {
+    fn schema_name() -> String {
+        "AssemblyAndFunction".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Two identifiers separated by a dot, such as `assembly_name.function_name`"
+                        .into(),
+                ),
+                ..Default::default()
+            })),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^[^.]*\..*$".into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+// Maps don't carry a schema for their keys, but editors can complete the HTTP
+// methods if we spell them out as property names.
+pub(crate) fn endpoints(gen: &mut SchemaGenerator) -> Schema {
+    let methods = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            property_names: Some(Box::new(gen.subschema_for::<HttpMethod>())),
+            additional_properties: Some(Box::new(gen.subschema_for::<AssemblyAndFunction>())),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            additional_properties: Some(Box::new(methods.into())),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+pub(crate) fn byte_size(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(SingleOrVec::Vec(vec![
+            InstanceType::String,
+            InstanceType::Integer,
+        ])),
+        metadata: Some(Box::new(Metadata {
+            description: Some("A number of bytes, optionally with a unit such as `64MiB`".into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonschema::JSONSchema;
+
+    use super::*;
+
+    const VALID: &str = r#"
+name: my_stack
+version: 0.1.0
+services:
+  - type: KeyValueTable
+    name: users
+  - type: Storage
+    name: files
+    delete: true
+  - type: Function
+    name: greeter
+    binary: greeter.wasm
+    runtime: wasi1.0
+    env: {}
+    memory_limit: 64MiB
+  - type: Gateway
+    name: gw
+    endpoints:
+      /greet/{name}:
+        get: greeter.greet_user
+        post: greeter.greet_post
+    cors:
+      allowed_origins: ["*"]
+"#;
+
+    fn is_valid(yaml: &str) -> bool {
+        let schema = serde_json::to_value(stack_schema()).unwrap();
+        let schema = JSONSchema::compile(&schema).unwrap();
+        let instance: serde_json::Value = serde_yaml::from_str(yaml).unwrap();
+        schema.is_valid(&instance)
+    }
+
+    #[test]
+    fn known_good_manifest_matches_schema() {
+        Stack::from_yaml(VALID).unwrap();
+        assert!(is_valid(VALID));
+    }
+
+    #[test]
+    fn known_bad_manifests_do_not_match_schema() {
+        let bad = [
+            VALID.replace("type: Storage", "type: Database"),
+            VALID.replace("    memory_limit: 64MiB\n", ""),
+            VALID.replace("get: greeter", "fetch: greeter"),
+            VALID.replace("greeter.greet_user", "greet_user"),
+            VALID.replace("runtime: wasi1.0", "runtime: wasi2.0"),
+        ];
+
+        for yaml in bad {
+            assert!(Stack::from_yaml(&yaml).is_err(), "{yaml}");
+            assert!(!is_valid(&yaml), "{yaml}");
+        }
+    }
+}
@@ -4,6 +4,10 @@ use thiserror::Error;
 
 use crate::{HttpMethod, Stack};
 
+/// Table names starting with this are used by mu itself (e.g. for stack
+/// secrets), so stacks can't declare them.
+pub const RESERVED_TABLE_NAME_PREFIX: &str = "__";
+
 #[derive(Clone, Debug, Default)]
 pub struct ValidatedStack(Stack);
 
@@ -29,6 +33,9 @@ pub enum StackValidationError {
     #[error("Duplicate table name '{0}'")]
     DuplicateTableName(String),
 
+    #[error("Table name '{0}' is reserved, names can't start with '{RESERVED_TABLE_NAME_PREFIX}'")]
+    ReservedTableName(String),
+
     #[error("Duplicate gateway name '{0}'")]
     DuplicateGatewayName(String),
 
@@ -46,78 +53,159 @@ pub enum StackValidationError {
         path: String,
         method: HttpMethod,
     },
+
+    #[error("Invalid endpoint path '{path}' in gateway '{gateway}': {reason}")]
+    InvalidEndpointTemplate {
+        gateway: String,
+        path: String,
+        reason: EndpointPathError,
+    },
+
+    #[error(
+        "Accepted content types given for unknown endpoint path '{path}' in gateway '{gateway}'"
+    )]
+    ContentTypesForUnknownEndpoint { gateway: String, path: String },
 }
 
-macro_rules! attempt_with {
-    ($ex:expr, $mk_err:expr, $stack:ident) => {
-        match $ex {
-            Ok(x) => x,
-            Err(e) => {
-                let e = $mk_err(e);
-                return Err(($stack, e));
-            }
-        }
-    };
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointPathError {
+    #[error("path parameters must span an entire segment")]
+    ParamNotEntireSegment,
+
+    #[error("only one path parameter is allowed per segment")]
+    MultipleParamsInSegment,
+
+    #[error("braces must be balanced")]
+    UnbalancedBraces,
+
+    #[error("path parameter names can't be empty")]
+    EmptyParamName,
+
+    #[error("catch-all parameters must be the last segment")]
+    CatchAllNotLast,
 }
 
 #[allow(clippy::result_large_err)]
 pub(super) fn validate(stack: Stack) -> Result<ValidatedStack, (Stack, StackValidationError)> {
-    attempt_with!(
-        ensure_all_unique(stack.functions().map(|f| &f.name)),
-        |e: &String| StackValidationError::DuplicateFunctionName(e.clone()),
-        stack
-    );
-
-    attempt_with!(
-        ensure_all_unique(stack.key_value_tables().map(|t| &t.name)),
-        |e: &String| StackValidationError::DuplicateTableName(e.clone()),
-        stack
-    );
-
-    attempt_with!(
-        ensure_all_unique(stack.gateways().map(|g| &g.name)),
-        |e: &String| StackValidationError::DuplicateGatewayName(e.clone()),
-        stack
-    );
-
-    attempt_with!(
-        ensure_all_unique(stack.storages().map(|g| &g.name)),
-        |e: &String| StackValidationError::DuplicateStorageName(e.clone()),
-        stack
-    );
-
-    attempt_with!(ensure_gateway_functions_correct(&stack), |e| e, stack);
-
-    let mut err = None;
+    match validation_errors(&stack).into_iter().next() {
+        Some(e) => Err((stack, e)),
+        None => Ok(ValidatedStack(stack)),
+    }
+}
+
+// Runs every check instead of stopping at the first failure. Each check still
+// reports only the first problem it finds, and the errors come out in the
+// same order `validate` would have hit them.
+pub(super) fn validation_errors(stack: &Stack) -> Vec<StackValidationError> {
+    let mut errors = vec![];
+
+    if let Err(name) = ensure_all_unique(stack.functions().map(|f| &f.name)) {
+        errors.push(StackValidationError::DuplicateFunctionName(name.clone()));
+    }
+
+    if let Err(name) = ensure_all_unique(stack.key_value_tables().map(|t| &t.name)) {
+        errors.push(StackValidationError::DuplicateTableName(name.clone()));
+    }
+
+    if let Some(table) = stack
+        .key_value_tables()
+        .find(|t| t.name.starts_with(RESERVED_TABLE_NAME_PREFIX))
+    {
+        errors.push(StackValidationError::ReservedTableName(table.name.clone()));
+    }
+
+    if let Err(name) = ensure_all_unique(stack.gateways().map(|g| &g.name)) {
+        errors.push(StackValidationError::DuplicateGatewayName(name.clone()));
+    }
+
+    if let Err(name) = ensure_all_unique(stack.storages().map(|g| &g.name)) {
+        errors.push(StackValidationError::DuplicateStorageName(name.clone()));
+    }
+
+    errors.extend(ensure_gateway_functions_correct(stack).err());
+
+    let templates_valid = match ensure_endpoint_templates_valid(stack) {
+        Ok(()) => true,
+        Err(e) => {
+            errors.push(e);
+            false
+        }
+    };
+
+    errors.extend(ensure_content_type_paths_known(stack).err());
+
+    if templates_valid {
+        errors.extend(ensure_endpoints_unique(stack).err());
+    }
+
+    errors
+}
+
+fn ensure_gateway_functions_correct(stack: &Stack) -> Result<(), StackValidationError> {
     for gw in stack.gateways() {
-        if let Err(e) = ensure_all_unique(
-            gw.endpoints
-                .iter()
-                .flat_map(|(path, eps)| eps.iter().map(|ep| (path.clone(), *ep.0))),
-        ) {
-            err = Some(StackValidationError::DuplicateEndpointInGateway {
-                gateway: gw.name.clone(),
-                path: e.0.clone(),
-                method: e.1,
-            });
+        let targets = gw
+            .endpoints
+            .values()
+            .flat_map(|eps| eps.values())
+            .chain(gw.auth.iter());
+
+        for target in targets {
+            if !stack.functions().any(|f| f.name == target.assembly) {
+                return Err(StackValidationError::UnknownFunctionInGateway {
+                    function: target.assembly.clone(),
+                    gateway: gw.name.clone(),
+                });
+            }
         }
     }
+    Ok(())
+}
 
-    if let Some(err) = err {
-        return Err((stack, err));
+fn ensure_endpoint_templates_valid(stack: &Stack) -> Result<(), StackValidationError> {
+    for gw in stack.gateways() {
+        for path in gw.endpoints.keys() {
+            if let Err(reason) = validate_endpoint_template(path) {
+                return Err(StackValidationError::InvalidEndpointTemplate {
+                    gateway: gw.name.clone(),
+                    path: path.clone(),
+                    reason,
+                });
+            }
+        }
     }
+    Ok(())
+}
 
-    Ok(ValidatedStack(stack))
+fn ensure_content_type_paths_known(stack: &Stack) -> Result<(), StackValidationError> {
+    for gw in stack.gateways() {
+        for path in gw.accepted_content_types.keys() {
+            if !gw.endpoints.contains_key(path) {
+                return Err(StackValidationError::ContentTypesForUnknownEndpoint {
+                    gateway: gw.name.clone(),
+                    path: path.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
 }
 
-fn ensure_gateway_functions_correct(stack: &Stack) -> Result<(), StackValidationError> {
+// Paths differing only in parameter names match the same requests, so they
+// count as the same endpoint. Only valid templates can be normalized.
+fn ensure_endpoints_unique(stack: &Stack) -> Result<(), StackValidationError> {
     for gw in stack.gateways() {
-        for eps in gw.endpoints.values() {
-            for ep in eps {
-                if !stack.functions().any(|f| f.name == ep.1.assembly) {
-                    return Err(StackValidationError::UnknownFunctionInGateway {
-                        function: ep.1.assembly.clone(),
+        let mut paths = gw.endpoints.iter().collect::<Vec<_>>();
+        paths.sort_by_key(|(path, _)| *path);
+
+        let mut seen = HashSet::new();
+        for (path, endpoints) in paths {
+            let normalized = normalize_endpoint_template(path);
+            for method in endpoints.keys() {
+                if !seen.insert((normalized.clone(), *method)) {
+                    return Err(StackValidationError::DuplicateEndpointInGateway {
                         gateway: gw.name.clone(),
+                        path: path.clone(),
+                        method: *method,
                     });
                 }
             }
@@ -126,6 +214,78 @@ fn ensure_gateway_functions_correct(stack: &Stack) -> Result<(), StackValidation
     Ok(())
 }
 
+fn normalize_endpoint_template(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if !segment.starts_with('{') {
+                segment
+            } else if segment.ends_with(":*}") {
+                "{*}"
+            } else {
+                "{}"
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Each segment must either be fixed text without braces, or exactly one
+// `{name}` parameter. The last segment may instead be a `{name:*}` catch-all.
+// This mirrors what the gateway's path matcher supports.
+fn validate_endpoint_template(path: &str) -> Result<(), EndpointPathError> {
+    let mut segments = path.split('/').peekable();
+
+    while let Some(segment) = segments.next() {
+        if !segment.contains(['{', '}']) {
+            continue;
+        }
+
+        ensure_braces_balanced(segment)?;
+
+        if segment.matches('{').count() > 1 {
+            return Err(EndpointPathError::MultipleParamsInSegment);
+        }
+
+        if !segment.starts_with('{') || !segment.ends_with('}') {
+            return Err(EndpointPathError::ParamNotEntireSegment);
+        }
+
+        let mut name = &segment[1..segment.len() - 1];
+
+        if let Some(catch_all_name) = name.strip_suffix(":*") {
+            if segments.peek().is_some() {
+                return Err(EndpointPathError::CatchAllNotLast);
+            }
+            name = catch_all_name;
+        }
+
+        if name.is_empty() {
+            return Err(EndpointPathError::EmptyParamName);
+        }
+    }
+
+    Ok(())
+}
+
+// Parameters can't be nested, so every brace must close the one before it
+fn ensure_braces_balanced(segment: &str) -> Result<(), EndpointPathError> {
+    let mut open = false;
+    for c in segment.chars() {
+        match (c, open) {
+            ('{', false) => open = true,
+            ('}', true) => open = false,
+            ('{', true) | ('}', false) => return Err(EndpointPathError::UnbalancedBraces),
+            _ => (),
+        }
+    }
+
+    if open {
+        Err(EndpointPathError::UnbalancedBraces)
+    } else {
+        Ok(())
+    }
+}
+
 fn ensure_all_unique<T: Hash + Eq + Clone>(it: impl Iterator<Item = T>) -> Result<(), T> {
     let mut hashset = HashSet::new();
 
@@ -139,3 +299,227 @@ fn ensure_all_unique<T: Hash + Eq + Clone>(it: impl Iterator<Item = T>) -> Resul
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{AssemblyAndFunction, AssemblyRuntime, Function, Gateway, NameAndDelete, Service};
+
+    fn function(name: &str) -> Service {
+        Service::Function(Function {
+            name: name.into(),
+            binary: format!("{name}.wasm"),
+            runtime: AssemblyRuntime::Wasi1_0,
+            env: HashMap::new(),
+            secrets: HashMap::new(),
+            memory_limit: byte_unit::Byte::from_bytes(1024 * 1024),
+            compression: Default::default(),
+            compiler: None,
+        })
+    }
+
+    fn gateway(endpoints: &[(&str, HttpMethod)]) -> Service {
+        let mut gateway = Gateway {
+            name: "gw".into(),
+            endpoints: HashMap::new(),
+            auth: None,
+            accepted_content_types: HashMap::new(),
+            cors: None,
+        };
+        for (path, method) in endpoints {
+            gateway
+                .endpoints
+                .entry(path.to_string())
+                .or_default()
+                .insert(
+                    *method,
+                    AssemblyAndFunction {
+                        assembly: "f".into(),
+                        function: "f".into(),
+                    },
+                );
+        }
+        Service::Gateway(gateway)
+    }
+
+    fn stack(services: Vec<Service>) -> Stack {
+        Stack {
+            name: "stack".into(),
+            version: "1.0".into(),
+            services,
+        }
+    }
+
+    #[test]
+    fn duplicate_function_names_are_rejected() {
+        let result = validate(stack(vec![function("a"), function("b"), function("a")]));
+
+        assert!(matches!(
+            result,
+            Err((_, StackValidationError::DuplicateFunctionName(name))) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn distinct_function_names_are_accepted() {
+        assert!(validate(stack(vec![function("a"), function("b")])).is_ok());
+    }
+
+    fn table(name: &str) -> Service {
+        Service::KeyValueTable(NameAndDelete {
+            name: name.into(),
+            delete: None,
+        })
+    }
+
+    #[test]
+    fn reserved_table_names_are_rejected() {
+        let result = validate(stack(vec![table("users"), table("__secrets")]));
+
+        assert!(matches!(
+            result,
+            Err((_, StackValidationError::ReservedTableName(name))) if name == "__secrets"
+        ));
+        assert!(validate(stack(vec![table("users"), table("_private")])).is_ok());
+    }
+
+    #[test]
+    fn all_validation_errors_are_reported() {
+        let mut gw = gateway(&[("/get/{}", HttpMethod::Get)]);
+        if let Service::Gateway(gw) = &mut gw {
+            gw.endpoints
+                .get_mut("/get/{}")
+                .unwrap()
+                .get_mut(&HttpMethod::Get)
+                .unwrap()
+                .assembly = "missing".into();
+        }
+
+        let errors = validation_errors(&stack(vec![function("a"), function("a"), gw]));
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                StackValidationError::DuplicateFunctionName(_),
+                StackValidationError::UnknownFunctionInGateway { .. },
+                StackValidationError::InvalidEndpointTemplate { .. },
+            ]
+        ));
+    }
+
+    fn path_error(path: &str) -> Option<EndpointPathError> {
+        match validate(stack(vec![
+            function("f"),
+            gateway(&[(path, HttpMethod::Get)]),
+        ])) {
+            Ok(_) => None,
+            Err((_, StackValidationError::InvalidEndpointTemplate { reason, .. })) => Some(reason),
+            Err((_, e)) => panic!("Unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn valid_endpoint_paths_are_accepted() {
+        assert_eq!(path_error("/get/{a}/{b}"), None);
+        assert_eq!(path_error("/files/{path:*}"), None);
+        assert_eq!(path_error("/plain/path/"), None);
+    }
+
+    #[test]
+    fn paths_without_params_are_accepted() {
+        for path in ["", "/", "plain", "/a/b/c/", "/with-dashes_and.dots"] {
+            assert_eq!(validate_endpoint_template(path), Ok(()), "{path}");
+        }
+    }
+
+    #[test]
+    fn invalid_paths_are_reported_with_their_gateway() {
+        let result = validate(stack(vec![
+            function("f"),
+            gateway(&[("/ok/{a}", HttpMethod::Get), ("/bad/x{a}", HttpMethod::Get)]),
+        ]));
+
+        assert!(matches!(
+            result,
+            Err((_, StackValidationError::InvalidEndpointTemplate { gateway, path, reason }))
+                if gateway == "gw"
+                    && path == "/bad/x{a}"
+                    && reason == EndpointPathError::ParamNotEntireSegment
+        ));
+    }
+
+    #[test]
+    fn empty_param_names_are_rejected() {
+        assert_eq!(
+            path_error("/get/{}"),
+            Some(EndpointPathError::EmptyParamName)
+        );
+        assert_eq!(
+            path_error("/get/{:*}"),
+            Some(EndpointPathError::EmptyParamName)
+        );
+    }
+
+    #[test]
+    fn multiple_params_in_one_segment_are_rejected() {
+        assert_eq!(
+            path_error("/get/{a}{b}/"),
+            Some(EndpointPathError::MultipleParamsInSegment)
+        );
+    }
+
+    #[test]
+    fn unbalanced_braces_are_rejected() {
+        for path in ["/get/{a", "/get/a}", "/get/{a}}", "/get/{{a}}"] {
+            assert_eq!(
+                path_error(path),
+                Some(EndpointPathError::UnbalancedBraces),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn params_must_span_entire_segments() {
+        assert_eq!(
+            path_error("/get/x{a}"),
+            Some(EndpointPathError::ParamNotEntireSegment)
+        );
+    }
+
+    #[test]
+    fn catch_all_params_must_be_last() {
+        assert_eq!(
+            path_error("/get/{a:*}/b"),
+            Some(EndpointPathError::CatchAllNotLast)
+        );
+    }
+
+    #[test]
+    fn equivalent_endpoint_paths_are_rejected() {
+        let result = validate(stack(vec![
+            function("f"),
+            gateway(&[("/get/{a}", HttpMethod::Get), ("/get/{b}", HttpMethod::Get)]),
+        ]));
+
+        assert!(matches!(
+            result,
+            Err((_, StackValidationError::DuplicateEndpointInGateway { path, method, .. }))
+                if path == "/get/{b}" && method == HttpMethod::Get
+        ));
+    }
+
+    #[test]
+    fn equivalent_endpoint_paths_with_different_methods_are_accepted() {
+        assert!(validate(stack(vec![
+            function("f"),
+            gateway(&[
+                ("/get/{a}", HttpMethod::Get),
+                ("/get/{b}", HttpMethod::Post)
+            ]),
+        ]))
+        .is_ok());
+    }
+}
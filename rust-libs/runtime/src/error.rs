@@ -34,6 +34,12 @@ pub enum Error {
 
     #[error("The runtime was shut down")]
     RuntimeIsShutDown,
+
+    #[error("Function instance exceeded its maximum lifetime and was reaped")]
+    InstanceReaped,
+
+    #[error("The stack has too many invocations running at once")]
+    TooManyConcurrentInvocations,
 }
 
 #[derive(Error, Debug)]
@@ -52,6 +58,66 @@ pub enum FunctionRuntimeError {
 
     #[error("Failed to serialize message: {0:?}")]
     SerializationError(std::io::Error),
+
+    #[error("Function uses protocol version {found}, expected {expected}")]
+    ProtocolVersionMismatch { expected: u16, found: u16 },
+
+    #[error("Function did not complete the protocol handshake: {0}")]
+    HandshakeFailed(String),
+
+    #[error("Function exceeded its maximum execution time")]
+    Timeout,
+
+    #[error("Function misused its response stream: {0}")]
+    InvalidResponseStream(&'static str),
+
+    #[error("Function panicked: {0}")]
+    Panicked(String),
+}
+
+/// What the function being invoked did wrong, as opposed to failures of the
+/// runtime itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionFailureKind {
+    Failed,
+    TimedOut,
+    MemoryExceeded,
+}
+
+impl Error {
+    /// `None` if the error isn't the function's fault. The message never
+    /// includes runtime internals, so it's safe to show to the function's
+    /// callers.
+    pub fn function_failure(&self) -> Option<(FunctionFailureKind, String)> {
+        use FunctionFailureKind::*;
+
+        let failure = match self {
+            Error::FunctionRuntimeError(e) => match e {
+                FunctionRuntimeError::FatalError(message)
+                | FunctionRuntimeError::Panicked(message) => (Failed, message.clone()),
+                FunctionRuntimeError::MaximumMemoryExceeded => (MemoryExceeded, e.to_string()),
+                FunctionRuntimeError::Timeout => (TimedOut, e.to_string()),
+                FunctionRuntimeError::FunctionInitializationFailed(_) => {
+                    (Failed, "Function initialization failed".into())
+                }
+                FunctionRuntimeError::MissingStartFunction(_) => {
+                    (Failed, "_start function is missing".into())
+                }
+                FunctionRuntimeError::HandshakeFailed(_) => (
+                    Failed,
+                    "Function did not complete the protocol handshake".into(),
+                ),
+                FunctionRuntimeError::ProtocolVersionMismatch { .. }
+                | FunctionRuntimeError::InvalidResponseStream(_) => (Failed, e.to_string()),
+                FunctionRuntimeError::SerializationError(_) => return None,
+            },
+            Error::FunctionDidntTerminateCleanly => (Failed, self.to_string()),
+            Error::Timeout | Error::InstanceReaped => (TimedOut, self.to_string()),
+            _ => return None,
+        };
+
+        Some(failure)
+    }
 }
 #[derive(Error, Debug)]
 pub enum FunctionLoadingError {
@@ -64,6 +130,18 @@ pub enum FunctionLoadingError {
     #[error("WASM module for assembly {0:?} is corrupted or invalid")]
     InvalidAssembly(AssemblyID),
 
+    #[error("Failed to decompress assembly {0:?}: {1}")]
+    DecompressAssembly(AssemblyID, std::io::Error),
+
+    #[error("Assembly {0:?} is defined more than once")]
+    DuplicateFunctionName(AssemblyID),
+
+    #[error("Secret '{0}' is not defined for this stack")]
+    MissingSecret(String),
+
+    #[error("Secret '{0}' is not valid UTF-8 or contains a null character")]
+    InvalidSecret(String),
+
     #[error("Failed to build Wasi Env: {0:?}")]
     FailedToBuildWasmEnv(WasiStateCreationError),
 
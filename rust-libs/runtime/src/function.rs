@@ -1,20 +1,21 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io::Read};
 
 use super::{
     error::{Error, FunctionLoadingError, FunctionRuntimeError, Result},
     pipe::Pipe,
-    types::{FunctionHandle, FunctionIO},
+    types::{ExecutionUsage, FunctionHandle, FunctionIO, WasiConfig},
 };
 
 use wasmer::{Instance, Module, Store};
-use wasmer_middlewares::metering::{get_remaining_points, MeteringPoints};
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
 use wasmer_wasi::WasiState;
 
 pub fn start(
     mut store: Store,
     module: &Module,
-    envs: HashMap<String, String>,
-    giga_instructions_limit: Option<u32>,
+    mut envs: HashMap<String, String>,
+    wasi_config: &WasiConfig,
+    instruction_limit: u64,
 ) -> Result<FunctionHandle> {
     //TODO: Check wasi version specified in this module and if we can run it!
 
@@ -22,12 +23,37 @@ pub fn start(
     let stdout = Pipe::new();
     let stderr = Pipe::new();
 
+    for name in &wasi_config.env_allowlist {
+        if let Ok(value) = std::env::var(name) {
+            envs.entry(name.clone()).or_insert(value);
+        }
+    }
+
     let program_name = module.name().unwrap_or("module");
-    let wasi_env = WasiState::new(program_name)
+    let mut wasi_state = WasiState::new(program_name);
+    wasi_state
         .stdin(Box::new(stdin.clone()))
         .stdout(Box::new(stdout.clone()))
         .stderr(Box::new(stderr.clone()))
-        .envs(envs)
+        .envs(envs);
+
+    // Without preopened directories, functions have no access to the host's
+    // file system at all
+    for dir in &wasi_config.preopened_dirs {
+        wasi_state
+            .preopen(|p| {
+                p.directory(&dir.host_path)
+                    .alias(&dir.guest_path)
+                    .read(true)
+                    .write(!dir.read_only)
+                    .create(!dir.read_only)
+            })
+            .map_err(|e| {
+                Error::FunctionLoadingError(FunctionLoadingError::FailedToBuildWasmEnv(e))
+            })?;
+    }
+
+    let wasi_env = wasi_state
         .finalize(&mut store)
         .map_err(|e| Error::FunctionLoadingError(FunctionLoadingError::FailedToBuildWasmEnv(e)))?;
 
@@ -53,10 +79,13 @@ pub fn start(
         }
     })?;
 
+    set_remaining_points(&mut store, &instance, instruction_limit);
+
     let memory = instance
         .exports
         .get_memory("memory")
-        .map_err(|e| Error::FunctionLoadingError(FunctionLoadingError::FailedToGetMemory(e)))?;
+        .map_err(|e| Error::FunctionLoadingError(FunctionLoadingError::FailedToGetMemory(e)))?
+        .clone();
 
     wasi_env.data_mut(&mut store).set_memory(memory.clone());
 
@@ -64,53 +93,68 @@ pub fn start(
     let mut stdout_clone = stdout.clone();
     let mut stderr_clone = stderr.clone();
 
-    // If this module exports an _initialize function, run that first.
     let join_handle = tokio::task::spawn_blocking(move || {
-        if let Ok(initialize) = instance.exports.get_function("_initialize") {
-            initialize.call(&mut store, &[]).map_err(|e| {
+        let mut run = || -> Result<u64, (Error, u64)> {
+            // If this module exports an _initialize function, run that first.
+            if let Ok(initialize) = instance.exports.get_function("_initialize") {
+                initialize.call(&mut store, &[]).map_err(|e| {
+                    (
+                        Error::FunctionRuntimeError(
+                            FunctionRuntimeError::FunctionInitializationFailed(e),
+                        ),
+                        points_to_instruction_count(
+                            get_remaining_points(&mut store, &instance),
+                            instruction_limit,
+                        ),
+                    )
+                })?;
+            }
+
+            let start = instance.exports.get_function("_start").map_err(|e| {
                 (
-                    Error::FunctionRuntimeError(
-                        FunctionRuntimeError::FunctionInitializationFailed(e),
-                    ),
+                    Error::FunctionRuntimeError(FunctionRuntimeError::MissingStartFunction(e)),
                     points_to_instruction_count(
                         get_remaining_points(&mut store, &instance),
-                        giga_instructions_limit,
+                        instruction_limit,
                     ),
                 )
             })?;
-        }
 
-        let start = instance.exports.get_function("_start").map_err(|e| {
-            (
-                Error::FunctionRuntimeError(FunctionRuntimeError::MissingStartFunction(e)),
-                points_to_instruction_count(
-                    get_remaining_points(&mut store, &instance),
-                    giga_instructions_limit,
-                ),
-            )
-        })?;
-
-        let result = start
-            .call(&mut store, &[])
-            .map(|_| get_remaining_points(&mut store, &instance))
-            .map_err(|e| (e, get_remaining_points(&mut store, &instance)));
-
-        stdin_clone.close();
-        stdout_clone.close();
-        stderr_clone.close();
-
-        match (result, giga_instructions_limit) {
-            (Ok(points), limit) => Ok(points_to_instruction_count(points, limit)),
-
-            (Err((_, MeteringPoints::Exhausted)), limit) => Err((
-                Error::Timeout,
-                points_to_instruction_count(MeteringPoints::Exhausted, limit),
-            )),
+            let result = start
+                .call(&mut store, &[])
+                .map(|_| get_remaining_points(&mut store, &instance))
+                .map_err(|e| (e, get_remaining_points(&mut store, &instance)));
 
-            (Err((_, MeteringPoints::Remaining(points))), limit) => Err((
-                Error::FunctionDidntTerminateCleanly,
-                points_to_instruction_count(MeteringPoints::Remaining(points), limit),
-            )),
+            stdin_clone.close();
+            stdout_clone.close();
+            stderr_clone.close();
+
+            match (result, instruction_limit) {
+                (Ok(points), limit) => Ok(points_to_instruction_count(points, limit)),
+
+                (Err((_, MeteringPoints::Exhausted)), limit) => Err((
+                    Error::Timeout,
+                    points_to_instruction_count(MeteringPoints::Exhausted, limit),
+                )),
+
+                (Err((_, MeteringPoints::Remaining(points))), limit) => Err((
+                    trap_error(&mut stderr_clone),
+                    points_to_instruction_count(MeteringPoints::Remaining(points), limit),
+                )),
+            }
+        };
+        let result = run();
+
+        // Linear memory can only grow, so its size once the function stops
+        // is the most it used
+        let usage = |instructions| ExecutionUsage {
+            instructions,
+            peak_memory_bytes: memory.view(&store).data_size(),
+        };
+
+        match result {
+            Ok(instructions) => Ok(usage(instructions)),
+            Err((e, instructions)) => Err((e, usage(instructions))),
         }
     });
 
@@ -124,16 +168,40 @@ pub fn start(
     ))
 }
 
+const MAX_PANIC_MESSAGE_LEN: usize = 1024;
+
+// Panics and failed allocations both end in an `unreachable` trap, so the
+// only way to tell them apart is what Rust's std prints to stderr first.
+fn trap_error(stderr: &mut Pipe) -> Error {
+    let mut output = String::new();
+    // The pipe is already closed, so this returns once it's drained
+    if stderr.read_to_string(&mut output).is_err() {
+        return Error::FunctionDidntTerminateCleanly;
+    }
+
+    if output.contains("memory allocation of ") {
+        return Error::FunctionRuntimeError(FunctionRuntimeError::MaximumMemoryExceeded);
+    }
+
+    let Some(start) = output.find("panicked at") else {
+        return Error::FunctionDidntTerminateCleanly;
+    };
+
+    // Leave out hints about backtraces, which can't be enabled anyway
+    let message = output[start..]
+        .lines()
+        .filter(|l| !l.starts_with("note:"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let message = message.trim().chars().take(MAX_PANIC_MESSAGE_LEN).collect();
+
+    Error::FunctionRuntimeError(FunctionRuntimeError::Panicked(message))
+}
+
 #[inline]
-fn points_to_instruction_count(
-    points: MeteringPoints,
-    giga_instructions_limit: Option<u32>,
-) -> u64 {
-    let limit = giga_instructions_limit.map(|p| p as u64 * 1_000_000_000u64);
-    match (points, limit) {
-        (MeteringPoints::Remaining(r), None) => u64::MAX - r,
-        (MeteringPoints::Remaining(r), Some(limit)) => limit - r,
-        (MeteringPoints::Exhausted, None) => u64::MAX,
-        (MeteringPoints::Exhausted, Some(limit)) => limit,
+fn points_to_instruction_count(points: MeteringPoints, instruction_limit: u64) -> u64 {
+    match points {
+        MeteringPoints::Remaining(r) => instruction_limit - r,
+        MeteringPoints::Exhausted => instruction_limit,
     }
 }
@@ -2,36 +2,71 @@ mod database;
 mod http_client;
 pub(crate) mod utils;
 
-use std::{borrow::BorrowMut, ops::Deref};
-use std::{borrow::Cow, collections::HashMap, future::Future};
+use std::{borrow::BorrowMut, ops::Deref, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    future::Future,
+};
 
 use crate::{
-    error::{Error, FunctionRuntimeError, Result},
+    error::{Error, FunctionLoadingError, FunctionRuntimeError, Result},
     function,
     instance::utils::create_usage,
-    types::{ExecuteFunctionRequest, ExecuteFunctionResponse, FunctionHandle, InstanceID},
-    Usage,
+    types::{
+        ExecuteFunctionRequest, FunctionHandle, FunctionIO, FunctionResponse, InstanceID, Respond,
+        WasiConfig,
+    },
+    FunctionLog, Notification, Usage,
 };
 
 use mu_db::{DbClient, DbManager};
 use mu_stack::StackID;
-use mu_storage::{StorageClient, StorageManager};
+use mu_storage::{ETagMismatch, StorageClient, StorageManager};
 use musdk_common::{
     incoming_message::{
         self,
         db::*,
-        storage::{ObjectListResult, StorageEmptyResult, StorageError, StorageGetResult},
+        storage::{
+            ObjectListResult, StorageETagMismatch, StorageETagResult, StorageEmptyResult,
+            StorageError, StorageGetResult, StorageHeadResult, StoragePresignResult,
+            StoragePutManyResult,
+        },
         IncomingMessage,
     },
-    outgoing_message::{LogLevel, OutgoingMessage},
+    outgoing_message::{
+        storage::{PresignMethod, StoragePutMany},
+        LogLevel, OutgoingMessage, ReportMetric,
+    },
+    PROTOCOL_VERSION,
 };
 
 use anyhow::anyhow;
-use log::{error, log, trace, Level};
+use futures::{stream, StreamExt};
+use log::{error, log, trace, warn, Level};
+use mailbox_processor::NotificationChannel;
+use tokio::sync::mpsc;
 use wasmer::{Module, Store};
 
 const FUNCTION_LOG_TARGET: &str = "mu_function";
 
+// How many uploads of a single `StoragePutMany` run at the same time
+const STORAGE_PUT_MANY_CONCURRENCY: usize = 8;
+
+// Limits on custom metrics reported during a single request, so functions
+// can't flood usage reports with arbitrary names.
+const MAX_CUSTOM_METRIC_NAMES: usize = 16;
+const MAX_CUSTOM_METRIC_NAME_LENGTH: usize = 64;
+
+// Chunks of a streamed response held until the receiver reads them. Once
+// full, the function blocks on its next write, which bounds the memory a
+// stream can use to this many chunks.
+const RESPONSE_BODY_BUFFER_CHUNKS: usize = 16;
+
+// Counting pages through every key, so functions can't ask for more than
+// this in one request
+const MAX_COUNT_LIMIT: u64 = 100_000;
+
 type ResultWithUsage<T> = Result<T, (Error, Usage)>;
 
 pub(crate) struct Instance {
@@ -39,69 +74,113 @@ pub(crate) struct Instance {
     handle: FunctionHandle,
 
     // Options
-    memory_limit: byte_unit::Byte,
     include_logs: bool,
 
+    // Function logs are reported through this
+    notification_channel: NotificationChannel<Notification>,
+
     // Resources
     db_manager: Box<dyn DbManager>,
     storage_manager: Box<dyn StorageManager>,
     db_client: Option<Box<dyn DbClient>>,
-    http_client: Option<reqwest::blocking::Client>,
+    // Redirect policies are per client, so there's one for each limit in use
+    http_clients: HashMap<u32, reqwest::blocking::Client>,
     storage_client: Option<Box<dyn StorageClient>>,
 
     // Usage calculation
     database_write_count: u64,
     database_read_count: u64,
+    custom_metrics: HashMap<String, u64>,
 }
 
 impl Instance {
     #[allow(clippy::too_many_arguments)]
-    pub fn start(
+    pub async fn start(
         id: InstanceID,
-        envs: HashMap<String, String>,
+        mut envs: HashMap<String, String>,
+        secrets: HashMap<String, String>,
         store: Store,
         module: Module,
-        memory_limit: byte_unit::Byte,
-        giga_instructions_limit: Option<u32>,
+        instruction_limit: u64,
         include_logs: bool,
+        wasi_config: &WasiConfig,
         db_manager: Box<dyn DbManager>,
         storage_manager: Box<dyn StorageManager>,
+        notification_channel: NotificationChannel<Notification>,
     ) -> Result<Self> {
         trace!("starting instance {}", id);
 
-        let handle = function::start(store, &module, envs, giga_instructions_limit)?;
+        let mut db_client = None;
+        if !secrets.is_empty() {
+            let client = db_manager.make_client().await.map_err(Error::DBError)?;
+            envs.extend(resolve_secrets(&*client, id.function_id.stack_id, secrets).await?);
+            db_client = Some(client);
+        }
+
+        let handle = function::start(store, &module, envs, wasi_config, instruction_limit)?;
 
         Ok(Instance {
             id,
             handle,
 
-            memory_limit,
             include_logs,
 
+            notification_channel,
+
             db_manager,
             storage_manager,
-            db_client: None,
+            db_client,
             storage_client: None,
-            http_client: None,
+            http_clients: HashMap::new(),
 
             database_write_count: 0,
             database_read_count: 0,
+            custom_metrics: HashMap::new(),
         })
     }
 
+    /// The response is passed to `respond`, so streamed responses can be
+    /// sent while the function is still running.
     #[inline]
     pub async fn run_request(
         self,
         request: ExecuteFunctionRequest<'static>,
-    ) -> ResultWithUsage<(ExecuteFunctionResponse, Usage)> {
-        tokio::task::spawn_blocking(move || self.inner_run_request(request))
-            .await
-            .map_err(|_| {
-                (
-                    Error::Internal(anyhow!("can not run function task to end")),
-                    Default::default(),
-                )
-            })?
+        respond: Respond,
+    ) -> ResultWithUsage<Usage> {
+        tokio::task::spawn_blocking(move || {
+            let (body_tx, body_rx) = mpsc::channel(RESPONSE_BODY_BUFFER_CHUNKS);
+            let result = self.inner_run_request(request, respond, &body_tx, body_rx);
+
+            // This only reaches anyone if the response was being streamed,
+            // otherwise the receiver is already dropped
+            if let Err((e, _)) = &result {
+                let _ = body_tx.blocking_send(Err(anyhow!(
+                    "function failed while streaming its response: {e}"
+                )));
+            }
+
+            result
+        })
+        .await
+        .map_err(|_| {
+            (
+                Error::Internal(anyhow!("can not run function task to end")),
+                Default::default(),
+            )
+        })?
+    }
+
+    #[inline]
+    pub fn id(&self) -> &InstanceID {
+        &self.id
+    }
+
+    /// Returns the instance's pipes, which remain usable after the instance
+    /// itself is moved into `run_request`. Closing them cuts the function off
+    /// from the runtime.
+    #[inline]
+    pub fn io(&self) -> FunctionIO {
+        self.handle.io.clone()
     }
 
     #[inline]
@@ -109,6 +188,26 @@ impl Instance {
         self.handle.is_finished()
     }
 
+    /// Stops an instance that was never sent a request and returns what it
+    /// used while starting up. The function sees EOF while waiting for the
+    /// handshake and exits on its own.
+    pub async fn discard(self) -> Usage {
+        trace!("discarding instance {}", self.id);
+        self.io().close();
+
+        match self.handle.join_handle.await {
+            Ok(Ok(execution) | Err((_, execution))) => Usage {
+                custom_metrics: self.custom_metrics,
+                ..create_usage(
+                    self.database_read_count,
+                    self.database_write_count,
+                    execution,
+                )
+            },
+            Err(_) => Default::default(),
+        }
+    }
+
     #[inline]
     fn write_message(&mut self, message: IncomingMessage) -> Result<()> {
         message.write(&mut self.handle.io.stdin).map_err(|e| {
@@ -124,21 +223,80 @@ impl Instance {
         OutgoingMessage::read(&mut self.handle.io.stdout).map_err(Error::FailedToReadMessage)
     }
 
+    // Makes sure the function speaks the same message protocol before sending
+    // it anything else, so a mismatched SDK fails cleanly instead of
+    // mis-parsing messages.
+    fn handshake(&mut self) -> Result<()> {
+        self.write_message(IncomingMessage::Handshake(incoming_message::Handshake {
+            protocol_version: PROTOCOL_VERSION,
+        }))?;
+
+        match self.read_message() {
+            Ok(OutgoingMessage::Handshake(h)) if h.protocol_version == PROTOCOL_VERSION => {
+                trace!("Instance {} uses musdk {}", self.id, h.sdk_version);
+                Ok(())
+            }
+            Ok(OutgoingMessage::Handshake(h)) => Err(Error::FunctionRuntimeError(
+                FunctionRuntimeError::ProtocolVersionMismatch {
+                    expected: PROTOCOL_VERSION,
+                    found: h.protocol_version,
+                },
+            )),
+            Ok(_) => Err(Error::FunctionRuntimeError(
+                FunctionRuntimeError::HandshakeFailed(
+                    "function sent another message before the handshake".to_string(),
+                ),
+            )),
+
+            // The function stopped without writing anything
+            Err(Error::FailedToReadMessage(e)) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                Err(Error::FunctionDidntTerminateCleanly)
+            }
+            Err(Error::FailedToReadMessage(e)) => Err(Error::FunctionRuntimeError(
+                FunctionRuntimeError::HandshakeFailed(e.to_string()),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn report_metric(&mut self, metric: ReportMetric) {
+        let name = metric.name.into_owned();
+
+        if name.is_empty() || name.len() > MAX_CUSTOM_METRIC_NAME_LENGTH {
+            warn!("Instance {} reported metric with invalid name", self.id);
+            return;
+        }
+
+        if !self.custom_metrics.contains_key(&name)
+            && self.custom_metrics.len() >= MAX_CUSTOM_METRIC_NAMES
+        {
+            warn!(
+                "Instance {} exceeded {MAX_CUSTOM_METRIC_NAMES} custom metrics, dropping '{name}'",
+                self.id
+            );
+            return;
+        }
+
+        let total = self.custom_metrics.entry(name).or_insert(0);
+        *total = total.saturating_add(metric.value);
+    }
+
     fn wait_to_finish_and_get_usage(self) -> ResultWithUsage<Usage> {
+        let custom_metrics = self.custom_metrics;
         tokio::runtime::Handle::current()
             .block_on(self.handle.join_handle)
-            .map(move |metering_points| {
-                let usage = |instructions_count| {
-                    create_usage(
+            .map(move |execution| {
+                let usage = move |execution| Usage {
+                    custom_metrics,
+                    ..create_usage(
                         self.database_read_count,
                         self.database_write_count,
-                        instructions_count,
-                        self.memory_limit,
+                        execution,
                     )
                 };
                 trace!("instance {} finished", &self.id);
 
-                match metering_points {
+                match execution {
                     Ok(m) => Ok(usage(m)),
                     Err((e, m)) => Err((e, usage(m))),
                 }
@@ -151,11 +309,23 @@ impl Instance {
             })?
     }
 
+    fn fail_response_stream(self, description: &'static str) -> ResultWithUsage<Usage> {
+        let error =
+            Error::FunctionRuntimeError(FunctionRuntimeError::InvalidResponseStream(description));
+
+        match self.wait_to_finish_and_get_usage() {
+            Ok(u) | Err((_, u)) => Err((error, u)),
+        }
+    }
+
     #[inline]
     fn inner_run_request(
         mut self,
         request: ExecuteFunctionRequest<'static>,
-    ) -> ResultWithUsage<(ExecuteFunctionResponse, Usage)> {
+        respond: Respond,
+        body_tx: &mpsc::Sender<anyhow::Result<Vec<u8>>>,
+        body_rx: mpsc::Receiver<anyhow::Result<Vec<u8>>>,
+    ) -> ResultWithUsage<Usage> {
         if self.is_finished() {
             trace!(
                 "Instance {} is already exited before sending request",
@@ -163,9 +333,23 @@ impl Instance {
             );
         }
 
+        if let Err(e) = self.handshake() {
+            error!("Handshake with instance {} failed: {e:?}", self.id);
+
+            // Let the function see EOF so it stops waiting for input
+            self.handle.io.stdin.close();
+            return match self.wait_to_finish_and_get_usage() {
+                Ok(u) | Err((_, u)) => Err((e, u)),
+            };
+        }
+
         self.write_message(IncomingMessage::ExecuteFunction(request))
             .map_err(|e| (e, Default::default()))?;
 
+        // Taken once the response is sent, which for streamed responses is
+        // when the stream starts
+        let mut pending_response = Some((respond, body_rx));
+
         loop {
             // TODO: make this async? Possible, but needs work in Borsh as well
             trace!("Waiting for Instance {} message", &self.id);
@@ -204,12 +388,47 @@ impl Instance {
                 Ok(message) => {
                     trace!("Message from function {}: {:?}", self.id, message);
                     match message {
-                        OutgoingMessage::FunctionResult(response) => {
+                        OutgoingMessage::FunctionResult(result) => {
                             return match self.wait_to_finish_and_get_usage() {
-                                Ok(u) => Ok((response, u)),
+                                Ok(u) => {
+                                    // What a function returns after streaming
+                                    // its response is dropped
+                                    if let Some((respond, _)) = pending_response.take() {
+                                        respond(FunctionResponse {
+                                            response: result.response,
+                                            body_stream: None,
+                                        });
+                                    }
+                                    Ok(u)
+                                }
                                 Err((e, u)) => Err((e, u)),
                             };
                         }
+
+                        OutgoingMessage::StartResponseStream(stream) => {
+                            let Some((respond, body_rx)) = pending_response.take() else {
+                                return self.fail_response_stream("stream was started twice");
+                            };
+
+                            respond(FunctionResponse {
+                                response: stream.response,
+                                body_stream: Some(body_rx),
+                            });
+                        }
+
+                        OutgoingMessage::ResponseChunk(chunk) => {
+                            if pending_response.is_some() {
+                                return self.fail_response_stream(
+                                    "chunk was written before the stream started",
+                                );
+                            }
+
+                            // The function keeps running if the receiver goes
+                            // away, since it may still have other work to do
+                            if body_tx.blocking_send(Ok(chunk.data.into_owned())).is_err() {
+                                trace!("Response stream of instance {} was dropped", self.id);
+                            }
+                        }
                         OutgoingMessage::FatalError(e) => {
                             log!(
                                 target: FUNCTION_LOG_TARGET,
@@ -230,15 +449,15 @@ impl Instance {
                         }
 
                         OutgoingMessage::Log(log) => {
-                            if self.include_logs {
-                                let level = match log.level {
-                                    LogLevel::Error => Level::Error,
-                                    LogLevel::Warn => Level::Warn,
-                                    LogLevel::Info => Level::Info,
-                                    LogLevel::Debug => Level::Debug,
-                                    LogLevel::Trace => Level::Trace,
-                                };
+                            let level = match log.level {
+                                LogLevel::Error => Level::Error,
+                                LogLevel::Warn => Level::Warn,
+                                LogLevel::Info => Level::Info,
+                                LogLevel::Debug => Level::Debug,
+                                LogLevel::Trace => Level::Trace,
+                            };
 
+                            if self.include_logs {
                                 log!(
                                     target: FUNCTION_LOG_TARGET,
                                     level,
@@ -247,10 +466,31 @@ impl Instance {
                                     log.body
                                 );
                             }
+
+                            self.notification_channel.send(Notification::FunctionLog(
+                                FunctionLog {
+                                    function_id: self.id.function_id.clone(),
+                                    level,
+                                    body: log.body.into_owned(),
+                                },
+                            ));
                         }
 
                         OutgoingMessage::HttpRequest(req) => self.execute_http_request(req)?,
 
+                        OutgoingMessage::ReportMetric(metric) => self.report_metric(metric),
+
+                        OutgoingMessage::Handshake(_) => {
+                            let error =
+                                Error::FunctionRuntimeError(FunctionRuntimeError::HandshakeFailed(
+                                    "function repeated the handshake mid-request".to_string(),
+                                ));
+
+                            return match self.wait_to_finish_and_get_usage() {
+                                Ok(u) | Err((_, u)) => Err((error, u)),
+                            };
+                        }
+
                         // Database requests
                         OutgoingMessage::Put(_)
                         | OutgoingMessage::Get(_)
@@ -264,7 +504,10 @@ impl Instance {
                         | OutgoingMessage::BatchDelete(_)
                         | OutgoingMessage::BatchScan(_)
                         | OutgoingMessage::BatchScanKeys(_)
-                        | OutgoingMessage::CompareAndSwap(_) => self.handle_db_request(message)?,
+                        | OutgoingMessage::CompareAndSwap(_)
+                        | OutgoingMessage::BatchCompareAndSwap(_)
+                        | OutgoingMessage::CountByPrefix(_)
+                        | OutgoingMessage::PutWithTtl(_) => self.handle_db_request(message)?,
 
                         OutgoingMessage::StoragePut(req) => {
                             self.storage_request(|client, owner| async move {
@@ -274,6 +517,7 @@ impl Instance {
                                         &req.storage_name,
                                         &req.key,
                                         req.reader.deref().borrow_mut(),
+                                        req.content_type.as_deref(),
                                     )
                                     .await
                                     .map(|()| {
@@ -281,19 +525,94 @@ impl Instance {
                                     })
                             })?
                         }
+                        OutgoingMessage::StoragePutMany(req) => {
+                            self.storage_request(|client, owner| async move {
+                                Ok(IncomingMessage::StoragePutManyResult(
+                                    put_many(client.as_ref(), owner, &req).await,
+                                ))
+                            })?
+                        }
                         OutgoingMessage::StorageGet(req) => {
                             self.storage_request(|client, owner| async move {
                                 let mut data: Vec<u8> = vec![];
                                 client
                                     .get(owner, &req.storage_name, &req.key, &mut data)
                                     .await
-                                    .map(move |()| {
+                                    .map(move |content_type| {
                                         IncomingMessage::StorageGetResult(StorageGetResult {
                                             data: Cow::Owned(data),
+                                            content_type: content_type.map(Cow::Owned),
                                         })
                                     })
                             })?
                         }
+                        OutgoingMessage::StorageGetETag(req) => {
+                            self.storage_request(|client, owner| async move {
+                                client
+                                    .get_etag(owner, &req.storage_name, &req.key)
+                                    .await
+                                    .map(|etag| {
+                                        IncomingMessage::StorageETagResult(StorageETagResult {
+                                            etag: etag.map(Cow::Owned),
+                                        })
+                                    })
+                            })?
+                        }
+                        OutgoingMessage::StoragePutIfMatch(req) => {
+                            self.storage_request(|client, owner| async move {
+                                match client
+                                    .put_if_match(
+                                        owner,
+                                        &req.storage_name,
+                                        &req.key,
+                                        &req.etag,
+                                        &req.data,
+                                    )
+                                    .await
+                                {
+                                    Ok(()) => {
+                                        Ok(IncomingMessage::StorageEmptyResult(StorageEmptyResult))
+                                    }
+                                    Err(e) if e.is::<ETagMismatch>() => Ok(
+                                        IncomingMessage::StorageETagMismatch(StorageETagMismatch),
+                                    ),
+                                    Err(e) => Err(e),
+                                }
+                            })?
+                        }
+                        OutgoingMessage::StorageHead(req) => {
+                            self.storage_request(|client, owner| async move {
+                                client.head(owner, &req.storage_name, &req.key).await.map(
+                                    |object| {
+                                        IncomingMessage::StorageHeadResult(StorageHeadResult {
+                                            object: object.map(sdk_object_metadata),
+                                        })
+                                    },
+                                )
+                            })?
+                        }
+                        OutgoingMessage::StoragePresign(req) => {
+                            self.storage_request(|client, owner| async move {
+                                let expiry = Duration::from_secs(req.expiry_secs);
+                                let url = match req.method {
+                                    PresignMethod::Get => {
+                                        client
+                                            .presign_get(owner, &req.storage_name, &req.key, expiry)
+                                            .await?
+                                    }
+                                    PresignMethod::Put => {
+                                        client
+                                            .presign_put(owner, &req.storage_name, &req.key, expiry)
+                                            .await?
+                                    }
+                                };
+                                Ok(IncomingMessage::StoragePresignResult(
+                                    StoragePresignResult {
+                                        url: Cow::Owned(url),
+                                    },
+                                ))
+                            })?
+                        }
                         OutgoingMessage::StorageDelete(req) => {
                             self.storage_request(|client, owner| async move {
                                 client
@@ -311,13 +630,7 @@ impl Instance {
                                     .await
                                     .map(|res| {
                                         IncomingMessage::ObjectListResult(ObjectListResult {
-                                            list: res
-                                                .into_iter()
-                                                .map(|o| incoming_message::storage::Object {
-                                                    key: Cow::Owned(o.key),
-                                                    size: o.size,
-                                                })
-                                                .collect(),
+                                            list: res.into_iter().map(sdk_object).collect(),
                                         })
                                     })
                             })?
@@ -335,7 +648,18 @@ impl Instance {
                 self.execute_db_request(|db_client, stack_id| async move {
                     let key = make_mudb_key(stack_id, req.table, req.key)?;
                     db_client
-                        .put(key, req.value.into_owned(), req.is_atomic)
+                        .put(key, req.value.into_owned(), req.is_atomic, None)
+                        .await
+                        .map(into_empty_incoming_msg)
+                })
+            }
+
+            OutgoingMessage::PutWithTtl(req) => {
+                self.execute_db_request(|db_client, stack_id| async move {
+                    let key = make_mudb_key(stack_id, req.table, req.key)?;
+                    let ttl = Duration::from_millis(req.ttl_millis);
+                    db_client
+                        .put(key, req.value.into_owned(), req.is_atomic, Some(ttl))
                         .await
                         .map(into_empty_incoming_msg)
                 })
@@ -362,21 +686,29 @@ impl Instance {
             }
 
             OutgoingMessage::DeleteByPrefix(req) => {
-                self.execute_db_request(|db_client, stack_id| async move {
+                let mut deleted_count = 0;
+                let deleted = &mut deleted_count;
+                let result = self.execute_db_request(|db_client, stack_id| async move {
                     let table_name = req.table.into_owned().try_into()?;
                     let key_prefix = req.key_prefix.into_owned();
                     db_client
                         .delete_by_prefix(stack_id, table_name, key_prefix)
                         .await
-                        .map(into_empty_incoming_msg)
-                })
+                        .map(|count| {
+                            *deleted = count;
+                            into_empty_incoming_msg(())
+                        })
+                });
+                // Each deleted key counts as a write
+                self.database_write_count += deleted_count;
+                result
             }
 
             OutgoingMessage::Scan(req) => {
                 self.execute_db_request(|db_client, stack_id| async move {
                     let db_key = make_mudb_scan(stack_id, req.table, req.key_prefix)?;
                     db_client
-                        .scan(db_key, req.limit)
+                        .scan(db_key, req.limit, req.reverse)
                         .await
                         .map(into_kv_pairs_incoming_msg)
                 })
@@ -388,7 +720,7 @@ impl Instance {
                     let mudb_keys_to_inner_keys =
                         |k: Vec<mu_db::Key>| k.into_iter().map(|k| k.inner_key);
                     db_client
-                        .scan_keys(mudb_scan, req.limit)
+                        .scan_keys(mudb_scan, req.limit, req.reverse)
                         .await
                         .map(mudb_keys_to_inner_keys)
                         .map(into_list_incoming_msg)
@@ -477,6 +809,44 @@ impl Instance {
                 })
             }
 
+            OutgoingMessage::BatchCompareAndSwap(req) => {
+                self.execute_db_request(|db_client, stack_id| async move {
+                    let ops = req
+                        .ops
+                        .into_iter()
+                        .map(|op| {
+                            make_mudb_key(stack_id, op.table, op.key).map(|key| {
+                                (
+                                    key,
+                                    op.previous_value.map(|x| x.into_owned()),
+                                    op.new_value.into_owned(),
+                                )
+                            })
+                        })
+                        .collect::<mu_db::error::Result<_>>()?;
+                    db_client
+                        .batch_compare_and_swap_non_atomic(ops)
+                        .await
+                        .map(into_batch_cas_incoming_msg)
+                })
+            }
+
+            OutgoingMessage::CountByPrefix(req) => {
+                self.execute_db_request(|db_client, stack_id| async move {
+                    let table_name = req.table.into_owned().try_into()?;
+                    let key_prefix = req.key_prefix.into_owned();
+                    db_client
+                        .count_by_prefix(
+                            stack_id,
+                            table_name,
+                            key_prefix,
+                            req.limit.min(MAX_COUNT_LIMIT),
+                        )
+                        .await
+                        .map(into_count_incoming_msg)
+                })
+            }
+
             // TODO: separate messages into enums containing messages for one system to avoid this
             _ => Err(Error::Internal(anyhow!(
                 "invalid request type, only database requests are handled here."
@@ -527,16 +897,21 @@ impl Instance {
     ) -> ResultWithUsage<()> {
         use http_client::*;
 
-        if self.http_client.is_none() {
-            self.http_client = Some(reqwest::blocking::Client::new());
-        }
+        let client = match self.http_clients.entry(req.max_redirects) {
+            Entry::Occupied(client) => client.into_mut(),
+            Entry::Vacant(entry) => {
+                let client = reqwest::blocking::Client::builder()
+                    .redirect(redirect_policy(req.max_redirects))
+                    .build()
+                    .map_err(|e| (Error::Internal(e.into()), Usage::default()))?;
+                entry.insert(client)
+            }
+        };
 
-        let mut request = self
-            .http_client
-            .as_ref()
-            .unwrap()
+        let mut request = client
             .request(http_method_to_reqwest_method(req.method), req.url)
-            .version(version_to_reqwest_version(req.version));
+            .version(version_to_reqwest_version(req.version))
+            .timeout(Duration::from_millis(req.timeout_millis));
 
         for header in req.headers {
             request = request.header(header.name.as_ref(), header.value.as_ref());
@@ -583,3 +958,139 @@ impl Instance {
         })
     }
 }
+
+// Objects are uploaded independently, so one failing doesn't stop the others
+async fn put_many(
+    client: &dyn StorageClient,
+    owner: mu_storage::Owner,
+    req: &StoragePutMany<'_>,
+) -> StoragePutManyResult<'static> {
+    let storage_name = &req.storage_name;
+    let errors = stream::iter(req.objects.iter())
+        .map(|object| async move {
+            client
+                .put(
+                    owner,
+                    storage_name,
+                    &object.key,
+                    object.data.deref().borrow_mut(),
+                    None,
+                )
+                .await
+                .err()
+                .map(|e| Cow::Owned(format!("{e:?}")))
+        })
+        .buffered(STORAGE_PUT_MANY_CONCURRENCY)
+        .collect()
+        .await;
+
+    StoragePutManyResult { errors }
+}
+
+fn sdk_object(object: mu_storage::Object) -> incoming_message::storage::Object<'static> {
+    incoming_message::storage::Object {
+        key: Cow::Owned(object.key),
+        size: object.size,
+        last_modified_millis: object
+            .last_modified
+            .map(|t| (t.unix_timestamp_nanos() / 1_000_000) as i64),
+    }
+}
+
+fn sdk_object_metadata(
+    metadata: mu_storage::ObjectMetadata,
+) -> incoming_message::storage::ObjectMetadata<'static> {
+    incoming_message::storage::ObjectMetadata {
+        object: sdk_object(metadata.object),
+        content_type: metadata.content_type.map(Cow::Owned),
+    }
+}
+
+async fn resolve_secrets(
+    db_client: &dyn DbClient,
+    stack_id: StackID,
+    secrets: HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let mut resolved = HashMap::with_capacity(secrets.len());
+
+    for (env_name, secret_name) in secrets {
+        let value = db_client
+            .get(mu_db::Key::secret(stack_id, &secret_name))
+            .await
+            .map_err(|e| Error::DBError(e.into()))?;
+
+        let Some(value) = value else {
+            return Err(Error::FunctionLoadingError(
+                FunctionLoadingError::MissingSecret(secret_name),
+            ));
+        };
+
+        match String::from_utf8(value) {
+            Ok(value) if !value.contains('\0') => {
+                resolved.insert(env_name, value);
+            }
+            _ => {
+                return Err(Error::FunctionLoadingError(
+                    FunctionLoadingError::InvalidSecret(secret_name),
+                ))
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mu_storage::{DeleteStorage, FilesystemStorageConfig, StorageConfig};
+    use musdk_common::outgoing_message::storage::StorageObject;
+
+    #[tokio::test]
+    async fn put_many_reports_the_outcome_of_each_object() {
+        let root = std::env::temp_dir().join(format!("mu-put-many-{}", rand::random::<u64>()));
+        let storage_manager = mu_storage::start(&StorageConfig {
+            external: None,
+            internal: None,
+            filesystem: Some(FilesystemStorageConfig { root: root.clone() }),
+            health_check: None,
+            retry: None,
+        })
+        .await
+        .unwrap();
+        let client = storage_manager.make_client().unwrap();
+        let owner = mu_storage::Owner::Stack(StackID::SolanaPublicKey([1; 32]));
+        client
+            .update_stack_storages(owner, vec![("files", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        let object = |key: &'static str, data: &'static [u8]| StorageObject {
+            key: Cow::Borrowed(key),
+            data: Cow::Borrowed(data),
+        };
+        let req = StoragePutMany {
+            storage_name: Cow::Borrowed("files"),
+            // The filesystem backend rejects `..` in keys
+            objects: vec![
+                object("a", b"1"),
+                object("../escape", b"2"),
+                object("b/c", b"3"),
+            ],
+        };
+
+        let result = put_many(client.as_ref(), owner, &req).await;
+
+        assert_eq!(result.errors.len(), 3);
+        assert!(result.errors[0].is_none());
+        assert!(result.errors[1].is_some());
+        assert!(result.errors[2].is_none());
+
+        // Objects after the failed one are still stored
+        let mut data = vec![];
+        client.get(owner, "files", "b/c", &mut data).await.unwrap();
+        assert_eq!(data, b"3");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -4,8 +4,9 @@ use mu_db::{error::Result, Key, Scan};
 use mu_stack::StackID;
 use musdk_common::incoming_message::{
     db::{
-        CasResult, EmptyResult, KeyValue, KeyValueListResult, ListResult, SingleResult, TableKey,
-        TableKeyListResult, TableKeyValue, TableKeyValueListResult,
+        BatchCasResult, CasResult, CountResult, EmptyResult, KeyValue, KeyValueListResult,
+        ListResult, SingleResult, TableKey, TableKeyListResult, TableKeyValue,
+        TableKeyValueListResult,
     },
     IncomingMessage,
 };
@@ -116,3 +117,19 @@ pub fn into_cas_incoming_msg<'a>(x: (Option<Vec<u8>>, bool)) -> IncomingMessage<
         is_swapped: x.1,
     })
 }
+
+pub fn into_batch_cas_incoming_msg<'a>(x: Vec<(Option<Vec<u8>>, bool)>) -> IncomingMessage<'a> {
+    IncomingMessage::BatchCasResult(BatchCasResult {
+        results: x
+            .into_iter()
+            .map(|(previous_value, is_swapped)| CasResult {
+                previous_value: previous_value.map(Cow::Owned),
+                is_swapped,
+            })
+            .collect(),
+    })
+}
+
+pub fn into_count_incoming_msg<'a>(count: u64) -> IncomingMessage<'a> {
+    IncomingMessage::CountResult(CountResult { count })
+}
@@ -1,8 +1,35 @@
-use std::{borrow::Cow, error::Error};
+use std::{borrow::Cow, error::Error, fmt};
 
 use log::error;
 use musdk_common::http_client::{self, *};
-use reqwest::Method;
+use reqwest::{redirect, Method};
+
+/// Reported by the redirect policy, so it can be told apart from other
+/// redirect errors when converting the result.
+#[derive(Debug)]
+struct TooManyRedirects(u32);
+
+impl fmt::Display for TooManyRedirects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "too many redirects, at most {} are allowed", self.0)
+    }
+}
+
+impl Error for TooManyRedirects {}
+
+pub fn redirect_policy(max_redirects: u32) -> redirect::Policy {
+    if max_redirects == 0 {
+        return redirect::Policy::none();
+    }
+
+    redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects as usize {
+            attempt.error(TooManyRedirects(max_redirects))
+        } else {
+            attempt.follow()
+        }
+    })
+}
 
 pub fn http_method_to_reqwest_method(method: HttpMethod) -> reqwest::Method {
     match method {
@@ -34,7 +61,14 @@ fn error_reason(error: reqwest::Error) -> String {
 }
 
 pub fn reqwest_error_to_http_error(error: reqwest::Error) -> http_client::Error {
-    if error.is_builder() {
+    if error.is_timeout() {
+        http_client::Error::Timeout(error_reason(error))
+    } else if let Some(TooManyRedirects(max)) = error
+        .source()
+        .and_then(|e| e.downcast_ref::<TooManyRedirects>())
+    {
+        http_client::Error::TooManyRedirects(*max)
+    } else if error.is_builder() {
         http_client::Error::Builder(error_reason(error))
     } else if error.is_request() {
         http_client::Error::Request(error_reason(error))
@@ -1,21 +1,33 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use crate::{memory::create_memory, Error, FunctionLoadingError, Result, Usage};
+use crate::{
+    memory::create_memory, types::ExecutionUsage, Error, FunctionLoadingError, Result, Usage,
+    WasmCompiler,
+};
 
 use wasmer::{CompilerConfig, Store};
+use wasmer_compiler_cranelift::Cranelift;
 use wasmer_compiler_llvm::LLVM;
+use wasmer_compiler_singlepass::Singlepass;
 use wasmer_middlewares::Metering;
 
 #[inline]
-pub fn create_store(
-    memory_limit: byte_unit::Byte,
-    giga_instructions_limit: Option<u32>,
-) -> Result<Store> {
-    let mut compiler_config = LLVM::default();
-
-    let metering_points = giga_instructions_limit.unwrap_or(u32::MAX) as u64 * 1_000_000_000;
-
-    let metering = Arc::new(Metering::new(metering_points, |_| 1));
+pub fn create_store(memory_limit: byte_unit::Byte, compiler: WasmCompiler) -> Result<Store> {
+    let mut compiler_config: Box<dyn CompilerConfig> = match compiler {
+        WasmCompiler::Llvm => Box::<LLVM>::default(),
+        WasmCompiler::Cranelift => Box::<Cranelift>::default(),
+        WasmCompiler::Singlepass => Box::<Singlepass>::default(),
+    };
+
+    // Metering is also the only way to stop a function that never yields to
+    // the host: wasmer 3 has no epoch or deadline interruption, and a call
+    // can't be preempted from another thread since it holds the store
+    // mutably. Functions blocked on a host call are cut off when
+    // `max_execution_time` passes instead, by closing their pipes. The
+    // actual limit is set per instance, see `function::start`, and is
+    // derived from `max_execution_time` when no instruction limit is
+    // configured, see `RuntimeConfig::instruction_limit`.
+    let metering = Arc::new(Metering::new(u64::MAX, |_| 1));
     compiler_config.push_middleware(metering);
 
     let memory = create_memory(memory_limit).map_err(|_| {
@@ -26,23 +38,17 @@ pub fn create_store(
 }
 
 #[inline]
-pub fn create_usage(
-    db_read: u64,
-    db_write: u64,
-    instructions_count: u64,
-    memory: byte_unit::Byte,
-) -> Usage {
-    let memory_megabytes = memory
-        .get_adjusted_unit(byte_unit::ByteUnit::MB)
-        .get_value();
-    let memory_megabytes = memory_megabytes.floor() as u64;
+pub fn create_usage(db_read: u64, db_write: u64, execution: ExecutionUsage) -> Usage {
+    // Partially used megabytes are billed as whole ones
+    let memory_megabytes = execution.peak_memory_bytes.div_ceil(1_000_000);
 
     Usage {
         db_strong_reads: 0,
         db_strong_writes: 0,
         db_weak_reads: db_read,
         db_weak_writes: db_write,
-        function_instructions: instructions_count,
+        function_instructions: execution.instructions,
         memory_megabytes,
+        custom_metrics: HashMap::new(),
     }
 }
@@ -2,23 +2,29 @@ pub mod error;
 pub mod function;
 pub mod instance;
 pub mod memory;
+mod module_cache;
 mod pipe;
 pub mod providers;
 mod types;
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    future::Future,
     ops::{Add, AddAssign},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
 use async_trait::async_trait;
 use dyn_clonable::clonable;
 use log::*;
-use tokio::sync::mpsc;
+use tokio::{
+    sync::mpsc,
+    task::{AbortHandle, JoinSet},
+};
 use wasmer::{Module, Store};
-use wasmer_cache::{Cache, FileSystemCache};
 
 use mailbox_processor::{callback::CallbackMailboxProcessor, NotificationChannel, ReplyChannel};
 use mu_common::id::IdExt;
@@ -28,31 +34,65 @@ use mu_storage::StorageManager;
 use musdk_common::{Header, Request, Response};
 
 use instance::{utils::create_store, Instance};
+use module_cache::ModuleCache;
 use providers::AssemblyProvider;
 
-pub use error::{Error, FunctionLoadingError, FunctionRuntimeError, Result};
-pub use types::{AssemblyDefinition, InvokeFunctionRequest, RuntimeConfig};
+pub use error::{Error, FunctionFailureKind, FunctionLoadingError, FunctionRuntimeError, Result};
+pub use types::{
+    AssemblyDefinition, FunctionResponse, InvokeFunctionRequest, PreopenedDir,
+    ReloadableRuntimeConfig, ResponseBodyStream, RuntimeConfig, WasiConfig, WasmCompiler,
+};
+
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
 
 #[async_trait]
 #[clonable]
 pub trait Runtime: Clone + Send + Sync {
+    /// Collects streamed response bodies, see [`Runtime::invoke_function_streaming`].
     async fn invoke_function<'a>(
         &self,
         function_id: FunctionID,
         request: Request<'a>,
     ) -> Result<Response<'static>>;
 
+    /// Returns as soon as the function's response is ready. Functions that
+    /// stream their response are still running by then, and the body must be
+    /// read from the returned stream for them to make progress.
+    async fn invoke_function_streaming<'a>(
+        &self,
+        function_id: FunctionID,
+        request: Request<'a>,
+    ) -> Result<FunctionResponse>;
+
     async fn stop(&self) -> Result<()>;
 
     async fn add_functions(&self, functions: Vec<AssemblyDefinition>) -> Result<()>;
     async fn remove_functions(&self, stack_id: StackID, names: Vec<String>) -> Result<()>;
     async fn remove_all_functions(&self, stack_id: StackID) -> Result<()>;
     async fn get_function_names(&self, stack_id: StackID) -> Result<Vec<String>>;
+
+    /// Applies to invocations started after the call; running ones keep the
+    /// limits they were started with.
+    async fn reconfigure(&self, config: ReloadableRuntimeConfig) -> Result<()>;
+
+    /// Number of instances started on demand because no warm instance was
+    /// available for the invoked function.
+    async fn cold_start_count(&self) -> Result<u64>;
 }
 
 #[derive(Clone)]
 pub enum Notification {
     ReportUsage(StackID, Usage),
+    FunctionLog(FunctionLog),
+}
+
+/// A log message written by a function, reported regardless of
+/// whether function logs are included in the runtime's own logs.
+#[derive(Clone, Debug)]
+pub struct FunctionLog {
+    pub function_id: AssemblyID,
+    pub level: Level,
+    pub body: String,
 }
 
 #[derive(Default, Clone)]
@@ -62,19 +102,17 @@ pub struct Usage {
     pub db_weak_writes: u64,
     pub db_strong_writes: u64,
     pub function_instructions: u64,
+    /// The most linear memory the function used, in megabytes rounded up.
     pub memory_megabytes: u64,
+    /// Application-defined metrics reported by functions, by name.
+    pub custom_metrics: HashMap<String, u64>,
 }
 
 impl Add for Usage {
     type Output = Self;
 
     fn add(mut self, rhs: Self) -> Self::Output {
-        self.db_weak_reads += rhs.db_weak_reads;
-        self.db_weak_writes += rhs.db_weak_writes;
-        self.db_strong_reads += rhs.db_strong_reads;
-        self.db_strong_writes += rhs.db_strong_writes;
-        self.function_instructions += rhs.function_instructions;
-        self.memory_megabytes += rhs.memory_megabytes;
+        self += rhs;
         self
     }
 }
@@ -87,6 +125,10 @@ impl AddAssign for Usage {
         self.db_strong_writes += rhs.db_strong_writes;
         self.function_instructions += rhs.function_instructions;
         self.memory_megabytes += rhs.memory_megabytes;
+        for (name, value) in rhs.custom_metrics {
+            let total = self.custom_metrics.entry(name).or_insert(0);
+            *total = total.saturating_add(value);
+        }
     }
 }
 
@@ -99,6 +141,15 @@ enum MailboxMessage {
     RemoveFunctions(StackID, Vec<String>),
     RemoveAllFunctions(StackID),
     GetFunctionNames(StackID, ReplyChannel<Vec<String>>),
+    Reconfigure(ReloadableRuntimeConfig),
+    GetColdStartCount(ReplyChannel<u64>),
+    ReapInstances,
+    InvocationFinished(StackID),
+    WarmInstanceStarted {
+        assembly_id: AssemblyID,
+        generation: u64,
+        result: Result<Instance>,
+    },
 }
 
 #[derive(Clone)]
@@ -106,9 +157,44 @@ struct RuntimeImpl {
     mailbox: CallbackMailboxProcessor<MailboxMessage>,
 }
 
+type InvokeFunctionReply = ReplyChannel<Result<FunctionResponse>>;
+
+struct LiveInstance {
+    id: types::InstanceID,
+    started_at: Instant,
+    task: AbortHandle,
+    io: types::FunctionIO,
+    // Reaped instances are kept until their task ends, since the function
+    // may keep running until then
+    reaped: bool,
+    // Taken by whichever of the instance's task or the reaper finishes first
+    reply: Arc<Mutex<Option<InvokeFunctionReply>>>,
+}
+
+struct WarmInstance {
+    instance: Instance,
+    started_at: Instant,
+}
+
+// Releases the stack's invocation slot when the task running the invocation
+// ends, which for reaped instances is once the function actually stopped,
+// and for streamed responses once the body stream ends.
+struct InvocationSlot {
+    mailbox: CallbackMailboxProcessor<MailboxMessage>,
+    stack_id: StackID,
+}
+
+impl Drop for InvocationSlot {
+    fn drop(&mut self) {
+        self.mailbox
+            .post_and_forget(MailboxMessage::InvocationFinished(self.stack_id));
+    }
+}
+
 struct CacheHashAndMemoryLimit {
     hash: wasmer_cache::Hash,
     memory_limit: byte_unit::Byte,
+    compiler: WasmCompiler,
 }
 
 struct RuntimeState {
@@ -117,10 +203,23 @@ struct RuntimeState {
     db_manager: Box<dyn DbManager>,
     storage_manager: Box<dyn StorageManager>,
     hashkey_dict: HashMap<AssemblyID, CacheHashAndMemoryLimit>,
-    cache: FileSystemCache,
+    cache: ModuleCache,
     next_instance_id: u64,
     notification_channel: NotificationChannel<Notification>,
     is_shut_down: bool,
+    instance_tasks: JoinSet<()>,
+    live_instances: Vec<LiveInstance>,
+    // Instances that were started ahead of time and are waiting for a
+    // request. Each one still serves a single request, so no state carries
+    // over between invocations.
+    warm_instances: HashMap<AssemblyID, Vec<WarmInstance>>,
+    // Warm instances still being started in the background
+    starting_warm_instances: HashMap<AssemblyID, usize>,
+    // Bumped whenever warm instances are evicted, so the ones that were still
+    // starting at the time are discarded once they're ready
+    warm_pool_generation: u64,
+    cold_starts: u64,
+    running_invocations: HashMap<StackID, usize>,
 }
 
 impl RuntimeState {
@@ -132,8 +231,8 @@ impl RuntimeState {
         let (tx, rx) = NotificationChannel::new();
 
         let hashkey_dict = HashMap::new();
-        let mut cache = FileSystemCache::new(&config.cache_path).map_err(Error::CacheSetup)?;
-        cache.set_cache_extension(Some("wasmu"));
+        let cache = ModuleCache::new(&config.cache_path, config.cache_max_bytes)
+            .map_err(Error::CacheSetup)?;
 
         Ok((
             Self {
@@ -146,6 +245,13 @@ impl RuntimeState {
                 next_instance_id: 0,
                 notification_channel: tx,
                 is_shut_down: false,
+                instance_tasks: JoinSet::new(),
+                live_instances: vec![],
+                warm_instances: HashMap::new(),
+                starting_warm_instances: HashMap::new(),
+                warm_pool_generation: 0,
+                cold_starts: 0,
+                running_invocations: HashMap::new(),
             },
             rx,
         ))
@@ -153,13 +259,17 @@ impl RuntimeState {
 
     fn load_module(&mut self, assembly_id: &AssemblyID) -> Result<(Store, Module)> {
         if self.hashkey_dict.contains_key(assembly_id) {
-            let CacheHashAndMemoryLimit { hash, memory_limit } = self
+            let CacheHashAndMemoryLimit {
+                hash,
+                memory_limit,
+                compiler,
+            } = self
                 .hashkey_dict
                 .get(assembly_id)
                 .ok_or_else(|| Error::Internal(anyhow!("cache key can not be found")))?
                 .to_owned();
 
-            let store = create_store(*memory_limit, self.config.max_giga_instructions_per_call)?;
+            let store = create_store(*memory_limit, *compiler)?;
 
             match unsafe { self.cache.load(&store, *hash) } {
                 Ok(module) => Ok((store, module)),
@@ -172,7 +282,8 @@ impl RuntimeState {
                         ))
                     })?;
 
-                    let module = Module::new(&store, definition.source.clone()).map_err(|e| {
+                    let source = definition.decompressed_source()?;
+                    let module = Module::new(&store, source).map_err(|e| {
                         Error::FunctionLoadingError(FunctionLoadingError::CompileWasmModule(e))
                     })?;
 
@@ -195,11 +306,22 @@ impl RuntimeState {
                 }
             };
 
-            let mut hash_array = Vec::with_capacity(assembly_id.assembly_name.len() + 16); // Uuid is 16 bytes
-            hash_array.extend_from_slice(assembly_id.stack_id.get_bytes()); //This is bad, should
-                                                                            //use a method on
-                                                                            //StackID
+            // The stack ID's byte form includes its chain, so IDs from different
+            // chains can't collide
+            let stack_id = assembly_id.stack_id.to_bytes();
+            let mut hash_array = Vec::with_capacity(
+                stack_id.len() + assembly_id.assembly_name.len() + assembly_definition.source.len(),
+            );
+            hash_array.extend_from_slice(&stack_id);
             hash_array.extend_from_slice(assembly_id.assembly_name.as_bytes());
+
+            // Updated code must never be served from the previous version's artifact
+            hash_array.extend_from_slice(&assembly_definition.source);
+
+            // Artifacts from one backend can't be loaded by another
+            let compiler = assembly_definition.compiler.unwrap_or(self.config.compiler);
+            hash_array.extend_from_slice(compiler.name().as_bytes());
+
             let hash = wasmer_cache::Hash::generate(&hash_array);
 
             self.hashkey_dict.insert(
@@ -207,15 +329,14 @@ impl RuntimeState {
                 CacheHashAndMemoryLimit {
                     hash,
                     memory_limit: assembly_definition.memory_limit,
+                    compiler,
                 },
             );
 
-            let store = create_store(
-                assembly_definition.memory_limit,
-                self.config.max_giga_instructions_per_call,
-            )?;
+            let store = create_store(assembly_definition.memory_limit, compiler)?;
 
-            if let Ok(module) = Module::from_binary(&store, &assembly_definition.source) {
+            let source = assembly_definition.decompressed_source()?;
+            if let Ok(module) = Module::from_binary(&store, &source) {
                 if let Err(e) = self.cache.store(hash, &module) {
                     error!("failed to cache module: {e}, function id: {}", assembly_id);
                 }
@@ -229,7 +350,22 @@ impl RuntimeState {
         }
     }
 
+    fn forget_cached_module(&mut self, assembly_id: &AssemblyID) {
+        if let Some(entry) = self.hashkey_dict.remove(assembly_id) {
+            self.cache.remove(entry.hash);
+        }
+    }
+
     async fn start_function(&mut self, assembly_id: AssemblyID) -> Result<Instance> {
+        self.prepare_instance(assembly_id)?.await
+    }
+
+    // Does the part of starting an instance that needs the runtime's state,
+    // so the rest can run outside the mailbox
+    fn prepare_instance(
+        &mut self,
+        assembly_id: AssemblyID,
+    ) -> Result<impl Future<Output = Result<Instance>> + Send + 'static> {
         trace!("instantiate function {}", assembly_id);
         let definition = self
             .assembly_provider
@@ -250,17 +386,164 @@ impl RuntimeState {
             instance_id: self.next_instance_id.get_and_increment(),
         };
 
-        Instance::start(
-            instance_id,
-            definition.envs,
-            store,
-            module,
-            definition.memory_limit,
-            self.config.max_giga_instructions_per_call,
-            self.config.include_function_logs,
-            self.db_manager.clone(),
-            self.storage_manager.clone(),
-        )
+        let instruction_limit = self.config.instruction_limit();
+        let include_logs = self.config.include_function_logs;
+        let wasi_config = self.config.wasi.clone();
+        let db_manager = self.db_manager.clone();
+        let storage_manager = self.storage_manager.clone();
+        let notification_channel = self.notification_channel.clone();
+
+        Ok(async move {
+            Instance::start(
+                instance_id,
+                definition.envs,
+                definition.secrets,
+                store,
+                module,
+                instruction_limit,
+                include_logs,
+                &wasi_config,
+                db_manager,
+                storage_manager,
+                notification_channel,
+            )
+            .await
+        })
+    }
+
+    async fn take_or_start_function(&mut self, assembly_id: &AssemblyID) -> Result<Instance> {
+        while let Some(WarmInstance { instance, .. }) =
+            self.warm_instances.get_mut(assembly_id).and_then(Vec::pop)
+        {
+            if instance.is_finished() {
+                self.discard_instance(instance);
+            } else {
+                trace!("using warm instance {}", instance.id());
+                return Ok(instance);
+            }
+        }
+
+        self.cold_starts += 1;
+        self.start_function(assembly_id.clone()).await
+    }
+
+    // Starting instances can take a while, so it's done in the background
+    // instead of holding up every other message. They're added to the pool
+    // with `MailboxMessage::WarmInstanceStarted`.
+    fn fill_warm_pool(
+        &mut self,
+        assembly_id: &AssemblyID,
+        mailbox: &CallbackMailboxProcessor<MailboxMessage>,
+    ) {
+        let target = self.config.warm_instances_per_function;
+        let ready = self.warm_instances.get(assembly_id).map_or(0, Vec::len);
+        let starting = self
+            .starting_warm_instances
+            .get(assembly_id)
+            .copied()
+            .unwrap_or(0);
+
+        for _ in (ready + starting)..target {
+            let start = match self.prepare_instance(assembly_id.clone()) {
+                Ok(start) => start,
+                Err(e) => {
+                    warn!("failed to start warm instance of function {assembly_id}: {e}");
+                    return;
+                }
+            };
+
+            *self
+                .starting_warm_instances
+                .entry(assembly_id.clone())
+                .or_default() += 1;
+
+            let mailbox = mailbox.clone();
+            let assembly_id = assembly_id.clone();
+            let generation = self.warm_pool_generation;
+            self.instance_tasks.spawn(async move {
+                let result = start.await;
+                mailbox.post_and_forget(MailboxMessage::WarmInstanceStarted {
+                    assembly_id,
+                    generation,
+                    result,
+                });
+            });
+        }
+    }
+
+    fn add_warm_instance(
+        &mut self,
+        assembly_id: AssemblyID,
+        generation: u64,
+        result: Result<Instance>,
+    ) {
+        if let Entry::Occupied(mut entry) = self.starting_warm_instances.entry(assembly_id.clone())
+        {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+
+        match result {
+            Ok(instance) if self.is_shut_down || generation != self.warm_pool_generation => {
+                self.discard_instance(instance)
+            }
+            Ok(instance) => {
+                self.warm_instances
+                    .entry(assembly_id)
+                    .or_default()
+                    .push(WarmInstance {
+                        instance,
+                        started_at: Instant::now(),
+                    })
+            }
+            Err(e) => warn!("failed to start warm instance of function {assembly_id}: {e}"),
+        }
+    }
+
+    fn evict_warm_instances(&mut self, filter: impl Fn(&AssemblyID) -> bool) {
+        self.warm_pool_generation += 1;
+
+        let ids = self
+            .warm_instances
+            .keys()
+            .filter(|id| filter(id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for id in ids {
+            for warm in self.warm_instances.remove(&id).into_iter().flatten() {
+                self.discard_instance(warm.instance);
+            }
+        }
+    }
+
+    fn reap_warm_instances(&mut self, max_lifetime: Duration) {
+        let mut expired = vec![];
+        for pool in self.warm_instances.values_mut() {
+            let (live, old): (Vec<_>, Vec<_>) = std::mem::take(pool)
+                .into_iter()
+                .partition(|warm| warm.started_at.elapsed() < max_lifetime);
+            *pool = live;
+            expired.extend(old);
+        }
+
+        for warm in expired {
+            trace!("reaping warm instance {}", warm.instance.id());
+            self.discard_instance(warm.instance);
+        }
+    }
+
+    // Instances use some resources while starting up, which are reported
+    // once they've exited
+    fn discard_instance(&mut self, instance: Instance) {
+        let notification_channel = self.notification_channel.clone();
+        self.instance_tasks.spawn(async move {
+            let stack_id = instance.id().function_id.stack_id;
+            let usage = instance.discard().await;
+            notification_channel.send(Notification::ReportUsage(stack_id, usage));
+        });
     }
 }
 
@@ -271,13 +554,36 @@ impl Runtime for RuntimeImpl {
         function_id: FunctionID,
         request: Request<'a>,
     ) -> Result<Response<'static>> {
+        let FunctionResponse {
+            mut response,
+            body_stream,
+        } = self.invoke_function_streaming(function_id, request).await?;
+
+        if let Some(mut body_stream) = body_stream {
+            let mut body = vec![];
+            while let Some(chunk) = body_stream.recv().await {
+                body.extend(chunk.map_err(Error::Internal)?);
+            }
+            response.body = Cow::Owned(body);
+        }
+
+        Ok(response)
+    }
+
+    async fn invoke_function_streaming<'a>(
+        &self,
+        function_id: FunctionID,
+        request: Request<'a>,
+    ) -> Result<FunctionResponse> {
         // TODO: This is a rather ridiculous thing to do, but necessary
         // since we're sending the request to another thread. There has
         // to be a better way.
         let request = musdk_common::incoming_message::ExecuteFunction {
+            stack_id: Cow::Owned(function_id.assembly_id.stack_id.to_string()),
             function: Cow::Owned(function_id.function_name),
             request: Request {
                 method: request.method,
+                route_template: Cow::Owned(request.route_template.into_owned()),
                 path_params: request
                     .path_params
                     .into_iter()
@@ -311,19 +617,28 @@ impl Runtime for RuntimeImpl {
             })
             .await
             .map_err(|e| Error::Internal(e.into()))??;
-        Ok(response.response)
+        Ok(response)
     }
 
+    /// Waits for running invocations to finish first, see
+    /// [`RuntimeConfig::shutdown_timeout`]. Stopping twice is harmless.
     async fn stop(&self) -> Result<()> {
-        self.mailbox
-            .post(MailboxMessage::Shutdown)
-            .await
-            .map_err(|e| Error::Internal(e.into()))?;
+        // Posting only fails if the mailbox is already stopped
+        let _ = self.mailbox.post(MailboxMessage::Shutdown).await;
         self.mailbox.clone().stop().await;
         Ok(())
     }
 
     async fn add_functions(&self, functions: Vec<AssemblyDefinition>) -> Result<()> {
+        // Functions replace earlier definitions with the same ID, which is how
+        // updates work, but a single batch must not contain the same ID twice.
+        let mut ids = HashSet::new();
+        if let Some(f) = functions.iter().find(|f| !ids.insert(&f.id)) {
+            return Err(Error::FunctionLoadingError(
+                FunctionLoadingError::DuplicateFunctionName(f.id.clone()),
+            ));
+        }
+
         self.mailbox
             .post(MailboxMessage::AddFunctions(functions))
             .await
@@ -350,6 +665,20 @@ impl Runtime for RuntimeImpl {
             .await
             .map_err(|e| Error::Internal(e.into()))
     }
+
+    async fn reconfigure(&self, config: ReloadableRuntimeConfig) -> Result<()> {
+        self.mailbox
+            .post(MailboxMessage::Reconfigure(config))
+            .await
+            .map_err(|e| Error::Internal(e.into()))
+    }
+
+    async fn cold_start_count(&self) -> Result<u64> {
+        self.mailbox
+            .post_and_reply(MailboxMessage::GetColdStartCount)
+            .await
+            .map_err(|e| Error::Internal(e.into()))
+    }
 }
 
 pub async fn start(
@@ -360,11 +689,35 @@ pub async fn start(
     let (state, notification_receiver) =
         RuntimeState::new(db_manager, storage_manager, config).await?;
     let mailbox = CallbackMailboxProcessor::start(mailbox_step, state, 10000);
-    Ok((Box::new(RuntimeImpl { mailbox }), notification_receiver))
+    let runtime = RuntimeImpl { mailbox };
+
+    let runtime_clone = runtime.clone();
+    tokio::spawn(async move { generate_reap_tick(runtime_clone).await });
+
+    Ok((Box::new(runtime), notification_receiver))
+}
+
+async fn generate_reap_tick(runtime: RuntimeImpl) {
+    let mut timer = tokio::time::interval(REAP_INTERVAL);
+    // Timers tick once immediately
+    timer.tick().await;
+
+    loop {
+        timer.tick().await;
+        // A runtime that's falling behind doesn't need more ticks queued up,
+        // the next one reaps whatever this one would have
+        match runtime.mailbox.try_post(MailboxMessage::ReapInstances) {
+            Ok(()) => (),
+            Err(mailbox_processor::Error::MailboxFull) => {
+                debug!("Skipping reap tick, the runtime's mailbox is full")
+            }
+            Err(mailbox_processor::Error::MailboxStopped) => return,
+        }
+    }
 }
 
 async fn mailbox_step(
-    _mb: CallbackMailboxProcessor<MailboxMessage>,
+    mb: CallbackMailboxProcessor<MailboxMessage>,
     msg: MailboxMessage,
     mut state: RuntimeState,
 ) -> RuntimeState {
@@ -373,18 +726,52 @@ async fn mailbox_step(
             if state.is_shut_down {
                 req.reply.reply(Err(Error::RuntimeIsShutDown));
             } else {
-                execute_function(&mut state, req).await;
+                execute_function(&mut state, &mb, req).await;
             }
         }
 
         MailboxMessage::Shutdown => {
-            // We need to wait for running user functions, so we simply
-            // stop accepting new requests.
+            // Running invocations still report their usage when they finish,
+            // so we wait for them before letting the runtime stop.
             state.is_shut_down = true;
+            state.evict_warm_instances(|_| true);
+
+            let timeout = state.config.shutdown_timeout.as_deref().copied();
+            let tasks = &mut state.instance_tasks;
+            let drain = async { while tasks.join_next().await.is_some() {} };
+            let drained = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, drain).await.is_ok(),
+                None => {
+                    drain.await;
+                    true
+                }
+            };
+
+            if !drained {
+                warn!(
+                    "Stopping {} invocations still running after the shutdown timeout",
+                    state.instance_tasks.len()
+                );
+
+                // Aborting the tasks alone would leave the functions running on
+                // their blocking threads, so they're cut off from their pipes
+                // first, same as in `reap_instances`
+                for instance in &mut state.live_instances {
+                    instance.io.close();
+                    if let Some(reply) = instance.reply.lock().unwrap().take() {
+                        reply.reply(Err(Error::RuntimeIsShutDown));
+                    }
+                }
+                state.instance_tasks.shutdown().await;
+            }
         }
 
         MailboxMessage::AddFunctions(functions) => {
             for f in functions {
+                // Warm instances and cached modules of an updated function
+                // are for the old code
+                state.evict_warm_instances(|id| *id == f.id);
+                state.forget_cached_module(&f.id);
                 state.assembly_provider.add_function(f);
             }
         }
@@ -396,16 +783,18 @@ async fn mailbox_step(
                     assembly_name: function_name,
                 };
 
+                state.evict_warm_instances(|id| *id == assembly_id);
                 state.assembly_provider.remove_function(&assembly_id);
-                state.hashkey_dict.remove(&assembly_id);
+                state.forget_cached_module(&assembly_id);
             }
         }
 
         MailboxMessage::RemoveAllFunctions(stack_id) => {
+            state.evict_warm_instances(|id| id.stack_id == stack_id);
             let function_names = state.assembly_provider.remove_all_functions(&stack_id);
             if let Some(names) = function_names {
                 for name in names {
-                    state.hashkey_dict.remove(&AssemblyID {
+                    state.forget_cached_module(&AssemblyID {
                         stack_id,
                         assembly_name: name,
                     });
@@ -416,31 +805,167 @@ async fn mailbox_step(
         MailboxMessage::GetFunctionNames(stack_id, r) => {
             r.reply(state.assembly_provider.get_function_names(&stack_id));
         }
+
+        MailboxMessage::Reconfigure(config) => {
+            let include_function_logs_changed =
+                state.config.include_function_logs != config.include_function_logs;
+            state.config.apply(config);
+
+            if include_function_logs_changed {
+                // Warm instances were started with the old setting
+                state.evict_warm_instances(|_| true);
+            }
+        }
+
+        MailboxMessage::GetColdStartCount(r) => r.reply(state.cold_starts),
+
+        MailboxMessage::ReapInstances => reap_instances(&mut state),
+
+        MailboxMessage::WarmInstanceStarted {
+            assembly_id,
+            generation,
+            result,
+        } => state.add_warm_instance(assembly_id, generation, result),
+
+        MailboxMessage::InvocationFinished(stack_id) => {
+            if let Entry::Occupied(mut entry) = state.running_invocations.entry(stack_id) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
     }
     state
 }
-async fn execute_function(state: &mut RuntimeState, req: InvokeFunctionRequest) {
-    match state.start_function(req.assembly_id.clone()).await {
+
+fn reap_instances(state: &mut RuntimeState) {
+    while state.instance_tasks.try_join_next().is_some() {}
+    state.live_instances.retain(|i| !i.task.is_finished());
+
+    let max_lifetime = state.config.max_instance_lifetime.as_deref().copied();
+    let warm_idle_timeout = state.config.warm_instance_idle_timeout.as_deref().copied();
+    if let Some(max_age) = max_lifetime.into_iter().chain(warm_idle_timeout).min() {
+        state.reap_warm_instances(max_age);
+    }
+
+    let Some(max_lifetime) = max_lifetime else {
+        return;
+    };
+
+    // As with timeouts, wasmer can't interrupt a running call, so reaped
+    // instances are cut off from their pipes and stop at their next read, or
+    // when they run out of instructions. Their task still reports the usage
+    // they accumulated.
+    for instance in &mut state.live_instances {
+        if instance.reaped || instance.started_at.elapsed() < max_lifetime {
+            continue;
+        }
+
+        warn!(
+            "Reaping instance {} of function {} after exceeding maximum lifetime of {max_lifetime:?}",
+            instance.id.instance_id, instance.id.function_id
+        );
+        instance.reaped = true;
+        instance.io.close();
+        if let Some(reply) = instance.reply.lock().unwrap().take() {
+            reply.reply(Err(Error::InstanceReaped));
+        }
+    }
+}
+async fn execute_function(
+    state: &mut RuntimeState,
+    mailbox: &CallbackMailboxProcessor<MailboxMessage>,
+    req: InvokeFunctionRequest,
+) {
+    let stack_id = req.assembly_id.stack_id;
+    let running = state
+        .running_invocations
+        .get(&stack_id)
+        .copied()
+        .unwrap_or(0);
+    if let Some(limit) = state.config.max_concurrent_invocations_per_stack {
+        if running >= limit {
+            warn!("Rejecting invocation for stack {stack_id}, {running} already running");
+            req.reply.reply(Err(Error::TooManyConcurrentInvocations));
+            return;
+        }
+    }
+
+    match state.take_or_start_function(&req.assembly_id).await {
         Ok(instance) => {
+            let assembly_id = req.assembly_id.clone();
+            *state.running_invocations.entry(stack_id).or_insert(0) += 1;
+            let slot = InvocationSlot {
+                mailbox: mailbox.clone(),
+                stack_id,
+            };
             let notification_channel = state.notification_channel.clone();
+            let id = instance.id().clone();
+            let task_id = id.clone();
+            let reply = Arc::new(Mutex::new(Some(req.reply)));
+            let task_reply = reply.clone();
+            let respond_reply = reply.clone();
+            let respond: types::Respond = Box::new(move |response| {
+                if let Some(reply) = respond_reply.lock().unwrap().take() {
+                    reply.reply(Ok(response));
+                }
+            });
+            let max_execution_time = *state.config.max_execution_time;
+            let io = instance.io();
+            let mut task_io = io.clone();
+
+            let task = state.instance_tasks.spawn(async move {
+                let _slot = slot;
+                let mut run = Box::pin(instance.run_request(req.request, respond));
+
+                let result = match tokio::time::timeout(max_execution_time, &mut run).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        warn!(
+                            "Instance {} of function {} exceeded maximum execution time of {max_execution_time:?}",
+                            task_id.instance_id, task_id.function_id
+                        );
+                        if let Some(reply) = task_reply.lock().unwrap().take() {
+                            reply.reply(Err(Error::FunctionRuntimeError(
+                                FunctionRuntimeError::Timeout,
+                            )));
+                        }
+
+                        // wasmer can't interrupt a running call, so the best we
+                        // can do is cut the function off from its pipes. It will
+                        // stop at its next read, or when it runs out of
+                        // instructions, and we still wait for that to report
+                        // the usage it accumulated.
+                        task_io.close();
+                        run.await
+                    }
+                };
 
-            tokio::spawn(async move {
-                let result = instance
-                    .run_request(req.request)
-                    .await
-                    .map(|(resp, usages)| {
-                        notification_channel
-                            .send(Notification::ReportUsage(req.assembly_id.stack_id, usages));
-                        resp
-                    })
-                    .map_err(|(error, usages)| {
+                // Successful runs have already sent their response
+                match result {
+                    Ok(usages) => notification_channel
+                        .send(Notification::ReportUsage(req.assembly_id.stack_id, usages)),
+                    Err((error, usages)) => {
                         notification_channel
                             .send(Notification::ReportUsage(req.assembly_id.stack_id, usages));
-                        error
-                    });
+                        if let Some(reply) = task_reply.lock().unwrap().take() {
+                            reply.reply(Err(error));
+                        }
+                    }
+                }
+            });
 
-                req.reply.reply(result);
+            state.live_instances.push(LiveInstance {
+                id,
+                started_at: Instant::now(),
+                task,
+                io,
+                reaped: false,
+                reply,
             });
+
+            state.fill_warm_pool(&assembly_id, mailbox);
         }
         Err(f) => req.reply.reply(Err(f)),
     }
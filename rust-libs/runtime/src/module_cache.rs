@@ -0,0 +1,274 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use wasmer::{DeserializeError, Module, SerializeError, Store};
+use wasmer_cache::{Cache, FileSystemCache, Hash};
+
+const EXTENSION: &str = "wasmu";
+
+struct CacheEntry {
+    size: u64,
+    last_access: u64,
+}
+
+/// Compiled modules stored on disk. Wasmer's `FileSystemCache` never deletes
+/// anything, so once the directory grows past `max_bytes` the least recently
+/// used artifacts are evicted.
+pub(crate) struct ModuleCache {
+    inner: FileSystemCache,
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    entries: HashMap<String, CacheEntry>,
+    total_bytes: u64,
+    // Incremented on every access, so entries can be ordered even when
+    // accessed within the same clock tick
+    access_counter: u64,
+}
+
+impl ModuleCache {
+    pub fn new(path: &Path, max_bytes: Option<u64>) -> io::Result<Self> {
+        let mut inner = FileSystemCache::new(path)?;
+        inner.set_cache_extension(Some(EXTENSION));
+
+        // Artifacts left over from earlier runs are ranked by when they were written
+        let mut existing = vec![];
+        for dir_entry in fs::read_dir(path)? {
+            let dir_entry = dir_entry?;
+            let file_path = dir_entry.path();
+            if file_path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(key) = file_path.file_stem().and_then(|s| s.to_str()) {
+                let metadata = dir_entry.metadata()?;
+                existing.push((key.to_owned(), metadata.len(), metadata.modified().ok()));
+            }
+        }
+        existing.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut cache = Self {
+            inner,
+            path: path.to_owned(),
+            max_bytes,
+            entries: HashMap::new(),
+            total_bytes: 0,
+            access_counter: 0,
+        };
+        for (key, size, _) in existing {
+            cache.track(key, size);
+        }
+        cache.evict(None);
+
+        Ok(cache)
+    }
+
+    /// # Safety
+    ///
+    /// Same as [`FileSystemCache::load`]: the artifact on disk must not have
+    /// been tampered with.
+    pub unsafe fn load(
+        &mut self,
+        store: &Store,
+        hash: Hash,
+    ) -> std::result::Result<Module, DeserializeError> {
+        let module = self.inner.load(store, hash)?;
+
+        let key = hash.to_string();
+        match self.entries.get_mut(&key) {
+            Some(entry) => {
+                self.access_counter += 1;
+                entry.last_access = self.access_counter;
+            }
+            None => {
+                let size = self.file_size(&key);
+                self.track(key, size);
+            }
+        }
+
+        Ok(module)
+    }
+
+    pub fn store(
+        &mut self,
+        hash: Hash,
+        module: &Module,
+    ) -> std::result::Result<(), SerializeError> {
+        self.inner.store(hash, module)?;
+
+        let key = hash.to_string();
+        let size = self.file_size(&key);
+        self.track(key.clone(), size);
+        self.evict(Some(&key));
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, hash: Hash) {
+        self.remove_entry(&hash.to_string());
+    }
+
+    fn track(&mut self, key: String, size: u64) {
+        self.access_counter += 1;
+        let entry = CacheEntry {
+            size,
+            last_access: self.access_counter,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.total_bytes -= old.size;
+        }
+        self.total_bytes += size;
+    }
+
+    // The entry that was just stored is never evicted, even if it doesn't
+    // fit on its own
+    fn evict(&mut self, keep: Option<&str>) {
+        let Some(max_bytes) = self.max_bytes else {
+            return;
+        };
+
+        while self.total_bytes > max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(key, _)| Some(key.as_str()) != keep)
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone());
+
+            match oldest {
+                Some(key) => {
+                    debug!("evicting cached module {key}");
+                    self.remove_entry(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn remove_entry(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.size;
+        }
+
+        match fs::remove_file(self.file_path(key)) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => warn!("failed to delete cached module {key}: {e}"),
+        }
+    }
+
+    fn file_path(&self, key: &str) -> PathBuf {
+        self.path.join(format!("{key}.{EXTENSION}"))
+    }
+
+    fn file_size(&self, key: &str) -> u64 {
+        fs::metadata(self.file_path(key))
+            .map(|m| m.len())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use wasmer::{Module, Store};
+    use wasmer_cache::Hash;
+
+    use super::ModuleCache;
+    use crate::{instance::utils::create_store, WasmCompiler};
+
+    // The smallest valid module: just the magic number and version
+    const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn temp_cache_dir() -> PathBuf {
+        let rand: [u8; 8] = rand::random();
+        let name = rand
+            .into_iter()
+            .fold(String::from("module-cache-"), |a, i| format!("{a}{i:02x}"));
+        std::env::temp_dir().join(name)
+    }
+
+    fn make_module() -> (Store, Module) {
+        let store = create_store(
+            byte_unit::Byte::from_bytes(1024 * 1024),
+            WasmCompiler::Cranelift,
+        )
+        .unwrap();
+        let module = Module::new(&store, EMPTY_MODULE).unwrap();
+        (store, module)
+    }
+
+    fn is_cached(dir: &Path, hash: Hash) -> bool {
+        let key = hash.to_string();
+        dir.join(format!("{key}.wasmu")).exists()
+    }
+
+    #[test]
+    fn least_recently_used_modules_are_evicted() {
+        let dir = temp_cache_dir();
+        let (store, module) = make_module();
+        let size = module.serialize().unwrap().len() as u64;
+
+        let [first, second, third] =
+            ["first", "second", "third"].map(|k| Hash::generate(k.as_bytes()));
+
+        // Room for exactly two modules
+        let mut cache = ModuleCache::new(&dir, Some(size * 2)).unwrap();
+        cache.store(first, &module).unwrap();
+        cache.store(second, &module).unwrap();
+
+        // Using the first one makes the second the oldest
+        unsafe { cache.load(&store, first) }.unwrap();
+        cache.store(third, &module).unwrap();
+
+        assert!(is_cached(&dir, first));
+        assert!(!is_cached(&dir, second));
+        assert!(is_cached(&dir, third));
+        assert!(unsafe { cache.load(&store, second) }.is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn existing_modules_are_evicted_when_over_the_limit() {
+        let dir = temp_cache_dir();
+        let (_, module) = make_module();
+        let size = module.serialize().unwrap().len() as u64;
+
+        let hashes = ["first", "second", "third"].map(|k| Hash::generate(k.as_bytes()));
+
+        let mut cache = ModuleCache::new(&dir, None).unwrap();
+        for hash in hashes {
+            cache.store(hash, &module).unwrap();
+        }
+        drop(cache);
+
+        ModuleCache::new(&dir, Some(size)).unwrap();
+        let remaining = hashes.iter().filter(|h| is_cached(&dir, **h)).count();
+        assert_eq!(1, remaining);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removed_modules_are_deleted() {
+        let dir = temp_cache_dir();
+        let (_, module) = make_module();
+        let hash = Hash::generate(b"removed");
+
+        let mut cache = ModuleCache::new(&dir, None).unwrap();
+        cache.store(hash, &module).unwrap();
+        assert!(is_cached(&dir, hash));
+
+        cache.remove(hash);
+        assert!(!is_cached(&dir, hash));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
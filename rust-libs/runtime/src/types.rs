@@ -5,22 +5,42 @@ use super::{
     pipe::Pipe,
 };
 
-use mu_stack::{AssemblyID, AssemblyRuntime};
+use mu_common::serde_support::ConfigDuration;
+pub use mu_stack::WasmCompiler;
+use mu_stack::{AssemblyID, AssemblyRuntime, BinaryCompression};
 
 use bytes::Bytes;
 use mailbox_processor::ReplyChannel;
+use musdk_common::Response;
 use serde::Deserialize;
-use std::{collections::HashMap, fmt::Display, marker::PhantomData, path::PathBuf};
-use tokio::task::JoinHandle;
+use std::{
+    borrow::Cow, collections::HashMap, fmt::Display, io::Read, marker::PhantomData, path::PathBuf,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
 
 pub(super) type ExecuteFunctionRequest<'a> = musdk_common::incoming_message::ExecuteFunction<'a>;
-pub(super) type ExecuteFunctionResponse = musdk_common::outgoing_message::FunctionResult<'static>;
+
+/// Chunks of a streamed response body. An error means the function failed
+/// part way through, so the body is incomplete.
+pub type ResponseBodyStream = mpsc::Receiver<anyhow::Result<Vec<u8>>>;
+
+/// The response of a function. Streamed responses have an empty body, which
+/// instead arrives through `body_stream` while the function is running.
+#[derive(Debug)]
+pub struct FunctionResponse {
+    pub response: Response<'static>,
+    pub body_stream: Option<ResponseBodyStream>,
+}
+
+// Called by an instance as soon as its response is ready, which for
+// streamed responses is before the function finishes.
+pub(super) type Respond = Box<dyn FnOnce(FunctionResponse) + Send>;
 
 #[derive(Debug)]
 pub struct InvokeFunctionRequest {
     pub assembly_id: AssemblyID,
     pub request: ExecuteFunctionRequest<'static>,
-    pub reply: ReplyChannel<Result<ExecuteFunctionResponse>>,
+    pub reply: ReplyChannel<Result<FunctionResponse>>,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
@@ -35,6 +55,9 @@ impl Display for InstanceID {
     }
 }
 
+// Far more than any real function needs
+const MAX_WASM_BYTES: u64 = 256 * 1024 * 1024;
+
 #[derive(Clone, Debug)]
 pub struct AssemblyDefinition {
     pub id: AssemblyID,
@@ -42,7 +65,12 @@ pub struct AssemblyDefinition {
     pub runtime: AssemblyRuntime,
 
     pub envs: HashMap<String, String>,
+    pub secrets: HashMap<String, String>,
     pub memory_limit: byte_unit::Byte,
+    /// Overrides `RuntimeConfig::compiler` for this assembly.
+    pub compiler: Option<WasmCompiler>,
+    /// `source` is kept compressed and only decompressed when compiled.
+    pub compression: BinaryCompression,
 
     _make_me_private: PhantomData<()>,
 }
@@ -56,10 +84,15 @@ impl AssemblyDefinition {
             IntoIter = impl Iterator<Item = (String, String)>,
             Item = (String, String),
         >,
+        secrets: impl IntoIterator<
+            IntoIter = impl Iterator<Item = (String, String)>,
+            Item = (String, String),
+        >,
         memory_limit: byte_unit::Byte,
     ) -> Result<Self> {
         let envs: HashMap<String, String> = envs.into_iter().collect();
-        for e in &envs {
+        let secrets: HashMap<String, String> = secrets.into_iter().collect();
+        for e in envs.iter().chain(secrets.iter()) {
             if e.0.contains('=') {
                 return Err(Error::FunctionLoadingError(
                     FunctionLoadingError::InvalidAssemblyDefinition(
@@ -82,32 +115,103 @@ impl AssemblyDefinition {
                 ));
             }
         }
+        if let Some(name) = secrets.keys().find(|k| envs.contains_key(*k)) {
+            return Err(Error::FunctionLoadingError(
+                FunctionLoadingError::InvalidAssemblyDefinition(format!(
+                    "Env '{name}' cannot be both a plain env and a secret"
+                )),
+            ));
+        }
         Ok(Self {
             id,
             source,
             runtime,
             envs,
+            secrets,
             memory_limit,
+            compiler: None,
+            compression: BinaryCompression::None,
             _make_me_private: PhantomData,
         })
     }
+
+    /// `None` uses `RuntimeConfig::compiler`.
+    pub fn with_compiler(mut self, compiler: Option<WasmCompiler>) -> Self {
+        self.compiler = compiler;
+        self
+    }
+
+    pub fn with_compression(mut self, compression: BinaryCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Fails once the output grows past `MAX_WASM_BYTES`, so a small
+    /// compressed source can't expand into more than we're willing to hold.
+    pub(crate) fn decompressed_source(&self) -> Result<Cow<'_, [u8]>> {
+        let source = self.source.as_ref();
+        let decoder: Box<dyn Read + '_> = match self.compression {
+            BinaryCompression::None => return Ok(Cow::Borrowed(source)),
+            BinaryCompression::Gzip => Box::new(flate2::read::GzDecoder::new(source)),
+            BinaryCompression::Zstd => {
+                Box::new(zstd::Decoder::new(source).map_err(|e| self.decompress_error(e))?)
+            }
+        };
+
+        let mut decompressed = vec![];
+        decoder
+            .take(MAX_WASM_BYTES + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| self.decompress_error(e))?;
+
+        if decompressed.len() as u64 > MAX_WASM_BYTES {
+            return Err(self.decompress_error(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("decompressed size exceeds {MAX_WASM_BYTES} bytes"),
+            )));
+        }
+
+        Ok(Cow::Owned(decompressed))
+    }
+
+    fn decompress_error(&self, e: std::io::Error) -> Error {
+        Error::FunctionLoadingError(FunctionLoadingError::DecompressAssembly(self.id.clone(), e))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FunctionIO {
     pub stdin: Pipe,
     pub stdout: Pipe,
     pub stderr: Pipe,
 }
 
+impl FunctionIO {
+    pub fn close(&mut self) {
+        self.stdin.close();
+        self.stdout.close();
+        self.stderr.close();
+    }
+}
+
+/// Resources a function used by the time it stopped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExecutionUsage {
+    pub instructions: u64,
+    pub peak_memory_bytes: u64,
+}
+
 #[derive(Debug)]
 pub struct FunctionHandle {
-    pub join_handle: JoinHandle<Result<u64, (Error, u64)>>,
+    pub join_handle: JoinHandle<Result<ExecutionUsage, (Error, ExecutionUsage)>>,
     pub io: FunctionIO,
 }
 
 impl FunctionHandle {
-    pub fn new(join_handle: JoinHandle<Result<u64, (Error, u64)>>, io: FunctionIO) -> Self {
+    pub fn new(
+        join_handle: JoinHandle<Result<ExecutionUsage, (Error, ExecutionUsage)>>,
+        io: FunctionIO,
+    ) -> Self {
         Self { join_handle, io }
     }
 
@@ -116,10 +220,115 @@ impl FunctionHandle {
     }
 }
 
+/// A host directory made visible to functions.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PreopenedDir {
+    pub host_path: PathBuf,
+    /// Where functions see the directory.
+    pub guest_path: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// What functions can see of the host through WASI. Nothing is exposed
+/// unless listed here, so functions can't open host files or read the
+/// runtime's environment by default.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct WasiConfig {
+    #[serde(default)]
+    pub preopened_dirs: Vec<PreopenedDir>,
+    /// Host environment variables passed on to functions. Variables set by
+    /// the function's own definition take precedence.
+    #[serde(default)]
+    pub env_allowlist: Vec<String>,
+}
+
+// More than any machine executes in a second, so the time limits are what
+// stops functions in practice. A function spinning without calling into the
+// host runs for a few times its time limit at most before being stopped.
+const MAX_INSTRUCTIONS_PER_SECOND: u128 = 10_000_000_000;
+
 #[derive(Deserialize, Clone)]
 pub struct RuntimeConfig {
     pub cache_path: PathBuf,
+    /// Least recently used compiled modules are deleted once the cache grows
+    /// past this size. `None` lets the cache grow indefinitely.
+    #[serde(default)]
+    pub cache_max_bytes: Option<u64>,
     pub include_function_logs: bool,
     // TODO: move this into a separate struct
     pub max_giga_instructions_per_call: Option<u32>,
+    #[serde(default)]
+    pub compiler: WasmCompiler,
+    /// Instances running for longer than this are stopped, independently of
+    /// per-request limits. `None` lets instances run indefinitely.
+    #[serde(default)]
+    pub max_instance_lifetime: Option<ConfigDuration>,
+    /// Requests taking longer than this fail with a timeout error. Functions
+    /// that keep running without calling into the host can't be interrupted,
+    /// so they're stopped once they've executed as many instructions as this
+    /// much time allows, see `instruction_limit`.
+    pub max_execution_time: ConfigDuration,
+    /// Instances kept started ahead of time for each function that has been
+    /// invoked at least once. Zero disables warm instances.
+    #[serde(default)]
+    pub warm_instances_per_function: usize,
+    /// Warm instances not used within this long are stopped, so functions
+    /// that aren't invoked anymore don't keep a thread each. `None` keeps
+    /// them until `max_instance_lifetime`.
+    #[serde(default)]
+    pub warm_instance_idle_timeout: Option<ConfigDuration>,
+    /// Invocations beyond this many running at once for the same stack are
+    /// rejected. `None` disables the limit. Functions streaming their
+    /// response keep running until the whole body is written, and writes
+    /// block while the client is slow to read, so a streamed invocation
+    /// holds its slot until the body stream ends.
+    #[serde(default)]
+    pub max_concurrent_invocations_per_stack: Option<usize>,
+    /// How long stopping the runtime waits for running invocations, so they
+    /// can still report their usage. Any left after this are cut off from
+    /// their pipes, like timed out ones, and their usage is lost. `None`
+    /// waits for all of them.
+    #[serde(default)]
+    pub shutdown_timeout: Option<ConfigDuration>,
+    #[serde(default)]
+    pub wasi: WasiConfig,
+}
+
+impl RuntimeConfig {
+    pub(crate) fn apply(&mut self, config: ReloadableRuntimeConfig) {
+        self.include_function_logs = config.include_function_logs;
+        self.max_instance_lifetime = config.max_instance_lifetime;
+        self.max_execution_time = config.max_execution_time;
+        self.max_concurrent_invocations_per_stack = config.max_concurrent_invocations_per_stack;
+    }
+
+    /// The number of instructions a new instance may execute. Running out of
+    /// instructions is the only way to stop a function that never calls into
+    /// the host, so besides `max_giga_instructions_per_call` this is also
+    /// bounded by the time limits.
+    pub(crate) fn instruction_limit(&self) -> u64 {
+        let max_time = match self.max_instance_lifetime.as_deref() {
+            Some(lifetime) => (*lifetime).min(*self.max_execution_time),
+            None => *self.max_execution_time,
+        };
+        let time_limit = u64::try_from(max_time.as_millis() * MAX_INSTRUCTIONS_PER_SECOND / 1000)
+            .unwrap_or(u64::MAX);
+
+        match self.max_giga_instructions_per_call {
+            Some(giga) => time_limit.min(giga as u64 * 1_000_000_000),
+            None => time_limit,
+        }
+    }
+}
+
+/// The settings of a [`RuntimeConfig`] that can be changed on a running
+/// runtime, see [`crate::Runtime::reconfigure`]. Everything else needs a
+/// restart.
+#[derive(Clone, Debug)]
+pub struct ReloadableRuntimeConfig {
+    pub include_function_logs: bool,
+    pub max_instance_lifetime: Option<ConfigDuration>,
+    pub max_execution_time: ConfigDuration,
+    pub max_concurrent_invocations_per_stack: Option<usize>,
 }
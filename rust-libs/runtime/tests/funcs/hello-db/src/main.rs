@@ -13,6 +13,19 @@ pub struct Create {
 
 pub type Update = Create;
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Todo {
+    pub title: String,
+    pub done: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PutTodo {
+    pub table_name: String,
+    pub key: String,
+    pub todo: Todo,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Read {
     pub table_name: String,
@@ -74,6 +87,24 @@ mod hello_db {
             .unwrap_or("".into())
     }
 
+    #[mu_function]
+    fn put_todo<'a>(ctx: &'a mut MuContext, req: Json<PutTodo>) {
+        let req = req.into_inner();
+        ctx.db()
+            .put_json(&req.table_name, req.key.as_bytes(), &req.todo, false)
+            .unwrap();
+    }
+
+    #[mu_function]
+    fn get_todo<'a>(ctx: &'a mut MuContext, req: Json<Read>) -> Json<Option<Todo>> {
+        let req = req.into_inner();
+        Json(
+            ctx.db()
+                .get_json(&req.table_name, req.key.as_bytes())
+                .unwrap(),
+        )
+    }
+
     #[mu_function]
     fn update<'a>(ctx: &'a mut MuContext, req: Json<Update>) {
         let req = req.into_inner();
@@ -143,6 +174,15 @@ mod hello_db {
             .unwrap()
     }
 
+    #[mu_function]
+    fn count<'a>(ctx: &'a mut MuContext, req: Json<(String, String, u64)>) -> String {
+        let req = req.into_inner();
+        ctx.db()
+            .count_by_prefix(&req.0, req.1.as_bytes(), req.2)
+            .unwrap()
+            .to_string()
+    }
+
     #[mu_function]
     fn batch_get<'a>(
         ctx: &'a mut MuContext,
@@ -214,4 +254,24 @@ mod hello_db {
             .collect::<Vec<_>>();
         ctx.db().batch_delete(&table_key_tuples).unwrap()
     }
+
+    #[mu_function]
+    fn batch_compare_and_swap<'a>(
+        ctx: &'a mut MuContext,
+        req: Json<Vec<(String, String, Option<String>, String)>>,
+    ) -> Json<Vec<(Option<String>, bool)>> {
+        let req = req.into_inner();
+        let ops = req
+            .iter()
+            .map(|(t, k, pv, v)| (t.as_str(), k.as_bytes(), pv.as_deref(), v.as_bytes()))
+            .collect::<Vec<_>>();
+        let results = ctx
+            .db()
+            .batch_compare_and_swap(&ops)
+            .unwrap()
+            .into_iter()
+            .map(|(v, swapped)| (v.map(|v| blob_to_string(v.as_ref())), swapped))
+            .collect();
+        Json(results)
+    }
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use itertools::Itertools;
 use musdk::*;
 
@@ -18,11 +20,70 @@ mod hello_wasm {
         data
     }
 
+    #[mu_function]
+    fn allocate<'a>(_ctx: &'a MuContext, megabytes: String) -> String {
+        let size = megabytes.parse::<usize>().unwrap() * 1_000_000;
+        let buffer = vec![1u8; size];
+        format!("Allocated {} bytes", buffer.len())
+    }
+
+    #[mu_function]
+    fn identity<'a>(ctx: &'a MuContext) -> String {
+        format!(
+            "{}/{}",
+            ctx.stack_id().unwrap(),
+            ctx.function_name().unwrap()
+        )
+    }
+
     #[mu_function]
     fn failing<'a>(_ctx: &'a MuContext) {
         panic!("Let me get out of here!");
     }
 
+    #[mu_function]
+    fn checked_hello<'a>(_ctx: &'a MuContext, name: &'a str) -> Result<String, Status> {
+        if name.is_empty() {
+            return Err(Status::BadRequest);
+        }
+        Ok(format!("Hello {}, welcome to MuRuntime", name))
+    }
+
+    #[mu_function]
+    fn stream_response<'a>(ctx: &'a mut MuContext) -> Result<()> {
+        let mut stream = ctx.response_stream();
+        stream.start(
+            Response::builder()
+                .content_type("application/octet-stream".into())
+                .no_body(),
+        )?;
+
+        // 10MB in 64KB chunks, each filled with its own index
+        let mut chunk = vec![0u8; 64 * 1024];
+        for i in 0..160 {
+            chunk.fill(i as u8);
+            stream.write(&chunk)?;
+        }
+
+        Ok(())
+    }
+
+    #[mu_function]
+    fn session<'a>(_ctx: &'a MuContext, cookies: Cookies<'a>) -> Response<'static> {
+        let previous = cookies.get("session").copied().unwrap_or("none");
+
+        Response::builder()
+            .set_cookie(
+                &SetCookie::new("session", "new-session")
+                    .path("/")
+                    .max_age(Duration::from_secs(3600))
+                    .http_only()
+                    .same_site(SameSite::Strict),
+            )
+            .set_cookie(&SetCookie::new("visited", "yes"))
+            .body_from_string(previous.to_string())
+    }
+
     #[mu_function]
     fn path_params<'a>(_ctx: &'a MuContext, req: &'a Request<'a>) -> String {
         req.path_params
@@ -33,6 +94,15 @@ mod hello_wasm {
             .unwrap_or("".into())
     }
 
+    #[mu_function]
+    fn report_metrics<'a>(ctx: &'a mut MuContext) {
+        ctx.report_metric("premium_operation", 1).unwrap();
+        ctx.report_metric("premium_operation", 2).unwrap();
+        for i in 0..20 {
+            ctx.report_metric(format!("metric_{i}"), 1).unwrap();
+        }
+    }
+
     #[mu_function]
     fn long_running<'a>(ctx: &'a mut MuContext) -> String {
         for i in 0..1_000_000_000u64 {
@@ -43,4 +113,37 @@ mod hello_wasm {
         }
         "Hey!".into()
     }
+
+    #[mu_function]
+    fn slow<'a>(_ctx: &'a MuContext, millis: &'a str) -> String {
+        std::thread::sleep(Duration::from_millis(millis.parse().unwrap()));
+        "Done".into()
+    }
+
+    #[mu_function]
+    fn busy_loop<'a>(_ctx: &'a MuContext) {
+        let mut i = 0u64;
+        while std::hint::black_box(true) {
+            i = std::hint::black_box(i.wrapping_add(1));
+        }
+    }
+
+    #[mu_function]
+    fn read_file<'a>(_ctx: &'a MuContext, path: &'a str) -> Result<String, Status> {
+        std::fs::read_to_string(path).map_err(|_| Status::NotFound)
+    }
+
+    #[mu_function]
+    fn read_env<'a>(_ctx: &'a MuContext, name: &'a str) -> Result<String, Status> {
+        std::env::var(name).map_err(|_| Status::NotFound)
+    }
+
+    #[mu_function]
+    fn conditional_put<'a>(ctx: &'a mut MuContext, etag: &'a str) -> String {
+        match ctx.storage().put_if_match("files", "key", etag, b"updated") {
+            Ok(()) => "written".to_string(),
+            Err(musdk::Error::StorageETagMismatch) => "mismatch".to_string(),
+            Err(e) => panic!("{e}"),
+        }
+    }
 }
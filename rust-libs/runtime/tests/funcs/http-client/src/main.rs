@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use musdk::*;
 
 #[mu_functions]
@@ -23,4 +25,20 @@ mod http_client {
 
         b"Failed to sent http request".to_vec()
     }
+
+    #[mu_function]
+    fn download_with_timeout<'a>(ctx: &'a mut MuContext, url: String) -> String {
+        let result = ctx
+            .http_client()
+            .get(url)
+            .timeout(Duration::from_millis(200))
+            .send();
+
+        match result {
+            Ok(Err(HttpError::Timeout(_))) => "timed out".to_string(),
+            Ok(Err(http_error)) => format!("http error: {http_error:?}"),
+            Ok(Ok(response)) => format!("got a response: {:?}", response.status),
+            Err(client_error) => format!("client error: {client_error:?}"),
+        }
+    }
 }
@@ -7,6 +7,14 @@ pub struct Form {
     pub password: String,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Paging {
+    pub page: u32,
+    pub size: Option<u32>,
+    #[serde(default)]
+    pub tag: Vec<String>,
+}
+
 #[derive(Serialize)]
 pub struct Response {
     pub token: String,
@@ -26,8 +34,35 @@ mod hello_wasm {
         })
     }
 
+    #[mu_function]
+    fn msgpack_body<'a>(_ctx: &'a MuContext, request: MsgPack<Form>) -> MsgPack<Response> {
+        let request = request.into_inner();
+        MsgPack(Response {
+            token: format!("token_for_{}_{}", request.username, request.password),
+            ttl: 14011018,
+        })
+    }
+
     #[mu_function]
     fn string_body<'a>(_ctx: &'a MuContext, request: String) -> String {
         format!("Hello {request}, got your message")
     }
+
+    #[mu_function]
+    fn query_params<'a>(_ctx: &'a MuContext, query: Query<Paging>) -> Json<Paging> {
+        Json(query.into_inner())
+    }
+
+    #[mu_function]
+    fn multipart_body<'a>(_ctx: &'a MuContext, request: Multipart<'a>) -> Json<Vec<String>> {
+        let names = request
+            .parts()
+            .iter()
+            .map(|p| match p.filename {
+                Some(filename) => format!("{}:{filename}", p.name),
+                None => p.name.to_string(),
+            })
+            .collect();
+        Json(names)
+    }
 }
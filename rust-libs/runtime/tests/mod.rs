@@ -1,4 +1,4 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, io::Write};
 
 use futures::FutureExt;
 use itertools::Itertools;
@@ -8,7 +8,8 @@ use test_context::test_context;
 
 use mu_db::DeleteTable;
 use mu_runtime::*;
-use musdk_common::{Header, Status};
+use mu_stack::{AssemblyRuntime, BinaryCompression, FunctionID, StackID};
+use musdk_common::{Header, Response, Status};
 
 use crate::utils::*;
 
@@ -16,6 +17,13 @@ mod utils;
 
 type RuntimeWithoutDB = fixture::RuntimeFixtureWithoutDB<NormalConfig>;
 type RuntimeWithDB = fixture::RuntimeFixture<NormalConfig>;
+type RuntimeWithShortLivedInstances = fixture::RuntimeFixtureWithoutDB<ShortLivedInstancesConfig>;
+type RuntimeWithShortExecutionTime = fixture::RuntimeFixtureWithoutDB<ShortExecutionTimeConfig>;
+type RuntimeWithWarmInstances = fixture::RuntimeFixtureWithoutDB<WarmInstancesConfig>;
+type RuntimeWithIdleWarmInstances = fixture::RuntimeFixtureWithoutDB<IdleWarmInstancesConfig>;
+type RuntimeWithLimitedConcurrency = fixture::RuntimeFixtureWithoutDB<LimitedConcurrencyConfig>;
+type RuntimeWithShortShutdownTimeout = fixture::RuntimeFixtureWithoutDB<ShortShutdownTimeoutConfig>;
+type RuntimeWithExposedHost = fixture::RuntimeFixtureWithoutDB<ExposedHostConfig>;
 
 #[test_context(RuntimeWithoutDB)]
 #[tokio::test]
@@ -46,6 +54,250 @@ async fn test_simple_func(fixture: &mut RuntimeWithoutDB) {
     );
 }
 
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn function_logs_are_reported(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["say_hello"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(
+        Some(Cow::Borrowed(b"Chappy")),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await
+        .unwrap();
+
+    // Notifications are collected by another task
+    let log = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Some(log) = fixture.logs.lock().await.first() {
+                return log.clone();
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the function's log should be reported");
+
+    assert_eq!(projects[0].id, log.function_id);
+    assert_eq!(log::Level::Debug, log.level);
+    assert_eq!("say_hello_started!", log.body);
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn updated_functions_run_their_new_code(fixture: &mut RuntimeWithoutDB) {
+    let old = create_project("hello-wasm", &["say_hello"], &None);
+    let new = create_project("multi-body", &["string_body"], &None);
+
+    let projects = [old, new];
+    let mut definitions = read_wasm_functions(&projects).await.unwrap();
+    let old_definition = definitions.remove(&projects[0].id).unwrap();
+    let new_source = definitions.remove(&projects[1].id).unwrap().source;
+
+    let make_hello_request = || {
+        make_request(
+            Some(Cow::Borrowed(b"Chappy")),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+        )
+    };
+
+    fixture
+        .runtime
+        .add_functions(vec![old_definition.clone()])
+        .await
+        .unwrap();
+
+    let resp = fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), make_hello_request())
+        .await
+        .unwrap();
+    assert_eq!(b"Hello Chappy, welcome to MuRuntime", resp.body.as_ref());
+
+    // Same assembly, new binary
+    let new_definition = AssemblyDefinition::try_new(
+        old_definition.id.clone(),
+        new_source,
+        AssemblyRuntime::Wasi1_0,
+        [],
+        [],
+        old_definition.memory_limit,
+    )
+    .unwrap();
+    fixture
+        .runtime
+        .add_functions(vec![new_definition])
+        .await
+        .unwrap();
+
+    let function_id = FunctionID {
+        assembly_id: old_definition.id,
+        function_name: "string_body".into(),
+    };
+    let resp = fixture
+        .runtime
+        .invoke_function(function_id, make_hello_request())
+        .await
+        .unwrap();
+    assert_eq!(b"Hello Chappy, got your message", resp.body.as_ref());
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn compressed_binaries_behave_like_plain_ones(fixture: &mut RuntimeWithoutDB) {
+    let projects = vec![create_project("hello-wasm", &["say_hello"], &None)];
+    let plain = read_wasm_functions(&projects)
+        .await
+        .unwrap()
+        .remove(&projects[0].id)
+        .unwrap();
+
+    let gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&plain.source).unwrap();
+        encoder.finish().unwrap()
+    };
+    let zstd = zstd::encode_all(plain.source.as_ref(), 0).unwrap();
+
+    let definitions = [
+        (plain.source.clone(), BinaryCompression::None),
+        (gzip.into(), BinaryCompression::Gzip),
+        (zstd.into(), BinaryCompression::Zstd),
+    ]
+    .map(|(source, compression)| {
+        AssemblyDefinition::try_new(
+            plain.id.clone(),
+            source,
+            AssemblyRuntime::Wasi1_0,
+            [],
+            [],
+            plain.memory_limit,
+        )
+        .unwrap()
+        .with_compression(compression)
+    });
+
+    for definition in definitions {
+        let compression = definition.compression;
+        fixture
+            .runtime
+            .add_functions(vec![definition])
+            .await
+            .unwrap();
+
+        let request = make_request(
+            Some(Cow::Borrowed(b"Chappy")),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+        );
+        let resp = fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            b"Hello Chappy, welcome to MuRuntime",
+            resp.body.as_ref(),
+            "{compression:?}"
+        );
+    }
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn oversized_decompressed_binaries_are_rejected(fixture: &mut RuntimeWithoutDB) {
+    let projects = vec![create_project("hello-wasm", &["say_hello"], &None)];
+    let plain = read_wasm_functions(&projects)
+        .await
+        .unwrap()
+        .remove(&projects[0].id)
+        .unwrap();
+
+    // Compresses to next to nothing, but expands past the 256MiB limit
+    let bomb = {
+        let mut encoder = zstd::Encoder::new(vec![], 0).unwrap();
+        let zeros = vec![0u8; 1024 * 1024];
+        for _ in 0..257 {
+            encoder.write_all(&zeros).unwrap();
+        }
+        encoder.finish().unwrap()
+    };
+
+    let definition = AssemblyDefinition::try_new(
+        plain.id.clone(),
+        bomb.into(),
+        AssemblyRuntime::Wasi1_0,
+        [],
+        [],
+        plain.memory_limit,
+    )
+    .unwrap()
+    .with_compression(BinaryCompression::Zstd);
+    fixture
+        .runtime
+        .add_functions(vec![definition])
+        .await
+        .unwrap();
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let result = fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::FunctionLoadingError(
+            FunctionLoadingError::DecompressAssembly(id, _)
+        )) if id == projects[0].id
+    ));
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn duplicate_functions_in_one_batch_are_rejected(fixture: &mut RuntimeWithoutDB) {
+    let projects = vec![create_project("hello-wasm", &["say_hello"], &None)];
+    let definition = read_wasm_functions(&projects)
+        .await
+        .unwrap()
+        .remove(&projects[0].id)
+        .unwrap();
+
+    let result = fixture
+        .runtime
+        .add_functions(vec![definition.clone(), definition])
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::FunctionLoadingError(
+            FunctionLoadingError::DuplicateFunctionName(id)
+        )) if id == projects[0].id
+    ));
+
+    assert!(fixture
+        .runtime
+        .get_function_names(projects[0].id.stack_id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
 #[test_context(RuntimeWithoutDB)]
 #[tokio::test]
 async fn can_run_multiple_instance_of_the_same_function(fixture: &mut RuntimeWithoutDB) {
@@ -266,6 +518,7 @@ async fn function_usage_is_reported_correctly_1(fixture: &mut RuntimeWithoutDB)
         db_strong_writes,
         function_instructions,
         memory_megabytes,
+        custom_metrics,
     } = usages.get(function_id.stack_id()).unwrap();
 
     assert_eq!(*db_weak_writes, 0);
@@ -273,7 +526,38 @@ async fn function_usage_is_reported_correctly_1(fixture: &mut RuntimeWithoutDB)
     assert_eq!(*db_strong_writes, 0);
     assert_eq!(*db_strong_reads, 0);
     assert!(*function_instructions > 0);
-    assert_eq!(*memory_megabytes, 100);
+    // The function's peak memory, well below its 100MB limit
+    assert!((1..=10).contains(memory_megabytes));
+    assert!(custom_metrics.is_empty());
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn custom_metrics_are_reported_in_usage(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["report_metrics"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let function_id = projects[0].function_id(0).unwrap();
+
+    fixture
+        .runtime
+        .invoke_function(function_id.clone(), request)
+        .await
+        .unwrap();
+
+    let usages = fixture.usages.lock().await;
+    let custom_metrics = &usages.get(function_id.stack_id()).unwrap().custom_metrics;
+
+    // The function reports more distinct names than allowed, the rest are dropped
+    assert_eq!(custom_metrics.len(), 16);
+    assert_eq!(custom_metrics.get("premium_operation"), Some(&3));
+    assert_eq!(custom_metrics.get("metric_14"), Some(&1));
+    assert_eq!(custom_metrics.get("metric_15"), None);
 }
 
 //#[tokio::test]
@@ -343,12 +627,150 @@ async fn failing_function_should_not_hang(fixture: &mut RuntimeWithoutDB) {
         .invoke_function(projects[0].function_id(0).unwrap(), request)
         .await;
 
-    match result.err().unwrap() {
-        Error::FunctionDidntTerminateCleanly => (),
+    let error = result.err().unwrap();
+    assert_eq!(
+        Some(FunctionFailureKind::Failed),
+        error.function_failure().map(|(kind, _)| kind)
+    );
+    match error {
+        Error::FunctionRuntimeError(FunctionRuntimeError::Panicked(message))
+            if message.contains("Let me get out of here!") => {}
         _ => panic!("function should have been failed!"),
     }
 }
 
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn running_out_of_memory_is_reported(fixture: &mut RuntimeWithoutDB) {
+    use mu_runtime::error::*;
+
+    let projects = create_and_add_projects(
+        vec![(
+            "hello-wasm",
+            &["allocate"],
+            Some(byte_unit::Byte::from_unit(20.0, byte_unit::ByteUnit::MB).unwrap()),
+        )],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(
+        Some(Cow::Borrowed(b"100")),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    let error = fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await
+        .err()
+        .unwrap();
+
+    assert_eq!(
+        Some(FunctionFailureKind::MemoryExceeded),
+        error.function_failure().map(|(kind, _)| kind)
+    );
+    assert!(matches!(
+        error,
+        Error::FunctionRuntimeError(FunctionRuntimeError::MaximumMemoryExceeded)
+    ));
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn functions_can_fail_by_returning_err(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["checked_hello"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(
+        Some(Cow::Borrowed(b"")),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::BadRequest, r.status);
+            assert!(r.body.is_empty());
+        })
+        .await;
+
+    let request = make_request(
+        Some(Cow::Borrowed(b"Chappy")),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::Ok, r.status);
+            assert_eq!(b"Hello Chappy, welcome to MuRuntime", r.body.as_ref());
+        })
+        .await;
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn responses_can_be_streamed(fixture: &mut RuntimeWithoutDB) {
+    const CHUNK_SIZE: usize = 64 * 1024;
+    const CHUNK_COUNT: usize = 160;
+
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["stream_response"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let response = fixture
+        .runtime
+        .invoke_function_streaming(projects[0].function_id(0).unwrap(), request)
+        .await
+        .unwrap();
+
+    assert_eq!(Status::Ok, response.response.status);
+    assert!(response.response.body.is_empty());
+
+    let mut body_stream = response.body_stream.expect("response should be streamed");
+    let mut chunk_count = 0;
+    while let Some(chunk) = body_stream.recv().await {
+        let chunk = chunk.unwrap();
+        assert_eq!(CHUNK_SIZE, chunk.len());
+        assert!(chunk.iter().all(|b| *b == chunk_count as u8));
+        chunk_count += 1;
+    }
+    assert_eq!(CHUNK_COUNT, chunk_count);
+
+    // Streamed bodies are collected when not invoking for a stream
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let response = fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await
+        .unwrap();
+
+    assert_eq!(CHUNK_SIZE * CHUNK_COUNT, response.body.len());
+    for (i, chunk) in response.body.chunks(CHUNK_SIZE).enumerate() {
+        assert!(chunk.iter().all(|b| *b == i as u8));
+    }
+}
+
 #[test_context(RuntimeWithoutDB)]
 #[tokio::test]
 async fn json_body_request_and_response(fixture: &mut RuntimeWithoutDB) {
@@ -410,22 +832,92 @@ async fn json_body_request_and_response(fixture: &mut RuntimeWithoutDB) {
 
 #[test_context(RuntimeWithoutDB)]
 #[tokio::test]
-async fn string_body_request_and_response(fixture: &mut RuntimeWithoutDB) {
+async fn msgpack_body_request_and_response(fixture: &mut RuntimeWithoutDB) {
+    use serde::{Deserialize, Serialize};
+
     let projects = create_and_add_projects(
-        vec![("multi-body", &["string_body"], None)],
+        vec![("multi-body", &["msgpack_body"], None)],
         &*fixture.runtime,
     )
     .await
     .unwrap();
 
-    let request = make_request(
-        Some(Cow::Borrowed(b"Due")),
-        vec![],
-        HashMap::new(),
-        HashMap::new(),
-    );
+    #[derive(Serialize)]
+    pub struct Form {
+        pub username: String,
+        pub password: String,
+    }
 
-    fixture
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    pub struct Response {
+        pub token: String,
+        pub ttl: u64,
+    }
+
+    let form = rmp_serde::to_vec_named(&Form {
+        username: "John".into(),
+        password: "12345".into(),
+    })
+    .unwrap();
+
+    let expected_response = Response {
+        token: "token_for_John_12345".into(),
+        ttl: 14011018,
+    };
+
+    let request = make_request(
+        Some(Cow::Borrowed(&form)),
+        vec![
+            Header {
+                name: Cow::Borrowed("content-type"),
+                value: Cow::Borrowed("application/msgpack"),
+            },
+            Header {
+                name: Cow::Borrowed("accept"),
+                value: Cow::Borrowed("application/msgpack"),
+            },
+        ],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::Ok, r.status);
+            assert!(r
+                .headers
+                .iter()
+                .any(|h| h.name.eq_ignore_ascii_case("content-type")
+                    && h.value == "application/msgpack"));
+            assert_eq!(
+                expected_response,
+                rmp_serde::from_slice(r.body.as_ref()).unwrap()
+            )
+        })
+        .await;
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn string_body_request_and_response(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("multi-body", &["string_body"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(
+        Some(Cow::Borrowed(b"Due")),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
         .runtime
         .invoke_function(projects[0].function_id(0).unwrap(), request)
         .then(|r| async move {
@@ -502,6 +994,130 @@ async fn string_body_request_and_response_do_not_care_for_content_type(
         .await;
 }
 
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn typed_query_params(fixture: &mut RuntimeWithoutDB) {
+    use serde::Deserialize;
+
+    let projects = create_and_add_projects(
+        vec![("multi-body", &["query_params"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    #[derive(Deserialize, PartialEq, Eq, Debug)]
+    pub struct Paging {
+        pub page: u32,
+        pub size: Option<u32>,
+        pub tag: Vec<String>,
+    }
+
+    let request = |query: &[(&'static str, &'static str)]| {
+        let mut request = make_request(None, vec![], HashMap::new(), HashMap::new());
+        request.query_params = query
+            .iter()
+            .map(|(k, v)| (Cow::Borrowed(*k), Cow::Borrowed(*v)))
+            .collect();
+        request
+    };
+
+    fixture
+        .runtime
+        .invoke_function(
+            projects[0].function_id(0).unwrap(),
+            request(&[("tag", "a"), ("page", "2"), ("tag", "b")]),
+        )
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::Ok, r.status);
+            assert_eq!(
+                Paging {
+                    page: 2,
+                    size: None,
+                    tag: vec!["a".into(), "b".into()],
+                },
+                serde_json::from_slice::<Paging>(r.body.as_ref()).unwrap()
+            );
+        })
+        .await;
+
+    fixture
+        .runtime
+        .invoke_function(
+            projects[0].function_id(0).unwrap(),
+            request(&[("page", "two")]),
+        )
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::BadRequest, r.status);
+        })
+        .await;
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn multipart_body_request(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("multi-body", &["multipart_body"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let body = b"--boundary\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Buy milk\r\n\
+        --boundary\r\n\
+        Content-Disposition: form-data; name=\"attachment\"; filename=\"list.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        milk\r\n\
+        --boundary--\r\n";
+
+    let request = |content_type| {
+        make_request(
+            Some(Cow::Borrowed(body)),
+            vec![Header {
+                name: Cow::Borrowed("content-type"),
+                value: Cow::Borrowed(content_type),
+            }],
+            HashMap::new(),
+            HashMap::new(),
+        )
+    };
+
+    fixture
+        .runtime
+        .invoke_function(
+            projects[0].function_id(0).unwrap(),
+            request("multipart/form-data; boundary=boundary"),
+        )
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::Ok, r.status);
+            assert_eq!(
+                vec!["title".to_string(), "attachment:list.txt".to_string()],
+                serde_json::from_slice::<Vec<String>>(r.body.as_ref()).unwrap()
+            );
+        })
+        .await;
+
+    fixture
+        .runtime
+        .invoke_function(
+            projects[0].function_id(0).unwrap(),
+            request("multipart/form-data"),
+        )
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::BadRequest, r.status);
+            assert_eq!(b"multipart boundary is missing", r.body.as_ref());
+        })
+        .await;
+}
+
 #[test_context(RuntimeWithoutDB)]
 #[tokio::test]
 async fn can_access_path_params(fixture: &mut RuntimeWithoutDB) {
@@ -538,6 +1154,79 @@ async fn can_access_path_params(fixture: &mut RuntimeWithoutDB) {
         .await;
 }
 
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn functions_can_read_their_own_identity(fixture: &mut RuntimeWithoutDB) {
+    let projects =
+        create_and_add_projects(vec![("hello-wasm", &["identity"], None)], &*fixture.runtime)
+            .await
+            .unwrap();
+
+    let function_id = projects[0].function_id(0).unwrap();
+    let expected_response = format!(
+        "{}/{}",
+        function_id.assembly_id.stack_id, function_id.function_name
+    );
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let r = fixture
+        .runtime
+        .invoke_function(function_id, request)
+        .await
+        .unwrap();
+    assert_eq!(Status::Ok, r.status);
+    assert_eq!(expected_response.as_bytes(), r.body.as_ref());
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn cookies_are_read_and_set(fixture: &mut RuntimeWithoutDB) {
+    let projects =
+        create_and_add_projects(vec![("hello-wasm", &["session"], None)], &*fixture.runtime)
+            .await
+            .unwrap();
+
+    let request = make_request(
+        None,
+        vec![
+            Header {
+                name: Cow::Borrowed("Cookie"),
+                value: Cow::Borrowed("theme=dark; session=abc123"),
+            },
+            Header {
+                name: Cow::Borrowed("cookie"),
+                value: Cow::Borrowed("lang=en"),
+            },
+        ],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::Ok, r.status);
+            assert_eq!(b"abc123", r.body.as_ref());
+
+            let set_cookies = r
+                .headers
+                .iter()
+                .filter(|h| h.name.eq_ignore_ascii_case("set-cookie"))
+                .map(|h| h.value.as_ref())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                vec![
+                    "session=new-session; Path=/; Max-Age=3600; HttpOnly; SameSite=Strict",
+                    "visited=yes",
+                ],
+                set_cookies
+            );
+        })
+        .await;
+}
+
 #[test_context(RuntimeWithDB)]
 #[tokio::test]
 #[serial]
@@ -1016,35 +1705,267 @@ async fn db_batch_crud(fixture: &mut RuntimeWithDB) {
         .await;
 }
 
-#[test_context(RuntimeWithoutDB)]
+#[test_context(RuntimeWithDB)]
 #[tokio::test]
-async fn instant_exit_is_handled(fixture: &mut RuntimeWithoutDB) {
-    use mu_runtime::error::*;
+#[serial]
+async fn db_count_goes_through_large_tables(fixture: &mut RuntimeWithDB) {
+    const TABLE_NAME: &str = "table_1";
+    const KEY_COUNT: usize = 10_000;
+
+    let projects = create_and_add_projects(vec![("hello-db", &["count"], None)], &*fixture.runtime)
+        .await
+        .unwrap();
+
+    let stack_id = projects[0].id.stack_id;
+    let db_client = fixture
+        .db_manager_fixture
+        .db_manager
+        .make_client()
+        .await
+        .unwrap();
+    db_client
+        .update_stack_tables(
+            stack_id,
+            vec![(TABLE_NAME.try_into().unwrap(), DeleteTable(false))],
+        )
+        .await
+        .unwrap();
+
+    // Seed well past a single page of keys, one in ten under the "a::" prefix
+    for chunk in &(0..KEY_COUNT).chunks(1000) {
+        let pairs = chunk
+            .map(|i| {
+                let prefix = if i % 10 == 0 { "a" } else { "b" };
+                let key = mu_db::Key {
+                    stack_id,
+                    table_name: TABLE_NAME.try_into().unwrap(),
+                    inner_key: format!("{prefix}::{i:05}").into_bytes(),
+                };
+                (key, b"value".to_vec())
+            })
+            .collect();
+        db_client.batch_put(pairs, false).await.unwrap();
+    }
+
+    let count = |prefix: &str, limit: u64| {
+        let body = serde_json::to_vec(&(TABLE_NAME, prefix, limit)).unwrap();
+        let request = make_request(
+            Some(Cow::Owned(body)),
+            vec![Header {
+                name: Cow::Borrowed("content-type"),
+                value: Cow::Borrowed("application/json; charset=utf-8"),
+            }],
+            HashMap::new(),
+            HashMap::new(),
+        );
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+            .map(|r| {
+                let r = r.unwrap();
+                assert_eq!(Status::Ok, r.status);
+                String::from_utf8(r.body.into_owned()).unwrap()
+            })
+    };
+
+    assert_eq!(KEY_COUNT.to_string(), count("", u64::MAX).await);
+    assert_eq!((KEY_COUNT / 10).to_string(), count("a::", u64::MAX).await);
+    assert_eq!("0", count("c::", u64::MAX).await);
+    assert_eq!("100", count("", 100).await);
+}
+
+#[test_context(RuntimeWithDB)]
+#[tokio::test]
+#[serial]
+async fn db_json_values_round_trip(fixture: &mut RuntimeWithDB) {
+    const TABLE_NAME: &str = "table_1";
+    const PUT_TODO: usize = 0;
+    const GET_TODO: usize = 1;
+    const READ: usize = 2;
 
     let projects = create_and_add_projects(
-        vec![("instant-exit", &["say_hello"], None)],
+        vec![("hello-db", &["put_todo", "get_todo", "read"], None)],
         &*fixture.runtime,
     )
     .await
     .unwrap();
 
-    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
-
-    match fixture
-        .runtime
-        .invoke_function(projects[0].function_id(0).unwrap(), request)
+    let stack_id = projects[0].id.stack_id;
+    fixture
+        .db_manager_fixture
+        .db_manager
+        .make_client()
         .await
-    {
-        Err(Error::FunctionDidntTerminateCleanly) => (),
-        _ => panic!("Instant exit function should fail to run"),
-    }
+        .unwrap()
+        .update_stack_tables(
+            stack_id,
+            vec![(TABLE_NAME.try_into().unwrap(), DeleteTable(false))],
+        )
+        .await
+        .unwrap();
+
+    let invoke = |function: usize, body: serde_json::Value| {
+        let request = make_request(
+            Some(Cow::Owned(serde_json::to_vec(&body).unwrap())),
+            vec![Header {
+                name: Cow::Borrowed("content-type"),
+                value: Cow::Borrowed("application/json; charset=utf-8"),
+            }],
+            HashMap::new(),
+            HashMap::new(),
+        );
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(function).unwrap(), request)
+            .map(|r| {
+                let r = r.unwrap();
+                assert_eq!(Status::Ok, r.status);
+                String::from_utf8(r.body.into_owned()).unwrap()
+            })
+    };
+
+    let todo = serde_json::json!({ "title": "write tests", "done": false });
+    invoke(
+        PUT_TODO,
+        serde_json::json!({ "table_name": TABLE_NAME, "key": "todo::1", "todo": todo }),
+    )
+    .await;
+
+    let read = |key: &str| serde_json::json!({ "table_name": TABLE_NAME, "key": key });
+
+    let fetched: serde_json::Value =
+        serde_json::from_str(&invoke(GET_TODO, read("todo::1")).await).unwrap();
+    assert_eq!(todo, fetched);
+
+    // Values are stored as plain JSON, so the byte API sees the same thing
+    let raw: serde_json::Value =
+        serde_json::from_str(&invoke(READ, read("todo::1")).await).unwrap();
+    assert_eq!(todo, raw);
+
+    assert_eq!("null", invoke(GET_TODO, read("todo::2")).await);
 }
 
-#[test_context(RuntimeWithoutDB)]
+#[test_context(RuntimeWithDB)]
 #[tokio::test]
-async fn can_send_http_requests_with_http_client(fixture: &mut RuntimeWithoutDB) {
+#[serial]
+async fn db_batch_compare_and_swap_checks_every_key_before_swapping(fixture: &mut RuntimeWithDB) {
+    const TABLE_NAME: &str = "table_1";
+
     let projects = create_and_add_projects(
-        vec![("http-client", &["test_download"], None)],
+        vec![("hello-db", &["batch_compare_and_swap", "read"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let stack_id = projects[0].id.stack_id;
+    let db_client = fixture
+        .db_manager_fixture
+        .db_manager
+        .make_client()
+        .await
+        .unwrap();
+    db_client
+        .update_stack_tables(
+            stack_id,
+            vec![(TABLE_NAME.try_into().unwrap(), DeleteTable(false))],
+        )
+        .await
+        .unwrap();
+    db_client
+        .put(
+            mu_db::Key {
+                stack_id,
+                table_name: TABLE_NAME.try_into().unwrap(),
+                inner_key: b"a".to_vec(),
+            },
+            b"1".to_vec(),
+            true,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let json_request = |body: Vec<u8>| {
+        make_request(
+            Some(Cow::Owned(body)),
+            vec![Header {
+                name: Cow::Borrowed("content-type"),
+                value: Cow::Borrowed("application/json; charset=utf-8"),
+            }],
+            HashMap::new(),
+            HashMap::new(),
+        )
+    };
+    let swap = |ops: Vec<(&str, &str, Option<&str>, &str)>| {
+        let request = json_request(serde_json::to_vec(&ops).unwrap());
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+            .map(|r| {
+                let r = r.unwrap();
+                assert_eq!(Status::Ok, r.status);
+                serde_json::from_slice::<Vec<(Option<String>, bool)>>(&r.body).unwrap()
+            })
+    };
+    let read = |key: &str| {
+        let body = serde_json::json!({ "table_name": TABLE_NAME, "key": key });
+        let request = json_request(serde_json::to_vec(&body).unwrap());
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(1).unwrap(), request)
+            .map(|r| String::from_utf8(r.unwrap().body.into_owned()).unwrap())
+    };
+
+    // "b" doesn't have the expected value, so "a" isn't swapped either
+    let results = swap(vec![
+        (TABLE_NAME, "a", Some("1"), "2"),
+        (TABLE_NAME, "b", Some("1"), "3"),
+    ])
+    .await;
+    assert_eq!(vec![(Some("1".into()), false), (None, false)], results);
+    assert_eq!("1", read("a").await);
+    assert_eq!("", read("b").await);
+
+    let results = swap(vec![
+        (TABLE_NAME, "a", Some("1"), "2"),
+        (TABLE_NAME, "b", None, "3"),
+    ])
+    .await;
+    assert_eq!(vec![(Some("1".into()), true), (None, true)], results);
+    assert_eq!("2", read("a").await);
+    assert_eq!("3", read("b").await);
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn instant_exit_is_handled(fixture: &mut RuntimeWithoutDB) {
+    use mu_runtime::error::*;
+
+    let projects = create_and_add_projects(
+        vec![("instant-exit", &["say_hello"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+
+    match fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await
+    {
+        Err(Error::FunctionDidntTerminateCleanly) => (),
+        _ => panic!("Instant exit function should fail to run"),
+    }
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn can_send_http_requests_with_http_client(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("http-client", &["test_download"], None)],
         &*fixture.runtime,
     )
     .await
@@ -1111,6 +2032,41 @@ async fn can_send_http_requests_with_http_client(fixture: &mut RuntimeWithoutDB)
         .await;
 }
 
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn http_requests_time_out(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("http-client", &["download_with_timeout"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    // Accepts connections, but never responds
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/slow", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let _connections = listener.incoming().collect::<Vec<_>>();
+    });
+
+    let request = make_request(
+        Some(Cow::Owned(url.into_bytes())),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .then(|r| async move {
+            let r = r.unwrap();
+            assert_eq!(Status::Ok, r.status);
+            assert_eq!(b"timed out", r.body.as_ref());
+        })
+        .await;
+}
+
 #[test_context(RuntimeWithoutDB)]
 #[tokio::test]
 async fn functions_will_be_terminated_when_there_is_timeout(fixture: &mut RuntimeWithoutDB) {
@@ -1130,10 +2086,609 @@ async fn functions_will_be_terminated_when_there_is_timeout(fixture: &mut Runtim
         .invoke_function(projects[0].function_id(0).unwrap(), request)
         .await
     {
-        Err(Error::Timeout) => (),
+        Err(e @ Error::Timeout) => assert_eq!(
+            Some(FunctionFailureKind::TimedOut),
+            e.function_failure().map(|(kind, _)| kind)
+        ),
         e => {
             trace!("{e:#?}");
             panic!("should fail to run");
         }
     }
 }
+
+#[test_context(RuntimeWithShortLivedInstances)]
+#[tokio::test]
+async fn instances_exceeding_max_lifetime_are_reaped(fixture: &mut RuntimeWithShortLivedInstances) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["long_running"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+
+    match fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await
+    {
+        Err(Error::InstanceReaped) => (),
+        e => {
+            trace!("{e:#?}");
+            panic!("should be reaped");
+        }
+    }
+
+    // The function only stops once it notices its pipes are closed, and
+    // still reports what it used until then
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while !fixture
+            .usages
+            .lock()
+            .await
+            .contains_key(&projects[0].id.stack_id)
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("usage of the reaped instance should be reported");
+}
+
+#[test_context(RuntimeWithShortExecutionTime)]
+#[tokio::test]
+async fn functions_exceeding_max_execution_time_are_stopped(
+    fixture: &mut RuntimeWithShortExecutionTime,
+) {
+    use mu_runtime::error::*;
+
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["busy_loop"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    // The first call also compiles the module, so only time the second one
+    let mut elapsed = Default::default();
+    for _ in 0..2 {
+        let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+
+        let started_at = std::time::Instant::now();
+        match fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+            .await
+        {
+            Err(Error::FunctionRuntimeError(FunctionRuntimeError::Timeout)) => (),
+            e => {
+                trace!("{e:#?}");
+                panic!("should time out");
+            }
+        }
+        elapsed = started_at.elapsed();
+    }
+
+    assert!(elapsed >= std::time::Duration::from_millis(200));
+    assert!(elapsed < std::time::Duration::from_secs(1));
+
+    // Busy loops never read from their pipes, so they're only stopped once
+    // they've executed the instructions allowed for the execution time
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while !fixture
+            .usages
+            .lock()
+            .await
+            .contains_key(&projects[0].id.stack_id)
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("timed out functions should stop and report their usage");
+}
+
+#[test_context(RuntimeWithShortExecutionTime)]
+#[tokio::test]
+async fn spinning_functions_are_stopped_by_the_execution_time(
+    fixture: &mut RuntimeWithShortExecutionTime,
+) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["busy_loop"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let started_at = std::time::Instant::now();
+    fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await
+        .unwrap_err();
+
+    let usage = tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        loop {
+            if let Some(usage) = fixture.usages.lock().await.get(&projects[0].id.stack_id) {
+                return usage.clone();
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("spinning functions should be stopped");
+    let elapsed = started_at.elapsed();
+
+    // The derived limit assumes a faster machine than any we run on, so the
+    // function gets stopped some time after its execution time, but well
+    // before it could run unbounded
+    assert!(elapsed >= std::time::Duration::from_millis(200));
+    assert!(elapsed < std::time::Duration::from_secs(15));
+
+    // No instruction limit is configured, so the function can only have been
+    // stopped by the one derived from its 200ms of execution time, which is
+    // 10 giga-instructions per second
+    assert!(usage.function_instructions > 0);
+    assert!(usage.function_instructions <= 2_000_000_000);
+}
+
+#[test_context(RuntimeWithWarmInstances)]
+#[tokio::test]
+async fn warm_instances_are_used_for_later_invocations(fixture: &mut RuntimeWithWarmInstances) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["say_hello"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    for name in ["first", "second", "third"] {
+        let request = make_request(
+            Some(Cow::Borrowed(name.as_bytes())),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+        );
+
+        let response = fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            format!("Hello {name}, welcome to MuRuntime").as_bytes(),
+            response.body.as_ref()
+        );
+
+        // Warm instances are started in the background
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+
+    // Only the first invocation had to start an instance on demand
+    assert_eq!(fixture.runtime.cold_start_count().await.unwrap(), 1);
+}
+
+#[test_context(RuntimeWithIdleWarmInstances)]
+#[tokio::test]
+async fn idle_warm_instances_are_stopped(fixture: &mut RuntimeWithIdleWarmInstances) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["say_hello"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let invoke = || {
+        let request = make_request(
+            Some(Cow::Borrowed(b"idle")),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+        );
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+    };
+
+    invoke().await.unwrap();
+
+    // Outlive the idle timeout, so the warm instance is gone by the next call
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    invoke().await.unwrap();
+
+    assert_eq!(fixture.runtime.cold_start_count().await.unwrap(), 2);
+}
+
+#[test_context(RuntimeWithLimitedConcurrency)]
+#[tokio::test]
+async fn invocations_over_the_stack_concurrency_limit_are_rejected(
+    fixture: &mut RuntimeWithLimitedConcurrency,
+) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["long_running"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    // The limit is 2, so one of these has to be turned away
+    let results = futures::future::join_all((0..3).map(|_| {
+        let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+    }))
+    .await;
+
+    let rejected = results
+        .iter()
+        .filter(|r| matches!(r, Err(Error::TooManyConcurrentInvocations)))
+        .count();
+    assert_eq!(rejected, 1);
+
+    // Slots are released once the invocations are done, which the runtime
+    // learns about shortly after replying
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+    let result = fixture
+        .runtime
+        .invoke_function(projects[0].function_id(0).unwrap(), request)
+        .await;
+    assert!(!matches!(result, Err(Error::TooManyConcurrentInvocations)));
+}
+
+#[test_context(RuntimeWithLimitedConcurrency)]
+#[tokio::test]
+async fn reconfigured_limits_apply_to_new_invocations(fixture: &mut RuntimeWithLimitedConcurrency) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["say_hello"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let invoke = || {
+        let request = make_request(None, vec![], HashMap::new(), HashMap::new());
+        fixture
+            .runtime
+            .invoke_function(projects[0].function_id(0).unwrap(), request)
+    };
+
+    let reconfigure = |max_concurrent_invocations_per_stack| {
+        fixture.runtime.reconfigure(ReloadableRuntimeConfig {
+            include_function_logs: false,
+            max_instance_lifetime: None,
+            max_execution_time: std::time::Duration::from_secs(60).into(),
+            max_concurrent_invocations_per_stack,
+        })
+    };
+
+    reconfigure(Some(0)).await.unwrap();
+    assert!(matches!(
+        invoke().await,
+        Err(Error::TooManyConcurrentInvocations)
+    ));
+
+    reconfigure(None).await.unwrap();
+    assert!(invoke().await.is_ok());
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn stopping_waits_for_running_invocations(fixture: &mut RuntimeWithoutDB) {
+    let projects =
+        create_and_add_projects(vec![("hello-wasm", &["slow"], None)], &*fixture.runtime)
+            .await
+            .unwrap();
+    let function_id = projects[0].function_id(0).unwrap();
+
+    let invocation = tokio::spawn({
+        let runtime = fixture.runtime.clone();
+        let function_id = function_id.clone();
+        async move {
+            let request = make_request(
+                Some(Cow::Borrowed(b"500")),
+                vec![],
+                HashMap::new(),
+                HashMap::new(),
+            );
+            runtime.invoke_function(function_id, request).await
+        }
+    });
+
+    // Let the invocation reach the runtime before it's stopped
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    fixture.runtime.stop().await.unwrap();
+
+    let response = invocation.await.unwrap().unwrap();
+    assert_eq!(b"Done", response.body.as_ref());
+
+    // Notifications are collected by another task
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while !fixture
+            .usages
+            .lock()
+            .await
+            .contains_key(function_id.stack_id())
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("usage of the invocation should be reported");
+}
+
+#[test_context(RuntimeWithShortShutdownTimeout)]
+#[tokio::test]
+async fn invocations_running_past_the_shutdown_timeout_are_stopped(
+    fixture: &mut RuntimeWithShortShutdownTimeout,
+) {
+    let projects =
+        create_and_add_projects(vec![("hello-wasm", &["slow"], None)], &*fixture.runtime)
+            .await
+            .unwrap();
+
+    let invocation = tokio::spawn({
+        let runtime = fixture.runtime.clone();
+        let function_id = projects[0].function_id(0).unwrap();
+        async move {
+            let request = make_request(
+                Some(Cow::Borrowed(b"3000")),
+                vec![],
+                HashMap::new(),
+                HashMap::new(),
+            );
+            runtime.invoke_function(function_id, request).await
+        }
+    });
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let started_at = std::time::Instant::now();
+    fixture.runtime.stop().await.unwrap();
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(5));
+
+    assert!(matches!(
+        invocation.await.unwrap(),
+        Err(Error::RuntimeIsShutDown)
+    ));
+}
+
+async fn invoke_with_body(
+    runtime: &dyn Runtime,
+    function_id: FunctionID,
+    body: &str,
+) -> Response<'static> {
+    let request = make_request(
+        Some(Cow::Owned(body.as_bytes().to_vec())),
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+    );
+    runtime.invoke_function(function_id, request).await.unwrap()
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn functions_cannot_see_host_files_or_env(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["read_file", "read_env"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let host_file = std::env::temp_dir().join("mu-runtime-test-host-file");
+    std::fs::write(&host_file, "secret").unwrap();
+    std::env::set_var("MU_RUNTIME_TEST_HOST_ENV", "secret");
+
+    let resp = invoke_with_body(
+        &*fixture.runtime,
+        projects[0].function_id(0).unwrap(),
+        host_file.to_str().unwrap(),
+    )
+    .await;
+    assert_eq!(Status::NotFound, resp.status);
+
+    let resp = invoke_with_body(
+        &*fixture.runtime,
+        projects[0].function_id(1).unwrap(),
+        "MU_RUNTIME_TEST_HOST_ENV",
+    )
+    .await;
+    assert_eq!(Status::NotFound, resp.status);
+
+    std::fs::remove_file(host_file).unwrap();
+}
+
+#[test_context(RuntimeWithDB)]
+#[tokio::test]
+#[serial]
+async fn secrets_are_injected_into_function_env(fixture: &mut RuntimeWithDB) {
+    let project = create_project("hello-wasm", &["read_file", "read_env"], &None);
+    let definition = read_wasm_functions(std::slice::from_ref(&project))
+        .await
+        .unwrap()
+        .remove(&project.id)
+        .unwrap();
+
+    let with_secrets = |secrets: &[(&str, &str)]| {
+        AssemblyDefinition::try_new(
+            definition.id.clone(),
+            definition.source.clone(),
+            AssemblyRuntime::Wasi1_0,
+            [],
+            secrets
+                .iter()
+                .map(|(env, secret)| (env.to_string(), secret.to_string())),
+            definition.memory_limit,
+        )
+        .unwrap()
+    };
+
+    let db_client = fixture
+        .db_manager_fixture
+        .db_manager
+        .make_client()
+        .await
+        .unwrap();
+    db_client
+        .put(
+            mu_db::Key::secret(project.id.stack_id, "api-token"),
+            b"s3cr3t".to_vec(),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+
+    fixture
+        .runtime
+        .add_functions(vec![with_secrets(&[("API_TOKEN", "api-token")])])
+        .await
+        .unwrap();
+
+    let resp = invoke_with_body(
+        &*fixture.runtime,
+        project.function_id(1).unwrap(),
+        "API_TOKEN",
+    )
+    .await;
+    assert_eq!(Status::Ok, resp.status);
+    assert_eq!(b"s3cr3t", resp.body.as_ref());
+
+    // Secrets of other stacks aren't visible
+    db_client
+        .put(
+            mu_db::Key::secret(StackID::SolanaPublicKey(rand::random()), "other"),
+            b"other".to_vec(),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+
+    fixture
+        .runtime
+        .add_functions(vec![with_secrets(&[("OTHER", "other")])])
+        .await
+        .unwrap();
+
+    let result = fixture
+        .runtime
+        .invoke_function(
+            project.function_id(1).unwrap(),
+            make_request(
+                Some(Cow::Borrowed(b"OTHER")),
+                vec![],
+                HashMap::new(),
+                HashMap::new(),
+            ),
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::FunctionLoadingError(FunctionLoadingError::MissingSecret(name))) if name == "other"
+    ));
+}
+
+#[test_context(RuntimeWithExposedHost)]
+#[tokio::test]
+async fn configured_host_dirs_and_env_are_exposed(fixture: &mut RuntimeWithExposedHost) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["read_file", "read_env"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let host_dir = preopened_host_dir();
+    std::fs::create_dir_all(&host_dir).unwrap();
+    std::fs::write(host_dir.join("shared.txt"), "shared").unwrap();
+    std::env::set_var(ALLOWED_ENV, "allowed");
+
+    let resp = invoke_with_body(
+        &*fixture.runtime,
+        projects[0].function_id(0).unwrap(),
+        &format!("{PREOPENED_GUEST_PATH}/shared.txt"),
+    )
+    .await;
+    assert_eq!(Status::Ok, resp.status);
+    assert_eq!(b"shared", resp.body.as_ref());
+
+    // Only the preopened directory is reachable
+    let resp = invoke_with_body(
+        &*fixture.runtime,
+        projects[0].function_id(0).unwrap(),
+        host_dir.join("shared.txt").to_str().unwrap(),
+    )
+    .await;
+    assert_eq!(Status::NotFound, resp.status);
+
+    let resp = invoke_with_body(
+        &*fixture.runtime,
+        projects[0].function_id(1).unwrap(),
+        ALLOWED_ENV,
+    )
+    .await;
+    assert_eq!(Status::Ok, resp.status);
+    assert_eq!(b"allowed", resp.body.as_ref());
+
+    std::fs::remove_dir_all(host_dir).unwrap();
+}
+
+#[test_context(RuntimeWithoutDB)]
+#[tokio::test]
+async fn conditional_puts_only_write_unmodified_objects(fixture: &mut RuntimeWithoutDB) {
+    let projects = create_and_add_projects(
+        vec![("hello-wasm", &["conditional_put"], None)],
+        &*fixture.runtime,
+    )
+    .await
+    .unwrap();
+
+    let owner = mu_storage::Owner::Stack(projects[0].id.stack_id);
+    let client = fixture.storage_manager.make_client().unwrap();
+    client
+        .update_stack_storages(owner, vec![("files", mu_storage::DeleteStorage(false))])
+        .await
+        .unwrap();
+    client
+        .put(owner, "files", "key", &mut &b"original"[..], None)
+        .await
+        .unwrap();
+    let etag = client
+        .get_etag(owner, "files", "key")
+        .await
+        .unwrap()
+        .unwrap();
+
+    let runtime = &*fixture.runtime;
+    let function_id = projects[0].function_id(0).unwrap();
+    let put_if_match = move |etag: String| {
+        let function_id = function_id.clone();
+        async move {
+            let request = make_request(
+                Some(Cow::Owned(etag.into_bytes())),
+                vec![],
+                HashMap::new(),
+                HashMap::new(),
+            );
+            let response = runtime.invoke_function(function_id, request).await.unwrap();
+            String::from_utf8(response.body.into_owned()).unwrap()
+        }
+    };
+
+    assert_eq!("mismatch", put_if_match("\"stale\"".to_string()).await);
+    let mut data = vec![];
+    client.get(owner, "files", "key", &mut data).await.unwrap();
+    assert_eq!(b"original", data.as_slice());
+
+    assert_eq!("written", put_if_match(etag).await);
+    let mut data = vec![];
+    client.get(owner, "files", "key", &mut data).await.unwrap();
+    assert_eq!(b"updated", data.as_slice());
+}
@@ -6,13 +6,17 @@ use std::{
     path::{Path, PathBuf},
     process::Command,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Result;
 
 use async_trait::async_trait;
 
-use mu_runtime::{start, AssemblyDefinition, Notification, Runtime, RuntimeConfig, Usage};
+use mu_runtime::{
+    start, AssemblyDefinition, FunctionLog, Notification, PreopenedDir, Runtime, RuntimeConfig,
+    Usage, WasiConfig,
+};
 use mu_stack::{AssemblyID, AssemblyRuntime, FunctionID, StackID};
 use musdk_common::http_client::*;
 
@@ -32,23 +36,85 @@ pub trait RuntimeTestConfig: Sync + Send {
     fn make() -> RuntimeConfig;
 }
 
+fn base_config() -> RuntimeConfig {
+    RuntimeConfig {
+        cache_path: PathBuf::from(""), // We will replace this in Fixture with actual temp dir.
+        cache_max_bytes: None,
+        include_function_logs: false,
+        max_giga_instructions_per_call: None,
+        compiler: Default::default(),
+        max_instance_lifetime: None,
+        max_execution_time: Duration::from_secs(60).into(),
+        warm_instances_per_function: 0,
+        warm_instance_idle_timeout: None,
+        max_concurrent_invocations_per_stack: None,
+        shutdown_timeout: None,
+        wasi: Default::default(),
+    }
+}
+
 macro_rules! create_config {
-    ($name: ident, $logs: expr, $limit: expr) => {
+    ($name: ident, { $($field: ident: $value: expr),* $(,)? }) => {
         pub struct $name;
 
         impl RuntimeTestConfig for $name {
             fn make() -> RuntimeConfig {
                 RuntimeConfig {
-                    cache_path: PathBuf::from(""), // We will replace this in Fixture with actual temp dir.
-                    include_function_logs: $logs,
-                    max_giga_instructions_per_call: $limit,
+                    $($field: $value,)*
+                    ..base_config()
                 }
             }
         }
     };
 }
 
-create_config!(NormalConfig, true, Some(1));
+create_config!(NormalConfig, {
+    include_function_logs: true,
+    max_giga_instructions_per_call: Some(1),
+});
+create_config!(ShortLivedInstancesConfig, {
+    max_instance_lifetime: Some(Duration::from_secs(1).into()),
+});
+// No instruction limit, so the execution time alone has to stop functions
+create_config!(ShortExecutionTimeConfig, {
+    max_execution_time: Duration::from_millis(200).into(),
+});
+create_config!(WarmInstancesConfig, {
+    max_giga_instructions_per_call: Some(1),
+    warm_instances_per_function: 1,
+});
+create_config!(IdleWarmInstancesConfig, {
+    max_giga_instructions_per_call: Some(1),
+    warm_instances_per_function: 1,
+    warm_instance_idle_timeout: Some(Duration::from_secs(1).into()),
+});
+create_config!(LimitedConcurrencyConfig, {
+    max_giga_instructions_per_call: Some(1),
+    max_concurrent_invocations_per_stack: Some(2),
+});
+create_config!(ShortShutdownTimeoutConfig, {
+    max_giga_instructions_per_call: Some(1),
+    shutdown_timeout: Some(Duration::from_millis(200).into()),
+});
+
+pub const PREOPENED_GUEST_PATH: &str = "/data";
+pub const ALLOWED_ENV: &str = "MU_RUNTIME_TEST_ALLOWED_ENV";
+
+pub fn preopened_host_dir() -> PathBuf {
+    std::env::temp_dir().join("mu-runtime-test-preopened")
+}
+
+create_config!(ExposedHostConfig, {
+    max_giga_instructions_per_call: Some(1),
+    wasi: WasiConfig {
+        preopened_dirs: vec![PreopenedDir {
+            host_path: preopened_host_dir(),
+            guest_path: PREOPENED_GUEST_PATH.into(),
+            read_only: true,
+        }],
+        env_allowlist: vec![ALLOWED_ENV.into()],
+    },
+});
 
 #[derive(Debug)]
 pub struct Project<'a> {
@@ -93,6 +159,7 @@ pub async fn read_wasm_functions<'a>(
                 source.into(),
                 AssemblyRuntime::Wasi1_0,
                 [],
+                [],
                 project.memory_limit,
             )?,
         );
@@ -105,11 +172,13 @@ pub mod fixture {
     use std::{marker::PhantomData, sync::Mutex};
 
     use super::*;
-    use db_embedded_tikv::{DbManagerWithTikv, PdConfig, TikvConfig, TikvRunnerConfig};
+    use db_embedded_tikv::{
+        DbManagerWithTikv, PdConfig, TikvConfig, TikvRunnerConfig, DEFAULT_MAX_RESTARTS,
+    };
     use log::trace;
     use mu_common::serde_support::IpOrHostname;
     use mu_common::serde_support::TcpPortAddress;
-    use mu_storage::{StorageConfig, StorageManager};
+    use mu_storage::{FilesystemStorageConfig, StorageConfig, StorageManager};
     use storage_embedded_juicefs::{InternalStorageConfig, StorageInfo};
     use test_context::{AsyncTestContext, TestContext};
 
@@ -221,6 +290,7 @@ pub mod fixture {
                     data_dir: data_dir.get_rand_sub_dir(Some("tikv_data_dir")),
                     log_file: Some(data_dir.get_rand_sub_dir(Some("tikv_log"))),
                 },
+                max_restarts: DEFAULT_MAX_RESTARTS,
             };
 
             Self {
@@ -266,6 +336,9 @@ pub mod fixture {
                         endpoint: addr(3089),
                     },
                 }),
+                filesystem: None,
+                health_check: None,
+                retry: None,
             };
             Self {
                 storage_manager: mu_storage::start(&config).await.unwrap(),
@@ -325,6 +398,7 @@ pub mod fixture {
                                         *map.get_mut(&stack_id).unwrap() += usage;
                                     }
                                 }
+                                Notification::FunctionLog(_) => (),
                             }
                         }
                     }
@@ -352,6 +426,10 @@ pub mod fixture {
     pub struct RuntimeFixtureWithoutDB<Config: RuntimeTestConfig> {
         pub runtime: Box<dyn Runtime>,
         pub usages: Arc<tokio::sync::Mutex<HashMap<StackID, Usage>>>,
+        pub logs: Arc<tokio::sync::Mutex<Vec<FunctionLog>>>,
+        // Stored on the local file system, so storage works without running
+        // a storage backend
+        pub storage_manager: Box<dyn StorageManager>,
         data_dir: TempDir,
         config: PhantomData<Config>,
     }
@@ -364,21 +442,33 @@ pub mod fixture {
             setup_logger();
 
             let db_manager = mock_db::EmptyDBManager;
-            let storage_manager = mock_storage::EmptyStorageManager;
             let data_dir = TempDir::setup();
+            let storage_manager = mu_storage::start(&StorageConfig {
+                external: None,
+                internal: None,
+                filesystem: Some(FilesystemStorageConfig {
+                    root: data_dir.get_rand_sub_dir(Some("storage")),
+                }),
+                health_check: None,
+                retry: None,
+            })
+            .await
+            .unwrap();
 
             let mut config = Config::make();
             config.cache_path = data_dir.get_rand_sub_dir(Some("runtime-cache"));
 
             let (runtime, mut notifications) =
-                start(Box::new(db_manager), Box::new(storage_manager), config)
+                start(Box::new(db_manager), storage_manager.clone(), config)
                     .await
                     .unwrap();
 
             let usages = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+            let logs = Arc::new(tokio::sync::Mutex::new(vec![]));
 
             tokio::spawn({
                 let usages = usages.clone();
+                let logs = logs.clone();
                 async move {
                     loop {
                         if let Some(n) = notifications.recv().await {
@@ -391,6 +481,7 @@ pub mod fixture {
                                         *map.get_mut(&stack_id).unwrap() += usage;
                                     }
                                 }
+                                Notification::FunctionLog(log) => logs.lock().await.push(log),
                             }
                         }
                     }
@@ -400,6 +491,8 @@ pub mod fixture {
             RuntimeFixtureWithoutDB {
                 runtime,
                 usages,
+                logs,
+                storage_manager,
                 data_dir,
                 config: PhantomData,
             }
@@ -456,10 +549,11 @@ pub fn make_request<'a>(
 ) -> musdk_common::Request<'a> {
     musdk_common::Request {
         method: musdk_common::HttpMethod::Get,
+        route_template: Cow::Borrowed("/"),
         headers,
         body: body.unwrap_or(Cow::Borrowed(&[])),
         path_params,
-        query_params,
+        query_params: query_params.into_iter().collect(),
     }
 }
 
@@ -467,8 +561,9 @@ mod mock_db {
     #![allow(unused)]
     use async_trait::async_trait;
     use mu_db::error::Result;
-    use mu_db::{Blob, DbClient, DbManager, DeleteTable, Key, Scan, TableName};
+    use mu_db::{Blob, DbClient, DbManager, DeleteTable, Key, Scan, TableName, TransactionFn};
     use mu_stack::StackID;
+    use std::time::Duration;
     use tikv_client::Value;
 
     #[derive(Clone)]
@@ -491,10 +586,20 @@ mod mock_db {
             Ok(None)
         }
 
-        async fn put(&self, key: Key, value: Value, is_atomic: bool) -> Result<()> {
+        async fn put(
+            &self,
+            key: Key,
+            value: Value,
+            is_atomic: bool,
+            ttl: Option<Duration>,
+        ) -> Result<()> {
             Ok(())
         }
 
+        async fn get_ttl(&self, key: Key) -> Result<Option<Duration>> {
+            Ok(None)
+        }
+
         async fn scan_raw(
             &self,
             lower_inclusive: Vec<u8>,
@@ -525,7 +630,13 @@ mod mock_db {
             Ok(())
         }
 
-        async fn put_raw(&self, key: Vec<u8>, value: Value, is_atomic: bool) -> Result<()> {
+        async fn put_raw(
+            &self,
+            key: Vec<u8>,
+            value: Value,
+            is_atomic: bool,
+            ttl: Option<Duration>,
+        ) -> Result<()> {
             Ok(())
         }
 
@@ -534,22 +645,36 @@ mod mock_db {
             stack_id: StackID,
             table_name: TableName,
             prefix_inner_key: Blob,
-        ) -> Result<()> {
-            Ok(())
+        ) -> Result<u64> {
+            Ok(0)
         }
 
-        async fn clear_table(&self, stack_id: StackID, table_name: TableName) -> Result<()> {
-            Ok(())
+        async fn clear_table(&self, stack_id: StackID, table_name: TableName) -> Result<u64> {
+            Ok(0)
         }
 
-        async fn scan(&self, scan: Scan, limit: u32) -> Result<Vec<(Key, Value)>> {
+        async fn scan(&self, scan: Scan, limit: u32, reverse: bool) -> Result<Vec<(Key, Value)>> {
             Ok(vec![])
         }
 
-        async fn scan_keys(&self, scan: Scan, limit: u32) -> Result<Vec<Key>> {
+        async fn scan_keys(&self, scan: Scan, limit: u32, reverse: bool) -> Result<Vec<Key>> {
             Ok(vec![])
         }
 
+        async fn count_by_prefix(
+            &self,
+            stack_id: StackID,
+            table_name: TableName,
+            prefix_inner_key: Blob,
+            limit: u64,
+        ) -> Result<u64> {
+            Ok(0)
+        }
+
+        async fn count(&self, scan: Scan, limit: u64) -> Result<u64> {
+            Ok(0)
+        }
+
         async fn table_list(
             &self,
             stack_id: StackID,
@@ -570,6 +695,10 @@ mod mock_db {
             Ok(vec![])
         }
 
+        async fn batch_get_ordered(&self, keys: Vec<Key>) -> Result<Vec<Option<(Key, Value)>>> {
+            Ok(vec![None; keys.len()])
+        }
+
         async fn batch_put(&self, pairs: Vec<(Key, Value)>, is_atomic: bool) -> Result<()> {
             Ok(())
         }
@@ -590,104 +719,27 @@ mod mock_db {
         ) -> Result<(Option<Value>, bool)> {
             Ok((None, false))
         }
-    }
 
-    #[async_trait]
-    impl DbManager for EmptyDBManager {
-        async fn make_client(&self) -> anyhow::Result<Box<dyn DbClient>> {
-            Ok(Box::new(EmptyDBClient))
+        async fn batch_compare_and_swap_non_atomic(
+            &self,
+            ops: Vec<(Key, Option<Value>, Value)>,
+        ) -> Result<Vec<(Option<Value>, bool)>> {
+            Ok(vec![(None, false); ops.len()])
         }
 
-        async fn stop(&self) -> anyhow::Result<()> {
+        async fn transaction(&self, f: TransactionFn) -> Result<()> {
             Ok(())
         }
     }
-}
-
-mod mock_storage {
-    use async_trait::async_trait;
-    use mu_storage::{DeleteStorage, Object, Owner, StorageClient, StorageManager};
-    use tokio::io::{AsyncRead, AsyncWrite};
-
-    #[derive(Clone)]
-    pub struct EmptyStorageManager;
-
-    #[derive(Clone)]
-    pub struct EmptyStorageClient;
 
     #[async_trait]
-    impl StorageManager for EmptyStorageManager {
-        fn make_client(&self) -> anyhow::Result<Box<dyn mu_storage::StorageClient>> {
-            Ok(Box::new(EmptyStorageClient))
+    impl DbManager for EmptyDBManager {
+        async fn make_client(&self) -> anyhow::Result<Box<dyn DbClient>> {
+            Ok(Box::new(EmptyDBClient))
         }
 
         async fn stop(&self) -> anyhow::Result<()> {
             Ok(())
         }
     }
-
-    #[async_trait]
-    impl StorageClient for EmptyStorageClient {
-        async fn update_stack_storages(
-            &self,
-            _owner: Owner,
-            _storage_delete_pairs: Vec<(&str, DeleteStorage)>,
-        ) -> anyhow::Result<()> {
-            Ok(())
-        }
-
-        async fn storage_list(&self, _owner: Owner) -> anyhow::Result<Vec<String>> {
-            Ok(vec![])
-        }
-
-        async fn contains_storage(
-            &self,
-            _owner: Owner,
-            _storage_name: &str,
-        ) -> anyhow::Result<bool> {
-            Ok(false)
-        }
-
-        async fn remove_storage(&self, _owner: Owner, _storage_name: &str) -> anyhow::Result<()> {
-            Ok(())
-        }
-
-        async fn get(
-            &self,
-            _owner: Owner,
-            _storage_name: &str,
-            _key: &str,
-            _writer: &mut (dyn AsyncWrite + Send + Sync + Unpin),
-        ) -> anyhow::Result<()> {
-            Ok(())
-        }
-
-        async fn put(
-            &self,
-            _owner: Owner,
-            _storage_name: &str,
-            _key: &str,
-            _reader: &mut (dyn AsyncRead + Send + Sync + Unpin),
-        ) -> anyhow::Result<()> {
-            Ok(())
-        }
-
-        async fn delete(
-            &self,
-            _owner: Owner,
-            _storage_name: &str,
-            _key: &str,
-        ) -> anyhow::Result<()> {
-            Ok(())
-        }
-
-        async fn list(
-            &self,
-            _owner: Owner,
-            _storage_name: &str,
-            _prefix: &str,
-        ) -> anyhow::Result<Vec<Object>> {
-            Ok(vec![])
-        }
-    }
 }
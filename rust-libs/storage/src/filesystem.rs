@@ -0,0 +1,748 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Error, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncWrite},
+    sync::Mutex,
+};
+
+use crate::{
+    DeleteStorage, ETagMismatch, Object, ObjectMetadata, ObjectNotFound, Owner, StorageClient,
+    StorageManager, DEFAULT_CONTENT_TYPE, METADATA_PREFIX,
+};
+
+// Content types are kept next to the objects, in a tree of their own. Owner
+// prefixes never start with `!`, so these can't collide with objects.
+const CONTENT_TYPE_PREFIX: &str = "!content-type";
+
+// Objects are written here first, then moved into place, so readers never
+// see a partially written object.
+const TEMP_PREFIX: &str = "!tmp";
+
+/// Stores objects as plain files under `root`, with the same layout as the
+/// S3 backends. Meant for local development and tests, where running
+/// JuiceFS and TiKV is too heavy. Presigned URLs aren't supported, and a key
+/// can't be both an object and the prefix of another one (e.g. `a` and
+/// `a/b`), since `a` can't be both a file and a directory.
+#[derive(Deserialize, Clone, Debug)]
+pub struct FilesystemStorageConfig {
+    pub root: PathBuf,
+}
+
+#[derive(Clone)]
+struct FilesystemStorageManager {
+    client: FilesystemStorageClient,
+}
+
+pub(crate) async fn start(config: &FilesystemStorageConfig) -> Result<Box<dyn StorageManager>> {
+    fs::create_dir_all(&config.root)
+        .await
+        .with_context(|| format!("Failed to create storage root {}", config.root.display()))?;
+
+    Ok(Box::new(FilesystemStorageManager {
+        client: FilesystemStorageClient::new(config.root.clone()),
+    }))
+}
+
+#[async_trait]
+impl StorageManager for FilesystemStorageManager {
+    fn make_client(&self) -> Result<Box<dyn StorageClient>> {
+        Ok(Box::new(self.client.clone()))
+    }
+
+    async fn health(&self) -> Result<()> {
+        let metadata = fs::metadata(&self.client.root).await?;
+        if !metadata.is_dir() {
+            bail!(
+                "Storage root {} isn't a directory",
+                self.client.root.display()
+            )
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct FilesystemStorageClient {
+    root: PathBuf,
+    // Makes `put_if_match`'s check and write atomic, as long as this is the
+    // only process using `root`
+    write_lock: Arc<Mutex<()>>,
+    temp_file_counter: Arc<AtomicU64>,
+}
+
+impl FilesystemStorageClient {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            write_lock: Arc::new(Mutex::new(())),
+            temp_file_counter: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn manifest_dir(&self, owner: Owner) -> PathBuf {
+        self.root.join(METADATA_PREFIX).join(owner.path_prefix())
+    }
+
+    fn storage_dir(&self, owner: Owner, storage_name: &str) -> Result<PathBuf> {
+        validate_path_segment(storage_name)?;
+        Ok(self.root.join(owner.path_prefix()).join(storage_name))
+    }
+
+    fn content_type_dir(&self, owner: Owner, storage_name: &str) -> Result<PathBuf> {
+        validate_path_segment(storage_name)?;
+        Ok(self
+            .root
+            .join(CONTENT_TYPE_PREFIX)
+            .join(owner.path_prefix())
+            .join(storage_name))
+    }
+
+    fn object_path(&self, owner: Owner, storage_name: &str, key: &str) -> Result<PathBuf> {
+        join_key(self.storage_dir(owner, storage_name)?, key)
+    }
+
+    fn content_type_path(&self, owner: Owner, storage_name: &str, key: &str) -> Result<PathBuf> {
+        join_key(self.content_type_dir(owner, storage_name)?, key)
+    }
+
+    async fn ensure_storage_exists(&self, owner: Owner, storage_name: &str) -> Result<()> {
+        if !self.contains_storage(owner, storage_name).await? {
+            bail!("Storage not found")
+        }
+        Ok(())
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let id = self.temp_file_counter.fetch_add(1, Ordering::Relaxed);
+        self.root
+            .join(TEMP_PREFIX)
+            .join(format!("{}-{id}", std::process::id()))
+    }
+
+    // Writes to a temporary file first, so the object is replaced in one go
+    async fn write_file(
+        &self,
+        path: &Path,
+        reader: &mut (dyn AsyncRead + Send + Sync + Unpin),
+    ) -> Result<()> {
+        let temp_path = self.temp_path();
+        create_parent_dir(&temp_path).await?;
+
+        let result = async {
+            let mut file = fs::File::create(&temp_path).await?;
+            tokio::io::copy(reader, &mut file).await?;
+            file.sync_all().await?;
+            create_parent_dir(path).await?;
+            fs::rename(&temp_path, path).await?;
+            Ok::<_, Error>(())
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path).await;
+        }
+        result
+    }
+
+    async fn write_object(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Sync + Unpin),
+        content_type: Option<&str>,
+    ) -> Result<()> {
+        let path = self.object_path(owner, storage_name, key)?;
+        let content_type = content_type.unwrap_or(DEFAULT_CONTENT_TYPE);
+
+        self.write_file(
+            &self.content_type_path(owner, storage_name, key)?,
+            &mut content_type.as_bytes(),
+        )
+        .await?;
+        self.write_file(&path, reader).await
+    }
+
+    async fn read_content_type(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<Option<String>> {
+        let path = self.content_type_path(owner, storage_name, key)?;
+        match fs::read_to_string(path).await {
+            Ok(content_type) => Ok(Some(content_type)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_object(&self, owner: Owner, storage_name: &str, key: &str) -> Result<Vec<u8>> {
+        let path = self.object_path(owner, storage_name, key)?;
+        fs::read(path).await.map_err(not_found_to_object_not_found)
+    }
+
+    // Removes the object and its content type, then any directories that
+    // were only there for it
+    async fn remove_object(&self, owner: Owner, storage_name: &str, key: &str) -> Result<()> {
+        let storage_dir = self.storage_dir(owner, storage_name)?;
+        let content_type_dir = self.content_type_dir(owner, storage_name)?;
+
+        for (dir, path) in [
+            (&storage_dir, join_key(storage_dir.clone(), key)?),
+            (&content_type_dir, join_key(content_type_dir.clone(), key)?),
+        ] {
+            match fs::remove_file(&path).await {
+                Ok(()) => remove_empty_parents(&path, dir).await,
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn object_metadata(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: String,
+        metadata: std::fs::Metadata,
+    ) -> Result<ObjectMetadata> {
+        let content_type = self.read_content_type(owner, storage_name, &key).await?;
+        Ok(ObjectMetadata {
+            object: Object {
+                size: metadata.len(),
+                last_modified: metadata.modified().ok().map(OffsetDateTime::from),
+                key,
+            },
+            content_type,
+        })
+    }
+}
+
+#[async_trait]
+impl StorageClient for FilesystemStorageClient {
+    async fn update_stack_storages(
+        &self,
+        owner: Owner,
+        storage_delete_pairs: Vec<(&str, DeleteStorage)>,
+    ) -> Result<()> {
+        let existing_storages = self.storage_list(owner).await?;
+
+        for (storage_name, is_delete) in storage_delete_pairs {
+            let exists = existing_storages.iter().any(|s| s == storage_name);
+            if !exists && !*is_delete {
+                // Like the S3 backends, only stacks' storages are listed
+                if let Owner::Stack(_) = owner {
+                    validate_path_segment(storage_name)?;
+                    let path = self.manifest_dir(owner).join(storage_name);
+                    self.write_file(&path, &mut &b""[..]).await?;
+                }
+            } else if exists && *is_delete {
+                self.remove_storage(owner, storage_name).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn storage_list(&self, owner: Owner) -> Result<Vec<String>> {
+        let mut entries = match fs::read_dir(self.manifest_dir(owner)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut storages = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            storages.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        storages.sort();
+        Ok(storages)
+    }
+
+    async fn contains_storage(&self, owner: Owner, storage_name: &str) -> Result<bool> {
+        match owner {
+            Owner::User(_) => Ok(true),
+            _ => Ok(self
+                .storage_list(owner)
+                .await?
+                .iter()
+                .any(|s| s == storage_name)),
+        }
+    }
+
+    async fn remove_storage(&self, owner: Owner, storage_name: &str) -> Result<()> {
+        if let Owner::Stack(_) = owner {
+            validate_path_segment(storage_name)?;
+            match fs::remove_file(self.manifest_dir(owner).join(storage_name)).await {
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        for dir in [
+            self.storage_dir(owner, storage_name)?,
+            self.content_type_dir(owner, storage_name)?,
+        ] {
+            match fs::remove_dir_all(dir).await {
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        writer: &mut (dyn AsyncWrite + Send + Sync + Unpin),
+    ) -> Result<Option<String>> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+
+        let path = self.object_path(owner, storage_name, key)?;
+        let mut file = fs::File::open(path)
+            .await
+            .map_err(not_found_to_object_not_found)?;
+        tokio::io::copy(&mut file, writer).await?;
+
+        self.read_content_type(owner, storage_name, key).await
+    }
+
+    async fn put(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        reader: &mut (dyn AsyncRead + Send + Sync + Unpin),
+        content_type: Option<&str>,
+    ) -> Result<()> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+        self.write_object(owner, storage_name, key, reader, content_type)
+            .await
+    }
+
+    async fn head(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<Option<ObjectMetadata>> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+
+        let path = self.object_path(owner, storage_name, key)?;
+        match fs::metadata(path).await {
+            Ok(metadata) if metadata.is_file() => Ok(Some(
+                self.object_metadata(owner, storage_name, key.to_string(), metadata)
+                    .await?,
+            )),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_etag(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<Option<String>> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+
+        match self.read_object(owner, storage_name, key).await {
+            Ok(data) => Ok(Some(etag(&data))),
+            Err(e) if e.is::<ObjectNotFound>() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn put_if_match(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        etag: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+
+        match self.get_etag(owner, storage_name, key).await? {
+            Some(current) if current.trim_matches('"') == etag.trim_matches('"') => (),
+            _ => return Err(ETagMismatch.into()),
+        }
+
+        // Like S3, a conditional write keeps the object's content type
+        let content_type = self.read_content_type(owner, storage_name, key).await?;
+        self.write_object(
+            owner,
+            storage_name,
+            key,
+            &mut &data[..],
+            content_type.as_deref(),
+        )
+        .await
+    }
+
+    async fn delete(&self, owner: Owner, storage_name: &str, key: &str) -> Result<()> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+        self.remove_object(owner, storage_name, key).await
+    }
+
+    async fn delete_many(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        keys: Vec<String>,
+    ) -> Result<Vec<(String, Error)>> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+
+        let mut failed = vec![];
+        for key in keys {
+            if let Err(e) = self.remove_object(owner, storage_name, &key).await {
+                failed.push((key, e));
+            }
+        }
+        Ok(failed)
+    }
+
+    async fn list(&self, owner: Owner, storage_name: &str, prefix: &str) -> Result<Vec<Object>> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+
+        let storage_dir = self.storage_dir(owner, storage_name)?;
+        let mut objects = vec![];
+        let mut dirs = vec![(storage_dir, String::new())];
+
+        while let Some((dir, dir_key)) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let key = format!("{dir_key}{}", entry.file_name().to_string_lossy());
+                let metadata = entry.metadata().await?;
+
+                if metadata.is_dir() {
+                    let dir_key = format!("{key}/");
+                    // Only descend into directories that can hold matching keys
+                    if dir_key.starts_with(prefix) || prefix.starts_with(&dir_key) {
+                        dirs.push((entry.path(), dir_key));
+                    }
+                } else if key.starts_with(prefix) {
+                    objects.push(Object {
+                        size: metadata.len(),
+                        last_modified: metadata.modified().ok().map(OffsetDateTime::from),
+                        key,
+                    });
+                }
+            }
+        }
+
+        // S3 lists keys in order
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn copy(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        src_key: &str,
+        dst_key: &str,
+    ) -> Result<()> {
+        self.ensure_storage_exists(owner, storage_name).await?;
+
+        let data = self.read_object(owner, storage_name, src_key).await?;
+        let content_type = self.read_content_type(owner, storage_name, src_key).await?;
+
+        self.write_object(
+            owner,
+            storage_name,
+            dst_key,
+            &mut &data[..],
+            content_type.as_deref(),
+        )
+        .await
+    }
+
+    async fn rename(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        src_key: &str,
+        dst_key: &str,
+    ) -> Result<()> {
+        if src_key == dst_key {
+            return match self.get_etag(owner, storage_name, src_key).await? {
+                Some(_) => Ok(()),
+                None => Err(ObjectNotFound.into()),
+            };
+        }
+
+        self.copy(owner, storage_name, src_key, dst_key).await?;
+        self.delete(owner, storage_name, src_key).await
+    }
+
+    async fn presign_get(
+        &self,
+        _owner: Owner,
+        _storage_name: &str,
+        _key: &str,
+        _expiry: Duration,
+    ) -> Result<String> {
+        bail!("Presigned URLs aren't supported by filesystem storage")
+    }
+
+    async fn presign_put(
+        &self,
+        _owner: Owner,
+        _storage_name: &str,
+        _key: &str,
+        _expiry: Duration,
+    ) -> Result<String> {
+        bail!("Presigned URLs aren't supported by filesystem storage")
+    }
+}
+
+// Only plain names are allowed, so nothing can be read or written outside
+// the storage's directory
+fn validate_path_segment(segment: &str) -> Result<()> {
+    if segment.is_empty() || segment == "." || segment == ".." || segment.contains(['/', '\\']) {
+        bail!("Invalid name for filesystem storage: '{segment}'")
+    }
+    Ok(())
+}
+
+fn join_key(mut path: PathBuf, key: &str) -> Result<PathBuf> {
+    for segment in key.split('/') {
+        validate_path_segment(segment)?;
+        path.push(segment);
+    }
+    Ok(path)
+}
+
+async fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    Ok(())
+}
+
+async fn remove_empty_parents(path: &Path, stop_at: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        // Fails once a directory isn't empty
+        if current == stop_at || fs::remove_dir(current).await.is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+fn not_found_to_object_not_found(e: std::io::Error) -> Error {
+    if e.kind() == ErrorKind::NotFound {
+        ObjectNotFound.into()
+    } else {
+        e.into()
+    }
+}
+
+fn etag(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use mu_stack::StackID;
+
+    use super::*;
+
+    const OWNER: Owner = Owner::Stack(StackID::SolanaPublicKey([1; 32]));
+
+    async fn test_client(name: &str) -> Box<dyn StorageClient> {
+        let root = std::env::temp_dir().join(format!(
+            "mu-filesystem-storage-{}-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root).await;
+
+        let manager = start(&FilesystemStorageConfig { root }).await.unwrap();
+        manager.health().await.unwrap();
+
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+        client
+    }
+
+    async fn get(client: &dyn StorageClient, key: &str) -> Result<(Vec<u8>, Option<String>)> {
+        let mut data = vec![];
+        let content_type = client.get(OWNER, "s1", key, &mut data).await?;
+        Ok((data, content_type))
+    }
+
+    #[tokio::test]
+    async fn objects_can_be_written_read_listed_and_deleted() {
+        let client = test_client("round-trip").await;
+
+        client
+            .put(OWNER, "s1", "a/b.png", &mut &b"png"[..], Some("image/png"))
+            .await
+            .unwrap();
+        client
+            .put(OWNER, "s1", "a/c.txt", &mut &b"text"[..], None)
+            .await
+            .unwrap();
+        client
+            .put(OWNER, "s1", "d", &mut &b"d"[..], None)
+            .await
+            .unwrap();
+
+        let (data, content_type) = get(client.as_ref(), "a/b.png").await.unwrap();
+        assert_eq!(data, b"png");
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+
+        let listed = client.list(OWNER, "s1", "a/").await.unwrap();
+        let keys = listed.iter().map(|o| o.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["a/b.png", "a/c.txt"]);
+        assert_eq!(listed[1].size, 4);
+        assert!(listed[1].last_modified.is_some());
+
+        let object = client.head(OWNER, "s1", "a/c.txt").await.unwrap().unwrap();
+        assert_eq!(object.content_type.as_deref(), Some(DEFAULT_CONTENT_TYPE));
+
+        let keys = client
+            .list(OWNER, "s1", "")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|o| o.key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["a/b.png", "a/c.txt", "d"]);
+
+        client.delete(OWNER, "s1", "a/b.png").await.unwrap();
+        client.delete(OWNER, "s1", "missing").await.unwrap();
+        assert!(get(client.as_ref(), "a/b.png")
+            .await
+            .unwrap_err()
+            .is::<ObjectNotFound>());
+        assert!(client.head(OWNER, "s1", "a/b.png").await.unwrap().is_none());
+        assert_eq!(client.list(OWNER, "s1", "a/").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stack_storages_must_exist() {
+        let client = test_client("manifest").await;
+
+        assert_eq!(client.storage_list(OWNER).await.unwrap(), ["s1"]);
+        assert!(client
+            .put(OWNER, "s2", "key", &mut &b"data"[..], None)
+            .await
+            .is_err());
+
+        client
+            .put(OWNER, "s1", "key", &mut &b"data"[..], None)
+            .await
+            .unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(true))])
+            .await
+            .unwrap();
+
+        assert!(client.storage_list(OWNER).await.unwrap().is_empty());
+        assert!(get(client.as_ref(), "key").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn conditional_writes_check_the_etag() {
+        let client = test_client("etag").await;
+
+        client
+            .put(OWNER, "s1", "key", &mut &b"v1"[..], Some("text/plain"))
+            .await
+            .unwrap();
+        let etag = client.get_etag(OWNER, "s1", "key").await.unwrap().unwrap();
+
+        client
+            .put_if_match(OWNER, "s1", "key", &etag, b"v2")
+            .await
+            .unwrap();
+        let err = client
+            .put_if_match(OWNER, "s1", "key", &etag, b"v3")
+            .await
+            .unwrap_err();
+        assert!(err.is::<ETagMismatch>());
+
+        let (data, content_type) = get(client.as_ref(), "key").await.unwrap();
+        assert_eq!(data, b"v2");
+        assert_eq!(content_type.as_deref(), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn objects_can_be_copied_and_renamed() {
+        let client = test_client("copy").await;
+
+        client
+            .put(OWNER, "s1", "src", &mut &b"data"[..], Some("text/plain"))
+            .await
+            .unwrap();
+        client.copy(OWNER, "s1", "src", "copy").await.unwrap();
+        client
+            .rename(OWNER, "s1", "src", "dir/moved")
+            .await
+            .unwrap();
+
+        assert!(client.head(OWNER, "s1", "src").await.unwrap().is_none());
+        for key in ["copy", "dir/moved"] {
+            let (data, content_type) = get(client.as_ref(), key).await.unwrap();
+            assert_eq!(data, b"data");
+            assert_eq!(content_type.as_deref(), Some("text/plain"));
+        }
+
+        let err = client.copy(OWNER, "s1", "src", "dst").await.unwrap_err();
+        assert!(err.is::<ObjectNotFound>());
+    }
+
+    #[tokio::test]
+    async fn keys_cant_escape_the_storage() {
+        let client = test_client("escape").await;
+
+        for key in ["../s2/key", "a/../../key", "/etc/passwd", "a//b", ""] {
+            assert!(
+                client
+                    .put(OWNER, "s1", key, &mut &b"data"[..], None)
+                    .await
+                    .is_err(),
+                "{key}"
+            );
+        }
+    }
+}
@@ -1,25 +1,83 @@
 use anyhow::{bail, Error, Result};
 use async_trait::async_trait;
 use dyn_clonable::clonable;
+use futures::{stream, StreamExt};
 use log::warn;
+use mu_common::health_check::HealthCheckConfig;
 use mu_stack::{StackID, StackOwner};
 use pin_project_lite::pin_project;
-use s3::{creds::Credentials, Bucket};
+use s3::{creds::Credentials, error::S3Error, Bucket};
 use serde::Deserialize;
 use std::{fmt::Debug, ops::Deref, pin::Pin, time::Duration};
-use storage_embedded_juicefs::{InternalStorageConfig, JuicefsRunner, LiveStorageConfig};
+use storage_embedded_juicefs::{InternalStorageConfig, JuicefsRunner, LiveStorageConfig, Region};
+use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime,
+};
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite},
     time::sleep,
 };
 
+mod filesystem;
+mod retry;
+
+pub use filesystem::FilesystemStorageConfig;
+pub use retry::RetryConfig;
+
 const METADATA_PREFIX: &str = "!";
 
+const HTTP_NOT_FOUND: u16 = 404;
+const HTTP_PRECONDITION_FAILED: u16 = 412;
+const HTTP_NOT_IMPLEMENTED: u16 = 501;
+
+/// Size of the in-memory pipe used when a copy has to be streamed through
+/// this node.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Content type S3 stores objects with when none is given.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Objects up to this size are buffered before they're uploaded, so the
+// upload can be retried. Larger ones are streamed and can't be, since the
+// reader can't be rewound. This matches the S3 client's chunk size, so
+// anything smaller would be buffered by it anyway.
+const RETRYABLE_PUT_SIZE: u64 = 8 * 1024 * 1024;
+
+// How many deletes of a single `delete_many` run at the same time. The S3
+// client we use has no multi-object delete, so keys are deleted one by one.
+const DELETE_MANY_CONCURRENCY: usize = 16;
+
+/// The longest a presigned URL can stay valid for, as limited by S3.
+pub const MAX_PRESIGN_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 pub struct Object {
     pub key: String,
     pub size: u64,
+    /// `None` if the backend reported a date we couldn't parse.
+    pub last_modified: Option<OffsetDateTime>,
 }
 
+/// Returned by `head`. Listings only return `Object`s, since S3 would need
+/// a separate request per object for their content types.
+pub struct ObjectMetadata {
+    pub object: Object,
+    /// As given to `put`.
+    pub content_type: Option<String>,
+}
+
+/// Returned (inside an `anyhow::Error`) by `put_if_match` when the object's
+/// current ETag isn't the expected one.
+#[derive(Debug, thiserror::Error)]
+#[error("Object was modified, ETag mismatch")]
+pub struct ETagMismatch;
+
+/// Returned (inside an `anyhow::Error`) by `copy` and `rename` when the
+/// source object doesn't exist.
+#[derive(Debug, thiserror::Error)]
+#[error("Object not found")]
+pub struct ObjectNotFound;
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Owner {
     User(StackOwner),
@@ -50,30 +108,110 @@ pub trait StorageClient: Send + Sync + Clone {
 
     async fn remove_storage(&self, owner: Owner, storage_name: &str) -> Result<()>;
 
+    /// Writes the object's contents to `writer` and returns its content type.
     async fn get(
         &self,
         owner: Owner,
         storage_name: &str,
         key: &str,
         writer: &mut (dyn AsyncWrite + Send + Sync + Unpin),
-    ) -> Result<()>;
+    ) -> Result<Option<String>>;
 
+    /// Stores the object with the given content type, or the backend's
+    /// default (usually `application/octet-stream`) if it's `None`.
     async fn put(
         &self,
         owner: Owner,
         storage_name: &str,
         key: &str,
         reader: &mut (dyn AsyncRead + Send + Sync + Unpin),
+        content_type: Option<&str>,
+    ) -> Result<()>;
+
+    /// Returns the object's metadata without downloading it, or `None` if
+    /// it doesn't exist.
+    async fn head(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<Option<ObjectMetadata>>;
+
+    /// Returns `None` if the object doesn't exist.
+    async fn get_etag(&self, owner: Owner, storage_name: &str, key: &str)
+        -> Result<Option<String>>;
+
+    /// Writes the object only if its current ETag is `etag`, failing with
+    /// `ETagMismatch` otherwise.
+    async fn put_if_match(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        etag: &str,
+        data: &[u8],
     ) -> Result<()>;
 
     async fn delete(&self, owner: Owner, storage_name: &str, key: &str) -> Result<()>;
 
+    /// Deletes every key, carrying on past failures. Returns the keys that
+    /// couldn't be deleted along with why, in the order they were given.
+    /// Keys that don't exist aren't failures.
+    async fn delete_many(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        keys: Vec<String>,
+    ) -> Result<Vec<(String, Error)>>;
+
     async fn list(&self, owner: Owner, storage_name: &str, prefix: &str) -> Result<Vec<Object>>;
+
+    /// Copies an object within a storage without sending its contents
+    /// through this node, unless the backend can't copy objects itself.
+    /// Fails with `ObjectNotFound` if `src_key` doesn't exist.
+    async fn copy(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        src_key: &str,
+        dst_key: &str,
+    ) -> Result<()>;
+
+    /// Like `copy`, but removes the source object afterwards.
+    async fn rename(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        src_key: &str,
+        dst_key: &str,
+    ) -> Result<()>;
+
+    /// Returns a URL the object can be downloaded from directly, without
+    /// going through this node, until `expiry` has passed. The expiry must
+    /// be at least a second and at most `MAX_PRESIGN_EXPIRY`.
+    async fn presign_get(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        expiry: Duration,
+    ) -> Result<String>;
+
+    /// Like `presign_get`, but the URL accepts a `PUT` with the object's
+    /// new contents instead.
+    async fn presign_put(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        expiry: Duration,
+    ) -> Result<String>;
 }
 
 #[derive(Clone, Debug)]
 struct StorageClientImpl {
     bucket: Bucket,
+    retry: RetryConfig,
 }
 
 // exactly one should be provided
@@ -82,12 +220,24 @@ struct StorageClientImpl {
 pub struct StorageConfig {
     pub external: Option<LiveStorageConfig>,
     pub internal: Option<InternalStorageConfig>,
+    pub filesystem: Option<FilesystemStorageConfig>,
+
+    /// How startup checks wait for the storage backend to become reachable.
+    pub health_check: Option<HealthCheckConfig>,
+
+    /// How operations are retried after transient failures.
+    pub retry: Option<RetryConfig>,
 }
 
 #[async_trait]
 #[clonable]
 pub trait StorageManager: Send + Sync + Clone {
     fn make_client(&self) -> anyhow::Result<Box<dyn StorageClient>>;
+
+    /// Performs a cheap request against the storage backend, failing
+    /// if it can't be reached.
+    async fn health(&self) -> anyhow::Result<()>;
+
     async fn stop(&self) -> anyhow::Result<()>;
 }
 
@@ -95,13 +245,21 @@ pub trait StorageManager: Send + Sync + Clone {
 struct StorageManagerImpl {
     inner: Option<Box<dyn JuicefsRunner>>,
     config: LiveStorageConfig,
+    retry: RetryConfig,
 }
 
 #[async_trait]
 impl StorageManager for StorageManagerImpl {
     //TODO: Useless Ok??
     fn make_client(&self) -> anyhow::Result<Box<dyn StorageClient>> {
-        Ok(Box::new(StorageClientImpl::new(&self.config)?))
+        Ok(Box::new(StorageClientImpl::new(
+            &self.config,
+            self.retry.clone(),
+        )?))
+    }
+
+    async fn health(&self) -> anyhow::Result<()> {
+        probe_storage_backend(self.make_client()?.as_ref()).await
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
@@ -113,7 +271,7 @@ impl StorageManager for StorageManagerImpl {
 }
 
 impl StorageClientImpl {
-    pub fn new(config: &LiveStorageConfig) -> Result<StorageClientImpl> {
+    pub fn new(config: &LiveStorageConfig, retry: RetryConfig) -> Result<StorageClientImpl> {
         let credentials = Credentials::new(
             config.auth_config.access_key.as_deref(),
             config.auth_config.secret_key.as_deref(),
@@ -123,15 +281,35 @@ impl StorageClientImpl {
         )
         .map_err(|e| Error::msg(e.to_string()))?;
 
-        let region = s3::Region::Custom {
-            region: config.region.region.to_owned(),
-            endpoint: config.region.endpoint.clone(),
-        };
+        let mut bucket = Bucket::new(
+            &config.bucket_name,
+            Self::create_region(&config.region)?,
+            credentials,
+        )?;
+        if config.path_style {
+            bucket.set_path_style();
+        }
 
-        let mut bucket = Bucket::new(&config.bucket_name, region, credentials)?;
-        bucket.set_path_style();
+        Ok(StorageClientImpl { bucket, retry })
+    }
+
+    fn create_region(region: &Region) -> Result<s3::Region> {
+        if let Some(endpoint) = &region.endpoint {
+            return Ok(s3::Region::Custom {
+                region: region.region.clone(),
+                endpoint: endpoint.clone(),
+            });
+        }
 
-        Ok(StorageClientImpl { bucket })
+        // Unknown names parse into a custom region, using the name as the
+        // endpoint
+        match region.region.parse::<s3::Region>()? {
+            s3::Region::Custom { .. } => bail!(
+                "Unknown storage region '{}', an endpoint must be set for it",
+                region.region
+            ),
+            region => Ok(region),
+        }
     }
 
     fn create_path(owner: Owner, storage_name: &str, key: &str) -> String {
@@ -145,13 +323,124 @@ impl StorageClientImpl {
             .nth(1)
             .map(|(i, _)| object.key.split_at(i + 1).1.to_string());
 
-        // TODO: deserialize last modified date
+        let last_modified = match OffsetDateTime::parse(&object.last_modified, &Rfc3339) {
+            Ok(date) => Some(date),
+            Err(e) => {
+                warn!(
+                    "Invalid last modified date '{}' for object {}: {e}",
+                    object.last_modified, object.key
+                );
+                None
+            }
+        };
+
         Object {
             key: key.unwrap_or_default(),
             size: object.size,
+            last_modified,
+        }
+    }
+
+    fn create_head_object(key: &str, head: s3::serde_types::HeadObjectResult) -> ObjectMetadata {
+        let last_modified = head.last_modified.and_then(|date| {
+            Self::parse_http_date(&date)
+                .map_err(|e| warn!("Invalid last modified date '{date}' for object {key}: {e}"))
+                .ok()
+        });
+
+        ObjectMetadata {
+            object: Object {
+                key: key.to_string(),
+                size: head.content_length.unwrap_or_default().max(0) as u64,
+                last_modified,
+            },
+            content_type: head.content_type,
         }
     }
 
+    // HEAD responses carry HTTP dates, e.g. `Wed, 01 Mar 2023 12:34:56 GMT`,
+    // unlike listings
+    fn parse_http_date(date: &str) -> Result<OffsetDateTime, time::error::Parse> {
+        OffsetDateTime::parse(date, &Rfc2822)
+    }
+
+    // `Bucket::list` follows continuation tokens and returns every page, so
+    // all of them must be read, not only the first one
+    fn create_objects(
+        pages: impl IntoIterator<Item = Vec<s3::serde_types::Object>>,
+    ) -> Vec<Object> {
+        pages
+            .into_iter()
+            .flatten()
+            .map(|o| Self::create_object(&o))
+            .collect()
+    }
+
+    fn presign_expiry_secs(expiry: Duration) -> Result<u32> {
+        if expiry.as_secs() == 0 || expiry > MAX_PRESIGN_EXPIRY {
+            bail!("Presigned URLs must expire after between one second and seven days")
+        }
+        Ok(expiry.as_secs() as u32)
+    }
+
+    async fn stream_copy(&self, src_path: &str, dst_path: &str) -> Result<()> {
+        let (head, _) = self.bucket.head_object(src_path).await?;
+        let content_type = head
+            .content_type
+            .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.into());
+
+        let (mut reader, mut writer) = tokio::io::duplex(COPY_BUFFER_SIZE);
+
+        let download = async move {
+            let result = self.bucket.get_object_stream(src_path, &mut writer).await;
+            // Closing the pipe lets the upload see the end of the object
+            drop(writer);
+            result.map_err(Error::from)
+        };
+        let upload = async {
+            self.bucket
+                .put_object_stream_with_content_type(&mut reader, dst_path, content_type)
+                .await
+                .map_err(Error::from)
+        };
+
+        // If the download fails, the upload is dropped before it completes,
+        // so a truncated object is never written
+        tokio::try_join!(download, upload)?;
+        Ok(())
+    }
+
+    // Backends disagree on whether ETags are quoted
+    fn etags_match(a: &str, b: &str) -> bool {
+        a.trim_matches('"') == b.trim_matches('"')
+    }
+
+    // `remove_storage` deletes the data after the storage itself is gone,
+    // so this can't check it exists
+    async fn delete_many_unchecked(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        keys: Vec<String>,
+    ) -> Vec<(String, Error)> {
+        stream::iter(keys)
+            .map(|key| async move {
+                let path = Self::create_path(owner, storage_name, &key);
+                match self
+                    .retry
+                    .run("delete", || self.bucket.delete_object(&path))
+                    .await
+                {
+                    Ok(_) | Err(S3Error::Http(HTTP_NOT_FOUND, _)) => None,
+                    Err(e) => Some((key, e.into())),
+                }
+            })
+            .buffered(DELETE_MANY_CONCURRENCY)
+            .filter_map(|failure| async move { failure })
+            .collect()
+            .await
+    }
+
     async fn add_storage(&self, owner: Owner, name: &str) -> Result<()> {
         if let Owner::Stack(_) = owner {
             let path = format!("{METADATA_PREFIX}/{}/{name}", owner.path_prefix());
@@ -185,11 +474,14 @@ impl StorageClient for StorageClientImpl {
     async fn storage_list(&self, owner: Owner) -> Result<Vec<String>> {
         let prefix = format!("{METADATA_PREFIX}/{}/", owner.path_prefix());
 
-        let resp = self.bucket.list(prefix, None).await?;
+        let resp = self
+            .retry
+            .run("list", || self.bucket.list(prefix.clone(), None))
+            .await?;
 
-        let objects = resp[0]
-            .contents
+        let objects = resp
             .iter()
+            .flat_map(|page| page.contents.iter())
             .filter_map(|x| x.key.split('/').last().map(ToString::to_string))
             .collect();
 
@@ -207,6 +499,14 @@ impl StorageClient for StorageClientImpl {
     }
 
     async fn remove_storage(&self, owner: Owner, storage_name: &str) -> Result<()> {
+        // list the data first, `list` fails once the storage is gone
+        let keys = self
+            .list(owner, storage_name, "")
+            .await?
+            .into_iter()
+            .map(|o| o.key)
+            .collect();
+
         // remove from manifest
         if let Owner::Stack(_) = owner {
             let path = format!("{METADATA_PREFIX}/{}/{storage_name}", owner.path_prefix());
@@ -214,15 +514,12 @@ impl StorageClient for StorageClientImpl {
         }
 
         // remove data
-        let keys = self
-            .list(owner, storage_name, "")
-            .await?
-            .into_iter()
-            .map(|o| o.key);
-
-        for key in keys {
-            let path = Self::create_path(owner, storage_name, &key);
-            self.bucket.delete_object(path).await?;
+        let failed = self.delete_many_unchecked(owner, storage_name, keys).await;
+        if let Some((key, e)) = failed.first() {
+            bail!(
+                "Failed to delete {} objects, including {key}: {e:?}",
+                failed.len()
+            );
         }
 
         Ok(())
@@ -234,15 +531,38 @@ impl StorageClient for StorageClientImpl {
         storage_name: &str,
         key: &str,
         writer: &mut (dyn AsyncWrite + Send + Sync + Unpin),
-    ) -> Result<()> {
+    ) -> Result<Option<String>> {
         if !self.contains_storage(owner, storage_name).await? {
             bail!("Storage not found")
         }
 
-        let mut wrapper = AsyncWriterWrapper { writer };
         let path = Self::create_path(owner, storage_name, key);
-        self.bucket.get_object_stream(path, &mut wrapper).await?;
-        Ok(())
+
+        // Streamed downloads don't expose the response headers, so the
+        // content type is looked up first. If the object is replaced in
+        // between, it can belong to the previous version.
+        let (head, _) = self
+            .retry
+            .run("head", || self.bucket.head_object(&path))
+            .await?;
+
+        let mut wrapper = AsyncWriterWrapper { writer, written: 0 };
+        let mut attempt = 1;
+        loop {
+            match self.bucket.get_object_stream(&path, &mut wrapper).await {
+                // Once part of the object was written, retrying would write
+                // it again
+                Err(e) if wrapper.written == 0 && self.retry.backoff("get", attempt, &e).await => {
+                    attempt += 1
+                }
+                result => {
+                    result?;
+                    break;
+                }
+            }
+        }
+
+        Ok(head.content_type)
     }
 
     async fn put(
@@ -251,18 +571,110 @@ impl StorageClient for StorageClientImpl {
         storage_name: &str,
         key: &str,
         reader: &mut (dyn AsyncRead + Send + Sync + Unpin),
+        content_type: Option<&str>,
     ) -> Result<()> {
         if !self.contains_storage(owner, storage_name).await? {
             bail!("Storage not found")
         }
 
-        let mut wrapper = AsyncReaderWrapper { reader };
         let path = Self::create_path(owner, storage_name, key);
-
-        self.bucket.put_object_stream(&mut wrapper, path).await?;
+        let content_type = content_type.unwrap_or(DEFAULT_CONTENT_TYPE);
+
+        let mut buffer = vec![];
+        (&mut *reader)
+            .take(RETRYABLE_PUT_SIZE + 1)
+            .read_to_end(&mut buffer)
+            .await?;
+
+        if buffer.len() as u64 <= RETRYABLE_PUT_SIZE {
+            self.retry
+                .run("put", || {
+                    self.bucket
+                        .put_object_with_content_type(&path, &buffer, content_type)
+                })
+                .await?;
+        } else {
+            let mut wrapper = buffer.as_slice().chain(AsyncReaderWrapper { reader });
+            self.bucket
+                .put_object_stream_with_content_type(&mut wrapper, path, content_type)
+                .await?;
+        }
         Ok(())
     }
 
+    async fn head(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<Option<ObjectMetadata>> {
+        if !self.contains_storage(owner, storage_name).await? {
+            bail!("Storage not found")
+        }
+
+        let path = Self::create_path(owner, storage_name, key);
+
+        match self
+            .retry
+            .run("head", || self.bucket.head_object(&path))
+            .await
+        {
+            Ok((_, HTTP_NOT_FOUND)) | Err(S3Error::Http(HTTP_NOT_FOUND, _)) => Ok(None),
+            Ok((head, _)) => Ok(Some(Self::create_head_object(key, head))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_etag(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<Option<String>> {
+        if !self.contains_storage(owner, storage_name).await? {
+            bail!("Storage not found")
+        }
+
+        let path = Self::create_path(owner, storage_name, key);
+
+        match self.bucket.head_object(path).await {
+            Ok((_, HTTP_NOT_FOUND)) | Err(S3Error::Http(HTTP_NOT_FOUND, _)) => Ok(None),
+            Ok((head, _)) => Ok(head.e_tag),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put_if_match(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        etag: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        // Not every backend supports conditional writes, so the ETag is
+        // checked here as well. On such backends, a write landing between
+        // the check and our own write can still be lost.
+        match self.get_etag(owner, storage_name, key).await? {
+            Some(current) if Self::etags_match(&current, etag) => (),
+            _ => return Err(ETagMismatch.into()),
+        }
+
+        let mut bucket = self.bucket.clone();
+        bucket.add_header("If-Match", etag);
+
+        let path = Self::create_path(owner, storage_name, key);
+
+        match bucket.put_object(path, data).await {
+            Ok(response) if response.status_code() == HTTP_PRECONDITION_FAILED => {
+                Err(ETagMismatch.into())
+            }
+            Err(S3Error::Http(HTTP_PRECONDITION_FAILED, _)) => Err(ETagMismatch.into()),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     async fn delete(&self, owner: Owner, storage_name: &str, key: &str) -> Result<()> {
         if !self.contains_storage(owner, storage_name).await? {
             bail!("Storage not found")
@@ -270,11 +682,26 @@ impl StorageClient for StorageClientImpl {
 
         let path = Self::create_path(owner, storage_name, key);
 
-        self.bucket.delete_object(path).await?;
+        self.retry
+            .run("delete", || self.bucket.delete_object(&path))
+            .await?;
 
         Ok(())
     }
 
+    async fn delete_many(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        keys: Vec<String>,
+    ) -> Result<Vec<(String, Error)>> {
+        if !self.contains_storage(owner, storage_name).await? {
+            bail!("Storage not found")
+        }
+
+        Ok(self.delete_many_unchecked(owner, storage_name, keys).await)
+    }
+
     async fn list(&self, owner: Owner, storage_name: &str, prefix: &str) -> Result<Vec<Object>> {
         if !self.contains_storage(owner, storage_name).await? {
             bail!("Storage not found")
@@ -282,65 +709,162 @@ impl StorageClient for StorageClientImpl {
 
         let prefix = Self::create_path(owner, storage_name, prefix);
 
-        let resp = self.bucket.list(prefix, None).await?;
+        let resp = self
+            .retry
+            .run("list", || self.bucket.list(prefix.clone(), None))
+            .await?;
 
-        let objects = resp[0]
-            .contents
-            .iter()
-            .map(StorageClientImpl::create_object)
-            .collect::<Vec<_>>();
+        Ok(Self::create_objects(
+            resp.into_iter().map(|page| page.contents),
+        ))
+    }
 
-        Ok(objects)
+    async fn presign_get(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        expiry: Duration,
+    ) -> Result<String> {
+        let expiry_secs = Self::presign_expiry_secs(expiry)?;
+
+        if !self.contains_storage(owner, storage_name).await? {
+            bail!("Storage not found")
+        }
+
+        let path = Self::create_path(owner, storage_name, key);
+        Ok(self.bucket.presign_get(path, expiry_secs, None)?)
+    }
+
+    async fn presign_put(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        key: &str,
+        expiry: Duration,
+    ) -> Result<String> {
+        let expiry_secs = Self::presign_expiry_secs(expiry)?;
+
+        if !self.contains_storage(owner, storage_name).await? {
+            bail!("Storage not found")
+        }
+
+        let path = Self::create_path(owner, storage_name, key);
+        Ok(self.bucket.presign_put(path, expiry_secs, None)?)
+    }
+
+    async fn copy(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        src_key: &str,
+        dst_key: &str,
+    ) -> Result<()> {
+        if self.get_etag(owner, storage_name, src_key).await?.is_none() {
+            return Err(ObjectNotFound.into());
+        }
+
+        let src_path = Self::create_path(owner, storage_name, src_key);
+        let dst_path = Self::create_path(owner, storage_name, dst_key);
+
+        match self.bucket.copy_object_internal(&src_path, &dst_path).await {
+            Ok(HTTP_NOT_IMPLEMENTED) | Err(S3Error::Http(HTTP_NOT_IMPLEMENTED, _)) => {
+                warn!(
+                    "Storage backend doesn't support copying objects, \
+                    streaming {src_path} to {dst_path} instead"
+                );
+                self.stream_copy(&src_path, &dst_path).await
+            }
+            Ok(HTTP_NOT_FOUND) | Err(S3Error::Http(HTTP_NOT_FOUND, _)) => {
+                Err(ObjectNotFound.into())
+            }
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn rename(
+        &self,
+        owner: Owner,
+        storage_name: &str,
+        src_key: &str,
+        dst_key: &str,
+    ) -> Result<()> {
+        if src_key == dst_key {
+            return match self.get_etag(owner, storage_name, src_key).await? {
+                Some(_) => Ok(()),
+                None => Err(ObjectNotFound.into()),
+            };
+        }
+
+        self.copy(owner, storage_name, src_key, dst_key).await?;
+        self.delete(owner, storage_name, src_key).await
+    }
+}
+
+async fn probe_storage_backend(client: &dyn StorageClient) -> anyhow::Result<()> {
+    // This call will not succeed unless the bucket is made successfully.
+    let mut a = vec![];
+    match client
+        .get(Owner::User(StackOwner::Solana([0u8; 32])), "", "", &mut a)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) if e.to_string().contains("HTTP 404") => Ok(()),
+        Err(e) => Err(e),
     }
 }
 
 async fn ensure_storage_backend_is_healthy(
     client: &dyn StorageClient,
-    max_try_count: u32,
+    config: &HealthCheckConfig,
 ) -> anyhow::Result<()> {
     #[tailcall::tailcall]
     async fn helper(
         client: &dyn StorageClient,
         try_count: u32,
-        max_try_count: u32,
+        config: &HealthCheckConfig,
     ) -> anyhow::Result<()> {
-        // This call will not succeed unless the bucket is made successfully.
-
-        let mut a = vec![];
-        match client
-            .get(Owner::User(StackOwner::Solana([0u8; 32])), "", "", &mut a)
-            .await
-        {
+        match probe_storage_backend(client).await {
             Ok(_) => Ok(()),
-            Err(e) if e.to_string().contains("HTTP 404") => Ok(()),
 
-            Err(e) if try_count < max_try_count => {
+            Err(e) if try_count < config.max_tries => {
                 warn!("Failed to storage client due to: {e:?}");
-                sleep(Duration::from_millis(
-                    (1.5_f64.powf(try_count as f64) * 1000.0).round() as u64,
-                ))
-                .await;
-                helper(client, try_count + 1, max_try_count)
+                sleep(config.delay(try_count)).await;
+                helper(client, try_count + 1, config)
             }
             Err(e) => bail!(e),
         }
     }
 
-    helper(client, 0, max_try_count).await
+    helper(client, 0, config).await
 }
 
 pub async fn start(config: &StorageConfig) -> Result<Box<dyn StorageManager>> {
-    let (inner, config) = match (&config.external, &config.internal) {
-        (Some(ext_config), None) => (None, ext_config.clone()),
-        (None, Some(int_config)) => {
+    let health_check = config.health_check.clone().unwrap_or_default();
+    let retry = config.retry.clone().unwrap_or_default();
+    let (inner, config) = match (&config.external, &config.internal, &config.filesystem) {
+        (Some(ext_config), None, None) => (None, ext_config.clone()),
+        (None, Some(int_config), None) => {
             let (runner, config) = storage_embedded_juicefs::start(int_config).await?;
             (Some(runner), config)
         }
-        _ => bail!("Exactly one of internal or external storage config should be provided"),
+        (None, None, Some(fs_config)) => return filesystem::start(fs_config).await,
+        _ => bail!(
+            "Exactly one of internal, external or filesystem storage config should be provided"
+        ),
     };
 
-    let storage_manager = Box::new(StorageManagerImpl { inner, config });
-    ensure_storage_backend_is_healthy(storage_manager.make_client().unwrap().as_ref(), 5).await?;
+    let storage_manager = Box::new(StorageManagerImpl {
+        inner,
+        config,
+        retry,
+    });
+    ensure_storage_backend_is_healthy(
+        storage_manager.make_client().unwrap().as_ref(),
+        &health_check,
+    )
+    .await?;
 
     Ok(storage_manager)
 }
@@ -363,7 +887,8 @@ impl<'a> AsyncRead for AsyncReaderWrapper<'a> {
 
 pin_project! {
     struct AsyncWriterWrapper<'a>{
-        writer: &'a mut (dyn AsyncWrite + Send + Sync + Unpin)
+        writer: &'a mut (dyn AsyncWrite + Send + Sync + Unpin),
+        written: usize,
     }
 }
 
@@ -373,7 +898,12 @@ impl<'a> AsyncWrite for AsyncWriterWrapper<'a> {
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> std::task::Poll<std::result::Result<usize, std::io::Error>> {
-        Pin::new(self.project().writer).poll_write(cx, buf)
+        let this = self.project();
+        let result = Pin::new(this.writer).poll_write(cx, buf);
+        if let std::task::Poll::Ready(Ok(n)) = result {
+            *this.written += n;
+        }
+        result
     }
 
     fn poll_flush(
@@ -404,7 +934,7 @@ impl Deref for DeleteStorage {
 #[cfg(test)]
 mod test {
     use mu_common::serde_support::{IpOrHostname, TcpPortAddress};
-    use storage_embedded_juicefs::StorageInfo;
+    use storage_embedded_juicefs::{AuthConfig, Region, StorageInfo};
 
     use super::*;
 
@@ -425,6 +955,9 @@ mod test {
         let conf = StorageConfig {
             external: None,
             internal: Some(internal_conf),
+            filesystem: None,
+            health_check: None,
+            retry: None,
         };
         start(&conf).await
     }
@@ -452,4 +985,362 @@ mod test {
 
         assert_eq!(insertion_storages, x);
     }
+
+    // Presigning happens locally, so this needs no running storage backend
+    fn offline_client() -> StorageClientImpl {
+        offline_client_with(
+            Region {
+                region: "mu".into(),
+                endpoint: Some("http://127.0.0.1:9015".into()),
+            },
+            true,
+        )
+        .unwrap()
+    }
+
+    fn offline_client_with(region: Region, path_style: bool) -> Result<StorageClientImpl> {
+        StorageClientImpl::new(
+            &LiveStorageConfig {
+                auth_config: AuthConfig {
+                    access_key: Some("access".into()),
+                    secret_key: Some("secret".into()),
+                    security_token: None,
+                    session_token: None,
+                    profile: None,
+                },
+                region,
+                bucket_name: "bucket".into(),
+                path_style,
+            },
+            RetryConfig::default(),
+        )
+    }
+
+    // Users' storages always exist, so no request is made to check them
+    const USER: Owner = Owner::User(StackOwner::Solana([1; 32]));
+
+    #[tokio::test]
+    async fn presigned_urls_point_at_the_object() {
+        let client = offline_client();
+
+        let get_url = client
+            .presign_get(USER, "files", "a/b.txt", Duration::from_secs(600))
+            .await
+            .unwrap();
+        let put_url = client
+            .presign_put(USER, "files", "a/b.txt", Duration::from_secs(600))
+            .await
+            .unwrap();
+
+        for url in [get_url, put_url] {
+            assert!(url.starts_with("http://127.0.0.1:9015/bucket/"), "{url}");
+            assert!(url.contains("/files/a/b.txt?"), "{url}");
+            assert!(url.contains("X-Amz-Expires=600"), "{url}");
+        }
+    }
+
+    async fn presigned_get_url(client: &StorageClientImpl) -> String {
+        client
+            .presign_get(USER, "files", "a", Duration::from_secs(600))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn bucket_addressing_style_is_configurable() {
+        let region = || Region {
+            region: "mu".into(),
+            endpoint: Some("http://storage.local:9015".into()),
+        };
+
+        let path_style = offline_client_with(region(), true).unwrap();
+        let url = presigned_get_url(&path_style).await;
+        assert!(
+            url.starts_with("http://storage.local:9015/bucket/"),
+            "{url}"
+        );
+
+        let virtual_hosted = offline_client_with(region(), false).unwrap();
+        let url = presigned_get_url(&virtual_hosted).await;
+        assert!(
+            url.starts_with("http://bucket.storage.local:9015/"),
+            "{url}"
+        );
+        assert!(!url.contains("/bucket/"), "{url}");
+    }
+
+    #[tokio::test]
+    async fn well_known_regions_need_no_endpoint() {
+        let client = offline_client_with(
+            Region {
+                region: "eu-west-1".into(),
+                endpoint: None,
+            },
+            false,
+        )
+        .unwrap();
+
+        let url = presigned_get_url(&client).await;
+        assert!(
+            url.starts_with("https://bucket.s3-eu-west-1.amazonaws.com/")
+                || url.starts_with("https://bucket.s3.eu-west-1.amazonaws.com/"),
+            "{url}"
+        );
+
+        let unknown = Region {
+            region: "moon-1".into(),
+            endpoint: None,
+        };
+        assert!(offline_client_with(unknown, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn presigned_url_expiry_is_bounded() {
+        let client = offline_client();
+
+        for expiry in [Duration::ZERO, MAX_PRESIGN_EXPIRY + Duration::from_secs(1)] {
+            assert!(client
+                .presign_get(USER, "files", "a", expiry)
+                .await
+                .is_err());
+        }
+        assert!(client
+            .presign_get(USER, "files", "a", MAX_PRESIGN_EXPIRY)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs a running storage backend"]
+    async fn copy_within_storage() {
+        let manager = test_start().await.unwrap();
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        client
+            .put(OWNER, "s1", "src", &mut &b"contents"[..], None)
+            .await
+            .unwrap();
+        client.copy(OWNER, "s1", "src", "dst").await.unwrap();
+
+        let mut copied = vec![];
+        client.get(OWNER, "s1", "dst", &mut copied).await.unwrap();
+        assert_eq!(copied, b"contents");
+
+        client.rename(OWNER, "s1", "dst", "moved").await.unwrap();
+        assert!(client.get_etag(OWNER, "s1", "dst").await.unwrap().is_none());
+        assert!(client
+            .get_etag(OWNER, "s1", "moved")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs a running storage backend"]
+    async fn copy_fails_if_source_is_missing() {
+        let manager = test_start().await.unwrap();
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        let err = client
+            .copy(OWNER, "s1", "missing", "dst")
+            .await
+            .unwrap_err();
+        assert!(err.is::<ObjectNotFound>());
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs a running storage backend"]
+    async fn delete_many_deletes_every_key() {
+        let manager = test_start().await.unwrap();
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        let keys = (0..50).map(|i| format!("batch/{i}")).collect::<Vec<_>>();
+        for key in &keys {
+            client
+                .put(OWNER, "s1", key, &mut &b"contents"[..], None)
+                .await
+                .unwrap();
+        }
+
+        let failed = client.delete_many(OWNER, "s1", keys).await.unwrap();
+        assert!(failed.is_empty());
+        assert!(client.list(OWNER, "s1", "batch/").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs a running storage backend"]
+    async fn delete_many_ignores_missing_keys() {
+        let manager = test_start().await.unwrap();
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        client
+            .put(OWNER, "s1", "present", &mut &b"contents"[..], None)
+            .await
+            .unwrap();
+
+        let failed = client
+            .delete_many(OWNER, "s1", vec!["present".into(), "absent".into()])
+            .await
+            .unwrap();
+        assert!(failed.is_empty());
+        assert!(client
+            .get_etag(OWNER, "s1", "present")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs a running storage backend"]
+    async fn content_types_are_stored() {
+        let manager = test_start().await.unwrap();
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        client
+            .put(
+                OWNER,
+                "s1",
+                "images/logo.png",
+                &mut &b"\x89PNG"[..],
+                Some("image/png"),
+            )
+            .await
+            .unwrap();
+
+        let mut data = vec![];
+        let content_type = client
+            .get(OWNER, "s1", "images/logo.png", &mut data)
+            .await
+            .unwrap();
+        assert_eq!(data, b"\x89PNG");
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+
+        let listed = client.list(OWNER, "s1", "images/").await.unwrap();
+        assert_eq!(listed.len(), 1);
+
+        let object = client
+            .head(OWNER, "s1", "images/logo.png")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(object.content_type.as_deref(), Some("image/png"));
+    }
+
+    #[tokio::test]
+    #[ignore = "Needs a running storage backend"]
+    async fn head_returns_metadata_of_present_objects_only() {
+        let manager = test_start().await.unwrap();
+        let client = manager.make_client().unwrap();
+        client
+            .update_stack_storages(OWNER, vec![("s1", DeleteStorage(false))])
+            .await
+            .unwrap();
+
+        client
+            .put(
+                OWNER,
+                "s1",
+                "present",
+                &mut &b"contents"[..],
+                Some("text/plain"),
+            )
+            .await
+            .unwrap();
+
+        let metadata = client.head(OWNER, "s1", "present").await.unwrap().unwrap();
+        assert_eq!(metadata.object.key, "present");
+        assert_eq!(metadata.object.size, 8);
+        assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+        assert!(metadata.object.last_modified.is_some());
+
+        assert!(client.head(OWNER, "s1", "absent").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn head_dates_are_parsed() {
+        // 2023-03-01T12:34:56Z
+        assert_eq!(
+            StorageClientImpl::parse_http_date("Wed, 01 Mar 2023 12:34:56 GMT").unwrap(),
+            OffsetDateTime::from_unix_timestamp(1677674096).unwrap()
+        );
+        assert!(StorageClientImpl::parse_http_date("yesterday").is_err());
+    }
+
+    fn listed_object(last_modified: &str) -> s3::serde_types::Object {
+        listed_object_with_key(last_modified, "u!owner/files/a/b.txt")
+    }
+
+    fn listed_object_with_key(last_modified: &str, key: &str) -> s3::serde_types::Object {
+        s3::serde_types::Object {
+            last_modified: last_modified.into(),
+            e_tag: Some("\"etag\"".into()),
+            storage_class: Some("STANDARD".into()),
+            key: key.into(),
+            owner: None,
+            size: 42,
+        }
+    }
+
+    #[test]
+    fn listings_include_every_page() {
+        let page = |start: usize, count: usize| {
+            (start..start + count)
+                .map(|i| {
+                    listed_object_with_key(
+                        "2023-03-01T12:34:56.000Z",
+                        &format!("u!owner/files/{i}"),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let objects =
+            StorageClientImpl::create_objects([page(0, 1000), page(1000, 1000), page(2000, 5)]);
+
+        assert_eq!(objects.len(), 2005);
+        for (i, object) in objects.iter().enumerate() {
+            assert_eq!(object.key, i.to_string());
+        }
+    }
+
+    #[test]
+    fn listed_objects_include_metadata() {
+        let object = StorageClientImpl::create_object(&listed_object("2023-03-01T12:34:56.000Z"));
+
+        assert_eq!(object.key, "a/b.txt");
+        assert_eq!(object.size, 42);
+        // 2023-03-01T12:34:56Z
+        assert_eq!(
+            object.last_modified,
+            Some(OffsetDateTime::from_unix_timestamp(1677674096).unwrap())
+        );
+    }
+
+    #[test]
+    fn invalid_last_modified_dates_are_ignored() {
+        let object = StorageClientImpl::create_object(&listed_object("yesterday"));
+
+        assert_eq!(object.key, "a/b.txt");
+        assert_eq!(object.last_modified, None);
+    }
 }
@@ -0,0 +1,187 @@
+use std::{future::Future, io::ErrorKind, time::Duration};
+
+use log::warn;
+use s3::error::S3Error;
+use serde::Deserialize;
+use tokio::time::sleep;
+
+/// Controls how storage operations are retried after transient failures,
+/// such as timeouts, dropped connections or 5xx responses. Permanent
+/// failures (a missing object, denied access, ...) are never retried.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct RetryConfig {
+    /// How many times an operation is attempted in total, 1 disables retries.
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub backoff_factor: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 100,
+            backoff_factor: 2.0,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay before the attempt following the `attempt`th one, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay_ms =
+            self.backoff_factor.powf(attempt.saturating_sub(1) as f64) * self.base_delay_ms as f64;
+        Duration::from_millis(delay_ms.round() as u64)
+    }
+
+    /// Waits before retrying `operation` if it's worth retrying after its
+    /// `attempt`th attempt failed with `error`, returning whether it is.
+    pub(crate) async fn backoff(&self, operation: &str, attempt: u32, error: &S3Error) -> bool {
+        if attempt >= self.max_attempts || !is_transient(error) {
+            return false;
+        }
+
+        warn!(
+            "Storage {operation} failed (attempt {attempt} of {}), retrying: {error}",
+            self.max_attempts
+        );
+        sleep(self.delay(attempt)).await;
+        true
+    }
+
+    /// Runs `operation` until it succeeds, fails permanently or runs out of
+    /// attempts.
+    pub(crate) async fn run<T, F, Fut>(&self, operation: &str, mut f: F) -> Result<T, S3Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, S3Error>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(e) if self.backoff(operation, attempt, &e).await => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(error: &S3Error) -> bool {
+    match error {
+        // Request timeout, too many requests and server errors
+        S3Error::Http(status, _) => matches!(*status, 408 | 429 | 500..=599),
+        S3Error::Reqwest(e) => e.is_timeout() || e.is_connect(),
+        S3Error::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut
+                | ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn fast_retries() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay_ms: 1,
+            backoff_factor: 2.0,
+        }
+    }
+
+    // Fails with `error` the first `failures` times it's called
+    struct FlakyBucket {
+        calls: AtomicU32,
+        failures: u32,
+        error: fn() -> S3Error,
+    }
+
+    impl FlakyBucket {
+        fn new(failures: u32, error: fn() -> S3Error) -> Self {
+            Self {
+                calls: AtomicU32::new(0),
+                failures,
+                error,
+            }
+        }
+
+        async fn get_object(&self) -> Result<&'static [u8], S3Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err((self.error)())
+            } else {
+                Ok(b"contents")
+            }
+        }
+
+        fn calls(&self) -> u32 {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    fn unavailable() -> S3Error {
+        S3Error::Http(503, "Service Unavailable".into())
+    }
+
+    fn connection_reset() -> S3Error {
+        S3Error::Io(std::io::Error::from(ErrorKind::ConnectionReset))
+    }
+
+    fn not_found() -> S3Error {
+        S3Error::Http(404, "Not Found".into())
+    }
+
+    fn forbidden() -> S3Error {
+        S3Error::Http(403, "Forbidden".into())
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried_until_success() {
+        for error in [unavailable, connection_reset] {
+            let bucket = FlakyBucket::new(2, error);
+
+            let result = fast_retries().run("get", || bucket.get_object()).await;
+
+            assert_eq!(result.unwrap(), b"contents");
+            assert_eq!(bucket.calls(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_max_attempts() {
+        let bucket = FlakyBucket::new(5, unavailable);
+
+        let result = fast_retries().run("get", || bucket.get_object()).await;
+
+        assert!(matches!(result, Err(S3Error::Http(503, _))));
+        assert_eq!(bucket.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried() {
+        for error in [not_found, forbidden] {
+            let bucket = FlakyBucket::new(2, error);
+
+            let result = fast_retries().run("get", || bucket.get_object()).await;
+
+            assert!(result.is_err());
+            assert_eq!(bucket.calls(), 1);
+        }
+    }
+
+    #[test]
+    fn delays_grow_with_each_attempt() {
+        let config = RetryConfig::default();
+        assert_eq!(config.delay(1), Duration::from_millis(100));
+        assert_eq!(config.delay(2), Duration::from_millis(200));
+        assert_eq!(config.delay(3), Duration::from_millis(400));
+    }
+}
@@ -1,5 +1,5 @@
 use std::process::Stdio;
-use std::{env, os::unix::prelude::PermissionsExt, path::PathBuf, process, vec};
+use std::{env, path::PathBuf, process, vec};
 
 use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
@@ -7,12 +7,11 @@ use base64::Engine;
 use dyn_clonable::clonable;
 use log::error;
 use mailbox_processor::callback::CallbackMailboxProcessor;
-use mu_common::serde_support::TcpPortAddress;
+use mu_common::{embedded_executable::extract_embedded_executable, serde_support::TcpPortAddress};
 use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
 use rust_embed::RustEmbed;
 use serde::Deserialize;
-use tokio::{fs::File, io::AsyncWriteExt};
 
 const ACCESS_KEY: &str = "admin";
 const BUCKET_NAME: &str = "mu-default";
@@ -29,7 +28,9 @@ pub struct AuthConfig {
 #[derive(Clone, Debug, Deserialize)]
 pub struct Region {
     pub region: String,
-    pub endpoint: String,
+    /// Needed for anything but AWS S3, whose well-known regions (e.g.
+    /// `eu-west-1`) have their endpoints built in.
+    pub endpoint: Option<String>,
 }
 
 #[derive(Clone, Deserialize, Debug)]
@@ -37,6 +38,15 @@ pub struct LiveStorageConfig {
     pub auth_config: AuthConfig,
     pub region: Region,
     pub bucket_name: String,
+    /// Whether buckets are addressed as `https://endpoint/bucket` rather than
+    /// `https://bucket.endpoint`. Most self-hosted S3 implementations need
+    /// this, while AWS S3 is moving away from it.
+    #[serde(default = "default_path_style")]
+    pub path_style: bool,
+}
+
+fn default_path_style() -> bool {
+    true
 }
 
 #[async_trait]
@@ -73,46 +83,14 @@ impl JuicefsRunner for JuicefsRunnerImpl {
 #[folder = "assets"]
 pub struct Assets;
 
-// TODO: move this in with db_embedded_tikv's version somewhere
-async fn check_and_extract_embedded_executable(name: &str) -> Result<PathBuf> {
-    let mut temp_address = env::temp_dir();
-    temp_address.push(name);
-
-    // TODO check checksum instead existing.
-    let file = if temp_address.exists() {
-        File::open(temp_address.as_path())
-            .await
-            .context("Failed to open temp file")?
-    } else {
-        let mut file = File::create(temp_address.as_path())
-            .await
-            .context("Failed to create temp file")?;
-
-        let tool = <Assets as RustEmbed>::get(name).context("Failed to get embedded asset")?;
-        let tool_bytes = tool.data;
-
-        file.write_all(&tool_bytes)
-            .await
-            .context("Failed to write embedded resource to temp file")?;
-
-        file.flush().await.context("Failed to flush temp file")?;
-
-        file
-    };
-
-    let mut perms = file
-        .metadata()
-        .await
-        .context("Failed to get temp file metadata")?
-        .permissions();
-
-    perms.set_mode(0o500);
-
-    file.set_permissions(perms)
-        .await
-        .context("Failed to set executable permission on temp file")?;
-
-    Ok(temp_address)
+fn check_and_extract_embedded_executable(name: &str) -> Result<PathBuf> {
+    let tool = <Assets as RustEmbed>::get(name).context("Failed to get embedded asset")?;
+    extract_embedded_executable(
+        &env::temp_dir(),
+        name,
+        &tool.data,
+        &tool.metadata.sha256_hash(),
+    )
 }
 
 #[derive(Deserialize)]
@@ -193,7 +171,6 @@ pub async fn start(
     let tag_name = env!("TAG_NAME");
 
     let juicefs_exe = check_and_extract_embedded_executable(&format!("juicefs-{tag_name}"))
-        .await
         .context("Failed to create juicefs executable")?;
 
     let args = generate_arguments(config);
@@ -234,9 +211,10 @@ pub async fn start(
         },
         region: Region {
             region: "us-east-1".to_owned(),
-            endpoint: format!("http://{}", config.storage.endpoint),
+            endpoint: Some(format!("http://{}", config.storage.endpoint)),
         },
         bucket_name: BUCKET_NAME.to_string(),
+        path_style: true,
     };
 
     Ok((Box::new(JuicefsRunnerImpl { mailbox }), live_storage_config))
@@ -67,15 +67,28 @@ mod greeting {
         let key = format!("{}!!{}", user_id.0, todo.title).into_bytes();
         let value = if todo.done { [1] } else { [0] };
         ctx.db().put("todos", key, value, false).unwrap();
-        let mut storage = ctx.storage();
-        for a in todo.attachments {
-            storage
-                .put(
-                    "todo-attachments",
-                    &format!("{}/{}/{}", user_id.0, todo.title, a.name),
-                    &STANDARD.decode(a.data).unwrap(),
+
+        let attachments = todo
+            .attachments
+            .into_iter()
+            .map(|a| {
+                (
+                    format!("{}/{}/{}", user_id.0, todo.title, a.name),
+                    STANDARD.decode(a.data).unwrap(),
                 )
-                .unwrap();
+            })
+            .collect::<Vec<_>>();
+        let objects = attachments
+            .iter()
+            .map(|(key, data)| (key.as_str(), data.as_slice()))
+            .collect::<Vec<_>>();
+
+        for result in ctx
+            .storage()
+            .put_many("todo-attachments", &objects)
+            .unwrap()
+        {
+            result.unwrap();
         }
     }
 }
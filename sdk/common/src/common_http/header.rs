@@ -4,6 +4,8 @@ use borsh::{BorshDeserialize, BorshSerialize};
 
 pub const AUTHORIZATION_HEADER: &str = "authorization";
 pub const CONTENT_TYPE_HEADER: &str = "content-type";
+pub const COOKIE_HEADER: &str = "cookie";
+pub const SET_COOKIE_HEADER: &str = "set-cookie";
 
 pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
 pub const STRING_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
@@ -1,3 +1,4 @@
+mod cookie;
 mod response;
 
 use std::{borrow::Cow, collections::HashMap};
@@ -5,13 +6,17 @@ use std::{borrow::Cow, collections::HashMap};
 use borsh::{BorshDeserialize, BorshSerialize};
 
 pub use crate::common_http::{Header, HttpMethod, Status};
+pub use cookie::{SameSite, SetCookie};
 pub use response::{Response, ResponseBuilder};
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct Request<'a> {
     pub method: HttpMethod,
+    /// The endpoint path template that matched this request, e.g. `/users/{id}`.
+    pub route_template: Cow<'a, str>,
     pub path_params: HashMap<Cow<'a, str>, Cow<'a, str>>,
-    pub query_params: HashMap<Cow<'a, str>, Cow<'a, str>>,
+    /// In the order they appear in the URL, keys may repeat.
+    pub query_params: Vec<(Cow<'a, str>, Cow<'a, str>)>,
     pub headers: Vec<Header<'a>>,
     pub body: Cow<'a, [u8]>,
 }
@@ -0,0 +1,106 @@
+use std::{borrow::Cow, fmt::Write, time::Duration};
+
+use crate::http_client::{header::SET_COOKIE_HEADER, Header};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Browsers only accept this on secure cookies.
+    None,
+}
+
+/// A cookie to send in a `Set-Cookie` header, see
+/// [`super::ResponseBuilder::set_cookie`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie<'a> {
+    pub name: Cow<'a, str>,
+    pub value: Cow<'a, str>,
+    pub path: Option<Cow<'a, str>>,
+    pub domain: Option<Cow<'a, str>>,
+    /// A zero max age removes the cookie from the client.
+    pub max_age: Option<Duration>,
+    pub http_only: bool,
+    pub secure: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl<'a> SetCookie<'a> {
+    /// A session cookie with no attributes.
+    pub fn new(name: impl Into<Cow<'a, str>>, value: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<Cow<'a, str>>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<Cow<'a, str>>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    // The name and value are written as given, so they must already be
+    // valid cookie octets (no spaces, semicolons, commas, etc.)
+    pub fn to_header(&self) -> Header<'static> {
+        let mut value = format!("{}={}", self.name, self.value);
+
+        if let Some(path) = &self.path {
+            let _ = write!(value, "; Path={path}");
+        }
+        if let Some(domain) = &self.domain {
+            let _ = write!(value, "; Domain={domain}");
+        }
+        if let Some(max_age) = self.max_age {
+            let _ = write!(value, "; Max-Age={}", max_age.as_secs());
+        }
+        if self.http_only {
+            value.push_str("; HttpOnly");
+        }
+        if self.secure {
+            value.push_str("; Secure");
+        }
+        if let Some(same_site) = self.same_site {
+            value.push_str(match same_site {
+                SameSite::Strict => "; SameSite=Strict",
+                SameSite::Lax => "; SameSite=Lax",
+                SameSite::None => "; SameSite=None",
+            });
+        }
+
+        Header {
+            name: Cow::Borrowed(SET_COOKIE_HEADER),
+            value: Cow::Owned(value),
+        }
+    }
+}
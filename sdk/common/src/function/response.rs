@@ -7,6 +7,8 @@ use crate::http_client::{
     Header, Status,
 };
 
+use super::SetCookie;
+
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct Response<'a> {
     pub status: Status,
@@ -19,11 +21,18 @@ impl<'a> Response<'a> {
     pub fn builder() -> ResponseBuilder<'a> {
         ResponseBuilder::default()
     }
+
+    /// Adds a `Set-Cookie` header, keeping any cookies already set.
+    pub fn set_cookie(&mut self, cookie: &SetCookie) {
+        self.headers.push(cookie.to_header());
+    }
 }
 
 pub struct ResponseBuilder<'a> {
     status: Status,
     headers: HashMap<Cow<'a, str>, Header<'a>>,
+    // Headers that may appear more than once, such as `Set-Cookie`
+    appended_headers: Vec<Header<'a>>,
 }
 
 impl<'a> ResponseBuilder<'a> {
@@ -31,6 +40,7 @@ impl<'a> ResponseBuilder<'a> {
         ResponseBuilder {
             status: Status::Ok,
             headers: HashMap::new(),
+            appended_headers: vec![],
         }
     }
 
@@ -67,24 +77,38 @@ impl<'a> ResponseBuilder<'a> {
         headers.into_iter().fold(self, Self::header)
     }
 
-    pub fn no_body(self) -> Response<'a> {
+    /// Adds a [`Header`] to response, keeping any headers with the same name.
+    pub fn append_header(mut self, header: Header<'a>) -> Self {
+        self.appended_headers.push(header);
+        self
+    }
+
+    pub fn set_cookie(self, cookie: &SetCookie) -> Self {
+        self.append_header(cookie.to_header())
+    }
+
+    fn build(self, body: Cow<'a, [u8]>) -> Response<'a> {
         Response {
             status: self.status,
-            headers: self.headers.into_values().collect(),
-            body: Cow::Borrowed(&[]),
+            headers: self
+                .headers
+                .into_values()
+                .chain(self.appended_headers)
+                .collect(),
+            body,
         }
     }
 
+    pub fn no_body(self) -> Response<'a> {
+        self.build(Cow::Borrowed(&[]))
+    }
+
     pub fn body_from_slice(mut self, slice: &'a [u8]) -> Response<'a> {
         if !self.has_content_type() {
             self = self.content_type(Cow::Borrowed(BINARY_CONTENT_TYPE));
         }
 
-        Response {
-            status: self.status,
-            headers: self.headers.into_values().collect(),
-            body: Cow::Borrowed(slice),
-        }
+        self.build(Cow::Borrowed(slice))
     }
 
     pub fn body_from_vec(mut self, vec: Vec<u8>) -> Response<'a> {
@@ -92,11 +116,7 @@ impl<'a> ResponseBuilder<'a> {
             self = self.content_type(Cow::Borrowed(BINARY_CONTENT_TYPE));
         }
 
-        Response {
-            status: self.status,
-            headers: self.headers.into_values().collect(),
-            body: Cow::Owned(vec),
-        }
+        self.build(Cow::Owned(vec))
     }
 
     pub fn body_from_string(mut self, string: String) -> Response<'a> {
@@ -104,11 +124,7 @@ impl<'a> ResponseBuilder<'a> {
             self = self.content_type(Cow::Borrowed(STRING_CONTENT_TYPE));
         }
 
-        Response {
-            status: self.status,
-            headers: self.headers.into_values().collect(),
-            body: Cow::Owned(string.as_bytes().to_vec()),
-        }
+        self.build(Cow::Owned(string.into_bytes()))
     }
 
     pub fn body_from_str(mut self, str: &'a str) -> Response<'a> {
@@ -116,11 +132,7 @@ impl<'a> ResponseBuilder<'a> {
             self = self.content_type(Cow::Borrowed(STRING_CONTENT_TYPE));
         }
 
-        Response {
-            status: self.status,
-            headers: self.headers.into_values().collect(),
-            body: Cow::Borrowed(str.as_bytes()),
-        }
+        self.build(Cow::Borrowed(str.as_bytes()))
     }
 }
 
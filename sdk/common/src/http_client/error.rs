@@ -36,6 +36,10 @@ pub enum Error {
     Body(String),
     Decode(String),
     Upgrade(String),
+    Timeout(String),
+    /// The response redirected more times than the request's non-zero
+    /// `max_redirects`.
+    TooManyRedirects(u32),
 }
 
 impl fmt::Display for Error {
@@ -47,6 +51,10 @@ impl fmt::Display for Error {
             Error::Decode(e) => f.write_fmt(format_args!("error decoding response body: {e:?}"))?,
             Error::Redirect(e) => f.write_fmt(format_args!("error following redirect {e:?}"))?,
             Error::Upgrade(e) => f.write_fmt(format_args!("error upgrading connection {e:?}"))?,
+            Error::Timeout(e) => f.write_fmt(format_args!("request timed out {e:?}"))?,
+            Error::TooManyRedirects(max) => f.write_fmt(format_args!(
+                "too many redirects, at most {max} are allowed"
+            ))?,
             Error::Status(ref status) => {
                 let prefix = if status.is_client_error() {
                     "HTTP status client error"
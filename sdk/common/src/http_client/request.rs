@@ -27,6 +27,14 @@ use borsh::{BorshDeserialize, BorshSerialize};
 
 use super::{Body, Header, HttpMethod, Url, Version};
 
+/// How long the runtime waits for a response before giving up, unless
+/// the request specifies otherwise.
+pub const DEFAULT_TIMEOUT_MILLIS: u64 = 30_000;
+
+/// How many redirects the runtime follows before giving up, unless
+/// the request specifies otherwise.
+pub const DEFAULT_MAX_REDIRECTS: u32 = 10;
+
 #[derive(BorshSerialize, BorshDeserialize, Clone)]
 pub struct Request<'a> {
     pub method: HttpMethod,
@@ -34,6 +42,12 @@ pub struct Request<'a> {
     pub headers: Vec<Header<'a>>,
     pub body: Body<'a>,
     pub version: Version,
+    /// Total time allowed for the request, from connecting until the
+    /// response body has been read.
+    pub timeout_millis: u64,
+    /// Zero disables following redirects, so redirect responses are
+    /// returned as they are.
+    pub max_redirects: u32,
 }
 
 impl<'a> Request<'a> {
@@ -46,6 +60,8 @@ impl<'a> Request<'a> {
             headers: vec![],
             body: Cow::Borrowed(&[]),
             version: Version::default(),
+            timeout_millis: DEFAULT_TIMEOUT_MILLIS,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
         }
     }
 
@@ -74,4 +90,6 @@ fn fmt_request_fields<'a, 'b>(
     f.field("method", &req.method)
         .field("url", &req.url)
         .field("headers", &req.headers)
+        .field("timeout_millis", &req.timeout_millis)
+        .field("max_redirects", &req.max_redirects)
 }
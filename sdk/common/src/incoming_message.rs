@@ -20,6 +20,7 @@ use storage::*;
 enum IncomingMessageKind {
     // Runtime messages
     ExecuteFunction = 1,
+    Handshake = 2,
 
     // DB Messages
     DbError = 1001,
@@ -30,19 +31,33 @@ enum IncomingMessageKind {
     TableKeyValueListResult = 1006,
     EmptyResult = 1007,
     CasResult = 1008,
+    CountResult = 1009,
+    BatchCasResult = 1010,
 
     // Storage messages
     StorageError = 2001,
     StorageGetResult = 2002,
     StorageEmptyResult = 2003,
     ObjectListResult = 2004,
+    StoragePutManyResult = 2005,
+    StorageETagResult = 2006,
+    StorageETagMismatch = 2007,
+    StoragePresignResult = 2008,
+    StorageHeadResult = 2009,
 
     // Http Client
     HttpResponse = 3001,
 }
 
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct Handshake {
+    pub protocol_version: u16,
+}
+
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 pub struct ExecuteFunction<'a> {
+    /// In its string form, e.g. `s_<base58 public key>`
+    pub stack_id: Cow<'a, str>,
     pub function: Cow<'a, str>,
     pub request: Request<'a>,
 }
@@ -53,6 +68,7 @@ pub type HttpResponse<'a> = Result<http_client::Response<'a>, http_client::error
 pub enum IncomingMessage<'a> {
     // Runtime messages
     ExecuteFunction(ExecuteFunction<'a>),
+    Handshake(Handshake),
 
     // DB messages
     DbError(DbError<'a>),
@@ -63,12 +79,19 @@ pub enum IncomingMessage<'a> {
     TableKeyValueListResult(TableKeyValueListResult<'a>),
     EmptyResult(EmptyResult),
     CasResult(CasResult<'a>),
+    CountResult(CountResult),
+    BatchCasResult(BatchCasResult<'a>),
 
     // Storage messages
     StorageError(StorageError<'a>),
     StorageGetResult(StorageGetResult<'a>),
     StorageEmptyResult(StorageEmptyResult),
     ObjectListResult(ObjectListResult<'a>),
+    StoragePutManyResult(StoragePutManyResult<'a>),
+    StorageETagResult(StorageETagResult<'a>),
+    StorageETagMismatch(StorageETagMismatch),
+    StoragePresignResult(StoragePresignResult<'a>),
+    StorageHeadResult(StorageHeadResult<'a>),
 
     // Http client
     HttpResponse(HttpResponse<'a>),
@@ -124,12 +147,23 @@ impl<'a> IncomingMessage<'a> {
                 TableKeyListResult,
                 TableKeyValueListResult,
                 CasResult,
+                BatchCasResult,
                 StorageError,
                 StorageGetResult,
                 ObjectListResult,
+                StoragePutManyResult,
+                StorageETagResult,
+                StoragePresignResult,
+                StorageHeadResult,
                 HttpResponse
             ] * 'static,
-            [EmptyResult, StorageEmptyResult]
+            [
+                Handshake,
+                EmptyResult,
+                CountResult,
+                StorageEmptyResult,
+                StorageETagMismatch
+            ]
         )
     }
 
@@ -139,6 +173,7 @@ impl<'a> IncomingMessage<'a> {
             writer,
             [
                 ExecuteFunction,
+                Handshake,
                 DbError,
                 SingleResult,
                 ListResult,
@@ -147,10 +182,17 @@ impl<'a> IncomingMessage<'a> {
                 TableKeyValueListResult,
                 EmptyResult,
                 CasResult,
+                CountResult,
+                BatchCasResult,
                 StorageError,
                 StorageGetResult,
                 StorageEmptyResult,
                 ObjectListResult,
+                StoragePutManyResult,
+                StorageETagResult,
+                StorageETagMismatch,
+                StoragePresignResult,
+                StorageHeadResult,
                 HttpResponse
             ]
         );
@@ -55,6 +55,16 @@ pub struct CasResult<'a> {
     pub is_swapped: bool,
 }
 
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct BatchCasResult<'a> {
+    pub results: Vec<CasResult<'a>>,
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct CountResult {
+    pub count: u64,
+}
+
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct DbError<'a> {
     pub error: Cow<'a, str>,
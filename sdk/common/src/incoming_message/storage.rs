@@ -1,4 +1,7 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
@@ -9,6 +12,20 @@ pub struct StorageEmptyResult;
 pub struct Object<'a> {
     pub key: Cow<'a, str>,
     pub size: u64,
+    /// Milliseconds since the Unix epoch, see [`Object::last_modified`].
+    pub last_modified_millis: Option<i64>,
+}
+
+impl<'a> Object<'a> {
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        let millis = self.last_modified_millis?;
+        let offset = Duration::from_millis(millis.unsigned_abs());
+        if millis >= 0 {
+            UNIX_EPOCH.checked_add(offset)
+        } else {
+            UNIX_EPOCH.checked_sub(offset)
+        }
+    }
 }
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct ObjectListResult<'a> {
@@ -23,4 +40,41 @@ pub struct StorageError<'a> {
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct StorageGetResult<'a> {
     pub data: Cow<'a, [u8]>,
+    pub content_type: Option<Cow<'a, str>>,
+}
+
+/// Outcome of a `StoragePutMany`, with one entry per object in request
+/// order: `None` if the object was stored, or the error that prevented it.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StoragePutManyResult<'a> {
+    pub errors: Vec<Option<Cow<'a, str>>>,
 }
+
+/// An object along with what listings leave out.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct ObjectMetadata<'a> {
+    pub object: Object<'a>,
+    pub content_type: Option<Cow<'a, str>>,
+}
+
+/// `None` if the object doesn't exist.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StorageHeadResult<'a> {
+    pub object: Option<ObjectMetadata<'a>>,
+}
+
+/// `None` if the object doesn't exist.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StorageETagResult<'a> {
+    pub etag: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StoragePresignResult<'a> {
+    pub url: Cow<'a, str>,
+}
+
+/// Sent in response to a `StoragePutIfMatch` when the object's ETag
+/// didn't match, in which case nothing was written.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StorageETagMismatch;
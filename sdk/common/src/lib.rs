@@ -5,3 +5,10 @@ pub mod incoming_message;
 pub mod outgoing_message;
 
 pub use function::*;
+
+/// Version of the message protocol spoken between the runtime and functions,
+/// checked by a handshake before each request. Bump this whenever messages
+/// change in a way older peers can't parse. New message kinds don't need a
+/// bump: kinds are numbered explicitly, and older functions never send them
+/// or receive their results.
+pub const PROTOCOL_VERSION: u16 = 8;
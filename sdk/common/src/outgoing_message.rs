@@ -21,6 +21,10 @@ pub enum OutgoingMessageKind {
     FatalError = 1,
     FunctionResult = 2,
     Log = 3,
+    Handshake = 4,
+    ReportMetric = 5,
+    StartResponseStream = 6,
+    ResponseChunk = 7,
 
     // DB messages
     Put = 1001,
@@ -36,17 +40,31 @@ pub enum OutgoingMessageKind {
     BatchScan = 1011,
     BatchScanKeys = 1012,
     CompareAndSwap = 1013,
+    CountByPrefix = 1014,
+    PutWithTtl = 1016,
+    BatchCompareAndSwap = 1017,
 
     // Storage messages
     StoragePut = 2001,
     StorageGet = 2002,
     StorageDelete = 2003,
     StorageList = 2004,
+    StoragePutMany = 2005,
+    StorageGetETag = 2006,
+    StoragePutIfMatch = 2007,
+    StoragePresign = 2008,
+    StorageHead = 2009,
 
     // Http Client
     HttpRequest = 3001,
 }
 
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct Handshake<'a> {
+    pub protocol_version: u16,
+    pub sdk_version: Cow<'a, str>,
+}
+
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 pub struct FatalError<'a> {
     pub error: Cow<'a, str>,
@@ -57,12 +75,31 @@ pub struct FunctionResult<'a> {
     pub response: Response<'a>,
 }
 
+/// Starts a streamed response. The response's body is ignored; it's sent
+/// afterwards in [`ResponseChunk`]s, and ends when the function writes its
+/// [`FunctionResult`], whose response is then discarded.
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct StartResponseStream<'a> {
+    pub response: Response<'a>,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct ResponseChunk<'a> {
+    pub data: Cow<'a, [u8]>,
+}
+
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 pub struct Log<'a> {
     pub body: Cow<'a, str>,
     pub level: LogLevel,
 }
 
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct ReportMetric<'a> {
+    pub name: Cow<'a, str>,
+    pub value: u64,
+}
+
 #[repr(u8)]
 #[derive(Debug, FromPrimitive, BorshDeserialize, BorshSerialize)]
 pub enum LogLevel {
@@ -79,6 +116,10 @@ pub enum OutgoingMessage<'a> {
     FatalError(FatalError<'a>),
     FunctionResult(FunctionResult<'a>),
     Log(Log<'a>),
+    Handshake(Handshake<'a>),
+    ReportMetric(ReportMetric<'a>),
+    StartResponseStream(StartResponseStream<'a>),
+    ResponseChunk(ResponseChunk<'a>),
 
     // DB messages
     Put(Put<'a>),
@@ -94,12 +135,20 @@ pub enum OutgoingMessage<'a> {
     BatchScan(BatchScan<'a>),
     BatchScanKeys(BatchScanKeys<'a>),
     CompareAndSwap(CompareAndSwap<'a>),
+    CountByPrefix(CountByPrefix<'a>),
+    PutWithTtl(PutWithTtl<'a>),
+    BatchCompareAndSwap(BatchCompareAndSwap<'a>),
 
     // Storage messages
     StoragePut(StoragePut<'a>),
     StorageGet(StorageGet<'a>),
     StorageDelete(StorageDelete<'a>),
     StorageList(StorageList<'a>),
+    StoragePutMany(StoragePutMany<'a>),
+    StorageGetETag(StorageGetETag<'a>),
+    StoragePutIfMatch(StoragePutIfMatch<'a>),
+    StoragePresign(StoragePresign<'a>),
+    StorageHead(StorageHead<'a>),
 
     // Http Client
     HttpRequest(HttpRequest<'a>),
@@ -145,6 +194,10 @@ impl<'a> OutgoingMessage<'a> {
                 FatalError,
                 FunctionResult,
                 Log,
+                Handshake,
+                ReportMetric,
+                StartResponseStream,
+                ResponseChunk,
                 Put,
                 Get,
                 Delete,
@@ -158,10 +211,18 @@ impl<'a> OutgoingMessage<'a> {
                 BatchScan,
                 BatchScanKeys,
                 CompareAndSwap,
+                CountByPrefix,
+                PutWithTtl,
+                BatchCompareAndSwap,
                 StoragePut,
                 StorageGet,
                 StorageDelete,
                 StorageList,
+                StoragePutMany,
+                StorageGetETag,
+                StoragePutIfMatch,
+                StoragePresign,
+                StorageHead,
                 HttpRequest
             ]
         )
@@ -175,6 +236,10 @@ impl<'a> OutgoingMessage<'a> {
                 FatalError,
                 FunctionResult,
                 Log,
+                Handshake,
+                ReportMetric,
+                StartResponseStream,
+                ResponseChunk,
                 Put,
                 Get,
                 Delete,
@@ -188,10 +253,18 @@ impl<'a> OutgoingMessage<'a> {
                 BatchScan,
                 BatchScanKeys,
                 CompareAndSwap,
+                CountByPrefix,
+                PutWithTtl,
+                BatchCompareAndSwap,
                 StoragePut,
                 StorageGet,
                 StorageDelete,
                 StorageList,
+                StoragePutMany,
+                StorageGetETag,
+                StoragePutIfMatch,
+                StoragePresign,
+                StorageHead,
                 HttpRequest
             ]
         );
@@ -10,6 +10,15 @@ pub struct Put<'a> {
     pub is_atomic: bool,
 }
 
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct PutWithTtl<'a> {
+    pub table: Cow<'a, [u8]>,
+    pub key: Cow<'a, [u8]>,
+    pub value: Cow<'a, [u8]>,
+    pub is_atomic: bool,
+    pub ttl_millis: u64,
+}
+
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct Get<'a> {
     pub table: Cow<'a, [u8]>,
@@ -34,6 +43,7 @@ pub struct Scan<'a> {
     pub table: Cow<'a, [u8]>,
     pub key_prefix: Cow<'a, [u8]>,
     pub limit: u32,
+    pub reverse: bool,
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
@@ -41,6 +51,14 @@ pub struct ScanKeys<'a> {
     pub table: Cow<'a, [u8]>,
     pub key_prefix: Cow<'a, [u8]>,
     pub limit: u32,
+    pub reverse: bool,
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct CountByPrefix<'a> {
+    pub table: Cow<'a, [u8]>,
+    pub key_prefix: Cow<'a, [u8]>,
+    pub limit: u64,
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
@@ -51,6 +69,12 @@ pub struct CompareAndSwap<'a> {
     pub previous_value: Option<Cow<'a, [u8]>>,
 }
 
+/// Either all swaps are applied or none are.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct BatchCompareAndSwap<'a> {
+    pub ops: Vec<CompareAndSwap<'a>>,
+}
+
 type TableName<'a> = Cow<'a, [u8]>;
 type Key<'a> = Cow<'a, [u8]>;
 type Value<'a> = Cow<'a, [u8]>;
@@ -13,6 +13,60 @@ pub struct StoragePut<'a> {
     pub storage_name: Cow<'a, str>,
     pub key: Cow<'a, str>,
     pub reader: Cow<'a, [u8]>,
+    /// Stored with the object and returned when it's read or listed.
+    pub content_type: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StorageObject<'a> {
+    pub key: Cow<'a, str>,
+    pub data: Cow<'a, [u8]>,
+}
+
+/// Uploads several objects in one round-trip. The objects are uploaded
+/// independently, so some may be stored even if others fail.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StoragePutMany<'a> {
+    pub storage_name: Cow<'a, str>,
+    pub objects: Vec<StorageObject<'a>>,
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StorageGetETag<'a> {
+    pub storage_name: Cow<'a, str>,
+    pub key: Cow<'a, str>,
+}
+
+/// Looks up an object's metadata without downloading it.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StorageHead<'a> {
+    pub storage_name: Cow<'a, str>,
+    pub key: Cow<'a, str>,
+}
+
+/// Stores the object only if its current ETag equals `etag`.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StoragePutIfMatch<'a> {
+    pub storage_name: Cow<'a, str>,
+    pub key: Cow<'a, str>,
+    pub etag: Cow<'a, str>,
+    pub data: Cow<'a, [u8]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum PresignMethod {
+    Get,
+    Put,
+}
+
+/// Asks for a URL the object can be accessed at directly, without going
+/// through the runtime, for the next `expiry_secs` seconds.
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct StoragePresign<'a> {
+    pub storage_name: Cow<'a, str>,
+    pub key: Cow<'a, str>,
+    pub method: PresignMethod,
+    pub expiry_secs: u64,
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
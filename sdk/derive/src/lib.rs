@@ -3,9 +3,14 @@ extern crate proc_macro;
 extern crate quote;
 
 use proc_macro::TokenStream;
+use proc_macro2::Span;
 use proc_macro_error::{abort, proc_macro_error};
 use quote::ToTokens;
-use syn::{parse_macro_input, FnArg, Ident, Item, ItemFn, ItemMod, ReturnType};
+use syn::{
+    fold::{self, Fold},
+    parse_macro_input, FnArg, GenericParam, Ident, Item, ItemFn, ItemMod, Lifetime, LifetimeDef,
+    ReturnType, TypeReference,
+};
 
 type TokenStream2 = proc_macro2::TokenStream;
 
@@ -175,15 +180,27 @@ fn generate_invokers(r#mod: &FunctionsMod) -> Vec<TokenStream2> {
         let name = &f.sig.ident;
         let invoker_name = Ident::new(format!("_invoker_{name}").as_str(), name.span());
 
-        let (generics, context_lifetime) = {
-            match f.sig.generics.params.iter().find_map(|g| match g {
-                syn::GenericParam::Lifetime(l) => Some(l),
-                _ => None,
-            }) {
-                Some(l) => (f.sig.generics.clone(), l.clone()),
-                None => {
-                    abort!(f.sig.ident, "mu functions must include a lifetime parameter, used to receive the MuContext by reference")
-                }
+        let declared_lifetime = f.sig.generics.params.iter().find_map(|g| match g {
+            GenericParam::Lifetime(l) => Some(l.lifetime.clone()),
+            _ => None,
+        });
+
+        // Functions without a lifetime parameter get one in their invoker,
+        // so the context and request references can be tied together
+        let (generics, context_lifetime, mut elided_lifetimes) = match declared_lifetime {
+            Some(l) => (f.sig.generics.clone(), l, None),
+            None => {
+                let lifetime = Lifetime::new("'__mu_context", Span::call_site());
+                let mut generics = f.sig.generics.clone();
+                generics.params.insert(
+                    0,
+                    GenericParam::Lifetime(LifetimeDef::new(lifetime.clone())),
+                );
+                (
+                    generics,
+                    lifetime.clone(),
+                    Some(NameElidedLifetimes(lifetime)),
+                )
             }
         };
 
@@ -197,7 +214,10 @@ fn generate_invokers(r#mod: &FunctionsMod) -> Vec<TokenStream2> {
                     abort!(input, "self arguments are not supported in mu functions")
                 }
             };
-            let typ = pat_type.ty.as_ref();
+            let typ = match elided_lifetimes.as_mut() {
+                Some(folder) => folder.fold_type(pat_type.ty.as_ref().clone()),
+                None => pat_type.ty.as_ref().clone(),
+            };
 
             input_arg.push(quote!(
                 match <#typ as ::musdk::FromRequest<#context_lifetime>>::from_request(request) {
@@ -233,3 +253,24 @@ fn generate_invokers(r#mod: &FunctionsMod) -> Vec<TokenStream2> {
 
     result
 }
+
+// Names the elided lifetimes in argument types, which aren't allowed in
+// the invoker's where clause, after the synthesized context lifetime.
+struct NameElidedLifetimes(Lifetime);
+
+impl Fold for NameElidedLifetimes {
+    fn fold_type_reference(&mut self, mut r: TypeReference) -> TypeReference {
+        if r.lifetime.is_none() {
+            r.lifetime = Some(self.0.clone());
+        }
+        fold::fold_type_reference(self, r)
+    }
+
+    fn fold_lifetime(&mut self, l: Lifetime) -> Lifetime {
+        if l.ident == "_" {
+            self.0.clone()
+        } else {
+            l
+        }
+    }
+}
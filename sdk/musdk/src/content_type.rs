@@ -30,9 +30,66 @@ pub fn parse(header: &str) -> (Option<Mime>, Option<Charset>) {
     (mime, charset)
 }
 
+/// Splits a header value on `;`, ignoring the ones inside quoted strings.
+pub fn split_params(header: &str) -> impl Iterator<Item = &str> {
+    let mut in_quotes = false;
+    header
+        .split(move |c| {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            c == ';' && !in_quotes
+        })
+        .map(|s| s.trim())
+}
+
+/// Finds a `name=value` parameter in a header value such as
+/// `multipart/form-data; boundary=xyz`, with surrounding quotes removed.
+pub fn param<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    split_params(header).skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        if !k.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+
+        let v = v.trim();
+        Some(
+            v.strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(v),
+        )
+    })
+}
+
+/// Whether an `Accept` header value such as `application/json, */*;q=0.1`
+/// allows `mime`. Media ranges with `q=0` are treated as refusals.
+pub fn accepts(accept: &str, mime: &str) -> bool {
+    let (kind, _) = mime.split_once('/').unwrap_or((mime, ""));
+
+    accept.split(',').any(|range| {
+        let mut params = split_params(range);
+        let range = params.next().unwrap_or_default();
+
+        let refused = params.any(|p| {
+            p.split_once('=').map_or(false, |(k, v)| {
+                k.trim().eq_ignore_ascii_case("q") && v.trim().parse::<f32>() == Ok(0.0)
+            })
+        });
+        if refused {
+            return false;
+        }
+
+        range == "*/*"
+            || range.eq_ignore_ascii_case(mime)
+            || range
+                .strip_suffix("/*")
+                .map_or(false, |k| k.eq_ignore_ascii_case(kind))
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::content_type::parse;
+    use crate::content_type::{accepts, param, parse};
 
     #[test]
     fn test_parsing() {
@@ -56,4 +113,33 @@ mod tests {
         assert_eq!(parse(""), (None, None));
         assert_eq!(parse(";charset=utf-8"), (None, Some("utf-8")));
     }
+
+    #[test]
+    fn test_param() {
+        let header = "multipart/form-data; boundary=abc123";
+        assert_eq!(param(header, "boundary"), Some("abc123"));
+        assert_eq!(param(header, "BOUNDARY"), Some("abc123"));
+        assert_eq!(param(header, "charset"), None);
+
+        let header = r#"form-data; name="a;b"; filename="c.txt""#;
+        assert_eq!(param(header, "name"), Some("a;b"));
+        assert_eq!(param(header, "filename"), Some("c.txt"));
+
+        assert_eq!(param("multipart/form-data", "boundary"), None);
+    }
+
+    #[test]
+    fn test_accepts() {
+        let mime = "application/msgpack";
+        assert!(accepts("application/msgpack", mime));
+        assert!(accepts("APPLICATION/MSGPACK", mime));
+        assert!(accepts("application/json, application/msgpack;q=0.9", mime));
+        assert!(accepts("application/*", mime));
+        assert!(accepts("*/*", mime));
+
+        assert!(!accepts("application/json", mime));
+        assert!(!accepts("text/*", mime));
+        assert!(!accepts("application/msgpack;q=0, application/json", mime));
+        assert!(!accepts("", mime));
+    }
 }
@@ -1,4 +1,5 @@
 pub mod db;
+pub mod response_stream;
 pub mod storage;
 
 use std::{
@@ -10,8 +11,10 @@ use std::{
 
 use musdk_common::{
     incoming_message::IncomingMessage,
-    outgoing_message::{FatalError, FunctionResult, Log, LogLevel, OutgoingMessage},
-    Request, Response,
+    outgoing_message::{
+        FatalError, FunctionResult, Handshake, Log, LogLevel, OutgoingMessage, ReportMetric,
+    },
+    Request, Response, PROTOCOL_VERSION,
 };
 
 use crate::{
@@ -26,6 +29,10 @@ pub struct MuContext {
     stdout: Stdout,
 
     functions: HashMap<String, MuFunction>,
+    stack_id: Option<String>,
+    function_name: Option<String>,
+    route_template: Option<String>,
+    response_stream_started: bool,
 }
 
 impl MuContext {
@@ -39,9 +46,31 @@ impl MuContext {
             stdin: stdin(),
             stdout: stdout(),
             functions,
+            stack_id: None,
+            function_name: None,
+            route_template: None,
+            response_stream_started: false,
         }
     }
 
+    /// The stack the function being executed belongs to, in its string
+    /// form, e.g. `s_<base58 public key>`. Useful for namespacing keys in
+    /// shared storage.
+    pub fn stack_id(&self) -> Option<&str> {
+        self.stack_id.as_deref()
+    }
+
+    /// The name of the function being executed.
+    pub fn function_name(&self) -> Option<&str> {
+        self.function_name.as_deref()
+    }
+
+    /// The gateway endpoint path template (e.g. `/users/{id}`) that matched
+    /// the request currently being executed.
+    pub fn route_template(&self) -> Option<&str> {
+        self.route_template.as_deref()
+    }
+
     pub fn db(&mut self) -> db::DbHandle {
         db::DbHandle { context: self }
     }
@@ -50,12 +79,20 @@ impl MuContext {
         storage::StorageHandle { context: self }
     }
 
+    /// Sends the response body in chunks instead of returning it, see
+    /// [`response_stream::ResponseStreamHandle`].
+    pub fn response_stream(&mut self) -> response_stream::ResponseStreamHandle {
+        response_stream::ResponseStreamHandle { context: self }
+    }
+
     pub fn http_client(&mut self) -> HttpClient {
         HttpClient::new(self)
     }
 
     fn read_and_execute_function(&mut self) {
         fn helper(ctx: &mut MuContext) -> Result<()> {
+            ctx.handshake()?;
+
             let message = ctx.read_message()?;
             let IncomingMessage::ExecuteFunction(execute_function) = message else {
                  return Err(Error::UnexpectedFirstMessageKind)
@@ -66,6 +103,9 @@ impl MuContext {
                 .ok_or_else(|| Error::UnknownFunction(execute_function.function.into_owned()))?
                 .clone();
 
+            ctx.stack_id = Some(execute_function.stack_id.into_owned());
+            ctx.function_name = Some(execute_function.function.to_string());
+            ctx.route_template = Some(execute_function.request.route_template.to_string());
             let response = (*function)(ctx, &execute_function.request);
             let message = OutgoingMessage::FunctionResult(FunctionResult { response });
             ctx.write_message(message)?;
@@ -77,6 +117,27 @@ impl MuContext {
         }
     }
 
+    // We always report our own version, so the runtime can tell what went
+    // wrong if the two don't match.
+    fn handshake(&mut self) -> Result<()> {
+        let IncomingMessage::Handshake(handshake) = self.read_message()? else {
+            return Err(Error::MissingHandshake);
+        };
+
+        self.write_message(OutgoingMessage::Handshake(Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            sdk_version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+        }))?;
+
+        if handshake.protocol_version != PROTOCOL_VERSION {
+            return Err(Error::UnsupportedProtocolVersion(
+                handshake.protocol_version,
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn log<S: AsRef<str>>(&mut self, message: S, level: LogLevel) -> Result<()> {
         // TODO: make macros so the message doesn't have to be evaluated if its
         //       level is skipped
@@ -87,6 +148,18 @@ impl MuContext {
         self.write_message(message)
     }
 
+    /// Reports an application-defined usage metric, such as a premium
+    /// operation being performed. Values are summed per name, and the
+    /// totals can be read with `mu stack metrics`. Only a limited number of distinct names are accepted per request;
+    /// values for any more than that are dropped.
+    pub fn report_metric<S: AsRef<str>>(&mut self, name: S, value: u64) -> Result<()> {
+        let message = OutgoingMessage::ReportMetric(ReportMetric {
+            name: Cow::Borrowed(name.as_ref()),
+            value,
+        });
+        self.write_message(message)
+    }
+
     fn die(&mut self, error: Error) -> ! {
         let error_description = error.to_string();
         let write_result = self.write_message(OutgoingMessage::FatalError(FatalError {
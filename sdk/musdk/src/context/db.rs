@@ -1,10 +1,13 @@
-use std::{borrow::Cow, ops::Deref};
+use std::{borrow::Cow, ops::Deref, time::Duration};
 
 use musdk_common::{
     incoming_message::IncomingMessage as IM,
     outgoing_message::{db::*, OutgoingMessage as OM},
 };
 
+#[cfg(feature = "json")]
+use serde::{de::DeserializeOwned, Serialize};
+
 use crate::{Error, Result};
 
 type Blob = Vec<u8>;
@@ -176,6 +179,28 @@ impl<'a> DbHandle<'a> {
         from_empty_resp(resp, "Put")
     }
 
+    /// Same as `put`, but the key is deleted once `ttl` passes. Expired keys
+    /// disappear from reads right away. Writing the key again any other way,
+    /// or deleting it, makes it permanent.
+    pub fn put_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        table: &str,
+        key: K,
+        value: V,
+        is_atomic: bool,
+        ttl: Duration,
+    ) -> Result<()> {
+        let req = PutWithTtl {
+            table: Cow::Borrowed(table.as_bytes()),
+            key: Cow::Borrowed(key.as_ref()),
+            value: Cow::Borrowed(value.as_ref()),
+            is_atomic,
+            ttl_millis: ttl.as_millis() as u64,
+        };
+        let resp = self.request(OM::PutWithTtl(req))?;
+        from_empty_resp(resp, "PutWithTtl")
+    }
+
     pub fn get(&mut self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Value>> {
         let req = Get {
             table: Cow::Borrowed(table.as_bytes()),
@@ -204,36 +229,103 @@ impl<'a> DbHandle<'a> {
         from_empty_resp(resp, "DeleteByPrefix")
     }
 
+    /// Returns up to `limit` pairs in ascending key order.
     pub fn scan(
         &mut self,
         table: &str,
         key_prefix: impl AsRef<[u8]>,
         limit: u32,
+    ) -> Result<Vec<(Key, Value)>> {
+        self.scan_impl(table, key_prefix, limit, false)
+    }
+
+    /// Returns the last `limit` pairs in descending key order, which is
+    /// handy for "latest N entries" but slower on large tables.
+    pub fn scan_reverse(
+        &mut self,
+        table: &str,
+        key_prefix: impl AsRef<[u8]>,
+        limit: u32,
+    ) -> Result<Vec<(Key, Value)>> {
+        self.scan_impl(table, key_prefix, limit, true)
+    }
+
+    /// Same as [`Self::scan`], without the values.
+    pub fn scan_keys(
+        &mut self,
+        table: &str,
+        key_prefix: impl AsRef<[u8]>,
+        limit: u32,
+    ) -> Result<Vec<Key>> {
+        self.scan_keys_impl(table, key_prefix, limit, false)
+    }
+
+    /// Same as [`Self::scan_reverse`], without the values.
+    pub fn scan_keys_reverse(
+        &mut self,
+        table: &str,
+        key_prefix: impl AsRef<[u8]>,
+        limit: u32,
+    ) -> Result<Vec<Key>> {
+        self.scan_keys_impl(table, key_prefix, limit, true)
+    }
+
+    fn scan_impl(
+        &mut self,
+        table: &str,
+        key_prefix: impl AsRef<[u8]>,
+        limit: u32,
+        reverse: bool,
     ) -> Result<Vec<(Key, Value)>> {
         let req = Scan {
             table: Cow::Borrowed(table.as_bytes()),
             key_prefix: Cow::Borrowed(key_prefix.as_ref()),
             limit,
+            reverse,
         };
         let resp = self.request(OM::Scan(req))?;
         from_kv_pairs_resp(resp, "Scan")
     }
 
-    pub fn scan_keys(
+    fn scan_keys_impl(
         &mut self,
         table: &str,
         key_prefix: impl AsRef<[u8]>,
         limit: u32,
+        reverse: bool,
     ) -> Result<Vec<Key>> {
         let req = ScanKeys {
             table: Cow::Borrowed(table.as_bytes()),
             key_prefix: Cow::Borrowed(key_prefix.as_ref()),
             limit,
+            reverse,
         };
         let resp = self.request(OM::ScanKeys(req))?;
         Ok(from_list_resp(resp, "ScanKeys")?.map(Key::from).collect())
     }
 
+    /// Counts the keys starting with `key_prefix` without loading them, so
+    /// an empty prefix gives the size of the whole table. This still takes
+    /// time proportional to the number of keys, and stops counting at
+    /// `limit`, so a result equal to `limit` means "at least". The runtime
+    /// caps `limit` at 100,000.
+    pub fn count_by_prefix(
+        &mut self,
+        table: &str,
+        key_prefix: impl AsRef<[u8]>,
+        limit: u64,
+    ) -> Result<u64> {
+        let req = CountByPrefix {
+            table: Cow::Borrowed(table.as_bytes()),
+            key_prefix: Cow::Borrowed(key_prefix.as_ref()),
+            limit,
+        };
+        match self.request(OM::CountByPrefix(req))? {
+            IM::CountResult(x) => Ok(x.count),
+            left => resp_to_err(left, "CountByPrefix"),
+        }
+    }
+
     pub fn compare_and_swap<K: AsRef<[u8]>, V: AsRef<[u8]>, PV: AsRef<[u8]>>(
         &mut self,
         table: &str,
@@ -253,6 +345,80 @@ impl<'a> DbHandle<'a> {
             left => resp_to_err(left, "CompareAndSwap"),
         }
     }
+
+    /// Compares and swaps several keys as a group: if any key doesn't have
+    /// its expected previous value, none are swapped. Returns each key's
+    /// current value and whether it was swapped, in the same order as `ops`.
+    /// A key can only appear once in a batch.
+    ///
+    /// This is not atomic: keys are swapped one at a time, so other
+    /// functions may see part of a batch, and a batch that fails midway is
+    /// undone on a best-effort basis.
+    pub fn batch_compare_and_swap<
+        'b,
+        K: AsRef<[u8]> + 'b,
+        V: AsRef<[u8]> + 'b,
+        PV: AsRef<[u8]> + 'b,
+    >(
+        &mut self,
+        ops: impl IntoIterator<Item = &'b (&'b str, K, Option<PV>, V)>,
+    ) -> Result<Vec<(Option<Value>, bool)>> {
+        let req = BatchCompareAndSwap {
+            ops: ops
+                .into_iter()
+                .map(|(t, k, pv, v)| CompareAndSwap {
+                    table: Cow::Borrowed(t.as_bytes()),
+                    key: Cow::Borrowed(k.as_ref()),
+                    new_value: Cow::Borrowed(v.as_ref()),
+                    previous_value: pv.as_ref().map(|pv| Cow::Borrowed(pv.as_ref())),
+                })
+                .collect(),
+        };
+        let resp = self.request(OM::BatchCompareAndSwap(req))?;
+        match resp {
+            IM::BatchCasResult(x) => Ok(x
+                .results
+                .into_iter()
+                .map(|r| (r.previous_value.map(Value::from), r.is_swapped))
+                .collect()),
+            left => resp_to_err(left, "BatchCompareAndSwap"),
+        }
+    }
+}
+
+/// Typed access to values stored as JSON, on top of the byte API above.
+///
+/// # Optional
+///
+/// This requires the optional `json` feature enabled.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl<'a> DbHandle<'a> {
+    /// Same as `put`, with the value serialized to JSON.
+    pub fn put_json<K: AsRef<[u8]>, T: Serialize + ?Sized>(
+        &mut self,
+        table: &str,
+        key: K,
+        value: &T,
+        is_atomic: bool,
+    ) -> Result<()> {
+        let value =
+            serde_json::to_vec(value).map_err(|e| Error::DatabaseValueConversion(e.to_string()))?;
+        self.put(table, key, value, is_atomic)
+    }
+
+    /// Same as `get`, with the value deserialized from JSON. Values that
+    /// aren't valid JSON for `T` result in an error rather than `None`.
+    pub fn get_json<T: DeserializeOwned>(
+        &mut self,
+        table: &str,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<T>> {
+        self.get(table, key)?
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(|e| Error::DatabaseValueConversion(e.to_string()))
+    }
 }
 
 fn from_empty_resp(resp: IM, kind_name: &'static str) -> Result<()> {
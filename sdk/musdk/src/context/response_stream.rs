@@ -0,0 +1,51 @@
+use std::borrow::Cow;
+
+use musdk_common::{
+    outgoing_message::{OutgoingMessage as OM, ResponseChunk, StartResponseStream},
+    Response,
+};
+
+use crate::{Error, Result};
+
+/// Streams a response to the client in chunks, so large bodies never have
+/// to be held in memory all at once:
+///
+/// ```ignore
+/// let mut stream = ctx.response_stream();
+/// stream.start(Response::builder().content_type("text/csv".into()).no_body())?;
+/// for row in rows {
+///     stream.write(row.as_bytes())?;
+/// }
+/// ```
+///
+/// Once a stream is started, whatever the function returns is discarded.
+/// The runtime only buffers a few chunks, so writes block until the client
+/// catches up; chunks should be kept reasonably small (e.g. 64KB) since each
+/// one is copied in full between the function, runtime and gateway.
+pub struct ResponseStreamHandle<'a> {
+    pub(super) context: &'a mut super::MuContext,
+}
+
+impl<'a> ResponseStreamHandle<'a> {
+    /// Sends the response's status and headers. Its body is ignored.
+    pub fn start(&mut self, response: Response) -> Result<()> {
+        if self.context.response_stream_started {
+            return Err(Error::ResponseStreamAlreadyStarted);
+        }
+
+        self.context
+            .write_message(OM::StartResponseStream(StartResponseStream { response }))?;
+        self.context.response_stream_started = true;
+        Ok(())
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        if !self.context.response_stream_started {
+            return Err(Error::ResponseStreamNotStarted);
+        }
+
+        self.context.write_message(OM::ResponseChunk(ResponseChunk {
+            data: Cow::Borrowed(chunk),
+        }))
+    }
+}
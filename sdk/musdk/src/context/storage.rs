@@ -1,7 +1,10 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 
 use musdk_common::{
-    incoming_message::{storage::Object, IncomingMessage as IM},
+    incoming_message::{
+        storage::{Object, ObjectMetadata},
+        IncomingMessage as IM,
+    },
     outgoing_message::{storage::*, OutgoingMessage as OM},
 };
 
@@ -41,6 +44,16 @@ impl<'a> StorageHandle<'a> {
     }
 
     pub fn get(&mut self, storage_name: &str, key: &str) -> Result<Cow<[u8]>> {
+        self.get_with_content_type(storage_name, key)
+            .map(|(data, _)| data)
+    }
+
+    /// Returns the object along with the content type it was stored with.
+    pub fn get_with_content_type(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+    ) -> Result<(Cow<[u8]>, Option<String>)> {
         let req = StorageGet {
             storage_name: Cow::Borrowed(storage_name),
             key: Cow::Borrowed(key),
@@ -49,22 +62,186 @@ impl<'a> StorageHandle<'a> {
         let resp = self.request(OM::StorageGet(req))?;
 
         match resp {
-            IM::StorageGetResult(x) => Ok(x.data),
+            IM::StorageGetResult(x) => Ok((x.data, x.content_type.map(Cow::into_owned))),
             resp => resp_to_err(resp, "StorageGet"),
         }
     }
 
     pub fn put(&mut self, storage_name: &str, key: &str, data: &[u8]) -> Result<()> {
+        self.put_impl(storage_name, key, data, None)
+    }
+
+    /// Stores the object with a content type, which is returned by
+    /// `get_with_content_type` and `head`.
+    pub fn put_with_content_type(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+        data: &[u8],
+        content_type: &str,
+    ) -> Result<()> {
+        self.put_impl(storage_name, key, data, Some(content_type))
+    }
+
+    fn put_impl(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+        data: &[u8],
+        content_type: Option<&str>,
+    ) -> Result<()> {
         let req = StoragePut {
             storage_name: Cow::Borrowed(storage_name),
             key: Cow::Borrowed(key),
             reader: Cow::Borrowed(data),
+            content_type: content_type.map(Cow::Borrowed),
         };
 
         let resp = self.request(OM::StoragePut(req))?;
 
         from_empty_resp(resp, "StoragePut")
     }
+
+    /// Returns the object's size, content type and last modification date
+    /// without downloading it, or `None` if it doesn't exist.
+    pub fn head(&mut self, storage_name: &str, key: &str) -> Result<Option<ObjectMetadata>> {
+        let req = StorageHead {
+            storage_name: Cow::Borrowed(storage_name),
+            key: Cow::Borrowed(key),
+        };
+
+        let resp = self.request(OM::StorageHead(req))?;
+
+        match resp {
+            IM::StorageHeadResult(x) => Ok(x.object),
+            resp => resp_to_err(resp, "StorageHead"),
+        }
+    }
+
+    /// Returns the object's current ETag, or `None` if it doesn't exist.
+    pub fn get_etag(&mut self, storage_name: &str, key: &str) -> Result<Option<String>> {
+        let req = StorageGetETag {
+            storage_name: Cow::Borrowed(storage_name),
+            key: Cow::Borrowed(key),
+        };
+
+        let resp = self.request(OM::StorageGetETag(req))?;
+
+        match resp {
+            IM::StorageETagResult(x) => Ok(x.etag.map(Cow::into_owned)),
+            resp => resp_to_err(resp, "StorageGetETag"),
+        }
+    }
+
+    /// Stores the object only if its ETag still equals `etag`, as returned
+    /// by `get_etag`. Fails with `Error::StorageETagMismatch` if the object
+    /// was modified (or deleted) in the meantime, in which case nothing is
+    /// written and the caller can re-read the object and retry.
+    pub fn put_if_match(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+        etag: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        let req = StoragePutIfMatch {
+            storage_name: Cow::Borrowed(storage_name),
+            key: Cow::Borrowed(key),
+            etag: Cow::Borrowed(etag),
+            data: Cow::Borrowed(data),
+        };
+
+        let resp = self.request(OM::StoragePutIfMatch(req))?;
+
+        match resp {
+            IM::StorageETagMismatch(_) => Err(Error::StorageETagMismatch),
+            resp => from_empty_resp(resp, "StoragePutIfMatch"),
+        }
+    }
+
+    /// Returns a URL the object can be downloaded from directly, without
+    /// going through the function, until `expiry` has passed. Useful for
+    /// handing large objects to clients. S3 limits the expiry to 7 days.
+    pub fn presign_get(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+        expiry: Duration,
+    ) -> Result<String> {
+        self.presign(storage_name, key, PresignMethod::Get, expiry)
+    }
+
+    /// Like `presign_get`, but the URL accepts a `PUT` with the object's
+    /// new contents instead.
+    pub fn presign_put(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+        expiry: Duration,
+    ) -> Result<String> {
+        self.presign(storage_name, key, PresignMethod::Put, expiry)
+    }
+
+    fn presign(
+        &mut self,
+        storage_name: &str,
+        key: &str,
+        method: PresignMethod,
+        expiry: Duration,
+    ) -> Result<String> {
+        let req = StoragePresign {
+            storage_name: Cow::Borrowed(storage_name),
+            key: Cow::Borrowed(key),
+            method,
+            expiry_secs: expiry.as_secs(),
+        };
+
+        let resp = self.request(OM::StoragePresign(req))?;
+
+        match resp {
+            IM::StoragePresignResult(x) => Ok(x.url.into_owned()),
+            resp => resp_to_err(resp, "StoragePresign"),
+        }
+    }
+
+    /// Uploads several objects in a single request to the runtime, which
+    /// uploads them concurrently.
+    ///
+    /// Each object is stored independently; a failure doesn't roll back the
+    /// others. The outer result fails only if the request as a whole failed,
+    /// in which case none of the objects can be assumed to be stored.
+    /// Otherwise, the returned list has one entry per object, in the same
+    /// order as `objects`, telling whether that object was stored.
+    pub fn put_many(
+        &mut self,
+        storage_name: &str,
+        objects: &[(&str, &[u8])],
+    ) -> Result<Vec<Result<()>>> {
+        let req = StoragePutMany {
+            storage_name: Cow::Borrowed(storage_name),
+            objects: objects
+                .iter()
+                .map(|(key, data)| StorageObject {
+                    key: Cow::Borrowed(key),
+                    data: Cow::Borrowed(data),
+                })
+                .collect(),
+        };
+
+        let resp = self.request(OM::StoragePutMany(req))?;
+
+        match resp {
+            IM::StoragePutManyResult(x) if x.errors.len() == objects.len() => Ok(x
+                .errors
+                .into_iter()
+                .map(|e| match e {
+                    None => Ok(()),
+                    Some(e) => Err(Error::StorageError(e.into_owned())),
+                })
+                .collect()),
+            resp => resp_to_err(resp, "StoragePutMany"),
+        }
+    }
 }
 
 fn resp_to_err<T>(resp: IM, kind_name: &'static str) -> Result<T> {
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, ops::Deref};
+
+use musdk_common::{common_http::header::COOKIE_HEADER, Request};
+
+use crate::FromRequest;
+
+/// Cookies sent with the request, from all of its `Cookie` headers. If a
+/// name is repeated, the first value is kept, since clients send cookies
+/// with more specific paths first.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Cookies<'a>(HashMap<&'a str, &'a str>);
+
+impl<'a> Cookies<'a> {
+    /// Consumes wrapper and returns wrapped item
+    #[inline(always)]
+    pub fn into_inner(self) -> HashMap<&'a str, &'a str> {
+        self.0
+    }
+}
+
+impl<'a> FromRequest<'a> for Cookies<'a> {
+    type Error = ();
+
+    fn from_request(req: &'a Request) -> Result<Self, Self::Error> {
+        let mut cookies = HashMap::new();
+
+        for header in &req.headers {
+            if header.name.eq_ignore_ascii_case(COOKIE_HEADER) {
+                for (name, value) in parse(&header.value) {
+                    cookies.entry(name).or_insert(value);
+                }
+            }
+        }
+
+        Ok(Self(cookies))
+    }
+}
+
+impl<'a> Deref for Cookies<'a> {
+    type Target = HashMap<&'a str, &'a str>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// Malformed pairs are skipped rather than failing the whole request, since
+// browsers may send cookies set by other services on the same domain.
+fn parse(header: &str) -> impl Iterator<Item = (&str, &str)> {
+    header.split(';').filter_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        let name = name.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+
+        (!name.is_empty()).then_some((name, value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, time::Duration};
+
+    use musdk_common::{Header, HttpMethod, Response, SameSite, SetCookie};
+
+    use super::*;
+
+    fn request(cookie_headers: &[&'static str]) -> Request<'static> {
+        Request {
+            method: HttpMethod::Get,
+            route_template: Cow::Borrowed("/"),
+            path_params: HashMap::new(),
+            query_params: vec![],
+            headers: cookie_headers
+                .iter()
+                .map(|value| Header {
+                    name: Cow::Borrowed("Cookie"),
+                    value: Cow::Borrowed(*value),
+                })
+                .collect(),
+            body: Cow::Borrowed(&[]),
+        }
+    }
+
+    #[test]
+    fn parses_cookies_from_all_headers() {
+        let req = request(&[" session = abc123 ;theme=dark", "lang=\"en\";  ; broken"]);
+        let cookies = Cookies::from_request(&req).unwrap();
+
+        assert_eq!(
+            cookies.into_inner(),
+            [("session", "abc123"), ("theme", "dark"), ("lang", "en")].into()
+        );
+    }
+
+    #[test]
+    fn keeps_the_first_of_repeated_cookies() {
+        let req = request(&["id=1; id=2", "id=3"]);
+        assert_eq!(Cookies::from_request(&req).unwrap().get("id"), Some(&"1"));
+
+        let req = request(&[]);
+        assert!(Cookies::from_request(&req).unwrap().is_empty());
+    }
+
+    #[test]
+    fn formats_set_cookie_attributes() {
+        let cookie = SetCookie::new("session", "xyz")
+            .path("/")
+            .max_age(Duration::from_secs(3600))
+            .http_only()
+            .secure()
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_header().value,
+            "session=xyz; Path=/; Max-Age=3600; HttpOnly; Secure; SameSite=Lax"
+        );
+
+        assert_eq!(SetCookie::new("a", "b").to_header().value, "a=b");
+    }
+
+    #[test]
+    fn responses_keep_every_cookie() {
+        let response = Response::builder()
+            .set_cookie(&SetCookie::new("a", "1"))
+            .set_cookie(&SetCookie::new("b", "2"))
+            .no_body();
+
+        let set_cookies = response
+            .headers
+            .iter()
+            .filter(|h| h.name == "set-cookie")
+            .map(|h| h.value.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(set_cookies, vec!["a=1", "b=2"]);
+    }
+}
@@ -2,6 +2,12 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[error("Unexpected message kind, first message must be a Handshake")]
+    MissingHandshake,
+
+    #[error("Runtime uses unsupported protocol version {0}")]
+    UnsupportedProtocolVersion(u16),
+
     #[error("Unexpected message kind, first message must be an ExecuteFunction request")]
     UnexpectedFirstMessageKind,
 
@@ -20,9 +26,21 @@ pub enum Error {
     #[error("Database error: {0}")]
     DatabaseError(String),
 
+    #[error("Failed to convert database value: {0}")]
+    DatabaseValueConversion(String),
+
     #[error("Storage error: {0}")]
     StorageError(String),
 
+    #[error("Object was modified since its ETag was read")]
+    StorageETagMismatch,
+
+    #[error("Response stream was already started")]
+    ResponseStreamAlreadyStarted,
+
+    #[error("Response stream must be started before writing to it")]
+    ResponseStreamNotStarted,
+
     #[error("Unexpected message kind, was expecting {0}")]
     UnexpectedMessageKind(&'static str),
 }
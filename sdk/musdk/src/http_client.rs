@@ -20,7 +20,7 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use std::{borrow::Cow, fmt};
+use std::{borrow::Cow, fmt, time::Duration};
 
 use musdk_common::{
     http_client::{
@@ -223,6 +223,28 @@ impl<'a, 'c: 'a> RequestBuilder<'a, 'c> {
         self
     }
 
+    /// Set a timeout for the whole request, from connecting until the
+    /// response body has been read. Defaults to 30 seconds.
+    ///
+    /// Requests that take longer fail with `Error::Timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        if let Ok(ref mut req) = self.request {
+            req.timeout_millis = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+        }
+        self
+    }
+
+    /// Set how many redirects are followed before giving up. Defaults to 10.
+    ///
+    /// Requests that redirect more times fail with `Error::TooManyRedirects`,
+    /// except with `0`, which returns redirect responses as they are.
+    pub fn max_redirects(mut self, max_redirects: u32) -> Self {
+        if let Ok(ref mut req) = self.request {
+            req.max_redirects = max_redirects;
+        }
+        self
+    }
+
     /// Send a form body.
     ///
     /// Sets the body to the url encoded serialization of the passed value,
@@ -1,5 +1,6 @@
 mod content_type;
 mod context;
+mod cookies;
 mod error;
 mod http_client;
 mod request_adapters;
@@ -8,14 +9,33 @@ mod response_adapters;
 #[cfg(feature = "json")]
 mod json_body;
 
-pub use musdk_common::{outgoing_message::LogLevel, Header, HttpMethod, Request, Response, Status};
+#[cfg(feature = "msgpack")]
+mod msgpack_body;
+
+#[cfg(feature = "multipart")]
+mod multipart_body;
+
+#[cfg(feature = "serde")]
+mod query;
+
+pub use musdk_common::{
+    http_client::Error as HttpError, outgoing_message::LogLevel, Header, HttpMethod, Request,
+    Response, SameSite, SetCookie, Status,
+};
 pub use musdk_derive::mu_functions;
 
 pub use context::*;
+pub use cookies::*;
 pub use error::*;
-pub use http_client::HttpClient;
+pub use http_client::{ClientError, HttpClient};
 pub use request_adapters::*;
 pub use response_adapters::*;
 
 #[cfg(feature = "json")]
 pub use json_body::*;
+
+#[cfg(feature = "msgpack")]
+pub use msgpack_body::*;
+
+#[cfg(feature = "multipart")]
+pub use multipart_body::*;
@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+
+use musdk_common::{Request, Response, Status};
+use serde::{Deserialize, Serialize};
+
+use crate::{content_type, FromRequest, IntoResponse};
+
+const MSGPACK_MIME: &str = "application/msgpack";
+
+// Not registered, but still commonly used
+const LEGACY_MSGPACK_MIME: &str = "application/x-msgpack";
+
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MsgPack<T>(pub T);
+
+impl<T> MsgPack<T> {
+    /// Consumes wrapper and returns wrapped item
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Whether the request's `Accept` header asks for MessagePack. Requests
+/// without an `Accept` header don't, so functions can fall back to JSON
+/// for them.
+pub fn accepts_msgpack(req: &Request) -> bool {
+    req.headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("accept"))
+        .any(|header| {
+            content_type::accepts(&header.value, MSGPACK_MIME)
+                || content_type::accepts(&header.value, LEGACY_MSGPACK_MIME)
+        })
+}
+
+impl<'a, T: Deserialize<'a>> FromRequest<'a> for MsgPack<T> {
+    type Error = (&'static str, Status);
+
+    fn from_request(req: &'a Request) -> Result<Self, Self::Error> {
+        let Some(content_type) = req.content_type() else {
+            return Err(("content-type is missing", Status::BadRequest));
+        };
+
+        match content_type::parse(&content_type) {
+            (Some(mime), _) if mime == MSGPACK_MIME || mime == LEGACY_MSGPACK_MIME => {
+                rmp_serde::from_slice::<T>(req.body.as_ref())
+                    .map(Self)
+                    .map_err(|_| ("invalid msgpack", Status::BadRequest))
+            }
+            _ => Err((
+                "invalid content-type, expecting `application/msgpack`",
+                Status::BadRequest,
+            )),
+        }
+    }
+}
+
+impl<'a, T: Serialize> IntoResponse<'a> for MsgPack<T> {
+    fn into_response(self) -> Response<'a> {
+        // Structs are encoded as maps, so clients don't need to know
+        // the order of their fields
+        match rmp_serde::to_vec_named(&self.0) {
+            Ok(vec) => Response::builder()
+                .content_type(Cow::Borrowed(MSGPACK_MIME))
+                .body_from_vec(vec),
+
+            Err(_) => Status::InternalServerError.into_response(),
+        }
+    }
+}
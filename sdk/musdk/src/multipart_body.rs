@@ -0,0 +1,198 @@
+use musdk_common::{Request, Status};
+
+use crate::{content_type, FromRequest};
+
+/// A `multipart/form-data` request body, split into its parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multipart<'a> {
+    parts: Vec<Part<'a>>,
+}
+
+/// A single part of a [`Multipart`] body, either a plain field or a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part<'a> {
+    pub name: &'a str,
+    pub filename: Option<&'a str>,
+    pub content_type: Option<&'a str>,
+    pub data: &'a [u8],
+}
+
+impl<'a> Part<'a> {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+}
+
+impl<'a> Multipart<'a> {
+    /// All parts, in the order they were sent.
+    pub fn parts(&self) -> &[Part<'a>] {
+        &self.parts
+    }
+
+    /// The first part with the given name.
+    pub fn get(&self, name: &str) -> Option<&Part<'a>> {
+        self.parts.iter().find(|p| p.name == name)
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &Part<'a>> {
+        self.parts.iter().filter(|p| !p.is_file())
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Part<'a>> {
+        self.parts.iter().filter(|p| p.is_file())
+    }
+
+    /// Consumes wrapper and returns the parts
+    #[inline(always)]
+    pub fn into_parts(self) -> Vec<Part<'a>> {
+        self.parts
+    }
+}
+
+impl<'a> FromRequest<'a> for Multipart<'a> {
+    type Error = (&'static str, Status);
+
+    fn from_request(req: &'a Request) -> Result<Self, Self::Error> {
+        let Some(content_type) = req.content_type() else {
+            return Err(("content-type is missing", Status::BadRequest));
+        };
+
+        let mime = content_type::parse(&content_type).0;
+        if !matches!(mime, Some(m) if m.eq_ignore_ascii_case("multipart/form-data")) {
+            return Err((
+                "invalid content-type, expecting `multipart/form-data`",
+                Status::BadRequest,
+            ));
+        }
+
+        let Some(boundary) = content_type::param(&content_type, "boundary") else {
+            return Err(("multipart boundary is missing", Status::BadRequest));
+        };
+
+        parse(&req.body, boundary.as_bytes())
+            .map(|parts| Self { parts })
+            .ok_or(("invalid multipart body", Status::BadRequest))
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse<'a>(body: &'a [u8], boundary: &[u8]) -> Option<Vec<Part<'a>>> {
+    if boundary.is_empty() {
+        return None;
+    }
+
+    let delimiter = [b"--".as_slice(), boundary].concat();
+    let close_delimiter = [b"\r\n".as_slice(), delimiter.as_slice()].concat();
+
+    // Anything before the first delimiter is a preamble and is ignored
+    let start = find(body, &delimiter)?;
+    let mut rest = &body[start + delimiter.len()..];
+
+    let mut parts = vec![];
+    loop {
+        if rest.starts_with(b"--") {
+            return Some(parts);
+        }
+
+        let rest_of_line = rest.strip_prefix(b"\r\n")?;
+        let headers_end = find(rest_of_line, b"\r\n\r\n")?;
+        let headers = std::str::from_utf8(&rest_of_line[..headers_end]).ok()?;
+        let content = &rest_of_line[headers_end + 4..];
+
+        let data_end = find(content, &close_delimiter)?;
+        parts.push(parse_part(headers, &content[..data_end])?);
+        rest = &content[data_end + close_delimiter.len()..];
+    }
+}
+
+fn parse_part<'a>(headers: &'a str, data: &'a [u8]) -> Option<Part<'a>> {
+    let mut disposition = None;
+    let mut content_type = None;
+
+    for line in headers.split("\r\n") {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("content-disposition") {
+            disposition = Some(value.trim());
+        } else if name.trim().eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.trim());
+        }
+    }
+
+    let disposition = disposition?;
+    if !content_type::split_params(disposition)
+        .next()?
+        .eq_ignore_ascii_case("form-data")
+    {
+        return None;
+    }
+
+    Some(Part {
+        name: content_type::param(disposition, "name")?,
+        filename: content_type::param(disposition, "filename"),
+        content_type,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"preamble\r\n\
+        --xyz\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Buy milk\r\n\
+        --xyz\r\n\
+        Content-Disposition: form-data; name=\"attachment\"; filename=\"list.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        milk\r\neggs\r\n\
+        --xyz--\r\n";
+
+    #[test]
+    fn parses_fields_and_files() {
+        let parts = parse(BODY, b"xyz").unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                Part {
+                    name: "title",
+                    filename: None,
+                    content_type: None,
+                    data: b"Buy milk",
+                },
+                Part {
+                    name: "attachment",
+                    filename: Some("list.txt"),
+                    content_type: Some("text/plain"),
+                    data: b"milk\r\neggs",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        assert_eq!(parse(BODY, b"abc"), None);
+        assert_eq!(parse(BODY, b""), None);
+
+        // Missing the closing delimiter
+        assert_eq!(parse(&BODY[..BODY.len() - 9], b"xyz"), None);
+
+        let no_name = b"--xyz\r\n\
+            Content-Disposition: form-data\r\n\
+            \r\n\
+            data\r\n\
+            --xyz--";
+        assert_eq!(parse(no_name, b"xyz"), None);
+    }
+
+    #[test]
+    fn accepts_empty_bodies() {
+        assert_eq!(parse(b"--xyz--\r\n", b"xyz"), Some(vec![]));
+    }
+}
@@ -0,0 +1,258 @@
+//! A serde deserializer over query parameters, used by [`crate::Query`].
+//!
+//! Values are parsed into whatever type the target field asks for, and
+//! repeated keys can be collected into sequences, which `serde_urlencoded`
+//! doesn't support.
+
+use std::borrow::Cow;
+
+use serde::{
+    de::{
+        value::{Error, MapDeserializer, SeqDeserializer},
+        Deserializer, Error as _, IntoDeserializer, Unexpected, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
+};
+
+pub fn from_pairs<'de, T: Deserialize<'de>>(
+    pairs: &'de [(Cow<'de, str>, Cow<'de, str>)],
+) -> Result<T, Error> {
+    // Group values by key, keeping the order in which keys first appear
+    let mut groups: Vec<(&str, Vec<&str>)> = vec![];
+    for (key, value) in pairs {
+        match groups.iter_mut().find(|(k, _)| *k == key.as_ref()) {
+            Some((_, values)) => values.push(value.as_ref()),
+            None => groups.push((key.as_ref(), vec![value.as_ref()])),
+        }
+    }
+
+    T::deserialize(MapDeserializer::new(
+        groups.into_iter().map(|(k, v)| (k, Values(v))),
+    ))
+}
+
+// All values of one key. Deserializes as a sequence when asked for one,
+// and as the last value otherwise.
+struct Values<'de>(Vec<&'de str>);
+
+// A single value, parsed on demand
+struct Value<'de>(&'de str);
+
+impl<'de> IntoDeserializer<'de, Error> for Values<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl<'de> Values<'de> {
+    fn last(self) -> Value<'de> {
+        // Groups are never empty
+        Value(self.0.last().copied().unwrap_or_default())
+    }
+}
+
+macro_rules! forward_to_last {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                Deserializer::$method(self.last(), visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Values<'de> {
+    type Error = Error;
+
+    forward_to_last! {
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16
+        deserialize_i32 deserialize_i64 deserialize_u8 deserialize_u16
+        deserialize_u32 deserialize_u64 deserialize_f32 deserialize_f64
+        deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_unit deserialize_identifier
+        deserialize_ignored_any
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(SeqDeserializer::new(self.0.into_iter().map(Value)))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.last().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        unit_struct tuple_struct map struct
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.parse() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(Error::invalid_value(Unexpected::Str(self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Value<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct
+        map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(
+        query: &[(&'static str, &'static str)],
+    ) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        query
+            .iter()
+            .map(|(k, v)| (Cow::Borrowed(*k), Cow::Borrowed(*v)))
+            .collect()
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Order {
+        Asc,
+        Desc,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Paging {
+        page: u32,
+        size: Option<u8>,
+        order: Order,
+        #[serde(default)]
+        tag: Vec<String>,
+    }
+
+    #[test]
+    fn parses_typed_values() {
+        let query = pairs(&[("page", "3"), ("order", "desc"), ("size", "20")]);
+        assert_eq!(
+            from_pairs::<Paging>(&query).unwrap(),
+            Paging {
+                page: 3,
+                size: Some(20),
+                order: Order::Desc,
+                tag: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn collects_repeated_keys() {
+        let query = pairs(&[
+            ("tag", "a"),
+            ("page", "1"),
+            ("tag", "b"),
+            ("order", "asc"),
+            ("tag", "c"),
+        ]);
+        let paging = from_pairs::<Paging>(&query).unwrap();
+        assert_eq!(paging.tag, vec!["a", "b", "c"]);
+        assert_eq!(paging.size, None);
+
+        // Non-sequence fields take the last value
+        let query = pairs(&[("page", "1"), ("page", "2"), ("order", "asc")]);
+        assert_eq!(from_pairs::<Paging>(&query).unwrap().page, 2);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let query = pairs(&[("page", "first"), ("order", "asc")]);
+        assert!(from_pairs::<Paging>(&query).is_err());
+
+        let query = pairs(&[("page", "1"), ("order", "random")]);
+        assert!(from_pairs::<Paging>(&query).is_err());
+
+        let query = pairs(&[("order", "asc")]);
+        assert!(from_pairs::<Paging>(&query).is_err());
+    }
+}
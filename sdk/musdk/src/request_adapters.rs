@@ -5,7 +5,11 @@ use std::{
 };
 
 use musdk_common::{Request, Status};
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
 
+#[cfg(feature = "serde")]
+use crate::query;
 use crate::{content_type, IntoResponse};
 
 pub trait FromRequest<'a>: Sized {
@@ -66,10 +70,121 @@ impl<'a> FromRequest<'a> for String {
     }
 }
 
+/// Default maximum body size accepted by [`BodyBytes`] and [`BodyText`].
+pub const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// The raw request body, rejected with `413 Payload Too Large` if it is
+/// longer than `LIMIT` bytes.
+pub struct BodyBytes<'a, const LIMIT: usize = DEFAULT_BODY_LIMIT>(pub &'a [u8]);
+
+/// The request body as text, rejected with `400 Bad Request` if it is not
+/// valid UTF-8 and with `413 Payload Too Large` if it is longer than `LIMIT`
+/// bytes.
+pub struct BodyText<'a, const LIMIT: usize = DEFAULT_BODY_LIMIT>(pub &'a str);
+
+impl<'a, const LIMIT: usize> BodyBytes<'a, LIMIT> {
+    /// Consumes wrapper and returns wrapped item
+    #[inline(always)]
+    pub fn into_inner(self) -> &'a [u8] {
+        self.0
+    }
+}
+
+impl<'a, const LIMIT: usize> BodyText<'a, LIMIT> {
+    /// Consumes wrapper and returns wrapped item
+    #[inline(always)]
+    pub fn into_inner(self) -> &'a str {
+        self.0
+    }
+}
+
+fn check_body_limit(req: &Request, limit: usize) -> Result<(), (String, Status)> {
+    if req.body.len() > limit {
+        Err((
+            format!("request body exceeds the limit of {limit} bytes"),
+            Status::PayloadTooLarge,
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+impl<'a, const LIMIT: usize> FromRequest<'a> for BodyBytes<'a, LIMIT> {
+    type Error = (String, Status);
+
+    fn from_request(req: &'a Request) -> Result<Self, Self::Error> {
+        check_body_limit(req, LIMIT)?;
+        Ok(Self(&req.body))
+    }
+}
+
+impl<'a, const LIMIT: usize> FromRequest<'a> for BodyText<'a, LIMIT> {
+    type Error = (String, Status);
+
+    fn from_request(req: &'a Request) -> Result<Self, Self::Error> {
+        check_body_limit(req, LIMIT)?;
+        <&'a str as FromRequest<'a>>::from_request(req).map(Self)
+    }
+}
+
+impl<'a, const LIMIT: usize> Deref for BodyBytes<'a, LIMIT> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<'a, const LIMIT: usize> Deref for BodyText<'a, LIMIT> {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
 //TODO: Deserialize into the concrete struct, like `PathParam<Request>`
 pub struct PathParams<'a>(HashMap<Cow<'a, str>, Cow<'a, str>>);
+/// Raw query parameters. If a key is repeated, only its last value is kept,
+/// use [`Query`] to collect all of them.
 pub struct QueryParams<'a>(HashMap<Cow<'a, str>, Cow<'a, str>>);
 
+/// Query parameters deserialized into `T`, rejected with `400 Bad Request`
+/// if they don't fit. Repeated keys, as in `?tag=a&tag=b`, can be collected
+/// into a `Vec` field.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T> Query<T> {
+    /// Consumes wrapper and returns wrapped item
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, T: DeserializeOwned> FromRequest<'a> for Query<T> {
+    type Error = (String, Status);
+
+    fn from_request(req: &'a Request) -> Result<Self, Self::Error> {
+        query::from_pairs(&req.query_params)
+            .map(Self)
+            .map_err(|e| (format!("invalid query string: {e}"), Status::BadRequest))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<'a> FromRequest<'a> for PathParams<'a> {
     type Error = ();
 
@@ -125,3 +240,69 @@ impl<'a> DerefMut for QueryParams<'a> {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use musdk_common::HttpMethod;
+
+    use super::*;
+
+    fn request(body: &'static [u8]) -> Request<'static> {
+        Request {
+            method: HttpMethod::Post,
+            route_template: Cow::Borrowed("/"),
+            path_params: HashMap::new(),
+            query_params: vec![],
+            headers: vec![],
+            body: Cow::Borrowed(body),
+        }
+    }
+
+    #[test]
+    fn bodies_within_the_limit_are_accepted() {
+        let req = request(b"hello");
+
+        let bytes = BodyBytes::<5>::from_request(&req).unwrap();
+        assert_eq!(bytes.into_inner(), b"hello");
+
+        let text = BodyText::<5>::from_request(&req).unwrap();
+        assert_eq!(&*text, "hello");
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_rejected() {
+        let req = request(b"hello!");
+
+        assert!(matches!(
+            BodyBytes::<5>::from_request(&req),
+            Err((_, Status::PayloadTooLarge))
+        ));
+        assert!(matches!(
+            BodyText::<5>::from_request(&req),
+            Err((_, Status::PayloadTooLarge))
+        ));
+    }
+
+    #[test]
+    fn invalid_text_bodies_are_rejected() {
+        let req = request(&[0xff, 0xfe]);
+
+        assert!(matches!(
+            BodyText::<5>::from_request(&req),
+            Err((_, Status::BadRequest))
+        ));
+        // The raw bytes are still fine
+        assert!(BodyBytes::<5>::from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn default_limit_is_applied() {
+        let body: &'static [u8] = vec![b'a'; DEFAULT_BODY_LIMIT + 1].leak();
+
+        assert!(matches!(
+            <BodyBytes>::from_request(&request(body)),
+            Err((_, Status::PayloadTooLarge))
+        ));
+        assert!(<BodyText>::from_request(&request(&body[1..])).is_ok());
+    }
+}
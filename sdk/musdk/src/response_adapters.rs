@@ -1,5 +1,7 @@
 use musdk_common::{Response, Status};
 
+use crate::Error;
+
 pub trait IntoResponse<'a> {
     fn into_response(self) -> Response<'a>;
 }
@@ -70,3 +72,26 @@ impl<'a> IntoResponse<'a> for Status {
         Response::builder().status(self).no_body()
     }
 }
+
+impl<'a> IntoResponse<'a> for Error {
+    fn into_response(self) -> Response<'a> {
+        let status = match self {
+            Error::StorageETagMismatch => Status::PreconditionFailed,
+            _ => Status::InternalServerError,
+        };
+        (self.to_string(), status).into_response()
+    }
+}
+
+impl<'a> IntoResponse<'a> for std::io::Error {
+    fn into_response(self) -> Response<'a> {
+        (self.to_string(), Status::InternalServerError).into_response()
+    }
+}
+
+/// Lets functions use `?` on any error type, failing with a 500.
+impl<'a> IntoResponse<'a> for Box<dyn std::error::Error + Send + Sync> {
+    fn into_response(self) -> Response<'a> {
+        (self.to_string(), Status::InternalServerError).into_response()
+    }
+}
@@ -0,0 +1,37 @@
+use musdk_derive::mu_functions;
+
+// Like derive_macro_compiles.rs, this only checks that functions returning
+// a `Result` generate code that compiles, through the `IntoResponse` impl
+// of `Result` and those of the common error types.
+
+#[mu_functions]
+mod functions {
+    use musdk::{Error, MuContext, Status};
+
+    #[mu_function]
+    fn status_error<'a>(_ctx: &'a MuContext, name: &'a str) -> Result<String, Status> {
+        match name {
+            "" => Err(Status::BadRequest),
+            name => Ok(format!("Hello {name}")),
+        }
+    }
+
+    #[mu_function]
+    fn sdk_error<'a>(ctx: &'a mut MuContext) -> Result<(), Error> {
+        ctx.report_metric("calls", 1)
+    }
+
+    #[mu_function]
+    fn boxed_error<'a>(
+        _ctx: &'a MuContext,
+        data: &'a str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        let number: u32 = data.parse()?;
+        Ok(number.to_be_bytes().to_vec())
+    }
+
+    #[mu_function]
+    fn result_alias<'a>(_ctx: &'a MuContext) -> musdk::Result<&'static str> {
+        Ok("done")
+    }
+}
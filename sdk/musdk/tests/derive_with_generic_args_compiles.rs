@@ -0,0 +1,25 @@
+use musdk_derive::mu_functions;
+
+// Same as derive_macro_compiles.rs, for functions whose arguments use the
+// declared lifetime alongside other generic arguments.
+
+#[mu_functions]
+mod functions {
+    use musdk::{BodyBytes, BodyText, MuContext, Request};
+
+    #[mu_function]
+    fn limited_text<'a>(_ctx: &'a mut MuContext, text: BodyText<'a, 16>) -> String {
+        text.into_inner().to_uppercase()
+    }
+
+    #[mu_function]
+    fn limited_bytes<'a>(
+        _ctx: &'a MuContext,
+        request: &'a Request<'a>,
+        body: BodyBytes<'a, 1024>,
+    ) -> Vec<u8> {
+        let mut result = request.route_template.as_bytes().to_vec();
+        result.extend_from_slice(&body);
+        result
+    }
+}
@@ -0,0 +1,29 @@
+use musdk_derive::mu_functions;
+
+// Same as derive_macro_compiles.rs, for functions that don't declare a
+// lifetime parameter.
+
+#[mu_functions]
+mod functions {
+    use musdk::{Json, MuContext, PathParams};
+
+    #[mu_function]
+    fn no_arguments(_ctx: &mut MuContext) -> String {
+        "pong".into()
+    }
+
+    #[mu_function]
+    fn elided_references(_ctx: &MuContext, data: &[u8], text: &str) -> Vec<u8> {
+        [data, text.as_bytes()].concat()
+    }
+
+    #[mu_function]
+    fn placeholder_lifetime(_ctx: &MuContext, params: PathParams<'_>) -> String {
+        params.len().to_string()
+    }
+
+    #[mu_function]
+    fn owned_arguments(_ctx: &MuContext, numbers: Json<Vec<u32>>) -> Json<u32> {
+        Json(numbers.into_inner().into_iter().sum())
+    }
+}